@@ -0,0 +1,69 @@
+//! Benchmarks for encoding requests and decoding responses.
+//!
+//! Run with `cargo bench --features bench --bench codec`.
+
+#[macro_use]
+extern crate criterion;
+extern crate tokio_zookeeper;
+
+use criterion::{black_box, Criterion, Throughput};
+use tokio_zookeeper::bench;
+
+static DATA: [u8; 4096] = [0xab; 4096];
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    let mut buf = Vec::new();
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("get_data", |b| {
+        b.iter(|| bench::encode_get_data(&mut buf, black_box("/some/path/to/a/node")))
+    });
+    for &size in &[16, 4096] {
+        let data = &DATA[..size];
+        group.bench_function(format!("set_data/{}", size), |b| {
+            b.iter(|| bench::encode_set_data(&mut buf, black_box("/some/path"), data))
+        });
+        group.bench_function(format!("create/{}", size), |b| {
+            b.iter(|| bench::encode_create(&mut buf, black_box("/some/path"), data))
+        });
+    }
+    group.finish();
+}
+
+/// Encoding into a new buffer for every request, as the client does, with and without sizing the
+/// buffer up front.
+fn allocate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allocate");
+    group.throughput(Throughput::Elements(1));
+    for &size in &[16, 4096] {
+        let data = &DATA[..size];
+        group.bench_function(format!("grown/{}", size), |b| {
+            b.iter(|| bench::encode_set_data_grown(black_box("/some/path"), data))
+        });
+        group.bench_function(format!("sized/{}", size), |b| {
+            b.iter(|| bench::encode_set_data_sized(black_box("/some/path"), data))
+        });
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(1));
+    for &size in &[16, 4096] {
+        let body = bench::get_data_response(&DATA[..size]);
+        group.bench_function(format!("get_data/{}", size), |b| {
+            b.iter(|| bench::decode_get_data(black_box(&body)))
+        });
+    }
+    for &n in &[10, 1000] {
+        let body = bench::get_children_response(n);
+        group.bench_function(format!("get_children/{}", n), |b| {
+            b.iter(|| bench::decode_get_children(black_box(&body)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encode, allocate, decode);
+criterion_main!(benches);
@@ -0,0 +1,134 @@
+//! End-to-end benchmarks of issuing requests through the client.
+//!
+//! The client talks to a minimal ZooKeeper server on the loopback interface that answers every
+//! `get_data` with the same 128 bytes, so the numbers reflect the client's own overhead.
+
+extern crate byteorder;
+#[macro_use]
+extern crate criterion;
+extern crate tokio;
+extern crate tokio_zookeeper;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use criterion::{Criterion, Throughput};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use tokio_zookeeper::{FlushStrategy, ZooKeeperBuilder};
+
+const GET_DATA: i32 = 4;
+const CLOSE_SESSION: i32 = -11;
+const DATA: [u8; 128] = [0xab; 128];
+
+fn read_frame<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let len = r.read_i32::<BigEndian>()?;
+    let mut frame = vec![0; len as usize];
+    r.read_exact(&mut frame)?;
+    Ok(frame)
+}
+
+/// Serve a single client session.
+fn serve(stream: TcpStream) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut r = BufReader::new(stream.try_clone()?);
+    let mut w = BufWriter::new(stream);
+
+    // connect request; respond with a session
+    read_frame(&mut r)?;
+    w.write_i32::<BigEndian>(4 + 4 + 8 + 4 + 16 + 1)?;
+    w.write_i32::<BigEndian>(0)?; // protocol version
+    w.write_i32::<BigEndian>(30_000)?; // timeout
+    w.write_i64::<BigEndian>(1)?; // session id
+    w.write_i32::<BigEndian>(16)?;
+    w.write_all(&[0; 16])?; // password
+    w.write_u8(0)?; // read-only
+    w.flush()?;
+
+    loop {
+        let frame = read_frame(&mut r)?;
+        let mut frame = &frame[..];
+        let xid = frame.read_i32::<BigEndian>()?;
+        let opcode = frame.read_i32::<BigEndian>()?;
+        let body = if opcode == GET_DATA {
+            4 + DATA.len() + 68
+        } else {
+            0
+        };
+        w.write_i32::<BigEndian>(4 + 8 + 4 + body as i32)?;
+        w.write_i32::<BigEndian>(xid)?;
+        w.write_i64::<BigEndian>(1)?; // zxid
+        w.write_i32::<BigEndian>(0)?; // no error
+        if opcode == GET_DATA {
+            w.write_i32::<BigEndian>(DATA.len() as i32)?;
+            w.write_all(&DATA)?;
+            w.write_all(&[0; 68])?; // stat
+        }
+        if opcode == CLOSE_SESSION {
+            return w.flush();
+        }
+        // only flush once the client has no more requests in flight
+        if r.buffer().is_empty() {
+            w.flush()?;
+        }
+    }
+}
+
+fn mock_server() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = stream.unwrap();
+            thread::spawn(move || serve(stream));
+        }
+    });
+    addr
+}
+
+fn get_data_many(c: &mut Criterion) {
+    const N: usize = 1000;
+    let addr = mock_server();
+    let mut group = c.benchmark_group("get_data_many");
+    group.throughput(Throughput::Elements(N as u64));
+
+    let strategies = [
+        ("immediate", FlushStrategy::Immediate),
+        (
+            "batched",
+            FlushStrategy::Batched {
+                delay: std::time::Duration::from_micros(50),
+                max_bytes: 16 * 1024,
+            },
+        ),
+    ];
+    for &(name, flush) in &strategies {
+        for &concurrency in &[1, 64] {
+            if concurrency == 1 && flush != FlushStrategy::Immediate {
+                // every request would wait out the whole window
+                continue;
+            }
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            let mut builder = ZooKeeperBuilder::default();
+            builder.set_flush_strategy(flush);
+            let (zk, _) = rt.block_on(builder.connect(&addr)).unwrap();
+            let paths: Vec<_> = (0..N).map(|i| format!("/node-{}", i)).collect();
+
+            group.bench_function(format!("{}/{}", name, concurrency), |b| {
+                b.iter(|| {
+                    let res = rt
+                        .block_on(zk.get_data_many(&paths, concurrency))
+                        .unwrap();
+                    assert_eq!(res.len(), N);
+                })
+            });
+
+            drop(zk);
+            // give the packetizer a chance to close the session
+            rt.shutdown_timeout(std::time::Duration::from_secs(1));
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, get_data_many);
+criterion_main!(benches);
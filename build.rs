@@ -0,0 +1,522 @@
+//! Generates `codec::records` from the jute definitions in `jute/zookeeper.jute`.
+//!
+//! This is a small parser for the subset of the jute language that `zookeeper.jute` uses:
+//! modules of classes, whose fields are `int`, `long`, `boolean`, `byte`, `float`, `double`,
+//! `buffer`, `ustring`, `vector<T>`, or other classes. Every class becomes a struct along with
+//! `ReadFrom` and `WriteTo` implementations that read and write its fields in the order they are
+//! declared in, which is what keeps the generated code in step with the servers.
+
+use std::collections::{BTreeSet, HashMap};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+const DEFINITIONS: &str = "jute/zookeeper.jute";
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", DEFINITIONS);
+
+    let source = fs::read_to_string(DEFINITIONS)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", DEFINITIONS, e));
+    let modules = Parser::new(&source).modules();
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    let out = Path::new(&out_dir).join("records.rs");
+    fs::write(&out, generate(&modules))
+        .unwrap_or_else(|e| panic!("cannot write {}: {}", out.display(), e));
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Int,
+    Long,
+    Bool,
+    Byte,
+    Float,
+    Double,
+    Buffer,
+    UString,
+    Vector(Box<Type>),
+    /// A class, along with the module it is declared in if the reference names one.
+    Record(Option<String>, String),
+}
+
+#[derive(Debug)]
+struct Field {
+    name: String,
+    ty: Type,
+    doc: Option<String>,
+}
+
+#[derive(Debug)]
+struct Class {
+    name: String,
+    fields: Vec<Field>,
+    doc: Option<String>,
+}
+
+#[derive(Debug)]
+struct Module {
+    name: String,
+    classes: Vec<Class>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Symbol(char),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+    /// The `//` comment at the end of each line that has one.
+    trailing: HashMap<usize, String>,
+    /// The lines that hold nothing but a `//` comment.
+    standalone: HashMap<usize, String>,
+}
+
+impl Parser {
+    fn new(source: &str) -> Parser {
+        let mut tokens = Vec::new();
+        let mut trailing = HashMap::new();
+        let mut standalone = HashMap::new();
+        let mut in_block = false;
+        for (i, mut line) in source.lines().enumerate() {
+            let lineno = i + 1;
+            let mut code = String::new();
+            loop {
+                if in_block {
+                    match line.find("*/") {
+                        Some(end) => {
+                            line = &line[end + 2..];
+                            in_block = false;
+                        }
+                        None => break,
+                    }
+                } else if let Some(start) = line.find("/*") {
+                    if line.find("//").is_none_or(|c| start < c) {
+                        code.push_str(&line[..start]);
+                        line = &line[start + 2..];
+                        in_block = true;
+                    } else {
+                        code.push_str(line);
+                        break;
+                    }
+                } else {
+                    code.push_str(line);
+                    break;
+                }
+            }
+            if let Some(c) = code.find("//") {
+                let comment = code[c + 2..].trim().to_string();
+                code.truncate(c);
+                if code.trim().is_empty() {
+                    standalone.insert(lineno, comment);
+                } else {
+                    trailing.insert(lineno, comment);
+                }
+            }
+
+            let mut word = String::new();
+            for ch in code.chars() {
+                if ch.is_alphanumeric() || ch == '_' || ch == '.' {
+                    word.push(ch);
+                    continue;
+                }
+                if !word.is_empty() {
+                    tokens.push((Token::Word(std::mem::take(&mut word)), lineno));
+                }
+                match ch {
+                    '{' | '}' | '<' | '>' | ';' => tokens.push((Token::Symbol(ch), lineno)),
+                    c if c.is_whitespace() => {}
+                    c => panic!("{}:{}: unexpected {:?}", DEFINITIONS, lineno, c),
+                }
+            }
+            if !word.is_empty() {
+                tokens.push((Token::Word(word), lineno));
+            }
+        }
+
+        Parser {
+            tokens,
+            next: 0,
+            trailing,
+            standalone,
+        }
+    }
+
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .or_else(|| self.tokens.last())
+            .map_or(0, |&(_, line)| line)
+    }
+
+    fn fail(&self, what: &str) -> ! {
+        panic!("{}:{}: {}", DEFINITIONS, self.line(), what)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(t, _)| t)
+    }
+
+    fn word(&mut self) -> String {
+        match self.tokens.get(self.next) {
+            Some((Token::Word(w), _)) => {
+                self.next += 1;
+                w.clone()
+            }
+            _ => self.fail("expected a name"),
+        }
+    }
+
+    fn symbol(&mut self, symbol: char) {
+        if self.peek() != Some(&Token::Symbol(symbol)) {
+            self.fail(&format!("expected `{}`", symbol));
+        }
+        self.next += 1;
+    }
+
+    fn keyword(&mut self, keyword: &str) {
+        if self.word() != keyword {
+            self.next -= 1;
+            self.fail(&format!("expected `{}`", keyword));
+        }
+    }
+
+    fn modules(mut self) -> Vec<Module> {
+        let mut modules = Vec::new();
+        while self.peek().is_some() {
+            self.keyword("module");
+            let name = self.word();
+            self.symbol('{');
+            let mut classes = Vec::new();
+            while self.peek() != Some(&Token::Symbol('}')) {
+                classes.push(self.class());
+            }
+            self.symbol('}');
+            modules.push(Module { name, classes });
+        }
+        modules
+    }
+
+    fn class(&mut self) -> Class {
+        let doc = self.standalone.get(&(self.line() - 1)).cloned();
+        self.keyword("class");
+        let name = self.word();
+        self.symbol('{');
+        let mut fields = Vec::new();
+        while self.peek() != Some(&Token::Symbol('}')) {
+            let ty = self.ty();
+            let name = self.word();
+            let doc = self.trailing.get(&self.line()).cloned();
+            self.symbol(';');
+            fields.push(Field { name, ty, doc });
+        }
+        self.symbol('}');
+        Class { name, fields, doc }
+    }
+
+    fn ty(&mut self) -> Type {
+        match &*self.word() {
+            "int" => Type::Int,
+            "long" => Type::Long,
+            "boolean" => Type::Bool,
+            "byte" => Type::Byte,
+            "float" => Type::Float,
+            "double" => Type::Double,
+            "buffer" => Type::Buffer,
+            "ustring" => Type::UString,
+            "vector" => {
+                self.symbol('<');
+                let item = self.ty();
+                self.symbol('>');
+                Type::Vector(Box::new(item))
+            }
+            name => match name.rfind('.') {
+                Some(dot) => Type::Record(Some(name[..dot].to_string()), name[dot + 1..].into()),
+                None => Type::Record(None, name.to_string()),
+            },
+        }
+    }
+}
+
+/// The Rust name of a class: `ACL` becomes `Acl`, and `GetSASLRequest` becomes `GetSaslRequest`.
+fn type_name(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut out = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        let after_upper = i > 0 && chars[i - 1].is_uppercase();
+        let before_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+        if c.is_uppercase() && after_upper && !before_lower {
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// The Rust name of a field: `ephemeralOwner` becomes `ephemeral_owner`.
+fn field_name(name: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                out.push('_');
+            }
+            out.extend(c.to_lowercase());
+        } else {
+            out.push(c);
+        }
+    }
+    match &*out {
+        "as" | "break" | "const" | "continue" | "crate" | "else" | "enum" | "extern" | "fn"
+        | "for" | "if" | "impl" | "in" | "let" | "loop" | "match" | "mod" | "move" | "mut"
+        | "pub" | "ref" | "return" | "static" | "struct" | "trait" | "type" | "unsafe" | "use"
+        | "where" | "while" | "async" | "await" | "dyn" => format!("r#{}", out),
+        _ => out,
+    }
+}
+
+/// The name of the Rust module that a jute module is generated into: its last component.
+fn module_name(name: &str) -> &str {
+    name.rsplit('.').next().unwrap()
+}
+
+/// A comment from the definitions as a sentence.
+fn sentence(comment: &str) -> String {
+    let mut chars = comment.chars();
+    let mut out: String = match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    };
+    if !out.ends_with('.') {
+        out.push('.');
+    }
+    out
+}
+
+struct Generator<'a> {
+    module: &'a str,
+    /// The classes that only hold fields that are `Copy`, identified by jute module and name.
+    copy: BTreeSet<(String, String)>,
+}
+
+impl Generator<'_> {
+    fn record_module<'m>(&'m self, module: &'m Option<String>) -> &'m str {
+        module.as_deref().unwrap_or(self.module)
+    }
+
+    fn is_copy(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Int | Type::Long | Type::Bool | Type::Byte | Type::Float | Type::Double => true,
+            Type::Buffer | Type::UString | Type::Vector(_) => false,
+            Type::Record(module, name) => {
+                let module = self.record_module(module).to_string();
+                self.copy.contains(&(module, name.clone()))
+            }
+        }
+    }
+
+    fn rust_type(&self, ty: &Type) -> String {
+        match ty {
+            Type::Int => "i32".into(),
+            Type::Long => "i64".into(),
+            Type::Bool => "bool".into(),
+            Type::Byte => "i8".into(),
+            Type::Float => "f32".into(),
+            Type::Double => "f64".into(),
+            Type::Buffer => "Vec<u8>".into(),
+            Type::UString => "String".into(),
+            Type::Vector(item) => format!("Vec<{}>", self.rust_type(item)),
+            Type::Record(module, name) => {
+                let module = self.record_module(module);
+                if module == self.module {
+                    type_name(name)
+                } else {
+                    format!("super::{}::{}", module_name(module), type_name(name))
+                }
+            }
+        }
+    }
+
+    /// An `io::Result` expression that reads a `ty` from the reader `r`.
+    fn read(&self, ty: &Type, r: &str) -> String {
+        match ty {
+            Type::Int => format!("{}.read_i32::<BigEndian>()", r),
+            Type::Long => format!("{}.read_i64::<BigEndian>()", r),
+            Type::Bool => format!("{}.read_u8().map(|b| b != 0)", r),
+            Type::Byte => format!("{}.read_i8()", r),
+            Type::Float => format!("{}.read_f32::<BigEndian>()", r),
+            Type::Double => format!("{}.read_f64::<BigEndian>()", r),
+            Type::Buffer => format!("{}.read_buffer()", r),
+            Type::UString => format!("{}.read_string()", r),
+            Type::Vector(item) => match **item {
+                Type::Record(..) => {
+                    format!("read_list({}, {}::read_from)", r, self.rust_type(item))
+                }
+                _ => format!("read_list({}, |r| {})", r, self.read(item, "r")),
+            },
+            Type::Record(..) => format!("{}::read_from({})", self.rust_type(ty), r),
+        }
+    }
+
+    /// Statements that write the `ty` that `value` refers to, or is if `by_ref` is false.
+    fn write(&self, out: &mut String, indent: usize, ty: &Type, value: &str, by_ref: bool) {
+        let pad = " ".repeat(indent);
+        let deref = if by_ref { "*" } else { "" };
+        let primitive = match ty {
+            Type::Int => "write_i32::<BigEndian>",
+            Type::Long => "write_i64::<BigEndian>",
+            Type::Bool => "write_u8",
+            Type::Byte => "write_i8",
+            Type::Float => "write_f32::<BigEndian>",
+            Type::Double => "write_f64::<BigEndian>",
+            Type::Buffer | Type::UString | Type::Record(..) => {
+                let _ = writeln!(out, "{}{}.write_to(&mut writer)?;", pad, value);
+                return;
+            }
+            Type::Vector(item) => {
+                let depth = (indent - 12) / 4;
+                let name = match depth {
+                    0 => "item".to_string(),
+                    _ => format!("item{}", depth),
+                };
+                let len = format!("{}.len() as i32", value);
+                let _ = writeln!(out, "{}writer.write_i32::<BigEndian>({})?;", pad, len);
+                let _ = writeln!(out, "{}for {} in &{} {{", pad, name, value);
+                self.write(out, indent + 4, item, &name, true);
+                let _ = writeln!(out, "{}}}", pad);
+                return;
+            }
+        };
+        let cast = if let Type::Bool = ty { " as u8" } else { "" };
+        let _ = writeln!(out, "{}writer.{}({}{}{})?;", pad, primitive, deref, value, cast);
+    }
+
+    fn class(&self, out: &mut String, class: &Class) {
+        let name = type_name(&class.name);
+        let _ = writeln!(out);
+        let _ = writeln!(out, "    /// `{}.{}`.", self.module, class.name);
+        if let Some(doc) = &class.doc {
+            let _ = writeln!(out, "    ///");
+            let _ = writeln!(out, "    /// {}", sentence(doc));
+        }
+        let mut derives = vec!["Clone"];
+        if class.fields.iter().all(|f| self.is_copy(&f.ty)) {
+            derives.push("Copy");
+        }
+        derives.extend(&["Debug", "Default", "PartialEq"]);
+        if !class.fields.iter().any(|f| matches!(f.ty, Type::Float | Type::Double)) {
+            derives.push("Eq");
+        }
+        let _ = writeln!(out, "    #[derive({})]", derives.join(", "));
+        let _ = writeln!(out, "    pub struct {} {{", name);
+        for field in &class.fields {
+            let _ = match &field.doc {
+                Some(doc) => writeln!(out, "        /// {}", sentence(doc)),
+                None => writeln!(out, "        /// `{}`.", field.name),
+            };
+            let ty = self.rust_type(&field.ty);
+            let _ = writeln!(out, "        pub {}: {},", field_name(&field.name), ty);
+        }
+        let _ = writeln!(out, "    }}");
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "    impl ReadFrom for {} {{", name);
+        let read = if class.fields.is_empty() { "_read" } else { "read" };
+        let signature = format!("fn read_from<R: Read>({}: &mut R) -> io::Result<Self>", read);
+        let _ = writeln!(out, "        {} {{", signature);
+        let _ = writeln!(out, "            Ok({} {{", name);
+        for field in &class.fields {
+            let value = match field.ty {
+                Type::Bool => "read.read_u8()? != 0".to_string(),
+                _ => format!("{}?", self.read(&field.ty, "read")),
+            };
+            let _ = writeln!(out, "                {}: {},", field_name(&field.name), value);
+        }
+        let _ = writeln!(out, "            }})");
+        let _ = writeln!(out, "        }}");
+        let _ = writeln!(out, "    }}");
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "    impl WriteTo for {} {{", name);
+        let writer = if class.fields.is_empty() { "_writer" } else { "mut writer" };
+        let signature = format!("fn write_to<W: Write>(&self, {}: W) -> io::Result<()>", writer);
+        let _ = writeln!(out, "        {} {{", signature);
+        for field in &class.fields {
+            let value = format!("self.{}", field_name(&field.name));
+            self.write(out, 12, &field.ty, &value, false);
+        }
+        let _ = writeln!(out, "            Ok(())");
+        let _ = writeln!(out, "        }}");
+        let _ = writeln!(out, "    }}");
+    }
+}
+
+fn generate(modules: &[Module]) -> String {
+    let mut copy = BTreeSet::new();
+    // a class can only hold classes declared before it, so one pass in order settles them all
+    for module in modules {
+        for class in &module.classes {
+            let generator = Generator {
+                module: &module.name,
+                copy: copy.clone(),
+            };
+            if class.fields.iter().all(|f| generator.is_copy(&f.ty)) {
+                copy.insert((module.name.clone(), class.name.clone()));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "// Generated by build.rs from {}; do not edit.", DEFINITIONS);
+    for module in modules {
+        let generator = Generator {
+            module: &module.name,
+            copy: copy.clone(),
+        };
+        let mut body = String::new();
+        for class in &module.classes {
+            generator.class(&mut body, class);
+        }
+
+        let mut byteorder = Vec::new();
+        if body.contains("BigEndian>") {
+            byteorder.push("BigEndian");
+        }
+        if ["read_i", "read_u8", "read_f"].iter().any(|m| body.contains(m)) {
+            byteorder.push("ReadBytesExt");
+        }
+        if ["write_i", "write_u8", "write_f"].iter().any(|m| body.contains(m)) {
+            byteorder.push("WriteBytesExt");
+        }
+        let mut jute = Vec::new();
+        if body.contains("read_list(") {
+            jute.push("read_list");
+        }
+        if body.contains(".read_buffer()") {
+            jute.push("BufferReader");
+        }
+        jute.push("ReadFrom");
+        if body.contains(".read_string()") {
+            jute.push("StringReader");
+        }
+        jute.push("WriteTo");
+
+        let _ = writeln!(out);
+        let _ = writeln!(out, "/// The records of `{}`.", module.name);
+        let _ = writeln!(out, "pub mod {} {{", module_name(&module.name));
+        if !byteorder.is_empty() {
+            let _ = writeln!(out, "    use byteorder::{{{}}};", byteorder.join(", "));
+        }
+        let _ = writeln!(out, "    use std::io::{{self, Read, Write}};");
+        let _ = writeln!(out, "    use crate::codec::jute::{{{}}};", jute.join(", "));
+        out.push_str(&body);
+        let _ = writeln!(out, "}}");
+    }
+    out
+}
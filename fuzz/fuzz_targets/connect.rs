@@ -0,0 +1,9 @@
+//! The body of a response to a handshake.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_zookeeper::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz::decode_connect(data);
+});
@@ -0,0 +1,9 @@
+//! The body of a watch notification.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_zookeeper::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz::decode_event(data);
+});
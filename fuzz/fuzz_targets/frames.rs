@@ -0,0 +1,10 @@
+//! Everything that a server sends on a connection, from the response to the handshake on, fed to
+//! a connection that has one request of every kind in flight.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_zookeeper::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    fuzz::read_frames(data);
+});
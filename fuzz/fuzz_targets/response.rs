@@ -0,0 +1,12 @@
+//! The body of a response to any request: the first byte picks the opcode, from -128 to 127,
+//! which covers all those that are known, and the rest is the body.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tokio_zookeeper::fuzz;
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((&opcode, body)) = data.split_first() {
+        fuzz::decode_response(opcode as i8 as i32, body);
+    }
+});
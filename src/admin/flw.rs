@@ -0,0 +1,658 @@
+//! ZooKeeper's four-letter-word commands.
+//!
+//! Each function here connects to the server at the given address, sends the command of the same
+//! name, and parses the server's answer. [`ruok`] makes for a cheap liveness check, [`srvr`],
+//! [`stat`] and [`mntr`] report on the server as a whole, and [`cons`] and [`wchs`] on the
+//! clients connected to it and the watches they have set.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::admin::flw;
+//! # async fn run() -> Result<(), tokio_zookeeper::Error> {
+//! let addr = "127.0.0.1:2181".parse().unwrap();
+//! if flw::ruok(&addr).await? {
+//!     for client in flw::cons(&addr).await? {
+//!         println!("{} has sent {} packets", client.addr, client.received);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Since ZooKeeper 3.5, servers only answer the commands listed in their
+//! `4lw.commands.whitelist` setting. The futures fail with the server's explanation if a command
+//! is not on the list.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use super::ServerMode;
+use crate::Error;
+
+/// A server's answer to `mntr`, or its AdminServer's to `monitor`.
+///
+/// Latencies are in milliseconds. Newer servers report many more values than are broken out
+/// here; those are kept in `other`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerMetrics {
+    /// The server's version.
+    pub version: String,
+    /// The average time the server took to process a request.
+    pub avg_latency: f64,
+    /// The longest time the server took to process a request.
+    pub max_latency: u64,
+    /// The shortest time the server took to process a request.
+    pub min_latency: u64,
+    /// The number of packets the server has received.
+    pub packets_received: u64,
+    /// The number of packets the server has sent.
+    pub packets_sent: u64,
+    /// The number of connected clients.
+    pub num_alive_connections: u64,
+    /// The number of requests that are queued for processing.
+    pub outstanding_requests: u64,
+    /// The role the server plays in its ensemble.
+    pub server_state: ServerMode,
+    /// The number of znodes.
+    pub znode_count: u64,
+    /// The number of watches.
+    pub watch_count: u64,
+    /// The number of ephemeral znodes.
+    pub ephemerals_count: u64,
+    /// The approximate size of all data, in bytes.
+    pub approximate_data_size: u64,
+    /// The number of open file descriptors, on Unix platforms.
+    pub open_file_descriptor_count: Option<u64>,
+    /// The maximum number of file descriptors, on Unix platforms.
+    pub max_file_descriptor_count: Option<u64>,
+    /// The number of followers, if the server is the leader.
+    pub followers: Option<u64>,
+    /// The number of followers that are in sync, if the server is the leader.
+    pub synced_followers: Option<u64>,
+    /// The number of followers that are syncing, if the server is the leader.
+    pub pending_syncs: Option<u64>,
+    /// Every other value, keyed by its name without the `zk_` prefix.
+    pub other: BTreeMap<String, String>,
+}
+
+impl FromStr for ServerMetrics {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = BTreeMap::new();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            let mut parts = line.splitn(2, |c: char| c.is_whitespace());
+            let key = parts.next().unwrap_or("");
+            match (key.strip_prefix("zk_"), parts.next()) {
+                (Some(key), Some(value)) => {
+                    values.insert(key.to_string(), value.trim().to_string());
+                }
+                // most likely, the command is not whitelisted
+                _ => return Err(Error::Protocol(format!("unexpected mntr output: {}", line))),
+            }
+        }
+        ServerMetrics::from_values(values)
+    }
+}
+
+impl ServerMetrics {
+    /// Pick the metrics out of `values`, which are keyed by their names without the `zk_` prefix.
+    pub(super) fn from_values(mut values: BTreeMap<String, String>) -> Result<Self, Error> {
+        let mut take = |key: &str| -> Result<String, Error> {
+            values
+                .remove(key)
+                .ok_or_else(|| Error::Protocol(format!("metrics are missing {}", key)))
+        };
+        let version = take("version")?;
+        let avg_latency = parse(&take("avg_latency")?, "avg_latency")?;
+        let max_latency = parse(&take("max_latency")?, "max_latency")?;
+        let min_latency = parse(&take("min_latency")?, "min_latency")?;
+        let packets_received = parse(&take("packets_received")?, "packets_received")?;
+        let packets_sent = parse(&take("packets_sent")?, "packets_sent")?;
+        let num_alive_connections =
+            parse(&take("num_alive_connections")?, "num_alive_connections")?;
+        let outstanding_requests = parse(&take("outstanding_requests")?, "outstanding_requests")?;
+        let server_state = ServerMode::from(&take("server_state")?[..]);
+        let znode_count = parse(&take("znode_count")?, "znode_count")?;
+        let watch_count = parse(&take("watch_count")?, "watch_count")?;
+        let ephemerals_count = parse(&take("ephemerals_count")?, "ephemerals_count")?;
+        let approximate_data_size =
+            parse(&take("approximate_data_size")?, "approximate_data_size")?;
+
+        let mut optional = |key: &str| match values.remove(key) {
+            Some(value) => parse(&value, key).map(Some),
+            None => Ok(None),
+        };
+        Ok(ServerMetrics {
+            version,
+            avg_latency,
+            max_latency,
+            min_latency,
+            packets_received,
+            packets_sent,
+            num_alive_connections,
+            outstanding_requests,
+            server_state,
+            znode_count,
+            watch_count,
+            ephemerals_count,
+            approximate_data_size,
+            open_file_descriptor_count: optional("open_file_descriptor_count")?,
+            max_file_descriptor_count: optional("max_file_descriptor_count")?,
+            followers: optional("followers")?,
+            synced_followers: optional("synced_followers")?,
+            pending_syncs: optional("pending_syncs")?,
+            other: values,
+        })
+    }
+}
+
+/// A server's answer to `srvr`.
+///
+/// Latencies are in milliseconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerInfo {
+    /// The server's version, including its build information.
+    pub version: String,
+    /// The shortest time the server took to process a request.
+    pub min_latency: u64,
+    /// The average time the server took to process a request.
+    pub avg_latency: f64,
+    /// The longest time the server took to process a request.
+    pub max_latency: u64,
+    /// The number of packets the server has received.
+    pub received: u64,
+    /// The number of packets the server has sent.
+    pub sent: u64,
+    /// The number of connected clients.
+    pub connections: u64,
+    /// The number of requests that are queued for processing.
+    pub outstanding: u64,
+    /// The last transaction the server has seen.
+    pub zxid: i64,
+    /// The role the server plays in its ensemble.
+    pub mode: ServerMode,
+    /// The number of znodes.
+    pub node_count: u64,
+}
+
+impl FromStr for ServerInfo {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut values = BTreeMap::new();
+        for line in s.lines().filter(|l| !l.trim().is_empty()) {
+            match line.find(':') {
+                Some(i) => values.insert(&line[..i], line[i + 1..].trim()),
+                // most likely, the command is not whitelisted
+                None => return Err(Error::Protocol(format!("unexpected srvr output: {}", line))),
+            };
+        }
+        let get = |key: &str| {
+            values
+                .get(key)
+                .cloned()
+                .ok_or_else(|| Error::Protocol(format!("srvr output is missing {:?}", key)))
+        };
+
+        let latency = get("Latency min/avg/max")?;
+        let mut latency = latency.split('/');
+        let mut next_latency = || {
+            latency
+                .next()
+                .ok_or_else(|| Error::Protocol("srvr output has too few latencies".to_string()))
+        };
+        let min_latency = parse(next_latency()?, "min latency")?;
+        let avg_latency = parse(next_latency()?, "avg latency")?;
+        let max_latency = parse(next_latency()?, "max latency")?;
+
+        let zxid = get("Zxid")?;
+        let zxid = if zxid.starts_with("0x") {
+            hex(zxid, "zxid")?
+        } else {
+            parse(zxid, "zxid")?
+        };
+
+        Ok(ServerInfo {
+            version: get("Zookeeper version")?.to_string(),
+            min_latency,
+            avg_latency,
+            max_latency,
+            received: parse(get("Received")?, "received")?,
+            sent: parse(get("Sent")?, "sent")?,
+            connections: parse(get("Connections")?, "connections")?,
+            outstanding: parse(get("Outstanding")?, "outstanding")?,
+            zxid,
+            mode: ServerMode::from(get("Mode")?),
+            node_count: parse(get("Node count")?, "node count")?,
+        })
+    }
+}
+
+/// A server's answer to `stat`, which is its answer to `srvr` along with a list of its clients.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerStat {
+    /// Everything that the server also answers `srvr` with.
+    pub info: ServerInfo,
+    /// The clients that are connected to the server.
+    ///
+    /// `stat` describes them in brief, so only their addresses and packet counts are filled in.
+    pub clients: Vec<Connection>,
+}
+
+impl FromStr for ServerStat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut info = String::new();
+        let mut clients = Vec::new();
+        let mut lines = s.lines();
+        while let Some(line) = lines.next() {
+            if line.trim() == "Clients:" {
+                // one client per line, up to the first empty one
+                for client in lines.by_ref().take_while(|l| !l.trim().is_empty()) {
+                    clients.push(client.parse()?);
+                }
+            } else {
+                info.push_str(line);
+                info.push('\n');
+            }
+        }
+        Ok(ServerStat {
+            info: info.parse()?,
+            clients,
+        })
+    }
+}
+
+/// A client's connection to a server, as `cons` and `stat`, or the AdminServer's `connections`,
+/// describe it.
+///
+/// Latencies are in milliseconds, and points in time in milliseconds since the Unix epoch. The
+/// fields that only `cons` reports are `None` for connections that have no session yet.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Connection {
+    /// The client's address, as the server prints it, such as `/127.0.0.1:52178`, or
+    /// `127.0.0.1:52178` from the AdminServer.
+    pub addr: String,
+    /// The number of the client's requests that are queued for processing.
+    pub queued: u64,
+    /// The number of packets the server has received from the client.
+    pub received: u64,
+    /// The number of packets the server has sent to the client.
+    pub sent: u64,
+    /// The ID of the client's session.
+    pub session_id: Option<i64>,
+    /// The last operation that the client asked for, such as `PING` or `GETD`.
+    pub last_operation: Option<String>,
+    /// When the connection was established.
+    pub established: Option<u64>,
+    /// The session timeout that the server agreed to.
+    pub timeout: Option<u64>,
+    /// The xid of the client's last request.
+    pub last_cxid: Option<i64>,
+    /// The last transaction that the client has seen.
+    pub last_zxid: Option<i64>,
+    /// When the server last answered the client.
+    pub last_response: Option<u64>,
+    /// The time the server took to process the client's last request.
+    pub last_latency: Option<u64>,
+    /// The shortest time the server took to process one of the client's requests.
+    pub min_latency: Option<u64>,
+    /// The average time the server took to process one of the client's requests.
+    pub avg_latency: Option<f64>,
+    /// The longest time the server took to process one of the client's requests.
+    pub max_latency: Option<u64>,
+}
+
+impl FromStr for Connection {
+    type Err = Error;
+
+    /// Parse one line of the list of connections, such as
+    /// ` /127.0.0.1:52178[1](queued=0,recved=3,sent=3)`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || Error::Protocol(format!("unexpected connection: {}", s.trim()));
+        let (head, fields) = s
+            .trim()
+            .strip_suffix(')')
+            .and_then(|s| s.split_once("]("))
+            .ok_or_else(bad)?;
+        // the brackets hold the connection's interest set, which says nothing about the client
+        let (addr, _) = head.rsplit_once('[').ok_or_else(bad)?;
+
+        let mut c = Connection {
+            addr: addr.to_string(),
+            ..Connection::default()
+        };
+        for field in fields.split(',') {
+            let (key, value) = field.split_once('=').ok_or_else(bad)?;
+            match key {
+                "queued" => c.queued = parse(value, "queued")?,
+                "recved" => c.received = parse(value, "received")?,
+                "sent" => c.sent = parse(value, "sent")?,
+                "sid" => c.session_id = Some(hex(value, "session id")?),
+                "lop" => c.last_operation = Some(value.to_string()),
+                "est" => c.established = Some(parse(value, "established")?),
+                "to" => c.timeout = Some(parse(value, "timeout")?),
+                "lcxid" => c.last_cxid = Some(hex(value, "last cxid")?),
+                "lzxid" => c.last_zxid = Some(hex(value, "last zxid")?),
+                "lresp" => c.last_response = Some(parse(value, "last response")?),
+                "llat" => c.last_latency = Some(parse(value, "last latency")?),
+                "minlat" => c.min_latency = Some(parse(value, "min latency")?),
+                "avglat" => c.avg_latency = Some(parse(value, "avg latency")?),
+                "maxlat" => c.max_latency = Some(parse(value, "max latency")?),
+                // newer servers may know more about their clients than this version of the crate
+                _ => {}
+            }
+        }
+        Ok(c)
+    }
+}
+
+fn connections(s: &str) -> Result<Vec<Connection>, Error> {
+    s.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+/// A server's answer to `wchs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchSummary {
+    /// The number of connections that have watches set.
+    pub connections: u64,
+    /// The number of paths that are watched.
+    pub paths: u64,
+    /// The number of watches, over all connections and paths.
+    pub watches: u64,
+}
+
+impl FromStr for WatchSummary {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // 1 connections watching 2 paths
+        // Total watches:3
+        let bad = || Error::Protocol(format!("unexpected wchs output: {}", s.trim()));
+        let mut lines = s.lines().filter(|l| !l.trim().is_empty());
+        let summary: Vec<_> = lines.next().ok_or_else(bad)?.split_whitespace().collect();
+        let (connections, paths) = match summary[..] {
+            [connections, "connections", "watching", paths, "paths"] => (connections, paths),
+            _ => return Err(bad()),
+        };
+        let watches = lines
+            .next()
+            .and_then(|l| l.trim().strip_prefix("Total watches:"))
+            .ok_or_else(bad)?;
+        Ok(WatchSummary {
+            connections: parse(connections, "connections")?,
+            paths: parse(paths, "paths")?,
+            watches: parse(watches, "watches")?,
+        })
+    }
+}
+
+fn parse<T>(value: &str, what: &str) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: ::std::fmt::Display,
+{
+    value
+        .parse()
+        .map_err(|e| Error::Protocol(format!("bad {} {:?}: {}", what, value, e)))
+}
+
+/// Parse an id the way that the server prints them: in hex, as unsigned, with `0x` in front.
+fn hex(value: &str, what: &str) -> Result<i64, Error> {
+    value
+        .strip_prefix("0x")
+        .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+        // ids that are -1 when unset are printed as 0xffffffffffffffff
+        .map(|id| id as i64)
+        .ok_or_else(|| Error::Protocol(format!("bad {} {:?}", what, value)))
+}
+
+/// Send the four-letter-word `command` to the server at `addr`, and return its answer.
+pub async fn command(addr: &SocketAddr, command: &'static str) -> Result<String, Error> {
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    stream.write_all(command.as_bytes()).await?;
+    let mut answer = Vec::new();
+    stream.read_to_end(&mut answer).await?;
+    String::from_utf8(answer).map_err(|e| Error::Protocol(e.to_string()))
+}
+
+/// Ask the server at `addr` for its metrics with the `mntr` command.
+pub async fn mntr(addr: &SocketAddr) -> Result<ServerMetrics, Error> {
+    command(addr, "mntr").await?.parse()
+}
+
+/// Ask the server at `addr` for details about itself with the `srvr` command.
+pub async fn srvr(addr: &SocketAddr) -> Result<ServerInfo, Error> {
+    command(addr, "srvr").await?.parse()
+}
+
+/// Ask the server at `addr` whether it is running with the `ruok` command.
+///
+/// A server that is running answers, while one that is in an error state closes the connection
+/// without a word, which makes this `false`.
+pub async fn ruok(addr: &SocketAddr) -> Result<bool, Error> {
+    match command(addr, "ruok").await?.trim() {
+        "imok" => Ok(true),
+        "" => Ok(false),
+        other => Err(Error::Protocol(format!("unexpected ruok output: {}", other))),
+    }
+}
+
+/// Ask the server at `addr` for details about itself and its clients with the `stat` command.
+pub async fn stat(addr: &SocketAddr) -> Result<ServerStat, Error> {
+    command(addr, "stat").await?.parse()
+}
+
+/// Ask the server at `addr` for details about the connections to it with the `cons` command.
+pub async fn cons(addr: &SocketAddr) -> Result<Vec<Connection>, Error> {
+    connections(&command(addr, "cons").await?)
+}
+
+/// Ask the server at `addr` how many watches are set on it with the `wchs` command.
+pub async fn wchs(addr: &SocketAddr) -> Result<WatchSummary, Error> {
+    command(addr, "wchs").await?.parse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mntr() {
+        let leader = "zk_version\t3.4.12-e5259e43, built on 03/27/2018 03:55 GMT
+zk_avg_latency\t0
+zk_max_latency\t12
+zk_min_latency\t0
+zk_packets_received\t70
+zk_packets_sent\t69
+zk_num_alive_connections\t1
+zk_outstanding_requests\t0
+zk_server_state\tleader
+zk_znode_count\t4
+zk_watch_count\t0
+zk_ephemerals_count\t0
+zk_approximate_data_size\t27
+zk_open_file_descriptor_count\t23
+zk_max_file_descriptor_count\t1024
+zk_followers\t2
+zk_synced_followers\t2
+zk_pending_syncs\t0
+";
+        let m: ServerMetrics = leader.parse().unwrap();
+        assert!(m.version.starts_with("3.4.12"));
+        assert_eq!(m.max_latency, 12);
+        assert_eq!(m.packets_received, 70);
+        assert_eq!(m.server_state, ServerMode::Leader);
+        assert_eq!(m.approximate_data_size, 27);
+        assert_eq!(m.max_file_descriptor_count, Some(1024));
+        assert_eq!(m.synced_followers, Some(2));
+        assert!(m.other.is_empty());
+
+        // newer servers report fractional latencies, and many more values
+        let follower = leader
+            .replace("zk_avg_latency\t0", "zk_avg_latency\t0.5")
+            .replace("leader", "follower")
+            .replace(
+                "zk_followers\t2\nzk_synced_followers\t2\nzk_pending_syncs\t0\n",
+                "",
+            )
+            + "zk_uptime\t12345\n";
+        let m: ServerMetrics = follower.parse().unwrap();
+        assert_eq!(m.avg_latency, 0.5);
+        assert_eq!(m.server_state, ServerMode::Follower);
+        assert_eq!(m.followers, None);
+        assert_eq!(m.other.get("uptime").map(|s| &s[..]), Some("12345"));
+
+        assert!("mntr is not executed because it is not in the whitelist.\n"
+            .parse::<ServerMetrics>()
+            .is_err());
+        assert!("zk_version\t3.4.12\n".parse::<ServerMetrics>().is_err());
+    }
+
+    #[test]
+    fn parse_srvr() {
+        let answer = "Zookeeper version: 3.5.5-390fe37e, built on 05/03/2019 12:07 GMT
+Latency min/avg/max: 0/0.25/3
+Received: 5
+Sent: 4
+Connections: 1
+Outstanding: 0
+Zxid: 0x10000002a
+Mode: standalone
+Node count: 5
+";
+        let info: ServerInfo = answer.parse().unwrap();
+        assert!(info.version.starts_with("3.5.5-"));
+        assert_eq!(
+            (info.min_latency, info.avg_latency, info.max_latency),
+            (0, 0.25, 3)
+        );
+        assert_eq!(info.received, 5);
+        assert_eq!(info.zxid, 0x1_0000_002a);
+        assert_eq!(info.mode, ServerMode::Standalone);
+        assert_eq!(info.node_count, 5);
+
+        assert!("srvr is not executed because it is not in the whitelist.\n"
+            .parse::<ServerInfo>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_stat() {
+        let answer = "Zookeeper version: 3.4.12-e5259e43, built on 03/27/2018 03:55 GMT
+Clients:
+ /127.0.0.1:52178[1](queued=0,recved=3,sent=3)
+ /0:0:0:0:0:0:0:1:52180[0](queued=2,recved=1,sent=0)
+
+Latency min/avg/max: 0/0/12
+Received: 70
+Sent: 69
+Connections: 2
+Outstanding: 0
+Zxid: 0x1a
+Mode: leader
+Node count: 4
+";
+        let stat: ServerStat = answer.parse().unwrap();
+        assert_eq!(stat.info.connections, 2);
+        assert_eq!(stat.info.zxid, 0x1a);
+        assert_eq!(stat.info.mode, ServerMode::Leader);
+        let clients: Vec<_> = stat
+            .clients
+            .iter()
+            .map(|c| (&c.addr[..], c.queued, c.received, c.sent))
+            .collect();
+        assert_eq!(
+            clients,
+            [
+                ("/127.0.0.1:52178", 0, 3, 3),
+                ("/0:0:0:0:0:0:0:1:52180", 2, 1, 0)
+            ]
+        );
+        assert_eq!(stat.clients[0].session_id, None);
+
+        assert!("This ZooKeeper instance is not currently serving requests\n"
+            .parse::<ServerStat>()
+            .is_err());
+    }
+
+    #[test]
+    fn parse_cons() {
+        let answer = " /127.0.0.1:52178[1](queued=0,recved=3,sent=3,sid=0x100075a0c5e0000,\
+lop=GETD,est=1530000000000,to=30000,lcxid=0x2,lzxid=0xffffffffffffffff,lresp=1530000000100,\
+llat=1,minlat=0,avglat=0.5,maxlat=1)
+ /127.0.0.1:52180[0](queued=0,recved=1,sent=0)
+
+";
+        let cons = connections(answer).unwrap();
+        assert_eq!(cons.len(), 2);
+        let c = &cons[0];
+        assert_eq!(c.session_id, Some(0x0100_075a_0c5e_0000));
+        assert_eq!(c.last_operation.as_deref(), Some("GETD"));
+        assert_eq!(c.established, Some(1_530_000_000_000));
+        assert_eq!(c.timeout, Some(30_000));
+        assert_eq!(c.last_cxid, Some(2));
+        // a client that has seen no transaction yet
+        assert_eq!(c.last_zxid, Some(-1));
+        assert_eq!(c.last_response, Some(1_530_000_000_100));
+        assert_eq!(
+            (c.last_latency, c.min_latency, c.avg_latency, c.max_latency),
+            (Some(1), Some(0), Some(0.5), Some(1))
+        );
+        // one that has not established its session yet
+        assert_eq!(
+            cons[1],
+            Connection {
+                addr: "/127.0.0.1:52180".to_string(),
+                received: 1,
+                ..Connection::default()
+            }
+        );
+
+        assert!(connections("cons is not executed because it is not in the whitelist.\n").is_err());
+    }
+
+    #[test]
+    fn parse_wchs() {
+        let answer = "1 connections watching 2 paths\nTotal watches:3\n";
+        assert_eq!(
+            answer.parse::<WatchSummary>().unwrap(),
+            WatchSummary {
+                connections: 1,
+                paths: 2,
+                watches: 3,
+            }
+        );
+        assert!("1 connections watching 2 paths\n"
+            .parse::<WatchSummary>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn ruok() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = async {
+            for answer in [&b"imok"[..], b""] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut command = [0; 4];
+                stream.read_exact(&mut command).await.unwrap();
+                assert_eq!(&command, b"ruok");
+                stream.write_all(answer).await.unwrap();
+            }
+        };
+        let client = async {
+            let ok = super::ruok(&addr).await.unwrap();
+            let not_ok = super::ruok(&addr).await.unwrap();
+            (ok, not_ok)
+        };
+        let ((), (ok, not_ok)) = futures::join!(server, client);
+        assert!(ok);
+        assert!(!not_ok);
+    }
+}
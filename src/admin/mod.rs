@@ -0,0 +1,54 @@
+//! Querying servers about themselves, rather than about the data they hold.
+//!
+//! Besides the client protocol, ZooKeeper servers answer a few plain-text commands on the client
+//! port, the "four-letter words". The [`flw`] module sends them, and parses the server's answers.
+//! Since ZooKeeper 3.5, servers also embed an AdminServer that answers much the same questions
+//! over HTTP, which the `rest` module queries with the `admin-server` feature enabled. Since none
+//! of this establishes a session, it can be pointed at any server, for instance by a monitoring
+//! agent or a health check.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::admin;
+//! # async fn run() -> Result<(), tokio_zookeeper::Error> {
+//! let info = admin::flw::srvr(&"127.0.0.1:2181".parse().unwrap()).await?;
+//! println!("{:?} at zxid {:#x}", info.mode, info.zxid);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod flw;
+#[cfg(feature = "admin-server")]
+pub mod rest;
+
+// `mntr` and `srvr` lived here before the four-letter words got a module of their own
+pub use self::flw::{command, mntr, srvr, ServerInfo, ServerMetrics};
+
+/// The role a server plays in its ensemble.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServerMode {
+    /// The server is not part of an ensemble.
+    Standalone,
+    /// The server is the leader of its ensemble.
+    Leader,
+    /// The server follows the leader, and votes on proposals.
+    Follower,
+    /// The server follows the leader, but does not vote.
+    Observer,
+    /// The server has lost contact with a quorum, and only serves reads.
+    ReadOnly,
+    /// A mode that this version of the crate does not know about.
+    Other(String),
+}
+
+impl From<&str> for ServerMode {
+    fn from(mode: &str) -> Self {
+        match mode {
+            "standalone" => ServerMode::Standalone,
+            "leader" => ServerMode::Leader,
+            "follower" => ServerMode::Follower,
+            "observer" => ServerMode::Observer,
+            "read-only" => ServerMode::ReadOnly,
+            other => ServerMode::Other(other.to_string()),
+        }
+    }
+}
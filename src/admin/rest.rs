@@ -0,0 +1,394 @@
+//! Querying the AdminServer that ZooKeeper 3.5 and later embed.
+//!
+//! The AdminServer answers the same questions as the four-letter words, and then some, as JSON
+//! over HTTP, by default on port 8080 under `/commands`. That makes it the way to go where the
+//! four-letter words are disabled, as they are by default since 3.5. [`monitor`] and
+//! [`connections`] parse the answers into the same types as their counterparts [`flw::mntr`] and
+//! [`flw::cons`], and [`dump`] lists the sessions that the server is keeping track of.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::admin::rest;
+//! # async fn run() -> Result<(), tokio_zookeeper::Error> {
+//! let metrics = rest::monitor(&"127.0.0.1:8080".parse().unwrap()).await?;
+//! println!("{} znodes, {} watches", metrics.znode_count, metrics.watch_count);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available with the `admin-server` feature enabled. It speaks plain HTTP,
+//! so AdminServers that only accept HTTPS cannot be queried with it.
+//!
+//! [`flw::mntr`]: super::flw::mntr
+//! [`flw::cons`]: super::flw::cons
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use super::flw::{Connection, ServerMetrics};
+use crate::Error;
+
+/// The sessions that a server is keeping track of, as the AdminServer's `dump` describes them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dump {
+    /// The IDs of the sessions that expire at each point in time, in milliseconds since the Unix
+    /// epoch, unless they hear from their clients before then.
+    ///
+    /// Only the leader of an ensemble knows when sessions expire, so this is empty on others.
+    pub expiry: BTreeMap<u64, Vec<i64>>,
+    /// The paths of the ephemeral nodes that each session owns, by session ID.
+    pub ephemerals: BTreeMap<i64, Vec<String>>,
+}
+
+/// Run the AdminServer command with the given `name` on the AdminServer at `addr`, and return
+/// its answer.
+///
+/// The answer is the JSON object that the server sent, less the `command` and `error` fields.
+/// If the server sends an error instead, the future fails with [`Error::Protocol`] and the
+/// server's explanation.
+pub async fn command(addr: &SocketAddr, name: &str) -> Result<Map<String, Value>, Error> {
+    let body = get(addr, &format!("/commands/{}", name)).await?;
+    answer(&body)
+}
+
+/// Ask the AdminServer at `addr` for its server's metrics with the `monitor` command.
+pub async fn monitor(addr: &SocketAddr) -> Result<ServerMetrics, Error> {
+    metrics(command(addr, "monitor").await?)
+}
+
+/// Ask the AdminServer at `addr` for details about the connections to its server with the
+/// `connections` command.
+///
+/// Both plain and secure connections are returned.
+pub async fn connections(addr: &SocketAddr) -> Result<Vec<Connection>, Error> {
+    connection_list(command(addr, "connections").await?)
+}
+
+/// Ask the AdminServer at `addr` for the sessions and ephemeral nodes that its server is keeping
+/// track of with the `dump` command.
+pub async fn dump(addr: &SocketAddr) -> Result<Dump, Error> {
+    dump_of(command(addr, "dump").await?)
+}
+
+/// Send a `GET` for `path` to the HTTP server at `addr`, and return the body of its response.
+async fn get(addr: &SocketAddr, path: &str) -> Result<Vec<u8>, Error> {
+    let mut stream = tokio::net::TcpStream::connect(addr).await?;
+    // HTTP/1.0, so that the server closes the connection at the end of a body that is neither
+    // chunked nor compressed
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n\r\n",
+        path, addr
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+
+    let end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| Error::Protocol("HTTP response without a body".to_string()))?;
+    let head = String::from_utf8_lossy(&response[..end]);
+    let status = head.lines().next().unwrap_or("");
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(Error::Protocol(format!("AdminServer answered with {:?}", status)));
+    }
+    response.drain(..end + 4);
+    Ok(response)
+}
+
+fn answer(body: &[u8]) -> Result<Map<String, Value>, Error> {
+    let mut answer = match serde_json::from_slice(body) {
+        Ok(Value::Object(answer)) => answer,
+        Ok(other) => {
+            return Err(Error::Protocol(format!("unexpected AdminServer answer: {}", other)))
+        }
+        Err(e) => return Err(Error::Protocol(format!("bad AdminServer answer: {}", e))),
+    };
+    answer.remove("command");
+    match answer.remove("error") {
+        None | Some(Value::Null) => Ok(answer),
+        Some(Value::String(e)) => Err(Error::Protocol(e)),
+        Some(e) => Err(Error::Protocol(e.to_string())),
+    }
+}
+
+fn metrics(answer: Map<String, Value>) -> Result<ServerMetrics, Error> {
+    let values = answer
+        .into_iter()
+        .filter_map(|(key, value)| match value {
+            Value::Null => None,
+            Value::String(s) => Some((key, s)),
+            value => Some((key, value.to_string())),
+        })
+        .collect();
+    ServerMetrics::from_values(values)
+}
+
+fn connection_list(mut answer: Map<String, Value>) -> Result<Vec<Connection>, Error> {
+    let mut connections = Vec::new();
+    for key in &["connections", "secure_connections"] {
+        match answer.remove(*key) {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(list)) => {
+                for c in list {
+                    connections.push(connection(&c)?);
+                }
+            }
+            Some(other) => return Err(unexpected(key, &other)),
+        }
+    }
+    Ok(connections)
+}
+
+fn connection(c: &Value) -> Result<Connection, Error> {
+    let number = |key: &str| match c.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_u64().map(Some).ok_or_else(|| unexpected(key, value)),
+    };
+    let signed = |key: &str| match c.get(key) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_i64().map(Some).ok_or_else(|| unexpected(key, value)),
+    };
+    let required = |key: &str| {
+        number(key)?.ok_or_else(|| Error::Protocol(format!("connection is missing {}", key)))
+    };
+    let addr = match c.get("remote_socket_address") {
+        Some(Value::String(addr)) => addr.clone(),
+        Some(other) => return Err(unexpected("remote_socket_address", other)),
+        None => return Err(Error::Protocol("connection without an address".to_string())),
+    };
+
+    let mut connection = Connection {
+        addr,
+        queued: required("outstanding_requests")?,
+        received: required("packets_received")?,
+        sent: required("packets_sent")?,
+        ..Connection::default()
+    };
+    // like `cons`, only say more about connections that have a session
+    match signed("session_id")? {
+        None | Some(0) => return Ok(connection),
+        id => connection.session_id = id,
+    }
+    connection.last_operation = c.get("last_operation").and_then(Value::as_str).map(Into::into);
+    connection.established = number("established")?;
+    connection.timeout = number("session_timeout")?;
+    connection.last_cxid = signed("last_cxid")?;
+    connection.last_zxid = signed("last_zxid")?;
+    connection.last_response = number("last_response_time")?;
+    connection.last_latency = number("last_latency")?;
+    connection.min_latency = number("min_latency")?;
+    connection.avg_latency = c.get("avg_latency").and_then(Value::as_f64);
+    connection.max_latency = number("max_latency")?;
+    Ok(connection)
+}
+
+fn dump_of(mut answer: Map<String, Value>) -> Result<Dump, Error> {
+    fn map<K, V>(
+        answer: &mut Map<String, Value>,
+        key: &str,
+        element: impl Fn(&Value) -> Option<V>,
+    ) -> Result<BTreeMap<K, Vec<V>>, Error>
+    where
+        K: std::str::FromStr + Ord,
+    {
+        let object = match answer.remove(key) {
+            None | Some(Value::Null) => return Ok(BTreeMap::new()),
+            Some(Value::Object(object)) => object,
+            Some(other) => return Err(unexpected(key, &other)),
+        };
+        object
+            .iter()
+            .map(|(k, list)| {
+                let bad = || unexpected(key, list);
+                let k = k.parse().map_err(|_| bad())?;
+                let list = list.as_array().ok_or_else(bad)?;
+                let list = list.iter().map(|v| element(v).ok_or_else(bad));
+                Ok((k, list.collect::<Result<_, _>>()?))
+            })
+            .collect()
+    }
+
+    Ok(Dump {
+        expiry: map(&mut answer, "expiry_time_to_session_ids", Value::as_i64)?,
+        ephemerals: map(&mut answer, "session_id_to_ephemeral_paths", |v| {
+            v.as_str().map(Into::into)
+        })?,
+    })
+}
+
+fn unexpected(key: &str, value: &Value) -> Error {
+    Error::Protocol(format!("unexpected {} in AdminServer answer: {}", key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::ServerMode;
+
+    fn parse(body: &str) -> Map<String, Value> {
+        answer(body.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn monitor() {
+        let m = metrics(parse(
+            r#"{
+  "version" : "3.5.5-390fe37e, built on 05/03/2019 12:07 GMT",
+  "avg_latency" : 0.5,
+  "max_latency" : 3,
+  "min_latency" : 0,
+  "packets_received" : 12,
+  "packets_sent" : 11,
+  "num_alive_connections" : 1,
+  "outstanding_requests" : 0,
+  "server_state" : "standalone",
+  "znode_count" : 5,
+  "watch_count" : 1,
+  "ephemerals_count" : 0,
+  "approximate_data_size" : 44,
+  "open_file_descriptor_count" : 67,
+  "max_file_descriptor_count" : 1048576,
+  "last_client_response_size" : -1,
+  "command" : "monitor",
+  "error" : null
+}"#,
+        ))
+        .unwrap();
+        assert!(m.version.starts_with("3.5.5-"));
+        assert_eq!(m.avg_latency, 0.5);
+        assert_eq!(m.packets_received, 12);
+        assert_eq!(m.server_state, ServerMode::Standalone);
+        assert_eq!(m.max_file_descriptor_count, Some(1_048_576));
+        assert_eq!(m.followers, None);
+        let other: Vec<_> = m.other.iter().collect();
+        assert_eq!(
+            other,
+            [(&"last_client_response_size".to_string(), &"-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let e = answer(br#"{"command":"monitor","error":"Command not found"}"#);
+        assert!(matches!(e, Err(Error::Protocol(ref e)) if e == "Command not found"));
+        assert!(answer(b"<html>").is_err());
+        assert!(answer(b"[]").is_err());
+    }
+
+    #[test]
+    fn connections() {
+        let cons = connection_list(parse(
+            r#"{
+  "connections" : [ {
+    "remote_socket_address" : "127.0.0.1:52178",
+    "interest_ops" : 1,
+    "outstanding_requests" : 0,
+    "packets_received" : 3,
+    "packets_sent" : 3,
+    "session_id" : 72065677373865984,
+    "last_operation" : "GETD",
+    "established" : 1530000000000,
+    "session_timeout" : 30000,
+    "last_cxid" : 2,
+    "last_zxid" : -1,
+    "last_response_time" : 1530000000100,
+    "last_latency" : 1,
+    "min_latency" : 0,
+    "avg_latency" : 0,
+    "max_latency" : 1
+  } ],
+  "secure_connections" : [ {
+    "remote_socket_address" : "127.0.0.1:52180",
+    "interest_ops" : 0,
+    "outstanding_requests" : 0,
+    "packets_received" : 1,
+    "packets_sent" : 0,
+    "session_id" : 0
+  } ],
+  "command" : "connections",
+  "error" : null
+}"#,
+        ))
+        .unwrap();
+        assert_eq!(cons.len(), 2);
+        let c = &cons[0];
+        assert_eq!(c.addr, "127.0.0.1:52178");
+        assert_eq!((c.queued, c.received, c.sent), (0, 3, 3));
+        assert_eq!(c.session_id, Some(0x0100_075a_0c5e_0000));
+        assert_eq!(c.last_operation.as_deref(), Some("GETD"));
+        assert_eq!(c.timeout, Some(30_000));
+        assert_eq!(c.last_zxid, Some(-1));
+        assert_eq!(c.avg_latency, Some(0.0));
+        assert_eq!(
+            cons[1],
+            Connection {
+                addr: "127.0.0.1:52180".to_string(),
+                received: 1,
+                ..Connection::default()
+            }
+        );
+
+        let missing = parse(r#"{"connections":[{"remote_socket_address":"127.0.0.1:52178"}]}"#);
+        assert!(connection_list(missing).is_err());
+    }
+
+    #[test]
+    fn dump() {
+        let d = dump_of(parse(
+            r#"{
+  "expiry_time_to_session_ids" : {
+    "1530000030000" : [ 72065677373865984, 72065677373865985 ]
+  },
+  "session_id_to_ephemeral_paths" : {
+    "72065677373865984" : [ "/lock/a", "/lock/b" ]
+  },
+  "command" : "dump",
+  "error" : null
+}"#,
+        ))
+        .unwrap();
+        let id = 0x0100_075a_0c5e_0000;
+        assert_eq!(
+            d.expiry.into_iter().collect::<Vec<_>>(),
+            [(1_530_000_030_000, vec![id, id + 1])]
+        );
+        assert_eq!(
+            d.ephemerals.into_iter().collect::<Vec<_>>(),
+            [(id, vec!["/lock/a".to_string(), "/lock/b".to_string()])]
+        );
+
+        assert_eq!(dump_of(parse("{}")).unwrap(), Dump::default());
+    }
+
+    #[tokio::test]
+    async fn http() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = async {
+            for response in [
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"watch_count\":1}",
+                "HTTP/1.1 404 Not Found\r\n\r\n",
+            ] {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                while !request.ends_with(b"\r\n\r\n") {
+                    let mut byte = [0];
+                    stream.read_exact(&mut byte).await.unwrap();
+                    request.push(byte[0]);
+                }
+                let request = String::from_utf8(request).unwrap();
+                assert!(request.starts_with("GET /commands/monitor HTTP/1.0\r\n"));
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        };
+        let client = async {
+            let found = command(&addr, "monitor").await.unwrap();
+            let not_found = command(&addr, "monitor").await;
+            (found, not_found)
+        };
+        let ((), (found, not_found)) = futures::join!(server, client);
+        assert_eq!(found.get("watch_count"), Some(&Value::from(1)));
+        assert!(matches!(not_found, Err(Error::Protocol(_))));
+    }
+}
@@ -0,0 +1,163 @@
+//! Auditing the ACLs of a subtree against a policy.
+//!
+//! Since ZooKeeper ACLs are not inherited, a single node with an overly permissive ACL is enough
+//! to expose its data, no matter how well its ancestors are protected. [`ZooKeeper::audit_acls`]
+//! checks every node in a subtree against an [`AclPolicy`], and reports the nodes that violate it
+//! in an [`AuditReport`].
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use tokio_zookeeper::audit::NoWorldWritable;
+//! # async fn run(zk: ZooKeeper) -> Result<(), Error> {
+//! let report = zk.audit_acls("/app", NoWorldWritable).await?;
+//! for violation in report.map(|r| r.violations).unwrap_or_default() {
+//!     eprintln!("{}: {}", violation.path, violation.problems.join(", "));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::prelude::*;
+use futures::{future, stream};
+use crate::subtree::CONCURRENCY;
+use crate::{Acl, Error, Permission, ZooKeeper};
+
+/// A rule that the ACLs of audited nodes are expected to follow.
+///
+/// Closures that take a node's path and ACL and return a list of problems also implement this
+/// trait.
+pub trait AclPolicy {
+    /// Check `acl`, the ACL of the node at `path`, returning a description of every way in which
+    /// it violates this policy.
+    fn check(&self, path: &str, acl: &[Acl]) -> Vec<String>;
+}
+
+impl<F> AclPolicy for F
+where
+    F: Fn(&str, &[Acl]) -> Vec<String>,
+{
+    fn check(&self, path: &str, acl: &[Acl]) -> Vec<String> {
+        self(path, acl)
+    }
+}
+
+/// A policy that flags ACL entries allowing anyone (`world:anyone`) to modify a node, that is,
+/// entries that grant `WRITE`, `CREATE`, `DELETE`, or `ADMIN` to everyone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NoWorldWritable;
+
+impl AclPolicy for NoWorldWritable {
+    fn check(&self, _: &str, acl: &[Acl]) -> Vec<String> {
+        let modify =
+            Permission::WRITE | Permission::CREATE | Permission::DELETE | Permission::ADMIN;
+        acl.iter()
+            .filter(|entry| entry.scheme == "world" && entry.id == "anyone")
+            .filter(|entry| entry.perms & modify != Permission::NONE)
+            .map(|entry| format!("world:anyone is granted {}", entry.perms))
+            .collect()
+    }
+}
+
+/// A node whose ACL violates the audited policy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    /// The path of the node.
+    pub path: String,
+    /// The node's ACL.
+    pub acl: Vec<Acl>,
+    /// The problems the policy found with the ACL.
+    pub problems: Vec<String>,
+}
+
+/// The result of [`ZooKeeper::audit_acls`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuditReport {
+    /// The number of nodes whose ACL was checked.
+    pub checked: usize,
+    /// The nodes whose ACL violates the policy, in breadth-first order.
+    pub violations: Vec<Violation>,
+}
+
+impl AuditReport {
+    /// Whether no violations were found.
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+impl ZooKeeper {
+    /// Check the ACL of the node at the given `path` and of all of its descendants against
+    /// `policy`, or return `None` if the node does not exist.
+    ///
+    /// Nodes that are deleted while the audit is in progress are left out of the report.
+    pub async fn audit_acls<P>(&self, path: &str, policy: P) -> Result<Option<AuditReport>, Error>
+    where
+        P: AclPolicy,
+    {
+        trace!(self.logger, "audit_acls"; "path" => path);
+        let paths = match self.list_subtree(path).await? {
+            Some(paths) => paths,
+            None => return Ok(None),
+        };
+
+        let report = stream::iter(paths)
+            .map(|path| async move {
+                let res = self.get_acl(&path).await?;
+                Ok::<_, Error>((path, res))
+            })
+            .buffered(CONCURRENCY)
+            .try_fold(AuditReport::default(), |mut report, (path, res)| {
+                if let Ok((acl, _)) = res {
+                    report.checked += 1;
+                    let problems = policy.check(&path, &acl);
+                    if !problems.is_empty() {
+                        report.violations.push(Violation {
+                            path,
+                            acl,
+                            problems,
+                        });
+                    }
+                }
+                future::ok(report)
+            })
+            .await?;
+        Ok(Some(report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_writable() {
+        assert!(NoWorldWritable.check("/", Acl::read_unsafe()).is_empty());
+        assert!(NoWorldWritable.check("/", Acl::creator_all()).is_empty());
+        assert_eq!(
+            NoWorldWritable.check("/", Acl::open_unsafe()),
+            ["world:anyone is granted ALL"]
+        );
+        let acl = [Acl::new(
+            Permission::READ | Permission::CREATE,
+            "world",
+            "anyone",
+        )];
+        assert_eq!(
+            NoWorldWritable.check("/", &acl),
+            ["world:anyone is granted READ|CREATE"]
+        );
+    }
+
+    #[test]
+    fn closure_policy() {
+        let policy = |path: &str, acl: &[Acl]| {
+            if path.starts_with("/secret") && acl.iter().any(|e| e.scheme == "world") {
+                vec![String::from("secrets must not be world-accessible")]
+            } else {
+                Vec::new()
+            }
+        };
+        assert_eq!(policy.check("/secret/a", Acl::read_unsafe()).len(), 1);
+        assert!(policy.check("/public", Acl::read_unsafe()).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+//! An interactive shell for ZooKeeper, in the spirit of the `zkCli.sh` that ships with it.
+//!
+//! ```text
+//! zk-shell [-server CONNECT-STRING | -config FILE] [COMMAND [ARGUMENT...]]
+//! ```
+//!
+//! The shell connects to the ensemble of the connection string, `localhost:2181` by default, or
+//! the one of a configuration file (see `tokio_zookeeper::config`). If a command is given, it is
+//! run and the shell exits; otherwise commands are read from standard input, with a history and
+//! tab completion of commands and paths when it is a terminal. Run `help` for the commands.
+
+use futures::StreamExt;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Editor, Helper};
+use tokio::runtime::Runtime;
+use tokio_zookeeper::config::ClientConfig;
+use tokio_zookeeper::*;
+
+/// The commands, and how to use them.
+const COMMANDS: &[(&str, &str)] = &[
+    ("ls", "ls <path>: list the children of a node"),
+    ("get", "get <path>: print the data of a node"),
+    ("set", "set <path> <data> [version]: set the data of a node"),
+    (
+        "create",
+        "create [-e] [-s] <path> [data]: create a node, ephemeral with -e, sequential with -s",
+    ),
+    ("delete", "delete <path> [version]: delete a node, which must not have children"),
+    ("stat", "stat <path>: print the stat of a node"),
+    ("getAcl", "getAcl <path>: print the ACL of a node"),
+    ("watch", "watch <path>: print the next change to a node, and to its children"),
+    ("help", "help: print this list"),
+    ("quit", "quit: leave the shell"),
+];
+
+fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    let mut connect = None;
+    let mut config = None;
+    match args.peek().map(String::as_str) {
+        Some("-server") => {
+            args.next();
+            connect = args.next();
+        }
+        Some("-config") => {
+            args.next();
+            config = args.next();
+        }
+        _ => {}
+    }
+    let command: Vec<String> = args.collect();
+
+    let config = match config {
+        Some(path) => ClientConfig::load(path).map_err(|e| e.to_string()),
+        None => connect
+            .as_deref()
+            .unwrap_or("localhost:2181")
+            .parse::<ConnectString>()
+            .map(ClientConfig::from)
+            .map_err(|e| e.to_string()),
+    };
+    let config = config.unwrap_or_else(|e| exit(&format!("invalid configuration: {}", e)));
+
+    let rt = Runtime::new().unwrap_or_else(|e| exit(&format!("failed to start runtime: {}", e)));
+    let (zk, mut events) = rt
+        .block_on(config.connect())
+        .unwrap_or_else(|e| exit(&format!("failed to connect to {}: {}", config.connect, e)));
+    rt.spawn(async move {
+        while let Some(event) = events.next().await {
+            println!(
+                "WATCHER:: {:?} {} ({:?})",
+                event.event_type, event.path, event.keeper_state
+            );
+        }
+    });
+
+    if !command.is_empty() {
+        if let Err(e) = rt.block_on(execute(&zk, &command)) {
+            exit(&e);
+        }
+        return;
+    }
+
+    let mut editor: Editor<Shell, DefaultHistory> = Editor::new()
+        .unwrap_or_else(|e| exit(&format!("failed to read from the terminal: {}", e)));
+    editor.set_helper(Some(Shell { rt: &rt, zk: &zk }));
+    loop {
+        // the terminal is only in raw mode while a line is read, so that ctrl-c still interrupts
+        // a command that hangs, and abandons the line otherwise
+        let line = match editor.readline("zk> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("zk-shell: failed to read command: {}", e);
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.as_str());
+        }
+        let args = split(&line);
+        match args.first().map(String::as_str) {
+            None => continue,
+            Some("quit") | Some("exit") => break,
+            Some(_) => {
+                if let Err(e) = rt.block_on(execute(&zk, &args)) {
+                    println!("{}", e);
+                }
+            }
+        }
+    }
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("zk-shell: {}", message);
+    std::process::exit(1)
+}
+
+/// Split a command line into words, of which those in double quotes may contain spaces.
+fn split(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+async fn execute(zk: &ZooKeeper, args: &[String]) -> Result<(), String> {
+    let usage = || {
+        let (_, usage) = COMMANDS.iter().find(|(name, _)| *name == args[0]).unwrap();
+        format!("usage: {}", usage)
+    };
+    let version = |arg: Option<&String>| match arg {
+        Some(version) => version.parse().map(Some).map_err(|_| usage()),
+        None => Ok(None),
+    };
+    let no_node = |path: &str| format!("node does not exist: {}", path);
+    match (args[0].as_str(), &args[1..]) {
+        ("ls", [path]) => {
+            let mut children = zk.get_children(path).await.map_err(|e| e.to_string())?;
+            let children = children.as_mut().ok_or_else(|| no_node(path))?;
+            children.sort();
+            println!("[{}]", children.join(", "));
+        }
+        ("get", [path]) => {
+            let data = zk.get_data(path).await.map_err(|e| e.to_string())?;
+            let (data, _) = data.ok_or_else(|| no_node(path))?;
+            println!("{}", String::from_utf8_lossy(&data));
+        }
+        ("set", [path, data, rest @ ..]) if rest.len() <= 1 => {
+            let version = version(rest.first())?;
+            let set = zk.set_data(path, version, data.clone().into_bytes()).await;
+            set.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+        }
+        ("create", args) => {
+            let (mut ephemeral, mut sequential) = (false, false);
+            let mut args = args.iter().peekable();
+            while let Some(flag) = args.next_if(|arg| arg.starts_with('-')) {
+                match flag.as_str() {
+                    "-e" => ephemeral = true,
+                    "-s" => sequential = true,
+                    _ => return Err(usage()),
+                }
+            }
+            let mode = match (ephemeral, sequential) {
+                (false, false) => CreateMode::Persistent,
+                (true, false) => CreateMode::Ephemeral,
+                (false, true) => CreateMode::PersistentSequential,
+                (true, true) => CreateMode::EphemeralSequential,
+            };
+            let (path, data) = match (args.next(), args.next(), args.next()) {
+                (Some(path), data, None) => (path, data.map_or("", String::as_str)),
+                _ => return Err(usage()),
+            };
+            let data = data.as_bytes().to_vec();
+            let created = zk.create(path, data, Acl::open_unsafe(), mode).await;
+            let created = created.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+            println!("created {}", created);
+        }
+        ("delete", [path, rest @ ..]) if rest.len() <= 1 => {
+            let version = version(rest.first())?;
+            let delete = zk.delete(path, version).await;
+            delete.map_err(|e| e.to_string())?.map_err(|e| e.to_string())?;
+        }
+        ("stat", [path]) => {
+            let stat = zk.exists(path).await.map_err(|e| e.to_string())?;
+            let stat = stat.ok_or_else(|| no_node(path))?;
+            println!("cZxid = {:#x}", stat.czxid);
+            println!("ctime = {}", stat.ctime);
+            println!("mZxid = {:#x}", stat.mzxid);
+            println!("mtime = {}", stat.mtime);
+            println!("pZxid = {:#x}", stat.pzxid);
+            println!("cversion = {}", stat.cversion);
+            println!("dataVersion = {}", stat.version);
+            println!("aclVersion = {}", stat.aversion);
+            println!("ephemeralOwner = {:#x}", stat.ephemeral_owner);
+            println!("dataLength = {}", stat.data_length);
+            println!("numChildren = {}", stat.num_children);
+        }
+        ("getAcl", [path]) => {
+            let acl = zk.get_acl(path).await.map_err(|e| e.to_string())?;
+            let (acl, _) = acl.map_err(|e| e.to_string())?;
+            for acl in acl {
+                println!("{}", acl);
+            }
+        }
+        ("watch", [path]) => {
+            let stat = zk.watch().exists(path).await.map_err(|e| e.to_string())?;
+            if stat.is_some() {
+                zk.watch().get_children(path).await.map_err(|e| e.to_string())?;
+            }
+        }
+        ("help", []) => {
+            for (_, usage) in COMMANDS {
+                println!("{}", usage);
+            }
+        }
+        (command, _) if COMMANDS.iter().any(|(name, _)| *name == command) => return Err(usage()),
+        (command, _) => return Err(format!("unknown command {:?}, see `help`", command)),
+    }
+    Ok(())
+}
+
+/// The completions of the last word of `line`: where in the line the word starts, and what could
+/// replace it. Commands complete to themselves followed by a space, and paths to the paths of the
+/// children of their parent node.
+fn complete(rt: &Runtime, zk: &ZooKeeper, line: &str) -> (usize, Vec<String>) {
+    let start = line.rfind(' ').map_or(0, |i| i + 1);
+    let word = &line[start..];
+    if start == 0 {
+        let commands = COMMANDS.iter().map(|(name, _)| *name);
+        let matches = commands.filter(|name| name.starts_with(word));
+        return (start, matches.map(|name| format!("{} ", name)).collect());
+    }
+    let parent_end = match word.rfind('/') {
+        Some(i) if word.starts_with('/') => i,
+        _ => return (start, Vec::new()),
+    };
+    let parent = if parent_end == 0 { "/" } else { &word[..parent_end] };
+    let prefix = &word[parent_end + 1..];
+    let mut children = match rt.block_on(zk.get_children(parent)) {
+        Ok(Some(children)) => children,
+        _ => return (start, Vec::new()),
+    };
+    children.sort();
+    let base = &word[..parent_end];
+    let matches = children.into_iter().filter(|child| child.starts_with(prefix));
+    (start, matches.map(|child| format!("{}/{}", base, child)).collect())
+}
+
+/// Completes commands and paths for the line editor.
+struct Shell<'a> {
+    rt: &'a Runtime,
+    zk: &'a ZooKeeper,
+}
+
+impl Completer for Shell<'_> {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(self.rt, self.zk, &line[..pos]))
+    }
+}
+
+impl Hinter for Shell<'_> {
+    type Hint = String;
+}
+
+impl Highlighter for Shell<'_> {}
+
+impl Validator for Shell<'_> {}
+
+impl Helper for Shell<'_> {}
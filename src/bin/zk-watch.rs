@@ -0,0 +1,229 @@
+//! Prints the changes to a node, or to every node of a subtree, as JSON lines.
+//!
+//! ```text
+//! zk-watch [-server CONNECT-STRING | -config FILE] [-r] PATH
+//! ```
+//!
+//! The tool connects to the ensemble of the connection string, `localhost:2181` by default, or
+//! the one of a configuration file (see `tokio_zookeeper::config`), and watches the node at
+//! `PATH`, its data and its children. Every event, and every change to the state of the session,
+//! is printed on a line of its own, as a JSON object with the fields of a `WatchedEvent` and the
+//! time it was received:
+//!
+//! ```text
+//! {"time_ms":1700000000000,"event_type":"NodeCreated","keeper_state":"SyncConnected","path":"/a"}
+//! ```
+//!
+//! Watches fire once, so the tool sets them again after each event; a change that happens
+//! between an event and the watch being set again is reported by the next event, rather than on
+//! its own.
+//!
+//! With `-r`, the tool instead sets a persistent recursive watch on `PATH`, which needs ZooKeeper
+//! 3.6 or later, and prints the creation, deletion and data change of every node below it too,
+//! each with the `zxid` of the change. Changes made while the session is disconnected are not
+//! reported, so a line with a `keeper_state` of `Disconnected` means that changes may have been
+//! missed until the next one of `SyncConnected`.
+//!
+//! The tool exits when the session expires.
+
+use futures::future;
+use futures::stream::{self, StreamExt};
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::runtime::Runtime;
+use tokio_zookeeper::config::ClientConfig;
+use tokio_zookeeper::journal::{ChangeType, Entry};
+use tokio_zookeeper::*;
+
+/// An event, as it is printed.
+#[derive(serde::Serialize)]
+struct Line<'a> {
+    /// When the event was received, in milliseconds since the Unix epoch.
+    time_ms: u128,
+    #[serde(flatten)]
+    event: &'a WatchedEvent,
+    /// The zxid of the change, for the changes that a recursive watch reports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zxid: Option<i64>,
+}
+
+/// The kind of a watch that is set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Kind {
+    Data,
+    Children,
+}
+
+/// Sets one-shot watches on a node, and sets them again as they fire.
+struct Watcher {
+    zk: ZooKeeper,
+    /// The watches that are set, and have not fired yet.
+    armed: HashSet<(Kind, String)>,
+}
+
+impl Watcher {
+    /// Set the watches on the node at `path` that are not set.
+    async fn arm(&mut self, path: &str) -> Result<(), Error> {
+        if !self.armed.contains(&(Kind::Data, path.to_string())) {
+            // an exists watch is left even if the node does not exist, and fires when it is
+            // created
+            self.zk.watch().exists(path).await?;
+            self.armed.insert((Kind::Data, path.to_string()));
+        }
+        if !self.armed.contains(&(Kind::Children, path.to_string()))
+            && self.zk.watch().get_children(path).await?.is_some()
+        {
+            self.armed.insert((Kind::Children, path.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Print `event`, and set the watches that it fired again.
+    async fn handle(&mut self, event: WatchedEvent) -> Result<(), Error> {
+        let path = event.path.clone();
+        let fired = match event.event_type {
+            WatchedEventType::None => true,
+            WatchedEventType::NodeCreated
+            | WatchedEventType::NodeDataChanged
+            | WatchedEventType::DataWatchRemoved => {
+                self.armed.remove(&(Kind::Data, path.clone()))
+            }
+            WatchedEventType::NodeChildrenChanged | WatchedEventType::ChildWatchRemoved => {
+                self.armed.remove(&(Kind::Children, path.clone()))
+            }
+            // a deletion fires both of the node's watches, and is printed once
+            WatchedEventType::NodeDeleted => {
+                let data = self.armed.remove(&(Kind::Data, path.clone()));
+                self.armed.remove(&(Kind::Children, path.clone())) || data
+            }
+        };
+        if fired {
+            print(&event, None);
+        }
+        match event.event_type {
+            WatchedEventType::None => Ok(()),
+            _ => self.arm(&path).await,
+        }
+    }
+}
+
+fn print(event: &WatchedEvent, zxid: Option<i64>) {
+    let time_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis());
+    let line = Line {
+        time_ms,
+        event,
+        zxid,
+    };
+    println!("{}", serde_json::to_string(&line).expect("events serialize to JSON"));
+}
+
+fn exit(message: &str) -> ! {
+    eprintln!("zk-watch: {}", message);
+    std::process::exit(1)
+}
+
+fn main() {
+    let usage = "usage: zk-watch [-server CONNECT-STRING | -config FILE] [-r] PATH";
+    let mut args = std::env::args().skip(1);
+    let mut connect = None;
+    let mut config = None;
+    let mut recursive = false;
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-server" => connect = Some(args.next().unwrap_or_else(|| exit(usage))),
+            "-config" => config = Some(args.next().unwrap_or_else(|| exit(usage))),
+            "-r" => recursive = true,
+            _ if path.is_none() && arg.starts_with('/') => path = Some(arg),
+            _ => exit(usage),
+        }
+    }
+    let path = path.unwrap_or_else(|| exit(usage));
+
+    let config = match config {
+        Some(path) => ClientConfig::load(path).map_err(|e| e.to_string()),
+        None => connect
+            .as_deref()
+            .unwrap_or("localhost:2181")
+            .parse::<ConnectString>()
+            .map(ClientConfig::from)
+            .map_err(|e| e.to_string()),
+    };
+    let config = config.unwrap_or_else(|e| exit(&format!("invalid configuration: {}", e)));
+
+    let rt = Runtime::new().unwrap_or_else(|e| exit(&format!("failed to start runtime: {}", e)));
+    let result = rt.block_on(async {
+        let (zk, _) = config.connect().await?;
+        // a watcher of its own reports paths relative to the chroot, as they were watched
+        let (zk, events) = zk.with_own_watcher();
+        if recursive {
+            watch_subtree(&zk, &path, events).await
+        } else {
+            watch_node(zk, &path, events).await
+        }
+    });
+    if let Err(e) = result {
+        exit(&e.to_string());
+    }
+}
+
+async fn watch_node(
+    zk: ZooKeeper,
+    path: &str,
+    mut events: WatchedEventStream,
+) -> Result<(), Error> {
+    let mut watcher = Watcher {
+        zk,
+        armed: HashSet::new(),
+    };
+    watcher.arm(path).await?;
+    while let Some(event) = events.next().await {
+        let expired = event.keeper_state == KeeperState::Expired;
+        watcher.handle(event).await?;
+        if expired {
+            return Err(Error::SessionExpired);
+        }
+    }
+    Ok(())
+}
+
+async fn watch_subtree(
+    zk: &ZooKeeper,
+    path: &str,
+    events: WatchedEventStream,
+) -> Result<(), Error> {
+    // the journal reports the changes, and the handle's own watcher the state of the session
+    let changes = zk.change_journal(path).await?.map(Ok);
+    let session = events
+        .filter(|event| future::ready(event.event_type == WatchedEventType::None))
+        .map(Err);
+    let mut items = stream::select(changes, session);
+    while let Some(item) = items.next().await {
+        match item {
+            Ok(Entry::Change { zxid, path, change }) => {
+                let event_type = match change {
+                    ChangeType::Created => WatchedEventType::NodeCreated,
+                    ChangeType::Deleted => WatchedEventType::NodeDeleted,
+                    ChangeType::DataChanged => WatchedEventType::NodeDataChanged,
+                };
+                let event = WatchedEvent {
+                    event_type,
+                    keeper_state: KeeperState::SyncConnected,
+                    path,
+                };
+                print(&event, Some(zxid));
+            }
+            // the session's events report the disconnection that caused it
+            Ok(Entry::Gap { .. }) => {}
+            Err(event) => {
+                print(&event, None);
+                if event.keeper_state == KeeperState::Expired {
+                    return Err(Error::SessionExpired);
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,234 @@
+//! A synchronous client, for code that cannot be async.
+//!
+//! [`ZooKeeper`] owns a small Tokio runtime that drives the connection in the background, and
+//! runs every operation to completion on the calling thread before returning it. Its methods must
+//! not be called from within an async context, since blocking there would stall the executor; an
+//! async application should use [`crate::ZooKeeper`] directly.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! let (zk, _) = blocking::ZooKeeper::connect(&"127.0.0.1:2181".parse().unwrap())?;
+//! if let Some((data, _)) = zk.get_data("/config")? {
+//!     println!("{} bytes of config", data.len());
+//! }
+//! # Ok::<(), Error>(())
+//! ```
+//!
+//! This module is only available with the `blocking` feature enabled.
+
+use futures::StreamExt;
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime;
+use tokio::task::JoinHandle;
+use crate::{
+    error, Acl, CreateMode, Error, Stat, WatchedEvent, WatchedEventStream, ZooKeeperBuilder,
+};
+
+/// How long dropping the last handle waits for the session to be closed before giving up on it.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The runtime, and the task on it that drives the connection, shared by a client and its
+/// [`Watcher`].
+struct Shared {
+    runtime: runtime::Runtime,
+    driver: Mutex<Option<JoinHandle<Result<(), Error>>>>,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // every handle is gone by now, so the driver closes the session and then resolves; wait
+        // for that rather than leave the server to expire the session later
+        if let Some(driver) = self.driver.lock().unwrap().take() {
+            let _ = self
+                .runtime
+                .block_on(async { tokio::time::timeout(CLOSE_TIMEOUT, driver).await });
+        }
+    }
+}
+
+/// A synchronous client for a ZooKeeper session.
+///
+/// Each method blocks until the corresponding method of [`crate::ZooKeeper`] resolves, and returns
+/// what it resolves with. The session is closed once this and all its clones have been dropped.
+#[derive(Clone)]
+pub struct ZooKeeper {
+    zk: crate::ZooKeeper,
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for ZooKeeper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZooKeeper").field("zk", &self.zk).finish()
+    }
+}
+
+/// The events of watches set on a session, received by blocking.
+///
+/// The iterator ends once the session has been closed.
+pub struct Watcher {
+    events: WatchedEventStream,
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for Watcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watcher")
+            .field("events", &self.events)
+            .finish()
+    }
+}
+
+impl Iterator for Watcher {
+    type Item = WatchedEvent;
+
+    fn next(&mut self) -> Option<WatchedEvent> {
+        self.shared.runtime.block_on(self.events.next())
+    }
+}
+
+impl ZooKeeper {
+    /// Connect to a ZooKeeper server instance at the given address with default parameters.
+    ///
+    /// See [`ZooKeeperBuilder::connect`].
+    pub fn connect(addr: &SocketAddr) -> Result<(Self, Watcher), Error> {
+        Self::connect_with(ZooKeeperBuilder::default(), addr)
+    }
+
+    /// Connect to a ZooKeeper server instance at the given address with the parameters of
+    /// `builder`.
+    ///
+    /// See [`ZooKeeperBuilder::connect`].
+    pub fn connect_with(
+        builder: ZooKeeperBuilder,
+        addr: &SocketAddr,
+    ) -> Result<(Self, Watcher), Error> {
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("tokio-zookeeper")
+            .enable_all()
+            .build()?;
+        let (zk, events, driver) = runtime.block_on(builder.connect_with_driver(addr))?;
+        let driver = runtime.spawn(driver);
+        let shared = Arc::new(Shared {
+            runtime,
+            driver: Mutex::new(Some(driver)),
+        });
+        let watcher = Watcher {
+            events,
+            shared: shared.clone(),
+        };
+        Ok((ZooKeeper { zk, shared }, watcher))
+    }
+
+    /// Return the asynchronous client for the same session.
+    ///
+    /// The session is not closed until the handles cloned from it have been dropped as well.
+    pub fn as_async(&self) -> &crate::ZooKeeper {
+        &self.zk
+    }
+
+    /// Run `future` to completion on the runtime that drives the connection.
+    ///
+    /// This gives blocking access to the parts of [`crate::ZooKeeper`] that this client does not
+    /// wrap, such as multi-operations, or waiting for a watch to fire.
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.shared.runtime.block_on(future)
+    }
+
+    /// See [`crate::ZooKeeper::exists`].
+    pub fn exists(&self, path: &str) -> Result<Option<Stat>, Error> {
+        self.block_on(self.zk.exists(path))
+    }
+
+    /// See [`crate::ZooKeeper::get_children`].
+    pub fn get_children(&self, path: &str) -> Result<Option<Vec<String>>, Error> {
+        self.block_on(self.zk.get_children(path))
+    }
+
+    /// See [`crate::ZooKeeper::get_children_with_stat`].
+    pub fn get_children_with_stat(&self, path: &str) -> Result<Option<(Vec<String>, Stat)>, Error> {
+        self.block_on(self.zk.get_children_with_stat(path))
+    }
+
+    /// See [`crate::ZooKeeper::get_data`].
+    pub fn get_data(&self, path: &str) -> Result<Option<(Vec<u8>, Stat)>, Error> {
+        self.block_on(self.zk.get_data(path))
+    }
+
+    /// See [`crate::ZooKeeper::get_acl`].
+    pub fn get_acl(&self, path: &str) -> Result<Result<(Vec<Acl>, Stat), error::GetAcl>, Error> {
+        self.block_on(self.zk.get_acl(path))
+    }
+
+    /// See [`crate::ZooKeeper::create`].
+    pub fn create<D, A>(
+        &self,
+        path: &str,
+        data: D,
+        acl: A,
+        mode: CreateMode,
+    ) -> Result<Result<String, error::Create>, Error>
+    where
+        D: Into<Cow<'static, [u8]>>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        self.block_on(self.zk.create(path, data, acl, mode))
+    }
+
+    /// See [`crate::ZooKeeper::set_data`].
+    pub fn set_data<D>(
+        &self,
+        path: &str,
+        version: Option<i32>,
+        data: D,
+    ) -> Result<Result<Stat, error::SetData>, Error>
+    where
+        D: Into<Cow<'static, [u8]>>,
+    {
+        self.block_on(self.zk.set_data(path, version, data))
+    }
+
+    /// See [`crate::ZooKeeper::set_data_any`].
+    pub fn set_data_any<D>(
+        &self,
+        path: &str,
+        data: D,
+    ) -> Result<Result<Stat, error::SetData>, Error>
+    where
+        D: Into<Cow<'static, [u8]>>,
+    {
+        self.block_on(self.zk.set_data_any(path, data))
+    }
+
+    /// See [`crate::ZooKeeper::delete`].
+    pub fn delete(
+        &self,
+        path: &str,
+        version: Option<i32>,
+    ) -> Result<Result<(), error::Delete>, Error> {
+        self.block_on(self.zk.delete(path, version))
+    }
+
+    /// See [`crate::ZooKeeper::delete_any`].
+    pub fn delete_any(&self, path: &str) -> Result<Result<(), error::Delete>, Error> {
+        self.block_on(self.zk.delete_any(path))
+    }
+
+    /// See [`crate::ZooKeeper::set_acl`].
+    pub fn set_acl<A>(
+        &self,
+        path: &str,
+        acl: A,
+        version: Option<i32>,
+    ) -> Result<Result<Stat, error::SetAcl>, Error>
+    where
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        self.block_on(self.zk.set_acl(path, acl, version))
+    }
+}
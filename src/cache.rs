@@ -0,0 +1,443 @@
+//! A read-through cache of the nodes that a client reads, kept up to date with watches.
+//!
+//! [`CachedZooKeeper`] answers [`get_data`](CachedZooKeeper::get_data),
+//! [`get_children`](CachedZooKeeper::get_children) and [`exists`](CachedZooKeeper::exists) from
+//! memory once it has read a node, which takes most of the read load of configuration-heavy
+//! applications off the ensemble. Each read that fills the cache leaves a watch on the node, and
+//! the watch's event drops what the change made stale, so that the next read goes to the server
+//! again. Everything is dropped when the connection is lost, since the changes made while the
+//! client is disconnected are only reported once it reconnects.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! use tokio_zookeeper::cache::CachedZooKeeper;
+//! # async fn run(zk: ZooKeeper) -> Result<(), Error> {
+//! let cache = CachedZooKeeper::new(&zk);
+//! // only the first read goes to the server, until the node changes
+//! for _ in 0..100 {
+//!     let config = cache.get_data("/app/config").await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! By default, only nodes that exist are cached. For workloads that keep probing for nodes that
+//! have yet to be created, [`CacheOptions::negative_ttl`] also caches that a node does not exist,
+//! until the node is created or the entry has been cached for too long.
+//!
+//! A cached read returns what the server returned when the node was read, including its
+//! [`Stat`]. Since a change to the children of a node does not fire the watches on its data, the
+//! fields of the stat that describe the children, such as
+//! [`num_children`](Stat::num_children), may be out of date.
+
+use futures::StreamExt;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::{Duration, Instant};
+use crate::proto::Watch;
+use crate::{Error, KeeperState, Stat, WatchedEvent, WatchedEventType, ZooKeeper};
+
+/// The kind of watch that a read sets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Kind {
+    Data,
+    Exist,
+    Child,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// Counts the events that have dropped entries, so that a read that was under way while one
+    /// was handled does not fill the cache with what the event made stale.
+    generation: u64,
+    data: HashMap<String, (Arc<[u8]>, Stat)>,
+    children: HashMap<String, Vec<String>>,
+    stats: HashMap<String, Stat>,
+    /// The nodes that did not exist, and when they were found not to.
+    missing: HashMap<String, Instant>,
+    /// The watches that have been set, and have not fired yet.
+    watched: HashSet<(Kind, String)>,
+}
+
+impl Entries {
+    /// Drop what `e` made stale.
+    fn invalidate(&mut self, e: &WatchedEvent) {
+        self.generation += 1;
+        let path = &e.path;
+        match e.event_type {
+            WatchedEventType::None => match e.keeper_state {
+                KeeperState::Disconnected | KeeperState::Expired => {
+                    self.clear();
+                    // the watches are set again if the session is resumed
+                    if e.keeper_state == KeeperState::Expired {
+                        self.watched.clear();
+                    }
+                }
+                _ => {}
+            },
+            WatchedEventType::NodeCreated => {
+                self.stats.remove(path);
+                self.missing.remove(path);
+                self.unwatch(Kind::Exist, path);
+            }
+            WatchedEventType::NodeDataChanged | WatchedEventType::DataWatchRemoved => {
+                self.data.remove(path);
+                self.stats.remove(path);
+                self.unwatch(Kind::Data, path);
+                self.unwatch(Kind::Exist, path);
+            }
+            WatchedEventType::NodeChildrenChanged | WatchedEventType::ChildWatchRemoved => {
+                self.children.remove(path);
+                self.unwatch(Kind::Child, path);
+            }
+            WatchedEventType::NodeDeleted => {
+                self.data.remove(path);
+                self.children.remove(path);
+                self.stats.remove(path);
+                self.unwatch(Kind::Data, path);
+                self.unwatch(Kind::Exist, path);
+                self.unwatch(Kind::Child, path);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.generation += 1;
+        self.data.clear();
+        self.children.clear();
+        self.stats.clear();
+        self.missing.clear();
+    }
+
+    fn unwatch(&mut self, kind: Kind, path: &str) {
+        self.watched.remove(&(kind, path.to_string()));
+    }
+}
+
+/// A read that the cache could not answer.
+struct Miss {
+    /// Whether the read has to set a watch, because none of its kind is set on the node.
+    watch: bool,
+    generation: u64,
+}
+
+/// Options for [`CachedZooKeeper::with_options`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheOptions {
+    /// Also cache that a node does not exist when [`CachedZooKeeper::exists`] finds so, for at
+    /// most this long, or until the node is created.
+    ///
+    /// Defaults to `None`, which does not cache missing nodes.
+    pub negative_ttl: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    options: CacheOptions,
+    entries: Mutex<Entries>,
+}
+
+/// A handle that answers reads from a cache, which the watches set by its reads keep up to date.
+///
+/// See the [`cache`](crate::cache) module.
+#[derive(Clone)]
+pub struct CachedZooKeeper {
+    zk: ZooKeeper,
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for CachedZooKeeper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries = self.inner.entries.lock().unwrap();
+        f.debug_struct("CachedZooKeeper")
+            .field("zk", &self.zk)
+            .field("data", &entries.data.len())
+            .field("children", &entries.children.len())
+            .field("stats", &entries.stats.len())
+            .field("missing", &entries.missing.len())
+            .finish()
+    }
+}
+
+impl CachedZooKeeper {
+    /// Return a cache of the nodes read through a handle to the session of `zk`.
+    ///
+    /// The handle has a default watcher of its own, as by [`ZooKeeper::with_own_watcher`], for
+    /// the watches of the cache. It keeps the namespace of `zk`, if any.
+    pub fn new(zk: &ZooKeeper) -> Self {
+        CachedZooKeeper::with_options(zk, CacheOptions::default())
+    }
+
+    /// Return a cache of the nodes read through a handle to the session of `zk`, configured by
+    /// `options`.
+    pub fn with_options(zk: &ZooKeeper, options: CacheOptions) -> Self {
+        let (zk, mut events) = zk.with_own_watcher();
+        let inner = Arc::new(Inner {
+            options,
+            entries: Mutex::default(),
+        });
+        let weak: Weak<Inner> = Arc::downgrade(&inner);
+        zk.runtime.spawn(async move {
+            while let Some(e) = events.next().await {
+                match weak.upgrade() {
+                    Some(inner) => inner.entries.lock().unwrap().invalidate(&e),
+                    None => return,
+                }
+            }
+        });
+        CachedZooKeeper { zk, inner }
+    }
+
+    /// The handle that the cache reads through, for the requests that it does not cache.
+    pub fn client(&self) -> &ZooKeeper {
+        &self.zk
+    }
+
+    /// Drop every entry of the cache, so that the next reads go to the server.
+    pub fn clear(&self) {
+        self.inner.entries.lock().unwrap().clear();
+    }
+
+    /// Return the cached answer to a read of `kind` on `path`, or how to read it from the server.
+    fn lookup<T, F>(&self, kind: Kind, path: &str, cached: F) -> Result<T, Miss>
+    where
+        F: FnOnce(&Entries) -> Option<T>,
+    {
+        let mut entries = self.inner.entries.lock().unwrap();
+        if let Some(hit) = cached(&entries) {
+            return Ok(hit);
+        }
+        Err(Miss {
+            watch: entries.watched.insert((kind, path.to_string())),
+            generation: entries.generation,
+        })
+    }
+
+    /// Record whether the read for `miss` left a watch, and return the entries for the read to
+    /// fill in, unless an event made what it read stale while it was under way.
+    fn fill(
+        &self,
+        kind: Kind,
+        path: &str,
+        miss: Miss,
+        watched: bool,
+    ) -> Option<MutexGuard<'_, Entries>> {
+        let mut entries = self.inner.entries.lock().unwrap();
+        if miss.watch && !watched {
+            entries.unwatch(kind, path);
+        }
+        Some(entries).filter(|entries| entries.generation == miss.generation)
+    }
+
+    fn watch(&self, miss: &Miss) -> Watch {
+        if miss.watch {
+            self.zk.default_watch()
+        } else {
+            Watch::None
+        }
+    }
+
+    /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
+    /// exist, as by [`ZooKeeper::get_data_shared`].
+    pub async fn get_data_shared(&self, path: &str) -> Result<Option<(Arc<[u8]>, Stat)>, Error> {
+        let miss = match self.lookup(Kind::Data, path, |e| e.data.get(path).cloned()) {
+            Ok(hit) => return Ok(Some(hit)),
+            Err(miss) => miss,
+        };
+        let r = self.zk.get_data_w(path, self.watch(&miss)).await;
+        let r = r.map(|r| r.map(|(b, stat)| (Arc::from(&b[..]), stat)));
+        // no watch is left on a node that does not exist
+        let fresh = self.fill(Kind::Data, path, miss, matches!(r, Ok(Some(_))));
+        if let (Some(mut entries), Ok(Some(ref entry))) = (fresh, &r) {
+            entries.data.insert(path.to_string(), entry.clone());
+        }
+        r
+    }
+
+    /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
+    /// exist, as by [`ZooKeeper::get_data`].
+    pub async fn get_data(&self, path: &str) -> Result<Option<(Vec<u8>, Stat)>, Error> {
+        let r = self.get_data_shared(path).await?;
+        Ok(r.map(|(data, stat)| (data.to_vec(), stat)))
+    }
+
+    /// Return the names of the children of the node at the given `path`, or `None` if the node
+    /// does not exist, as by [`ZooKeeper::get_children`].
+    pub async fn get_children(&self, path: &str) -> Result<Option<Vec<String>>, Error> {
+        let miss = match self.lookup(Kind::Child, path, |e| e.children.get(path).cloned()) {
+            Ok(hit) => return Ok(Some(hit)),
+            Err(miss) => miss,
+        };
+        let r = self.zk.get_children_w(path, self.watch(&miss)).await;
+        let fresh = self.fill(Kind::Child, path, miss, matches!(r, Ok(Some(_))));
+        if let (Some(mut entries), Ok(Some(ref children))) = (fresh, &r) {
+            entries.children.insert(path.to_string(), children.clone());
+        }
+        r
+    }
+
+    /// Return the [`Stat`] of the node of the given `path`, or `None` if the node does not
+    /// exist, as by [`ZooKeeper::exists`].
+    ///
+    /// That the node does not exist is only cached if [`CacheOptions::negative_ttl`] is set.
+    pub async fn exists(&self, path: &str) -> Result<Option<Stat>, Error> {
+        let ttl = self.inner.options.negative_ttl;
+        let cached = |e: &Entries| {
+            let stat = e.stats.get(path).or_else(|| e.data.get(path).map(|(_, stat)| stat));
+            match (stat, ttl, e.missing.get(path)) {
+                (Some(stat), _, _) => Some(Some(*stat)),
+                (None, Some(ttl), Some(since)) if since.elapsed() < ttl => Some(None),
+                _ => None,
+            }
+        };
+        let miss = match self.lookup(Kind::Exist, path, cached) {
+            Ok(hit) => return Ok(hit),
+            Err(miss) => miss,
+        };
+        let r = self.zk.exists_w(path, self.watch(&miss)).await;
+        // the watch is left whether the node exists or not, and fires once it is created
+        let mut fresh = match self.fill(Kind::Exist, path, miss, r.is_ok()) {
+            Some(entries) => entries,
+            None => return r,
+        };
+        match (&r, ttl) {
+            (Ok(Some(stat)), _) => {
+                fresh.stats.insert(path.to_string(), *stat);
+            }
+            (Ok(None), Some(ttl)) => {
+                let now = Instant::now();
+                fresh.missing.retain(|_, since| now.duration_since(*since) < ttl);
+                fresh.missing.insert(path.to_string(), now);
+            }
+            _ => {}
+        }
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use super::*;
+    use crate::interceptor::{Interceptor, Response};
+    use crate::runtime::Runtime;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode};
+
+    /// Counts the requests that reach the server.
+    #[derive(Default)]
+    struct Reads(AtomicUsize);
+
+    impl Interceptor for Reads {
+        fn after(&self, _: &mut Response<'_>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    impl Reads {
+        fn take(&self) -> usize {
+            self.0.swap(0, Ordering::Relaxed)
+        }
+    }
+
+    /// Wait for the cache to have handled the events of the changes made so far.
+    async fn settle() {
+        Runtime::Tokio.sleep(Duration::from_millis(20)).await;
+    }
+
+    #[tokio::test]
+    async fn serves_and_invalidates() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let reads = Arc::new(Reads::default());
+        let cache = CachedZooKeeper::new(&zk.with_interceptor(reads.clone()));
+        let acl = Acl::open_unsafe();
+        zk.create("/c", &b"a"[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(cache.get_data("/c").await.unwrap().unwrap().0, b"a");
+            assert_eq!(cache.get_children("/c").await.unwrap().unwrap(), Vec::<String>::new());
+            assert!(cache.exists("/c").await.unwrap().is_some());
+        }
+        // the stat of the data answers exists
+        assert_eq!(reads.take(), 2);
+
+        other.set_data("/c", None, &b"b"[..]).await.unwrap().unwrap();
+        settle().await;
+        assert_eq!(cache.get_data("/c").await.unwrap().unwrap().0, b"b");
+        assert_eq!(cache.get_children("/c").await.unwrap().unwrap(), Vec::<String>::new());
+        assert_eq!(reads.take(), 1);
+
+        other.create("/c/d", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        settle().await;
+        assert_eq!(cache.get_children("/c").await.unwrap().unwrap(), ["d"]);
+        assert_eq!(cache.get_data("/c").await.unwrap().unwrap().0, b"b");
+        assert_eq!(reads.take(), 1);
+
+        // missing nodes are not cached
+        assert_eq!(cache.get_data("/missing").await.unwrap(), None);
+        assert_eq!(cache.get_data("/missing").await.unwrap(), None);
+        assert_eq!(reads.take(), 2);
+    }
+
+    #[tokio::test]
+    async fn negative() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let reads = Arc::new(Reads::default());
+        let options = CacheOptions {
+            negative_ttl: Some(Duration::from_millis(100)),
+        };
+        let cache = CachedZooKeeper::with_options(&zk.with_interceptor(reads.clone()), options);
+        let acl = Acl::open_unsafe();
+
+        assert_eq!(cache.exists("/n").await.unwrap(), None);
+        assert_eq!(cache.exists("/n").await.unwrap(), None);
+        assert_eq!(reads.take(), 1);
+
+        // the entry expires
+        Runtime::Tokio.sleep(Duration::from_millis(100)).await;
+        assert_eq!(cache.exists("/n").await.unwrap(), None);
+        assert_eq!(reads.take(), 1);
+
+        // and creating the node drops it
+        zk.create("/n", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        settle().await;
+        assert!(cache.exists("/n").await.unwrap().is_some());
+        assert!(cache.exists("/n").await.unwrap().is_some());
+        assert_eq!(reads.take(), 1);
+
+        // without a ttl, missing nodes are always read again
+        let cache = CachedZooKeeper::new(&zk.with_interceptor(reads.clone()));
+        assert_eq!(cache.exists("/m").await.unwrap(), None);
+        assert_eq!(cache.exists("/m").await.unwrap(), None);
+        assert_eq!(reads.take(), 2);
+    }
+
+    #[tokio::test]
+    async fn flushes_on_disconnect() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let reads = Arc::new(Reads::default());
+        let cache = CachedZooKeeper::new(&zk.with_interceptor(reads.clone()));
+        let acl = Acl::open_unsafe();
+        zk.create("/f", &b"a"[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        assert!(cache.get_data("/f").await.unwrap().is_some());
+        assert!(cache.get_data("/f").await.unwrap().is_some());
+        assert_eq!(reads.take(), 1);
+
+        server.disconnect_all();
+        cache.client().wait_until_connected(Duration::from_secs(5)).await.unwrap();
+        settle().await;
+        assert!(cache.get_data("/f").await.unwrap().is_some());
+        assert!(cache.get_data("/f").await.unwrap().is_some());
+        assert_eq!(reads.take(), 1);
+
+        // the watch was set again when the session was resumed
+        zk.delete("/f", None).await.unwrap().unwrap();
+        settle().await;
+        assert_eq!(cache.get_data("/f").await.unwrap(), None);
+    }
+}
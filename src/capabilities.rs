@@ -0,0 +1,293 @@
+//! Telling which release of ZooKeeper a client is connected to, and what it supports.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use crate::proto::{self, Watch, ZkError};
+use crate::runtime::Runtime;
+use crate::{admin, transform, Error, ZooKeeper};
+
+/// The node that servers have since 3.5, which holds the ensemble's dynamic configuration.
+const CONFIG: &str = "/zookeeper/config";
+
+/// How long to wait for the answer to `srvr` when no connect timeout is set, since a server with
+/// four-letter words disabled may never answer.
+const SRVR_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A release of ZooKeeper.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ServerVersion {
+    /// The major version, such as the 3 in 3.5.5.
+    pub major: u32,
+    /// The minor version, such as the 5 in 3.5.5.
+    pub minor: u32,
+    /// The patch version, such as the last 5 in 3.5.5.
+    pub patch: u32,
+}
+
+impl ServerVersion {
+    /// The release `major.minor.patch`.
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        ServerVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl fmt::Display for ServerVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for ServerVersion {
+    type Err = Error;
+
+    /// Parse a version the way servers report it, such as `3.5.5-390fe37e, built on 05/03/2019`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let end = s
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len());
+        let mut parts = s[..end].split('.').map(str::parse);
+        let bad = || Error::Protocol(format!("bad server version {:?}", s));
+        let major = parts.next().and_then(Result::ok).ok_or_else(bad)?;
+        let mut next = || parts.next().unwrap_or(Ok(0)).map_err(|_| bad());
+        Ok(ServerVersion::new(major, next()?, next()?))
+    }
+}
+
+/// What a client knows about the release of the server that it is connected to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Known {
+    Exactly(ServerVersion),
+    AtLeast(ServerVersion),
+    Before(ServerVersion),
+    Nothing,
+}
+
+/// What the server that a client is connected to supports, as far as the client can tell.
+///
+/// Newer releases of ZooKeeper support operations that older ones reject, often with errors that
+/// say little about why. The client finds out which release the server is by asking it with the
+/// `srvr` four-letter word, and, where the server does not answer it, by whether it has the
+/// `/zookeeper/config` node that servers have since 3.5. Where the client cannot tell whether the
+/// server supports something, it assumes that it does, and leaves it to the server to say
+/// otherwise.
+///
+/// See [`ZooKeeper::capabilities`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    known: Known,
+}
+
+impl Capabilities {
+    /// The capabilities of the given release of ZooKeeper.
+    pub fn of(version: ServerVersion) -> Self {
+        Capabilities {
+            known: Known::Exactly(version),
+        }
+    }
+
+    /// The release that the server is, if the client could tell exactly.
+    pub fn version(&self) -> Option<ServerVersion> {
+        match self.known {
+            Known::Exactly(version) => Some(version),
+            _ => None,
+        }
+    }
+
+    /// Whether the client could tell anything about the server at all.
+    pub(crate) fn detected(&self) -> bool {
+        self.known != Known::Nothing
+    }
+
+    fn since(&self, release: ServerVersion) -> bool {
+        match self.known {
+            Known::Exactly(version) => version >= release,
+            Known::Before(version) => release < version,
+            Known::AtLeast(_) | Known::Nothing => true,
+        }
+    }
+
+    /// Whether the server supports [`CreateMode::Container`](crate::CreateMode::Container)
+    /// nodes, which came with 3.5.
+    pub fn containers(&self) -> bool {
+        self.since(ServerVersion::new(3, 5, 0))
+    }
+
+    /// Whether the server answers a create with the stat of the new node, with `Create2`, which
+    /// came with 3.5.
+    pub fn create2(&self) -> bool {
+        self.since(ServerVersion::new(3, 5, 0))
+    }
+
+    /// Whether the server supports the persistent and recursive watches that are set with
+    /// `AddWatch`, which came with 3.6, such as the one behind
+    /// [`ZooKeeper::change_journal`](crate::ZooKeeper::change_journal).
+    pub fn persistent_watches(&self) -> bool {
+        self.since(ServerVersion::new(3, 6, 0))
+    }
+}
+
+/// Find out what the server that `zk` is connected to supports.
+pub(crate) async fn detect(zk: &ZooKeeper) -> Capabilities {
+    // in-memory transports have no address to send four-letter words to, and only Tokio has the
+    // sockets that they are sent over
+    if zk.runtime == Runtime::Tokio && !zk.addr.ip().is_unspecified() {
+        let timeout = zk.connect_timeout.unwrap_or(SRVR_TIMEOUT);
+        let srvr = tokio::time::timeout(timeout, admin::flw::srvr(&zk.addr)).await;
+        if let Ok(Ok(info)) = srvr {
+            if let Ok(version) = info.version.parse() {
+                return Capabilities::of(version);
+            }
+        }
+    }
+
+    // the node is outside of any namespace that `zk` may be confined to
+    let r = zk
+        .connection
+        .enqueue(proto::Request::Exists {
+            path: CONFIG.to_string(),
+            watch: Watch::None,
+        })
+        .await;
+    let known = match r.map(transform::exists) {
+        Ok(Ok(Some(_))) => Known::AtLeast(ServerVersion::new(3, 5, 0)),
+        Ok(Ok(None)) => Known::Before(ServerVersion::new(3, 5, 0)),
+        _ => Known::Nothing,
+    };
+    Capabilities { known }
+}
+
+/// The error that an operation fails with when the server is known not to support it.
+pub(crate) fn unsupported() -> Error {
+    Error::server(ZkError::Unimplemented, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockZk, Script};
+    use crate::{Acl, CreateMode, ZooKeeperBuilder};
+
+    #[test]
+    fn versions() {
+        let parse = |s: &str| s.parse::<ServerVersion>().unwrap();
+        assert_eq!(
+            parse("3.5.5-390fe37e, built on 05/03/2019 12:07 GMT"),
+            ServerVersion::new(3, 5, 5)
+        );
+        assert_eq!(parse("3.4.14"), ServerVersion::new(3, 4, 14));
+        assert_eq!(parse("3.9"), ServerVersion::new(3, 9, 0));
+        assert!("".parse::<ServerVersion>().is_err());
+        assert!("3.x".parse::<ServerVersion>().is_err());
+        assert_eq!(ServerVersion::new(3, 6, 1).to_string(), "3.6.1");
+
+        let old = Capabilities::of(parse("3.4.14"));
+        assert!(!old.containers() && !old.create2() && !old.persistent_watches());
+        let new = Capabilities::of(parse("3.5.5"));
+        assert!(new.containers() && new.create2() && !new.persistent_watches());
+        assert!(Capabilities::of(parse("3.6.0")).persistent_watches());
+
+        // without an exact version, only what is known to be missing is
+        let before = Capabilities {
+            known: Known::Before(ServerVersion::new(3, 5, 0)),
+        };
+        assert!(!before.containers());
+        let after = Capabilities {
+            known: Known::AtLeast(ServerVersion::new(3, 5, 0)),
+        };
+        assert!(after.containers() && after.persistent_watches());
+        assert_eq!(after.version(), None);
+    }
+
+    #[tokio::test]
+    async fn mock() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        assert!(zk.capabilities().await.containers());
+        let path = zk
+            .create("/c", &b""[..], Acl::open_unsafe(), CreateMode::Container)
+            .await
+            .unwrap();
+        assert_eq!(path.as_deref(), Ok("/c"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn containers_before_3_5() {
+        let script = Script::new();
+        let server = async {
+            let mut conn = script.accept().await;
+            conn.handshake().await.unwrap();
+            conn.accept(1, 30_000, &[1; 16]).await.unwrap();
+            conn
+        };
+        let (client, mut conn) = futures::join!(
+            ZooKeeperBuilder::default().connect_scripted(&script),
+            server
+        );
+        let (zk, _) = client.unwrap();
+
+        let create = zk.create("/c", &b""[..], Acl::open_unsafe(), CreateMode::Container);
+        let server = async {
+            let req = conn.recv().await.unwrap().unwrap();
+            // opcode 3 is exists
+            assert_eq!(req.opcode, 3);
+            assert!(req.body.ends_with(b"/zookeeper/config\0"));
+            conn.reply(req.xid, 1, Err(ZkError::NoNode)).await.unwrap();
+        };
+        let (create, ()) = futures::join!(create, server);
+        assert!(matches!(
+            create,
+            Err(Error::Server {
+                error: ZkError::Unimplemented,
+                context: None
+            })
+        ));
+        assert!(!zk.capabilities().await.containers());
+
+        // nothing but the closing of the session goes out after the exists
+        drop(zk);
+        let req = conn.recv().await.unwrap().unwrap();
+        assert_eq!(req.opcode, -11);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn journal_before_3_5() {
+        let script = Script::new();
+        let server = async {
+            let mut conn = script.accept().await;
+            conn.handshake().await.unwrap();
+            conn.accept(1, 30_000, &[1; 16]).await.unwrap();
+            conn
+        };
+        let (client, mut conn) = futures::join!(
+            ZooKeeperBuilder::default().connect_scripted(&script),
+            server
+        );
+        let (zk, _) = client.unwrap();
+
+        let journal = zk.change_journal("/app");
+        let server = async {
+            let req = conn.recv().await.unwrap().unwrap();
+            assert!(req.body.ends_with(b"/zookeeper/config\0"));
+            conn.reply(req.xid, 1, Err(ZkError::NoNode)).await.unwrap();
+        };
+        let (journal, ()) = futures::join!(journal, server);
+        assert!(matches!(
+            journal,
+            Err(Error::Server {
+                error: ZkError::Unimplemented,
+                context: None
+            })
+        ));
+
+        // the watch is never set, so a server without them does not drop the connection
+        drop(zk);
+        let req = conn.recv().await.unwrap().unwrap();
+        assert_eq!(req.opcode, -11);
+    }
+}
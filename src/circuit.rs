@@ -0,0 +1,161 @@
+//! A circuit breaker that fails requests right away while the server is unlikely to answer them.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::proto::Reply;
+use crate::{Error, ZkError};
+
+/// When requests should fail right away with [`Error::BrokenCircuit`] rather than be queued.
+///
+/// The circuit opens when too many requests fail, or when the connection has been lost for too
+/// long. While it is open, requests fail without being sent, so that callers who would rather
+/// fail fast than wait on a struggling ensemble do not pile up. Once `open_for` has passed, the
+/// circuit lets requests through again, and the first of them to complete decides whether it
+/// closes, or opens again for another `open_for`.
+///
+/// A request fails, as far as the circuit is concerned, if it gets no answer from the server, or
+/// an answer with one of ZooKeeper's system errors, such as
+/// [`OperationTimeout`](ZkError::OperationTimeout). Answers that are errors of the operation, such
+/// as a node not existing, are not failures.
+///
+/// See [`ZooKeeperBuilder::set_circuit_breaker`](crate::ZooKeeperBuilder::set_circuit_breaker).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircuitBreaker {
+    /// The fraction of the requests that complete within a `window` that have to fail for the
+    /// circuit to open.
+    ///
+    /// Defaults to 0.5.
+    pub error_rate: f64,
+
+    /// How many requests have to complete within a `window` before their error rate counts.
+    ///
+    /// Defaults to 20.
+    pub min_requests: u32,
+
+    /// How long the window is that the error rate is measured over.
+    ///
+    /// Defaults to 10 seconds.
+    pub window: Duration,
+
+    /// Open the circuit while the client is disconnected, once the server has not been heard from
+    /// for this long, or not at all if `None`.
+    ///
+    /// Defaults to `None`.
+    pub disconnected_for: Option<Duration>,
+
+    /// How long the circuit stays open after too many requests failed.
+    ///
+    /// Defaults to 5 seconds.
+    pub open_for: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        CircuitBreaker {
+            error_rate: 0.5,
+            min_requests: 20,
+            window: Duration::from_secs(10),
+            disconnected_for: None,
+            open_for: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Closed {
+        since: Instant,
+        requests: u32,
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// Requests go through, and the first of them to complete decides what comes next.
+    HalfOpen,
+}
+
+/// The state of a session's circuit breaker.
+#[derive(Debug)]
+pub(crate) struct Breaker {
+    config: CircuitBreaker,
+    state: Mutex<State>,
+}
+
+impl Breaker {
+    pub(crate) fn new(config: CircuitBreaker) -> Self {
+        Breaker {
+            config,
+            state: Mutex::new(State::Closed {
+                since: Instant::now(),
+                requests: 0,
+                failures: 0,
+            }),
+        }
+    }
+
+    /// Whether a request may be sent, given how long the client has been disconnected, if it is.
+    pub(crate) fn allows(&self, disconnected: Option<Duration>) -> bool {
+        if let (Some(limit), Some(disconnected)) = (self.config.disconnected_for, disconnected) {
+            if disconnected >= limit {
+                return false;
+            }
+        }
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Open { until } if Instant::now() < until => false,
+            State::Open { .. } => {
+                *state = State::HalfOpen;
+                true
+            }
+            State::Closed { .. } | State::HalfOpen => true,
+        }
+    }
+
+    /// Count the outcome of a request that completed.
+    pub(crate) fn record(&self, outcome: &Result<Reply, Error>) {
+        let failed = match *outcome {
+            Ok(Ok(_)) => false,
+            Ok(Err((e, _))) => e.code() > ZkError::APIError.code(),
+            Err(_) => true,
+        };
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let open = State::Open {
+            until: now + self.config.open_for,
+        };
+        let fresh = State::Closed {
+            since: now,
+            requests: 0,
+            failures: 0,
+        };
+        match *state {
+            State::HalfOpen => *state = if failed { open } else { fresh },
+            State::Closed { since, .. } if now.duration_since(since) >= self.config.window => {
+                *state = fresh;
+                self.count(&mut state, failed, now);
+            }
+            State::Closed { .. } => self.count(&mut state, failed, now),
+            // completions of requests sent before the circuit opened
+            State::Open { .. } => {}
+        }
+    }
+
+    fn count(&self, state: &mut State, failed: bool, now: Instant) {
+        if let State::Closed {
+            ref mut requests,
+            ref mut failures,
+            ..
+        } = *state
+        {
+            *requests += 1;
+            *failures += failed as u32;
+            let rate = f64::from(*failures) / f64::from(*requests);
+            if *requests >= self.config.min_requests && rate >= self.config.error_rate {
+                *state = State::Open {
+                    until: now + self.config.open_for,
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! An object-safe trait over the operations of a ZooKeeper client.
+//!
+//! Code that talks to ZooKeeper through a `&dyn ZkClient` (or an `Arc<dyn ZkClient>`) rather than
+//! a [`ZooKeeper`] can be handed an in-memory implementation of [`ZkClient`] in its unit tests,
+//! so that they do not need a live server.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use tokio_zookeeper::client::ZkClient;
+//! async fn feature_enabled(zk: &dyn ZkClient, feature: &str) -> Result<bool, Error> {
+//!     let path = format!("/features/{}", feature);
+//!     Ok(zk.get_data(&path).await?.map_or(false, |(data, _)| data == b"on"))
+//! }
+//! # async fn run(zk: ZooKeeper) -> Result<(), Error> {
+//! let enabled = feature_enabled(&zk, "dark-mode").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::channel::oneshot;
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use crate::{error, Acl, CreateMode, Error, MultiResponse, Stat, WatchedEvent, ZooKeeper};
+
+/// The future returned by the operations of a [`ZkClient`].
+pub type ZkFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// An operation in a request passed to [`ZkClient::multi`].
+///
+/// See [`MultiBuilder`](crate::MultiBuilder) for what each operation does.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MultiOp {
+    /// See [`MultiBuilder::create`](crate::MultiBuilder::create).
+    Create {
+        /// The path of the node to create.
+        path: String,
+        /// The data of the new node.
+        data: Cow<'static, [u8]>,
+        /// The ACL of the new node.
+        acl: Cow<'static, [Acl]>,
+        /// The kind of node to create.
+        mode: CreateMode,
+    },
+    /// See [`MultiBuilder::set_data`](crate::MultiBuilder::set_data).
+    SetData {
+        /// The path of the node.
+        path: String,
+        /// The version the node must have, if any.
+        version: Option<i32>,
+        /// The new data of the node.
+        data: Cow<'static, [u8]>,
+    },
+    /// See [`MultiBuilder::delete`](crate::MultiBuilder::delete).
+    Delete {
+        /// The path of the node.
+        path: String,
+        /// The version the node must have, if any.
+        version: Option<i32>,
+    },
+    /// See [`MultiBuilder::check`](crate::MultiBuilder::check).
+    Check {
+        /// The path of the node.
+        path: String,
+        /// The version the node must have.
+        version: i32,
+    },
+}
+
+/// The operations of a ZooKeeper client, in a form that can be used as a trait object.
+///
+/// Every method behaves like the method of the same name on [`ZooKeeper`], which implements this
+/// trait by forwarding to them. The `watch_*` methods behave like the methods of
+/// [`WithWatcher`](crate::WithWatcher).
+pub trait ZkClient: Send + Sync {
+    /// See [`ZooKeeper::create`].
+    fn create<'a>(
+        &'a self,
+        path: &'a str,
+        data: Cow<'static, [u8]>,
+        acl: Cow<'static, [Acl]>,
+        mode: CreateMode,
+    ) -> ZkFuture<'a, Result<String, error::Create>>;
+
+    /// See [`ZooKeeper::set_data`].
+    fn set_data<'a>(
+        &'a self,
+        path: &'a str,
+        version: Option<i32>,
+        data: Cow<'static, [u8]>,
+    ) -> ZkFuture<'a, Result<Stat, error::SetData>>;
+
+    /// See [`ZooKeeper::delete`].
+    fn delete<'a>(
+        &'a self,
+        path: &'a str,
+        version: Option<i32>,
+    ) -> ZkFuture<'a, Result<(), error::Delete>>;
+
+    /// See [`ZooKeeper::get_acl`].
+    fn get_acl<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Result<(Vec<Acl>, Stat), error::GetAcl>>;
+
+    /// See [`ZooKeeper::set_acl`].
+    fn set_acl<'a>(
+        &'a self,
+        path: &'a str,
+        acl: Cow<'static, [Acl]>,
+        version: Option<i32>,
+    ) -> ZkFuture<'a, Result<Stat, error::SetAcl>>;
+
+    /// See [`ZooKeeper::exists`].
+    fn exists<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Stat>>;
+
+    /// See [`ZooKeeper::get_children`].
+    fn get_children<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Vec<String>>>;
+
+    /// See [`ZooKeeper::get_data`].
+    fn get_data<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<(Vec<u8>, Stat)>>;
+
+    /// Run the given operations in one atomic unit, as by
+    /// [`MultiBuilder::run`](crate::MultiBuilder::run).
+    fn multi(&self, ops: Vec<MultiOp>) -> ZkFuture<'_, Vec<Result<MultiResponse, error::Multi>>>;
+
+    /// See [`WithWatcher::exists`](crate::WithWatcher::exists).
+    fn watch_exists<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, (oneshot::Receiver<WatchedEvent>, Option<Stat>)>;
+
+    /// See [`WithWatcher::get_children`](crate::WithWatcher::get_children).
+    fn watch_children<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<String>)>>;
+
+    /// See [`WithWatcher::get_data`](crate::WithWatcher::get_data).
+    #[allow(clippy::type_complexity)]
+    fn watch_data<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<u8>, Stat)>>;
+}
+
+impl ZkClient for ZooKeeper {
+    fn create<'a>(
+        &'a self,
+        path: &'a str,
+        data: Cow<'static, [u8]>,
+        acl: Cow<'static, [Acl]>,
+        mode: CreateMode,
+    ) -> ZkFuture<'a, Result<String, error::Create>> {
+        Box::pin(ZooKeeper::create(self, path, data, acl, mode))
+    }
+
+    fn set_data<'a>(
+        &'a self,
+        path: &'a str,
+        version: Option<i32>,
+        data: Cow<'static, [u8]>,
+    ) -> ZkFuture<'a, Result<Stat, error::SetData>> {
+        Box::pin(ZooKeeper::set_data(self, path, version, data))
+    }
+
+    fn delete<'a>(
+        &'a self,
+        path: &'a str,
+        version: Option<i32>,
+    ) -> ZkFuture<'a, Result<(), error::Delete>> {
+        Box::pin(ZooKeeper::delete(self, path, version))
+    }
+
+    fn get_acl<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Result<(Vec<Acl>, Stat), error::GetAcl>> {
+        Box::pin(ZooKeeper::get_acl(self, path))
+    }
+
+    fn set_acl<'a>(
+        &'a self,
+        path: &'a str,
+        acl: Cow<'static, [Acl]>,
+        version: Option<i32>,
+    ) -> ZkFuture<'a, Result<Stat, error::SetAcl>> {
+        Box::pin(ZooKeeper::set_acl(self, path, acl, version))
+    }
+
+    fn exists<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Stat>> {
+        Box::pin(ZooKeeper::exists(self, path))
+    }
+
+    fn get_children<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Vec<String>>> {
+        Box::pin(ZooKeeper::get_children(self, path))
+    }
+
+    fn get_data<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<(Vec<u8>, Stat)>> {
+        Box::pin(ZooKeeper::get_data(self, path))
+    }
+
+    fn multi(&self, ops: Vec<MultiOp>) -> ZkFuture<'_, Vec<Result<MultiResponse, error::Multi>>> {
+        let multi = ops.into_iter().fold(ZooKeeper::multi(self), |multi, op| match op {
+            MultiOp::Create {
+                path,
+                data,
+                acl,
+                mode,
+            } => multi.create(&path, data, acl, mode),
+            MultiOp::SetData {
+                path,
+                version,
+                data,
+            } => multi.set_data(&path, version, data),
+            MultiOp::Delete { path, version } => multi.delete(&path, version),
+            MultiOp::Check { path, version } => multi.check(&path, version),
+        });
+        Box::pin(multi.run())
+    }
+
+    fn watch_exists<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, (oneshot::Receiver<WatchedEvent>, Option<Stat>)> {
+        Box::pin(self.with_watcher().exists(path))
+    }
+
+    fn watch_children<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<String>)>> {
+        Box::pin(self.with_watcher().get_children(path))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn watch_data<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<u8>, Stat)>> {
+        Box::pin(self.with_watcher().get_data(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    /// Stands in for a server that only holds data, the way a downstream crate would mock one.
+    #[derive(Default)]
+    struct Mock(Mutex<HashMap<String, Vec<u8>>>);
+
+    impl Mock {
+        fn stat(&self, data: &[u8]) -> Stat {
+            Stat {
+                czxid: 0,
+                mzxid: 0,
+                ctime: 0,
+                mtime: 0,
+                version: 0,
+                cversion: 0,
+                aversion: 0,
+                ephemeral_owner: 0,
+                data_length: data.len() as i32,
+                num_children: 0,
+                pzxid: 0,
+            }
+        }
+    }
+
+    impl ZkClient for Mock {
+        fn create<'a>(
+            &'a self,
+            path: &'a str,
+            data: Cow<'static, [u8]>,
+            _: Cow<'static, [Acl]>,
+            _: CreateMode,
+        ) -> ZkFuture<'a, Result<String, error::Create>> {
+            let mut nodes = self.0.lock().unwrap();
+            let res = if nodes.contains_key(path) {
+                Err(error::Create::NodeExists {
+                    path: path.to_string(),
+                })
+            } else {
+                nodes.insert(path.to_string(), data.into_owned());
+                Ok(path.to_string())
+            };
+            Box::pin(future::ok(res))
+        }
+
+        fn set_data<'a>(
+            &'a self,
+            _: &'a str,
+            _: Option<i32>,
+            _: Cow<'static, [u8]>,
+        ) -> ZkFuture<'a, Result<Stat, error::SetData>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        fn delete<'a>(
+            &'a self,
+            path: &'a str,
+            _: Option<i32>,
+        ) -> ZkFuture<'a, Result<(), error::Delete>> {
+            let res = match self.0.lock().unwrap().remove(path) {
+                Some(_) => Ok(()),
+                None => Err(error::Delete::NoNode {
+                    path: path.to_string(),
+                }),
+            };
+            Box::pin(future::ok(res))
+        }
+
+        fn get_acl<'a>(
+            &'a self,
+            _: &'a str,
+        ) -> ZkFuture<'a, Result<(Vec<Acl>, Stat), error::GetAcl>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        fn set_acl<'a>(
+            &'a self,
+            _: &'a str,
+            _: Cow<'static, [Acl]>,
+            _: Option<i32>,
+        ) -> ZkFuture<'a, Result<Stat, error::SetAcl>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        fn exists<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Stat>> {
+            let stat = self.0.lock().unwrap().get(path).map(|data| self.stat(data));
+            Box::pin(future::ok(stat))
+        }
+
+        fn get_children<'a>(&'a self, _: &'a str) -> ZkFuture<'a, Option<Vec<String>>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        fn get_data<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<(Vec<u8>, Stat)>> {
+            let nodes = self.0.lock().unwrap();
+            let res = nodes.get(path).map(|data| (data.clone(), self.stat(data)));
+            Box::pin(future::ok(res))
+        }
+
+        fn multi(&self, _: Vec<MultiOp>) -> ZkFuture<'_, Vec<Result<MultiResponse, error::Multi>>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        fn watch_exists<'a>(
+            &'a self,
+            _: &'a str,
+        ) -> ZkFuture<'a, (oneshot::Receiver<WatchedEvent>, Option<Stat>)> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        fn watch_children<'a>(
+            &'a self,
+            _: &'a str,
+        ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<String>)>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+
+        #[allow(clippy::type_complexity)]
+        fn watch_data<'a>(
+            &'a self,
+            _: &'a str,
+        ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<u8>, Stat)>> {
+            Box::pin(future::err(Error::NotSent))
+        }
+    }
+
+    /// Code under test that only knows about the trait.
+    async fn toggle(zk: &dyn ZkClient, path: &str) -> Result<bool, Error> {
+        if zk.delete(path, None).await?.is_ok() {
+            return Ok(false);
+        }
+        let data = Cow::Borrowed(&b"on"[..]);
+        let acl = Cow::Borrowed(Acl::open_unsafe());
+        zk.create(path, data, acl, CreateMode::Persistent)
+            .await?
+            .expect("node was just deleted");
+        Ok(true)
+    }
+
+    #[test]
+    fn mock() {
+        let zk: Box<dyn ZkClient> = Box::<Mock>::default();
+        futures::executor::block_on(async {
+            assert!(toggle(&*zk, "/flag").await.unwrap());
+            assert!(zk.exists("/flag").await.unwrap().is_some());
+            let (data, stat) = zk.get_data("/flag").await.unwrap().unwrap();
+            assert_eq!(data, b"on");
+            assert_eq!(stat.data_length, 2);
+            assert!(!toggle(&*zk, "/flag").await.unwrap());
+            assert_eq!(zk.get_data("/flag").await.unwrap(), None);
+        });
+    }
+}
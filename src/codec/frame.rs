@@ -0,0 +1,69 @@
+//! Splitting a stream of bytes into the frames that ZooKeeper's messages are sent in.
+//!
+//! Every message, in either direction, is sent as a frame: an `i32` length followed by that many
+//! bytes.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use bytes::{Bytes, BytesMut};
+use crate::Error;
+
+/// The length of the frame at the start of `buf`, including its length prefix.
+///
+/// `buf` must hold at least the four bytes of the prefix.
+pub fn frame_len(buf: &[u8]) -> Result<usize, Error> {
+    let length = (&mut &buf[..]).read_i32::<BigEndian>()?;
+    if length < 0 {
+        return Err(Error::Protocol(format!("negative frame length {}", length)));
+    }
+    Ok(length as usize + 4)
+}
+
+/// Take the frame at the start of `buf` out of it, and return what it holds without its length
+/// prefix, or `None` if the frame has not arrived in full yet.
+pub fn split_frame(buf: &mut BytesMut) -> Result<Option<Bytes>, Error> {
+    if buf.len() < 4 {
+        return Ok(None);
+    }
+    let len = frame_len(buf)?;
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let mut frame = buf.split_to(len);
+    let _ = frame.split_to(4);
+    Ok(Some(frame.freeze()))
+}
+
+/// Build a frame out of what `body` writes.
+pub fn frame<F: FnOnce(&mut Vec<u8>)>(body: F) -> Vec<u8> {
+    let mut frame = vec![0; 4];
+    body(&mut frame);
+    let len = frame.len() as i32 - 4;
+    (&mut frame[..4])
+        .write_i32::<BigEndian>(len)
+        .expect("Vec::write should never fail");
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split() {
+        let mut buf = BytesMut::new();
+        assert_eq!(split_frame(&mut buf).unwrap(), None);
+        buf.extend_from_slice(&frame(|w| w.extend_from_slice(b"abc")));
+        buf.extend_from_slice(&frame(|_| {}));
+        buf.extend_from_slice(&[0, 0, 0, 2, b'x']);
+        assert_eq!(split_frame(&mut buf).unwrap().as_deref(), Some(&b"abc"[..]));
+        assert_eq!(split_frame(&mut buf).unwrap().as_deref(), Some(&b""[..]));
+        // the last frame is still missing a byte
+        assert_eq!(split_frame(&mut buf).unwrap(), None);
+        buf.extend_from_slice(b"y");
+        assert_eq!(split_frame(&mut buf).unwrap().as_deref(), Some(&b"xy"[..]));
+        assert!(buf.is_empty());
+
+        buf.extend_from_slice(&(-1i32).to_be_bytes());
+        assert!(matches!(split_frame(&mut buf), Err(Error::Protocol(_))));
+    }
+}
@@ -0,0 +1,178 @@
+//! The primitives of jute, the serialization that ZooKeeper's messages are written in.
+//!
+//! Integers are big-endian, booleans are a single byte, and buffers, strings and lists are
+//! prefixed with their length as an `i32`, where a negative length stands for an empty (or, in
+//! Java, `null`) value.
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use super::records::{data, proto};
+use crate::{Acl, Permission, Stat, WatchedEvent};
+
+/// A value that can be read in the jute encoding.
+pub trait ReadFrom: Sized {
+    /// Read the value from `read`.
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Self>;
+}
+
+/// A value that can be written in the jute encoding.
+pub trait WriteTo {
+    /// Write the value to `writer`.
+    fn write_to<W: Write>(&self, writer: W) -> io::Result<()>;
+}
+
+pub(crate) fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+/// How many items to make room for when reading a list that claims to hold `len`, which is not
+/// to be trusted with a large allocation before that many items have actually been read.
+fn capacity(len: i32) -> usize {
+    len.clamp(0, 1024) as usize
+}
+
+/// Read a length-prefixed list, with `item` reading each of its items.
+pub fn read_list<R, T, F>(read: &mut R, mut item: F) -> io::Result<Vec<T>>
+where
+    R: Read,
+    F: FnMut(&mut R) -> io::Result<T>,
+{
+    let len = read.read_i32::<BigEndian>()?;
+    let mut items = Vec::with_capacity(capacity(len));
+    for _ in 0..len {
+        items.push(item(read)?);
+    }
+    Ok(items)
+}
+
+/// Write a length-prefixed list of `ts`.
+pub fn write_list<W, T>(mut writer: W, ts: &[T]) -> io::Result<()>
+where
+    T: WriteTo,
+    W: Write,
+{
+    writer.write_i32::<BigEndian>(ts.len() as i32)?;
+    for elem in ts {
+        elem.write_to(&mut writer)?;
+    }
+    Ok(())
+}
+
+impl ReadFrom for Vec<String> {
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
+        read_list(read, |r| r.read_string())
+    }
+}
+
+impl ReadFrom for Stat {
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Stat> {
+        data::Stat::read_from(read).map(Stat::from)
+    }
+}
+
+impl ReadFrom for WatchedEvent {
+    fn read_from<R: Read>(read: &mut R) -> io::Result<WatchedEvent> {
+        WatchedEvent::try_from(proto::WatcherEvent::read_from(read)?)
+    }
+}
+
+impl ReadFrom for Vec<Acl> {
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
+        read_list(read, Acl::read_from)
+    }
+}
+
+impl ReadFrom for Acl {
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
+        data::Acl::read_from(read).map(Acl::from)
+    }
+}
+
+impl ReadFrom for Permission {
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
+        Ok(Permission::from_raw(read.read_u32::<BigEndian>()?))
+    }
+}
+
+/// Reading length-prefixed buffers.
+pub trait BufferReader: Read {
+    /// Read a length-prefixed buffer.
+    fn read_buffer(&mut self) -> io::Result<Vec<u8>>;
+}
+
+impl<R: Read> BufferReader for R {
+    fn read_buffer(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.read_i32::<BigEndian>()?;
+        let len = if len < 0 { 0 } else { len as usize };
+        // the buffer only grows as data arrives, so a bogus length cannot exhaust memory
+        let mut buf = Vec::with_capacity(len.min(4096));
+        self.take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() == len {
+            Ok(buf)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "read_buffer failed",
+            ))
+        }
+    }
+}
+
+/// Reading length-prefixed UTF-8 strings.
+pub trait StringReader: Read {
+    /// Read a length-prefixed string.
+    fn read_string(&mut self) -> io::Result<String>;
+}
+
+impl<R: Read> StringReader for R {
+    fn read_string(&mut self) -> io::Result<String> {
+        let raw = self.read_buffer()?;
+        String::from_utf8(raw).map_err(|_| invalid("string is not UTF-8"))
+    }
+}
+
+impl WriteTo for Acl {
+    fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        data::Acl::from(self).write_to(writer)
+    }
+}
+
+impl WriteTo for Stat {
+    fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        data::Stat::from(*self).write_to(writer)
+    }
+}
+
+impl WriteTo for WatchedEvent {
+    fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        proto::WatcherEvent::from(self).write_to(writer)
+    }
+}
+
+impl WriteTo for u8 {
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(*self)?;
+        Ok(())
+    }
+}
+
+impl WriteTo for str {
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_i32::<BigEndian>(self.len() as i32)?;
+        writer.write_all(self.as_ref())
+    }
+}
+
+impl WriteTo for String {
+    fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        self[..].write_to(writer)
+    }
+}
+
+impl WriteTo for [u8] {
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_i32::<BigEndian>(self.len() as i32)?;
+        writer.write_all(self.as_ref())
+    }
+}
@@ -0,0 +1,810 @@
+use std::fmt;
+use std::io::{self, Read};
+use super::jute::{invalid, ReadFrom, WriteTo};
+use super::opcode;
+use super::records::{data, proto};
+use crate::{Acl, CreateMode, Stat, ZkError};
+
+/// The handshake that a client opens every connection with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConnectRequest {
+    /// The version of the protocol that the client speaks, which is always 0.
+    pub protocol_version: i32,
+    /// The last zxid that the client has seen, which the server must have seen too.
+    pub last_zxid_seen: i64,
+    /// The session timeout that the client asks for, in milliseconds.
+    pub timeout: i32,
+    /// The session that the client resumes, or 0 for a new one.
+    pub session_id: i64,
+    /// The password of the session that the client resumes.
+    pub passwd: Vec<u8>,
+    /// Whether the client accepts a connection to a server that only serves reads.
+    pub read_only: bool,
+}
+
+impl ConnectRequest {
+    /// Read the handshake from the body of the first frame of a connection.
+    ///
+    /// Clients from before read-only servers do not send whether they accept one, and are taken
+    /// not to.
+    pub fn read(r: &[u8]) -> io::Result<Self> {
+        let req = proto::ConnectRequest::read_from(&mut r.chain(&[0][..]))?;
+        Ok(ConnectRequest {
+            protocol_version: req.protocol_version,
+            last_zxid_seen: req.last_zxid_seen,
+            timeout: req.time_out,
+            session_id: req.session_id,
+            passwd: req.passwd,
+            read_only: req.read_only,
+        })
+    }
+
+    /// Write the body of the handshake to `w`.
+    pub fn write_to(&self, w: &mut Vec<u8>) {
+        proto::ConnectRequest {
+            protocol_version: self.protocol_version,
+            last_zxid_seen: self.last_zxid_seen,
+            time_out: self.timeout,
+            session_id: self.session_id,
+            passwd: self.passwd.clone(),
+            read_only: self.read_only,
+        }
+        .write_to(w)
+        .unwrap();
+    }
+}
+
+impl fmt::Debug for ConnectRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectRequest")
+            .field("protocol_version", &self.protocol_version)
+            .field("last_zxid_seen", &self.last_zxid_seen)
+            .field("timeout", &self.timeout)
+            .field("session_id", &self.session_id)
+            .field("passwd", &format_args!("<redacted>"))
+            .field("read_only", &self.read_only)
+            .finish()
+    }
+}
+
+/// The server's answer to a [`ConnectRequest`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConnectResponse {
+    /// The version of the protocol that the server speaks, which is always 0.
+    pub protocol_version: i32,
+    /// The session timeout that the server settled on, in milliseconds, or 0 if the session that
+    /// the client tried to resume has expired.
+    pub timeout: i32,
+    /// The session that the client is connected with.
+    pub session_id: i64,
+    /// The password of the session.
+    pub passwd: Vec<u8>,
+    /// Whether the server only serves reads.
+    pub read_only: bool,
+}
+
+impl ConnectResponse {
+    /// Read the answer to the handshake from the body of the first frame from the server.
+    pub fn read(r: &[u8]) -> io::Result<Self> {
+        let res = proto::ConnectResponse::read_from(&mut r.chain(&[0][..]))?;
+        Ok(ConnectResponse {
+            protocol_version: res.protocol_version,
+            timeout: res.time_out,
+            session_id: res.session_id,
+            passwd: res.passwd,
+            read_only: res.read_only,
+        })
+    }
+
+    /// Write the body of the answer to the handshake to `w`.
+    pub fn write_to(&self, w: &mut Vec<u8>) {
+        proto::ConnectResponse {
+            protocol_version: self.protocol_version,
+            time_out: self.timeout,
+            session_id: self.session_id,
+            passwd: self.passwd.clone(),
+            read_only: self.read_only,
+        }
+        .write_to(w)
+        .unwrap();
+    }
+}
+
+impl fmt::Debug for ConnectResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ConnectResponse")
+            .field("protocol_version", &self.protocol_version)
+            .field("timeout", &self.timeout)
+            .field("session_id", &self.session_id)
+            .field("passwd", &format_args!("<redacted>"))
+            .field("read_only", &self.read_only)
+            .finish()
+    }
+}
+
+/// The header that every request after the handshake starts with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestHeader {
+    /// The xid that the client will match the response to the request by.
+    pub xid: i32,
+    /// What the request asks for, as one of the codes in [`opcode`](super::opcode).
+    pub opcode: i32,
+}
+
+impl RequestHeader {
+    /// Read the header from the start of `r`, and leave the rest of the request in it.
+    pub fn read(r: &mut &[u8]) -> io::Result<Self> {
+        let header = proto::RequestHeader::read_from(r)?;
+        Ok(RequestHeader {
+            xid: header.xid,
+            opcode: header.r#type,
+        })
+    }
+
+    /// Write the header to `w`.
+    pub fn write_to(&self, w: &mut Vec<u8>) {
+        proto::RequestHeader {
+            xid: self.xid,
+            r#type: self.opcode,
+        }
+        .write_to(w)
+        .unwrap();
+    }
+}
+
+/// The header that every message from the server after the handshake starts with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplyHeader {
+    /// The xid of the request that this answers, or one of the xids that the server sends
+    /// messages of its own with, such as [`NOTIFICATION_XID`](super::NOTIFICATION_XID).
+    pub xid: i32,
+    /// The zxid that the server was at when it answered.
+    pub zxid: i64,
+    /// The error that the request failed with, or `ZkError::Ok`.
+    pub err: ZkError,
+}
+
+impl ReplyHeader {
+    /// Read the header from the start of `r`, and leave the rest of the message in it.
+    pub fn read(r: &mut &[u8]) -> io::Result<Self> {
+        let header = proto::ReplyHeader::read_from(r)?;
+        Ok(ReplyHeader {
+            xid: header.xid,
+            zxid: header.zxid,
+            err: ZkError::from(header.err),
+        })
+    }
+
+    /// Write the header to `w`.
+    pub fn write_to(&self, w: &mut Vec<u8>) {
+        proto::ReplyHeader {
+            xid: self.xid,
+            zxid: self.zxid,
+            err: self.err.code(),
+        }
+        .write_to(w)
+        .unwrap();
+    }
+}
+
+/// The body of a request that a client sent once connected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Op {
+    /// Create a node.
+    Create {
+        /// Where to create the node.
+        path: String,
+        /// What the node holds.
+        data: Vec<u8>,
+        /// Who may do what with the node.
+        acl: Vec<Acl>,
+        /// What kind of node to create.
+        mode: CreateMode,
+    },
+    /// Create a node, and return its stat along with its path.
+    Create2 {
+        /// Where to create the node.
+        path: String,
+        /// What the node holds.
+        data: Vec<u8>,
+        /// Who may do what with the node.
+        acl: Vec<Acl>,
+        /// What kind of node to create.
+        mode: CreateMode,
+    },
+    /// Delete a node.
+    Delete {
+        /// The node to delete.
+        path: String,
+        /// The version that the node must be at, or [`ANY_VERSION`](crate::ANY_VERSION) for any.
+        version: i32,
+    },
+    /// Return the stat of a node, if it exists.
+    Exists {
+        /// The node to look for.
+        path: String,
+        /// Whether to watch the node.
+        watch: bool,
+    },
+    /// Return the data and stat of a node.
+    GetData {
+        /// The node to read.
+        path: String,
+        /// Whether to watch the node.
+        watch: bool,
+    },
+    /// Replace the data of a node.
+    SetData {
+        /// The node to write.
+        path: String,
+        /// What the node is to hold.
+        data: Vec<u8>,
+        /// The version that the node must be at, or [`ANY_VERSION`](crate::ANY_VERSION) for any.
+        version: i32,
+    },
+    /// Return the ACL and stat of a node.
+    GetAcl {
+        /// The node whose ACL to read.
+        path: String,
+    },
+    /// Replace the ACL of a node.
+    SetAcl {
+        /// The node whose ACL to replace.
+        path: String,
+        /// The ACL that the node is to have.
+        acl: Vec<Acl>,
+        /// The ACL version that the node must be at, or [`ANY_VERSION`](crate::ANY_VERSION) for
+        /// any.
+        version: i32,
+    },
+    /// Return the names of the children of a node.
+    GetChildren {
+        /// The node whose children to list.
+        path: String,
+        /// Whether to watch the node's children.
+        watch: bool,
+    },
+    /// Return the names of the children of a node, along with its stat.
+    GetChildren2 {
+        /// The node whose children to list.
+        path: String,
+        /// Whether to watch the node's children.
+        watch: bool,
+    },
+    /// Wait for the server to catch up with the leader.
+    Sync {
+        /// The node whose path the server answers with.
+        path: String,
+    },
+    /// Fail unless a node is at a version, as part of a multi.
+    Check {
+        /// The node to check.
+        path: String,
+        /// The version that the node must be at.
+        version: i32,
+    },
+    /// Apply all of the operations, or none of them.
+    Multi(Vec<Op>),
+    /// Watch the nodes that a client had watches on before it reconnected.
+    SetWatches {
+        /// The last zxid that the client has seen.
+        relative_zxid: i64,
+        /// The nodes with data watches.
+        data: Vec<String>,
+        /// The nodes with existence watches.
+        exist: Vec<String>,
+        /// The nodes with child watches.
+        child: Vec<String>,
+    },
+    /// Like [`Op::SetWatches`], for a client that also had persistent watches.
+    SetWatches2 {
+        /// The last zxid that the client has seen.
+        relative_zxid: i64,
+        /// The nodes with data watches.
+        data: Vec<String>,
+        /// The nodes with existence watches.
+        exist: Vec<String>,
+        /// The nodes with child watches.
+        child: Vec<String>,
+        /// The nodes with persistent watches.
+        persistent: Vec<String>,
+        /// The nodes with persistent recursive watches.
+        recursive: Vec<String>,
+    },
+    /// Set a persistent watch, which fires for every change until the session ends.
+    AddWatch {
+        /// The node to watch.
+        path: String,
+        /// Whether the watch also fires for changes to the node's descendants.
+        recursive: bool,
+    },
+    /// Keep the session alive.
+    Ping,
+    /// End the session.
+    CloseSession,
+    /// A request that this codec does not know.
+    Unknown {
+        /// The request's opcode.
+        opcode: i32,
+        /// The request's body, as it was sent.
+        body: Vec<u8>,
+    },
+}
+
+fn mode(flags: i32) -> io::Result<CreateMode> {
+    Ok(match flags {
+        0 => CreateMode::Persistent,
+        1 => CreateMode::Ephemeral,
+        2 => CreateMode::PersistentSequential,
+        3 => CreateMode::EphemeralSequential,
+        4 => CreateMode::Container,
+        _ => return Err(invalid("unknown create mode")),
+    })
+}
+
+fn acl_from(acl: Vec<data::Acl>) -> Vec<Acl> {
+    acl.into_iter().map(Acl::from).collect()
+}
+
+fn acl_into(acl: &[Acl]) -> Vec<data::Acl> {
+    acl.iter().map(data::Acl::from).collect()
+}
+
+/// Write the header that each operation of a multi, and the end of the multi, is tagged with.
+fn multi_header(w: &mut Vec<u8>, opcode: i32, done: bool, err: i32) {
+    proto::MultiHeader {
+        r#type: opcode,
+        done,
+        err,
+    }
+    .write_to(w)
+    .unwrap();
+}
+
+impl Op {
+    /// Read the body of a request with the given `opcode` from `r`.
+    pub fn read(opcode: i32, r: &mut &[u8]) -> io::Result<Op> {
+        Self::read_nested(opcode, r, false)
+    }
+
+    fn read_nested(opcode: i32, r: &mut &[u8], in_multi: bool) -> io::Result<Op> {
+        Ok(match opcode {
+            opcode::CREATE => {
+                let req = proto::CreateRequest::read_from(r)?;
+                Op::Create {
+                    path: req.path,
+                    data: req.data,
+                    acl: acl_from(req.acl),
+                    mode: mode(req.flags)?,
+                }
+            }
+            // the request is the same as that of a create; only the response differs
+            opcode::CREATE2 => {
+                let req = proto::CreateRequest::read_from(r)?;
+                Op::Create2 {
+                    path: req.path,
+                    data: req.data,
+                    acl: acl_from(req.acl),
+                    mode: mode(req.flags)?,
+                }
+            }
+            opcode::DELETE => {
+                let req = proto::DeleteRequest::read_from(r)?;
+                Op::Delete {
+                    path: req.path,
+                    version: req.version,
+                }
+            }
+            opcode::EXISTS => {
+                let req = proto::ExistsRequest::read_from(r)?;
+                Op::Exists {
+                    path: req.path,
+                    watch: req.watch,
+                }
+            }
+            opcode::GET_DATA => {
+                let req = proto::GetDataRequest::read_from(r)?;
+                Op::GetData {
+                    path: req.path,
+                    watch: req.watch,
+                }
+            }
+            opcode::SET_DATA => {
+                let req = proto::SetDataRequest::read_from(r)?;
+                Op::SetData {
+                    path: req.path,
+                    data: req.data,
+                    version: req.version,
+                }
+            }
+            opcode::GET_ACL => Op::GetAcl {
+                path: proto::GetAclRequest::read_from(r)?.path,
+            },
+            opcode::SET_ACL => {
+                let req = proto::SetAclRequest::read_from(r)?;
+                Op::SetAcl {
+                    path: req.path,
+                    acl: acl_from(req.acl),
+                    version: req.version,
+                }
+            }
+            opcode::GET_CHILDREN => {
+                let req = proto::GetChildrenRequest::read_from(r)?;
+                Op::GetChildren {
+                    path: req.path,
+                    watch: req.watch,
+                }
+            }
+            opcode::GET_CHILDREN2 => {
+                let req = proto::GetChildren2Request::read_from(r)?;
+                Op::GetChildren2 {
+                    path: req.path,
+                    watch: req.watch,
+                }
+            }
+            opcode::SYNC => Op::Sync {
+                path: proto::SyncRequest::read_from(r)?.path,
+            },
+            opcode::CHECK => {
+                let req = proto::CheckVersionRequest::read_from(r)?;
+                Op::Check {
+                    path: req.path,
+                    version: req.version,
+                }
+            }
+            // a multi cannot contain another, and nesting them would recurse as deep as the
+            // client cares to go
+            opcode::MULTI if in_multi => return Err(invalid("multi inside a multi")),
+            opcode::MULTI => {
+                let mut ops = Vec::new();
+                loop {
+                    let header = proto::MultiHeader::read_from(r)?;
+                    if header.done {
+                        break;
+                    }
+                    ops.push(Self::read_nested(header.r#type, r, true)?);
+                }
+                Op::Multi(ops)
+            }
+            opcode::SET_WATCHES => {
+                let req = proto::SetWatches::read_from(r)?;
+                Op::SetWatches {
+                    relative_zxid: req.relative_zxid,
+                    data: req.data_watches,
+                    exist: req.exist_watches,
+                    child: req.child_watches,
+                }
+            }
+            opcode::SET_WATCHES2 => {
+                let req = proto::SetWatches2::read_from(r)?;
+                Op::SetWatches2 {
+                    relative_zxid: req.relative_zxid,
+                    data: req.data_watches,
+                    exist: req.exist_watches,
+                    child: req.child_watches,
+                    persistent: req.persistent_watches,
+                    recursive: req.persistent_recursive_watches,
+                }
+            }
+            opcode::ADD_WATCH => {
+                let req = proto::AddWatchRequest::read_from(r)?;
+                Op::AddWatch {
+                    path: req.path,
+                    recursive: match req.mode {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(invalid("unknown watch mode")),
+                    },
+                }
+            }
+            opcode::PING => Op::Ping,
+            opcode::CLOSE_SESSION => Op::CloseSession,
+            // only the frame says where a request that is not known ends, so none can follow
+            // one inside a multi
+            _ if in_multi => return Err(invalid("unknown opcode inside a multi")),
+            opcode => {
+                let mut body = Vec::new();
+                r.read_to_end(&mut body)?;
+                Op::Unknown { opcode, body }
+            }
+        })
+    }
+
+    /// The opcode of the request.
+    pub fn opcode(&self) -> i32 {
+        match *self {
+            Op::Create { .. } => opcode::CREATE,
+            Op::Create2 { .. } => opcode::CREATE2,
+            Op::Delete { .. } => opcode::DELETE,
+            Op::Exists { .. } => opcode::EXISTS,
+            Op::GetData { .. } => opcode::GET_DATA,
+            Op::SetData { .. } => opcode::SET_DATA,
+            Op::GetAcl { .. } => opcode::GET_ACL,
+            Op::SetAcl { .. } => opcode::SET_ACL,
+            Op::GetChildren { .. } => opcode::GET_CHILDREN,
+            Op::GetChildren2 { .. } => opcode::GET_CHILDREN2,
+            Op::Sync { .. } => opcode::SYNC,
+            Op::Check { .. } => opcode::CHECK,
+            Op::Multi(..) => opcode::MULTI,
+            Op::SetWatches { .. } => opcode::SET_WATCHES,
+            Op::SetWatches2 { .. } => opcode::SET_WATCHES2,
+            Op::AddWatch { .. } => opcode::ADD_WATCH,
+            Op::Ping => opcode::PING,
+            Op::CloseSession => opcode::CLOSE_SESSION,
+            Op::Unknown { opcode, .. } => opcode,
+        }
+    }
+
+    /// Write the body of the request to `w`.
+    pub fn write_to(&self, w: &mut Vec<u8>) {
+        match *self {
+            Op::Create {
+                ref path,
+                ref data,
+                ref acl,
+                mode,
+            }
+            | Op::Create2 {
+                ref path,
+                ref data,
+                ref acl,
+                mode,
+            } => proto::CreateRequest {
+                path: path.clone(),
+                data: data.clone(),
+                acl: acl_into(acl),
+                flags: mode as i32,
+            }
+            .write_to(w),
+            Op::Delete { ref path, version } => proto::DeleteRequest {
+                path: path.clone(),
+                version,
+            }
+            .write_to(w),
+            Op::Exists { ref path, watch } => proto::ExistsRequest {
+                path: path.clone(),
+                watch,
+            }
+            .write_to(w),
+            Op::GetData { ref path, watch } => proto::GetDataRequest {
+                path: path.clone(),
+                watch,
+            }
+            .write_to(w),
+            Op::SetData {
+                ref path,
+                ref data,
+                version,
+            } => proto::SetDataRequest {
+                path: path.clone(),
+                data: data.clone(),
+                version,
+            }
+            .write_to(w),
+            Op::GetAcl { ref path } => proto::GetAclRequest { path: path.clone() }.write_to(w),
+            Op::SetAcl {
+                ref path,
+                ref acl,
+                version,
+            } => proto::SetAclRequest {
+                path: path.clone(),
+                acl: acl_into(acl),
+                version,
+            }
+            .write_to(w),
+            Op::GetChildren { ref path, watch } => proto::GetChildrenRequest {
+                path: path.clone(),
+                watch,
+            }
+            .write_to(w),
+            Op::GetChildren2 { ref path, watch } => proto::GetChildren2Request {
+                path: path.clone(),
+                watch,
+            }
+            .write_to(w),
+            Op::Sync { ref path } => proto::SyncRequest { path: path.clone() }.write_to(w),
+            Op::Check { ref path, version } => proto::CheckVersionRequest {
+                path: path.clone(),
+                version,
+            }
+            .write_to(w),
+            Op::Multi(ref ops) => {
+                for op in ops {
+                    multi_header(w, op.opcode(), false, -1);
+                    op.write_to(w);
+                }
+                multi_header(w, -1, true, -1);
+                Ok(())
+            }
+            Op::SetWatches {
+                relative_zxid,
+                ref data,
+                ref exist,
+                ref child,
+            } => proto::SetWatches {
+                relative_zxid,
+                data_watches: data.clone(),
+                exist_watches: exist.clone(),
+                child_watches: child.clone(),
+            }
+            .write_to(w),
+            Op::SetWatches2 {
+                relative_zxid,
+                ref data,
+                ref exist,
+                ref child,
+                ref persistent,
+                ref recursive,
+            } => proto::SetWatches2 {
+                relative_zxid,
+                data_watches: data.clone(),
+                exist_watches: exist.clone(),
+                child_watches: child.clone(),
+                persistent_watches: persistent.clone(),
+                persistent_recursive_watches: recursive.clone(),
+            }
+            .write_to(w),
+            Op::AddWatch {
+                ref path,
+                recursive,
+            } => proto::AddWatchRequest {
+                path: path.clone(),
+                mode: recursive as i32,
+            }
+            .write_to(w),
+            Op::Ping | Op::CloseSession => Ok(()),
+            Op::Unknown { ref body, .. } => {
+                w.extend_from_slice(body);
+                Ok(())
+            }
+        }
+        .unwrap();
+    }
+}
+
+/// The body of a successful response.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reply {
+    /// The answer to requests that succeed without saying anything more, such as deletes.
+    Empty,
+    /// The stat of a node.
+    Stat(Stat),
+    /// The data and stat of a node.
+    Data(Vec<u8>, Stat),
+    /// The ACL and stat of a node.
+    Acl(Vec<Acl>, Stat),
+    /// The names of the children of a node.
+    Children(Vec<String>),
+    /// The names of the children of a node, and its stat.
+    Children2(Vec<String>, Stat),
+    /// The path of a node, such as the one that a create made.
+    Path(String),
+    /// The path and stat of a node that a create made.
+    Created(String, Stat),
+    /// The outcome of each operation of a multi, along with its opcode if it succeeded.
+    Multi(Vec<Result<(i32, Reply), ZkError>>),
+    /// The answer to a request that this codec does not know, as it was sent.
+    Unknown(Vec<u8>),
+}
+
+impl Reply {
+    /// Read the body of a successful response to a request with the given `opcode` from `r`.
+    pub fn read(opcode: i32, r: &mut &[u8]) -> io::Result<Reply> {
+        Self::read_nested(opcode, r, false)
+    }
+
+    fn read_nested(opcode: i32, r: &mut &[u8], in_multi: bool) -> io::Result<Reply> {
+        Ok(match opcode {
+            opcode::EXISTS => Reply::Stat(proto::ExistsResponse::read_from(r)?.stat.into()),
+            opcode::SET_DATA => Reply::Stat(proto::SetDataResponse::read_from(r)?.stat.into()),
+            opcode::SET_ACL => Reply::Stat(proto::SetAclResponse::read_from(r)?.stat.into()),
+            opcode::GET_DATA => {
+                let res = proto::GetDataResponse::read_from(r)?;
+                Reply::Data(res.data, res.stat.into())
+            }
+            opcode::GET_ACL => {
+                let res = proto::GetAclResponse::read_from(r)?;
+                Reply::Acl(acl_from(res.acl), res.stat.into())
+            }
+            opcode::GET_CHILDREN => {
+                Reply::Children(proto::GetChildrenResponse::read_from(r)?.children)
+            }
+            opcode::GET_CHILDREN2 => {
+                let res = proto::GetChildren2Response::read_from(r)?;
+                Reply::Children2(res.children, res.stat.into())
+            }
+            opcode::CREATE => Reply::Path(proto::CreateResponse::read_from(r)?.path),
+            opcode::CREATE2 => {
+                let res = proto::Create2Response::read_from(r)?;
+                Reply::Created(res.path, res.stat.into())
+            }
+            opcode::SYNC => Reply::Path(proto::SyncResponse::read_from(r)?.path),
+            opcode::DELETE
+            | opcode::CHECK
+            | opcode::SET_WATCHES
+            | opcode::SET_WATCHES2
+            | opcode::ADD_WATCH
+            | opcode::PING
+            | opcode::CLOSE_SESSION => Reply::Empty,
+            opcode::MULTI if in_multi => return Err(invalid("multi inside a multi")),
+            opcode::MULTI => {
+                let mut results = Vec::new();
+                loop {
+                    let header = proto::MultiHeader::read_from(r)?;
+                    if header.done {
+                        break;
+                    }
+                    if header.r#type == opcode::ERROR {
+                        let _ = proto::ErrorResponse::read_from(r)?;
+                        results.push(Err(ZkError::from(header.err)));
+                    } else {
+                        let reply = Self::read_nested(header.r#type, r, true)?;
+                        results.push(Ok((header.r#type, reply)));
+                    }
+                }
+                Reply::Multi(results)
+            }
+            _ if in_multi => return Err(invalid("unknown opcode inside a multi")),
+            _ => {
+                let mut body = Vec::new();
+                r.read_to_end(&mut body)?;
+                Reply::Unknown(body)
+            }
+        })
+    }
+
+    /// Write the body of the response to `w`.
+    pub fn write_to(&self, w: &mut Vec<u8>) {
+        match *self {
+            Reply::Empty => Ok(()),
+            Reply::Stat(stat) => data::Stat::from(stat).write_to(w),
+            Reply::Data(ref data, stat) => proto::GetDataResponse {
+                data: data.clone(),
+                stat: stat.into(),
+            }
+            .write_to(w),
+            Reply::Acl(ref acl, stat) => proto::GetAclResponse {
+                acl: acl_into(acl),
+                stat: stat.into(),
+            }
+            .write_to(w),
+            Reply::Children(ref children) => proto::GetChildrenResponse {
+                children: children.clone(),
+            }
+            .write_to(w),
+            Reply::Children2(ref children, stat) => proto::GetChildren2Response {
+                children: children.clone(),
+                stat: stat.into(),
+            }
+            .write_to(w),
+            Reply::Path(ref path) => path.write_to(w),
+            Reply::Created(ref path, stat) => proto::Create2Response {
+                path: path.clone(),
+                stat: stat.into(),
+            }
+            .write_to(w),
+            Reply::Multi(ref results) => {
+                for result in results {
+                    match *result {
+                        Ok((opcode, ref reply)) => {
+                            // unlike the client, the server tags successes with no error
+                            multi_header(w, opcode, false, 0);
+                            reply.write_to(w);
+                        }
+                        Err(e) => {
+                            multi_header(w, opcode::ERROR, false, e.code());
+                            proto::ErrorResponse { err: e.code() }.write_to(&mut *w).unwrap();
+                        }
+                    }
+                }
+                multi_header(w, -1, true, -1);
+                Ok(())
+            }
+            Reply::Unknown(ref body) => {
+                w.extend_from_slice(body);
+                Ok(())
+            }
+        }
+        .unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+//! Encoding and decoding ZooKeeper's client protocol, without a client to go with it.
+//!
+//! This is what the client itself speaks the protocol with, made available for building other
+//! things that speak it too, such as proxies, traffic analyzers and mock servers. It comes in
+//! three layers:
+//!
+//! - [`frame`] splits a stream of bytes into the frames that messages are sent in;
+//! - [`jute`] reads and writes the primitives that messages are made of, and [`records`] the
+//!   records that ZooKeeper's jute definitions build out of them;
+//! - the types here read and write the messages themselves: the handshake that opens every
+//!   connection ([`ConnectRequest`] and [`ConnectResponse`]), and after it the requests
+//!   ([`RequestHeader`] and [`Op`]) and the server's replies ([`ReplyHeader`] and [`Reply`]).
+//!
+//! Only the server's side of a connection keeps any state: which opcode each xid was sent with,
+//! since a reply does not say what it is the reply to.
+//!
+//! ```
+//! use bytes::BytesMut;
+//! use tokio_zookeeper::codec::{self, frame, Op, RequestHeader};
+//!
+//! # fn main() -> Result<(), tokio_zookeeper::Error> {
+//! let mut wire = frame::frame(|w| {
+//!     RequestHeader { xid: 1, opcode: codec::opcode::DELETE }.write_to(w);
+//!     Op::Delete { path: "/foo".to_string(), version: -1 }.write_to(w);
+//! });
+//!
+//! let mut buf = BytesMut::from(&wire[..]);
+//! let frame = frame::split_frame(&mut buf)?.expect("whole frame");
+//! let mut r = &frame[..];
+//! let header = RequestHeader::read(&mut r)?;
+//! let op = Op::read(header.opcode, &mut r)?;
+//! assert_eq!(op, Op::Delete { path: "/foo".to_string(), version: -1 });
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod frame;
+pub mod jute;
+mod message;
+pub mod records;
+
+pub use self::message::{ConnectRequest, ConnectResponse, Op, Reply, ReplyHeader, RequestHeader};
+
+/// The xid of the watch events that the server sends.
+pub const NOTIFICATION_XID: i32 = -1;
+/// The xid of pings, and of the server's answers to them.
+pub const PING_XID: i32 = -2;
+/// The xid of requests to authenticate, and of the server's answers to them.
+pub const AUTH_XID: i32 = -4;
+/// The xid that clients send `SetWatches` with.
+pub const SET_WATCHES_XID: i32 = -8;
+
+/// The codes that requests say what they ask for with.
+pub mod opcode {
+    /// Watch events, which are not requests, but are tagged as such.
+    pub const NOTIFICATION: i32 = 0;
+    /// Creating a node.
+    pub const CREATE: i32 = 1;
+    /// Deleting a node.
+    pub const DELETE: i32 = 2;
+    /// Looking up a node's stat.
+    pub const EXISTS: i32 = 3;
+    /// Reading a node's data.
+    pub const GET_DATA: i32 = 4;
+    /// Replacing a node's data.
+    pub const SET_DATA: i32 = 5;
+    /// Reading a node's ACL.
+    pub const GET_ACL: i32 = 6;
+    /// Replacing a node's ACL.
+    pub const SET_ACL: i32 = 7;
+    /// Listing a node's children.
+    pub const GET_CHILDREN: i32 = 8;
+    /// Waiting for the server to catch up with the leader.
+    pub const SYNC: i32 = 9;
+    /// Keeping a session alive.
+    pub const PING: i32 = 11;
+    /// Listing a node's children along with its stat.
+    pub const GET_CHILDREN2: i32 = 12;
+    /// Checking a node's version in a multi.
+    pub const CHECK: i32 = 13;
+    /// Applying several operations at once.
+    pub const MULTI: i32 = 14;
+    /// Creating a node, and returning its stat.
+    pub const CREATE2: i32 = 15;
+    /// Adding credentials to a session.
+    pub const AUTH: i32 = 100;
+    /// Restoring watches after reconnecting.
+    pub const SET_WATCHES: i32 = 101;
+    /// Authenticating with SASL.
+    pub const SASL: i32 = 102;
+    /// Restoring watches, persistent ones included, after reconnecting.
+    pub const SET_WATCHES2: i32 = 105;
+    /// Setting a persistent watch.
+    pub const ADD_WATCH: i32 = 106;
+    /// Opening a session.
+    pub const CREATE_SESSION: i32 = -10;
+    /// Closing a session.
+    pub const CLOSE_SESSION: i32 = -11;
+    /// What results of a multi that failed are tagged with.
+    pub const ERROR: i32 = -1;
+}
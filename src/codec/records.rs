@@ -0,0 +1,154 @@
+//! The records of ZooKeeper's protocol, as declared in the jute definitions that ZooKeeper itself
+//! generates its Java classes from.
+//!
+//! `build.rs` generates this module from a copy of those definitions in `jute/zookeeper.jute`:
+//! a struct for every class, whose fields are read and written in the order the definitions
+//! declare them in. Adding a record that ZooKeeper adds is a matter of updating that copy. The
+//! structs are named and laid out as jute has them; the types of the crate's own API, such as
+//! [`crate::Stat`], convert to and from the records they correspond to.
+//!
+//! ```
+//! use tokio_zookeeper::codec::jute::{ReadFrom, WriteTo};
+//! use tokio_zookeeper::codec::records::proto::ReplyHeader;
+//!
+//! let header = ReplyHeader { xid: 1, zxid: 42, err: 0 };
+//! let mut wire = Vec::new();
+//! header.write_to(&mut wire).unwrap();
+//! assert_eq!(wire.len(), 16);
+//! assert_eq!(ReplyHeader::read_from(&mut &wire[..]).unwrap(), header);
+//! ```
+
+include!(concat!(env!("OUT_DIR"), "/records.rs"));
+
+use std::convert::TryFrom;
+use std::io;
+use super::jute::invalid;
+use crate::{Acl, KeeperState, Permission, Stat, WatchedEvent, WatchedEventType};
+
+impl From<data::Stat> for Stat {
+    fn from(stat: data::Stat) -> Self {
+        Stat {
+            czxid: stat.czxid,
+            mzxid: stat.mzxid,
+            ctime: stat.ctime,
+            mtime: stat.mtime,
+            version: stat.version,
+            cversion: stat.cversion,
+            aversion: stat.aversion,
+            ephemeral_owner: stat.ephemeral_owner,
+            data_length: stat.data_length,
+            num_children: stat.num_children,
+            pzxid: stat.pzxid,
+        }
+    }
+}
+
+impl From<Stat> for data::Stat {
+    fn from(stat: Stat) -> Self {
+        data::Stat {
+            czxid: stat.czxid,
+            mzxid: stat.mzxid,
+            ctime: stat.ctime,
+            mtime: stat.mtime,
+            version: stat.version,
+            cversion: stat.cversion,
+            aversion: stat.aversion,
+            ephemeral_owner: stat.ephemeral_owner,
+            data_length: stat.data_length,
+            num_children: stat.num_children,
+            pzxid: stat.pzxid,
+        }
+    }
+}
+
+impl From<data::Acl> for Acl {
+    fn from(acl: data::Acl) -> Self {
+        Acl {
+            perms: Permission::from_raw(acl.perms as u32),
+            scheme: acl.id.scheme,
+            id: acl.id.id,
+        }
+    }
+}
+
+impl From<&Acl> for data::Acl {
+    fn from(acl: &Acl) -> Self {
+        data::Acl {
+            perms: acl.perms.code() as i32,
+            id: data::Id {
+                scheme: acl.scheme.clone(),
+                id: acl.id.clone(),
+            },
+        }
+    }
+}
+
+impl TryFrom<proto::WatcherEvent> for WatchedEvent {
+    type Error = io::Error;
+
+    fn try_from(event: proto::WatcherEvent) -> io::Result<Self> {
+        Ok(WatchedEvent {
+            event_type: WatchedEventType::from_code(event.r#type)
+                .ok_or_else(|| invalid("unknown event type"))?,
+            keeper_state: KeeperState::from_code(event.state)
+                .ok_or_else(|| invalid("unknown keeper state"))?,
+            path: event.path,
+        })
+    }
+}
+
+impl From<&WatchedEvent> for proto::WatcherEvent {
+    fn from(event: &WatchedEvent) -> Self {
+        proto::WatcherEvent {
+            r#type: event.event_type as i32,
+            state: event.keeper_state as i32,
+            path: event.path.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{BigEndian, ByteOrder};
+    use super::*;
+    use crate::codec::jute::{ReadFrom, WriteTo};
+
+    #[test]
+    fn roundtrip() {
+        let stat = Stat {
+            czxid: 1,
+            mzxid: 2,
+            ctime: 3,
+            mtime: 4,
+            version: 5,
+            cversion: 6,
+            aversion: 7,
+            ephemeral_owner: 8,
+            data_length: 9,
+            num_children: 10,
+            pzxid: 11,
+        };
+        let mut wire = Vec::new();
+        data::Stat::from(stat).write_to(&mut wire).unwrap();
+        // the fields go out in the order that the definitions declare them in
+        let longs = |at: usize| BigEndian::read_i64(&wire[at..]);
+        let ints = |at: usize| BigEndian::read_i32(&wire[at..]);
+        assert_eq!((longs(0), longs(8), longs(16), longs(24)), (1, 2, 3, 4));
+        assert_eq!((ints(32), ints(36), ints(40)), (5, 6, 7));
+        assert_eq!((longs(44), ints(52), ints(56), longs(60)), (8, 9, 10, 11));
+        assert_eq!(wire.len(), 68);
+        assert_eq!(Stat::from(data::Stat::read_from(&mut &wire[..]).unwrap()), stat);
+
+        let watches = proto::SetWatches2 {
+            relative_zxid: 42,
+            data_watches: vec!["/a".to_string()],
+            persistent_recursive_watches: vec!["/b".to_string(), "/c".to_string()],
+            ..Default::default()
+        };
+        let mut wire = Vec::new();
+        watches.write_to(&mut wire).unwrap();
+        let mut r = &wire[..];
+        assert_eq!(proto::SetWatches2::read_from(&mut r).unwrap(), watches);
+        assert!(r.is_empty());
+    }
+}
@@ -0,0 +1,362 @@
+//! Loading the configuration of a client from a file.
+//!
+//! A [`ClientConfig`] holds the settings that tend to differ between deployments of a service
+//! rather than between its builds: where the ensemble is, the chroot, timeouts, and the TLS and
+//! authentication settings. Loading it from a file lets operators change them without recompiling
+//! the services that embed this crate.
+//!
+//! Files are in the format of Java properties files, or in TOML if their name ends in `.toml` and
+//! the `toml` feature is enabled. Both have the same keys:
+//!
+//! ```text
+//! # the connection string of the ensemble, see `ConnectString`
+//! ensemble = zk1:2181,zk2:2181,zk3:2181
+//! # the node all paths are relative to, instead of the chroot of `ensemble`
+//! chroot = /app
+//! session_timeout_ms = 10000
+//! connect_timeout_ms = 3000
+//! request_timeout_ms = 5000
+//! tls.ca = /etc/zookeeper/ca.pem
+//! tls.cert = /etc/zookeeper/client.pem
+//! tls.key = /etc/zookeeper/client.key
+//! # may be given more than once
+//! auth = digest:app:secret
+//! ```
+//!
+//! In TOML, the `tls` keys are a `[tls]` table, and every authentication entry is an `[[auth]]`
+//! table with a `scheme` and `credentials`. Only `ensemble` is required, and unknown keys are
+//! rejected, so that a misspelled setting does not go unnoticed.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! use tokio_zookeeper::config::ClientConfig;
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let config = ClientConfig::load("/etc/myservice/zookeeper.properties")?;
+//! let (zk, events) = config.connect().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::error::{Error, InvalidConfig};
+use crate::{ConnectString, WatchedEventStream, ZkPath, ZooKeeper, ZooKeeperBuilder};
+
+/// The configuration of a client, as loaded from a file.
+///
+/// See the [module documentation](self) for the format of the files.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClientConfig {
+    /// The ensemble to connect to, with the chroot to use, if any.
+    pub connect: ConnectString,
+    /// The session timeout to ask the server for, see [`ZooKeeperBuilder::set_timeout`].
+    pub session_timeout: Option<Duration>,
+    /// See [`ZooKeeperBuilder::set_connect_timeout`].
+    pub connect_timeout: Option<Duration>,
+    /// See [`ZooKeeperBuilder::set_request_timeout`].
+    pub request_timeout: Option<Duration>,
+    /// The files to connect over TLS with, if the client should.
+    pub tls: Option<Tls>,
+    /// The credentials to authenticate the session with, in order.
+    pub auth: Vec<Auth>,
+}
+
+/// The files that a client connects over TLS with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tls {
+    /// The certificates of the authorities to trust the servers' certificates from.
+    pub ca: Option<PathBuf>,
+    /// The client's certificate.
+    pub cert: Option<PathBuf>,
+    /// The private key of the client's certificate.
+    pub key: Option<PathBuf>,
+}
+
+/// Credentials to authenticate a session with, such as `digest` and `user:password`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Auth {
+    /// The authentication scheme.
+    pub scheme: String,
+    /// The credentials, in the form the scheme expects.
+    pub credentials: String,
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Auth").field("scheme", &self.scheme).finish_non_exhaustive()
+    }
+}
+
+impl From<ConnectString> for ClientConfig {
+    /// A configuration with only the ensemble of `connect`, and every other setting left at its
+    /// default.
+    fn from(connect: ConnectString) -> Self {
+        ClientConfig {
+            connect,
+            session_timeout: None,
+            connect_timeout: None,
+            request_timeout: None,
+            tls: None,
+            auth: Vec::new(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Load the configuration in the file at `path`.
+    ///
+    /// The file is read as TOML if its name ends in `.toml`, and as a properties file otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, InvalidConfig> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(InvalidConfig::Io)?;
+        if path.extension().is_some_and(|extension| extension == "toml") {
+            #[cfg(feature = "toml")]
+            return Self::from_toml(&contents);
+            #[cfg(not(feature = "toml"))]
+            return Err(InvalidConfig::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "reading TOML files requires the `toml` feature",
+            )));
+        }
+        Self::from_properties(&contents)
+    }
+
+    /// Parse a configuration in the format of a Java properties file.
+    ///
+    /// Every line is a `key = value` pair, or a comment if it starts with `#` or `!`. Keys and
+    /// values may also be separated by `:`.
+    pub fn from_properties(s: &str) -> Result<Self, InvalidConfig> {
+        let mut entries = Vec::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+                continue;
+            }
+            let (key, value) = line
+                .find(['=', ':'])
+                .map(|at| (line[..at].trim(), line[at + 1..].trim()))
+                .filter(|(key, _)| !key.is_empty())
+                .ok_or_else(|| InvalidConfig::Syntax {
+                    line: Some(i + 1),
+                    message: "expected a `key = value` pair".to_string(),
+                })?;
+            entries.push((key.to_string(), value.to_string()));
+        }
+        Self::from_entries(entries)
+    }
+
+    /// Parse a configuration in TOML.
+    #[cfg(feature = "toml")]
+    pub fn from_toml(s: &str) -> Result<Self, InvalidConfig> {
+        let table: toml::Table = s.parse().map_err(|e: toml::de::Error| InvalidConfig::Syntax {
+            line: e.span().map(|span| s[..span.start].lines().count().max(1)),
+            message: e.message().to_string(),
+        })?;
+        let mut entries = Vec::new();
+        for (key, value) in table {
+            match (key.as_str(), value) {
+                ("tls", toml::Value::Table(tls)) => {
+                    for (key, value) in tls {
+                        entries.push((format!("tls.{}", key), toml_scalar(&key, value)?));
+                    }
+                }
+                ("auth", toml::Value::Array(auth)) => {
+                    for entry in auth {
+                        let field = |key: &'static str| match entry.get(&key["auth.".len()..]) {
+                            Some(toml::Value::String(value)) => Ok(value.clone()),
+                            _ => Err(InvalidConfig::Missing(key)),
+                        };
+                        let scheme = field("auth.scheme")?;
+                        let auth = format!("{}:{}", scheme, field("auth.credentials")?);
+                        entries.push(("auth".to_string(), auth));
+                    }
+                }
+                (_, value) => {
+                    let value = toml_scalar(&key, value)?;
+                    entries.push((key, value));
+                }
+            }
+        }
+        Self::from_entries(entries)
+    }
+
+    fn from_entries(entries: Vec<(String, String)>) -> Result<Self, InvalidConfig> {
+        let mut connect: Option<ConnectString> = None;
+        let mut chroot = None;
+        let (mut session_timeout, mut connect_timeout, mut request_timeout) = (None, None, None);
+        let mut tls: Option<Tls> = None;
+        let mut auth = Vec::new();
+        for (key, value) in entries {
+            let invalid = |reason| InvalidConfig::Value {
+                key: key.clone(),
+                value: value.clone(),
+                reason,
+            };
+            let millis = || match value.parse() {
+                Ok(0) => Ok(None),
+                Ok(ms) => Ok(Some(Duration::from_millis(ms))),
+                Err(_) => Err(invalid("not a number of milliseconds")),
+            };
+            match key.as_str() {
+                "ensemble" => connect = Some(value.parse().map_err(InvalidConfig::Ensemble)?),
+                "chroot" => chroot = Some(ZkPath::new(&value).map_err(|_| invalid("not a path"))?),
+                "session_timeout_ms" => session_timeout = millis()?,
+                "connect_timeout_ms" => connect_timeout = millis()?,
+                "request_timeout_ms" => request_timeout = millis()?,
+                "tls.ca" => tls.get_or_insert_with(Tls::default).ca = Some(value.into()),
+                "tls.cert" => tls.get_or_insert_with(Tls::default).cert = Some(value.into()),
+                "tls.key" => tls.get_or_insert_with(Tls::default).key = Some(value.into()),
+                "auth" => {
+                    let (scheme, credentials) = value
+                        .split_once(':')
+                        .ok_or_else(|| invalid("expected `scheme:credentials`"))?;
+                    auth.push(Auth {
+                        scheme: scheme.to_string(),
+                        credentials: credentials.to_string(),
+                    });
+                }
+                _ => return Err(invalid("unknown key")),
+            }
+        }
+
+        let mut connect = connect.ok_or(InvalidConfig::Missing("ensemble"))?;
+        if chroot.is_some() {
+            connect.chroot = chroot;
+        }
+        connect.secure |= tls.is_some();
+        Ok(ClientConfig {
+            connect,
+            session_timeout,
+            connect_timeout,
+            request_timeout,
+            tls,
+            auth,
+        })
+    }
+
+    /// A builder with the settings of this configuration.
+    ///
+    /// The client supports neither TLS nor authentication, so this fails with an [`Error::Io`] of
+    /// kind `Unsupported` if the configuration has either.
+    pub fn builder(&self) -> Result<ZooKeeperBuilder, Error> {
+        let unsupported = |what| Error::Io(io::Error::new(io::ErrorKind::Unsupported, what));
+        if self.tls.is_some() || self.connect.secure {
+            return Err(unsupported("TLS is not supported"));
+        }
+        if !self.auth.is_empty() {
+            return Err(unsupported("authentication is not supported"));
+        }
+        let mut builder = ZooKeeperBuilder::default();
+        if let Some(timeout) = self.session_timeout {
+            builder.set_timeout(timeout);
+        }
+        builder.set_connect_timeout(self.connect_timeout);
+        builder.set_request_timeout(self.request_timeout);
+        Ok(builder)
+    }
+
+    /// Connect to the configured ensemble, with a builder from [`ClientConfig::builder`].
+    ///
+    /// See [`ZooKeeperBuilder::connect_string`].
+    pub async fn connect(&self) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        self.builder()?.connect_string(&self.connect).await
+    }
+}
+
+#[cfg(feature = "toml")]
+fn toml_scalar(key: &str, value: toml::Value) -> Result<String, InvalidConfig> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        value => Err(InvalidConfig::Value {
+            key: key.to_string(),
+            value: value.to_string(),
+            reason: "expected a string or an integer",
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::InvalidConnectString;
+
+    #[test]
+    fn properties() {
+        let config = ClientConfig::from_properties(
+            "# the production ensemble\n\
+             ensemble = zk1:2181,zk2:2182/ignored\n\
+             chroot: /app\n\
+             \n\
+             session_timeout_ms=10000\n\
+             request_timeout_ms = 0\n\
+             auth = digest:app:secret\n",
+        )
+        .unwrap();
+        assert_eq!(config.connect.to_string(), "zk1:2181,zk2:2182/app");
+        assert_eq!(config.session_timeout, Some(Duration::from_secs(10)));
+        assert_eq!(config.request_timeout, None);
+        assert_eq!(config.auth[0].scheme, "digest");
+        assert_eq!(config.auth[0].credentials, "app:secret");
+        assert!(!format!("{:?}", config).contains("secret"));
+        let builder = config.builder();
+        assert_eq!(builder.unwrap_err().io_kind(), Some(io::ErrorKind::Unsupported));
+
+        let config = ClientConfig::from_properties("ensemble=zk1\ntls.ca=/ca.pem").unwrap();
+        assert!(config.connect.secure);
+        assert_eq!(config.tls.unwrap().ca, Some(PathBuf::from("/ca.pem")));
+    }
+
+    #[test]
+    fn invalid() {
+        let parse = ClientConfig::from_properties;
+        assert!(matches!(parse("chroot = /app"), Err(InvalidConfig::Missing("ensemble"))));
+        assert!(matches!(
+            parse("ensemble = zk1\n\njust a line"),
+            Err(InvalidConfig::Syntax { line: Some(3), .. })
+        ));
+        assert!(matches!(
+            parse("ensemble = ,"),
+            Err(InvalidConfig::Ensemble(InvalidConnectString::NoHosts))
+        ));
+        assert!(matches!(
+            parse("ensemble = zk1\nsession_timeout = 10s"),
+            Err(InvalidConfig::Value { reason: "unknown key", .. })
+        ));
+        assert!(matches!(
+            parse("ensemble = zk1\nconnect_timeout_ms = 3s"),
+            Err(InvalidConfig::Value { reason: "not a number of milliseconds", .. })
+        ));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml() {
+        let config = ClientConfig::from_toml(
+            r#"
+            ensemble = "zk1:2181,zk2:2181"
+            chroot = "/app"
+            connect_timeout_ms = 3000
+
+            [tls]
+            ca = "/etc/zookeeper/ca.pem"
+
+            [[auth]]
+            scheme = "digest"
+            credentials = "app:secret"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.connect.to_string(), "zk1:2181,zk2:2181/app?secure=true");
+        assert_eq!(config.connect_timeout, Some(Duration::from_secs(3)));
+        assert_eq!(config.auth.len(), 1);
+
+        let invalid = ClientConfig::from_toml("ensemble = \"zk1\"\nsession_timeout_ms = true");
+        assert!(matches!(invalid, Err(InvalidConfig::Value { .. })));
+        let invalid = ClientConfig::from_toml("ensemble = \"zk1\"\n\nchroot = ");
+        assert!(matches!(invalid, Err(InvalidConfig::Syntax { line: Some(3), .. })));
+    }
+}
@@ -0,0 +1,248 @@
+//! Parsing the connection strings that ZooKeeper's Java client takes.
+
+use std::fmt;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use crate::error::InvalidConnectString;
+use crate::ZkPath;
+
+/// The port that servers listen for clients on, unless a connection string says otherwise.
+const DEFAULT_PORT: u16 = 2181;
+
+/// Where to find an ensemble, and how to talk to it, as given by a connection string.
+///
+/// Connection strings are parsed as the Java client parses them: a comma-separated list of
+/// `host:port` pairs, where the port defaults to 2181, optionally followed by a chroot path that
+/// all of the client's paths are relative to, as in `zk1:2181,zk2:2181,zk3/app`. So configuration
+/// can be shared verbatim between services that use either client. IPv6 addresses are written in
+/// brackets, as in `[2001:db8::1]:2181`, and are kept in [`hosts`](ConnectString::hosts) without
+/// them.
+///
+/// The Java client takes the settings that are not part of the string through system properties
+/// and constructor arguments instead; here, they can be appended as options, as in
+/// `zk1,zk2/app?readOnly=true`. The options are `secure`, to connect over TLS, and `readOnly`, to
+/// allow connecting to servers that are in read-only mode, each either `true` or `false`. A Java
+/// client would take options as part of the chroot, so strings that are shared with one should
+/// leave them off and set the fields instead.
+///
+/// The [`Display`](fmt::Display) implementation writes the string in a normal form: with every
+/// port, host names in lowercase, and only the options that are enabled.
+///
+/// See [`ZooKeeperBuilder::connect_string`](crate::ZooKeeperBuilder::connect_string).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConnectString {
+    /// The hosts and ports of the servers of the ensemble.
+    pub hosts: Vec<(String, u16)>,
+    /// The node that the client's paths are relative to, if any.
+    pub chroot: Option<ZkPath>,
+    /// Whether to connect over TLS.
+    pub secure: bool,
+    /// Whether the client may connect to servers that are in read-only mode.
+    pub read_only: bool,
+}
+
+impl FromStr for ConnectString {
+    type Err = InvalidConnectString;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (rest, options) = match s.find('?') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+        let (hosts, chroot) = match rest.find('/') {
+            Some(i) => (&rest[..i], Some(&rest[i..])),
+            None => (rest, None),
+        };
+
+        let mut connect = ConnectString {
+            hosts: Vec::new(),
+            chroot: None,
+            secure: false,
+            read_only: false,
+        };
+        for host in hosts.split(',').map(str::trim).filter(|host| !host.is_empty()) {
+            connect.hosts.push(parse_host(host)?);
+        }
+        if connect.hosts.is_empty() {
+            return Err(InvalidConnectString::NoHosts);
+        }
+        // like the Java client, take a chroot of `/` to mean none
+        if let Some(chroot) = chroot.filter(|chroot| *chroot != "/") {
+            connect.chroot = Some(ZkPath::new(chroot).map_err(InvalidConnectString::Chroot)?);
+        }
+        for option in options.into_iter().flat_map(|options| options.split('&')) {
+            let invalid = || InvalidConnectString::Option(option.to_string());
+            let (key, value) = option.split_once('=').ok_or_else(invalid)?;
+            let value = match value {
+                "true" => true,
+                "false" => false,
+                _ => return Err(invalid()),
+            };
+            match key {
+                "secure" => connect.secure = value,
+                "readOnly" => connect.read_only = value,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(connect)
+    }
+}
+
+fn parse_host(host: &str) -> Result<(String, u16), InvalidConnectString> {
+    let invalid = || InvalidConnectString::Host(host.to_string());
+    if let Some(rest) = host.strip_prefix('[') {
+        let (addr, port) = rest.split_once(']').ok_or_else(invalid)?;
+        let addr: Ipv6Addr = addr.parse().map_err(|_| invalid())?;
+        let port = match port {
+            "" | ":" => DEFAULT_PORT,
+            port => port.strip_prefix(':').and_then(|port| port.parse().ok()).ok_or_else(invalid)?,
+        };
+        return Ok((addr.to_string(), port));
+    }
+    let (name, port) = match host.rsplit_once(':') {
+        // the Java client takes an empty port to mean the default one
+        Some((name, "")) => (name, DEFAULT_PORT),
+        Some((name, port)) => (name, port.parse().map_err(|_| invalid())?),
+        None => (host, DEFAULT_PORT),
+    };
+    if name.is_empty() || name.contains(':') {
+        return Err(invalid());
+    }
+    Ok((name.to_ascii_lowercase(), port))
+}
+
+impl fmt::Display for ConnectString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (host, port)) in self.hosts.iter().enumerate() {
+            let comma = if i == 0 { "" } else { "," };
+            if host.contains(':') {
+                write!(f, "{}[{}]:{}", comma, host, port)?;
+            } else {
+                write!(f, "{}{}:{}", comma, host, port)?;
+            }
+        }
+        if let Some(ref chroot) = self.chroot {
+            write!(f, "{}", chroot)?;
+        }
+        match (self.secure, self.read_only) {
+            (true, true) => f.write_str("?secure=true&readOnly=true"),
+            (true, false) => f.write_str("?secure=true"),
+            (false, true) => f.write_str("?readOnly=true"),
+            (false, false) => Ok(()),
+        }
+    }
+}
+
+/// Order the addresses of an ensemble so that IPv6 and IPv4 addresses alternate, each family in
+/// the order it was resolved in.
+///
+/// Hosts often resolve to addresses of both families, and an ensemble may mix hosts of either, but
+/// a client may only be able to reach one of them. Alternating keeps a family that cannot connect
+/// from having every attempt spent on it before the other is tried.
+pub(crate) fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(SocketAddr::is_ipv6);
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut interleaved = Vec::with_capacity(v6.len() + v4.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return interleaved,
+            (a, b) => interleaved.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use super::*;
+    use crate::error::InvalidPath;
+    use crate::ZooKeeperBuilder;
+
+    fn parse(s: &str) -> Result<ConnectString, InvalidConnectString> {
+        s.parse()
+    }
+
+    #[test]
+    fn java() {
+        let connect = parse("zk1:2181, ZK2:2182,zk3/app").unwrap();
+        assert_eq!(
+            connect.hosts,
+            [
+                ("zk1".to_string(), 2181),
+                ("zk2".to_string(), 2182),
+                ("zk3".to_string(), 2181)
+            ]
+        );
+        assert_eq!(connect.chroot, Some(ZkPath::new("/app").unwrap()));
+        assert!(!connect.secure && !connect.read_only);
+        assert_eq!(connect.to_string(), "zk1:2181,zk2:2182,zk3:2181/app");
+
+        assert_eq!(parse("zk1:/").unwrap().to_string(), "zk1:2181");
+        assert_eq!(parse("127.0.0.1:2181,").unwrap().to_string(), "127.0.0.1:2181");
+    }
+
+    #[test]
+    fn options() {
+        let connect = parse("zk1/app?secure=true&readOnly=true").unwrap();
+        assert!(connect.secure && connect.read_only);
+        assert_eq!(connect.to_string(), "zk1:2181/app?secure=true&readOnly=true");
+        let connect = parse("zk1?readOnly=true&secure=false").unwrap();
+        assert_eq!(connect.to_string(), "zk1:2181?readOnly=true");
+
+        let option = |o: &str| Err(InvalidConnectString::Option(o.to_string()));
+        assert_eq!(parse("zk1?secure=yes"), option("secure=yes"));
+        assert_eq!(parse("zk1?tls=true"), option("tls=true"));
+        assert_eq!(parse("zk1?"), option(""));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(parse(""), Err(InvalidConnectString::NoHosts));
+        assert_eq!(parse(" , /app"), Err(InvalidConnectString::NoHosts));
+        assert_eq!(parse("zk1:port"), Err(InvalidConnectString::Host("zk1:port".to_string())));
+        assert_eq!(parse(":2181"), Err(InvalidConnectString::Host(":2181".to_string())));
+        assert_eq!(
+            parse("zk1/app/"),
+            Err(InvalidConnectString::Chroot(InvalidPath::TrailingSlash))
+        );
+    }
+
+    #[test]
+    fn ipv6() {
+        let connect = parse("[2001:DB8::1]:2182,[::1],127.0.0.1:2181,[::1]:/").unwrap();
+        assert_eq!(
+            connect.hosts,
+            [
+                ("2001:db8::1".to_string(), 2182),
+                ("::1".to_string(), 2181),
+                ("127.0.0.1".to_string(), 2181),
+                ("::1".to_string(), 2181)
+            ]
+        );
+        assert_eq!(connect.to_string(), "[2001:db8::1]:2182,[::1]:2181,127.0.0.1:2181,[::1]:2181");
+        assert_eq!(parse(&connect.to_string()), Ok(connect));
+
+        let host = |h: &str| Err(InvalidConnectString::Host(h.to_string()));
+        assert_eq!(parse("2001:db8::1"), host("2001:db8::1"));
+        assert_eq!(parse("[2001:db8::1"), host("[2001:db8::1"));
+        assert_eq!(parse("[zk1]:2181"), host("[zk1]:2181"));
+        assert_eq!(parse("[::1]2181"), host("[::1]2181"));
+    }
+
+    #[test]
+    fn interleaved() {
+        let addrs: Vec<SocketAddr> = ["[::1]:1", "[::1]:2", "127.0.0.1:3", "[::1]:4", "127.0.0.1:5"]
+            .iter()
+            .map(|addr| addr.parse().unwrap())
+            .collect();
+        let ports: Vec<_> = interleave(addrs).iter().map(SocketAddr::port).collect();
+        assert_eq!(ports, [1, 3, 2, 5, 4]);
+    }
+
+    #[tokio::test]
+    async fn secure() {
+        let connect = parse("127.0.0.1:2181?secure=true").unwrap();
+        let connected = ZooKeeperBuilder::default().connect_string(&connect).await;
+        assert_eq!(connected.unwrap_err().io_kind(), Some(io::ErrorKind::Unsupported));
+    }
+}
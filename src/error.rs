@@ -1,151 +1,814 @@
+use crate::proto::ZkError;
+use std::convert::Infallible;
+use std::error::Error as StdError;
+use std::{fmt, io};
+use crate::metrics::Operation;
+use crate::MultiResponse;
+
+/// The error that a request fails with if it did not get a response that the request's own error
+/// type can describe.
+///
+/// Errors that are specific to an operation, such as a node not existing, are not reported
+/// through this type, but as the `Err` variant of the operation's result, using one of the other
+/// types in this module.
+#[derive(Debug)]
+pub enum Error {
+    /// The connection to the server was lost before the request was answered.
+    ///
+    /// The request may or may not have taken effect.
+    ConnectionLoss,
+
+    /// The connection to the server was lost before the request was sent.
+    ///
+    /// Unlike with [`Error::ConnectionLoss`], the request is known not to have taken effect.
+    NotSent,
+
+    /// The session has expired, and the client can no longer be used.
+    ///
+    /// A new `ZooKeeper` instance has to be created to continue talking to the server.
+    SessionExpired,
+
+    /// The server failed the request with an error that the operation does not expect.
+    Server {
+        /// The error the server responded with.
+        error: ZkError,
+        /// The request that failed, if the error was the response to one.
+        context: Option<Context>,
+    },
+
+    /// The operation did not complete in time.
+    Timeout,
+
+    /// The request was not sent, because the circuit breaker of the client is open.
+    ///
+    /// See [`ZooKeeperBuilder::set_circuit_breaker`](crate::ZooKeeperBuilder::set_circuit_breaker).
+    BrokenCircuit,
+
+    /// The request was not sent, because the server would not accept it.
+    ///
+    /// The server drops the connection of a client that sends it a request that is too large, and
+    /// fails requests with invalid paths with errors that do not say what is wrong with them, so
+    /// the client checks requests before it sends them.
+    InvalidRequest(InvalidRequest),
+
+    /// A path given to a [`ZooKeeper`](crate::ZooKeeper) method as a string is not a valid
+    /// ZooKeeper path, so no request was made.
+    ///
+    /// The path is checked as it is converted into a [`ZkPath`](crate::ZkPath); methods that are
+    /// given a `ZkPath` never fail this way.
+    InvalidPath(InvalidPath),
+
+    /// The server sent something that the client did not expect.
+    Protocol(String),
+
+    /// Talking to the server failed, for instance because it could not be connected to.
+    ///
+    /// The `io::Error` is the one the transport failed with, so its kind tells why, for instance
+    /// `ConnectionRefused` or `TimedOut`. See also [`Error::io_kind`].
+    Io(io::Error),
+
+    /// Node data could not be converted to or from a Rust value by one of the accessors in the
+    /// `typed` module, or encoded or decoded by a [`PayloadCodec`](crate::payload::PayloadCodec).
+    Codec {
+        /// The node whose data could not be converted.
+        path: String,
+        /// The reason the data could not be converted.
+        error: Box<dyn StdError + Send + Sync>,
+    },
+}
+
+/// The request that an [`Error::Server`] was the response to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Context {
+    /// The operation of the request.
+    pub operation: Operation,
+    /// The path of the node the request was about, as sent to the server, if it had one.
+    pub path: Option<String>,
+    /// The xid the request was sent with.
+    pub xid: i32,
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.operation.name())?;
+        if let Some(ref path) = self.path {
+            write!(f, " {}", path)?;
+        }
+        write!(f, " (xid {})", self.xid)
+    }
+}
+
+impl Error {
+    /// The error for a request that the server failed with `error`.
+    pub(crate) fn server(error: ZkError, context: Option<Context>) -> Self {
+        match Error::from(error) {
+            Error::Server { error, .. } => Error::Server { error, context },
+            e => e,
+        }
+    }
+
+    /// The kind of I/O error the connection failed with, if this is an [`Error::Io`].
+    ///
+    /// This tells apart, for instance, a server that refused the connection from one that reset
+    /// it or one that could not be reached in time.
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match *self {
+            Error::Io(ref e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::ConnectionLoss => f.write_str("connection to the server was lost"),
+            Error::NotSent => f.write_str("connection to the server was lost before sending"),
+            Error::SessionExpired => f.write_str("session has expired"),
+            Error::Server {
+                error,
+                context: Some(ref context),
+            } => write!(f, "server failed {}: {:?}", context, error),
+            Error::Server { error, .. } => write!(f, "server failed the request: {:?}", error),
+            Error::Timeout => f.write_str("operation timed out"),
+            Error::BrokenCircuit => f.write_str("circuit breaker is open, request not sent"),
+            Error::InvalidRequest(ref e) => write!(f, "invalid request not sent: {}", e),
+            Error::InvalidPath(ref e) => write!(f, "invalid path: {}", e),
+            Error::Protocol(ref msg) => write!(f, "unexpected message from the server: {}", msg),
+            Error::Io(ref e) => write!(f, "connection failed: {}", e),
+            Error::Codec {
+                ref path,
+                ref error,
+            } => write!(f, "failed to convert the data of {}: {}", path, error),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            Error::Codec { ref error, .. } => Some(&**error),
+            Error::InvalidRequest(ref e) => Some(e),
+            Error::InvalidPath(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ZkError> for Error {
+    fn from(e: ZkError) -> Self {
+        match e {
+            ZkError::ConnectionLoss => Error::ConnectionLoss,
+            ZkError::SessionExpired => Error::SessionExpired,
+            ZkError::OperationTimeout => Error::Timeout,
+            error => Error::Server {
+                error,
+                context: None,
+            },
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<InvalidPath> for Error {
+    fn from(e: InvalidPath) -> Self {
+        Error::InvalidPath(e)
+    }
+}
+
+/// Lets the methods that take anything that converts into a [`ZkPath`](crate::ZkPath) be given
+/// a `ZkPath` itself, which cannot fail to convert.
+impl From<Infallible> for Error {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+/// An [`Error::Io`] converts back into the `io::Error` it wraps, and any other error into an
+/// `io::Error` of the closest kind that wraps it.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        let kind = match e {
+            Error::Io(e) => return e,
+            Error::ConnectionLoss => io::ErrorKind::ConnectionAborted,
+            Error::NotSent => io::ErrorKind::NotConnected,
+            Error::SessionExpired => io::ErrorKind::ConnectionReset,
+            Error::Timeout => io::ErrorKind::TimedOut,
+            Error::BrokenCircuit => io::ErrorKind::ConnectionRefused,
+            Error::Protocol(_) | Error::Codec { .. } => io::ErrorKind::InvalidData,
+            Error::InvalidRequest(_) | Error::InvalidPath(_) => io::ErrorKind::InvalidInput,
+            Error::Server { .. } => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, e)
+    }
+}
+
 /// Errors that may cause a delete request to fail.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Delete {
     /// No node exists with the given `path`.
-    #[fail(display = "target node does not exist")]
-    NoNode,
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The target node has a different version than was specified by the call to delete.
-    #[fail(
-        display = "target node has different version than expected ({})",
-        expected
-    )]
     BadVersion {
+        /// The path of the node, as it was given.
+        path: String,
         /// The expected node version.
         expected: i32,
     },
 
     /// The target node has child nodes, and therefore cannot be deleted.
-    #[fail(display = "target node has children, and cannot be deleted")]
-    NotEmpty,
+    NotEmpty {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 }
 
+impl fmt::Display for Delete {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Delete::NoNode { ref path } => write!(f, "target node {} does not exist", path),
+            Delete::BadVersion {
+                ref path,
+                ref expected,
+            } => write!(
+                f,
+                "target node {} has different version than expected ({})",
+                path, expected
+            ),
+            Delete::NotEmpty { ref path } => write!(
+                f,
+                "target node {} has children, and cannot be deleted",
+                path
+            ),
+        }
+    }
+}
+
+impl StdError for Delete {}
+
 /// Errors that may cause a `set_data` request to fail.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum SetData {
     /// No node exists with the given `path`.
-    #[fail(display = "target node does not exist")]
-    NoNode,
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The target node has a different version than was specified by the call to `set_data`.
-    #[fail(
-        display = "target node has different version than expected ({})",
-        expected
-    )]
     BadVersion {
+        /// The path of the node, as it was given.
+        path: String,
         /// The expected node version.
         expected: i32,
     },
 
     /// The target node's permission does not accept data modification or requires different
     /// authentication to be altered.
-    #[fail(display = "insuficient authentication")]
-    NoAuth,
+    NoAuth {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+}
+
+impl fmt::Display for SetData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SetData::NoNode { ref path } => write!(f, "target node {} does not exist", path),
+            SetData::BadVersion {
+                ref path,
+                ref expected,
+            } => write!(
+                f,
+                "target node {} has different version than expected ({})",
+                path, expected
+            ),
+            SetData::NoAuth { ref path } => write!(f, "insuficient authentication for {}", path),
+        }
+    }
 }
 
+impl StdError for SetData {}
+
 /// Errors that may cause a create request to fail.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Create {
     /// A node with the given `path` already exists.
-    #[fail(display = "target node already exists")]
-    NodeExists,
+    NodeExists {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The parent node of the given `path` does not exist.
-    #[fail(display = "parent node of target does not exist")]
-    NoNode,
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+
+    /// The parent node of the given `path` is ephemeral, and cannot have children.
+    NoChildrenForEphemerals {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+
+    /// The given ACL is invalid.
+    InvalidAcl {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+}
+
+impl fmt::Display for Create {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Create::NodeExists { ref path } => write!(f, "target node {} already exists", path),
+            Create::NoNode { ref path } => {
+                write!(f, "parent node of target {} does not exist", path)
+            }
+            Create::NoChildrenForEphemerals { ref path } => write!(
+                f,
+                "parent node of {} is ephemeral, and cannot have children",
+                path
+            ),
+            Create::InvalidAcl { ref path } => {
+                write!(f, "the given ACL for {} is invalid", path)
+            }
+        }
+    }
+}
+
+impl StdError for Create {}
+
+/// Errors that may cause a `create_or_set` request to fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CreateOrSet {
+    /// The parent node of the given `path` does not exist.
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The parent node of the given `path` is ephemeral, and cannot have children.
-    #[fail(display = "parent node is ephemeral, and cannot have children")]
-    NoChildrenForEphemerals,
+    NoChildrenForEphemerals {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The given ACL is invalid.
-    #[fail(display = "the given ACL is invalid")]
-    InvalidAcl,
+    InvalidAcl {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+
+    /// The target node already exists, and its permission does not accept data modification or
+    /// requires different authentication to be altered.
+    NoAuth {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+
+    /// The given mode is sequential, so every call would create a new node rather than set the
+    /// data of an existing one.
+    Sequential {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+}
+
+impl fmt::Display for CreateOrSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CreateOrSet::NoNode { ref path } => {
+                write!(f, "parent node of target {} does not exist", path)
+            }
+            CreateOrSet::NoChildrenForEphemerals { ref path } => write!(
+                f,
+                "parent node of {} is ephemeral, and cannot have children",
+                path
+            ),
+            CreateOrSet::InvalidAcl { ref path } => {
+                write!(f, "the given ACL for {} is invalid", path)
+            }
+            CreateOrSet::NoAuth { ref path } => {
+                write!(f, "insufficient authentication for {}", path)
+            }
+            CreateOrSet::Sequential { ref path } => {
+                write!(f, "sequential node {} cannot be set", path)
+            }
+        }
+    }
+}
+
+impl StdError for CreateOrSet {}
+
+/// Errors that may cause a `compare_and_swap` request to fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CompareAndSwap {
+    /// No node exists with the given `path`.
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+
+    /// The target node was modified concurrently on every attempt, and no retries remain.
+    BadVersion {
+        /// The path of the node, as it was given.
+        path: String,
+        /// The node version expected by the last attempt.
+        expected: i32,
+    },
+
+    /// The target node's permission does not accept data modification or requires different
+    /// authentication to be altered.
+    NoAuth {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+}
+
+impl fmt::Display for CompareAndSwap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CompareAndSwap::NoNode { ref path } => write!(f, "target node {} does not exist", path),
+            CompareAndSwap::BadVersion {
+                ref path,
+                ref expected,
+            } => write!(
+                f,
+                "target node {} has different version than expected ({})",
+                path, expected
+            ),
+            CompareAndSwap::NoAuth { ref path } => {
+                write!(f, "insufficient authentication for {}", path)
+            }
+        }
+    }
+}
+
+impl StdError for CompareAndSwap {}
+
+/// Errors that may cause a `copy_subtree` request to fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CopySubtree {
+    /// No node exists at the source path.
+    NoNode,
+
+    /// The destination path is equal to, or inside of, the source subtree.
+    Overlapping,
+
+    /// A destination node could not be created.
+    Create {
+        /// The destination node that could not be created.
+        path: String,
+        /// The reason the node could not be created.
+        error: Create,
+    },
+
+    /// An existing destination node could not be overwritten.
+    SetData {
+        /// The destination node that could not be overwritten.
+        path: String,
+        /// The reason the node could not be overwritten.
+        error: SetData,
+    },
+
+    /// The ACL of an existing destination node could not be overwritten.
+    SetAcl {
+        /// The destination node whose ACL could not be overwritten.
+        path: String,
+        /// The reason the ACL could not be overwritten.
+        error: SetAcl,
+    },
+}
+
+impl fmt::Display for CopySubtree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CopySubtree::NoNode => f.write_str("source node does not exist"),
+            CopySubtree::Overlapping => f.write_str("destination is inside the source subtree"),
+            CopySubtree::Create {
+                ref path,
+                ref error,
+            } => write!(f, "failed to create {}: {}", path, error),
+            CopySubtree::SetData {
+                ref path,
+                ref error,
+            } => write!(f, "failed to overwrite {}: {}", path, error),
+            CopySubtree::SetAcl {
+                ref path,
+                ref error,
+            } => write!(f, "failed to overwrite the ACL of {}: {}", path, error),
+        }
+    }
+}
+
+impl StdError for CopySubtree {}
+
+/// Errors that may cause a `move_subtree` request to fail.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum MoveSubtree {
+    /// No node exists at the source path.
+    NoNode,
+
+    /// The destination path is equal to, or inside of, the source subtree.
+    Overlapping,
+
+    /// The source subtree could not be copied to the destination. No source nodes were deleted.
+    Copy(CopySubtree),
+
+    /// A destination node is missing, or its data differs from that of its source node.
+    Mismatch {
+        /// The destination node that does not match.
+        path: String,
+    },
+
+    /// A source node was modified or deleted after it was copied.
+    Modified {
+        /// The source node that was modified.
+        path: String,
+    },
+
+    /// A source node has children that were not moved, such as ephemeral nodes or nodes created
+    /// after the copy, and therefore cannot be deleted.
+    NotEmpty {
+        /// The source node that could not be deleted.
+        path: String,
+    },
+}
+
+impl fmt::Display for MoveSubtree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MoveSubtree::NoNode => f.write_str("source node does not exist"),
+            MoveSubtree::Overlapping => f.write_str("destination is inside the source subtree"),
+            MoveSubtree::Copy(ref e) => write!(f, "copy failed: {}", e),
+            MoveSubtree::Mismatch { ref path } => write!(f, "{} does not match its source", path),
+            MoveSubtree::Modified { ref path } => {
+                write!(f, "{} was modified while it was being moved", path)
+            }
+            MoveSubtree::NotEmpty { ref path } => {
+                write!(f, "{} has children that were not moved", path)
+            }
+        }
+    }
+}
+
+impl StdError for MoveSubtree {}
+
+/// Errors that may cause an `export_tree` request to fail.
+#[derive(Debug)]
+pub enum ExportTree {
+    /// No node exists at the given path.
+    NoNode,
+
+    /// The export could not be written.
+    Write(io::Error),
+}
+
+impl fmt::Display for ExportTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExportTree::NoNode => f.write_str("node does not exist"),
+            ExportTree::Write(ref e) => write!(f, "failed to write the export: {}", e),
+        }
+    }
+}
+
+impl StdError for ExportTree {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ExportTree::Write(ref e) => Some(e),
+            ExportTree::NoNode => None,
+        }
+    }
+}
+
+/// Errors that may cause an `import_tree` request to fail.
+#[derive(Debug)]
+pub enum ImportTree {
+    /// What was read does not start like an export does.
+    NotAnExport,
+
+    /// The export is in a version of the format that this client does not know.
+    UnsupportedVersion(i32),
+
+    /// The export could not be read, or is cut short or corrupt.
+    Read(io::Error),
+
+    /// A node could not be created.
+    Create {
+        /// The node that could not be created.
+        path: String,
+        /// The reason the node could not be created.
+        error: Create,
+    },
+
+    /// An existing node could not be overwritten.
+    SetData {
+        /// The node that could not be overwritten.
+        path: String,
+        /// The reason the node could not be overwritten.
+        error: SetData,
+    },
+
+    /// The ACL of an existing node could not be overwritten.
+    SetAcl {
+        /// The node whose ACL could not be overwritten.
+        path: String,
+        /// The reason the ACL could not be overwritten.
+        error: SetAcl,
+    },
+}
+
+impl fmt::Display for ImportTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImportTree::NotAnExport => f.write_str("not an export"),
+            ImportTree::UnsupportedVersion(version) => {
+                write!(f, "unsupported export format version {}", version)
+            }
+            ImportTree::Read(ref e) => write!(f, "failed to read the export: {}", e),
+            ImportTree::Create {
+                ref path,
+                ref error,
+            } => write!(f, "failed to create {}: {}", path, error),
+            ImportTree::SetData {
+                ref path,
+                ref error,
+            } => write!(f, "failed to overwrite {}: {}", path, error),
+            ImportTree::SetAcl {
+                ref path,
+                ref error,
+            } => write!(f, "failed to overwrite the ACL of {}: {}", path, error),
+        }
+    }
+}
+
+impl StdError for ImportTree {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            ImportTree::Read(ref e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 /// Errors that may cause a `get_acl` request to fail.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum GetAcl {
     /// No node exists with the given `path`.
-    #[fail(display = "target node does not exist")]
-    NoNode,
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+}
+
+impl fmt::Display for GetAcl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GetAcl::NoNode { ref path } => write!(f, "target node {} does not exist", path),
+        }
+    }
 }
 
+impl StdError for GetAcl {}
+
 /// Errors that may cause a `set_acl` request to fail.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum SetAcl {
     /// No node exists with the given `path`.
-    #[fail(display = "target node does not exist")]
-    NoNode,
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The target node has a different version than was specified by the call to `set_acl`.
-    #[fail(
-        display = "target node has different version than expected ({})",
-        expected
-    )]
     BadVersion {
+        /// The path of the node, as it was given.
+        path: String,
         /// The expected node version.
         expected: i32,
     },
 
     /// The given ACL is invalid.
-    #[fail(display = "the given ACL is invalid")]
-    InvalidAcl,
+    InvalidAcl {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The target node's permission does not accept acl modification or requires different
     /// authentication to be altered.
-    #[fail(display = "insufficient authentication")]
-    NoAuth,
+    NoAuth {
+        /// The path of the node, as it was given.
+        path: String,
+    },
+}
+
+impl fmt::Display for SetAcl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SetAcl::NoNode { ref path } => write!(f, "target node {} does not exist", path),
+            SetAcl::BadVersion {
+                ref path,
+                ref expected,
+            } => write!(
+                f,
+                "target node {} has different version than expected ({})",
+                path, expected
+            ),
+            SetAcl::InvalidAcl { ref path } => write!(f, "the given ACL for {} is invalid", path),
+            SetAcl::NoAuth { ref path } => write!(f, "insufficient authentication for {}", path),
+        }
+    }
 }
 
+impl StdError for SetAcl {}
+
 /// Errors that may cause a `check` request to fail.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Check {
     /// No node exists with the given `path`.
-    #[fail(display = "target node does not exist")]
-    NoNode,
+    NoNode {
+        /// The path of the node, as it was given.
+        path: String,
+    },
 
     /// The target node has a different version than was specified by the call to `check`.
-    #[fail(
-        display = "target node has different version than expected ({})",
-        expected
-    )]
     BadVersion {
+        /// The path of the node, as it was given.
+        path: String,
         /// The expected node version.
         expected: i32,
     },
 }
 
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Check::NoNode { ref path } => write!(f, "target node {} does not exist", path),
+            Check::BadVersion {
+                ref path,
+                ref expected,
+            } => write!(
+                f,
+                "target node {} has different version than expected ({})",
+                path, expected
+            ),
+        }
+    }
+}
+
+impl StdError for Check {}
+
 /// The result of a failed `multi` request.
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Fail)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Multi {
     /// A failed `delete` request.
-    #[fail(display = "delete failed: {}", 0)]
     Delete(Delete),
 
     /// A failed `set_data` request.
-    #[fail(display = "set_data failed: {}", 0)]
     SetData(SetData),
 
     /// A failed `create` request.
-    #[fail(display = "create failed: {}", 0)]
     Create(Create),
 
     /// A failed `check` request.
-    #[fail(display = "check failed")]
     Check(Check),
 
     /// The request would have succeeded, but a later request in the `multi`
     /// batch failed and caused this request to get rolled back.
-    #[fail(display = "request rolled back due to later failed request")]
     RolledBack,
 
     /// The request was skipped because an earlier request in the `multi` batch
     /// failed. It is unknown whether this request would have succeeded.
-    #[fail(display = "request failed due to earlier failed request")]
     Skipped,
 }
 
+impl fmt::Display for Multi {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Multi::Delete(ref e) => write!(f, "delete failed: {}", e),
+            Multi::SetData(ref e) => write!(f, "set_data failed: {}", e),
+            Multi::Create(ref e) => write!(f, "create failed: {}", e),
+            Multi::Check(ref e) => write!(f, "check failed: {}", e),
+            Multi::RolledBack => f.write_str("request rolled back due to later failed request"),
+            Multi::Skipped => f.write_str("request failed due to earlier failed request"),
+        }
+    }
+}
+
+impl StdError for Multi {}
+
 impl From<Delete> for Multi {
     fn from(err: Delete) -> Self {
         Multi::Delete(err)
@@ -169,3 +832,349 @@ impl From<Check> for Multi {
         Multi::Check(err)
     }
 }
+
+/// The result of a `multi` request that failed as a whole, so that none of its operations took
+/// effect.
+#[derive(PartialEq, Debug)]
+pub struct MultiFailed {
+    /// The index of the operation that caused the request to fail.
+    pub index: usize,
+
+    /// The outcome of every operation, in the order in which they were added to the request.
+    ///
+    /// As the server reports them, the operations before `index` are [`Multi::RolledBack`], and
+    /// the ones after it are [`Multi::Skipped`].
+    pub results: Vec<Result<MultiResponse, Multi>>,
+}
+
+impl MultiFailed {
+    /// The error of the operation that caused the request to fail.
+    pub fn cause(&self) -> &Multi {
+        match self.results[self.index] {
+            Err(ref e) => e,
+            Ok(_) => unreachable!("the failed operation succeeded"),
+        }
+    }
+}
+
+impl fmt::Display for MultiFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation {} failed: {}", self.index, self.cause())
+    }
+}
+
+impl StdError for MultiFailed {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.cause())
+    }
+}
+
+/// Reasons why a string is not a valid ZooKeeper path.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum InvalidPath {
+    /// The path is empty.
+    Empty,
+
+    /// The path does not start with `/`.
+    NotAbsolute,
+
+    /// The path ends with `/`.
+    TrailingSlash,
+
+    /// The path contains an empty node name, such as in `/a//b`.
+    EmptyNode,
+
+    /// The path contains the relative node name `.` or `..`.
+    RelativeNode,
+
+    /// The path contains a character that ZooKeeper does not allow.
+    IllegalCharacter {
+        /// The offending character.
+        character: char,
+        /// The byte offset of `character` within the path.
+        index: usize,
+    },
+}
+
+impl fmt::Display for InvalidPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidPath::Empty => f.write_str("path must not be empty"),
+            InvalidPath::NotAbsolute => f.write_str("path must start with '/'"),
+            InvalidPath::TrailingSlash => f.write_str("path must not end with '/'"),
+            InvalidPath::EmptyNode => f.write_str("path must not contain empty node names"),
+            InvalidPath::RelativeNode => f.write_str("path must not contain relative node names"),
+            InvalidPath::IllegalCharacter {
+                ref character,
+                ref index,
+            } => write!(
+                f,
+                "path contains illegal character {:?} at byte {}",
+                character, index
+            ),
+        }
+    }
+}
+
+impl StdError for InvalidPath {}
+
+/// Reasons why a string is not a valid [`ConnectString`](crate::ConnectString).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InvalidConnectString {
+    /// The string does not name any hosts.
+    NoHosts,
+
+    /// One of the `host:port` pairs of the string, given here, cannot be parsed.
+    Host(String),
+
+    /// The chroot path of the string is not a valid path.
+    Chroot(InvalidPath),
+
+    /// One of the options of the string, given here, is not known, or has a value other than
+    /// `true` or `false`.
+    Option(String),
+}
+
+impl fmt::Display for InvalidConnectString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidConnectString::NoHosts => f.write_str("connection string names no hosts"),
+            InvalidConnectString::Host(ref host) => write!(f, "invalid host {:?}", host),
+            InvalidConnectString::Chroot(ref e) => write!(f, "invalid chroot: {}", e),
+            InvalidConnectString::Option(ref option) => write!(f, "invalid option {:?}", option),
+        }
+    }
+}
+
+impl StdError for InvalidConnectString {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            InvalidConnectString::Chroot(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Reasons why a [`ClientConfig`] could not be loaded.
+///
+/// [`ClientConfig`]: crate::config::ClientConfig
+#[derive(Debug)]
+pub enum InvalidConfig {
+    /// The file could not be read.
+    Io(io::Error),
+
+    /// The file is not in the format it was read as.
+    Syntax {
+        /// The line of the file with the error, counted from 1, if it is known.
+        line: Option<usize>,
+        /// What is wrong with it.
+        message: String,
+    },
+
+    /// A key that is required, given here, is missing.
+    Missing(&'static str),
+
+    /// A key is not known, or its value is not valid for it.
+    Value {
+        /// The key.
+        key: String,
+        /// Its value.
+        value: String,
+        /// Why the key cannot be given the value.
+        reason: &'static str,
+    },
+
+    /// The `ensemble` is not a valid connection string.
+    Ensemble(InvalidConnectString),
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidConfig::Io(ref e) => write!(f, "failed to read configuration: {}", e),
+            InvalidConfig::Syntax {
+                line: Some(line),
+                ref message,
+            } => write!(f, "syntax error on line {}: {}", line, message),
+            InvalidConfig::Syntax {
+                line: None,
+                ref message,
+            } => write!(f, "syntax error: {}", message),
+            InvalidConfig::Missing(key) => write!(f, "missing required key {:?}", key),
+            InvalidConfig::Value {
+                ref key,
+                ref value,
+                reason,
+            } => write!(f, "cannot set {} to {:?}: {}", key, value, reason),
+            InvalidConfig::Ensemble(ref e) => write!(f, "invalid ensemble: {}", e),
+        }
+    }
+}
+
+impl StdError for InvalidConfig {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            InvalidConfig::Io(ref e) => Some(e),
+            InvalidConfig::Ensemble(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A property that [`ZooKeeperBuilder::apply_properties`] could not apply.
+///
+/// [`ZooKeeperBuilder::apply_properties`]: crate::ZooKeeperBuilder::apply_properties
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidProperty {
+    /// The name of the property.
+    pub name: String,
+    /// The value it was given.
+    pub value: String,
+    /// Why the value cannot be applied.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for InvalidProperty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cannot apply {}={:?}: {}", self.name, self.value, self.reason)
+    }
+}
+
+impl StdError for InvalidProperty {}
+
+/// Reasons why the client refused to send a request, see [`Error::InvalidRequest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InvalidRequest {
+    /// A path of the request is not a valid ZooKeeper path.
+    Path {
+        /// The path, as it would have been sent to the server.
+        path: String,
+        /// What is wrong with it.
+        error: InvalidPath,
+    },
+
+    /// The request is larger than the server accepts.
+    ///
+    /// See [`ZooKeeperBuilder::set_max_request_size`].
+    ///
+    /// [`ZooKeeperBuilder::set_max_request_size`]: crate::ZooKeeperBuilder::set_max_request_size
+    TooLarge {
+        /// The path of the node the request operates on, if it operates on a single node.
+        path: Option<String>,
+        /// The size of the request in bytes, which is mostly that of the data it sets.
+        size: usize,
+        /// The largest request the server accepts.
+        max: usize,
+    },
+}
+
+impl fmt::Display for InvalidRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvalidRequest::Path {
+                ref path,
+                ref error,
+            } => write!(f, "invalid path {:?}: {}", path, error),
+            InvalidRequest::TooLarge {
+                path: Some(ref path),
+                size,
+                max,
+            } => write!(f, "request for {} is {} bytes, more than the {} allowed", path, size, max),
+            InvalidRequest::TooLarge { size, max, .. } => {
+                write!(f, "request is {} bytes, more than the {} allowed", size, max)
+            }
+        }
+    }
+}
+
+impl StdError for InvalidRequest {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match *self {
+            InvalidRequest::Path { ref error, .. } => Some(error),
+            InvalidRequest::TooLarge { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_zk_error() {
+        assert!(matches!(Error::from(ZkError::ConnectionLoss), Error::ConnectionLoss));
+        assert!(matches!(Error::from(ZkError::SessionExpired), Error::SessionExpired));
+        assert!(matches!(Error::from(ZkError::OperationTimeout), Error::Timeout));
+        assert!(matches!(
+            Error::from(ZkError::NoAuth),
+            Error::Server {
+                error: ZkError::NoAuth,
+                context: None
+            }
+        ));
+        assert_eq!(
+            Error::from(ZkError::NoAuth).to_string(),
+            "server failed the request: NoAuth"
+        );
+    }
+
+    #[test]
+    fn server_context() {
+        let context = Context {
+            operation: Operation::GetData,
+            path: Some("/foo".to_string()),
+            xid: 7,
+        };
+        assert_eq!(
+            Error::server(ZkError::NoAuth, Some(context.clone())).to_string(),
+            "server failed get_data /foo (xid 7): NoAuth"
+        );
+        // errors that are not about the request itself do not keep the context
+        assert!(matches!(
+            Error::server(ZkError::ConnectionLoss, Some(context)),
+            Error::ConnectionLoss
+        ));
+    }
+
+    #[test]
+    fn operation_path() {
+        let path = || "/foo".to_string();
+        assert_eq!(
+            Delete::NoNode { path: path() }.to_string(),
+            "target node /foo does not exist"
+        );
+        assert_eq!(
+            Create::NodeExists { path: path() }.to_string(),
+            "target node /foo already exists"
+        );
+        assert_eq!(
+            SetData::BadVersion {
+                path: path(),
+                expected: 3
+            }
+            .to_string(),
+            "target node /foo has different version than expected (3)"
+        );
+        // the path is kept through a multi request
+        assert_eq!(
+            Multi::from(Check::NoNode { path: path() }).to_string(),
+            "check failed: target node /foo does not exist"
+        );
+    }
+
+    #[test]
+    fn io_error() {
+        let refused = Error::from(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert_eq!(refused.io_kind(), Some(io::ErrorKind::ConnectionRefused));
+        assert_eq!(
+            io::Error::from(refused).kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+
+        assert_eq!(Error::Timeout.io_kind(), None);
+        let timeout = io::Error::from(Error::Timeout);
+        assert_eq!(timeout.kind(), io::ErrorKind::TimedOut);
+        let inner = timeout.get_ref().and_then(|e| e.downcast_ref::<Error>());
+        assert!(matches!(inner, Some(Error::Timeout)));
+    }
+}
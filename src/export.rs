@@ -0,0 +1,397 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use futures::prelude::*;
+use futures::{future, stream};
+use std::borrow::Cow;
+use std::io::{self, Read, Write};
+use crate::codec::jute::{
+    invalid, read_list, write_list, BufferReader, ReadFrom, StringReader, WriteTo,
+};
+use crate::subtree::{is_within, rebase, OnExisting, CONCURRENCY};
+use crate::{error, Acl, CreateMode, Error, ZooKeeper};
+
+/// What every export starts with.
+const MAGIC: &[u8; 6] = b"ZKTREE";
+
+/// The version of the format that exports are written in.
+const VERSION: i32 = 1;
+
+/// The subtree that servers keep their own state in.
+pub(crate) const SYSTEM: &str = "/zookeeper";
+
+/// The `ephemeral_owner` that servers give container nodes.
+const CONTAINER_OWNER: i64 = i64::MIN;
+
+/// Options for [`ZooKeeper::export_tree`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExportOptions {
+    /// Also export ephemeral nodes, which an import then creates as ephemeral nodes owned by the
+    /// importing client's session.
+    ///
+    /// Defaults to `false`.
+    pub include_ephemerals: bool,
+}
+
+/// Options for [`ZooKeeper::import_tree`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportOptions {
+    /// The ACL to give every imported node. If `None`, each node gets the ACL it was exported
+    /// with.
+    ///
+    /// Defaults to `None`.
+    pub acl: Option<Vec<Acl>>,
+
+    /// What to do when a node already exists.
+    ///
+    /// Defaults to [`OnExisting::Fail`].
+    pub on_existing: OnExisting,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        ImportOptions {
+            acl: None,
+            on_existing: OnExisting::Fail,
+        }
+    }
+}
+
+/// A node as it is exported.
+struct Node {
+    /// The node's path, relative to the root of the export.
+    path: String,
+    mode: CreateMode,
+    acl: Vec<Acl>,
+    data: Vec<u8>,
+}
+
+impl Node {
+    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_u8(1)?;
+        self.path.write_to(&mut writer)?;
+        writer.write_i32::<BigEndian>(self.mode as i32)?;
+        write_list(&mut writer, &self.acl)?;
+        self.data[..].write_to(writer)
+    }
+
+    /// Read the next node, or `None` at the end of the export.
+    fn read_from<R: Read>(read: &mut R) -> io::Result<Option<Self>> {
+        match read.read_u8()? {
+            0 => return Ok(None),
+            1 => {}
+            _ => return Err(invalid("corrupt export")),
+        }
+        let path = read.read_string()?;
+        if !path.starts_with('/') {
+            return Err(invalid("exported path is not absolute"));
+        }
+        let mode = match read.read_i32::<BigEndian>()? {
+            0 => CreateMode::Persistent,
+            1 => CreateMode::Ephemeral,
+            4 => CreateMode::Container,
+            _ => return Err(invalid("unknown create mode in export")),
+        };
+        let acl = read_list(read, Acl::read_from)?;
+        let data = read.read_buffer()?;
+        Ok(Some(Node {
+            path,
+            mode,
+            acl,
+            data,
+        }))
+    }
+}
+
+/// The mode that a node with the given `ephemeral_owner` is exported with.
+pub(crate) fn mode(ephemeral_owner: i64) -> CreateMode {
+    match ephemeral_owner {
+        0 => CreateMode::Persistent,
+        CONTAINER_OWNER => CreateMode::Container,
+        // TTL nodes, which this client cannot create, are marked by a top byte of all ones
+        owner if (owner as u64) >> 56 == 0xff => CreateMode::Persistent,
+        _ => CreateMode::Ephemeral,
+    }
+}
+
+/// Read the node at `path` for export, or `None` if it is left out of it.
+async fn read_node(
+    zk: &ZooKeeper,
+    path: &str,
+    root: &str,
+    options: ExportOptions,
+) -> Result<Option<Node>, Error> {
+    let (data, stat) = match zk.get_data(path).await? {
+        Some(res) => res,
+        // deleted since we listed it
+        None => return Ok(None),
+    };
+    let mode = mode(stat.ephemeral_owner);
+    if mode == CreateMode::Ephemeral && !options.include_ephemerals {
+        return Ok(None);
+    }
+    let acl = match zk.get_acl(path).await? {
+        Ok((acl, _)) => acl,
+        // deleted since we read its data
+        Err(_) => return Ok(None),
+    };
+    Ok(Some(Node {
+        path: rebase(path, root, "/"),
+        mode,
+        acl,
+        data,
+    }))
+}
+
+impl ZooKeeper {
+    /// Write the node at the given `path` and all of its descendants to `writer`, to be restored
+    /// later by [`ZooKeeper::import_tree`], for instance to back the subtree up, move it to
+    /// another ensemble, or seed a test environment with it.
+    ///
+    /// Each node's data, ACL and create mode is exported. Ephemeral nodes are left out unless
+    /// `options.include_ephemerals` is set, and so is the `/zookeeper` subtree, which the server
+    /// keeps its own state in, unless it is the one being exported. TTL nodes are exported as
+    /// persistent nodes, since this client cannot create them. On success, the number of nodes
+    /// that were exported is returned.
+    ///
+    /// Like [`ZooKeeper::list_subtree`], the export is not atomic: nodes that are modified while
+    /// it is in progress may be exported as they were before or after the change. The export is
+    /// written as the nodes are read, so a `writer` that is a file should be buffered.
+    ///
+    /// # Format
+    ///
+    /// Exports are written in the jute encoding that ZooKeeper's messages are written in (see
+    /// [`codec::jute`](crate::codec::jute)):
+    ///
+    /// - the six bytes `ZKTREE`;
+    /// - the version of the format as an `int`, which is 1;
+    /// - every node, parents before their children, as a `boolean` that is `true`, followed by
+    ///   the node's path as a `ustring` relative to the exported node, which is itself `/`, its
+    ///   [`CreateMode`] as an `int` (0 for persistent, 1 for ephemeral and 4 for container
+    ///   nodes), its ACL as a `vector<ACL>`, and its data as a `buffer`;
+    /// - a `boolean` that is `false`.
+    pub async fn export_tree<W>(
+        &self,
+        path: &str,
+        mut writer: W,
+        options: ExportOptions,
+    ) -> Result<Result<usize, error::ExportTree>, Error>
+    where
+        W: Write,
+    {
+        trace!(self.logger, "export_tree"; "path" => path);
+        let paths = match self.list_subtree(path).await? {
+            Some(paths) => paths,
+            None => return Ok(Err(error::ExportTree::NoNode)),
+        };
+        let header = writer
+            .write_all(MAGIC)
+            .and_then(|()| writer.write_i32::<BigEndian>(VERSION));
+        if let Err(e) = header {
+            return Ok(Err(error::ExportTree::Write(e)));
+        }
+
+        let skip_system = !is_within(path, SYSTEM);
+        let nodes = stream::iter(paths)
+            .filter(|node| future::ready(!(skip_system && is_within(node, SYSTEM))))
+            .map(|node| async move { read_node(self, &node, path, options).await })
+            .buffered(CONCURRENCY)
+            .try_filter_map(future::ok);
+        futures::pin_mut!(nodes);
+        let mut exported = 0;
+        while let Some(node) = nodes.try_next().await? {
+            if let Err(e) = node.write_to(&mut writer) {
+                return Ok(Err(error::ExportTree::Write(e)));
+            }
+            exported += 1;
+        }
+        if let Err(e) = writer.write_u8(0).and_then(|()| writer.flush()) {
+            return Ok(Err(error::ExportTree::Write(e)));
+        }
+        Ok(Ok(exported))
+    }
+
+    /// Recreate the nodes of an export that [`ZooKeeper::export_tree`] wrote to `reader` at the
+    /// given `path`, which takes the place of the node that was exported.
+    ///
+    /// Nodes are created with the data, create mode, and either the ACL they were exported with
+    /// or the one given in `options`, parents before their children. Ephemeral nodes in the
+    /// export are created as ephemeral nodes owned by this client's session. On success, the
+    /// number of nodes that were created or overwritten is returned.
+    ///
+    /// The parent of `path` must already exist. A node at `path` itself is treated like any other
+    /// existing node, so importing to `/` needs [`OnExisting::Skip`] or
+    /// [`OnExisting::Overwrite`]. The import is not atomic: if it fails part-way through, the
+    /// nodes that were imported until then are left in place.
+    pub async fn import_tree<R>(
+        &self,
+        path: &str,
+        mut reader: R,
+        options: ImportOptions,
+    ) -> Result<Result<usize, error::ImportTree>, Error>
+    where
+        R: Read,
+    {
+        trace!(self.logger, "import_tree"; "path" => path);
+        let mut magic = [0; 6];
+        match reader.read_exact(&mut magic) {
+            Ok(()) if &magic == MAGIC => {}
+            Ok(()) => return Ok(Err(error::ImportTree::NotAnExport)),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Ok(Err(error::ImportTree::NotAnExport));
+            }
+            Err(e) => return Ok(Err(error::ImportTree::Read(e))),
+        }
+        match reader.read_i32::<BigEndian>() {
+            Ok(VERSION) => {}
+            Ok(version) => return Ok(Err(error::ImportTree::UnsupportedVersion(version))),
+            Err(e) => return Ok(Err(error::ImportTree::Read(e))),
+        }
+
+        let mut imported = 0;
+        loop {
+            let node = match Node::read_from(&mut reader) {
+                Ok(Some(node)) => node,
+                Ok(None) => return Ok(Ok(imported)),
+                Err(e) => return Ok(Err(error::ImportTree::Read(e))),
+            };
+            let target = rebase(&node.path, "/", path);
+            match import_node(self, &target, node, &options).await? {
+                Ok(true) => imported += 1,
+                Ok(false) => {}
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+    }
+}
+
+/// Create a single imported node at `path`, resolving to whether it was written to.
+async fn import_node(
+    zk: &ZooKeeper,
+    path: &str,
+    node: Node,
+    options: &ImportOptions,
+) -> Result<Result<bool, error::ImportTree>, Error> {
+    let acl = options.acl.clone().unwrap_or(node.acl);
+    let data: Cow<'static, [u8]> = node.data.into();
+    let error = match zk.create(path, data.clone(), acl.clone(), node.mode).await? {
+        Ok(_) => return Ok(Ok(true)),
+        Err(error::Create::NodeExists { .. }) if options.on_existing == OnExisting::Skip => {
+            return Ok(Ok(false))
+        }
+        Err(error::Create::NodeExists { .. }) if options.on_existing == OnExisting::Overwrite => {
+            if let Err(error) = zk.set_data(path, None, data).await? {
+                return Ok(Err(error::ImportTree::SetData {
+                    path: path.to_string(),
+                    error,
+                }));
+            }
+            if options.acl.is_some() {
+                return Ok(Ok(true));
+            }
+            return match zk.set_acl(path, acl, None).await? {
+                Ok(_) => Ok(Ok(true)),
+                Err(error) => Ok(Err(error::ImportTree::SetAcl {
+                    path: path.to_string(),
+                    error,
+                })),
+            };
+        }
+        Err(error) => error,
+    };
+    Ok(Err(error::ImportTree::Create {
+        path: path.to_string(),
+        error,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockZk;
+
+    #[test]
+    fn modes() {
+        assert_eq!(mode(0), CreateMode::Persistent);
+        assert_eq!(mode(0x0100_0000_0000_0001), CreateMode::Ephemeral);
+        assert_eq!(mode(i64::MIN), CreateMode::Container);
+        assert_eq!(mode(0xff00_0000_0000_1000_u64 as i64), CreateMode::Persistent);
+    }
+
+    #[tokio::test]
+    async fn export_and_import() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::read_unsafe();
+        for &(path, data, acl, mode) in &[
+            ("/src", &b"root"[..], Acl::open_unsafe(), CreateMode::Persistent),
+            ("/src/a", &b"a"[..], acl, CreateMode::Persistent),
+            ("/src/a/b", &b""[..], Acl::open_unsafe(), CreateMode::Persistent),
+            ("/src/e", &b"e"[..], Acl::open_unsafe(), CreateMode::Ephemeral),
+        ] {
+            zk.create(path, data, acl, mode).await.unwrap().unwrap();
+        }
+
+        let mut export = Vec::new();
+        let res = zk.export_tree("/src", &mut export, Default::default()).await;
+        assert_eq!(res.unwrap().unwrap(), 3);
+        let res = zk.import_tree("/dst", &export[..], Default::default()).await;
+        assert_eq!(res.unwrap().unwrap(), 3);
+        let diff = zk.diff_subtrees("/src", "/dst", true).await.unwrap();
+        assert_eq!(diff.removed, ["/e"]);
+        assert!(diff.added.is_empty() && diff.modified.is_empty());
+
+        // the root takes part like any other node
+        let res = zk.import_tree("/dst", &export[..], Default::default()).await;
+        assert!(matches!(
+            res.unwrap(),
+            Err(error::ImportTree::Create { ref path, error: error::Create::NodeExists { .. } })
+                if path == "/dst"
+        ));
+        zk.set_data("/dst/a", None, &b"changed"[..]).await.unwrap().unwrap();
+        let options = ImportOptions {
+            on_existing: OnExisting::Overwrite,
+            ..Default::default()
+        };
+        let res = zk.import_tree("/dst", &export[..], options).await;
+        assert_eq!(res.unwrap().unwrap(), 3);
+        assert_eq!(zk.get_data("/dst/a").await.unwrap().unwrap().0, b"a");
+
+        let options = ExportOptions {
+            include_ephemerals: true,
+        };
+        let mut export = Vec::new();
+        let res = zk.export_tree("/", &mut export, options).await;
+        // the root and everything under /src and /dst, but not /zookeeper
+        assert_eq!(res.unwrap().unwrap(), 1 + 4 + 3);
+        let options = ImportOptions {
+            on_existing: OnExisting::Skip,
+            ..Default::default()
+        };
+        let res = zk.import_tree("/copy", &export[..], options).await;
+        assert_eq!(res.unwrap().unwrap(), 8);
+        let stat = zk.exists("/copy/src/e").await.unwrap().unwrap();
+        assert_eq!(stat.ephemeral_owner, zk.session().id);
+    }
+
+    #[tokio::test]
+    async fn bad_exports() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let res = zk.export_tree("/missing", Vec::new(), Default::default()).await;
+        assert!(matches!(res.unwrap(), Err(error::ExportTree::NoNode)));
+
+        let import = |export: Vec<u8>| {
+            let zk = zk.clone();
+            async move { zk.import_tree("/x", &export[..], Default::default()).await.unwrap() }
+        };
+        assert!(matches!(import(b"{}".to_vec()).await, Err(error::ImportTree::NotAnExport)));
+        let mut export = MAGIC.to_vec();
+        export.extend_from_slice(&2i32.to_be_bytes());
+        assert!(matches!(import(export).await, Err(error::ImportTree::UnsupportedVersion(2))));
+
+        let mut export = Vec::new();
+        zk.export_tree("/", &mut export, Default::default()).await.unwrap().unwrap();
+        export.pop();
+        assert!(matches!(import(export).await, Err(error::ImportTree::Read(_))));
+        assert!(zk.exists("/x").await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,159 @@
+use std::fmt;
+use std::sync::Arc;
+use super::{Interceptor, Request};
+use crate::metrics::Operation;
+use crate::ZkError;
+
+/// A request that an [`Authorizer`] decides on.
+#[derive(Clone, Copy, Debug)]
+pub struct Access<'a> {
+    /// The identity that the authorizer was created with.
+    pub identity: &'a str,
+    /// The operation of the request, or of one of the operations of a multi request.
+    pub operation: Operation,
+    /// The path of the node the request operates on, if it operates on a single node.
+    pub path: Option<&'a str>,
+}
+
+impl Access<'_> {
+    /// Whether the request operates on the node at `prefix` or on one below it.
+    pub fn is_within(&self, prefix: &str) -> bool {
+        self.path.is_some_and(|path| match path.strip_prefix(prefix) {
+            _ if prefix == "/" => true,
+            Some(rest) => rest.is_empty() || rest.starts_with('/'),
+            None => false,
+        })
+    }
+}
+
+type Policy = dyn Fn(&Access<'_>) -> bool + Send + Sync;
+
+/// An [`Interceptor`] that fails the requests that its policy denies with [`ZkError::NoAuth`],
+/// without sending them.
+///
+/// The policy is asked about every request, and about each operation of a multi request, with
+/// the identity that the authorizer was created with, so that tooling that several teams share
+/// can confine what each of them may do, whatever the ACLs on the server allow:
+///
+/// ```
+/// # extern crate tokio_zookeeper;
+/// # use std::sync::Arc;
+/// # use tokio_zookeeper::interceptor::{Access, Authorizer};
+/// # use tokio_zookeeper::metrics::Operation;
+/// # use tokio_zookeeper::ZooKeeper;
+/// # fn run(zk: ZooKeeper) {
+/// let authorizer = Authorizer::new("tooling", |access: &Access<'_>| {
+///     access.operation != Operation::Delete || access.is_within("/scratch")
+/// });
+/// let tooling = zk.with_interceptor(Arc::new(authorizer));
+/// # }
+/// ```
+///
+/// The policy sees the paths as the request would be sent with by the interceptors that the
+/// authorizer wraps, after the namespace of the handle, if any, has been added.
+#[derive(Clone)]
+pub struct Authorizer {
+    identity: Arc<str>,
+    policy: Arc<Policy>,
+}
+
+impl Authorizer {
+    /// Return an authorizer that lets requests through if `policy` allows them for `identity`.
+    pub fn new<F>(identity: &str, policy: F) -> Self
+    where
+        F: Fn(&Access<'_>) -> bool + Send + Sync + 'static,
+    {
+        Authorizer {
+            identity: identity.into(),
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl Interceptor for Authorizer {
+    fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+        let access = Access {
+            identity: &self.identity,
+            operation: request.operation(),
+            path: request.path(),
+        };
+        if !(self.policy)(&access) {
+            return Err(ZkError::NoAuth);
+        }
+        for mut part in request.parts() {
+            self.before(&mut part)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Authorizer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Authorizer")
+            .field("identity", &self.identity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockZk;
+    use crate::{error, Acl, CreateMode, Error};
+
+    #[tokio::test]
+    async fn denies() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        for path in &["/scratch", "/scratch/a", "/prod"] {
+            zk.create(path, &b""[..], acl, CreateMode::Persistent)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let authorizer = Authorizer::new("tooling", |access: &Access<'_>| {
+            assert_eq!(access.identity, "tooling");
+            let write = matches!(access.operation, Operation::Delete | Operation::SetData);
+            !write || access.is_within("/scratch")
+        });
+        let tooling = zk.with_interceptor(Arc::new(authorizer));
+
+        tooling.delete("/scratch/a", None).await.unwrap().unwrap();
+        let denied = tooling.delete("/prod", None).await;
+        assert!(matches!(
+            denied,
+            Err(Error::Server {
+                error: ZkError::NoAuth,
+                ..
+            })
+        ));
+        let denied = tooling.set_data("/prod", None, &b"x"[..]).await.unwrap();
+        assert_eq!(denied, Err(error::SetData::NoAuth { path: "/prod".into() }));
+        assert!(tooling.exists("/prod").await.unwrap().is_some());
+
+        // a multi request is denied if any of its operations is
+        let denied = tooling
+            .multi()
+            .delete("/scratch", None)
+            .delete("/prod", None)
+            .run()
+            .await;
+        assert!(denied.is_err());
+        assert!(zk.exists("/scratch").await.unwrap().is_some());
+    }
+
+    #[test]
+    fn within() {
+        let access = |path| Access {
+            identity: "",
+            operation: Operation::Delete,
+            path,
+        };
+        assert!(access(Some("/scratch")).is_within("/scratch"));
+        assert!(access(Some("/scratch/a")).is_within("/scratch"));
+        assert!(!access(Some("/scratchpad")).is_within("/scratch"));
+        assert!(access(Some("/a")).is_within("/"));
+        assert!(!access(None).is_within("/"));
+    }
+}
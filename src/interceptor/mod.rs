@@ -0,0 +1,522 @@
+//! Hooks that see, change or reject the requests made through a handle, and see and change their
+//! responses and the events of the watches set through it.
+//!
+//! Implement [`Interceptor`] and add it to a handle with [`ZooKeeper::with_interceptor`] to audit
+//! requests, enforce a policy on the paths they touch, or rewrite their paths and data, without
+//! wrapping the client at every call site. Every method has an empty default implementation, so
+//! an implementation only needs to provide the hooks it is interested in.
+//!
+//! Interceptors sit between the handle's namespace and the server: they see the paths of
+//! requests with the namespace already added, and the paths of responses and events before it is
+//! removed. An interceptor that is added to a handle wraps the ones that the handle already had,
+//! so its [`before`](Interceptor::before) sees requests before theirs do, and its
+//! [`after`](Interceptor::after) and [`on_event`](Interceptor::on_event) see responses and events
+//! after theirs do.
+//!
+//! `on_event` is called from the task that drives the connection, so it should be cheap and must
+//! not block.
+//!
+//! [`PathRewriter`] is a ready-made interceptor that maps the paths of a handle to other subtrees
+//! of the server, for instance to keep the nodes of a tenant apart from those of others, and
+//! [`Authorizer`] one that denies the requests that a policy does not allow.
+//!
+//! ```
+//! # extern crate tokio_zookeeper;
+//! use std::sync::Arc;
+//! use tokio_zookeeper::interceptor::{Interceptor, Request};
+//! use tokio_zookeeper::metrics::Operation;
+//! use tokio_zookeeper::{ZkError, ZooKeeper};
+//!
+//! /// Keeps the handle from deleting anything.
+//! struct NoDeletes;
+//!
+//! impl Interceptor for NoDeletes {
+//!     fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+//!         if request.operation() == Operation::Delete {
+//!             return Err(ZkError::NoAuth);
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # fn run(zk: ZooKeeper) {
+//! let zk = zk.with_interceptor(Arc::new(NoDeletes));
+//! # }
+//! ```
+//!
+//! [`ZooKeeper::with_interceptor`]: crate::ZooKeeper::with_interceptor
+
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use crate::metrics::Operation;
+use crate::proto::{self, Reply};
+use crate::{Error, WatchedEvent, ZkError};
+
+mod authorize;
+mod rewrite;
+pub use self::authorize::{Access, Authorizer};
+pub use self::rewrite::PathRewriter;
+
+/// Hooks through which requests, their responses and watch events pass.
+pub trait Interceptor: Send + Sync {
+    /// `request` is about to be sent to the server.
+    ///
+    /// The hook may change the request's path and data. If it returns an error, the request is
+    /// not sent, and fails as if the server had returned that error; for instance, a
+    /// [`ZkError::NoAuth`] makes [`ZooKeeper::set_data`](crate::ZooKeeper::set_data) return
+    /// [`error::SetData::NoAuth`](crate::error::SetData::NoAuth).
+    fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// The server has responded to a request.
+    ///
+    /// The hook may change the path and data that the server returned, or make the request fail
+    /// with [`Response::fail`]. Requests that never receive a response, for instance because the
+    /// connection failed or an interceptor rejected them, are not reported.
+    fn after(&self, response: &mut Response<'_>) {
+        let _ = response;
+    }
+
+    /// A watch that was set through the handle has fired with `event`.
+    ///
+    /// The hook may change the event, for instance to map its path back to the one that the
+    /// watch was set with. Events that are sent to the session's global watcher stream, rather
+    /// than to a watch of the handle, are not reported.
+    fn on_event(&self, event: &mut WatchedEvent) {
+        let _ = event;
+    }
+}
+
+/// A request that is about to be sent, as seen by [`Interceptor::before`].
+pub struct Request<'a> {
+    request: &'a mut proto::Request,
+    failure: &'a Cell<Option<Error>>,
+}
+
+impl Request<'_> {
+    /// The operation of the request.
+    pub fn operation(&self) -> Operation {
+        self.request
+            .operation()
+            .expect("only requests that users issue are intercepted")
+    }
+
+    /// The path of the node the request operates on, if it operates on a single node.
+    pub fn path(&self) -> Option<&str> {
+        self.request.path()
+    }
+
+    /// The path of the node the request operates on, for the interceptor to change.
+    pub fn path_mut(&mut self) -> Option<&mut String> {
+        self.request.path_mut()
+    }
+
+    /// The data that the request sets, if it creates a node or sets its data.
+    pub fn data(&self) -> Option<&[u8]> {
+        match *self.request {
+            proto::Request::Create { ref data, .. } | proto::Request::SetData { ref data, .. } => {
+                Some(data)
+            }
+            _ => None,
+        }
+    }
+
+    /// Replace the data that the request sets, if it creates a node or sets its data.
+    ///
+    /// Does nothing for other requests.
+    pub fn set_data(&mut self, new: Vec<u8>) {
+        match *self.request {
+            proto::Request::Create { ref mut data, .. }
+            | proto::Request::SetData { ref mut data, .. } => *data = Cow::Owned(new),
+            _ => {}
+        }
+    }
+
+    /// The operations of a [`Multi`](Operation::Multi) request, or none for other requests.
+    pub fn parts(&mut self) -> impl Iterator<Item = Request<'_>> {
+        let parts = match *self.request {
+            proto::Request::Multi(ref mut requests) => &mut requests[..],
+            _ => &mut [],
+        };
+        let failure = self.failure;
+        parts.iter_mut().map(move |request| Request { request, failure })
+    }
+
+    /// Fail the request with `error` rather than send it, once the hook returns.
+    ///
+    /// Unlike the [`ZkError`] that [`Interceptor::before`] may return, which the request fails
+    /// with as if the server had returned it, `error` is returned to the caller as is. If this is
+    /// called more than once, the first error wins.
+    pub fn fail(&mut self, error: Error) {
+        fail(self.failure, error);
+    }
+}
+
+impl fmt::Debug for Request<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Request").field(&*self.request).finish()
+    }
+}
+
+fn fail(failure: &Cell<Option<Error>>, error: Error) {
+    let first = failure.take().unwrap_or(error);
+    failure.set(Some(first));
+}
+
+/// What an interceptor's response hook is told of the request that was sent.
+#[derive(Debug)]
+struct Sent {
+    operation: Operation,
+    path: Option<String>,
+    parts: Vec<Sent>,
+}
+
+impl Sent {
+    fn of(request: &proto::Request) -> Self {
+        let parts = match *request {
+            proto::Request::Multi(ref requests) => requests.iter().map(Sent::of).collect(),
+            _ => Vec::new(),
+        };
+        Sent {
+            operation: request
+                .operation()
+                .expect("only requests that users issue are intercepted"),
+            path: request.path().map(str::to_string),
+            parts,
+        }
+    }
+}
+
+/// The response to a request, as seen by [`Interceptor::after`].
+pub struct Response<'a> {
+    request: &'a Sent,
+    outcome: Result<&'a mut proto::Response, ZkError>,
+    failure: &'a Cell<Option<Error>>,
+}
+
+impl Response<'_> {
+    /// The operation of the request.
+    pub fn operation(&self) -> Operation {
+        self.request.operation
+    }
+
+    /// The path of the node the request operated on, as it was sent, if it had one.
+    pub fn path(&self) -> Option<&str> {
+        self.request.path.as_deref()
+    }
+
+    /// The error that the server failed the request with, if it did.
+    pub fn error(&self) -> Option<ZkError> {
+        self.outcome.as_ref().err().copied()
+    }
+
+    /// The path that the server returned, of the node that a request created or synced.
+    pub fn returned_path(&self) -> Option<&str> {
+        match self.outcome {
+            Ok(proto::Response::String(ref path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The path that the server returned, for the interceptor to change.
+    pub fn returned_path_mut(&mut self) -> Option<&mut String> {
+        match self.outcome {
+            Ok(proto::Response::String(ref mut path)) => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The data of the node, if the request read it.
+    pub fn data(&self) -> Option<&[u8]> {
+        match self.outcome {
+            Ok(proto::Response::GetData { ref bytes, .. }) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Replace the data of the node, if the request read it.
+    ///
+    /// Does nothing for other responses.
+    pub fn set_data(&mut self, new: Vec<u8>) {
+        if let Ok(proto::Response::GetData { ref mut bytes, .. }) = self.outcome {
+            *bytes = Bytes::from(new);
+        }
+    }
+
+    /// The responses to the operations of a [`Multi`](Operation::Multi) request, or none for
+    /// other requests, or if the request failed as a whole.
+    pub fn parts(&mut self) -> impl Iterator<Item = Response<'_>> {
+        let parts = match self.outcome {
+            Ok(proto::Response::Multi(ref mut responses)) => &mut responses[..],
+            _ => &mut [],
+        };
+        let failure = self.failure;
+        self.request
+            .parts
+            .iter()
+            .zip(parts)
+            .map(move |(request, outcome)| Response {
+                request,
+                outcome: outcome.as_mut().map_err(|e| *e),
+                failure,
+            })
+    }
+
+    /// Fail the request with `error`, even though the server responded to it.
+    ///
+    /// The caller gets `error` in place of the response, for instance because the data that the
+    /// server returned could not be decoded. If this is called more than once, the first error
+    /// wins.
+    pub fn fail(&mut self, error: Error) {
+        fail(self.failure, error);
+    }
+}
+
+impl fmt::Debug for Response<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Response")
+            .field("request", self.request)
+            .field("outcome", &self.outcome)
+            .finish()
+    }
+}
+
+/// The interceptors of a handle, the one added last at the end.
+#[derive(Clone, Default)]
+pub(crate) struct Interceptors(Arc<Vec<Arc<dyn Interceptor>>>);
+
+impl Interceptors {
+    /// Return these interceptors, wrapped in `outer`.
+    pub(crate) fn with(&self, outer: Arc<dyn Interceptor>) -> Self {
+        let mut interceptors = Vec::clone(&self.0);
+        interceptors.push(outer);
+        Interceptors(Arc::new(interceptors))
+    }
+
+    /// Pass `request` through the interceptors, `send` it, and pass its reply back through them.
+    pub(crate) async fn send<F, R>(
+        &self,
+        mut request: proto::Request,
+        send: F,
+    ) -> Result<Reply, Error>
+    where
+        F: FnOnce(proto::Request) -> R,
+        R: Future<Output = Result<Reply, Error>>,
+    {
+        if self.0.is_empty() {
+            return send(request).await;
+        }
+        let failure = Cell::new(None);
+        for interceptor in self.0.iter().rev() {
+            let verdict = interceptor.before(&mut Request {
+                request: &mut request,
+                failure: &failure,
+            });
+            if let Some(e) = failure.take() {
+                return Err(e);
+            }
+            if let Err(e) = verdict {
+                return Ok(Err((e, None)));
+            }
+        }
+        let sent = Sent::of(&request);
+        let mut reply = send(request).await?;
+        for interceptor in self.0.iter() {
+            let outcome = match reply {
+                Ok(ref mut response) => Ok(response),
+                Err((e, _)) => Err(e),
+            };
+            interceptor.after(&mut Response {
+                request: &sent,
+                outcome,
+                failure: &failure,
+            });
+            if let Some(e) = failure.take() {
+                return Err(e);
+            }
+        }
+        Ok(reply)
+    }
+
+    /// Pass the event of a watch that was set through the handle through the interceptors.
+    pub(crate) fn on_event(&self, event: &mut WatchedEvent) {
+        for interceptor in self.0.iter() {
+            interceptor.on_event(event);
+        }
+    }
+}
+
+impl PartialEq for Interceptors {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(other.0.iter()).all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for Interceptors {}
+
+impl fmt::Debug for Interceptors {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Interceptors")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use super::*;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode, MultiResponse};
+
+    type Seen = (Operation, Option<String>, Option<ZkError>);
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Seen>>);
+
+    impl Interceptor for Recorder {
+        fn after(&self, response: &mut Response<'_>) {
+            let path = response.path().map(str::to_string);
+            self.0
+                .lock()
+                .unwrap()
+                .push((response.operation(), path, response.error()));
+            if let Some(upper) = response.data().map(<[u8]>::to_ascii_uppercase) {
+                response.set_data(upper);
+            }
+        }
+    }
+
+    struct NoDeletes;
+
+    impl Interceptor for NoDeletes {
+        fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+            match request.operation() {
+                Operation::Delete => Err(ZkError::NoAuth),
+                _ => Ok(()),
+            }
+        }
+    }
+
+    /// Keeps the nodes under `/logical` under `/physical` instead.
+    struct Moved;
+
+    impl Moved {
+        fn map(path: &mut String, from: &str, to: &str) {
+            if path.starts_with(from) {
+                path.replace_range(..from.len(), to);
+            }
+        }
+    }
+
+    impl Interceptor for Moved {
+        fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+            if let Some(path) = request.path_mut() {
+                Moved::map(path, "/logical", "/physical");
+            }
+            for mut part in request.parts() {
+                self.before(&mut part)?;
+            }
+            Ok(())
+        }
+
+        fn after(&self, response: &mut Response<'_>) {
+            if let Some(path) = response.returned_path_mut() {
+                Moved::map(path, "/physical", "/logical");
+            }
+            for mut part in response.parts() {
+                self.after(&mut part);
+            }
+        }
+
+        fn on_event(&self, event: &mut WatchedEvent) {
+            Moved::map(&mut event.path, "/physical", "/logical");
+        }
+    }
+
+    #[tokio::test]
+    async fn observes_and_rejects() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let recorder = Arc::new(Recorder::default());
+        let intercepted = zk
+            .with_interceptor(recorder.clone())
+            .with_interceptor(Arc::new(NoDeletes));
+
+        let acl = Acl::open_unsafe();
+        intercepted
+            .create("/i", &b"x"[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let (data, _) = intercepted.get_data("/i").await.unwrap().unwrap();
+        assert_eq!(data, b"X");
+        let deleted = intercepted.delete("/i", None).await;
+        assert!(matches!(
+            deleted,
+            Err(Error::Server {
+                error: ZkError::NoAuth,
+                ..
+            })
+        ));
+        assert_eq!(intercepted.exists("/nope").await.unwrap(), None);
+
+        // the rejected delete never reached the recorder, nor the server
+        let seen = recorder.0.lock().unwrap().clone();
+        let path = Some("/i".to_string());
+        assert_eq!(
+            seen,
+            [
+                (Operation::Create, path.clone(), None),
+                (Operation::GetData, path, None),
+                (Operation::Exists, Some("/nope".to_string()), Some(ZkError::NoNode)),
+            ]
+        );
+        let (data, _) = zk.get_data("/i").await.unwrap().unwrap();
+        assert_eq!(data, b"x");
+        zk.delete("/i", None).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn rewrites() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/physical", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let moved = zk.with_interceptor(Arc::new(Moved));
+
+        let (created, _) = moved.with_watcher().exists("/logical/a").await.unwrap();
+        let path = moved
+            .create("/logical/a", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(path, "/logical/a");
+        assert_eq!(created.await.unwrap().path, "/logical/a");
+        assert!(zk.exists("/physical/a").await.unwrap().is_some());
+        assert_eq!(zk.exists("/logical").await.unwrap(), None);
+
+        let results = moved
+            .multi()
+            .create("/logical/b", &b""[..], acl, CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(results, [Ok(MultiResponse::Create("/logical/b".to_string()))]);
+        assert!(zk.exists("/physical/b").await.unwrap().is_some());
+
+        // namespaces are applied before interceptors see the paths
+        let nested = zk.using_namespace("/logical").unwrap().with_interceptor(Arc::new(Moved));
+        assert!(nested.exists("/a").await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use super::{Interceptor, Request, Response};
+use crate::error::InvalidPath;
+use crate::{WatchedEvent, ZkError, ZkPath};
+
+/// An [`Interceptor`] that maps the logical paths a handle uses to the physical paths they are
+/// stored at on the server, and the physical paths in responses and watch events back.
+///
+/// Each mapping moves the subtree at a logical path to a physical one, and a path is mapped by
+/// the mapping with the longest logical path that it is in, or left alone if there is none.
+/// Unlike a namespace, which moves the whole tree below a single prefix, this lets a tenant's
+/// handle see its own subtree as the root while some paths lead to subtrees that tenants share:
+///
+/// ```
+/// # extern crate tokio_zookeeper;
+/// # use std::sync::Arc;
+/// # use tokio_zookeeper::interceptor::PathRewriter;
+/// # use tokio_zookeeper::{error, ZooKeeper};
+/// # fn run(zk: ZooKeeper) -> Result<(), error::InvalidPath> {
+/// let rewriter = PathRewriter::new()
+///     .map("/", "/tenants/acme")?
+///     .map("/config/global", "/shared/config")?;
+/// let acme = zk.with_interceptor(Arc::new(rewriter));
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Physical paths are mapped back by the mapping with the longest physical path that they are in.
+/// The names of children are returned as they are on the server, so listing the children of a
+/// node does not show the logical paths that are mapped to other subtrees below it.
+#[derive(Clone, Debug, Default)]
+pub struct PathRewriter {
+    /// The logical and physical paths of each mapping, longest logical path first.
+    to_physical: Vec<(Arc<str>, Arc<str>)>,
+    /// The physical and logical paths of each mapping, longest physical path first.
+    to_logical: Vec<(Arc<str>, Arc<str>)>,
+}
+
+impl PathRewriter {
+    /// Return a rewriter that leaves every path alone.
+    pub fn new() -> Self {
+        PathRewriter::default()
+    }
+
+    /// Add a mapping of the subtree at `logical` to the one at `physical`, replacing any earlier
+    /// mapping of `logical`.
+    pub fn map(mut self, logical: &str, physical: &str) -> Result<Self, InvalidPath> {
+        ZkPath::validate(logical)?;
+        ZkPath::validate(physical)?;
+        let (logical, physical): (Arc<str>, Arc<str>) = (logical.into(), physical.into());
+        self.to_physical.retain(|(from, _)| *from != logical);
+        self.to_logical.retain(|(_, to)| *to != logical);
+        self.to_physical.push((logical.clone(), physical.clone()));
+        self.to_logical.push((physical, logical));
+        self.to_physical.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+        self.to_logical.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+        Ok(self)
+    }
+
+    /// Map `path` from the subtree at `from` to the one at `to`, if it is in it.
+    fn swap(path: &str, from: &str, to: &str) -> Option<String> {
+        let rest = if from == "/" {
+            path.strip_prefix('/')?
+        } else {
+            match path.strip_prefix(from)? {
+                "" => "",
+                rest => rest.strip_prefix('/')?,
+            }
+        };
+        Some(match (to, rest) {
+            (to, "") => to.to_string(),
+            ("/", rest) => format!("/{}", rest),
+            (to, rest) => format!("{}/{}", to, rest),
+        })
+    }
+
+    fn rewrite(mappings: &[(Arc<str>, Arc<str>)], path: &mut String) {
+        let mapped = mappings
+            .iter()
+            .find_map(|(from, to)| PathRewriter::swap(path, from, to));
+        if let Some(mapped) = mapped {
+            *path = mapped;
+        }
+    }
+}
+
+impl Interceptor for PathRewriter {
+    fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+        if let Some(path) = request.path_mut() {
+            PathRewriter::rewrite(&self.to_physical, path);
+        }
+        for mut part in request.parts() {
+            self.before(&mut part)?;
+        }
+        Ok(())
+    }
+
+    fn after(&self, response: &mut Response<'_>) {
+        if let Some(path) = response.returned_path_mut() {
+            PathRewriter::rewrite(&self.to_logical, path);
+        }
+        for mut part in response.parts() {
+            self.after(&mut part);
+        }
+    }
+
+    fn on_event(&self, event: &mut WatchedEvent) {
+        // the events of changes to the state of the session have no path
+        if !event.path.is_empty() {
+            PathRewriter::rewrite(&self.to_logical, &mut event.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+    use super::*;
+    use crate::journal::{ChangeType, Entry};
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode};
+
+    fn rewriter() -> PathRewriter {
+        PathRewriter::new()
+            .map("/", "/tenants/acme")
+            .unwrap()
+            .map("/config/global", "/shared")
+            .unwrap()
+    }
+
+    #[test]
+    fn maps_longest_match() {
+        let r = rewriter();
+        let physical = |path: &str| {
+            let mut path = path.to_string();
+            PathRewriter::rewrite(&r.to_physical, &mut path);
+            path
+        };
+        assert_eq!(physical("/"), "/tenants/acme");
+        assert_eq!(physical("/a/b"), "/tenants/acme/a/b");
+        assert_eq!(physical("/config/global"), "/shared");
+        assert_eq!(physical("/config/global/x"), "/shared/x");
+        assert_eq!(physical("/config/globalx"), "/tenants/acme/config/globalx");
+
+        let logical = |path: &str| {
+            let mut path = path.to_string();
+            PathRewriter::rewrite(&r.to_logical, &mut path);
+            path
+        };
+        assert_eq!(logical("/tenants/acme"), "/");
+        assert_eq!(logical("/tenants/acme/a"), "/a");
+        assert_eq!(logical("/shared/x"), "/config/global/x");
+        assert_eq!(logical("/tenants/other"), "/tenants/other");
+
+        let root = PathRewriter::new().map("/app", "/").unwrap();
+        let mut path = "/app/x".to_string();
+        PathRewriter::rewrite(&root.to_physical, &mut path);
+        assert_eq!(path, "/x");
+        assert!(PathRewriter::new().map("/a/", "/b").is_err());
+    }
+
+    #[tokio::test]
+    async fn tenants() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        for path in &["/tenants", "/tenants/acme", "/shared"] {
+            zk.create(path, &b""[..], acl, CreateMode::Persistent)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        let acme = zk.with_interceptor(Arc::new(rewriter()));
+        let mut journal = acme.change_journal("/config/global").await.unwrap();
+
+        let path = acme
+            .create("/lock-", &b""[..], acl, CreateMode::PersistentSequential)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(path.starts_with("/lock-"));
+        assert!(zk.exists(&format!("/tenants/acme{}", path)).await.unwrap().is_some());
+
+        acme.create("/config", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let (changed, _) = acme.with_watcher().exists("/config/global/x").await.unwrap();
+        zk.create("/shared/x", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(changed.await.unwrap().path, "/config/global/x");
+        match journal.next().await.unwrap() {
+            Entry::Change { path, change, .. } => {
+                assert_eq!(path, "/config/global/x");
+                assert_eq!(change, ChangeType::Created);
+            }
+            e => panic!("expected a change, got {:?}", e),
+        }
+    }
+}
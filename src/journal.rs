@@ -0,0 +1,235 @@
+//! A journal of the changes made under a path, from a persistent recursive watch.
+//!
+//! [`ZooKeeper::change_journal`] sets a persistent recursive watch on a path, which, unlike the
+//! one-shot watches of [`ZooKeeper::watch`], stays set after it fires and covers every node below
+//! the path. Each creation, deletion and data change under the path arrives as an
+//! [`Entry::Change`], in the order the ensemble applied them, with the zxid of the change.
+//!
+//! A watch misses the changes made while its client is disconnected, and so does the journal.
+//! When the connection is lost, the journal yields an [`Entry::Gap`] with the last zxid the
+//! client saw; changes after that zxid may be missing, and the consumer must resync whatever it
+//! derives from the journal by reading the subtree again. The watch is set again when the client
+//! reconnects, and changes keep coming after the gap.
+//!
+//! Persistent watches need ZooKeeper 3.6 or later. Servers before 3.9 do not send the zxid of
+//! the change that fired a watch, and with them the zxid of every change is `-1`.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use futures::prelude::*;
+//! use tokio_zookeeper::journal::Entry;
+//! # async fn run(zk: ZooKeeper) -> Result<(), Error> {
+//! let mut journal = zk.change_journal("/app").await?;
+//! while let Some(entry) = journal.next().await {
+//!     match entry {
+//!         Entry::Change { zxid, path, change } => println!("{:x}: {} {:?}", zxid, path, change),
+//!         Entry::Gap { zxid } => println!("missed changes after {:x}, resyncing", zxid),
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::channel::mpsc;
+use futures::stream::{FusedStream, Stream};
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use crate::proto::{self, Watch};
+use crate::{capabilities, transform, Error, WatchedEvent, WatchedEventType, ZooKeeper};
+
+/// The kind of change to a node that a journal [`Entry`] records.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeType {
+    /// The node was created.
+    Created,
+    /// The node was deleted.
+    Deleted,
+    /// The data of the node was set.
+    DataChanged,
+}
+
+impl ChangeType {
+    fn of(event_type: WatchedEventType) -> Option<Self> {
+        match event_type {
+            WatchedEventType::NodeCreated => Some(ChangeType::Created),
+            WatchedEventType::NodeDeleted => Some(ChangeType::Deleted),
+            WatchedEventType::NodeDataChanged => Some(ChangeType::DataChanged),
+            _ => None,
+        }
+    }
+}
+
+/// An entry of a [`ChangeJournal`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Entry {
+    /// A node under the journal's path changed.
+    Change {
+        /// The zxid of the change, or `-1` if the server is older than 3.9.
+        zxid: i64,
+        /// The path of the node that changed.
+        path: String,
+        /// How the node changed.
+        change: ChangeType,
+    },
+    /// The connection was lost, and changes after `zxid` may be missing from the journal.
+    ///
+    /// The consumer should read the subtree again to catch up; the entries after the gap are
+    /// changes made once the client reconnected.
+    Gap {
+        /// The last zxid the client saw before the connection was lost.
+        zxid: i64,
+    },
+}
+
+/// The changes made under a path, as returned by [`ZooKeeper::change_journal`].
+///
+/// The journal ends when the session of the client that set it is closed or expires, and the
+/// watch is removed from the client once the journal is dropped.
+pub struct ChangeJournal {
+    rx: mpsc::UnboundedReceiver<(i64, WatchedEvent)>,
+    gap: bool,
+}
+
+impl fmt::Debug for ChangeJournal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ChangeJournal")
+            .field("terminated", &self.rx.is_terminated())
+            .finish()
+    }
+}
+
+impl Stream for ChangeJournal {
+    type Item = Entry;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Entry>> {
+        loop {
+            let (zxid, e) = match Pin::new(&mut self.rx).poll_next(cx) {
+                Poll::Ready(Some(e)) => e,
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            };
+            if let Some(change) = ChangeType::of(e.event_type) {
+                self.gap = false;
+                let path = e.path;
+                return Poll::Ready(Some(Entry::Change { zxid, path, change }));
+            }
+            // a client that fails to reconnect loses its connection again; one gap covers both
+            if e.event_type == WatchedEventType::None && !self.gap {
+                self.gap = true;
+                return Poll::Ready(Some(Entry::Gap { zxid }));
+            }
+        }
+    }
+}
+
+impl FusedStream for ChangeJournal {
+    fn is_terminated(&self) -> bool {
+        self.rx.is_terminated()
+    }
+}
+
+impl ZooKeeper {
+    /// Return a journal of the creations, deletions and data changes of the node at `prefix` and
+    /// of every node below it, from a persistent recursive watch set on `prefix`.
+    ///
+    /// The node need not exist. See the [`journal`](crate::journal) module for how the journal
+    /// reports changes it may have missed. Servers before 3.6 have no persistent watches, so with
+    /// those, this fails with [`Error::Server`] and [`ZkError::Unimplemented`] without sending
+    /// anything.
+    ///
+    /// [`ZkError::Unimplemented`]: crate::ZkError::Unimplemented
+    pub async fn change_journal(&self, prefix: &str) -> Result<ChangeJournal, Error> {
+        trace!(self.logger, "change_journal"; "prefix" => prefix);
+        if !self.capabilities().await.persistent_watches() {
+            return Err(capabilities::unsupported());
+        }
+        let (tx, rx) = mpsc::unbounded();
+        let r = self
+            .enqueue(proto::Request::AddWatch {
+                path: self.namespace.resolve(prefix),
+                recursive: true,
+                watch: Watch::Persistent(tx, self.namespace.clone()),
+            })
+            .await?;
+        transform::add_watch(r)?;
+        Ok(ChangeJournal { rx, gap: false })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::prelude::*;
+    use super::*;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode};
+
+    fn change(path: &str, change: ChangeType) -> (String, ChangeType) {
+        (path.to_string(), change)
+    }
+
+    async fn next_change(journal: &mut ChangeJournal) -> (String, ChangeType) {
+        match journal.next().await.unwrap() {
+            Entry::Change { path, change, .. } => (path, change),
+            e => panic!("expected a change, got {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn changes() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let mut journal = zk.change_journal("/app").await.unwrap();
+
+        let acl = Acl::open_unsafe();
+        zk.create("/app", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        zk.create("/app/a", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        zk.create("/other", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let stat = zk.set_data("/app/a", None, &b"x"[..]).await.unwrap().unwrap();
+        zk.delete("/app/a", None).await.unwrap().unwrap();
+
+        assert_eq!(next_change(&mut journal).await, change("/app", ChangeType::Created));
+        assert_eq!(next_change(&mut journal).await, change("/app/a", ChangeType::Created));
+        let set = journal.next().await.unwrap();
+        let expected = Entry::Change {
+            zxid: stat.mzxid,
+            path: "/app/a".to_string(),
+            change: ChangeType::DataChanged,
+        };
+        assert_eq!(set, expected);
+        assert_eq!(next_change(&mut journal).await, change("/app/a", ChangeType::Deleted));
+    }
+
+    #[tokio::test]
+    async fn gaps() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        let mut journal = zk.change_journal("/g").await.unwrap();
+        zk.create("/g", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        assert_eq!(next_change(&mut journal).await, change("/g", ChangeType::Created));
+        let seen = zk.stats().last_zxid_seen;
+
+        server.disconnect_all();
+        assert_eq!(journal.next().await.unwrap(), Entry::Gap { zxid: seen });
+        // the watch is set again on the next connection
+        zk.create("/g/after", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        assert_eq!(next_change(&mut journal).await, change("/g/after", ChangeType::Created));
+
+        assert!(server.expire_session(zk.stats().session_id));
+        let rest: Vec<_> = journal.collect().await;
+        assert!(rest.iter().all(|e| matches!(e, Entry::Gap { .. })));
+    }
+
+    #[tokio::test]
+    async fn namespaced() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/ns", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let ns = zk.using_namespace("/ns").unwrap();
+        let mut journal = ns.change_journal("/").await.unwrap();
+        zk.create("/ns/a", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        assert_eq!(next_change(&mut journal).await, change("/a", ChangeType::Created));
+    }
+}
@@ -93,129 +93,104 @@
 //!
 //! ## Interaction with Tokio
 //!
-//! The futures in this crate expect to be running under a `tokio::Runtime`. In the common case,
-//! you cannot resolve them solely using `.wait()`, but should instead use `tokio::run` or
-//! explicitly create a `tokio::Runtime` and then use `Runtime::block_on`.
+//! The futures in this crate expect to be running under a Tokio 1.x runtime, since the connection
+//! to the server is driven by a task that is spawned onto it. To spawn that task yourself, or to
+//! find out when the connection ends, use [`ZooKeeperBuilder::connect_with_driver`], which returns
+//! the future that drives the connection instead. With the `async-std` or `smol` features,
+//! `ZooKeeperBuilder::connect_async_std` and `ZooKeeperBuilder::connect_smol` connect and run the
+//! connection on those runtimes, without the need for a Tokio runtime.
+//! Code that cannot be async can use `blocking::ZooKeeper` from the `blocking` feature, which
+//! runs the connection on a runtime of its own.
+//!
+//! Operations are `async fn`s, so a request is only sent to the server once its future is first
+//! polled. Requests that are issued one after the other by awaiting each of them are therefore
+//! always sent in order.
 //!
 //! # A somewhat silly example
 //!
 //! ```no_run
-//! extern crate tokio;
-//! #[macro_use]
-//! extern crate failure;
-//! extern crate tokio_zookeeper;
-//!
+//! use futures::prelude::*;
 //! use tokio_zookeeper::*;
-//! use tokio::prelude::*;
 //!
-//! # fn main() {
-//! tokio::run(
-//!     ZooKeeper::connect(&"127.0.0.1:2181".parse().unwrap())
-//!         .and_then(|(zk, default_watcher)| {
-//!             // let's first check if /example exists. the .watch() causes us to be notified
-//!             // the next time the "exists" status of /example changes after the call.
-//!             zk.watch()
-//!                 .exists("/example")
-//!                 .inspect(|(_, stat)| {
-//!                     // initially, /example does not exist
-//!                     assert_eq!(stat, &None)
-//!                 })
-//!                 .and_then(|(zk, _)| {
-//!                     // so let's make it!
-//!                     zk.create(
-//!                         "/example",
-//!                         &b"Hello world"[..],
-//!                         Acl::open_unsafe(),
-//!                         CreateMode::Persistent,
-//!                     )
-//!                 })
-//!                 .inspect(|(_, ref path)| {
-//!                     assert_eq!(path.as_ref().map(String::as_str), Ok("/example"))
-//!                 })
-//!                 .and_then(|(zk, _)| {
-//!                     // does it exist now?
-//!                     zk.watch().exists("/example")
-//!                 })
-//!                 .inspect(|(_, stat)| {
-//!                     // looks like it!
-//!                     // note that the creation above also triggered our "exists" watch!
-//!                     assert_eq!(stat.unwrap().data_length as usize, b"Hello world".len())
-//!                 })
-//!                 .and_then(|(zk, _)| {
-//!                     // did the data get set correctly?
-//!                     zk.get_data("/example")
-//!                 })
-//!                 .inspect(|(_, res)| {
-//!                     let data = b"Hello world";
-//!                     let res = res.as_ref().unwrap();
-//!                     assert_eq!(res.0, data);
-//!                     assert_eq!(res.1.data_length as usize, data.len());
-//!                 })
-//!                 .and_then(|(zk, res)| {
-//!                     // let's update the data.
-//!                     zk.set_data("/example", Some(res.unwrap().1.version), &b"Bye world"[..])
-//!                 })
-//!                 .inspect(|(_, stat)| {
-//!                     assert_eq!(stat.unwrap().data_length as usize, "Bye world".len());
-//!                 })
-//!                 .and_then(|(zk, _)| {
-//!                     // create a child of /example
-//!                     zk.create(
-//!                         "/example/more",
-//!                         &b"Hello more"[..],
-//!                         Acl::open_unsafe(),
-//!                         CreateMode::Persistent,
-//!                     )
-//!                 })
-//!                 .inspect(|(_, ref path)| {
-//!                     assert_eq!(path.as_ref().map(String::as_str), Ok("/example/more"))
-//!                 })
-//!                 .and_then(|(zk, _)| {
-//!                     // it should be visible as a child of /example
-//!                     zk.get_children("/example")
-//!                 })
-//!                 .inspect(|(_, children)| {
-//!                     assert_eq!(children, &Some(vec!["more".to_string()]));
-//!                 })
-//!                 .and_then(|(zk, _)| {
-//!                     // it is not legal to delete a node that has children directly
-//!                     zk.delete("/example", None)
-//!                 })
-//!                 .inspect(|(_, res)| assert_eq!(res, &Err(error::Delete::NotEmpty)))
-//!                 .and_then(|(zk, _)| {
-//!                     // instead we must delete the children first
-//!                     zk.delete("/example/more", None)
-//!                 })
-//!                 .inspect(|(_, res)| assert_eq!(res, &Ok(())))
-//!                 .and_then(|(zk, _)| zk.delete("/example", None))
-//!                 .inspect(|(_, res)| assert_eq!(res, &Ok(())))
-//!                 .and_then(|(zk, _)| {
-//!                     // no /example should no longer exist!
-//!                     zk.exists("/example")
-//!                 })
-//!                 .inspect(|(_, stat)| assert_eq!(stat, &None))
-//!                 .and_then(move |(zk, _)| {
-//!                     // now let's check that the .watch().exists we did in the very
-//!                     // beginning actually triggered!
-//!                     default_watcher
-//!                         .into_future()
-//!                         .map(move |x| (zk, x))
-//!                         .map_err(|e| format_err!("stream error: {:?}", e.0))
-//!                 })
-//!                 .inspect(|(_, (event, _))| {
-//!                     assert_eq!(
-//!                         event,
-//!                         &Some(WatchedEvent {
-//!                             event_type: WatchedEventType::NodeCreated,
-//!                             keeper_state: KeeperState::SyncConnected,
-//!                             path: String::from("/example"),
-//!                         })
-//!                     );
-//!                 })
-//!         })
-//!         .map(|_| ())
-//!         .map_err(|e| panic!("{:?}", e)),
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Error> {
+//! let (zk, mut default_watcher) = ZooKeeper::connect(&"127.0.0.1:2181".parse().unwrap()).await?;
+//!
+//! // let's first check if /example exists. the .watch() causes us to be notified
+//! // the next time the "exists" status of /example changes after the call.
+//! let stat = zk.watch().exists("/example").await?;
+//! // initially, /example does not exist
+//! assert_eq!(stat, None);
+//!
+//! // so let's make it!
+//! let path = zk
+//!     .create(
+//!         "/example",
+//!         &b"Hello world"[..],
+//!         Acl::open_unsafe(),
+//!         CreateMode::Persistent,
+//!     )
+//!     .await?;
+//! assert_eq!(path.as_ref().map(String::as_str), Ok("/example"));
+//!
+//! // does it exist now?
+//! let stat = zk.watch().exists("/example").await?;
+//! // looks like it!
+//! // note that the creation above also triggered our "exists" watch!
+//! assert_eq!(stat.unwrap().data_length as usize, b"Hello world".len());
+//!
+//! // did the data get set correctly?
+//! let res = zk.get_data("/example").await?;
+//! let data = b"Hello world";
+//! let res = res.unwrap();
+//! assert_eq!(res.0, data);
+//! assert_eq!(res.1.data_length as usize, data.len());
+//!
+//! // let's update the data.
+//! let stat = zk
+//!     .set_data("/example", Some(res.1.version), &b"Bye world"[..])
+//!     .await?;
+//! assert_eq!(stat.unwrap().data_length as usize, "Bye world".len());
+//!
+//! // create a child of /example
+//! let path = zk
+//!     .create(
+//!         "/example/more",
+//!         &b"Hello more"[..],
+//!         Acl::open_unsafe(),
+//!         CreateMode::Persistent,
+//!     )
+//!     .await?;
+//! assert_eq!(path.as_ref().map(String::as_str), Ok("/example/more"));
+//!
+//! // it should be visible as a child of /example
+//! let children = zk.get_children("/example").await?;
+//! assert_eq!(children, Some(vec!["more".to_string()]));
+//!
+//! // it is not legal to delete a node that has children directly
+//! let res = zk.delete("/example", None).await?;
+//! assert_eq!(res, Err(error::Delete::NotEmpty { path: "/example".into() }));
+//! // instead we must delete the children first
+//! let res = zk.delete("/example/more", None).await?;
+//! assert_eq!(res, Ok(()));
+//! let res = zk.delete("/example", None).await?;
+//! assert_eq!(res, Ok(()));
+//! // no /example should no longer exist!
+//! let stat = zk.exists("/example").await?;
+//! assert_eq!(stat, None);
+//!
+//! // now let's check that the .watch().exists we did in the very
+//! // beginning actually triggered!
+//! let event = default_watcher.next().await;
+//! assert_eq!(
+//!     event,
+//!     Some(WatchedEvent {
+//!         event_type: WatchedEventType::NodeCreated,
+//!         keeper_state: KeeperState::SyncConnected,
+//!         path: String::from("/example"),
+//!     })
 //! );
+//! # Ok(())
 //! # }
 //! ```
 
@@ -224,36 +199,112 @@
 #![deny(missing_copy_implementations)]
 
 extern crate byteorder;
-#[macro_use]
-extern crate failure;
-#[macro_use]
+extern crate bytes;
 extern crate futures;
 extern crate tokio;
+extern crate tokio_util;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "log")]
+extern crate log;
+#[cfg(feature = "slog")]
 #[macro_use]
 extern crate slog;
+extern crate uuid;
+#[cfg(feature = "prometheus")]
+extern crate prometheus;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+#[cfg(feature = "tracing")]
+extern crate tracing;
 #[cfg(test)]
 extern crate slog_async;
 #[cfg(test)]
 extern crate slog_term;
 
-use futures::sync::oneshot;
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either};
+use futures::prelude::*;
+use futures::stream;
 use std::borrow::Cow;
+use std::convert::TryInto;
+use std::mem;
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::time;
-use tokio::prelude::*;
 
-/// Per-operation ZooKeeper error types.
+#[macro_use]
+mod logging;
+
+pub mod admin;
+pub mod audit;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod cache;
+mod capabilities;
+mod circuit;
+pub mod client;
+pub mod codec;
+pub mod config;
+mod connect;
+/// The error type shared by all operations, and per-operation error types.
 pub mod error;
+mod export;
+pub mod interceptor;
+pub mod journal;
+pub mod managed;
+pub mod metrics;
+pub mod mirror;
+mod namespace;
+pub mod payload;
+pub mod pool;
+mod properties;
 mod proto;
+pub mod proxy;
+mod rate;
+pub mod retry;
+mod runtime;
+pub mod sequential;
+#[cfg(feature = "tower")]
+pub mod service;
+mod subtree;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 mod transform;
+#[cfg(feature = "serde")]
+pub mod typed;
 mod types;
+mod watcher;
 
-use proto::{Watch, ZkError};
-pub use types::{
-    Acl, CreateMode, KeeperState, MultiResponse, Permission, Stat, WatchedEvent, WatchedEventType,
+#[cfg(feature = "zero-copy")]
+pub use bytes::Bytes;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub use crate::proto::bench;
+#[cfg(feature = "fuzz")]
+#[doc(hidden)]
+pub use crate::proto::fuzz;
+use crate::proto::Watch;
+use crate::runtime::Runtime;
+#[cfg(any(feature = "async-std", feature = "smol"))]
+use tokio_util::compat::Compat;
+pub use crate::capabilities::{Capabilities, ServerVersion};
+pub use crate::circuit::CircuitBreaker;
+pub use crate::connect::ConnectString;
+pub use crate::error::Error;
+pub use crate::export::{ExportOptions, ImportOptions};
+pub use crate::proto::ZkError;
+pub use crate::rate::RateLimit;
+pub use crate::subtree::{
+    AclChange, AclUpdate, AclUpdateOptions, CopyOptions, MoveOptions, OnExisting, SubtreeDiff,
+};
+pub use crate::types::{
+    Acl, ConnectionState, ConnectionStats, CreateMode, KeeperState, MultiResponse, Permission,
+    RawResponse, Session, Stat, Upsert, WatchedEvent, WatchedEventType, ZkPath, ANY_VERSION,
 };
+pub use crate::watcher::WatchedEventStream;
 
 /// A connection to ZooKeeper.
 ///
@@ -282,24 +333,59 @@ pub use types::{
 pub struct ZooKeeper {
     #[allow(dead_code)]
     connection: proto::Enqueuer,
-    logger: slog::Logger,
+    logger: logging::Logger,
+    namespace: namespace::Namespace,
+    addr: SocketAddr,
+    runtime: Runtime,
+    session: Arc<Session>,
+    capabilities: Arc<Mutex<Option<Capabilities>>>,
+    /// The id of the default watcher of this handle, if it has its own.
+    watcher: Option<usize>,
+    /// Fail requests that are not answered within this long.
+    request_timeout: Option<time::Duration>,
+    /// Give up on asking the server for its release after this long.
+    connect_timeout: Option<time::Duration>,
+}
+
+/// When a client writes the requests it has queued up to the server.
+///
+/// See [`ZooKeeperBuilder::set_flush_strategy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlushStrategy {
+    /// Write each request as soon as it has been issued.
+    ///
+    /// This gives the lowest latency, and is the default. Requests that are issued together are
+    /// still written together.
+    #[default]
+    Immediate,
+    /// Hold back requests until `delay` has passed since the first of them was issued, or until
+    /// at least `max_bytes` are waiting to be written, and then write them all at once.
+    ///
+    /// This adds up to `delay` of latency to each request, but lets bulk loads send many requests
+    /// with every write. Note that `delay` is rounded up to the resolution of the timer, which is
+    /// about a millisecond.
+    Batched {
+        /// How long to wait for more requests before writing.
+        delay: time::Duration,
+        /// How many bytes of requests to collect before writing regardless of `delay`.
+        max_bytes: usize,
+    },
 }
 
 /// Builder that allows customizing options for ZooKeeper connections.
 #[derive(Debug, Clone)]
 pub struct ZooKeeperBuilder {
     session_timeout: time::Duration,
-    logger: slog::Logger,
+    logger: logging::Logger,
+    options: proto::Options,
 }
 
 impl Default for ZooKeeperBuilder {
     fn default() -> Self {
-        let drain = slog::Discard;
-        let root = slog::Logger::root(drain, o!());
-
         ZooKeeperBuilder {
             session_timeout: time::Duration::new(0, 0),
-            logger: root,
+            logger: Default::default(),
+            options: Default::default(),
         }
     }
 }
@@ -310,24 +396,209 @@ impl ZooKeeperBuilder {
     /// Session establishment is asynchronous. This constructor will initiate connection to the
     /// server and return immediately - potentially (usually) before the session is fully
     /// established. When the session is established, a `ZooKeeper` instance is returned, along
-    /// with a "watcher" that will provide notifications of any changes in state.
+    /// with a [`WatchedEventStream`] "watcher" that will provide notifications of any changes in
+    /// state.
+    ///
+    /// If the connection to the server fails, the client will automatically try to re-connect,
+    /// backing off between attempts, for as long as the session may not have expired. Only if
+    /// re-connection fails for all of that time is an error returned to the client. Requests that
+    /// are in-flight during a disconnect fail with [`Error::ConnectionLoss`], and may have to be
+    /// retried, unless the server said that the session expired, in which case they fail with
+    /// [`Error::SessionExpired`].
+    ///
+    /// The connection is driven by a task that is spawned onto the current Tokio runtime. Use
+    /// [`ZooKeeperBuilder::connect_with_driver`] to decide where it runs instead.
+    pub async fn connect(
+        self,
+        addr: &SocketAddr,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        self.connect_hosts(proto::HostProvider::new(vec![*addr])).await
+    }
+
+    /// Connect to the first of `hosts`, moving on to the others whenever the connection is lost.
+    async fn connect_hosts(
+        self,
+        hosts: proto::HostProvider<SocketAddr>,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        let addr = *hosts.current();
+        self.connect_spawned::<tokio::net::TcpStream>(hosts, addr, Runtime::Tokio)
+            .await
+    }
+
+    /// Connect to the ensemble of a connection string, such as one shared with services that use
+    /// the Java client.
+    ///
+    /// The hosts are resolved and their addresses tried in order, alternating between IPv6 and
+    /// IPv4 addresses so that an ensemble of either or both families can be reached from a host
+    /// that only has one of them. The client connects to the first address that accepts the
+    /// connection; if none does, this fails with the error of the last one. Once connected, it
+    /// moves on to the next address, in the same order, whenever the connection is lost. If
+    /// the string has a chroot, the returned handle uses it as its
+    /// [namespace](ZooKeeper::using_namespace), so the paths of the events on the returned
+    /// stream, which is the session's global watcher stream, are not relative to it. An enabled
+    /// `readOnly` option enables [`ZooKeeperBuilder::set_read_only`].
     ///
-    /// If the connection to the server fails, the client will automatically try to re-connect.
-    /// Only if re-connection fails is an error returned to the client. Requests that are in-flight
-    /// during a disconnect may fail and have to be retried.
-    pub fn connect(
+    /// The client cannot connect over TLS, so this fails with an [`Error::Io`] of kind
+    /// `Unsupported` if the `secure` option is enabled.
+    pub async fn connect_string(
+        mut self,
+        connect: &ConnectString,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        if connect.secure {
+            let e = std::io::Error::new(std::io::ErrorKind::Unsupported, "TLS is not supported");
+            return Err(Error::Io(e));
+        }
+        if connect.read_only {
+            self.set_read_only(true);
+        }
+        let mut last = Error::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "connection string names no hosts",
+        ));
+        let mut addrs = Vec::new();
+        for (host, port) in &connect.hosts {
+            match tokio::net::lookup_host((host.as_str(), *port)).await {
+                Ok(resolved) => addrs.extend(resolved),
+                Err(e) => last = Error::Io(e),
+            }
+        }
+        let addrs = crate::connect::interleave(addrs);
+        let count = addrs.len();
+        if count == 0 {
+            return Err(last);
+        }
+        let mut hosts = proto::HostProvider::new(addrs);
+        for _ in 0..count {
+            match self.clone().connect_hosts(hosts.clone()).await {
+                Ok((zk, events)) => {
+                    let zk = match connect.chroot {
+                        Some(ref chroot) => zk
+                            .using_namespace(chroot)
+                            .expect("chroots are validated paths"),
+                        None => zk,
+                    };
+                    return Ok((zk, events));
+                }
+                Err(e) => last = e,
+            }
+            hosts.advance();
+        }
+        Err(last)
+    }
+
+    /// Connect to a ZooKeeper server instance at the given address, and return the future that
+    /// drives the connection instead of spawning it.
+    ///
+    /// This is like [`ZooKeeperBuilder::connect`], except that no requests are sent or responses
+    /// received once this returns until the caller polls the returned driver, typically by
+    /// spawning it onto an executor of their choosing. The driver resolves once every handle to
+    /// the session has been dropped and the session has been closed, or with the error that ended
+    /// the connection for good. It uses Tokio's timers and sockets, so it must be polled from
+    /// within the context of a Tokio runtime.
+    #[allow(clippy::type_complexity)]
+    pub async fn connect_with_driver(
         self,
         addr: &SocketAddr,
-    ) -> impl Future<
-        Item = (ZooKeeper, impl Stream<Item = WatchedEvent, Error = ()>),
-        Error = failure::Error,
+    ) -> Result<
+        (
+            ZooKeeper,
+            WatchedEventStream,
+            impl Future<Output = Result<(), Error>> + Send + 'static,
+        ),
+        Error,
     > {
-        let (tx, rx) = futures::sync::mpsc::unbounded();
-        let addr = addr.clone();
-        tokio::net::TcpStream::connect(&addr)
-            .map_err(failure::Error::from)
-            .and_then(move |stream| self.handshake(addr, stream, tx))
-            .map(move |zk| (zk, rx))
+        let hosts = proto::HostProvider::new(vec![*addr]);
+        self.connect_over::<tokio::net::TcpStream>(hosts, *addr, Runtime::Tokio)
+            .await
+    }
+
+    /// Connect to a ZooKeeper server instance at the given address using async-std.
+    ///
+    /// This is like [`ZooKeeperBuilder::connect`], except that the connection is made with
+    /// async-std's sockets, and is driven by a task that is spawned onto async-std's executor, so
+    /// no Tokio runtime is needed. The timers used by the returned `ZooKeeper`, such as those of
+    /// [`ZooKeeper::with_retry`], also come from async-std.
+    #[cfg(feature = "async-std")]
+    pub async fn connect_async_std(
+        self,
+        addr: &SocketAddr,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        let hosts = proto::HostProvider::new(vec![*addr]);
+        self.connect_spawned::<Compat<async_std::net::TcpStream>>(hosts, *addr, Runtime::AsyncStd)
+            .await
+    }
+
+    /// Connect to a ZooKeeper server instance at the given address using smol.
+    ///
+    /// This is like [`ZooKeeperBuilder::connect`], except that the connection is made with smol's
+    /// sockets, and is driven by a task that is spawned onto smol's global executor, so no Tokio
+    /// runtime is needed. The timers used by the returned `ZooKeeper`, such as those of
+    /// [`ZooKeeper::with_retry`], also come from smol.
+    #[cfg(feature = "smol")]
+    pub async fn connect_smol(
+        self,
+        addr: &SocketAddr,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        let hosts = proto::HostProvider::new(vec![*addr]);
+        self.connect_spawned::<Compat<smol::net::TcpStream>>(hosts, *addr, Runtime::Smol)
+            .await
+    }
+
+    /// Connect over the transport `S`, and drive the connection on a task that is spawned onto
+    /// `runtime`.
+    ///
+    /// The session connects to the first of `hosts`, and `server_addr` is the address that the
+    /// connection reports in its stats.
+    pub(crate) async fn connect_spawned<S>(
+        self,
+        hosts: proto::HostProvider<S::Addr>,
+        server_addr: SocketAddr,
+        runtime: Runtime,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error>
+    where
+        S: proto::ZooKeeperTransport + 'static,
+    {
+        let (zk, watcher, driver) = self.connect_over::<S>(hosts, server_addr, runtime).await?;
+        let logger = zk.logger.clone();
+        runtime.spawn(async move {
+            if let Err(e) = driver.await {
+                error!(logger, "packetizer exiting: {:?}", e);
+            }
+        });
+        Ok((zk, watcher))
+    }
+
+    /// Connect over the transport `S`, taking the connection's timers from `runtime`.
+    async fn connect_over<S>(
+        mut self,
+        hosts: proto::HostProvider<S::Addr>,
+        server_addr: SocketAddr,
+        runtime: Runtime,
+    ) -> Result<(ZooKeeper, WatchedEventStream, proto::Packetizer<S>), Error>
+    where
+        S: proto::ZooKeeperTransport + 'static,
+    {
+        self.options.runtime = runtime;
+        let (tx, rx) = mpsc::unbounded();
+        let rx = WatchedEventStream::new(
+            rx,
+            self.logger.clone(),
+            self.options.metrics.clone(),
+            self.options.slow_watch_threshold,
+        );
+        let connect_timeout = self.options.connect_timeout;
+        let connecting = Box::pin(async move {
+            let stream = S::connect(hosts.current()).await.map_err(Into::into)?;
+            self.handshake(hosts, server_addr, stream, tx).await
+        });
+        let (zk, driver) = match connect_timeout {
+            Some(timeout) => match future::select(connecting, runtime.sleep(timeout)).await {
+                Either::Left((connected, _)) => connected?,
+                Either::Right(((), _)) => return Err(Error::Timeout),
+            },
+            None => connecting.await?,
+        };
+        Ok((zk, rx, driver))
     }
 
     /// Set the ZooKeeper [session expiry
@@ -338,52 +609,562 @@ impl ZooKeeperBuilder {
         self.session_timeout = t;
     }
 
+    /// Fail to connect with [`Error::Timeout`] if the connection to the server, and the session on
+    /// it, are not established within `timeout`.
+    ///
+    /// This only applies to the first connection, not to those that re-establish the session once
+    /// it is lost, which each get an equal share of the session timeout among the servers. By
+    /// default, connecting waits for as long as the operating system lets it.
+    pub fn set_connect_timeout(&mut self, timeout: Option<time::Duration>) {
+        self.options.connect_timeout = timeout;
+    }
+
+    /// Fail requests with [`Error::Timeout`] if they are not answered within `timeout` of being
+    /// issued.
+    ///
+    /// Like with [`Error::ConnectionLoss`], a write that timed out may or may not have taken
+    /// effect. By default, requests wait for as long as the session lasts.
+    pub fn set_request_timeout(&mut self, timeout: Option<time::Duration>) {
+        self.options.request_timeout = timeout;
+    }
+
     /// Set the logger that should be used internally in the ZooKeeper client.
     ///
     /// By default, all logging is disabled. See also [the `slog`
     /// documentation](https://docs.rs/slog).
+    ///
+    /// This is only available with the `slog` feature, which is enabled by default. With the `log`
+    /// or `tracing` features, the client also logs to the global `log` logger or `tracing`
+    /// subscriber.
+    #[cfg(feature = "slog")]
     pub fn set_logger(&mut self, l: slog::Logger) {
-        self.logger = l;
+        self.logger = l.into();
+    }
+
+    /// Set when the client writes issued requests to the server.
+    ///
+    /// By default, requests are written immediately. Batch users that issue many requests in
+    /// quick succession can trade some latency for throughput with [`FlushStrategy::Batched`].
+    pub fn set_flush_strategy(&mut self, s: FlushStrategy) {
+        self.options.flush = s;
+    }
+
+    /// Log every request that takes at least `threshold` to complete at WARN level, along with its
+    /// operation, path, and latency, and the state of the connection.
+    ///
+    /// Slow requests are not logged by default.
+    pub fn set_slow_request_threshold(&mut self, threshold: Option<time::Duration>) {
+        self.options.slow_request_threshold = threshold;
+    }
+
+    /// Log node data in full when requests and responses are logged.
+    ///
+    /// By default, data is only logged as its length and a hash, since it may hold secrets. This
+    /// is meant for development, and should not be enabled in production. Session passwords are
+    /// never logged.
+    pub fn set_log_payloads(&mut self, full: bool) {
+        self.options.log_payloads = full;
+    }
+
+    /// Send `exists`, `get_data`, and `get_children` requests that were in flight when the
+    /// connection was lost again once the session has been resumed on a new connection, rather
+    /// than failing them with [`Error::ConnectionLoss`].
+    ///
+    /// Reads have no effect on the server, so repeating one is always safe, and any watch it sets
+    /// is set by the repeated request. Writes still fail, since they may have taken effect. Reads
+    /// are not re-issued if the session expired, or if the connection could not be re-established.
+    ///
+    /// Reads are not re-issued by default.
+    pub fn set_reissue_reads(&mut self, reissue: bool) {
+        self.options.reissue_reads = reissue;
+    }
+
+    /// Log every watch event that waits at least `threshold` in the watch stream returned by
+    /// [`ZooKeeperBuilder::connect`] at WARN level, along with its type, path, and how long it
+    /// waited.
+    ///
+    /// Events wait in the stream from when they are received until the application takes them
+    /// out, so this flags consumers that are slow to react to changes. Slow watch consumers are
+    /// not logged by default. Delivery latency is also reported to
+    /// [`ClientMetrics::on_watch_delivered`](metrics::ClientMetrics::on_watch_delivered).
+    pub fn set_slow_watch_threshold(&mut self, threshold: Option<time::Duration>) {
+        self.options.slow_watch_threshold = threshold;
+    }
+
+    /// Send an event with [`KeeperState::Expiring`] to the watch stream returned by
+    /// [`ZooKeeperBuilder::connect`] once the server has not been heard from for `fraction` of
+    /// the session timeout, for instance `0.8`.
+    ///
+    /// The session expires if the silence lasts for the whole timeout, so this gives applications
+    /// a chance to stop work that relies on the session, such as that of a leader, before it is
+    /// too late. If the server is heard from again on the same connection, the stream gets an
+    /// event with [`KeeperState::SyncConnected`]; if the connection is lost instead, it gets the
+    /// usual [`KeeperState::Disconnected`], and then `SyncConnected` once the session is resumed.
+    ///
+    /// No warnings are sent by default.
+    ///
+    /// # Panics
+    ///
+    /// If `fraction` is not between 0 and 1.
+    pub fn set_expiry_warning(&mut self, fraction: Option<f64>) {
+        if let Some(f) = fraction {
+            assert!(f > 0.0 && f <= 1.0, "expiry warning fraction {} is not in (0, 1]", f);
+        }
+        self.options.expiry_warning = fraction;
+    }
+
+    /// Fail requests right away with [`Error::BrokenCircuit`], rather than send them, while the
+    /// circuit of `breaker` is open.
+    ///
+    /// This protects callers that would rather fail fast than wait from an ensemble that is
+    /// failing requests, or that the client has lost its connection to; see [`CircuitBreaker`]
+    /// for when the circuit opens and closes. There is no circuit breaker by default.
+    pub fn set_circuit_breaker(&mut self, breaker: Option<CircuitBreaker>) {
+        self.options.circuit_breaker = breaker;
+    }
+
+    /// Hold requests back in the queue, rather than send them, while they come in faster than
+    /// `limit` allows.
+    ///
+    /// The limit applies to the session as a whole, across all of its handles, so that no part of
+    /// a process can flood the ensemble with requests. The requests that the client makes on its
+    /// own, such as pings and those that re-establish the session, do not count towards it. There
+    /// is no rate limit by default.
+    ///
+    /// # Panics
+    ///
+    /// If the rate of `limit` is not positive, or its burst is zero.
+    pub fn set_rate_limit(&mut self, limit: Option<RateLimit>) {
+        if let Some(limit) = limit {
+            assert!(limit.per_second > 0.0, "rate limit {} is not positive", limit.per_second);
+            assert!(limit.burst > 0, "rate limit burst is zero");
+        }
+        self.options.rate_limit = limit;
+    }
+
+    /// Fail requests that are larger than `bytes` with [`error::InvalidRequest::TooLarge`],
+    /// rather than send them.
+    ///
+    /// A server drops the connection of a client that sends it a request that is larger than its
+    /// `jute.maxbuffer`, which defaults to just under 1MB, so this should match that of the
+    /// ensemble. The size of a request is mostly that of the data it sets; for a multi, that of
+    /// all of its operations. Defaults to the default `jute.maxbuffer`.
+    ///
+    /// # Panics
+    ///
+    /// If `bytes` is zero.
+    pub fn set_max_request_size(&mut self, bytes: usize) {
+        assert!(bytes > 0, "max request size is zero");
+        self.options.max_request_size = Some(bytes);
+    }
+
+    /// Allow the client to connect to servers that are in read-only mode, because they have lost
+    /// touch with the majority of their ensemble.
+    ///
+    /// Such servers only serve reads, and report [`ConnectionState::ConnectedReadOnly`]. By
+    /// default, the client only connects to servers that can serve writes.
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.options.read_only = read_only;
+    }
+
+    /// Leave the session to expire once the last handle to it is dropped, rather than closing it.
+    ///
+    /// By default, the client closes its session when it is dropped, which deletes the session's
+    /// ephemeral nodes right away. A detached client just hangs up, so it shuts down without
+    /// waiting for the server, and its ephemeral nodes linger until the session times out, as
+    /// they would if the process had crashed. See also [`ZooKeeper::detach`].
+    pub fn set_detach_on_drop(&mut self, detach: bool) {
+        self.options.detach_on_drop = detach;
+    }
+
+    /// Set the hooks through which the client reports requests, watches and reconnects.
+    ///
+    /// See the [`metrics`] module.
+    pub fn set_metrics(&mut self, m: Arc<dyn metrics::ClientMetrics>) {
+        self.options.metrics = metrics::Metrics::new(m);
+    }
+
+    /// Call `f` as soon as a session has been established with the server.
+    ///
+    /// This and the other connection callbacks are called synchronously from the task that drives
+    /// the connection, before any other responses are processed. They let applications react
+    /// immediately, for instance by fencing themselves, but they should return quickly and must
+    /// not wait on requests to this client.
+    pub fn on_connected<F>(&mut self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.options.callbacks.connected = Some(Arc::new(f));
+    }
+
+    /// Call `f` when the connection to the server is lost.
+    ///
+    /// This is not called when the connection is closed because the last `ZooKeeper` handle was
+    /// dropped. See [`ZooKeeperBuilder::on_connected`].
+    pub fn on_disconnected<F>(&mut self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.options.callbacks.disconnected = Some(Arc::new(f));
+    }
+
+    /// Call `f` when the server reports that the session has expired.
+    ///
+    /// Any ephemeral nodes and watches of the session are gone at this point. See
+    /// [`ZooKeeperBuilder::on_connected`].
+    pub fn on_session_expired<F>(&mut self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.options.callbacks.session_expired = Some(Arc::new(f));
+    }
+
+    /// Call `f` when the server reports that authentication has failed.
+    ///
+    /// See [`ZooKeeperBuilder::on_connected`].
+    pub fn on_auth_failed<F>(&mut self, f: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.options.callbacks.auth_failed = Some(Arc::new(f));
     }
 
-    fn handshake(
+    async fn handshake<S>(
         self,
-        addr: SocketAddr,
-        stream: tokio::net::TcpStream,
-        default_watcher: futures::sync::mpsc::UnboundedSender<WatchedEvent>,
-    ) -> impl Future<Item = ZooKeeper, Error = failure::Error> {
+        hosts: proto::HostProvider<S::Addr>,
+        server_addr: SocketAddr,
+        stream: S,
+        default_watcher: mpsc::UnboundedSender<(WatchedEvent, time::Instant)>,
+    ) -> Result<(ZooKeeper, proto::Packetizer<S>), Error>
+    where
+        S: proto::ZooKeeperTransport,
+    {
         let request = proto::Request::Connect {
             protocol_version: 0,
             last_zxid_seen: 0,
             timeout: (self.session_timeout.as_secs() * 1_000) as i32
                 + self.session_timeout.subsec_millis() as i32,
             session_id: 0,
-            passwd: vec![],
-            read_only: false,
+            // what the Java client sends for a new session, though the server ignores it
+            passwd: vec![0; 16],
+            read_only: self.options.read_only,
         };
         debug!(self.logger, "about to perform handshake");
 
         let plog = self.logger.clone();
-        let enqueuer = proto::Packetizer::new(addr, stream, plog, default_watcher);
-        enqueuer.enqueue(request).map(move |response| {
-            trace!(self.logger, "{:?}", response);
-            ZooKeeper {
-                connection: enqueuer,
-                logger: self.logger,
-            }
-        })
+        let (enqueuer, mut packetizer) = proto::Packetizer::new(
+            hosts,
+            stream,
+            plog,
+            default_watcher,
+            self.options.clone(),
+        );
+        // the response arrives through the packetizer, so it has to be driven until then
+        let response = match future::select(enqueuer.enqueue(request), &mut packetizer).await {
+            Either::Left((response, _)) => response?,
+            Either::Right((exit, _)) => return Err(exit.err().unwrap_or(Error::ConnectionLoss)),
+        };
+        trace!(self.logger, "{:?}", proto::Logged(&response, self.options.log_payloads));
+        let session = match response {
+            Ok(proto::Response::Connect {
+                session_id,
+                password,
+                ..
+            }) => Session {
+                id: session_id,
+                password,
+            },
+            Ok(_) => unreachable!("the handshake is answered with a connect response"),
+            Err((e, _)) => return Err(e.into()),
+        };
+        let zk = ZooKeeper {
+            connection: enqueuer,
+            logger: self.logger,
+            namespace: Default::default(),
+            addr: server_addr,
+            runtime: self.options.runtime,
+            session: Arc::new(session),
+            capabilities: Default::default(),
+            watcher: None,
+            request_timeout: self.options.request_timeout,
+            connect_timeout: self.options.connect_timeout,
+        };
+        Ok((zk, packetizer))
     }
 }
 
+/// How long [`ZooKeeper::delete_guaranteed`] waits before retrying a delete that failed without a
+/// response from the server.
+const GUARANTEED_DELETE_RETRY_INTERVAL: time::Duration = time::Duration::from_millis(500);
+
 impl ZooKeeper {
     /// Connect to a ZooKeeper server instance at the given address with default parameters.
     ///
     /// See [`ZooKeeperBuilder::connect`].
-    pub fn connect(
+    pub async fn connect(
         addr: &SocketAddr,
-    ) -> impl Future<Item = (Self, impl Stream<Item = WatchedEvent, Error = ()>), Error = failure::Error>
+    ) -> Result<(Self, WatchedEventStream), Error> {
+        ZooKeeperBuilder::default().connect(addr).await
+    }
+
+    /// Return a handle to the same session whose paths are all relative to `namespace`.
+    ///
+    /// Every path passed to the returned handle is prefixed with `namespace` before it is sent to
+    /// the server, and the prefix is removed again from the paths the server returns, such as
+    /// the names of created nodes and the paths in events of watches set through the handle. `/`
+    /// refers to the namespace node itself, which is not created automatically. Namespaces nest:
+    /// `namespace` is itself relative to this handle's namespace, if it has one. The handle is
+    /// as cheap to create as a clone.
+    ///
+    /// Watches set with [`ZooKeeper::watch`] are reported on the session's global watcher
+    /// stream, which is shared by all handles, and so carry the full server-side path, unless
+    /// the handle has a watcher of its own; see [`ZooKeeper::with_own_watcher`].
+    pub fn using_namespace(&self, namespace: &str) -> Result<ZooKeeper, error::InvalidPath> {
+        ZkPath::validate(namespace)?;
+        let mut zk = self.clone();
+        zk.namespace = self.namespace.nest(namespace);
+        Ok(zk)
+    }
+
+    /// Return a handle to the same session that passes its requests, their responses and the
+    /// events of the watches set through it through `interceptor`.
+    ///
+    /// The interceptor wraps the ones that this handle already has, and the handles made from the
+    /// returned one, for instance with [`ZooKeeper::using_namespace`], keep it. See the
+    /// [`interceptor`] module.
+    pub fn with_interceptor(&self, interceptor: Arc<dyn interceptor::Interceptor>) -> ZooKeeper {
+        let mut zk = self.clone();
+        zk.namespace = self.namespace.intercepted(interceptor);
+        zk
+    }
+
+    /// Send `request` to the server, through the interceptors of this handle.
+    async fn enqueue(&self, request: proto::Request) -> Result<proto::Reply, Error> {
+        let connection = &self.connection;
+        let reply = self
+            .namespace
+            .interceptors()
+            .send(request, |request| connection.enqueue(request));
+        match self.request_timeout {
+            Some(timeout) => {
+                futures::pin_mut!(reply);
+                match future::select(reply, self.runtime.sleep(timeout)).await {
+                    Either::Left((reply, _)) => reply,
+                    Either::Right(((), _)) => Err(Error::Timeout),
+                }
+            }
+            None => reply.await,
+        }
+    }
+
+    /// Return a handle to the same session that has a default watcher of its own, along with the
+    /// stream of that watcher's events.
+    ///
+    /// The events of watches set with [`ZooKeeper::watch`] through the returned handle, or through
+    /// the handles made from it, are reported on the returned stream, with their paths relative
+    /// to the namespace of the handle that set them. So are the changes to the state of the
+    /// session. This lets a library share the session of its host application, each with a
+    /// namespace and a watcher of its own, instead of opening a session of its own:
+    ///
+    /// ```no_run
+    /// # use tokio_zookeeper::*;
+    /// # fn run(zk: ZooKeeper) -> Result<(), error::InvalidPath> {
+    /// let (library, library_events) = zk.using_namespace("/library")?.with_own_watcher();
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// The session's global watcher stream, which was returned when connecting, still receives
+    /// every event of the session. The returned stream ends once the session is closed.
+    pub fn with_own_watcher(&self) -> (ZooKeeper, WatchedEventStream) {
+        let (id, events) = self.connection.add_watcher(self.logger.clone());
+        let mut zk = self.clone();
+        zk.watcher = Some(id);
+        (zk, events)
+    }
+
+    /// Take a snapshot of the state of this client's connection, for instance to report from a
+    /// health check.
+    ///
+    /// All handles that share the connection report the same stats.
+    pub fn stats(&self) -> ConnectionStats {
+        self.connection.stats(self.addr)
+    }
+
+    /// Shut the session down: stop taking requests, wait up to `timeout` for the requests in
+    /// flight to complete, and then close the session.
+    ///
+    /// This applies to the whole session, so requests issued through any handle to it fail with
+    /// [`Error::NotSent`] from now on. Requests still in flight when `timeout` passes fail with
+    /// [`Error::ConnectionLoss`], and the client sends the close without waiting for the server
+    /// to acknowledge it, so this takes not much longer than `timeout` even if the server does
+    /// not respond. The watch stream ends once the session is closed.
+    pub async fn shutdown(&self, timeout: time::Duration) {
+        debug!(self.logger, "shutdown"; "timeout_ms" => timeout.as_millis() as u64);
+        self.connection.shutdown(timeout).await
+    }
+
+    /// Leave the session to expire once the last handle to it is dropped, rather than closing it,
+    /// and drop this handle.
+    ///
+    /// This applies to the whole session, so other handles to it, such as clones, also leave it
+    /// to expire once they are all dropped. See [`ZooKeeperBuilder::set_detach_on_drop`].
+    pub fn detach(self) {
+        self.connection.detach();
+    }
+
+    /// Return the last zxid this client has seen from the server, which is that of the newest
+    /// change that the client may have observed.
+    ///
+    /// A writer can hand this to a reader in another process, so that the reader can wait for
+    /// its own client to have caught up with it with [`ZooKeeper::wait_for_zxid`].
+    pub fn last_zxid_seen(&self) -> i64 {
+        self.stats().last_zxid_seen
+    }
+
+    /// Wait for the server this client is connected to to catch up with the leader of the
+    /// ensemble on the node at `path`.
+    ///
+    /// ZooKeeper only guarantees that each client sees changes in order, not that every client
+    /// sees them at the same time, so a server may lag behind the writes that other clients have
+    /// made. Reads issued after this resolves observe at least every change that the leader had
+    /// committed when the sync reached it.
+    pub async fn sync<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
     {
-        ZooKeeperBuilder::default().connect(addr)
+        let path = path.try_into().map_err(Into::into)?;
+        trace!(self.logger, "sync"; "path" => path.as_str());
+        let r = self
+            .enqueue(proto::Request::Sync {
+                path: self.namespace.resolve(&path),
+            })
+            .await?;
+        transform::sync(r)
+    }
+
+    /// Wait for this client to have seen at least `zxid`, for instance one that another client
+    /// shared after writing, so that reads issued after this resolves observe that write.
+    ///
+    /// Resolves right away if the client has already seen `zxid`, and otherwise syncs with the
+    /// leader first. Returns whether the client has seen `zxid`, which is only `false` if no server
+    /// of the ensemble had when the sync reached the leader, for instance because it came from
+    /// another ensemble.
+    pub async fn wait_for_zxid(&self, zxid: i64) -> Result<bool, Error> {
+        trace!(self.logger, "wait_for_zxid"; "zxid" => zxid);
+        if self.last_zxid_seen() >= zxid {
+            return Ok(true);
+        }
+        // the root always exists, whatever the namespace
+        let r = self
+            .enqueue(proto::Request::Sync {
+                path: "/".to_string(),
+            })
+            .await?;
+        transform::sync(r)?;
+        Ok(self.last_zxid_seen() >= zxid)
+    }
+
+    /// Return the current state of this client's connection.
+    ///
+    /// This is cheap, so it can be checked before every request, for instance to turn work away
+    /// while the client is reconnecting. The watch stream returned by
+    /// [`ZooKeeperBuilder::connect`] reports the same changes as they happen.
+    pub fn state(&self) -> ConnectionState {
+        self.connection.state()
+    }
+
+    /// Whether this client is currently connected to a server; see [`ZooKeeper::state`].
+    pub fn is_connected(&self) -> bool {
+        self.state().is_connected()
+    }
+
+    /// Wait for this client to be connected to a server, for instance to hold a startup sequence
+    /// back until ZooKeeper is available.
+    ///
+    /// Resolves right away if the client is already connected. Fails with [`Error::Timeout`] if
+    /// the client is not connected within `timeout`, with [`Error::SessionExpired`] if the session
+    /// expires, and with [`Error::ConnectionLoss`] if the client otherwise closes for good.
+    pub async fn wait_until_connected(&self, timeout: time::Duration) -> Result<(), Error> {
+        trace!(self.logger, "wait_until_connected"; "timeout_ms" => timeout.as_millis() as u64);
+        let settled = self.connection.settled();
+        futures::pin_mut!(settled);
+        let state = match future::select(settled, self.runtime.sleep(timeout)).await {
+            Either::Left((state, _)) => state,
+            Either::Right(((), _)) => return Err(Error::Timeout),
+        };
+        match state {
+            ConnectionState::Expired => Err(Error::SessionExpired),
+            ConnectionState::Closed => Err(Error::ConnectionLoss),
+            _ => Ok(()),
+        }
+    }
+
+    /// Return the credentials of this client's session.
+    ///
+    /// The session stays the same across reconnects, for as long as it does not expire.
+    pub fn session(&self) -> Session {
+        Session::clone(&self.session)
+    }
+
+    /// Return what the server that this client is connected to supports.
+    ///
+    /// This is found out the first time it is needed, by an operation that not every release
+    /// supports or by a call to this, which takes a round trip to the server. Asking the server
+    /// for its release with the `srvr` four-letter word is given up on after the
+    /// [connect timeout](ZooKeeperBuilder::set_connect_timeout), or after a few seconds if there
+    /// is none, since servers may have four-letter words disabled or not answer them at all. It
+    /// is only found out once, and not again when the client reconnects to another server of the
+    /// ensemble, which may run another release during a rolling upgrade.
+    ///
+    /// Operations that the server is known not to support fail with [`Error::Server`] and
+    /// [`ZkError::Unimplemented`] without being sent, rather than with whatever error the server
+    /// would answer them with.
+    pub async fn capabilities(&self) -> Capabilities {
+        if let Some(capabilities) = *self.capabilities.lock().unwrap() {
+            return capabilities;
+        }
+        let capabilities = capabilities::detect(self).await;
+        // try again next time if the server could not be asked
+        if capabilities.detected() {
+            *self.capabilities.lock().unwrap() = Some(capabilities);
+        }
+        capabilities
+    }
+
+    /// Send a request with the given `opcode` and `payload`, and return the server's response
+    /// without making anything of it.
+    ///
+    /// This is for opcodes that the crate has no operation for, such as those of newer or
+    /// experimental servers. The client frames the request and gives it an xid, but `payload`
+    /// must already be encoded the way the server expects it, and the body of the response is
+    /// left for the caller to decode. An error that the server answers with is returned as the
+    /// response's `err` rather than as an [`Error`], which is only returned if the request could
+    /// not be sent or answered. Paths in `payload` are not resolved against the client's
+    /// namespace, and the client knows nothing of any watches that the request sets.
+    ///
+    /// # Panics
+    ///
+    /// If `opcode` is that of a request that the client sends on its own, and whose response it
+    /// does not expect to share with anyone else: `CreateSession` (-10), `CloseSession` (-11),
+    /// `Ping` (11) or `Auth` (100).
+    pub async fn raw_request(
+        &self,
+        opcode: i32,
+        payload: impl Into<Cow<'static, [u8]>>,
+    ) -> Result<RawResponse, Error> {
+        assert!(
+            ![-10, -11, 11, 100].contains(&opcode),
+            "opcode {} cannot be sent with raw_request",
+            opcode
+        );
+        let body = payload.into();
+        trace!(self.logger, "raw_request"; "opcode" => opcode, "len" => body.len());
+        let r = self
+            .enqueue(proto::Request::Raw { opcode, body })
+            .await?;
+        transform::raw(r)
     }
 
     /// Create a node with the given `path` with `data` as its contents.
@@ -401,6 +1182,10 @@ impl ZooKeeper {
     /// the sequential number will be incremented by one. The newly created node's full name is
     /// returned when the future is resolved.
     ///
+    /// Unlike the other methods for single nodes, this takes any string as the `path`, and not
+    /// only a [`ZkPath`], because the `path` of a sequential node may end with `/` to create it as
+    /// a child of that node. The `path` is checked before the request is sent all the same.
+    ///
     /// If a node with the same actual path already exists in the ZooKeeper, the returned future
     /// resolves with an error of [`error::Create::NodeExists`]. Note that since a different actual
     /// path is used for each invocation of creating sequential nodes with the same `path`
@@ -415,28 +1200,60 @@ impl ZooKeeper {
     /// calls.
     ///
     /// The maximum allowable size of the data array is 1 MB (1,048,576 bytes).
-    pub fn create<D, A>(
-        self,
-        path: &str,
+    pub async fn create<P, D, A>(
+        &self,
+        path: P,
         data: D,
         acl: A,
         mode: CreateMode,
-    ) -> impl Future<Item = (Self, Result<String, error::Create>), Error = failure::Error>
+    ) -> Result<Result<String, error::Create>, Error>
     where
+        P: AsRef<str>,
         D: Into<Cow<'static, [u8]>>,
         A: Into<Cow<'static, [Acl]>>,
     {
+        let path = path.as_ref();
         let data = data.into();
         trace!(self.logger, "create"; "path" => path, "mode" => ?mode, "dlen" => data.len());
-        self.connection
+        if mode == CreateMode::Container && !self.capabilities().await.containers() {
+            return Err(capabilities::unsupported());
+        }
+        let r = self
             .enqueue(proto::Request::Create {
-                path: path.to_string(),
-                data: data,
+                path: self.namespace.resolve(path),
+                data,
                 acl: acl.into(),
                 mode,
+                with_stat: false,
+            })
+            .await?;
+        Ok(transform::create(path, r)?.map(|path| self.namespace.strip(&path)))
+    }
+
+    /// Create a node like [`ZooKeeper::create`], and return its stat along with its path.
+    ///
+    /// This needs a server of 3.5 or later; see [`Capabilities::create2`].
+    async fn create2(
+        &self,
+        path: &str,
+        data: Cow<'static, [u8]>,
+        acl: Cow<'static, [Acl]>,
+        mode: CreateMode,
+    ) -> Result<Result<(String, Stat), error::Create>, Error> {
+        trace!(self.logger, "create2"; "path" => path, "mode" => ?mode, "dlen" => data.len());
+        if mode == CreateMode::Container && !self.capabilities().await.containers() {
+            return Err(capabilities::unsupported());
+        }
+        let r = self
+            .enqueue(proto::Request::Create {
+                path: self.namespace.resolve(path),
+                data,
+                acl,
+                mode,
+                with_stat: true,
             })
-            .and_then(transform::create)
-            .map(move |r| (self, r))
+            .await?;
+        Ok(transform::create2(path, r)?.map(|(path, stat)| (self.namespace.strip(&path), stat)))
     }
 
     /// Set the data for the node at the given `path`.
@@ -449,26 +1266,47 @@ impl ZooKeeper {
     /// left by `get_data` calls.
     ///
     /// The maximum allowable size of the data array is 1 MB (1,048,576 bytes).
-    pub fn set_data<D>(
-        self,
-        path: &str,
+    pub async fn set_data<P, D>(
+        &self,
+        path: P,
         version: Option<i32>,
         data: D,
-    ) -> impl Future<Item = (Self, Result<Stat, error::SetData>), Error = failure::Error>
+    ) -> Result<Result<Stat, error::SetData>, Error>
     where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
         D: Into<Cow<'static, [u8]>>,
     {
+        let path = path.try_into().map_err(Into::into)?;
         let data = data.into();
-        trace!(self.logger, "set_data"; "path" => path, "version" => ?version, "dlen" => data.len());
-        let version = version.unwrap_or(-1);
-        self.connection
+        trace!(self.logger, "set_data"; "path" => path.as_str(), "version" => ?version, "dlen" => data.len());
+        let version = version.unwrap_or(ANY_VERSION);
+        let r = self
             .enqueue(proto::Request::SetData {
-                path: path.to_string(),
+                path: self.namespace.resolve(&path),
                 version,
                 data,
             })
-            .and_then(move |r| transform::set_data(version, r))
-            .map(move |r| (self, r))
+            .await?;
+        transform::set_data(&path, version, r)
+    }
+
+    /// Set the data for the node at the given `path`, whatever version the node is at.
+    ///
+    /// This is [`ZooKeeper::set_data`] with a version of [`ANY_VERSION`], so the write replaces
+    /// whatever data the node has, including data that was written since it was last read. Use
+    /// [`ZooKeeper::set_data`] with the version that was read to only replace that data.
+    pub async fn set_data_any<P, D>(
+        &self,
+        path: P,
+        data: D,
+    ) -> Result<Result<Stat, error::SetData>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+        D: Into<Cow<'static, [u8]>>,
+    {
+        self.set_data(path, None, data).await
     }
 
     /// Delete the node at the given `path`.
@@ -479,120 +1317,371 @@ impl ZooKeeper {
     /// This operation, if successful, will trigger all the watches on the node of the given `path`
     /// left by `exists` API calls, and the watches on the parent node left by `get_children` API
     /// calls.
-    pub fn delete(
-        self,
-        path: &str,
+    pub async fn delete<P>(
+        &self,
+        path: P,
         version: Option<i32>,
-    ) -> impl Future<Item = (Self, Result<(), error::Delete>), Error = failure::Error> {
-        trace!(self.logger, "delete"; "path" => path, "version" => ?version);
-        let version = version.unwrap_or(-1);
-        self.connection
+    ) -> Result<Result<(), error::Delete>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        trace!(self.logger, "delete"; "path" => path.as_str(), "version" => ?version);
+        let version = version.unwrap_or(ANY_VERSION);
+        let r = self
             .enqueue(proto::Request::Delete {
-                path: path.to_string(),
-                version: version,
+                path: self.namespace.resolve(&path),
+                version,
             })
-            .and_then(move |r| transform::delete(version, r))
-            .map(move |r| (self, r))
+            .await?;
+        transform::delete(&path, version, r)
     }
 
-    /// Return the [ACL](https://zookeeper.apache.org/doc/current/zookeeperProgrammers.html#sc_ZooKeeperAccessControl)
-    /// and Stat of the node at the given `path`.
+    /// Delete the node at the given `path`, whatever version the node is at.
     ///
-    /// If no node exists for the given path, the returned future resolves with an error of
-    /// [`error::GetAcl::NoNode`].
-    pub fn get_acl(
-        self,
-        path: &str,
-    ) -> impl Future<Item = (Self, Result<(Vec<Acl>, Stat), error::GetAcl>), Error = failure::Error>
+    /// This is [`ZooKeeper::delete`] with a version of [`ANY_VERSION`], so the node is deleted
+    /// even if it was written since it was last read.
+    pub async fn delete_any<P>(&self, path: P) -> Result<Result<(), error::Delete>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
     {
-        trace!(self.logger, "get_acl"; "path" => path);
-        self.connection
-            .enqueue(proto::Request::GetAcl {
-                path: path.to_string(),
-            })
-            .and_then(transform::get_acl)
-            .map(move |r| (self, r))
+        self.delete(path, None).await
     }
 
-    /// Set the [ACL](https://zookeeper.apache.org/doc/current/zookeeperProgrammers.html#sc_ZooKeeperAccessControl)
-    /// for the node of the given `path`.
+    /// Delete the node at the given `path`, retrying in the background until the deletion is
+    /// confirmed by the server.
     ///
-    /// The call will succeed if such a node exists and the given `version` matches the ACL version
-    /// of the node. On success, the updated [`Stat`] of the node is returned.
+    /// Unlike [`ZooKeeper::delete`], a request that fails without a response from the server,
+    /// for instance because the connection was lost, is retried until the server either deletes
+    /// the node or rejects the request, or until the session ends. Since an earlier attempt may
+    /// have deleted the node before its response was lost, a node that does not exist counts as
+    /// successfully deleted. See [`ZooKeeper::delete`] for the semantics of `version`.
     ///
-    /// If no node exists for the given path, the returned future resolves with an error of
+    /// The retries run on a task that is spawned onto the runtime the session was connected with
+    /// as soon as this is called. The returned future resolves with the final outcome, but need
+    /// not be polled: dropping it does not stop the retries.
+    pub fn delete_guaranteed<P>(
+        &self,
+        path: P,
+        version: Option<i32>,
+    ) -> impl Future<Output = Result<Result<(), error::Delete>, Error>>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = match path.try_into() {
+            Ok(path) => path,
+            Err(e) => return Either::Left(future::err(e.into())),
+        };
+        trace!(self.logger, "delete_guaranteed"; "path" => path.as_str(), "version" => ?version);
+        let (tx, rx) = oneshot::channel();
+        let zk = self.clone();
+        self.runtime.spawn(async move {
+            let res = loop {
+                match zk.delete(&path, version).await {
+                    Ok(Ok(())) | Ok(Err(error::Delete::NoNode { .. })) => break Ok(Ok(())),
+                    Ok(Err(e)) => break Ok(Err(e)),
+                    Err(e) => {
+                        if zk.connection.is_closed() {
+                            break Err(e);
+                        }
+                        debug!(zk.logger, "retrying delete: {}", e; "path" => path.as_str());
+                        zk.runtime.sleep(GUARANTEED_DELETE_RETRY_INTERVAL).await;
+                    }
+                }
+            };
+            // NOTE: the caller may not be interested in the outcome
+            let _ = tx.send(res);
+        });
+        Either::Right(rx.map(|res| res.unwrap_or(Err(Error::ConnectionLoss))))
+    }
+
+    /// Return the [ACL](https://zookeeper.apache.org/doc/current/zookeeperProgrammers.html#sc_ZooKeeperAccessControl)
+    /// and Stat of the node at the given `path`.
+    ///
+    /// If no node exists for the given path, the returned future resolves with an error of
+    /// [`error::GetAcl::NoNode`].
+    pub async fn get_acl<P>(
+        &self,
+        path: P,
+    ) -> Result<Result<(Vec<Acl>, Stat), error::GetAcl>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        trace!(self.logger, "get_acl"; "path" => path.as_str());
+        let r = self
+            .enqueue(proto::Request::GetAcl {
+                path: self.namespace.resolve(&path),
+            })
+            .await?;
+        transform::get_acl(&path, r)
+    }
+
+    /// Set the [ACL](https://zookeeper.apache.org/doc/current/zookeeperProgrammers.html#sc_ZooKeeperAccessControl)
+    /// for the node of the given `path`.
+    ///
+    /// The call will succeed if such a node exists and the given `version` matches the ACL version
+    /// of the node. On success, the updated [`Stat`] of the node is returned.
+    ///
+    /// If no node exists for the given path, the returned future resolves with an error of
     /// [`error::SetAcl::NoNode`]. If the given `version` does not match the ACL version, the
     /// returned future resolves with an error of [`error::SetAcl::BadVersion`].
-    pub fn set_acl<A>(
-        self,
-        path: &str,
+    pub async fn set_acl<P, A>(
+        &self,
+        path: P,
         acl: A,
         version: Option<i32>,
-    ) -> impl Future<Item = (Self, Result<Stat, error::SetAcl>), Error = failure::Error>
+    ) -> Result<Result<Stat, error::SetAcl>, Error>
     where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
         A: Into<Cow<'static, [Acl]>>,
     {
-        trace!(self.logger, "set_acl"; "path" => path, "version" => ?version);
-        let version = version.unwrap_or(-1);
-        self.connection
+        let path = path.try_into().map_err(Into::into)?;
+        trace!(self.logger, "set_acl"; "path" => path.as_str(), "version" => ?version);
+        let version = version.unwrap_or(ANY_VERSION);
+        let r = self
             .enqueue(proto::Request::SetAcl {
-                path: path.to_string(),
+                path: self.namespace.resolve(&path),
                 acl: acl.into(),
                 version,
             })
-            .and_then(move |r| transform::set_acl(version, r))
-            .map(move |r| (self, r))
+            .await?;
+        transform::set_acl(&path, version, r)
+    }
+
+    /// Create a node at the given `path` with `data` as its contents, or replace the data of the
+    /// node if it already exists.
+    ///
+    /// The race between a `create` that fails with `NodeExists` and the subsequent `set_data` is
+    /// resolved internally: if the node is deleted by someone else in between, the create is
+    /// simply retried. On success, the returned [`Upsert`] tells whether the node was created or
+    /// updated, along with the [`Stat`] that the create or the set left the node with. Servers
+    /// before 3.5 do not return the stat of the nodes they create, so with those, the stat of a
+    /// created node is read back afterwards, and may describe a later version of it.
+    ///
+    /// `acl` and `mode` are only used if the node is created; an existing node keeps its ACL and
+    /// mode. Sequential modes never conflict with an existing node, so they are rejected with
+    /// [`error::CreateOrSet::Sequential`] rather than create a new node on every call.
+    pub async fn create_or_set<P, D, A>(
+        &self,
+        path: P,
+        data: D,
+        acl: A,
+        mode: CreateMode,
+    ) -> Result<Result<(Upsert, Stat), error::CreateOrSet>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+        D: Into<Cow<'static, [u8]>>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        if matches!(
+            mode,
+            CreateMode::PersistentSequential | CreateMode::EphemeralSequential
+        ) {
+            let path = path.as_str().to_owned();
+            return Ok(Err(error::CreateOrSet::Sequential { path }));
+        }
+        let data = data.into();
+        let acl = acl.into();
+        trace!(self.logger, "create_or_set"; "path" => path.as_str(), "mode" => ?mode, "dlen" => data.len());
+        let create2 = self.capabilities().await.create2();
+        loop {
+            let created = if create2 {
+                self.create2(&path, data.clone(), acl.clone(), mode)
+                    .await?
+                    .map(|(_, stat)| Some(stat))
+            } else {
+                match self.create(&path, data.clone(), acl.clone(), mode).await? {
+                    Ok(created) => Ok(self.exists(&created).await?),
+                    Err(e) => Err(e),
+                }
+            };
+            match created {
+                Ok(Some(stat)) => return Ok(Ok((Upsert::Created, stat))),
+                // deleted again before we could read it back, so start over
+                Ok(None) => {}
+                Err(error::Create::NodeExists { .. }) => {
+                    match self.set_data_any(&path, data.clone()).await? {
+                        Ok(stat) => return Ok(Ok((Upsert::Updated, stat))),
+                        // deleted before we could update it
+                        Err(error::SetData::NoNode { .. }) => {}
+                        Err(error::SetData::NoAuth { path }) => {
+                            return Ok(Err(error::CreateOrSet::NoAuth { path }))
+                        }
+                        Err(error::SetData::BadVersion { .. }) => {
+                            unreachable!("set_data without version cannot fail on version")
+                        }
+                    }
+                }
+                Err(e) => {
+                    let e = match e {
+                        error::Create::NoNode { path } => error::CreateOrSet::NoNode { path },
+                        error::Create::NoChildrenForEphemerals { path } => {
+                            error::CreateOrSet::NoChildrenForEphemerals { path }
+                        }
+                        error::Create::InvalidAcl { path } => {
+                            error::CreateOrSet::InvalidAcl { path }
+                        }
+                        error::Create::NodeExists { .. } => unreachable!(),
+                    };
+                    return Ok(Err(e));
+                }
+            }
+        }
+    }
+
+    /// Atomically replace the data of the node at the given `path` with the result of applying
+    /// `f` to its current data.
+    ///
+    /// This performs the usual optimistic-update loop: the node's data and version are read, `f`
+    /// computes the new data, and the new data is written conditionally on the version not having
+    /// changed. If another client modified the node in the meantime, the whole cycle is repeated
+    /// after the delay that `policy` gives, for as long as it gives one, so that clients that
+    /// contend for a node back off rather than keep the leader busy with writes that fail. As a
+    /// consequence, `f` may be called more than once, and should not have side-effects. On
+    /// success, the updated [`Stat`] of the node is returned.
+    ///
+    /// If the node is still being concurrently modified once `policy` gives up, the returned
+    /// future resolves with an error of [`error::CompareAndSwap::BadVersion`]. Failures of the
+    /// connection are not retried; see [`ZooKeeper::with_retry`] for those.
+    pub async fn compare_and_swap<P, R, F, D>(
+        &self,
+        path: P,
+        policy: R,
+        mut f: F,
+    ) -> Result<Result<Stat, error::CompareAndSwap>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+        R: retry::RetryPolicy,
+        F: FnMut(&[u8]) -> D,
+        D: Into<Cow<'static, [u8]>>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        trace!(self.logger, "compare_and_swap"; "path" => path.as_str());
+        let mut attempt = 0;
+        loop {
+            let (data, stat) = match self.get_data(&path).await? {
+                Some(res) => res,
+                None => {
+                    let path = path.as_str().to_owned();
+                    return Ok(Err(error::CompareAndSwap::NoNode { path }));
+                }
+            };
+            let data = f(&data);
+            match self.set_data(&path, Some(stat.version), data).await? {
+                Ok(stat) => return Ok(Ok(stat)),
+                Err(error::SetData::BadVersion { path, expected }) => {
+                    match policy.next_delay(attempt) {
+                        Some(delay) => {
+                            trace!(self.logger, "compare_and_swap conflict"; "attempt" => attempt);
+                            self.runtime.sleep(delay).await;
+                            attempt += 1;
+                        }
+                        None => {
+                            return Ok(Err(error::CompareAndSwap::BadVersion { path, expected }))
+                        }
+                    }
+                }
+                Err(error::SetData::NoNode { path }) => {
+                    return Ok(Err(error::CompareAndSwap::NoNode { path }))
+                }
+                Err(error::SetData::NoAuth { path }) => {
+                    return Ok(Err(error::CompareAndSwap::NoAuth { path }))
+                }
+            }
+        }
     }
 }
 
 impl ZooKeeper {
     /// Add a global watch for the next chained operation.
-    pub fn watch(self) -> WatchGlobally {
+    ///
+    /// If this handle has a watcher of its own, its events go there rather than to the global
+    /// watcher stream; see [`ZooKeeper::with_own_watcher`].
+    pub fn watch(&self) -> WatchGlobally<'_> {
         WatchGlobally(self)
     }
 
+    /// The watch that sends its event to this handle's default watcher.
+    fn default_watch(&self) -> Watch {
+        match self.watcher {
+            Some(id) => Watch::Handle(id, self.namespace.clone()),
+            None => Watch::Global,
+        }
+    }
+
     /// Add a watch for the next chained operation, and return a future for any received event
     /// along with the operation's (successful) result.
-    pub fn with_watcher(self) -> WithWatcher {
+    pub fn with_watcher(&self) -> WithWatcher<'_> {
         WithWatcher(self)
     }
 
-    fn exists_w(
-        self,
-        path: &str,
-        watch: Watch,
-    ) -> impl Future<Item = (Self, Option<Stat>), Error = failure::Error> {
+    async fn exists_w(&self, path: &str, watch: Watch) -> Result<Option<Stat>, Error> {
         trace!(self.logger, "exists"; "path" => path, "watch" => ?watch);
-        self.connection
+        let r = self
             .enqueue(proto::Request::Exists {
-                path: path.to_string(),
+                path: self.namespace.resolve(path),
                 watch,
             })
-            .and_then(transform::exists)
-            .map(move |r| (self, r))
+            .await?;
+        transform::exists(r)
     }
 
     /// Return the [`Stat`] of the node of the given `path`, or `None` if the node does not exist.
-    pub fn exists(
-        self,
-        path: &str,
-    ) -> impl Future<Item = (Self, Option<Stat>), Error = failure::Error> {
-        self.exists_w(path, Watch::None)
+    pub async fn exists<P>(&self, path: P) -> Result<Option<Stat>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.exists_w(&path, Watch::None).await
     }
 
-    fn get_children_w(
-        self,
-        path: &str,
-        watch: Watch,
-    ) -> impl Future<Item = (Self, Option<Vec<String>>), Error = failure::Error> {
+    /// Return whether a node exists at the given `path`.
+    pub async fn exists_bool<P>(&self, path: P) -> Result<bool, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        Ok(self.exists(path).await?.is_some())
+    }
+
+    /// Return the [`Stat`] of the node of the given `path`, or `None` if the node does not exist,
+    /// along with a future that resolves the next time the node is created, deleted, or has its
+    /// data changed.
+    ///
+    /// This is a shorthand for `zk.with_watcher().exists(path)` for the common "check, then wait
+    /// for a change" pattern. The returned watch future resolves with an error if the connection
+    /// to ZooKeeper is closed before the watch triggers.
+    pub async fn exists_watch<P>(
+        &self,
+        path: P,
+    ) -> Result<(Option<Stat>, impl Future<Output = Result<WatchedEvent, Error>>), Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let (w, stat) = self.with_watcher().exists(path).await?;
+        Ok((stat, w.map_err(|_| Error::ConnectionLoss)))
+    }
+
+    async fn get_children_w(&self, path: &str, watch: Watch) -> Result<Option<Vec<String>>, Error> {
         trace!(self.logger, "get_children"; "path" => path, "watch" => ?watch);
-        self.connection
+        let r = self
             .enqueue(proto::Request::GetChildren {
-                path: path.to_string(),
+                path: self.namespace.resolve(path),
                 watch,
             })
-            .and_then(transform::get_children)
-            .map(move |r| (self, r))
+            .await?;
+        transform::get_children(r)
     }
 
     /// Return the names of the children of the node at the given `path`, or `None` if the node
@@ -600,43 +1689,241 @@ impl ZooKeeper {
     ///
     /// The returned list of children is not sorted and no guarantee is provided as to its natural
     /// or lexical order.
-    pub fn get_children(
-        self,
+    pub async fn get_children<P>(&self, path: P) -> Result<Option<Vec<String>>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.get_children_w(&path, Watch::None).await
+    }
+
+    async fn get_children2_w(
+        &self,
         path: &str,
-    ) -> impl Future<Item = (Self, Option<Vec<String>>), Error = failure::Error> {
-        self.get_children_w(path, Watch::None)
+        watch: Watch,
+    ) -> Result<Option<(Vec<String>, Stat)>, Error> {
+        trace!(self.logger, "get_children_with_stat"; "path" => path, "watch" => ?watch);
+        let r = self
+            .enqueue(proto::Request::GetChildren2 {
+                path: self.namespace.resolve(path),
+                watch,
+            })
+            .await?;
+        transform::get_children2(r)
     }
 
-    fn get_data_w(
-        self,
+    /// Return the names of the children of the node at the given `path` along with the node's
+    /// [`Stat`], or `None` if the node does not exist.
+    ///
+    /// The stat is read in the same request as the children, so its
+    /// [`cversion`](Stat::cversion) and [`pzxid`](Stat::pzxid) are those of the returned list. A
+    /// later read with a different `cversion` returns a list that has changed since, and one with
+    /// the same `cversion` returns the same list, which makes it possible to notice concurrent
+    /// modifications without a watch.
+    ///
+    /// As with [`ZooKeeper::get_children`], the children are not sorted.
+    pub async fn get_children_with_stat<P>(
+        &self,
+        path: P,
+    ) -> Result<Option<(Vec<String>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.get_children2_w(&path, Watch::None).await
+    }
+
+    async fn get_data_w(
+        &self,
         path: &str,
         watch: Watch,
-    ) -> impl Future<Item = (Self, Option<(Vec<u8>, Stat)>), Error = failure::Error> {
+    ) -> Result<Option<(bytes::Bytes, Stat)>, Error> {
         trace!(self.logger, "get_data"; "path" => path, "watch" => ?watch);
-        self.connection
+        let r = self
             .enqueue(proto::Request::GetData {
-                path: path.to_string(),
+                path: self.namespace.resolve(path),
                 watch,
             })
-            .and_then(transform::get_data)
-            .map(move |r| (self, r))
+            .await?;
+        transform::get_data(r)
     }
 
     /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
     /// exist.
-    pub fn get_data(
-        self,
-        path: &str,
-    ) -> impl Future<Item = (Self, Option<(Vec<u8>, Stat)>), Error = failure::Error> {
-        self.get_data_w(path, Watch::None)
+    pub async fn get_data<P>(&self, path: P) -> Result<Option<(Vec<u8>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let r = self.get_data_w(&path, Watch::None).await?;
+        Ok(r.map(|(b, s)| (b.to_vec(), s)))
+    }
+
+    /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
+    /// exist.
+    ///
+    /// The data is returned in an `Arc`, so it can be handed out to many consumers (for instance
+    /// by a cache that is kept up to date with watches) without being copied for each of them.
+    pub async fn get_data_shared<P>(&self, path: P) -> Result<Option<(Arc<[u8]>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let r = self.get_data_w(&path, Watch::None).await?;
+        Ok(r.map(|(b, s)| (Arc::from(&b[..]), s)))
+    }
+
+    /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
+    /// exist.
+    ///
+    /// Unlike [`ZooKeeper::get_data`], the data is not copied out of the buffer the response was
+    /// read into. The returned [`Bytes`] keeps (a part of) that buffer alive for as long as it
+    /// exists, so it should not be held on to long after large reads.
+    #[cfg(feature = "zero-copy")]
+    pub async fn get_data_bytes<P>(&self, path: P) -> Result<Option<(Bytes, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.get_data_w(&path, Watch::None).await
+    }
+
+    /// Return the data and the [`Stat`] of the nodes at each of the given `paths`, in the same
+    /// order as `paths`, with `None` for nodes that do not exist.
+    ///
+    /// Up to `max_concurrent` requests are kept in flight at any given time. All of them are
+    /// pipelined over this client's single connection, so a higher limit mostly reduces the
+    /// impact of round-trip latency. A `max_concurrent` of `0` is treated as `1`.
+    #[allow(clippy::type_complexity)]
+    pub async fn get_data_many<I>(
+        &self,
+        paths: I,
+        max_concurrent: usize,
+    ) -> Result<Vec<Option<(Vec<u8>, Stat)>>, Error>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let paths: Vec<String> = paths
+            .into_iter()
+            .map(|path| path.as_ref().to_string())
+            .collect();
+        trace!(self.logger, "get_data_many"; "n" => paths.len(), "max_concurrent" => max_concurrent);
+        stream::iter(paths.iter().map(|path| self.get_data(path)))
+            .buffered(max_concurrent.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Create a node for each of the given `(path, data, acl, mode)` entries, and return the
+    /// result of each creation in the same order as `entries`.
+    ///
+    /// The entries are split into `multi` transactions that stay below the maximum request size
+    /// (see [`ZooKeeperBuilder::set_max_request_size`]), so large batches need only a handful of
+    /// round-trips. Since a `multi` transaction is atomic, a single failing entry causes its whole
+    /// transaction to be rolled back. When that happens, the transaction is re-issued without the
+    /// failing entry until it succeeds, so every entry that can be created is created, and every
+    /// entry that cannot be created is reported with the reason why.
+    ///
+    /// See [`ZooKeeper::create`] for details on the meaning of each entry's fields.
+    pub async fn create_many<I, P, D, A>(
+        &self,
+        entries: I,
+    ) -> Result<Vec<Result<String, error::Create>>, Error>
+    where
+        I: IntoIterator<Item = (P, D, A, CreateMode)>,
+        P: AsRef<str>,
+        D: Into<Cow<'static, [u8]>>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        // leave room for the frame's xid and opcode, and for the header that ends the multi
+        let max_batch_bytes = self.connection.max_request_size().saturating_sub(4 + 4 + 9);
+
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        let mut batch_bytes = 0;
+        let mut n = 0;
+        for (path, data, acl, mode) in entries {
+            let (path, data, acl) = (path.as_ref().to_string(), data.into(), acl.into());
+            // multi header + path + data + acl list + mode
+            let bytes = 9
+                + 4
+                + self.namespace.resolve(&path).len()
+                + 4
+                + data.len()
+                + 4
+                + acl
+                    .iter()
+                    .map(|acl| 12 + acl.scheme.len() + acl.id.len())
+                    .sum::<usize>()
+                + 4;
+            if !batch.is_empty() && batch_bytes + bytes > max_batch_bytes {
+                batches.push(mem::take(&mut batch));
+                batch_bytes = 0;
+            }
+            batch.push((n, path, data, acl, mode));
+            batch_bytes += bytes;
+            n += 1;
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        trace!(self.logger, "create_many"; "n" => n, "batches" => batches.len());
+
+        let mut results: Vec<Option<Result<String, error::Create>>> = vec![None; n];
+        for mut batch in batches {
+            loop {
+                let mut multi = self.multi();
+                for &(_, ref path, ref data, ref acl, mode) in &batch {
+                    multi = multi.create(path, data.clone(), acl.clone(), mode);
+                }
+                let res = multi.run().await?;
+                let attempted = batch.len();
+                let mut retry = Vec::new();
+                for (entry, res) in batch.into_iter().zip(res) {
+                    let i = entry.0;
+                    match res {
+                        Ok(MultiResponse::Create(path)) => results[i] = Some(Ok(path)),
+                        Err(error::Multi::Create(e)) => results[i] = Some(Err(e)),
+                        Err(error::Multi::RolledBack) | Err(error::Multi::Skipped) => {
+                            retry.push(entry)
+                        }
+                        res => {
+                            return Err(Error::Protocol(format!(
+                                "got non-create response to create: {:?}",
+                                res
+                            )))
+                        }
+                    }
+                }
+                if retry.is_empty() {
+                    break;
+                } else if retry.len() == attempted {
+                    return Err(Error::Protocol(
+                        "multi create failed without reporting which request failed".to_string(),
+                    ));
+                }
+                batch = retry;
+            }
+        }
+        Ok(results
+            .into_iter()
+            .map(|r| r.expect("every entry is either created or fails"))
+            .collect())
     }
 
     /// Start building a multi request. Multi requests batch several operations
     /// into one atomic unit.
-    pub fn multi(self) -> MultiBuilder {
+    pub fn multi(&self) -> MultiBuilder<'_> {
         MultiBuilder {
             zk: self,
             requests: Vec::new(),
+            invalid: None,
         }
     }
 }
@@ -644,20 +1931,22 @@ impl ZooKeeper {
 /// Proxy for [`ZooKeeper`] that adds watches for initiated operations.
 ///
 /// Triggered watches produce events on the global watcher stream.
-#[derive(Debug, Clone)]
-pub struct WatchGlobally(ZooKeeper);
+#[derive(Debug, Clone, Copy)]
+pub struct WatchGlobally<'a>(&'a ZooKeeper);
 
-impl WatchGlobally {
+impl WatchGlobally<'_> {
     /// Return the [`Stat`] of the node of the given `path`, or `None` if the node does not exist.
     ///
     /// If no errors occur, a watch is left on the node at the given `path`. The watch is triggered
     /// by any successful operation that creates or deletes the node, or sets the node's data. When
     /// the watch triggers, an event is sent to the global watcher stream.
-    pub fn exists(
-        self,
-        path: &str,
-    ) -> impl Future<Item = (ZooKeeper, Option<Stat>), Error = failure::Error> {
-        self.0.exists_w(path, Watch::Global)
+    pub async fn exists<P>(self, path: P) -> Result<Option<Stat>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.0.exists_w(&path, self.0.default_watch()).await
     }
 
     /// Return the names of the children of the node at the given `path`, or `None` if the node
@@ -670,11 +1959,30 @@ impl WatchGlobally {
     /// by any successful operation that deletes the node at the given `path`, or creates or
     /// deletes a child of that node. When the watch triggers, an event is sent to the global
     /// watcher stream.
-    pub fn get_children(
+    pub async fn get_children<P>(self, path: P) -> Result<Option<Vec<String>>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.0.get_children_w(&path, self.0.default_watch()).await
+    }
+
+    /// Return the names of the children of the node at the given `path` along with the node's
+    /// [`Stat`], or `None` if the node does not exist.
+    ///
+    /// See [`ZooKeeper::get_children_with_stat`]. The watch is left as by
+    /// [`WatchGlobally::get_children`].
+    pub async fn get_children_with_stat<P>(
         self,
-        path: &str,
-    ) -> impl Future<Item = (ZooKeeper, Option<Vec<String>>), Error = failure::Error> {
-        self.0.get_children_w(path, Watch::Global)
+        path: P,
+    ) -> Result<Option<(Vec<String>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.0.get_children2_w(&path, self.0.default_watch()).await
     }
 
     /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
@@ -683,11 +1991,14 @@ impl WatchGlobally {
     /// If no errors occur, a watch is left on the node at the given `path`. The watch is triggered
     /// by any successful operation that sets the node's data, or deletes it. When the watch
     /// triggers, an event is sent to the global watcher stream.
-    pub fn get_data(
-        self,
-        path: &str,
-    ) -> impl Future<Item = (ZooKeeper, Option<(Vec<u8>, Stat)>), Error = failure::Error> {
-        self.0.get_data_w(path, Watch::Global)
+    pub async fn get_data<P>(self, path: P) -> Result<Option<(Vec<u8>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let r = self.0.get_data_w(&path, self.0.default_watch()).await?;
+        Ok(r.map(|(b, s)| (b.to_vec(), s)))
     }
 }
 
@@ -695,26 +2006,28 @@ impl WatchGlobally {
 ///
 /// Events from triggered watches are yielded through returned `oneshot` channels. All events are
 /// also produced on the global watcher stream.
-#[derive(Debug, Clone)]
-pub struct WithWatcher(ZooKeeper);
+#[derive(Debug, Clone, Copy)]
+pub struct WithWatcher<'a>(&'a ZooKeeper);
 
-impl WithWatcher {
+impl WithWatcher<'_> {
     /// Return the [`Stat`] of the node of the given `path`, or `None` if the node does not exist.
     ///
     /// If no errors occur, a watch will be left on the node at the given `path`. The watch is
     /// triggered by any successful operation that creates or deletes the node, or sets the data on
     /// the node, and in turn causes the included `oneshot::Receiver` to resolve.
-    pub fn exists(
+    pub async fn exists<P>(
         self,
-        path: &str,
-    ) -> impl Future<
-        Item = (ZooKeeper, oneshot::Receiver<WatchedEvent>, Option<Stat>),
-        Error = failure::Error,
-    > {
+        path: P,
+    ) -> Result<(oneshot::Receiver<WatchedEvent>, Option<Stat>), Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
         let (tx, rx) = oneshot::channel();
-        self.0
-            .exists_w(path, Watch::Custom(tx))
-            .map(|r| (r.0, rx, r.1))
+        let watch = Watch::Custom(tx, self.0.namespace.clone());
+        let stat = self.0.exists_w(&path, watch).await?;
+        Ok((rx, stat))
     }
 
     /// Return the names of the children of the node at the given `path`, or `None` if the node
@@ -727,20 +2040,40 @@ impl WithWatcher {
     /// by any successful operation that deletes the node at the given `path`, or creates or
     /// deletes a child of that node, and in turn causes the included `oneshot::Receiver` to
     /// resolve.
-    pub fn get_children(
+    pub async fn get_children<P>(
         self,
-        path: &str,
-    ) -> impl Future<
-        Item = (
-            ZooKeeper,
-            Option<(oneshot::Receiver<WatchedEvent>, Vec<String>)>,
-        ),
-        Error = failure::Error,
-    > {
+        path: P,
+    ) -> Result<Option<(oneshot::Receiver<WatchedEvent>, Vec<String>)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let (tx, rx) = oneshot::channel();
+        let watch = Watch::Custom(tx, self.0.namespace.clone());
+        let r = self.0.get_children_w(&path, watch).await?;
+        Ok(r.map(move |c| (rx, c)))
+    }
+
+    /// Return the names of the children of the node at the given `path` along with the node's
+    /// [`Stat`], or `None` if the node does not exist.
+    ///
+    /// See [`ZooKeeper::get_children_with_stat`]. The watch is left as by
+    /// [`WithWatcher::get_children`].
+    #[allow(clippy::type_complexity)]
+    pub async fn get_children_with_stat<P>(
+        self,
+        path: P,
+    ) -> Result<Option<(oneshot::Receiver<WatchedEvent>, Vec<String>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
         let (tx, rx) = oneshot::channel();
-        self.0
-            .get_children_w(path, Watch::Custom(tx))
-            .map(|r| (r.0, r.1.map(move |c| (rx, c))))
+        let watch = Watch::Custom(tx, self.0.namespace.clone());
+        let r = self.0.get_children2_w(&path, watch).await?;
+        Ok(r.map(move |(c, s)| (rx, c, s)))
     }
 
     /// Return the data and the [`Stat`] of the node at the given `path`, or `None` if it does not
@@ -749,44 +2082,83 @@ impl WithWatcher {
     /// If no errors occur, a watch is left on the node at the given `path`. The watch is triggered
     /// by any successful operation that sets the node's data, or deletes it, and in turn causes
     /// the included `oneshot::Receiver` to resolve.
-    pub fn get_data(
+    #[allow(clippy::type_complexity)]
+    pub async fn get_data<P>(
         self,
-        path: &str,
-    ) -> impl Future<
-        Item = (
-            ZooKeeper,
-            Option<(oneshot::Receiver<WatchedEvent>, Vec<u8>, Stat)>,
-        ),
-        Error = failure::Error,
-    > {
+        path: P,
+    ) -> Result<Option<(oneshot::Receiver<WatchedEvent>, Vec<u8>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let (tx, rx) = oneshot::channel();
+        let watch = Watch::Custom(tx, self.0.namespace.clone());
+        let r = self.0.get_data_w(&path, watch).await?;
+        Ok(r.map(move |(b, s)| (rx, b.to_vec(), s)))
+    }
+
+    /// Return the data and the [`Stat`] of the node at the given `path` in an `Arc`, or `None` if
+    /// it does not exist.
+    ///
+    /// See [`ZooKeeper::get_data_shared`] and [`WithWatcher::get_data`].
+    #[allow(clippy::type_complexity)]
+    pub async fn get_data_shared<P>(
+        self,
+        path: P,
+    ) -> Result<Option<(oneshot::Receiver<WatchedEvent>, Arc<[u8]>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
         let (tx, rx) = oneshot::channel();
-        self.0
-            .get_data_w(path, Watch::Custom(tx))
-            .map(|r| (r.0, r.1.map(move |(b, s)| (rx, b, s))))
+        let watch = Watch::Custom(tx, self.0.namespace.clone());
+        let r = self.0.get_data_w(&path, watch).await?;
+        Ok(r.map(move |(b, s)| (rx, Arc::from(&b[..]), s)))
     }
 }
 
 /// Proxy for [`ZooKeeper`] that batches operations into an atomic "multi" request.
 #[derive(Debug)]
-pub struct MultiBuilder {
-    zk: ZooKeeper,
+pub struct MultiBuilder<'a> {
+    zk: &'a ZooKeeper,
     requests: Vec<proto::Request>,
+    // the first path that was not valid, which fails the whole request
+    invalid: Option<Error>,
 }
 
-impl MultiBuilder {
+impl MultiBuilder<'_> {
+    /// Resolve `path` within the namespace, or remember why it is not a valid path.
+    fn path<P>(&mut self, path: P) -> Option<String>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        match path.try_into() {
+            Ok(path) => Some(self.zk.namespace.resolve(&path)),
+            Err(e) => {
+                self.invalid.get_or_insert(e.into());
+                None
+            }
+        }
+    }
+
     /// Attach a create operation to this multi request.
     ///
     /// See [`ZooKeeper::create`] for details.
-    pub fn create<D, A>(mut self, path: &str, data: D, acl: A, mode: CreateMode) -> Self
+    pub fn create<P, D, A>(mut self, path: P, data: D, acl: A, mode: CreateMode) -> Self
     where
+        P: AsRef<str>,
         D: Into<Cow<'static, [u8]>>,
         A: Into<Cow<'static, [Acl]>>,
     {
         self.requests.push(proto::Request::Create {
-            path: path.to_string(),
+            path: self.zk.namespace.resolve(path.as_ref()),
             data: data.into(),
             acl: acl.into(),
-            mode: mode,
+            mode,
+            with_stat: false,
         });
         self
     }
@@ -794,523 +2166,1407 @@ impl MultiBuilder {
     /// Attach a set data operation to this multi request.
     ///
     /// See [`ZooKeeper::set_data`] for details.
-    pub fn set_data<D>(mut self, path: &str, version: Option<i32>, data: D) -> Self
+    pub fn set_data<P, D>(mut self, path: P, version: Option<i32>, data: D) -> Self
     where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
         D: Into<Cow<'static, [u8]>>,
     {
+        let path = match self.path(path) {
+            Some(path) => path,
+            None => return self,
+        };
         self.requests.push(proto::Request::SetData {
-            path: path.to_string(),
-            version: version.unwrap_or(-1),
+            path,
+            version: version.unwrap_or(ANY_VERSION),
             data: data.into(),
         });
         self
     }
 
+    /// Attach a set data operation that matches any version to this multi request.
+    ///
+    /// See [`ZooKeeper::set_data_any`] for details.
+    pub fn set_data_any<P, D>(self, path: P, data: D) -> Self
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+        D: Into<Cow<'static, [u8]>>,
+    {
+        self.set_data(path, None, data)
+    }
+
     /// Attach a delete operation to this multi request.
     ///
     /// See [`ZooKeeper::delete`] for details.
-    pub fn delete(mut self, path: &str, version: Option<i32>) -> Self {
+    pub fn delete<P>(mut self, path: P, version: Option<i32>) -> Self
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = match self.path(path) {
+            Some(path) => path,
+            None => return self,
+        };
         self.requests.push(proto::Request::Delete {
-            path: path.to_string(),
-            version: version.unwrap_or(-1),
+            path,
+            version: version.unwrap_or(ANY_VERSION),
         });
         self
     }
 
+    /// Attach a delete operation that matches any version to this multi request.
+    ///
+    /// See [`ZooKeeper::delete_any`] for details.
+    pub fn delete_any<P>(self, path: P) -> Self
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        self.delete(path, None)
+    }
+
     /// Attach a check operation to this multi request.
     ///
     /// There is no equivalent to the check operation outside of a multi
     /// request.
-    pub fn check(mut self, path: &str, version: i32) -> Self {
+    pub fn check<P>(mut self, path: P, version: i32) -> Self
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = match self.path(path) {
+            Some(path) => path,
+            None => return self,
+        };
         self.requests.push(proto::Request::Check {
-            path: path.to_string(),
+            path,
             version,
         });
         self
     }
 
     /// Run executes the attached requests in one atomic unit.
-    pub fn run(
-        self,
-    ) -> impl Future<Item = (ZooKeeper, Vec<Result<MultiResponse, error::Multi>>), Error = failure::Error>
-    {
+    pub async fn run(self) -> Result<Vec<Result<MultiResponse, error::Multi>>, Error> {
         let (zk, requests) = (self.zk, self.requests);
-        let reqs_lite: Vec<transform::RequestMarker> = requests.iter().map(|r| r.into()).collect();
-        zk.connection
-            .enqueue(proto::Request::Multi(requests))
-            .and_then(move |r| match r {
-                Ok(proto::Response::Multi(responses)) => reqs_lite
-                    .iter()
-                    .zip(responses)
-                    .map(|(req, res)| transform::multi(req, res))
-                    .collect(),
-                Ok(r) => bail!("got non-multi response to multi: {:?}", r),
-                Err(e) => Err(format_err!("multi call failed: {:?}", e)),
-            })
-            .map(move |r| (zk, r))
+        if let Some(e) = self.invalid {
+            return Err(e);
+        }
+        let containers = requests.iter().any(|r| match *r {
+            proto::Request::Create { mode, .. } => mode == CreateMode::Container,
+            _ => false,
+        });
+        if containers && !zk.capabilities().await.containers() {
+            return Err(capabilities::unsupported());
+        }
+        let reqs_lite: Vec<_> = requests
+            .iter()
+            .map(|r| transform::RequestMarker::new(r, &zk.namespace))
+            .collect();
+        match zk.enqueue(proto::Request::Multi(requests)).await? {
+            Ok(proto::Response::Multi(responses)) => reqs_lite
+                .iter()
+                .zip(responses)
+                // the server does not say more about the operations than their errors
+                .map(|(req, res)| match transform::multi(req, res.map_err(|e| (e, None)))? {
+                    Ok(MultiResponse::Create(path)) => {
+                        Ok(Ok(MultiResponse::Create(zk.namespace.strip(&path))))
+                    }
+                    res => Ok(res),
+                })
+                .collect(),
+            Ok(r) => Err(Error::Protocol(format!(
+                "got non-multi response to multi: {:?}",
+                r
+            ))),
+            Err((e, context)) => Err(Error::server(e, context)),
+        }
+    }
+
+    /// Execute the attached requests in one atomic unit, and report the outcome of the request as
+    /// a whole.
+    ///
+    /// Unlike [`run`](MultiBuilder::run), this tells a request that succeeded, in which case the
+    /// responses of all operations are returned, apart from one that failed, in which case
+    /// [`error::MultiFailed`] names the operation that caused the failure.
+    pub async fn commit(self) -> Result<Result<Vec<MultiResponse>, error::MultiFailed>, Error> {
+        let results = self.run().await?;
+        if results.iter().all(Result::is_ok) {
+            let responses = results.into_iter().filter_map(Result::ok).collect();
+            return Ok(Ok(responses));
+        }
+        let index = results.iter().position(|r| match *r {
+            Ok(_) | Err(error::Multi::RolledBack) | Err(error::Multi::Skipped) => false,
+            Err(_) => true,
+        });
+        match index {
+            Some(index) => Ok(Err(error::MultiFailed { index, results })),
+            None => Err(Error::Protocol(
+                "multi failed without reporting which operation failed".to_string(),
+            )),
+        }
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "slog"))]
 mod tests {
     use super::*;
 
     use slog::Drain;
 
-    #[test]
-    fn it_works() {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
+    #[tokio::test]
+    async fn it_works() {
         let mut builder = ZooKeeperBuilder::default();
         let decorator = slog_term::TermDecorator::new().build();
         let drain = slog_term::FullFormat::new(decorator).build().fuse();
         let drain = slog_async::Async::new(drain).build().fuse();
         builder.set_logger(slog::Logger::root(drain, o!()));
 
-        let (zk, w): (ZooKeeper, _) =
-            rt.block_on(
-                builder
-                    .connect(&"127.0.0.1:2181".parse().unwrap())
-                    .and_then(|(zk, w)| {
-                        zk.with_watcher()
-                            .exists("/foo")
-                            .inspect(|(_, _, stat)| assert_eq!(stat, &None))
-                            .and_then(|(zk, exists_w, _)| {
-                                zk.watch()
-                                    .exists("/foo")
-                                    .map(move |(zk, x)| (zk, x, exists_w))
-                            })
-                            .inspect(|(_, stat, _)| assert_eq!(stat, &None))
-                            .and_then(|(zk, _, exists_w)| {
-                                zk.create(
-                                    "/foo",
-                                    &b"Hello world"[..],
-                                    Acl::open_unsafe(),
-                                    CreateMode::Persistent,
-                                ).map(move |(zk, x)| (zk, x, exists_w))
-                            })
-                            .inspect(|(_, ref path, _)| {
-                                assert_eq!(path.as_ref().map(String::as_str), Ok("/foo"))
-                            })
-                            .and_then(move |(zk, _, exists_w)| {
-                                exists_w
-                                    .map(move |w| (zk, w))
-                                    .map_err(|e| format_err!("exists_w failed: {:?}", e))
-                            })
-                            .inspect(|(_, event)| {
-                                assert_eq!(
-                                    event,
-                                    &WatchedEvent {
-                                        event_type: WatchedEventType::NodeCreated,
-                                        keeper_state: KeeperState::SyncConnected,
-                                        path: String::from("/foo"),
-                                    }
-                                );
-                            })
-                            .and_then(|(zk, _)| zk.watch().exists("/foo"))
-                            .inspect(|(_, stat)| {
-                                assert_eq!(stat.unwrap().data_length as usize, b"Hello world".len())
-                            })
-                            .and_then(|(zk, _)| zk.get_acl("/foo"))
-                            .inspect(|(_, res)| {
-                                let res = res.as_ref().unwrap();
-                                assert_eq!(res.0, Acl::open_unsafe())
-                            })
-                            .and_then(|(zk, _)| zk.get_data("/foo"))
-                            .inspect(|(_, res)| {
-                                let data = b"Hello world";
-                                let res = res.as_ref().unwrap();
-                                assert_eq!(res.0, data);
-                                assert_eq!(res.1.data_length as usize, data.len());
-                            })
-                            .and_then(|(zk, res)| {
-                                zk.set_data("/foo", Some(res.unwrap().1.version), &b"Bye world"[..])
-                            })
-                            .inspect(|(_, stat)| {
-                                assert_eq!(stat.unwrap().data_length as usize, "Bye world".len());
-                            })
-                            .and_then(|(zk, _)| zk.get_data("/foo"))
-                            .inspect(|(_, res)| {
-                                let data = b"Bye world";
-                                let res = res.as_ref().unwrap();
-                                assert_eq!(res.0, data);
-                                assert_eq!(res.1.data_length as usize, data.len());
-                            })
-                            .and_then(|(zk, _)| {
-                                zk.create(
-                                    "/foo/bar",
-                                    &b"Hello bar"[..],
-                                    Acl::open_unsafe(),
-                                    CreateMode::Persistent,
-                                )
-                            })
-                            .inspect(|(_, ref path)| {
-                                assert_eq!(path.as_ref().map(String::as_str), Ok("/foo/bar"))
-                            })
-                            .and_then(|(zk, _)| zk.get_children("/foo"))
-                            .inspect(|(_, children)| {
-                                assert_eq!(children, &Some(vec!["bar".to_string()]));
-                            })
-                            .and_then(|(zk, _)| zk.get_data("/foo/bar"))
-                            .inspect(|(_, res)| {
-                                let data = b"Hello bar";
-                                let res = res.as_ref().unwrap();
-                                assert_eq!(res.0, data);
-                                assert_eq!(res.1.data_length as usize, data.len());
-                            })
-                            .and_then(|(zk, _)| {
-                                // add a new exists watch so we'll get notified of delete
-                                zk.watch().exists("/foo")
-                            })
-                            .and_then(|(zk, _)| zk.delete("/foo", None))
-                            .inspect(|(_, res)| assert_eq!(res, &Err(error::Delete::NotEmpty)))
-                            .and_then(|(zk, _)| zk.delete("/foo/bar", None))
-                            .inspect(|(_, res)| assert_eq!(res, &Ok(())))
-                            .and_then(|(zk, _)| zk.delete("/foo", None))
-                            .inspect(|(_, res)| assert_eq!(res, &Ok(())))
-                            .and_then(|(zk, _)| zk.watch().exists("/foo"))
-                            .inspect(|(_, stat)| assert_eq!(stat, &None))
-                            .and_then(move |(zk, _)| {
-                                w.into_future()
-                                    .map(move |x| (zk, x))
-                                    .map_err(|e| format_err!("stream error: {:?}", e.0))
-                            })
-                            .inspect(|(_, (event, _))| {
-                                assert_eq!(
-                                    event,
-                                    &Some(WatchedEvent {
-                                        event_type: WatchedEventType::NodeCreated,
-                                        keeper_state: KeeperState::SyncConnected,
-                                        path: String::from("/foo"),
-                                    })
-                                );
-                            })
-                            .and_then(|(zk, (_, w))| {
-                                w.into_future()
-                                    .map(move |x| (zk, x))
-                                    .map_err(|e| format_err!("stream error: {:?}", e.0))
-                            })
-                            .and_then(|(zk, (event, w))| {
-                                assert_eq!(
-                                    event,
-                                    Some(WatchedEvent {
-                                        event_type: WatchedEventType::NodeDataChanged,
-                                        keeper_state: KeeperState::SyncConnected,
-                                        path: String::from("/foo"),
-                                    })
-                                );
-
-                                w.into_future()
-                                    .map(move |x| (zk, x))
-                                    .map_err(|e| format_err!("stream error: {:?}", e.0))
-                            })
-                            .inspect(|(_, (event, _))| {
-                                assert_eq!(
-                                    event,
-                                    &Some(WatchedEvent {
-                                        event_type: WatchedEventType::NodeDeleted,
-                                        keeper_state: KeeperState::SyncConnected,
-                                        path: String::from("/foo"),
-                                    })
-                                );
-                            })
-                            .map(|(zk, (_, w))| (zk, w))
-                    }),
-            ).unwrap();
+        let (zk, mut w) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let (exists_w, stat) = zk.with_watcher().exists("/foo").await.unwrap();
+        assert_eq!(stat, None);
+        let stat = zk.watch().exists("/foo").await.unwrap();
+        assert_eq!(stat, None);
+        let path = zk
+            .create(
+                "/foo",
+                &b"Hello world"[..],
+                Acl::open_unsafe(),
+                CreateMode::Persistent,
+            )
+            .await
+            .unwrap();
+        assert_eq!(path.as_ref().map(String::as_str), Ok("/foo"));
+        let event = exists_w.await.expect("exists_w failed");
+        assert_eq!(
+            event,
+            WatchedEvent {
+                event_type: WatchedEventType::NodeCreated,
+                keeper_state: KeeperState::SyncConnected,
+                path: String::from("/foo"),
+            }
+        );
+        let stat = zk.watch().exists("/foo").await.unwrap();
+        assert_eq!(stat.unwrap().data_length as usize, b"Hello world".len());
+        let res = zk.get_acl("/foo").await.unwrap();
+        assert_eq!(res.unwrap().0, Acl::open_unsafe());
+        let res = zk.get_data("/foo").await.unwrap();
+        let data = b"Hello world";
+        let res = res.unwrap();
+        assert_eq!(res.0, data);
+        assert_eq!(res.1.data_length as usize, data.len());
+        let stat = zk
+            .set_data("/foo", Some(res.1.version), &b"Bye world"[..])
+            .await
+            .unwrap();
+        assert_eq!(stat.unwrap().data_length as usize, "Bye world".len());
+        let res = zk.get_data("/foo").await.unwrap();
+        let data = b"Bye world";
+        let res = res.unwrap();
+        assert_eq!(res.0, data);
+        assert_eq!(res.1.data_length as usize, data.len());
+        let path = zk
+            .create(
+                "/foo/bar",
+                &b"Hello bar"[..],
+                Acl::open_unsafe(),
+                CreateMode::Persistent,
+            )
+            .await
+            .unwrap();
+        assert_eq!(path.as_ref().map(String::as_str), Ok("/foo/bar"));
+        let children = zk.get_children("/foo").await.unwrap();
+        assert_eq!(children, Some(vec!["bar".to_string()]));
+        let res = zk.get_data("/foo/bar").await.unwrap();
+        let data = b"Hello bar";
+        let res = res.unwrap();
+        assert_eq!(res.0, data);
+        assert_eq!(res.1.data_length as usize, data.len());
+        // add a new exists watch so we'll get notified of delete
+        let _ = zk.watch().exists("/foo").await.unwrap();
+        let res = zk.delete("/foo", None).await.unwrap();
+        assert_eq!(res, Err(error::Delete::NotEmpty { path: "/foo".into() }));
+        let res = zk.delete("/foo/bar", None).await.unwrap();
+        assert_eq!(res, Ok(()));
+        let res = zk.delete("/foo", None).await.unwrap();
+        assert_eq!(res, Ok(()));
+        let stat = zk.watch().exists("/foo").await.unwrap();
+        assert_eq!(stat, None);
+        let event = w.next().await;
+        assert_eq!(
+            event,
+            Some(WatchedEvent {
+                event_type: WatchedEventType::NodeCreated,
+                keeper_state: KeeperState::SyncConnected,
+                path: String::from("/foo"),
+            })
+        );
+        let event = w.next().await;
+        assert_eq!(
+            event,
+            Some(WatchedEvent {
+                event_type: WatchedEventType::NodeDataChanged,
+                keeper_state: KeeperState::SyncConnected,
+                path: String::from("/foo"),
+            })
+        );
+        let event = w.next().await;
+        assert_eq!(
+            event,
+            Some(WatchedEvent {
+                event_type: WatchedEventType::NodeDeleted,
+                keeper_state: KeeperState::SyncConnected,
+                path: String::from("/foo"),
+            })
+        );
 
         drop(zk); // make Packetizer idle
-        rt.shutdown_on_idle().wait().unwrap();
-        assert_eq!(w.wait().count(), 0);
+        assert_eq!(w.count().await, 0);
     }
 
-    #[test]
-    fn example() {
-        tokio::run(
-            ZooKeeper::connect(&"127.0.0.1:2181".parse().unwrap())
-                .and_then(|(zk, default_watcher)| {
-                    // let's first check if /example exists. the .watch() causes us to be notified
-                    // the next time the "exists" status of /example changes after the call.
-                    zk.watch()
-                        .exists("/example")
-                        .inspect(|(_, stat)| {
-                            // initially, /example does not exist
-                            assert_eq!(stat, &None)
-                        })
-                        .and_then(|(zk, _)| {
-                            // so let's make it!
-                            zk.create(
-                                "/example",
-                                &b"Hello world"[..],
-                                Acl::open_unsafe(),
-                                CreateMode::Persistent,
-                            )
-                        })
-                        .inspect(|(_, ref path)| {
-                            assert_eq!(path.as_ref().map(String::as_str), Ok("/example"))
-                        })
-                        .and_then(|(zk, _)| {
-                            // does it exist now?
-                            zk.watch().exists("/example")
-                        })
-                        .inspect(|(_, stat)| {
-                            // looks like it!
-                            // note that the creation above also triggered our "exists" watch!
-                            assert_eq!(stat.unwrap().data_length as usize, b"Hello world".len())
-                        })
-                        .and_then(|(zk, _)| {
-                            // did the data get set correctly?
-                            zk.get_data("/example")
-                        })
-                        .inspect(|(_, res)| {
-                            let data = b"Hello world";
-                            let res = res.as_ref().unwrap();
-                            assert_eq!(res.0, data);
-                            assert_eq!(res.1.data_length as usize, data.len());
-                        })
-                        .and_then(|(zk, res)| {
-                            // let's update the data.
-                            zk.set_data("/example", Some(res.unwrap().1.version), &b"Bye world"[..])
-                        })
-                        .inspect(|(_, stat)| {
-                            assert_eq!(stat.unwrap().data_length as usize, "Bye world".len());
-                        })
-                        .and_then(|(zk, _)| {
-                            // create a child of /example
-                            zk.create(
-                                "/example/more",
-                                &b"Hello more"[..],
-                                Acl::open_unsafe(),
-                                CreateMode::Persistent,
-                            )
-                        })
-                        .inspect(|(_, ref path)| {
-                            assert_eq!(path.as_ref().map(String::as_str), Ok("/example/more"))
-                        })
-                        .and_then(|(zk, _)| {
-                            // it should be visible as a child of /example
-                            zk.get_children("/example")
-                        })
-                        .inspect(|(_, children)| {
-                            assert_eq!(children, &Some(vec!["more".to_string()]));
-                        })
-                        .and_then(|(zk, _)| {
-                            // it is not legal to delete a node that has children directly
-                            zk.delete("/example", None)
-                        })
-                        .inspect(|(_, res)| assert_eq!(res, &Err(error::Delete::NotEmpty)))
-                        .and_then(|(zk, _)| {
-                            // instead we must delete the children first
-                            zk.delete("/example/more", None)
-                        })
-                        .inspect(|(_, res)| assert_eq!(res, &Ok(())))
-                        .and_then(|(zk, _)| zk.delete("/example", None))
-                        .inspect(|(_, res)| assert_eq!(res, &Ok(())))
-                        .and_then(|(zk, _)| {
-                            // no /example should no longer exist!
-                            zk.exists("/example")
-                        })
-                        .inspect(|(_, stat)| assert_eq!(stat, &None))
-                        .and_then(move |(zk, _)| {
-                            // now let's check that the .watch().exists we did in the very
-                            // beginning actually triggered!
-                            default_watcher
-                                .into_future()
-                                .map(move |x| (zk, x))
-                                .map_err(|e| format_err!("stream error: {:?}", e.0))
-                        })
-                        .inspect(|(_, (event, _))| {
-                            assert_eq!(
-                                event,
-                                &Some(WatchedEvent {
-                                    event_type: WatchedEventType::NodeCreated,
-                                    keeper_state: KeeperState::SyncConnected,
-                                    path: String::from("/example"),
-                                })
-                            );
-                        })
-                })
-                .map(|_| ())
-                .map_err(|e| panic!("{:?}", e)),
-        );
-    }
+    #[tokio::test]
+    async fn example() {
+        let (zk, mut default_watcher) = ZooKeeper::connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
 
-    #[test]
-    fn acl_test() {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
-        let mut builder = ZooKeeperBuilder::default();
-        let decorator = slog_term::TermDecorator::new().build();
-        let drain = slog_term::FullFormat::new(decorator).build().fuse();
-        let drain = slog_async::Async::new(drain).build().fuse();
-        builder.set_logger(slog::Logger::root(drain, o!()));
+        // let's first check if /example exists. the .watch() causes us to be notified
+        // the next time the "exists" status of /example changes after the call.
+        let stat = zk.watch().exists("/example").await.unwrap();
+        // initially, /example does not exist
+        assert_eq!(stat, None);
+        // so let's make it!
+        let path = zk
+            .create(
+                "/example",
+                &b"Hello world"[..],
+                Acl::open_unsafe(),
+                CreateMode::Persistent,
+            )
+            .await
+            .unwrap();
+        assert_eq!(path.as_ref().map(String::as_str), Ok("/example"));
 
-        let (zk, _): (ZooKeeper, _) =
-            rt.block_on(
-                builder
-                    .connect(&"127.0.0.1:2181".parse().unwrap())
-                    .and_then(|(zk, _)| {
-                        zk.create(
-                            "/acl_test",
-                            &b"foo"[..],
-                            Acl::open_unsafe(),
-                            CreateMode::Ephemeral,
-                        ).and_then(|(zk, _)| zk.get_acl("/acl_test"))
-                            .inspect(|(_, res)| {
-                                let res = res.as_ref().unwrap();
-                                assert_eq!(res.0, Acl::open_unsafe())
-                            })
-                            .and_then(|(zk, res)| {
-                                zk.set_acl(
-                                    "/acl_test",
-                                    Acl::creator_all(),
-                                    Some(res.unwrap().1.version),
-                                )
-                            })
-                            .inspect(|(_, res)| {
-                                // a not authenticated user is not able to set `auth` scheme acls.
-                                assert_eq!(res, &Err(error::SetAcl::InvalidAcl))
-                            })
-                            .and_then(|(zk, _)| zk.set_acl("/acl_test", Acl::read_unsafe(), None))
-                            .inspect(|(_, stat)| {
-                                // successfully change node acl to `read_unsafe`
-                                assert_eq!(stat.unwrap().data_length as usize, b"foo".len())
-                            })
-                            .and_then(|(zk, _)| zk.get_acl("/acl_test"))
-                            .inspect(|(_, res)| {
-                                let res = res.as_ref().unwrap();
-                                assert_eq!(res.0, Acl::read_unsafe())
-                            })
-                            .and_then(|(zk, _)| zk.set_data("/acl_test", None, &b"bar"[..]))
-                            .inspect(|(_, res)| {
-                                // cannot set data on a read only node
-                                assert_eq!(res, &Err(error::SetData::NoAuth))
-                            })
-                            .and_then(|(zk, _)| zk.set_acl("/acl_test", Acl::open_unsafe(), None))
-                            .inspect(|(_, res)| {
-                                // cannot change a read only node's acl
-                                assert_eq!(res, &Err(error::SetAcl::NoAuth))
-                            })
-                    }),
-            ).unwrap();
+        // does it exist now?
+        let stat = zk.watch().exists("/example").await.unwrap();
+        // looks like it!
+        // note that the creation above also triggered our "exists" watch!
+        assert_eq!(stat.unwrap().data_length as usize, b"Hello world".len());
 
-        drop(zk); // make Packetizer idle
-        rt.shutdown_on_idle().wait().unwrap();
-    }
+        // did the data get set correctly?
+        let res = zk.get_data("/example").await.unwrap();
+        let data = b"Hello world";
+        let res = res.unwrap();
+        assert_eq!(res.0, data);
+        assert_eq!(res.1.data_length as usize, data.len());
 
+        // let's update the data.
+        let stat = zk
+            .set_data("/example", Some(res.1.version), &b"Bye world"[..])
+            .await
+            .unwrap();
+        assert_eq!(stat.unwrap().data_length as usize, "Bye world".len());
+
+        // create a child of /example
+        let path = zk
+            .create(
+                "/example/more",
+                &b"Hello more"[..],
+                Acl::open_unsafe(),
+                CreateMode::Persistent,
+            )
+            .await
+            .unwrap();
+        assert_eq!(path.as_ref().map(String::as_str), Ok("/example/more"));
+
+        // it should be visible as a child of /example
+        let children = zk.get_children("/example").await.unwrap();
+        assert_eq!(children, Some(vec!["more".to_string()]));
+
+        // it is not legal to delete a node that has children directly
+        let res = zk.delete("/example", None).await.unwrap();
+        assert_eq!(res, Err(error::Delete::NotEmpty { path: "/example".into() }));
+        // instead we must delete the children first
+        let res = zk.delete("/example/more", None).await.unwrap();
+        assert_eq!(res, Ok(()));
+        let res = zk.delete("/example", None).await.unwrap();
+        assert_eq!(res, Ok(()));
+        // no /example should no longer exist!
+        let stat = zk.exists("/example").await.unwrap();
+        assert_eq!(stat, None);
+
+        // now let's check that the .watch().exists we did in the very
+        // beginning actually triggered!
+        let event = default_watcher.next().await;
+        assert_eq!(
+            event,
+            Some(WatchedEvent {
+                event_type: WatchedEventType::NodeCreated,
+                keeper_state: KeeperState::SyncConnected,
+                path: String::from("/example"),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn acl_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.create(
+            "/acl_test",
+            &b"foo"[..],
+            Acl::open_unsafe(),
+            CreateMode::Ephemeral,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let res = zk.get_acl("/acl_test").await.unwrap();
+        let res = res.unwrap();
+        assert_eq!(res.0, Acl::open_unsafe());
+        let res = zk
+            .set_acl("/acl_test", Acl::creator_all(), Some(res.1.version))
+            .await
+            .unwrap();
+        // a not authenticated user is not able to set `auth` scheme acls.
+        assert_eq!(res, Err(error::SetAcl::InvalidAcl { path: "/acl_test".into() }));
+        let stat = zk
+            .set_acl("/acl_test", Acl::read_unsafe(), None)
+            .await
+            .unwrap();
+        // successfully change node acl to `read_unsafe`
+        assert_eq!(stat.unwrap().data_length as usize, b"foo".len());
+        let res = zk.get_acl("/acl_test").await.unwrap();
+        assert_eq!(res.unwrap().0, Acl::read_unsafe());
+        let res = zk.set_data("/acl_test", None, &b"bar"[..]).await.unwrap();
+        // cannot set data on a read only node
+        assert_eq!(res, Err(error::SetData::NoAuth { path: "/acl_test".into() }));
+        let res = zk
+            .set_acl("/acl_test", Acl::open_unsafe(), None)
+            .await
+            .unwrap();
+        // cannot change a read only node's acl
+        assert_eq!(res, Err(error::SetAcl::NoAuth { path: "/acl_test".into() }));
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn create_or_set_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let res = zk
+            .create_or_set(
+                "/create_or_set_test",
+                &b"foo"[..],
+                Acl::open_unsafe(),
+                CreateMode::Ephemeral,
+            )
+            .await
+            .unwrap();
+        let (upsert, stat) = res.unwrap();
+        assert_eq!(upsert, Upsert::Created);
+        assert_eq!(stat.version, 0);
+        assert_eq!(stat.data_length as usize, b"foo".len());
+        let res = zk
+            .create_or_set(
+                "/create_or_set_test",
+                &b"barbaz"[..],
+                Acl::open_unsafe(),
+                CreateMode::Ephemeral,
+            )
+            .await
+            .unwrap();
+        let (upsert, stat) = res.unwrap();
+        assert_eq!(upsert, Upsert::Updated);
+        assert_eq!(stat.version, 1);
+        assert_eq!(stat.data_length as usize, b"barbaz".len());
+        let res = zk
+            .create_or_set(
+                "/create_or_set_test_missing/child",
+                &b"foo"[..],
+                Acl::open_unsafe(),
+                CreateMode::Ephemeral,
+            )
+            .await
+            .unwrap();
+        let path = "/create_or_set_test_missing/child".into();
+        assert_eq!(res, Err(error::CreateOrSet::NoNode { path }));
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn compare_and_swap_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let increment = |data: &[u8]| {
+            let n: u32 = std::str::from_utf8(data).unwrap().parse().unwrap();
+            (n + 1).to_string().into_bytes()
+        };
+        let no_retries = retry::BoundedRetries {
+            retries: 0,
+            delay: time::Duration::ZERO,
+        };
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let res = zk.compare_and_swap("/cas_test", no_retries, increment).await.unwrap();
+        assert_eq!(res, Err(error::CompareAndSwap::NoNode { path: "/cas_test".into() }));
+        zk.create(
+            "/cas_test",
+            &b"41"[..],
+            Acl::open_unsafe(),
+            CreateMode::Ephemeral,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let res = zk.compare_and_swap("/cas_test", no_retries, increment).await.unwrap();
+        assert_eq!(res.unwrap().version, 1);
+        let res = zk.get_data("/cas_test").await.unwrap();
+        assert_eq!(res.unwrap().0, b"42");
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn exists_watch_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        assert!(!zk.exists_bool("/exists_watch_test").await.unwrap());
+        let (stat, w) = zk.exists_watch("/exists_watch_test").await.unwrap();
+        assert_eq!(stat, None);
+        zk.create(
+            "/exists_watch_test",
+            &b""[..],
+            Acl::open_unsafe(),
+            CreateMode::Ephemeral,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        let event = w.await.unwrap();
+        assert_eq!(event.event_type, WatchedEventType::NodeCreated);
+        assert_eq!(event.path, "/exists_watch_test");
+        assert!(zk.exists_bool("/exists_watch_test").await.unwrap());
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn get_data_many_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.multi()
+            .create(
+                "/many_a",
+                &b"a"[..],
+                Acl::open_unsafe(),
+                CreateMode::Ephemeral,
+            )
+            .create(
+                "/many_b",
+                &b"b"[..],
+                Acl::open_unsafe(),
+                CreateMode::Ephemeral,
+            )
+            .run()
+            .await
+            .unwrap();
+        let res = zk
+            .get_data_many(vec!["/many_b", "/many_missing", "/many_a"], 2)
+            .await
+            .unwrap();
+        let data: Vec<_> = res
+            .iter()
+            .map(|r| r.as_ref().map(|(data, _)| &data[..]))
+            .collect();
+        assert_eq!(data, vec![Some(&b"b"[..]), None, Some(&b"a"[..])]);
+        let res = zk.get_data_shared("/many_a").await.unwrap();
+        let (data, _) = res.unwrap();
+        let copy = Arc::clone(&data);
+        assert_eq!(&copy[..], b"a");
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn create_many_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let entry = |path| (path, &b""[..], Acl::open_unsafe(), CreateMode::Ephemeral);
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let res = zk
+            .create_many(vec![
+                entry("/create_many_a"),
+                entry("/create_many_missing/child"),
+                entry("/create_many_b"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            [
+                Ok("/create_many_a".to_string()),
+                Err(error::Create::NoNode { path: "/create_many_missing/child".into() }),
+                Ok("/create_many_b".to_string()),
+            ]
+        );
+        assert!(zk.exists_bool("/create_many_b").await.unwrap());
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn copy_subtree_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.multi()
+            .create("/cps", &b"1"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/cps/a", &b"2"[..], Acl::read_unsafe(), CreateMode::Persistent)
+            .create("/cps/e", &b""[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+            .run()
+            .await
+            .unwrap();
+        let res = zk.copy_subtree("/cps", "/cps/x", Default::default()).await;
+        assert_eq!(res.unwrap(), Err(error::CopySubtree::Overlapping));
+        let res = zk.copy_subtree("/cps", "/cpd", Default::default()).await;
+        assert_eq!(res.unwrap(), Ok(2));
+        let paths = zk.list_subtree("/cpd").await.unwrap();
+        assert_eq!(paths, Some(vec!["/cpd".into(), "/cpd/a".into()]));
+        let res = zk.get_data("/cpd/a").await.unwrap();
+        assert_eq!(res.unwrap().0, b"2");
+        let res = zk.get_acl("/cpd/a").await.unwrap();
+        assert_eq!(res.unwrap().0, Acl::read_unsafe());
+        let res = zk.copy_subtree("/cps", "/cpd", Default::default()).await;
+        assert_eq!(
+            res.unwrap(),
+            Err(error::CopySubtree::Create {
+                path: "/cpd".into(),
+                error: error::Create::NodeExists { path: "/cpd".into() },
+            })
+        );
+        zk.multi()
+            .delete("/cpd/a", None)
+            .delete("/cpd", None)
+            .delete("/cps/a", None)
+            .delete("/cps/e", None)
+            .delete("/cps", None)
+            .run()
+            .await
+            .unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn move_subtree_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let transactional = MoveOptions {
+            transactional: true,
+            ..Default::default()
+        };
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.multi()
+            .create("/ms", &b"1"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/ms/a", &b"2"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/ms/a/b", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        let res = zk.move_subtree("/ms", "/ms/a/x", Default::default()).await;
+        assert_eq!(res.unwrap(), Err(error::MoveSubtree::Overlapping));
+        let res = zk.move_subtree("/ms", "/md", transactional).await;
+        assert_eq!(res.unwrap(), Ok(3));
+        assert!(!zk.exists_bool("/ms").await.unwrap());
+        let res = zk.get_data("/md/a").await.unwrap();
+        assert_eq!(res.unwrap().0, b"2");
+        zk.create("/md/e", &b""[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+            .await
+            .unwrap()
+            .unwrap();
+        let res = zk.move_subtree("/md", "/ms", Default::default()).await;
+        assert_eq!(
+            res.unwrap(),
+            Err(error::MoveSubtree::NotEmpty {
+                path: "/md".into()
+            })
+        );
+        let paths = zk.list_subtree("/md").await.unwrap();
+        assert_eq!(paths, Some(vec!["/md".into(), "/md/e".into()]));
+        zk.multi()
+            .delete("/md/e", None)
+            .delete("/md", None)
+            .delete("/ms/a/b", None)
+            .delete("/ms/a", None)
+            .delete("/ms", None)
+            .run()
+            .await
+            .unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn diff_subtrees_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.multi()
+            .create("/dfa", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/dfa/x", &b"1"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/dfa/y", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/dfb", &b""[..], Acl::read_unsafe(), CreateMode::Persistent)
+            .create("/dfb/x", &b"2"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/dfb/z", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        let diff = zk.diff_subtrees("/dfa", "/dfa", true).await.unwrap();
+        assert!(diff.is_empty());
+        let diff = zk.diff_subtrees("/dfa", "/dfb", false).await.unwrap();
+        assert_eq!(diff.added, ["/z"]);
+        assert_eq!(diff.removed, ["/y"]);
+        assert_eq!(diff.modified, ["/x"]);
+        let diff = zk.diff_subtrees("/dfa", "/dfb", true).await.unwrap();
+        assert_eq!(diff.modified, ["/", "/x"]);
+        let diff = zk.diff_subtrees("/dfa/y", "/dfa/nope", false).await.unwrap();
+        assert_eq!(diff.removed, ["/"]);
+        zk.multi()
+            .delete("/dfa/x", None)
+            .delete("/dfa/y", None)
+            .delete("/dfa", None)
+            .delete("/dfb/x", None)
+            .delete("/dfb/z", None)
+            .delete("/dfb", None)
+            .run()
+            .await
+            .unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn set_acl_recursive_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let dry_run = AclUpdateOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.multi()
+            .create("/sar", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/sar/a", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/sar/b", &b""[..], Acl::read_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        let res = zk
+            .set_acl_recursive("/nope", Acl::read_unsafe(), dry_run)
+            .await
+            .unwrap();
+        assert_eq!(res, None);
+        let res = zk
+            .set_acl_recursive("/sar", Acl::read_unsafe(), dry_run)
+            .await
+            .unwrap();
+        let res = res.unwrap();
+        let paths: Vec<_> = res.changed.iter().map(|c| &*c.path).collect();
+        assert_eq!(paths, ["/sar", "/sar/a"]);
+        assert_eq!(res.changed[0].old, Acl::open_unsafe());
+        assert!(res.failed.is_empty());
+        let res = zk.get_acl("/sar/a").await.unwrap();
+        assert_eq!(res.unwrap().0, Acl::open_unsafe());
+        let res = zk
+            .update_acl_recursive(
+                "/sar",
+                |path, acl| {
+                    if path == "/sar" {
+                        acl.to_vec()
+                    } else {
+                        Acl::creator_all().to_vec()
+                    }
+                },
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let res = res.unwrap();
+        let paths: Vec<_> = res.changed.iter().map(|c| &*c.path).collect();
+        assert_eq!(paths, ["/sar/a", "/sar/b"]);
+        let res = zk.get_acl("/sar/b").await.unwrap();
+        assert_eq!(res.unwrap().0, Acl::creator_all());
+        zk.multi()
+            .delete("/sar/a", None)
+            .delete("/sar/b", None)
+            .delete("/sar", None)
+            .run()
+            .await
+            .unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn audit_acls_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.multi()
+            .create("/aud", &b""[..], Acl::read_unsafe(), CreateMode::Persistent)
+            .create("/aud/a", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/aud/b", &b""[..], Acl::read_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        let report = zk.audit_acls("/aud", audit::NoWorldWritable).await.unwrap();
+        let report = report.unwrap();
+        assert_eq!(report.checked, 3);
+        assert_eq!(report.violations.len(), 1);
+        assert_eq!(report.violations[0].path, "/aud/a");
+        assert_eq!(report.violations[0].acl, Acl::open_unsafe());
+        let report = zk.audit_acls("/aud/b", audit::NoWorldWritable).await.unwrap();
+        assert!(report.unwrap().is_clean());
+        zk.multi()
+            .delete("/aud/a", None)
+            .delete("/aud/b", None)
+            .delete("/aud", None)
+            .run()
+            .await
+            .unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn namespace_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.create("/nst", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(zk.using_namespace("nst").is_err());
+        let ns = zk.using_namespace("/nst").unwrap();
+        let res = ns
+            .create("/a", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .await
+            .unwrap();
+        assert_eq!(res.unwrap(), "/a");
+        let (w, stat) = ns.with_watcher().exists("/a").await.unwrap();
+        assert!(stat.is_some());
+        zk.delete("/nst/a", None).await.unwrap().unwrap();
+        let e = w.await.unwrap();
+        assert_eq!(e.event_type, WatchedEventType::NodeDeleted);
+        assert_eq!(e.path, "/a");
+        let res = ns
+            .multi()
+            .create("/b", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(res, [Ok(MultiResponse::Create("/b".into()))]);
+        let children = ns.get_children("/").await.unwrap();
+        assert_eq!(children, Some(vec!["b".to_string()]));
+        ns.delete("/b", None).await.unwrap().unwrap();
+        zk.delete("/nst", None).await.unwrap().unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn create_protected_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.create("/prot", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let res = zk
+            .create_protected(
+                "/prot/lock-",
+                &b""[..],
+                Acl::open_unsafe(),
+                CreateMode::EphemeralSequential,
+            )
+            .await
+            .unwrap();
+        let path = res.unwrap();
+        let name = &path["/prot/".len()..];
+        assert!(name.starts_with(sequential::PROTECTED_PREFIX));
+        assert_eq!(sequential::unprotected(name), "lock-0000000000");
+        let children = zk.get_children("/prot").await.unwrap().unwrap();
+        assert_eq!(children, [name]);
+        let child = format!("/prot/{}", children[0]);
+        zk.delete(&child, None).await.unwrap().unwrap();
+        zk.delete("/prot", None).await.unwrap().unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn delete_guaranteed_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.create("/gdel", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let res = zk.delete_guaranteed("/gdel", Some(1)).await.unwrap();
+        assert_eq!(res, Err(error::Delete::BadVersion { path: "/gdel".into(), expected: 1 }));
+        let res = zk.delete_guaranteed("/gdel", Some(0)).await.unwrap();
+        assert_eq!(res, Ok(()));
+        let res = zk.delete_guaranteed("/gdel", None).await.unwrap();
+        assert_eq!(res, Ok(()));
+        assert!(!zk.exists_bool("/gdel").await.unwrap());
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn batched_flush_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+        builder.set_flush_strategy(FlushStrategy::Batched {
+            delay: time::Duration::from_millis(5),
+            max_bytes: 64 * 1024,
+        });
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        zk.create("/bfl", &b"x"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let res = zk.get_data_many(vec!["/bfl"; 32], 32).await.unwrap();
+        assert_eq!(res.len(), 32);
+        assert!(res.iter().all(|r| r.as_ref().unwrap().0 == b"x"));
+        zk.delete("/bfl", None).await.unwrap().unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn connection_callbacks_test() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let connected = Arc::new(AtomicUsize::new(0));
+        let disconnected = Arc::new(AtomicUsize::new(0));
+        {
+            let connected = connected.clone();
+            builder.on_connected(move || {
+                connected.fetch_add(1, Ordering::SeqCst);
+            });
+            let disconnected = disconnected.clone();
+            builder.on_disconnected(move || {
+                disconnected.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        let (zk, w) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(connected.load(Ordering::SeqCst), 1);
+
+        drop(zk); // make Packetizer idle
+        // the default watcher ends once the packetizer has closed the session
+        assert_eq!(w.count().await, 0);
+        // closing the session is not a disconnect
+        assert_eq!(disconnected.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn connect_with_driver_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _, driver) = builder
+            .connect_with_driver(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let driver = tokio::spawn(driver);
+        assert!(zk.exists("/").await.unwrap().is_some());
+
+        drop(zk); // make Packetizer idle
+        // the driver resolves once the session has been closed
+        driver.await.unwrap().unwrap();
+    }
+
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn async_std_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        async_std::task::block_on(async {
+            let (zk, w) = builder
+                .connect_async_std(&"127.0.0.1:2181".parse().unwrap())
+                .await
+                .unwrap();
+            let res = zk
+                .create("/async_std", &b"a"[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+                .await
+                .unwrap();
+            assert_eq!(res.as_ref().map(String::as_str), Ok("/async_std"));
+            let res = zk.get_data("/async_std").await.unwrap();
+            assert_eq!(res.unwrap().0, b"a");
+
+            drop(zk); // make Packetizer idle
+            assert_eq!(w.count().await, 0);
+        });
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn smol_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        smol::block_on(async {
+            let (zk, w) = builder
+                .connect_smol(&"127.0.0.1:2181".parse().unwrap())
+                .await
+                .unwrap();
+            let res = zk
+                .create("/smol", &b"a"[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+                .await
+                .unwrap();
+            assert_eq!(res.as_ref().map(String::as_str), Ok("/smol"));
+            let res = zk.get_data("/smol").await.unwrap();
+            assert_eq!(res.unwrap().0, b"a");
+
+            drop(zk); // make Packetizer idle
+            assert_eq!(w.count().await, 0);
+        });
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn service_test() {
+        use crate::service::{Request, Response};
+        use tower_service::Service;
+
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (mut zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        future::poll_fn(|cx| zk.poll_ready(cx)).await.unwrap();
+        let res = zk
+            .call(Request::Create {
+                path: "/service".to_string(),
+                data: b"a".to_vec().into(),
+                acl: Acl::open_unsafe().into(),
+                mode: CreateMode::Ephemeral,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res, Response::Create(Ok("/service".to_string())));
+        let res = zk
+            .call(Request::GetData {
+                path: "/service".to_string(),
+            })
+            .await
+            .unwrap();
+        match res {
+            Response::GetData(Some((data, _))) => assert_eq!(data, b"a"),
+            _ => panic!("unexpected response: {:?}", res),
+        }
+        let res = zk
+            .call(Request::Delete {
+                path: "/service".to_string(),
+                version: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(res, Response::Delete(Ok(())));
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[cfg(feature = "blocking")]
     #[test]
-    fn multi_test() {
-        let mut rt = tokio::runtime::Runtime::new().unwrap();
+    fn blocking_test() {
         let mut builder = ZooKeeperBuilder::default();
         let decorator = slog_term::TermDecorator::new().build();
         let drain = slog_term::FullFormat::new(decorator).build().fuse();
         let drain = slog_async::Async::new(drain).build().fuse();
         builder.set_logger(slog::Logger::root(drain, o!()));
 
-        let check_exists = |zk: ZooKeeper, paths: &'static [&'static str]| {
-            let mut fut: Box<
-                futures::Future<Item = (ZooKeeper, Vec<bool>), Error = failure::Error> + Send,
-            > = Box::new(futures::future::ok((zk, Vec::new())));
+        let (zk, w) =
+            blocking::ZooKeeper::connect_with(builder, &"127.0.0.1:2181".parse().unwrap())
+                .unwrap();
+        let res = zk
+            .create("/blocking", &b"a"[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+            .unwrap();
+        assert_eq!(res.as_ref().map(String::as_str), Ok("/blocking"));
+        let (data, stat) = zk.get_data("/blocking").unwrap().unwrap();
+        assert_eq!(data, b"a");
+        let res = zk.set_data("/blocking", Some(stat.version), &b"b"[..]).unwrap();
+        assert!(res.is_ok());
+        let res = zk.block_on(zk.as_async().multi().check("/blocking", 1).run());
+        assert_eq!(res.unwrap(), vec![Ok(MultiResponse::Check)]);
+
+        drop(zk); // make Packetizer idle
+        assert_eq!(w.count(), 0);
+    }
+
+    #[tokio::test]
+    async fn stats_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let addr = "127.0.0.1:2181".parse().unwrap();
+        let (zk, _) = builder.connect(&addr).await.unwrap();
+        let stat = zk.exists("/").await.unwrap();
+        let stats = zk.stats();
+        assert_ne!(stats.session_id, 0);
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.queued, 0);
+        assert_eq!(stats.outbox_bytes, 0);
+        assert!(stats.last_zxid_seen >= stat.unwrap().pzxid);
+        assert_eq!(stats.server_addr, addr);
+        assert!(stats.since_last_packet.is_some());
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn with_retry_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let policy = retry::BoundedRetries {
+            retries: 3,
+            delay: time::Duration::from_millis(10),
+        };
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let res = zk
+            .with_retry(policy)
+            .create(
+                "/retried",
+                &b"hello"[..],
+                Acl::open_unsafe(),
+                CreateMode::Ephemeral,
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.as_ref().map(String::as_str), Ok("/retried"));
+        let res = zk.with_retry(policy).get_data("/retried").await.unwrap();
+        assert_eq!(res.as_ref().map(|(data, _)| &data[..]), Some(&b"hello"[..]));
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn multi_commit_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
+            .unwrap();
+        let res = zk
+            .multi()
+            .create("/mc", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .check("/mc", 0)
+            .commit()
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            Ok(vec![MultiResponse::Create("/mc".into()), MultiResponse::Check])
+        );
+        let res = zk
+            .multi()
+            .set_data("/mc", None, &b"a"[..])
+            .check("/mc", 0)
+            .delete("/mc", None)
+            .commit()
+            .await
+            .unwrap();
+        let failed = res.unwrap_err();
+        assert_eq!(failed.index, 1);
+        assert_eq!(
+            failed.cause(),
+            &error::Multi::Check(error::Check::BadVersion { path: "/mc".into(), expected: 0 })
+        );
+        assert_eq!(
+            failed.results,
+            vec![
+                Err(error::Multi::RolledBack),
+                Err(error::Multi::Check(error::Check::BadVersion {
+                    path: "/mc".into(),
+                    expected: 0
+                })),
+                Err(error::Multi::Skipped),
+            ]
+        );
+        zk.delete("/mc", None).await.unwrap().unwrap();
+
+        drop(zk); // make Packetizer idle
+    }
+
+    #[tokio::test]
+    async fn multi_test() {
+        let mut builder = ZooKeeperBuilder::default();
+        let decorator = slog_term::TermDecorator::new().build();
+        let drain = slog_term::FullFormat::new(decorator).build().fuse();
+        let drain = slog_async::Async::new(drain).build().fuse();
+        builder.set_logger(slog::Logger::root(drain, o!()));
+
+        async fn check_exists(zk: &ZooKeeper, paths: &[&str]) -> Result<Vec<bool>, Error> {
+            let mut res = Vec::new();
             for p in paths {
-                fut = Box::new(fut.and_then(move |(zk, mut v)| {
-                    zk.exists(p).map(|(zk, stat)| {
-                        v.push(stat.is_some());
-                        (zk, v)
-                    })
-                }))
+                res.push(zk.exists(p).await?.is_some());
             }
-            fut
-        };
+            Ok(res)
+        }
 
-        let (zk, _): (ZooKeeper, _) = rt
-            .block_on(
-                builder
-                    .connect(&"127.0.0.1:2181".parse().unwrap())
-                    .and_then(|(zk, _)| {
-                        zk.multi()
-                            .create("/b", &b"a"[..], Acl::open_unsafe(), CreateMode::Persistent)
-                            .create("/c", &b"b"[..], Acl::open_unsafe(), CreateMode::Persistent)
-                            .run()
-                    })
-                    .inspect(|(_, res)| {
-                        assert_eq!(
-                            res,
-                            &[
-                                Ok(MultiResponse::Create("/b".into())),
-                                Ok(MultiResponse::Create("/c".into()))
-                            ]
-                        )
-                    })
-                    .and_then(move |(zk, _)| check_exists(zk, &["/a", "/b", "/c", "/d"]))
-                    .inspect(|(_, res)| assert_eq!(res, &[false, true, true, false]))
-                    .and_then(|(zk, _)| {
-                        zk.multi()
-                            .create("/a", &b"a"[..], Acl::open_unsafe(), CreateMode::Persistent)
-                            .create("/b", &b"b"[..], Acl::open_unsafe(), CreateMode::Persistent)
-                            .create("/c", &b"b"[..], Acl::open_unsafe(), CreateMode::Persistent)
-                            .create("/d", &b"a"[..], Acl::open_unsafe(), CreateMode::Persistent)
-                            .run()
-                    })
-                    .inspect(|(_, res)| {
-                        assert_eq!(
-                            res,
-                            &[
-                                Err(error::Multi::RolledBack),
-                                Err(error::Multi::Create(error::Create::NodeExists)),
-                                Err(error::Multi::Skipped),
-                                Err(error::Multi::Skipped),
-                            ]
-                        )
-                    })
-                    .and_then(move |(zk, _)| check_exists(zk, &["/a", "/b", "/c", "/d"]))
-                    .inspect(|(_, res)| assert_eq!(res, &[false, true, true, false]))
-                    .and_then(|(zk, _)| zk.multi().set_data("/b", None, &b"garbaggio"[..]).run())
-                    .inspect(|(_, res)| match res[0] {
-                        Ok(MultiResponse::SetData(stat)) => {
-                            assert_eq!(stat.data_length as usize, "garbaggio".len())
-                        }
-                        _ => panic!("unexpected response: {:?}", res),
-                    })
-                    .and_then(|(zk, _)| zk.multi().check("/b", 0).delete("/c", None).run())
-                    .inspect(|(_, res)| {
-                        assert_eq!(
-                            res,
-                            &[
-                                Err(error::Multi::Check(error::Check::BadVersion {
-                                    expected: 0
-                                })),
-                                Err(error::Multi::Skipped),
-                            ]
-                        )
-                    })
-                    .and_then(move |(zk, _)| check_exists(zk, &["/a", "/b", "/c", "/d"]))
-                    .inspect(|(_, res)| assert_eq!(res, &[false, true, true, false]))
-                    .and_then(|(zk, _)| zk.multi().check("/a", 0).run())
-                    .inspect(|(_, res)| {
-                        assert_eq!(res, &[Err(error::Multi::Check(error::Check::NoNode)),])
-                    })
-                    .and_then(|(zk, _)| {
-                        zk.multi()
-                            .check("/b", 1)
-                            .delete("/b", None)
-                            .check("/c", 0)
-                            .delete("/c", None)
-                            .run()
-                    })
-                    .inspect(|(_, res)| {
-                        assert_eq!(
-                            res,
-                            &[
-                                Ok(MultiResponse::Check),
-                                Ok(MultiResponse::Delete),
-                                Ok(MultiResponse::Check),
-                                Ok(MultiResponse::Delete),
-                            ]
-                        )
-                    })
-                    .and_then(move |(zk, _)| check_exists(zk, &["/a", "/b", "/c", "/d"]))
-                    .inspect(|(_, res)| assert_eq!(res, &[false, false, false, false])),
-            )
+        let (zk, _) = builder
+            .connect(&"127.0.0.1:2181".parse().unwrap())
+            .await
             .unwrap();
+        let res = zk
+            .multi()
+            .create("/b", &b"a"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/c", &b"b"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            [
+                Ok(MultiResponse::Create("/b".into())),
+                Ok(MultiResponse::Create("/c".into()))
+            ]
+        );
+        let res = check_exists(&zk, &["/a", "/b", "/c", "/d"]).await.unwrap();
+        assert_eq!(res, [false, true, true, false]);
+        let res = zk
+            .multi()
+            .create("/a", &b"a"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/b", &b"b"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/c", &b"b"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .create("/d", &b"a"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            [
+                Err(error::Multi::RolledBack),
+                Err(error::Multi::Create(error::Create::NodeExists { path: "/b".into() })),
+                Err(error::Multi::Skipped),
+                Err(error::Multi::Skipped),
+            ]
+        );
+        let res = check_exists(&zk, &["/a", "/b", "/c", "/d"]).await.unwrap();
+        assert_eq!(res, [false, true, true, false]);
+        let res = zk
+            .multi()
+            .set_data("/b", None, &b"garbaggio"[..])
+            .run()
+            .await
+            .unwrap();
+        match res[0] {
+            Ok(MultiResponse::SetData(stat)) => {
+                assert_eq!(stat.data_length as usize, "garbaggio".len())
+            }
+            _ => panic!("unexpected response: {:?}", res),
+        }
+        let res = zk
+            .multi()
+            .check("/b", 0)
+            .delete("/c", None)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            [
+                Err(error::Multi::Check(error::Check::BadVersion {
+                    path: "/b".into(),
+                    expected: 0
+                })),
+                Err(error::Multi::Skipped),
+            ]
+        );
+        let res = check_exists(&zk, &["/a", "/b", "/c", "/d"]).await.unwrap();
+        assert_eq!(res, [false, true, true, false]);
+        let res = zk.multi().check("/a", 0).run().await.unwrap();
+        assert_eq!(res, [Err(error::Multi::Check(error::Check::NoNode { path: "/a".into() }))]);
+        let res = zk
+            .multi()
+            .check("/b", 1)
+            .delete("/b", None)
+            .check("/c", 0)
+            .delete("/c", None)
+            .run()
+            .await
+            .unwrap();
+        assert_eq!(
+            res,
+            [
+                Ok(MultiResponse::Check),
+                Ok(MultiResponse::Delete),
+                Ok(MultiResponse::Check),
+                Ok(MultiResponse::Delete),
+            ]
+        );
+        let res = check_exists(&zk, &["/a", "/b", "/c", "/d"]).await.unwrap();
+        assert_eq!(res, [false, false, false, false]);
 
         drop(zk); // make Packetizer idle
-        rt.shutdown_on_idle().wait().unwrap();
     }
 }
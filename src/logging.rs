@@ -0,0 +1,208 @@
+//! The client's internal logging facade.
+//!
+//! Log statements are written with the `trace!`, `debug!`, `info!`, `warn!`, and `error!` macros
+//! defined here, which take the same arguments as their `slog` counterparts:
+//!
+//! ```ignore
+//! debug!(logger, "retrying delete: {}", e; "path" => &path, "version" => ?version);
+//! ```
+//!
+//! Each statement goes to every backend that is enabled:
+//!
+//!  - with the `slog` feature (the default), to the `slog::Logger` given to
+//!    `ZooKeeperBuilder::set_logger`, with the key-value pairs as structured fields.
+//!  - with the `log` feature, to the global `log` logger.
+//!  - with the `tracing` feature, as an event to the current `tracing` subscriber.
+//!
+//! The `log` and `tracing` backends use the `tokio_zookeeper` target, and append the key-value
+//! pairs to the message as `key=value`. The message is only formatted if one of them is
+//! interested in the statement's level. With no backend enabled, logging compiles down to nothing.
+
+use std::fmt::{self, Write};
+
+/// The target that statements are logged under by the `log` and `tracing` backends.
+#[cfg(any(feature = "log", feature = "tracing"))]
+const TARGET: &str = "tokio_zookeeper";
+
+/// The logger that a client was configured with.
+///
+/// Only the `slog` backend needs a logger instance; the others log to a global subscriber.
+#[derive(Clone, Debug)]
+pub(crate) struct Logger {
+    #[cfg(feature = "slog")]
+    pub(crate) slog: ::slog::Logger,
+}
+
+#[cfg_attr(not(feature = "slog"), allow(clippy::derivable_impls))]
+impl Default for Logger {
+    fn default() -> Self {
+        Logger {
+            #[cfg(feature = "slog")]
+            slog: ::slog::Logger::root(::slog::Discard, o!()),
+        }
+    }
+}
+
+#[cfg(feature = "slog")]
+impl From<::slog::Logger> for Logger {
+    fn from(slog: ::slog::Logger) -> Self {
+        Logger { slog }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+#[cfg(feature = "log")]
+impl From<Level> for ::log::Level {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => ::log::Level::Error,
+            Level::Warn => ::log::Level::Warn,
+            Level::Info => ::log::Level::Info,
+            Level::Debug => ::log::Level::Debug,
+            Level::Trace => ::log::Level::Trace,
+        }
+    }
+}
+
+/// Whether any of the `log` and `tracing` backends would record a statement at `level`.
+#[inline]
+#[cfg_attr(not(any(feature = "log", feature = "tracing")), allow(unused_variables))]
+pub(crate) fn enabled(level: Level) -> bool {
+    #[allow(unused_mut)]
+    let mut enabled = false;
+    #[cfg(feature = "log")]
+    {
+        enabled |= ::log::log_enabled!(target: TARGET, level.into());
+    }
+    #[cfg(feature = "tracing")]
+    {
+        enabled |= match level {
+            Level::Error => ::tracing::enabled!(target: TARGET, ::tracing::Level::ERROR),
+            Level::Warn => ::tracing::enabled!(target: TARGET, ::tracing::Level::WARN),
+            Level::Info => ::tracing::enabled!(target: TARGET, ::tracing::Level::INFO),
+            Level::Debug => ::tracing::enabled!(target: TARGET, ::tracing::Level::DEBUG),
+            Level::Trace => ::tracing::enabled!(target: TARGET, ::tracing::Level::TRACE),
+        };
+    }
+    enabled
+}
+
+/// Hand a formatted statement to the `log` and `tracing` backends.
+#[cfg_attr(not(any(feature = "log", feature = "tracing")), allow(unused_variables))]
+pub(crate) fn emit(level: Level, message: &str) {
+    #[cfg(feature = "log")]
+    ::log::log!(target: TARGET, level.into(), "{}", message);
+    #[cfg(feature = "tracing")]
+    match level {
+        Level::Error => ::tracing::error!(target: TARGET, "{}", message),
+        Level::Warn => ::tracing::warn!(target: TARGET, "{}", message),
+        Level::Info => ::tracing::info!(target: TARGET, "{}", message),
+        Level::Debug => ::tracing::debug!(target: TARGET, "{}", message),
+        Level::Trace => ::tracing::trace!(target: TARGET, "{}", message),
+    }
+}
+
+/// Append ` key=value` to `message`.
+pub(crate) fn push_kv(message: &mut String, key: &str, value: fmt::Arguments) {
+    let _ = write!(message, " {}={}", key, value);
+}
+
+/// Format a statement's message, followed by its key-value pairs.
+macro_rules! __zk_message {
+    (@fmt [$($fmt:tt)*] ; $($kv:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut message = format!($($fmt)*);
+        __zk_kv!(message, $($kv)* ,);
+        message
+    }};
+    (@fmt [$($fmt:tt)*] $next:tt $($rest:tt)*) => {
+        __zk_message!(@fmt [$($fmt)* $next] $($rest)*)
+    };
+    (@fmt [$($fmt:tt)*]) => {
+        format!($($fmt)*)
+    };
+    ($($args:tt)+) => {
+        __zk_message!(@fmt [] $($args)+)
+    };
+}
+
+/// Append `"key" => value` pairs to a message, using `Debug` for `?value` and `Display` otherwise.
+macro_rules! __zk_kv {
+    ($message:ident $(,)*) => {};
+    ($message:ident, $k:expr => ?$v:expr, $($rest:tt)*) => {
+        $crate::logging::push_kv(&mut $message, $k, format_args!("{:?}", $v));
+        __zk_kv!($message, $($rest)*);
+    };
+    ($message:ident, $k:expr => %$v:expr, $($rest:tt)*) => {
+        $crate::logging::push_kv(&mut $message, $k, format_args!("{}", $v));
+        __zk_kv!($message, $($rest)*);
+    };
+    ($message:ident, $k:expr => $v:expr, $($rest:tt)*) => {
+        $crate::logging::push_kv(&mut $message, $k, format_args!("{}", $v));
+        __zk_kv!($message, $($rest)*);
+    };
+}
+
+macro_rules! __zk_log {
+    ($slog:ident, $level:ident, $logger:expr, $($args:tt)+) => {{
+        #[cfg(feature = "slog")]
+        ::slog::$slog!($logger.slog, $($args)+);
+        let _ = &$logger;
+        if $crate::logging::enabled($crate::logging::Level::$level) {
+            let message = __zk_message!($($args)+);
+            $crate::logging::emit($crate::logging::Level::$level, &message);
+        }
+    }};
+}
+
+macro_rules! error {
+    ($logger:expr, $($args:tt)+) => {
+        __zk_log!(error, Error, $logger, $($args)+)
+    };
+}
+
+macro_rules! warn {
+    ($logger:expr, $($args:tt)+) => {
+        __zk_log!(warn, Warn, $logger, $($args)+)
+    };
+}
+
+macro_rules! info {
+    ($logger:expr, $($args:tt)+) => {
+        __zk_log!(info, Info, $logger, $($args)+)
+    };
+}
+
+macro_rules! debug {
+    ($logger:expr, $($args:tt)+) => {
+        __zk_log!(debug, Debug, $logger, $($args)+)
+    };
+}
+
+macro_rules! trace {
+    ($logger:expr, $($args:tt)+) => {
+        __zk_log!(trace, Trace, $logger, $($args)+)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn message() {
+        let (path, version) = ("/foo", Some(3));
+        assert_eq!(__zk_message!("hello"), "hello");
+        assert_eq!(__zk_message!("got {}", 42;), "got 42");
+        assert_eq!(
+            __zk_message!("delete {}", 1; "path" => path, "version" => ?version, "n" => %7,),
+            "delete 1 path=/foo version=Some(3) n=7"
+        );
+    }
+}
@@ -0,0 +1,465 @@
+//! A client that replaces its session with a new one when the session expires.
+//!
+//! A [`ZooKeeper`] handle is bound to one session: once the session expires, all that its
+//! requests do is fail with [`Error::SessionExpired`], and the application has to connect again
+//! and set up whatever it kept in the session. [`ManagedZooKeeper`] does that by itself. It
+//! watches its session, and when the session ends it connects a new one, re-creates the ephemeral
+//! nodes that were registered with [`ManagedZooKeeper::create_ephemeral`], runs the hooks that were
+//! registered with [`ManagedZooKeeper::on_new_session`], and only then hands the new session out
+//! from [`ManagedZooKeeper::client`].
+//!
+//! Every session is numbered with an epoch, starting at 0 for the first one and increasing by one
+//! for every new session. The epoch tells callers that a new session began: the watches of the
+//! old session are gone, and changes made while no session was connected were missed, so state
+//! derived from reads of an earlier epoch has to be read again. Watches are deliberately not set
+//! again on the new session, since a watch that is set again cannot tell its owner about what it
+//! missed; a hook is the place to read again and set again what is safe to. Hooks are also where
+//! anything else that a session carries, such as credentials, is set up.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use futures::prelude::*;
+//! use tokio_zookeeper::managed::{ManagedEvent, ManagedZooKeeper};
+//! # async fn run(addr: std::net::SocketAddr) -> Result<(), Error> {
+//! let (zk, mut events) = ManagedZooKeeper::connect(ZooKeeperBuilder::default(), addr).await?;
+//! zk.create_ephemeral("/workers/a", &b"up"[..], Acl::open_unsafe()).await?.unwrap();
+//! zk.on_new_session(|zk, epoch| async move {
+//!     println!("session {} began", epoch);
+//!     zk.exists("/config").await.map(drop)
+//! });
+//! while let Some(event) = events.next().await {
+//!     if let ManagedEvent::NewSession { epoch, .. } = event {
+//!         println!("now on session {}", epoch);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures::prelude::*;
+use futures::stream::FusedStream;
+use std::borrow::Cow;
+use std::fmt;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use crate::retry::{ExponentialBackoff, RetryPolicy};
+use crate::{
+    error, Acl, CreateMode, Error, WatchedEvent, WatchedEventStream, ZooKeeper, ZooKeeperBuilder,
+};
+
+/// Connects a new session; see [`ManagedZooKeeper::with_connector`].
+pub(crate) type Connector =
+    dyn Fn() -> BoxFuture<'static, Result<(ZooKeeper, WatchedEventStream), Error>> + Send + Sync;
+type Hook = dyn Fn(ZooKeeper, u64) -> BoxFuture<'static, Result<(), Error>> + Send + Sync;
+
+/// What a [`ManagedZooKeeper`] reports about its sessions.
+#[derive(Debug)]
+pub enum ManagedEvent {
+    /// The default watcher of the session of `epoch` saw `event`.
+    Watched {
+        /// The epoch of the session that saw the event.
+        epoch: u64,
+        /// The event.
+        event: WatchedEvent,
+    },
+    /// The session of `epoch` began, and its ephemeral nodes and hooks have been set up.
+    NewSession {
+        /// The epoch of the new session.
+        epoch: u64,
+        /// The id of the new session.
+        session_id: i64,
+    },
+    /// No new session could be connected within the reconnect policy, and the client will stay on
+    /// the session that ended.
+    GaveUp {
+        /// Why the last attempt to connect failed.
+        error: Error,
+    },
+}
+
+/// The events of a [`ManagedZooKeeper`], as returned along with it.
+///
+/// The stream ends once the client stops managing its sessions, which is when it gives up, is
+/// [closed](ManagedZooKeeper::close), or is dropped and its last session ends.
+pub struct ManagedEventStream(mpsc::UnboundedReceiver<ManagedEvent>);
+
+impl fmt::Debug for ManagedEventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ManagedEventStream")
+            .field("terminated", &self.0.is_terminated())
+            .finish()
+    }
+}
+
+impl Stream for ManagedEventStream {
+    type Item = ManagedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<ManagedEvent>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+impl FusedStream for ManagedEventStream {
+    fn is_terminated(&self) -> bool {
+        self.0.is_terminated()
+    }
+}
+
+struct Ephemeral {
+    path: String,
+    data: Cow<'static, [u8]>,
+    acl: Cow<'static, [Acl]>,
+}
+
+struct Shared {
+    connect: Box<Connector>,
+    current: Mutex<(ZooKeeper, u64)>,
+    ephemerals: Mutex<Vec<Ephemeral>>,
+    hooks: Mutex<Vec<Arc<Hook>>>,
+    policy: Mutex<Arc<dyn RetryPolicy>>,
+    closed: AtomicBool,
+}
+
+/// A ZooKeeper client that connects a new session whenever its session ends.
+///
+/// See the [module documentation](index.html) for what is carried over to a new session. Clones
+/// share the same sessions. Once every clone is dropped, the client stops managing its sessions,
+/// and its current session is closed once the handles to it that were taken from
+/// [`ManagedZooKeeper::client`] are dropped too.
+#[derive(Clone)]
+pub struct ManagedZooKeeper {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for ManagedZooKeeper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (ref zk, epoch) = *self.shared.current.lock().unwrap();
+        f.debug_struct("ManagedZooKeeper")
+            .field("zk", zk)
+            .field("epoch", &epoch)
+            .finish()
+    }
+}
+
+impl ManagedZooKeeper {
+    /// Connect to the server at `addr` with `builder`, and connect every new session the same way.
+    pub async fn connect(
+        builder: ZooKeeperBuilder,
+        addr: SocketAddr,
+    ) -> Result<(Self, ManagedEventStream), Error> {
+        Self::with_connector(move || {
+            let builder = builder.clone();
+            async move { builder.connect(&addr).await }
+        })
+        .await
+    }
+
+    /// Connect the first session with `connect`, and every new session by calling it again.
+    ///
+    /// This lets sessions be connected in ways that [`ManagedZooKeeper::connect`] does not cover,
+    /// such as over another runtime, or to another address each time. If connecting the first
+    /// session fails, so does this.
+    pub async fn with_connector<F, Fut>(connect: F) -> Result<(Self, ManagedEventStream), Error>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(ZooKeeper, WatchedEventStream), Error>> + Send + 'static,
+    {
+        let (zk, watcher) = connect().await?;
+        let (tx, rx) = mpsc::unbounded();
+        let runtime = zk.runtime;
+        let shared = Arc::new(Shared {
+            connect: Box::new(move || connect().boxed()),
+            current: Mutex::new((zk, 0)),
+            ephemerals: Mutex::new(Vec::new()),
+            hooks: Mutex::new(Vec::new()),
+            policy: Mutex::new(Arc::new(ExponentialBackoff {
+                max_retries: u32::MAX,
+                ..ExponentialBackoff::default()
+            })),
+            closed: AtomicBool::new(false),
+        });
+        runtime.spawn(supervise(Arc::downgrade(&shared), watcher, tx));
+        Ok((ManagedZooKeeper { shared }, ManagedEventStream(rx)))
+    }
+
+    /// Return a handle to the current session.
+    ///
+    /// The handle stays bound to its session; take a new one after a
+    /// [`ManagedEvent::NewSession`] to use the new session.
+    pub fn client(&self) -> ZooKeeper {
+        self.shared.current.lock().unwrap().0.clone()
+    }
+
+    /// Return the epoch of the current session.
+    pub fn epoch(&self) -> u64 {
+        self.shared.current.lock().unwrap().1
+    }
+
+    /// Decide with `policy` how often, and how long, to try to connect a new session before
+    /// giving up.
+    ///
+    /// By default, a new session is tried for forever, with a backoff of up to 10 seconds.
+    pub fn set_reconnect_policy<P>(&self, policy: P)
+    where
+        P: RetryPolicy + 'static,
+    {
+        *self.shared.policy.lock().unwrap() = Arc::new(policy);
+    }
+
+    /// Create an ephemeral node at `path` in the current session, and create it again in every
+    /// new session.
+    ///
+    /// The node is created again with the `data` and `acl` it was first created with, whatever it
+    /// was later changed to. If the node cannot be created now, it is not registered; if it cannot
+    /// be created again in a new session, for instance because another session took the path, the
+    /// failure is logged and the node registered still.
+    pub async fn create_ephemeral<D, A>(
+        &self,
+        path: &str,
+        data: D,
+        acl: A,
+    ) -> Result<Result<String, error::Create>, Error>
+    where
+        D: Into<Cow<'static, [u8]>>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        let (data, acl) = (data.into(), acl.into());
+        let r = self
+            .client()
+            .create(path, data.clone(), acl.clone(), CreateMode::Ephemeral)
+            .await?;
+        if r.is_ok() {
+            let mut ephemerals = self.shared.ephemerals.lock().unwrap();
+            ephemerals.retain(|e| e.path != path);
+            ephemerals.push(Ephemeral {
+                path: path.to_string(),
+                data,
+                acl,
+            });
+        }
+        Ok(r)
+    }
+
+    /// Stop creating the ephemeral node at `path` in new sessions, and delete it from the current
+    /// session.
+    pub async fn delete_ephemeral(&self, path: &str) -> Result<Result<(), error::Delete>, Error> {
+        self.shared.ephemerals.lock().unwrap().retain(|e| e.path != path);
+        self.client().delete_any(path).await
+    }
+
+    /// Run `hook` on every new session, with a handle to it and its epoch, before the session is
+    /// handed out.
+    ///
+    /// Hooks run in the order they were registered, after the ephemeral nodes were created again.
+    /// A hook that fails is logged, and does not keep the session from being handed out. Hooks do
+    /// not run on the session that is current when they are registered.
+    pub fn on_new_session<F, Fut>(&self, hook: F)
+    where
+        F: Fn(ZooKeeper, u64) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        let hook = move |zk, epoch| hook(zk, epoch).boxed();
+        self.shared.hooks.lock().unwrap().push(Arc::new(hook));
+    }
+
+    /// Stop connecting new sessions, and [shut down](ZooKeeper::shutdown) the current session
+    /// within `timeout`.
+    pub async fn close(&self, timeout: Duration) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.client().shutdown(timeout).await
+    }
+}
+
+impl Shared {
+    /// Connect a new session, trying as often as the reconnect policy allows.
+    async fn reconnect(
+        &self,
+        events: &mpsc::UnboundedSender<ManagedEvent>,
+    ) -> Option<(ZooKeeper, WatchedEventStream)> {
+        let (logger, runtime) = {
+            let current = self.current.lock().unwrap();
+            (current.0.logger.clone(), current.0.runtime)
+        };
+        let mut retries = 0;
+        loop {
+            let error = match (self.connect)().await {
+                Ok(connected) => return Some(connected),
+                Err(e) => e,
+            };
+            let policy = self.policy.lock().unwrap().clone();
+            match policy.next_delay(retries) {
+                Some(delay) if !self.closed.load(Ordering::SeqCst) => {
+                    debug!(logger, "failed to connect a new session, retrying: {}", error);
+                    runtime.sleep(delay).await;
+                    retries += 1;
+                }
+                _ => {
+                    warn!(logger, "failed to connect a new session, giving up: {}", error);
+                    let _ = events.unbounded_send(ManagedEvent::GaveUp { error });
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Set up the state that every session carries on the new session `zk`.
+    async fn restore(&self, zk: &ZooKeeper, epoch: u64) {
+        let ephemerals: Vec<_> = self
+            .ephemerals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| (e.path.clone(), e.data.clone(), e.acl.clone()))
+            .collect();
+        for (path, data, acl) in ephemerals {
+            let error = match zk.create(&path, data, acl, CreateMode::Ephemeral).await {
+                Ok(Ok(_)) => continue,
+                Ok(Err(e)) => e.to_string(),
+                Err(e) => e.to_string(),
+            };
+            warn!(zk.logger, "failed to create ephemeral again: {}", error; "path" => &path);
+        }
+        let hooks = self.hooks.lock().unwrap().clone();
+        for hook in hooks {
+            if let Err(e) = hook(zk.clone(), epoch).await {
+                warn!(zk.logger, "new session hook failed: {}", e; "epoch" => epoch);
+            }
+        }
+    }
+}
+
+/// Forward the events of each session, and replace each session with a new one once it ends.
+async fn supervise(
+    shared: Weak<Shared>,
+    mut watcher: WatchedEventStream,
+    events: mpsc::UnboundedSender<ManagedEvent>,
+) {
+    let mut epoch = 0;
+    loop {
+        // the stream ends once the session does, be it expired, lost, or closed
+        while let Some(event) = watcher.next().await {
+            let _ = events.unbounded_send(ManagedEvent::Watched { epoch, event });
+        }
+        let shared = match shared.upgrade() {
+            Some(shared) if !shared.closed.load(Ordering::SeqCst) => shared,
+            _ => return,
+        };
+        let (zk, next) = match shared.reconnect(&events).await {
+            Some(connected) => connected,
+            None => return,
+        };
+        epoch += 1;
+        shared.restore(&zk, epoch).await;
+        let session_id = zk.stats().session_id;
+        info!(zk.logger, "new session"; "epoch" => epoch, "session_id" => session_id);
+        *shared.current.lock().unwrap() = (zk, epoch);
+        let _ = events.unbounded_send(ManagedEvent::NewSession { epoch, session_id });
+        watcher = next;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use super::*;
+    use crate::retry::BoundedRetries;
+    use crate::testing::MockZk;
+
+    async fn managed(server: &MockZk) -> (ManagedZooKeeper, ManagedEventStream) {
+        let server = server.clone();
+        ManagedZooKeeper::with_connector(move || {
+            let server = server.clone();
+            async move { server.connect().await }
+        })
+        .await
+        .unwrap()
+    }
+
+    async fn new_session(events: &mut ManagedEventStream) -> (u64, i64) {
+        loop {
+            match events.next().await.unwrap() {
+                ManagedEvent::NewSession { epoch, session_id } => return (epoch, session_id),
+                ManagedEvent::GaveUp { error } => panic!("gave up: {}", error),
+                ManagedEvent::Watched { .. } => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn replaces_expired_sessions() {
+        let server = MockZk::new();
+        let (zk, mut events) = managed(&server).await;
+        assert_eq!(zk.epoch(), 0);
+        let acl = Acl::open_unsafe();
+        zk.create_ephemeral("/e", &b"x"[..], acl).await.unwrap().unwrap();
+        let hooked = Arc::new(Mutex::new(Vec::new()));
+        let seen = hooked.clone();
+        zk.on_new_session(move |zk, epoch| {
+            let seen = seen.clone();
+            async move {
+                seen.lock().unwrap().push((epoch, zk.stats().session_id));
+                Ok(())
+            }
+        });
+
+        let old = zk.client();
+        assert!(server.expire_session(old.stats().session_id));
+        let (epoch, session_id) = new_session(&mut events).await;
+        assert_eq!((epoch, zk.epoch()), (1, 1));
+        assert_ne!(session_id, old.stats().session_id);
+        assert_eq!(*hooked.lock().unwrap(), vec![(1, session_id)]);
+        let client = zk.client();
+        assert_eq!(client.stats().session_id, session_id);
+        let (data, stat) = client.get_data("/e").await.unwrap().unwrap();
+        assert_eq!((&data[..], stat.ephemeral_owner), (&b"x"[..], session_id));
+
+        // a node that is no longer registered is left to go with the session
+        zk.delete_ephemeral("/e").await.unwrap().unwrap();
+        assert!(server.expire_session(session_id));
+        assert_eq!(new_session(&mut events).await.0, 2);
+        assert_eq!(zk.client().exists("/e").await.unwrap(), None);
+
+        zk.close(Duration::from_secs(1)).await;
+        while events.next().await.is_some() {}
+    }
+
+    #[tokio::test]
+    async fn gives_up() {
+        let server = MockZk::new();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let connector = (server.clone(), attempts.clone());
+        let (zk, mut events) = ManagedZooKeeper::with_connector(move || {
+            let (server, attempts) = connector.clone();
+            async move {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => server.connect().await,
+                    _ => Err(Error::ConnectionLoss),
+                }
+            }
+        })
+        .await
+        .unwrap();
+        zk.set_reconnect_policy(BoundedRetries {
+            retries: 2,
+            delay: Duration::from_millis(1),
+        });
+
+        assert!(server.expire_session(zk.client().stats().session_id));
+        loop {
+            match events.next().await {
+                Some(ManagedEvent::GaveUp { error: Error::ConnectionLoss }) => break,
+                Some(ManagedEvent::Watched { .. }) => {}
+                e => panic!("unexpected {:?}", e),
+            }
+        }
+        assert!(events.next().await.is_none());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+        assert_eq!(zk.epoch(), 0);
+    }
+}
@@ -0,0 +1,183 @@
+//! Hooks for instrumenting a client.
+//!
+//! Implement [`ClientMetrics`] and install it with [`ZooKeeperBuilder::set_metrics`] to feed the
+//! client's activity into a telemetry system. Every method has an empty default implementation,
+//! so an implementation only needs to provide the callbacks it is interested in.
+//!
+//! The callbacks are invoked from the task that drives the connection, so they should be cheap
+//! and must not block.
+//!
+//! With the `prometheus` feature enabled, [`PrometheusMetrics`] provides a ready-made
+//! implementation.
+//!
+//! ```
+//! # extern crate tokio_zookeeper;
+//! use std::sync::atomic::{AtomicUsize, Ordering};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//! use tokio_zookeeper::metrics::{ClientMetrics, Operation};
+//! use tokio_zookeeper::{ZkError, ZooKeeperBuilder};
+//!
+//! #[derive(Default)]
+//! struct Failures(AtomicUsize);
+//!
+//! impl ClientMetrics for Failures {
+//!     fn on_request_complete(&self, _: Operation, _: Duration, error: Option<ZkError>) {
+//!         if error.is_some() {
+//!             self.0.fetch_add(1, Ordering::Relaxed);
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let mut builder = ZooKeeperBuilder::default();
+//! builder.set_metrics(Arc::new(Failures::default()));
+//! # }
+//! ```
+//!
+//! [`ZooKeeperBuilder::set_metrics`]: crate::ZooKeeperBuilder::set_metrics
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::{WatchedEventType, ZkError};
+
+#[cfg(feature = "prometheus")]
+mod prometheus;
+#[cfg(feature = "prometheus")]
+pub use self::prometheus::PrometheusMetrics;
+
+/// The kind of a request sent to the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    /// Establishing a session.
+    Connect,
+    /// [`ZooKeeper::create`](../struct.ZooKeeper.html#method.create).
+    Create,
+    /// [`ZooKeeper::delete`](../struct.ZooKeeper.html#method.delete).
+    Delete,
+    /// [`ZooKeeper::exists`](../struct.ZooKeeper.html#method.exists).
+    Exists,
+    /// [`ZooKeeper::get_data`](../struct.ZooKeeper.html#method.get_data).
+    GetData,
+    /// [`ZooKeeper::set_data`](../struct.ZooKeeper.html#method.set_data).
+    SetData,
+    /// [`ZooKeeper::get_acl`](../struct.ZooKeeper.html#method.get_acl).
+    GetAcl,
+    /// [`ZooKeeper::set_acl`](../struct.ZooKeeper.html#method.set_acl).
+    SetAcl,
+    /// [`ZooKeeper::get_children`](../struct.ZooKeeper.html#method.get_children).
+    GetChildren,
+    /// A version check in a [`MultiBuilder`](../struct.MultiBuilder.html).
+    Check,
+    /// [`MultiBuilder::run`](../struct.MultiBuilder.html#method.run).
+    Multi,
+    /// [`ZooKeeper::sync`](../struct.ZooKeeper.html#method.sync).
+    Sync,
+    /// Setting a persistent watch, as by
+    /// [`ZooKeeper::change_journal`](../struct.ZooKeeper.html#method.change_journal).
+    AddWatch,
+    /// [`ZooKeeper::raw_request`](../struct.ZooKeeper.html#method.raw_request).
+    Raw,
+}
+
+impl Operation {
+    /// A short, lower-case name for the operation, suitable as a metric label.
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::Connect => "connect",
+            Operation::Create => "create",
+            Operation::Delete => "delete",
+            Operation::Exists => "exists",
+            Operation::GetData => "get_data",
+            Operation::SetData => "set_data",
+            Operation::GetAcl => "get_acl",
+            Operation::SetAcl => "set_acl",
+            Operation::GetChildren => "get_children",
+            Operation::Check => "check",
+            Operation::Multi => "multi",
+            Operation::Sync => "sync",
+            Operation::AddWatch => "add_watch",
+            Operation::Raw => "raw",
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Callbacks through which a client reports what it is doing.
+pub trait ClientMetrics: Send + Sync {
+    /// A request for `op` has been queued to be sent to the server.
+    fn on_request_start(&self, op: Operation) {
+        let _ = op;
+    }
+
+    /// The server has responded to a request for `op` after `latency`, measured from when the
+    /// request was queued. `error` is the error the server returned, if any.
+    ///
+    /// Requests that never receive a response, for instance because the connection failed, are
+    /// not reported.
+    fn on_request_complete(&self, op: Operation, latency: Duration, error: Option<ZkError>) {
+        let _ = (op, latency, error);
+    }
+
+    /// The client has re-established its connection to the server.
+    fn on_reconnect(&self) {}
+
+    /// The server has sent a watch notification.
+    fn on_watch_fired(&self, event_type: WatchedEventType) {
+        let _ = event_type;
+    }
+
+    /// The application has taken a watch notification out of the watch stream, `latency` after
+    /// the client received it.
+    ///
+    /// Notifications that are delivered to the receivers of individual watches are not reported.
+    fn on_watch_delivered(&self, event_type: WatchedEventType, latency: Duration) {
+        let _ = (event_type, latency);
+    }
+
+    /// The number of requests that have been sent but not yet answered has changed to `depth`.
+    fn on_queue_depth(&self, depth: usize) {
+        let _ = depth;
+    }
+}
+
+#[derive(Debug)]
+struct NoMetrics;
+
+impl ClientMetrics for NoMetrics {}
+
+/// The metrics a client reports to.
+#[derive(Clone)]
+pub(crate) struct Metrics(Arc<dyn ClientMetrics>);
+
+impl Metrics {
+    pub(crate) fn new(metrics: Arc<dyn ClientMetrics>) -> Self {
+        Metrics(metrics)
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics(Arc::new(NoMetrics))
+    }
+}
+
+impl ::std::ops::Deref for Metrics {
+    type Target = dyn ClientMetrics;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Metrics").finish()
+    }
+}
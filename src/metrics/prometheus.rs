@@ -0,0 +1,177 @@
+use super::{ClientMetrics, Operation};
+use prometheus::{
+    exponential_buckets, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry,
+};
+use std::time::Duration;
+use crate::{WatchedEventType, ZkError};
+
+/// A [`ClientMetrics`] implementation that records into a Prometheus [`Registry`].
+///
+/// The following metrics are registered:
+///
+///  - `zookeeper_request_duration_seconds`: a histogram of request latencies, labeled by `op`.
+///  - `zookeeper_request_errors_total`: the number of requests that the server failed, labeled by
+///    `op` and `error`.
+///  - `zookeeper_reconnects_total`: the number of times the connection was re-established.
+///  - `zookeeper_watch_events_total`: the number of watch notifications, labeled by `type`.
+///  - `zookeeper_watch_delivery_seconds`: a histogram of how long watch notifications waited in
+///    the watch stream before the application took them, labeled by `type`.
+///  - `zookeeper_requests_in_flight`: the number of requests waiting for a response.
+///
+/// This type is only available with the `prometheus` feature enabled.
+#[derive(Clone, Debug)]
+pub struct PrometheusMetrics {
+    latency: HistogramVec,
+    errors: IntCounterVec,
+    reconnects: IntCounter,
+    watches: IntCounterVec,
+    delivery: HistogramVec,
+    in_flight: IntGauge,
+}
+
+impl PrometheusMetrics {
+    /// Create the client's metrics and register them with `registry`.
+    ///
+    /// Fails if any of them are already registered, for instance by another client. Use a
+    /// registry created with `Registry::new_custom` to give each client its own prefix or labels.
+    pub fn new(registry: &Registry) -> Result<Self, prometheus::Error> {
+        let latency = HistogramVec::new(
+            HistogramOpts::new(
+                "zookeeper_request_duration_seconds",
+                "Time from sending a ZooKeeper request until receiving its response.",
+            )
+            // 0.5ms to ~16s
+            .buckets(exponential_buckets(0.0005, 2.0, 16)?),
+            &["op"],
+        )?;
+        let errors = IntCounterVec::new(
+            Opts::new(
+                "zookeeper_request_errors_total",
+                "ZooKeeper requests that the server responded to with an error.",
+            ),
+            &["op", "error"],
+        )?;
+        let reconnects = IntCounter::new(
+            "zookeeper_reconnects_total",
+            "Times the connection to ZooKeeper was re-established.",
+        )?;
+        let watches = IntCounterVec::new(
+            Opts::new(
+                "zookeeper_watch_events_total",
+                "Watch notifications received from ZooKeeper.",
+            ),
+            &["type"],
+        )?;
+        let delivery = HistogramVec::new(
+            HistogramOpts::new(
+                "zookeeper_watch_delivery_seconds",
+                "Time from receiving a ZooKeeper watch notification until the application took it.",
+            )
+            // 0.1ms to ~3s
+            .buckets(exponential_buckets(0.0001, 2.0, 16)?),
+            &["type"],
+        )?;
+        let in_flight = IntGauge::new(
+            "zookeeper_requests_in_flight",
+            "ZooKeeper requests that are waiting for a response.",
+        )?;
+
+        registry.register(Box::new(latency.clone()))?;
+        registry.register(Box::new(errors.clone()))?;
+        registry.register(Box::new(reconnects.clone()))?;
+        registry.register(Box::new(watches.clone()))?;
+        registry.register(Box::new(delivery.clone()))?;
+        registry.register(Box::new(in_flight.clone()))?;
+
+        Ok(PrometheusMetrics {
+            latency,
+            errors,
+            reconnects,
+            watches,
+            delivery,
+            in_flight,
+        })
+    }
+}
+
+fn seconds(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) * 1e-9
+}
+
+impl ClientMetrics for PrometheusMetrics {
+    fn on_request_complete(&self, op: Operation, latency: Duration, error: Option<ZkError>) {
+        self.latency
+            .with_label_values(&[op.name()])
+            .observe(seconds(latency));
+        if let Some(e) = error {
+            self.errors
+                .with_label_values(&[op.name(), &format!("{:?}", e)])
+                .inc();
+        }
+    }
+
+    fn on_reconnect(&self) {
+        self.reconnects.inc();
+    }
+
+    fn on_watch_fired(&self, event_type: WatchedEventType) {
+        self.watches
+            .with_label_values(&[&format!("{:?}", event_type)])
+            .inc();
+    }
+
+    fn on_watch_delivered(&self, event_type: WatchedEventType, latency: Duration) {
+        self.delivery
+            .with_label_values(&[&format!("{:?}", event_type)])
+            .observe(seconds(latency));
+    }
+
+    fn on_queue_depth(&self, depth: usize) {
+        self.in_flight.set(depth as i64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records() {
+        let registry = Registry::new();
+        let metrics = PrometheusMetrics::new(&registry).unwrap();
+        metrics.on_request_complete(Operation::GetData, Duration::from_millis(3), None);
+        metrics.on_request_complete(
+            Operation::Create,
+            Duration::from_millis(1),
+            Some(ZkError::NodeExists),
+        );
+        metrics.on_watch_delivered(WatchedEventType::NodeDeleted, Duration::from_millis(2));
+        metrics.on_queue_depth(7);
+
+        let families = registry.gather();
+        let family = |name: &str| {
+            families
+                .iter()
+                .find(|f| f.get_name() == name)
+                .unwrap_or_else(|| panic!("{} is not registered", name))
+        };
+        let latency = family("zookeeper_request_duration_seconds").get_metric();
+        assert_eq!(latency.len(), 2);
+        let errors = family("zookeeper_request_errors_total").get_metric();
+        assert_eq!(errors.len(), 1);
+        let labels: Vec<_> = errors[0]
+            .get_label()
+            .iter()
+            .map(|l| (l.get_name(), l.get_value()))
+            .collect();
+        assert_eq!(labels, [("error", "NodeExists"), ("op", "create")]);
+        let delivery = family("zookeeper_watch_delivery_seconds").get_metric();
+        assert_eq!(delivery[0].get_histogram().get_sample_count(), 1);
+        let in_flight = family("zookeeper_requests_in_flight").get_metric();
+        assert_eq!(in_flight[0].get_gauge().get_value(), 7.0);
+
+        // a second client cannot register the same metrics
+        assert!(PrometheusMetrics::new(&registry).is_err());
+    }
+}
@@ -0,0 +1,741 @@
+//! One-way mirroring of a subtree from one ZooKeeper ensemble to another.
+//!
+//! [`mirror`] copies a subtree of a source ensemble to a target ensemble, and then keeps the copy
+//! in sync by watching every source node: data changes, new nodes and deleted nodes are applied
+//! to the target as they happen. This lets a subtree be migrated to another datacenter while its
+//! clients keep using the source, and move over once the target has caught up.
+//!
+//! The mirror is driven by polling the stream that [`mirror`] returns, and stops when the stream
+//! is dropped. Every change that is applied is reported with how far the target lags behind the
+//! source, and every target node that was modified by someone other than the mirror is reported
+//! as a [`Conflict`].
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use futures::prelude::*;
+//! use tokio_zookeeper::mirror::{self, Event, MirrorOptions};
+//! # async fn run(source: ZooKeeper, target: ZooKeeper) -> Result<(), Error> {
+//! let events = mirror::mirror(&source, "/app", &target, "/app", MirrorOptions::default());
+//! futures::pin_mut!(events);
+//! while let Some(event) = events.try_next().await? {
+//!     match event {
+//!         Event::Synced { nodes } => println!("copied {} nodes", nodes),
+//!         Event::Applied { path, lag: Some(lag), .. } => println!("{}: {:?} behind", path, lag),
+//!         Event::Conflict { path, conflict } => eprintln!("{}: {:?}", path, conflict),
+//!         Event::Applied { .. } => {}
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::channel::oneshot;
+use futures::prelude::*;
+use futures::stream::{self, FuturesUnordered};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::export::{mode, SYSTEM};
+use crate::subtree::{is_within, join, rebase, CONCURRENCY};
+use crate::{error, Acl, CreateMode, Error, Stat, WatchedEvent, WatchedEventType, ZooKeeper};
+
+/// Options for [`mirror`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MirrorOptions {
+    /// The ACL to give every node that is created on the target. If `None`, each node gets the
+    /// ACL of its source node.
+    ///
+    /// Defaults to `None`.
+    pub acl: Option<Vec<Acl>>,
+
+    /// Also mirror ephemeral nodes. The copies are ephemeral nodes owned by the target client's
+    /// session.
+    ///
+    /// Defaults to `false`.
+    pub include_ephemerals: bool,
+
+    /// Overwrite target nodes that were found to conflict with the mirror, rather than leaving
+    /// them as they are. Either way, the conflict is reported.
+    ///
+    /// Defaults to `true`.
+    pub overwrite: bool,
+}
+
+impl Default for MirrorOptions {
+    fn default() -> Self {
+        MirrorOptions {
+            acl: None,
+            include_ephemerals: false,
+            overwrite: true,
+        }
+    }
+}
+
+/// What the mirror reports as it runs.
+///
+/// All paths are those of target nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The initial copy of the subtree is complete, and the target now matches the source as it
+    /// was read. This is always the first event.
+    Synced {
+        /// The number of source nodes that were mirrored.
+        nodes: usize,
+    },
+
+    /// A change to the source was applied to the target.
+    Applied {
+        /// The node that was changed.
+        path: String,
+        /// How the node was changed.
+        change: Change,
+        /// How long after the change was made to the source it was applied to the target,
+        /// according to the source server's clock and this client's. `None` for deletions, for
+        /// which the source keeps no time.
+        lag: Option<Duration>,
+    },
+
+    /// A target node was not in the state that the mirror left it in.
+    Conflict {
+        /// The node that conflicted.
+        path: String,
+        /// What was wrong with it.
+        conflict: Conflict,
+    },
+}
+
+/// How a target node was changed, as reported by [`Event::Applied`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The node was created.
+    Created,
+    /// The node's data was set.
+    Updated,
+    /// The node was deleted.
+    Deleted,
+}
+
+/// What was wrong with a target node, as reported by [`Event::Conflict`].
+///
+/// The target node is overwritten afterwards if [`MirrorOptions::overwrite`] is set, except for
+/// [`Conflict::Rejected`], after which the node is left as it is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Conflict {
+    /// The node's data was set since the mirror last wrote it.
+    Modified,
+    /// The node was to be created, but already existed.
+    Existed,
+    /// The node was to be updated, but had been deleted.
+    Missing,
+    /// The target ensemble refused to apply the change, for the given reason.
+    Rejected(String),
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Conflict::Modified => f.write_str("target node was modified"),
+            Conflict::Existed => f.write_str("target node already existed"),
+            Conflict::Missing => f.write_str("target node was deleted"),
+            Conflict::Rejected(ref reason) => write!(f, "target refused the change: {}", reason),
+        }
+    }
+}
+
+/// Mirror the node at `src` on the `source` ensemble and all of its descendants to `dst` on the
+/// `target` ensemble, and keep them in sync until the returned stream is dropped.
+///
+/// The mirror first copies the subtree, and deletes every target node under `dst` that has no
+/// source node, which it reports with [`Event::Synced`]. Conflicts are not reported during this
+/// initial copy, since the target is expected to differ from the source, for instance because
+/// it holds an earlier copy. From then on, every change to the source is applied to the target
+/// and reported as an [`Event::Applied`], and every target node that someone other than the
+/// mirror changed is reported as an [`Event::Conflict`]. If the source node at `src` does not
+/// exist, or is deleted, the target nodes are deleted, and the mirror waits for it to be
+/// created.
+///
+/// The parent of `dst` must already exist on the target. Ephemeral nodes are left out unless
+/// `options.include_ephemerals` is set, and so is the `/zookeeper` subtree, which servers keep
+/// their own state in, unless it is the one being mirrored. Only node data and the existence of
+/// nodes are watched: the ACL of a node is copied when it is created on the target, but later
+/// changes to it are not.
+///
+/// The stream ends after the first error that either client reports, including when the
+/// source client's watches are lost because its session expired. A new mirror of the same
+/// subtree resumes where the old one stopped, since its initial copy makes up for any changes
+/// that were missed in between.
+pub fn mirror(
+    source: &ZooKeeper,
+    src: &str,
+    target: &ZooKeeper,
+    dst: &str,
+    options: MirrorOptions,
+) -> impl Stream<Item = Result<Event, Error>> + Send + 'static {
+    let mirror = Mirror {
+        source: source.clone(),
+        target: target.clone(),
+        src: src.to_string(),
+        dst: dst.to_string(),
+        options,
+        children: HashMap::new(),
+        written: HashMap::new(),
+        armed: HashSet::new(),
+        watches: FuturesUnordered::new(),
+        events: VecDeque::new(),
+        syncing: true,
+    };
+    stream::unfold(Some(mirror), |mirror| async move {
+        let mut mirror = mirror?;
+        match mirror.next().await? {
+            Ok(event) => Some((Ok(event), Some(mirror))),
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+/// A watch that the mirror has set on the source.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Watched {
+    /// A data watch on the node at the given path.
+    Data(String),
+    /// A child watch on the node at the given path.
+    Children(String),
+    /// An existence watch on the root of the mirrored subtree, while it does not exist.
+    Root,
+}
+
+/// What the mirror last wrote to a target node.
+#[derive(Clone, Copy, Debug)]
+struct Written {
+    /// The target node's version after the write.
+    version: i32,
+    /// The `mzxid` of the source node whose data was written.
+    mzxid: i64,
+}
+
+/// A source node, as read by [`Mirror::read`].
+struct Node {
+    path: String,
+    data: Vec<u8>,
+    stat: Stat,
+    children: Vec<String>,
+    data_watch: Option<oneshot::Receiver<WatchedEvent>>,
+    child_watch: Option<oneshot::Receiver<WatchedEvent>>,
+}
+
+// `Sync`, so that the mirror can be borrowed across the requests it sends
+type WatchFuture =
+    Pin<Box<dyn Future<Output = (Watched, Result<WatchedEvent, oneshot::Canceled>)> + Send + Sync>>;
+
+struct Mirror {
+    source: ZooKeeper,
+    target: ZooKeeper,
+    src: String,
+    dst: String,
+    options: MirrorOptions,
+
+    /// The children of every source node that is mirrored, by its path.
+    children: HashMap<String, BTreeSet<String>>,
+    /// What was last written to the copy of every source node, by the source node's path.
+    written: HashMap<String, Written>,
+
+    /// The watches that are set on the source and have not fired yet. Each is only set once, so
+    /// that nodes that are read again do not pile up watches.
+    armed: HashSet<Watched>,
+    watches: FuturesUnordered<WatchFuture>,
+
+    /// Events that have yet to be yielded.
+    events: VecDeque<Event>,
+    /// Whether the initial copy is in progress.
+    syncing: bool,
+}
+
+impl Mirror {
+    async fn next(&mut self) -> Option<Result<Event, Error>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(Ok(event));
+            }
+            let res = if self.syncing {
+                self.sync_root().await
+            } else {
+                match self.watches.next().await {
+                    Some((watched, Ok(event))) => self.handle(watched, event).await,
+                    // the source client is gone, or its session expired
+                    Some((_, Err(oneshot::Canceled))) => Err(Error::ConnectionLoss),
+                    // the root is always watched, so this cannot happen
+                    None => return None,
+                }
+            };
+            if let Err(e) = res {
+                return Some(Err(e));
+            }
+        }
+    }
+
+    async fn handle(&mut self, watched: Watched, event: WatchedEvent) -> Result<(), Error> {
+        self.armed.remove(&watched);
+        let path = match watched {
+            Watched::Root if event.event_type == WatchedEventType::NodeCreated => {
+                return self.sync_root().await;
+            }
+            Watched::Data(ref path) | Watched::Children(ref path)
+                if self.children.contains_key(path) =>
+            {
+                path.clone()
+            }
+            _ => return Ok(()),
+        };
+        match event.event_type {
+            WatchedEventType::NodeDataChanged => {
+                match self.read_data(&path).await? {
+                    Some((data, stat)) => self.write(&path, data, &stat).await,
+                    None => self.resync(&path).await,
+                }
+            }
+            WatchedEventType::NodeChildrenChanged => {
+                let names = match self.read_children(&path).await? {
+                    Some(names) => names.into_iter().collect(),
+                    None => return self.resync(&path).await,
+                };
+                let old = self.children.insert(path.clone(), names).unwrap_or_default();
+                let new = &self.children[&path];
+                let changed: Vec<_> = old.symmetric_difference(new).cloned().collect();
+                for name in changed {
+                    self.resync(&join(&path, &name)).await?;
+                }
+                Ok(())
+            }
+            // the node was deleted, and may already have been created again
+            _ => self.resync(&path).await,
+        }
+    }
+
+    /// Mirror the root of the subtree, or watch for it to be created if it does not exist.
+    async fn sync_root(&mut self) -> Result<(), Error> {
+        let src = self.src.clone();
+        let nodes = loop {
+            match self.sync(&src).await? {
+                Some(nodes) => break nodes,
+                None => {
+                    let (rx, stat) = self.source.with_watcher().exists(&src).await?;
+                    self.arm(Watched::Root, rx);
+                    if stat.is_none() {
+                        break 0;
+                    }
+                }
+            }
+        };
+        if self.syncing {
+            self.syncing = false;
+            self.events.push_back(Event::Synced { nodes });
+        }
+        Ok(())
+    }
+
+    async fn resync(&mut self, path: &str) -> Result<(), Error> {
+        if path == self.src {
+            self.sync_root().await
+        } else {
+            self.sync(path).await.map(|_| ())
+        }
+    }
+
+    /// Bring the target copy of the source node at `path` and its descendants up to date with
+    /// the source, and delete the target nodes under it that have no source node.
+    ///
+    /// Resolves to the number of nodes that were mirrored, or `None` if the node at `path` does
+    /// not exist.
+    async fn sync(&mut self, path: &str) -> Result<Option<usize>, Error> {
+        self.children.retain(|node, _| !is_within(node, path));
+        let skip_system = !is_within(&self.src, SYSTEM);
+        let mut found = false;
+        let mut nodes = 0;
+        let mut level = vec![path.to_string()];
+        while !level.is_empty() {
+            let read: Vec<_> = stream::iter(level)
+                .map(|node| self.read(node))
+                .buffered(CONCURRENCY)
+                .try_collect()
+                .await?;
+            level = Vec::new();
+            for node in read.into_iter().flatten() {
+                found = true;
+                let Node {
+                    path,
+                    data,
+                    stat,
+                    children,
+                    data_watch,
+                    child_watch,
+                } = node;
+                if mode(stat.ephemeral_owner) == CreateMode::Ephemeral
+                    && !self.options.include_ephemerals
+                {
+                    continue;
+                }
+                if let Some(rx) = data_watch {
+                    self.arm(Watched::Data(path.clone()), rx);
+                }
+                if let Some(rx) = child_watch {
+                    self.arm(Watched::Children(path.clone()), rx);
+                }
+                level.extend(
+                    children
+                        .iter()
+                        .map(|child| join(&path, child))
+                        .filter(|child| !(skip_system && is_within(child, SYSTEM))),
+                );
+                self.children.insert(path.clone(), children.into_iter().collect());
+                self.write(&path, data, &stat).await?;
+                nodes += 1;
+            }
+        }
+        self.prune(path).await?;
+        Ok(if found { Some(nodes) } else { None })
+    }
+
+    /// Read the source node at `path`, setting whichever of its watches are not set yet.
+    async fn read(&self, path: String) -> Result<Option<Node>, Error> {
+        let (data_watch, data, stat) = if self.armed.contains(&Watched::Data(path.clone())) {
+            match self.source.get_data(&path).await? {
+                Some((data, stat)) => (None, data, stat),
+                None => return Ok(None),
+            }
+        } else {
+            match self.source.with_watcher().get_data(&path).await? {
+                Some((rx, data, stat)) => (Some(rx), data, stat),
+                None => return Ok(None),
+            }
+        };
+        let (child_watch, children) = if self.armed.contains(&Watched::Children(path.clone())) {
+            match self.source.get_children(&path).await? {
+                Some(children) => (None, children),
+                None => return Ok(None),
+            }
+        } else {
+            match self.source.with_watcher().get_children(&path).await? {
+                Some((rx, children)) => (Some(rx), children),
+                None => return Ok(None),
+            }
+        };
+        Ok(Some(Node {
+            path,
+            data,
+            stat,
+            children,
+            data_watch,
+            child_watch,
+        }))
+    }
+
+    /// Read the data of the source node at `path`, and set its data watch again.
+    async fn read_data(&mut self, path: &str) -> Result<Option<(Vec<u8>, Stat)>, Error> {
+        match self.source.with_watcher().get_data(path).await? {
+            Some((rx, data, stat)) => {
+                self.arm(Watched::Data(path.to_string()), rx);
+                Ok(Some((data, stat)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Read the children of the source node at `path`, and set its child watch again.
+    async fn read_children(&mut self, path: &str) -> Result<Option<Vec<String>>, Error> {
+        match self.source.with_watcher().get_children(path).await? {
+            Some((rx, children)) => {
+                self.arm(Watched::Children(path.to_string()), rx);
+                Ok(Some(children))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn arm(&mut self, watched: Watched, rx: oneshot::Receiver<WatchedEvent>) {
+        if self.armed.insert(watched.clone()) {
+            self.watches.push(Box::pin(rx.map(move |res| (watched, res))));
+        }
+    }
+
+    /// Write the `data` of the source node at `path` to its target copy.
+    async fn write(&mut self, path: &str, data: Vec<u8>, stat: &Stat) -> Result<(), Error> {
+        let written = self.written.get(path).copied();
+        if written.is_some_and(|w| w.mzxid == stat.mzxid) {
+            return Ok(());
+        }
+        let target = rebase(path, &self.src, &self.dst);
+        let data: Cow<'static, [u8]> = data.into();
+        let conflict = match written {
+            Some(written) => {
+                let version = Some(written.version);
+                match self.target.set_data(&target, version, data.clone()).await? {
+                    Ok(new) => {
+                        self.applied(path, target, new.version, stat, Change::Updated);
+                        return Ok(());
+                    }
+                    Err(error::SetData::BadVersion { .. }) => Conflict::Modified,
+                    Err(error::SetData::NoNode { .. }) => Conflict::Missing,
+                    Err(e) => {
+                        self.reject(target, e);
+                        return Ok(());
+                    }
+                }
+            }
+            None => match self.create(path, &target, data.clone(), stat).await? {
+                Some(Ok(())) => {
+                    self.applied(path, target, 0, stat, Change::Created);
+                    return Ok(());
+                }
+                Some(Err(error::Create::NodeExists { .. })) => Conflict::Existed,
+                Some(Err(e)) => {
+                    self.reject(target, e);
+                    return Ok(());
+                }
+                // the source node is gone, which its watches will tell us about
+                None => return Ok(()),
+            },
+        };
+        if !self.syncing {
+            self.events.push_back(Event::Conflict {
+                path: target.clone(),
+                conflict,
+            });
+            if !self.options.overwrite {
+                return Ok(());
+            }
+        }
+
+        match self.target.set_data(&target, None, data.clone()).await? {
+            Ok(new) => self.applied(path, target, new.version, stat, Change::Updated),
+            Err(error::SetData::NoNode { .. }) => {
+                match self.create(path, &target, data, stat).await? {
+                    Some(Ok(())) => self.applied(path, target, 0, stat, Change::Created),
+                    Some(Err(e)) => self.reject(target, e),
+                    None => {}
+                }
+            }
+            Err(e) => self.reject(target, e),
+        }
+        Ok(())
+    }
+
+    /// Create the target copy of the source node at `path`, or resolve to `None` if the source
+    /// node's ACL could not be read because it no longer exists.
+    async fn create(
+        &self,
+        path: &str,
+        target: &str,
+        data: Cow<'static, [u8]>,
+        stat: &Stat,
+    ) -> Result<Option<Result<(), error::Create>>, Error> {
+        let acl = match self.options.acl {
+            Some(ref acl) => acl.clone(),
+            None => match self.source.get_acl(path).await? {
+                Ok((acl, _)) => acl,
+                Err(_) => return Ok(None),
+            },
+        };
+        let mode = mode(stat.ephemeral_owner);
+        let res = self.target.create(target, data, acl, mode).await?;
+        Ok(Some(res.map(|_| ())))
+    }
+
+    /// Delete the target nodes under the copy of the source node at `path` whose source nodes
+    /// are not mirrored, children before their parents.
+    async fn prune(&mut self, path: &str) -> Result<(), Error> {
+        let target = rebase(path, &self.src, &self.dst);
+        let nodes = match self.target.list_subtree(&target).await? {
+            Some(nodes) => nodes,
+            None => return Ok(()),
+        };
+        let skip_system = !is_within(&self.dst, SYSTEM);
+        for node in nodes.into_iter().rev() {
+            let source = rebase(&node, &self.dst, &self.src);
+            if self.children.contains_key(&source) || (skip_system && is_within(&node, SYSTEM)) {
+                continue;
+            }
+            match self.target.delete(&node, None).await? {
+                Ok(()) | Err(error::Delete::NoNode { .. }) => {
+                    self.written.remove(&source);
+                    if !self.syncing {
+                        self.events.push_back(Event::Applied {
+                            path: node,
+                            change: Change::Deleted,
+                            lag: None,
+                        });
+                    }
+                }
+                Err(e) => self.reject(node, e),
+            }
+        }
+        Ok(())
+    }
+
+    fn applied(&mut self, path: &str, target: String, version: i32, stat: &Stat, change: Change) {
+        let written = Written {
+            version,
+            mzxid: stat.mzxid,
+        };
+        self.written.insert(path.to_string(), written);
+        if !self.syncing {
+            self.events.push_back(Event::Applied {
+                path: target,
+                change,
+                lag: lag(stat.mtime),
+            });
+        }
+    }
+
+    fn reject<E: fmt::Display>(&mut self, target: String, error: E) {
+        self.events.push_back(Event::Conflict {
+            path: target,
+            conflict: Conflict::Rejected(error.to_string()),
+        });
+    }
+}
+
+/// How long ago the given time, in milliseconds since the epoch, was.
+fn lag(mtime: i64) -> Option<Duration> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    // the clocks of the source server and of this client may disagree by a little
+    Some(Duration::from_millis((now.as_millis() as i64 - mtime).max(0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockZk;
+
+    async fn next<S>(events: &mut S) -> Event
+    where
+        S: Stream<Item = Result<Event, Error>> + Unpin,
+    {
+        events.next().await.unwrap().unwrap()
+    }
+
+    fn applied(event: Event) -> (String, Change) {
+        match event {
+            Event::Applied { path, change, .. } => (path, change),
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn mirrors_changes() {
+        let (source, target) = (MockZk::new(), MockZk::new());
+        let (src, _) = source.connect().await.unwrap();
+        let (dst, _) = target.connect().await.unwrap();
+        let open = Acl::open_unsafe();
+        for &(path, data) in &[("/app", &b"root"[..]), ("/app/a", b"a"), ("/app/a/b", b"b")] {
+            src.create(path, data, open, CreateMode::Persistent).await.unwrap().unwrap();
+        }
+        let e = CreateMode::Ephemeral;
+        src.create("/app/e", &b""[..], open, e).await.unwrap().unwrap();
+        // left over from an earlier copy
+        dst.create("/copy", &b"old"[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+        dst.create("/copy/x", &b""[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+
+        let events = mirror(&src, "/app", &dst, "/copy", MirrorOptions::default());
+        futures::pin_mut!(events);
+        assert_eq!(next(&mut events).await, Event::Synced { nodes: 3 });
+        let copied = dst.list_subtree("/copy").await.unwrap().unwrap();
+        assert_eq!(copied, ["/copy", "/copy/a", "/copy/a/b"]);
+        assert_eq!(dst.get_data("/copy").await.unwrap().unwrap().0, b"root");
+        assert!(dst.exists("/copy/e").await.unwrap().is_none());
+
+        src.set_data("/app/a", None, &b"new"[..]).await.unwrap().unwrap();
+        match next(&mut events).await {
+            Event::Applied {
+                path,
+                change: Change::Updated,
+                lag: Some(_),
+            } => assert_eq!(path, "/copy/a"),
+            event => panic!("unexpected {:?}", event),
+        }
+        assert_eq!(dst.get_data("/copy/a").await.unwrap().unwrap().0, b"new");
+
+        src.create("/app/a/c", &b"c"[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+        let created = ("/copy/a/c".to_string(), Change::Created);
+        assert_eq!(applied(next(&mut events).await), created);
+        assert_eq!(dst.get_data("/copy/a/c").await.unwrap().unwrap().0, b"c");
+
+        src.delete("/app/a/b", None).await.unwrap().unwrap();
+        let deleted = ("/copy/a/b".to_string(), Change::Deleted);
+        assert_eq!(applied(next(&mut events).await), deleted);
+        assert!(dst.exists("/copy/a/b").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn conflicts() {
+        let (source, target) = (MockZk::new(), MockZk::new());
+        let (src, _) = source.connect().await.unwrap();
+        let (dst, _) = target.connect().await.unwrap();
+        let open = Acl::open_unsafe();
+        src.create("/app", &b""[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+        src.create("/app/a", &b"a"[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+
+        let options = MirrorOptions {
+            overwrite: false,
+            ..Default::default()
+        };
+        let events = mirror(&src, "/app", &dst, "/app", options);
+        futures::pin_mut!(events);
+        assert_eq!(next(&mut events).await, Event::Synced { nodes: 2 });
+
+        dst.set_data("/app/a", None, &b"theirs"[..]).await.unwrap().unwrap();
+        src.set_data("/app/a", None, &b"ours"[..]).await.unwrap().unwrap();
+        let conflict = Event::Conflict {
+            path: "/app/a".to_string(),
+            conflict: Conflict::Modified,
+        };
+        assert_eq!(next(&mut events).await, conflict);
+        assert_eq!(dst.get_data("/app/a").await.unwrap().unwrap().0, b"theirs");
+
+        // the source's root goes away, and comes back
+        src.delete("/app/a", None).await.unwrap().unwrap();
+        let deleted = ("/app/a".to_string(), Change::Deleted);
+        assert_eq!(applied(next(&mut events).await), deleted);
+        src.delete("/app", None).await.unwrap().unwrap();
+        let deleted = ("/app".to_string(), Change::Deleted);
+        assert_eq!(applied(next(&mut events).await), deleted);
+        assert!(dst.exists("/app").await.unwrap().is_none());
+        src.create("/app", &b"back"[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+        let created = ("/app".to_string(), Change::Created);
+        assert_eq!(applied(next(&mut events).await), created);
+        assert_eq!(dst.get_data("/app").await.unwrap().unwrap().0, b"back");
+
+        source.expire_session(src.session().id);
+        assert!(events.next().await.unwrap().is_err());
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn rejections() {
+        let (source, target) = (MockZk::new(), MockZk::new());
+        let (src, _) = source.connect().await.unwrap();
+        let (dst, _) = target.connect().await.unwrap();
+        let open = Acl::open_unsafe();
+        src.create("/app", &b""[..], open, CreateMode::Persistent).await.unwrap().unwrap();
+
+        // the target's parent does not exist, which is reported even during the initial copy
+        let events = mirror(&src, "/app", &dst, "/missing/app", MirrorOptions::default());
+        futures::pin_mut!(events);
+        match next(&mut events).await {
+            Event::Conflict {
+                path,
+                conflict: Conflict::Rejected(_),
+            } => assert_eq!(path, "/missing/app"),
+            event => panic!("unexpected {:?}", event),
+        }
+        assert_eq!(next(&mut events).await, Event::Synced { nodes: 1 });
+    }
+
+    #[test]
+    fn lags() {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+        assert!(lag(now - 1000).unwrap() >= Duration::from_secs(1));
+        assert_eq!(lag(now + 60_000), Some(Duration::from_millis(0)));
+    }
+}
@@ -0,0 +1,91 @@
+use std::sync::Arc;
+use crate::interceptor::{Interceptor, Interceptors};
+use crate::WatchedEvent;
+
+/// The path prefix that a [`ZooKeeper`](struct.ZooKeeper.html) handle transparently adds to the
+/// paths it sends to the server, and removes from the paths it receives.
+///
+/// The namespace also carries the interceptors of the handle, so that the watches set through the
+/// handle pass their events through them before the prefix is removed.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Namespace {
+    prefix: Option<Arc<str>>,
+    interceptors: Interceptors,
+}
+
+impl Namespace {
+    /// Return the namespace nested inside this one at the (absolute and valid) `path`.
+    pub(crate) fn nest(&self, path: &str) -> Namespace {
+        if path == "/" {
+            return self.clone();
+        }
+        Namespace {
+            prefix: Some(self.resolve(path).into()),
+            interceptors: self.interceptors.clone(),
+        }
+    }
+
+    /// Return this namespace, with its interceptors wrapped in `interceptor`.
+    pub(crate) fn intercepted(&self, interceptor: Arc<dyn Interceptor>) -> Namespace {
+        Namespace {
+            prefix: self.prefix.clone(),
+            interceptors: self.interceptors.with(interceptor),
+        }
+    }
+
+    pub(crate) fn interceptors(&self) -> &Interceptors {
+        &self.interceptors
+    }
+
+    /// Map a path relative to this namespace to the corresponding path on the server.
+    pub(crate) fn resolve(&self, path: &str) -> String {
+        match self.prefix {
+            None => path.to_string(),
+            Some(ref prefix) if path == "/" => prefix.to_string(),
+            Some(ref prefix) => format!("{}{}", prefix, path),
+        }
+    }
+
+    /// Map a path on the server to the corresponding path relative to this namespace.
+    pub(crate) fn strip(&self, path: &str) -> String {
+        let prefix = match self.prefix {
+            Some(ref prefix) if path.starts_with(&**prefix) => prefix,
+            _ => return path.to_string(),
+        };
+        match &path[prefix.len()..] {
+            "" => String::from("/"),
+            relative if relative.starts_with('/') => relative.to_string(),
+            // a sibling that merely shares the prefix, such as /ab for /a
+            _ => path.to_string(),
+        }
+    }
+
+    /// Map the event `e` of a watch set in this namespace to the event that its setter sees.
+    pub(crate) fn present(&self, e: &WatchedEvent) -> WatchedEvent {
+        let mut event = e.clone();
+        self.interceptors.on_event(&mut event);
+        event.path = self.strip(&event.path);
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_and_strip() {
+        let root = Namespace::default();
+        assert_eq!(root.resolve("/a"), "/a");
+        assert_eq!(root.strip("/a"), "/a");
+
+        let ns = root.nest("/tenants").nest("/acme");
+        assert_eq!(ns, root.nest("/tenants/acme"));
+        assert_eq!(ns, ns.nest("/"));
+        assert_eq!(ns.resolve("/"), "/tenants/acme");
+        assert_eq!(ns.resolve("/a/b"), "/tenants/acme/a/b");
+        assert_eq!(ns.strip("/tenants/acme"), "/");
+        assert_eq!(ns.strip("/tenants/acme/a/b"), "/a/b");
+        assert_eq!(ns.strip("/tenants/acmecorp"), "/tenants/acmecorp");
+    }
+}
@@ -0,0 +1,219 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use std::error::Error as StdError;
+use std::fmt;
+use super::PayloadCodec;
+
+const MAGIC: &[u8] = b"\xc0ae";
+
+/// How many bytes the nonce of every encrypted payload is.
+const NONCE: usize = 12;
+
+/// Where [`AesGcm`] gets its keys from.
+///
+/// Every key has an id, which is stored with the data it encrypted, so that keys can be rotated:
+/// new data is encrypted with the [`current`](KeyProvider::current) key, and data that was
+/// encrypted with an older one stays readable for as long as [`get`](KeyProvider::get) still
+/// returns it.
+pub trait KeyProvider: Send + Sync {
+    /// The id of the key to encrypt data with, and the key.
+    fn current(&self) -> Result<(u32, [u8; 32]), Box<dyn StdError + Send + Sync>>;
+
+    /// The key with the given id, to decrypt data that was encrypted with it.
+    fn get(&self, id: u32) -> Result<[u8; 32], Box<dyn StdError + Send + Sync>>;
+}
+
+/// A [`KeyProvider`] with a single key.
+#[derive(Clone, Copy)]
+pub struct StaticKey {
+    id: u32,
+    key: [u8; 32],
+}
+
+impl StaticKey {
+    /// Encrypt and decrypt data with `key`, under the id `id`.
+    pub fn new(id: u32, key: [u8; 32]) -> Self {
+        StaticKey { id, key }
+    }
+}
+
+impl KeyProvider for StaticKey {
+    fn current(&self) -> Result<(u32, [u8; 32]), Box<dyn StdError + Send + Sync>> {
+        Ok((self.id, self.key))
+    }
+
+    fn get(&self, id: u32) -> Result<[u8; 32], Box<dyn StdError + Send + Sync>> {
+        if id != self.id {
+            return Err(format!("no key with id {}", id).into());
+        }
+        Ok(self.key)
+    }
+}
+
+impl fmt::Debug for StaticKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StaticKey").field("id", &self.id).finish_non_exhaustive()
+    }
+}
+
+/// Encrypts data with AES-256-GCM, under keys from a [`KeyProvider`].
+///
+/// The stored bytes are the magic, `0xC0` followed by `ae`, the id of the key as four big-endian
+/// bytes, a random 12-byte nonce, and the ciphertext with its tag. The magic and the key id are
+/// authenticated along with the data, so data whose key id was tampered with fails to decrypt.
+///
+/// Encrypted data does not compress, so a compressing codec has to come before this one; see
+/// [`Payloads::then`](super::Payloads::then).
+///
+/// Data that was not encrypted is not read, since anyone who may write a node could store
+/// whatever they like in it otherwise; see [`allow_plaintext`](AesGcm::allow_plaintext).
+pub struct AesGcm<K> {
+    keys: K,
+    required: bool,
+}
+
+impl<K: KeyProvider> AesGcm<K> {
+    /// Encrypt data with the keys of `keys`.
+    pub fn new(keys: K) -> Self {
+        AesGcm {
+            keys,
+            required: true,
+        }
+    }
+
+    /// Read data that was not encrypted as it is, for instance while the nodes that were written
+    /// before encryption was introduced are being rewritten.
+    pub fn allow_plaintext(mut self) -> Self {
+        self.required = false;
+        self
+    }
+}
+
+impl<K> fmt::Debug for AesGcm<K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AesGcm")
+            .field("required", &self.required)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: KeyProvider> PayloadCodec for AesGcm<K> {
+    fn magic(&self) -> &[u8] {
+        MAGIC
+    }
+
+    fn required(&self) -> bool {
+        self.required
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        let (id, key) = self.keys.current()?;
+        let header = [MAGIC, &id.to_be_bytes()[..]].concat();
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = Aes256Gcm::new(&key.into())
+            .encrypt(&nonce, Payload { msg: data, aad: &header })
+            .map_err(|_| "failed to encrypt")?;
+        let mut encoded = id.to_be_bytes().to_vec();
+        encoded.extend_from_slice(&nonce);
+        encoded.extend(sealed);
+        Ok(encoded)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        if data.len() < 4 + NONCE {
+            return Err("encrypted data is truncated".into());
+        }
+        let (id, rest) = data.split_at(4);
+        let (nonce, sealed) = rest.split_at(NONCE);
+        let key = self.keys.get(u32::from_be_bytes([id[0], id[1], id[2], id[3]]))?;
+        let header = [MAGIC, id].concat();
+        let opened = Aes256Gcm::new(&key.into())
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: sealed, aad: &header })
+            .map_err(|_| "failed to decrypt, the data or its key id was tampered with")?;
+        Ok(opened)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use super::*;
+    use crate::payload::Payloads;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode, Error};
+
+    /// Encrypts with key 2, but still has key 1.
+    struct Rotated;
+
+    impl KeyProvider for Rotated {
+        fn current(&self) -> Result<(u32, [u8; 32]), Box<dyn StdError + Send + Sync>> {
+            Ok((2, [2; 32]))
+        }
+
+        fn get(&self, id: u32) -> Result<[u8; 32], Box<dyn StdError + Send + Sync>> {
+            match id {
+                1 => Ok([1; 32]),
+                2 => Ok([2; 32]),
+                _ => Err("unknown key".into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn encrypts() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let old = zk.with_interceptor(Arc::new(Payloads::new(AesGcm::new(StaticKey::new(
+            1,
+            [1; 32],
+        )))));
+        let new = zk.with_interceptor(Arc::new(Payloads::new(AesGcm::new(Rotated))));
+        let acl = Acl::open_unsafe();
+
+        old.create("/secret", &b"hunter2"[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let (stored, _) = zk.get_data("/secret").await.unwrap().unwrap();
+        assert!(stored.starts_with(b"\xc0ae\0\0\0\x01"));
+        assert!(!stored.windows(7).any(|w| w == b"hunter2"));
+        let (data, _) = new.get_data("/secret").await.unwrap().unwrap();
+        assert_eq!(data, b"hunter2");
+
+        new.set_data("/secret", None, &b"hunter3"[..]).await.unwrap().unwrap();
+        let read = old.get_data("/secret").await;
+        assert!(matches!(read, Err(Error::Codec { .. })));
+
+        // data that was tampered with is not read
+        let (mut stored, _) = zk.get_data("/secret").await.unwrap().unwrap();
+        stored[6] = 1;
+        zk.set_data("/secret", None, stored).await.unwrap().unwrap();
+        let read = new.get_data("/secret").await;
+        assert!(matches!(read, Err(Error::Codec { .. })));
+    }
+
+    #[tokio::test]
+    async fn plaintext() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let strict = zk.with_interceptor(Arc::new(Payloads::new(AesGcm::new(Rotated))));
+        let lenient = zk.with_interceptor(Arc::new(Payloads::new(
+            AesGcm::new(Rotated).allow_plaintext(),
+        )));
+        let acl = Acl::open_unsafe();
+
+        zk.create("/plain", &b"hunter2"[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let read = strict.get_data("/plain").await;
+        assert!(matches!(read, Err(Error::Codec { ref path, .. }) if path == "/plain"));
+        let (data, _) = lenient.get_data("/plain").await.unwrap().unwrap();
+        assert_eq!(data, b"hunter2");
+
+        // what is written is encrypted either way
+        lenient.set_data("/plain", None, &b"hunter3"[..]).await.unwrap().unwrap();
+        let (data, _) = strict.get_data("/plain").await.unwrap().unwrap();
+        assert_eq!(data, b"hunter3");
+    }
+}
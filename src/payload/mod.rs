@@ -0,0 +1,463 @@
+//! Encoding node data on its way to and from the server, for instance to compress it.
+//!
+//! A [`PayloadCodec`] turns the data that a handle creates nodes with or sets on them into the
+//! bytes that are stored, and those bytes back into the data when it is read. [`Payloads`] is an
+//! [`Interceptor`] that applies codecs to every request made through a handle, so that callers
+//! keep reading and writing plain data:
+//!
+//! ```no_run
+//! # extern crate tokio_zookeeper;
+//! use std::sync::Arc;
+//! use tokio_zookeeper::payload::{Gzip, Payloads};
+//! use tokio_zookeeper::ZooKeeper;
+//!
+//! # fn run(zk: ZooKeeper) {
+//! let zk = zk.with_interceptor(Arc::new(Payloads::new(Gzip::default())));
+//! # }
+//! ```
+//!
+//! The bytes that a codec stores start with its [`magic`](PayloadCodec::magic), which is how data
+//! is told apart from data that was stored without the codec. Such data is returned as it is, so
+//! a codec can be introduced without rewriting the nodes that already exist, and nodes that are
+//! read without the codec are still readable with it. That is, unless the codec is
+//! [`required`](PayloadCodec::required), as [`AesGcm`] is unless told otherwise: whoever may
+//! write a node could otherwise store plaintext in it that readers take for decrypted data. Data
+//! that is missing the magic of a required codec, or that does start with the magic of one of the
+//! codecs but that the codec fails to decode, makes the request fail with [`Error::Codec`].
+//!
+//! [`Gzip`] is available with the `gzip` feature, and [`Zstd`] with the `zstd` feature. With the
+//! `aes-gcm` feature, [`AesGcm`] encrypts data under keys from a [`KeyProvider`], so that it is
+//! ciphertext both on the wire and on the servers' disks:
+//!
+//! ```no_run
+//! # extern crate tokio_zookeeper;
+//! # #[cfg(all(feature = "gzip", feature = "aes-gcm"))]
+//! # fn run(zk: tokio_zookeeper::ZooKeeper, key: [u8; 32]) {
+//! use std::sync::Arc;
+//! use tokio_zookeeper::payload::{AesGcm, Gzip, Payloads, StaticKey};
+//!
+//! let payloads = Payloads::new(Gzip::default()).then(AesGcm::new(StaticKey::new(1, key)));
+//! let zk = zk.with_interceptor(Arc::new(payloads));
+//! # }
+//! ```
+//!
+//! [`Interceptor`]: crate::interceptor::Interceptor
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+use crate::interceptor::{Interceptor, Request, Response};
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::proto::DEFAULT_MAX_REQUEST_SIZE;
+use crate::{Error, ZkError};
+
+#[cfg(feature = "aes-gcm")]
+mod encrypt;
+#[cfg(feature = "aes-gcm")]
+pub use self::encrypt::{AesGcm, KeyProvider, StaticKey};
+
+/// A way of encoding the data of nodes.
+pub trait PayloadCodec: Send + Sync {
+    /// The bytes that the data this codec encodes starts with when it is stored.
+    ///
+    /// [`Payloads`] adds them in front of what [`encode`](PayloadCodec::encode) returns, and only
+    /// hands data that starts with them to [`decode`](PayloadCodec::decode), without them. They
+    /// should be unlikely to start data that was stored without the codec.
+    fn magic(&self) -> &[u8];
+
+    /// Whether `data` should be encoded, or stored as it is.
+    ///
+    /// Defaults to always encoding it.
+    fn applies_to(&self, data: &[u8]) -> bool {
+        let _ = data;
+        true
+    }
+
+    /// Whether data that does not start with the magic is rejected, rather than being returned as
+    /// it is.
+    ///
+    /// A codec that is required should apply to all data. Defaults to `false`.
+    fn required(&self) -> bool {
+        false
+    }
+
+    /// Encode `data` into the bytes to store, after the magic.
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>>;
+
+    /// Decode the bytes that were stored after the magic back into the data.
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>>;
+}
+
+/// An interceptor that encodes the data that nodes are created with or set to with one or more
+/// [`PayloadCodec`]s, and decodes the data that is read.
+///
+/// Codecs are applied in the order they were added in, each to the output of the one before it,
+/// so compression should come before anything that makes data look random, such as encryption.
+#[derive(Clone)]
+pub struct Payloads {
+    codecs: Vec<Arc<dyn PayloadCodec>>,
+}
+
+impl Payloads {
+    /// Encode data with `codec`.
+    pub fn new<C: PayloadCodec + 'static>(codec: C) -> Self {
+        Payloads {
+            codecs: vec![Arc::new(codec)],
+        }
+    }
+
+    /// Also encode data with `codec`, after the codecs that were added before it.
+    pub fn then<C: PayloadCodec + 'static>(mut self, codec: C) -> Self {
+        self.codecs.push(Arc::new(codec));
+        self
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        let mut data = data.to_vec();
+        for codec in &self.codecs {
+            if codec.applies_to(&data) {
+                let mut encoded = codec.magic().to_vec();
+                encoded.extend(codec.encode(&data)?);
+                data = encoded;
+            }
+        }
+        Ok(data)
+    }
+
+    /// Decode `data`, or return `None` if none of the codecs encoded it.
+    fn decode(&self, data: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn StdError + Send + Sync>> {
+        let mut decoded: Option<Vec<u8>> = None;
+        for codec in self.codecs.iter().rev() {
+            let current = decoded.as_deref().unwrap_or(data);
+            if let Some(rest) = current.strip_prefix(codec.magic()) {
+                decoded = Some(codec.decode(rest)?);
+            } else if codec.required() {
+                return Err("data is not encoded with a codec that is required".into());
+            }
+        }
+        Ok(decoded)
+    }
+}
+
+impl Interceptor for Payloads {
+    fn before(&self, request: &mut Request<'_>) -> Result<(), ZkError> {
+        if let Some(data) = request.data() {
+            match self.encode(data) {
+                Ok(encoded) => request.set_data(encoded),
+                Err(error) => {
+                    let path = request.path().unwrap_or_default().to_string();
+                    request.fail(Error::Codec { path, error });
+                }
+            }
+        }
+        for mut part in request.parts() {
+            self.before(&mut part)?;
+        }
+        Ok(())
+    }
+
+    fn after(&self, response: &mut Response<'_>) {
+        if let Some(data) = response.data() {
+            match self.decode(data) {
+                Ok(Some(decoded)) => response.set_data(decoded),
+                Ok(None) => {}
+                Err(error) => {
+                    let path = response.path().unwrap_or_default().to_string();
+                    response.fail(Error::Codec { path, error });
+                }
+            }
+        }
+        for mut part in response.parts() {
+            self.after(&mut part);
+        }
+    }
+}
+
+impl fmt::Debug for Payloads {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let magics: Vec<_> = self.codecs.iter().map(|codec| codec.magic()).collect();
+        f.debug_struct("Payloads").field("magics", &magics).finish()
+    }
+}
+
+/// Compresses data with gzip.
+///
+/// Its magic is `0xC0` followed by `gz`; `0xC0` never starts UTF-8 text, so text such as JSON
+/// that was stored without the codec is never mistaken for its output.
+#[cfg(feature = "gzip")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Gzip {
+    /// The compression level, from 0 for none to 9 for the best.
+    ///
+    /// Defaults to 6.
+    pub level: u32,
+
+    /// Store data that is shorter than this many bytes as it is, since compressing it would save
+    /// little, if anything.
+    ///
+    /// Defaults to 512.
+    pub min_size: usize,
+
+    /// Fail to decode data that decompresses to more than this many bytes, rather than running
+    /// out of memory on data that was made to decompress to far more than it takes up.
+    ///
+    /// Defaults to the default `jute.maxbuffer`, which no node's data can be larger than.
+    pub max_size: usize,
+}
+
+#[cfg(feature = "gzip")]
+impl Default for Gzip {
+    fn default() -> Self {
+        Gzip {
+            level: 6,
+            min_size: 512,
+            max_size: DEFAULT_MAX_REQUEST_SIZE,
+        }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl PayloadCodec for Gzip {
+    fn magic(&self) -> &[u8] {
+        b"\xc0gz"
+    }
+
+    fn applies_to(&self, data: &[u8]) -> bool {
+        data.len() >= self.min_size
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        use std::io::Write;
+        let level = flate2::Compression::new(self.level);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+        encoder.write_all(data)?;
+        Ok(encoder.finish()?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        decompress(flate2::read::GzDecoder::new(data), self.max_size)
+    }
+}
+
+/// Compresses data with zstd.
+///
+/// Its magic is `0xC0` followed by `zs`; see [`Gzip`] for why.
+#[cfg(feature = "zstd")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Zstd {
+    /// The compression level, from 1 for the fastest to 22 for the best.
+    ///
+    /// Defaults to 3.
+    pub level: i32,
+
+    /// Store data that is shorter than this many bytes as it is, since compressing it would save
+    /// little, if anything.
+    ///
+    /// Defaults to 512.
+    pub min_size: usize,
+
+    /// Fail to decode data that decompresses to more than this many bytes, rather than running
+    /// out of memory on data that was made to decompress to far more than it takes up.
+    ///
+    /// Defaults to the default `jute.maxbuffer`, which no node's data can be larger than.
+    pub max_size: usize,
+}
+
+#[cfg(feature = "zstd")]
+impl Default for Zstd {
+    fn default() -> Self {
+        Zstd {
+            level: 3,
+            min_size: 512,
+            max_size: DEFAULT_MAX_REQUEST_SIZE,
+        }
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl PayloadCodec for Zstd {
+    fn magic(&self) -> &[u8] {
+        b"\xc0zs"
+    }
+
+    fn applies_to(&self, data: &[u8]) -> bool {
+        data.len() >= self.min_size
+    }
+
+    fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        Ok(zstd::encode_all(data, self.level)?)
+    }
+
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        decompress(zstd::stream::read::Decoder::new(data)?, self.max_size)
+    }
+}
+
+/// Read all of what `decoder` decompresses, unless that is more than `max_size` bytes.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn decompress<R: std::io::Read>(
+    decoder: R,
+    max_size: usize,
+) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+    use std::io::Read;
+    let mut decoded = Vec::new();
+    decoder.take(max_size as u64 + 1).read_to_end(&mut decoded)?;
+    if decoded.len() > max_size {
+        return Err(format!("data decompresses to more than {} bytes", max_size).into());
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode};
+
+    /// Stores data reversed.
+    struct Reversed;
+
+    impl PayloadCodec for Reversed {
+        fn magic(&self) -> &[u8] {
+            b"R:"
+        }
+
+        fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+            Ok(data.iter().rev().copied().collect())
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+            if data.is_empty() {
+                return Err("nothing to reverse".into());
+            }
+            Ok(data.iter().rev().copied().collect())
+        }
+    }
+
+    /// Stores data with every byte incremented, unless it is a single byte long.
+    struct Shifted;
+
+    impl PayloadCodec for Shifted {
+        fn magic(&self) -> &[u8] {
+            b"S:"
+        }
+
+        fn applies_to(&self, data: &[u8]) -> bool {
+            data.len() > 1
+        }
+
+        fn encode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+            Ok(data.iter().map(|b| b + 1).collect())
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+            Ok(data.iter().map(|b| b - 1).collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn encodes() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let encoded = zk.with_interceptor(Arc::new(Payloads::new(Shifted).then(Reversed)));
+        let acl = Acl::open_unsafe();
+
+        encoded
+            .create("/p", &b"abc"[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let (data, _) = encoded.get_data("/p").await.unwrap().unwrap();
+        assert_eq!(data, b"abc");
+        let (stored, _) = zk.get_data("/p").await.unwrap().unwrap();
+        assert_eq!(stored, b"R:dcb:S");
+
+        // a codec that does not apply leaves the data to the next one
+        encoded.set_data("/p", None, &b"x"[..]).await.unwrap().unwrap();
+        let (stored, _) = zk.get_data("/p").await.unwrap().unwrap();
+        assert_eq!(stored, b"R:x");
+        let (data, _) = encoded.get_data("/p").await.unwrap().unwrap();
+        assert_eq!(data, b"x");
+
+        // data that was stored without the codecs is read as it is
+        zk.set_data("/p", None, &b"plain"[..]).await.unwrap().unwrap();
+        let (data, _) = encoded.get_data("/p").await.unwrap().unwrap();
+        assert_eq!(data, b"plain");
+
+        let results = encoded
+            .multi()
+            .set_data("/p", None, &b"multi"[..])
+            .run()
+            .await
+            .unwrap();
+        assert!(results[0].is_ok());
+        let (data, _) = encoded.get_data("/p").await.unwrap().unwrap();
+        assert_eq!(data, b"multi");
+
+        // data that looks encoded but is not fails to be read
+        zk.set_data("/p", None, &b"R:"[..]).await.unwrap().unwrap();
+        let read = encoded.get_data("/p").await;
+        assert!(matches!(read, Err(Error::Codec { ref path, .. }) if path == "/p"));
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
+    #[tokio::test]
+    async fn compresses() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let json = format!("[{}]", vec!["{\"key\": \"value\"}"; 1000].join(", "));
+        let acl = Acl::open_unsafe();
+        zk.create("/c", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+
+        for (payloads, magic) in [
+            (Payloads::new(Gzip::default()), &b"\xc0gz"[..]),
+            (Payloads::new(Zstd::default()), &b"\xc0zs"[..]),
+        ] {
+            let compressed = zk.with_interceptor(Arc::new(payloads));
+            compressed
+                .set_data("/c", None, json.clone().into_bytes())
+                .await
+                .unwrap()
+                .unwrap();
+            let (stored, _) = zk.get_data("/c").await.unwrap().unwrap();
+            assert!(stored.starts_with(magic));
+            assert!(stored.len() < json.len() / 10);
+            let (data, _) = compressed.get_data("/c").await.unwrap().unwrap();
+            assert_eq!(data, json.as_bytes());
+
+            // small payloads are not worth compressing
+            compressed.set_data("/c", None, &b"{}"[..]).await.unwrap().unwrap();
+            let (stored, _) = zk.get_data("/c").await.unwrap().unwrap();
+            assert_eq!(stored, b"{}");
+        }
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
+    #[tokio::test]
+    async fn bounds_decompression() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let zeroes = vec![0; 4096];
+        let acl = Acl::open_unsafe();
+        zk.create("/b", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let gzip = |max_size| Payloads::new(Gzip { max_size, ..Gzip::default() });
+        let zstd = |max_size| Payloads::new(Zstd { max_size, ..Zstd::default() });
+        for (fits, too_small) in [(gzip(4096), gzip(4095)), (zstd(4096), zstd(4095))] {
+            let fits = zk.with_interceptor(Arc::new(fits));
+            fits.set_data("/b", None, zeroes.clone()).await.unwrap().unwrap();
+            let (stored, _) = zk.get_data("/b").await.unwrap().unwrap();
+            assert!(stored.len() < 100);
+            let (data, _) = fits.get_data("/b").await.unwrap().unwrap();
+            assert_eq!(data, zeroes);
+            let read = zk.with_interceptor(Arc::new(too_small)).get_data("/b").await;
+            assert!(matches!(read, Err(Error::Codec { ref path, .. }) if path == "/b"));
+        }
+    }
+}
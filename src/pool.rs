@@ -0,0 +1,379 @@
+//! A pool of sessions that reads are spread across.
+//!
+//! A session sends its requests down one connection, and the server answers them one at a time,
+//! in order, so a single session can become the bottleneck of a workload that reads a lot.
+//! [`ZooKeeperPool`] keeps several sessions to the ensemble, and hands out a different one for
+//! every read, while writes all go to the same session, so that they are applied in the order
+//! they were issued. It implements [`ZkClient`], sending every operation where it belongs.
+//!
+//! The sessions of the pool are separate sessions, so a read from one may not yet see a write
+//! made through another. Use [`ZooKeeper::sync`] on the reading session, or read from
+//! [`ZooKeeperPool::writer`], where a read has to see the pool's own writes.
+//!
+//! A session that is not connected is skipped until it is reconnected. A session that can no
+//! longer be, because it expired or was closed, is evicted from the pool and replaced with a new
+//! one in the background; if it was the one taking writes, writes move to another session.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! use tokio_zookeeper::client::ZkClient;
+//! use tokio_zookeeper::pool::ZooKeeperPool;
+//! # async fn run(addr: std::net::SocketAddr) -> Result<(), Error> {
+//! let pool = ZooKeeperPool::connect(ZooKeeperBuilder::default(), addr, 4).await?;
+//! let config = pool.get_data("/config").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::channel::oneshot;
+use futures::future::{self, FutureExt};
+use std::borrow::Cow;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use crate::client::{MultiOp, ZkClient, ZkFuture};
+use crate::managed::Connector;
+use crate::retry::{ExponentialBackoff, RetryPolicy};
+use crate::{
+    error, Acl, CreateMode, Error, MultiResponse, Stat, WatchedEvent, WatchedEventStream,
+    ZooKeeper, ZooKeeperBuilder,
+};
+
+struct Member {
+    zk: ZooKeeper,
+    /// Whether the session has been evicted, and a new one is being connected to take its place.
+    replacing: bool,
+}
+
+struct Members {
+    members: Vec<Member>,
+    /// The index of the member that takes the writes.
+    writer: usize,
+}
+
+struct Inner {
+    connect: Box<Connector>,
+    members: Mutex<Members>,
+    next: AtomicUsize,
+}
+
+/// A pool of sessions, which spreads reads across its sessions and sends every write to the same
+/// one.
+///
+/// See the [module documentation](index.html) for how the sessions are picked. Clones share the
+/// same sessions, which are closed once every clone, and every handle taken from the pool, is
+/// dropped.
+#[derive(Clone)]
+pub struct ZooKeeperPool {
+    inner: Arc<Inner>,
+}
+
+impl fmt::Debug for ZooKeeperPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let members = self.inner.members.lock().unwrap();
+        f.debug_struct("ZooKeeperPool")
+            .field("size", &members.members.len())
+            .field("writer", &members.writer)
+            .finish()
+    }
+}
+
+impl ZooKeeperPool {
+    /// Connect `size` sessions to the server at `addr` with `builder`, and connect the sessions
+    /// that replace evicted ones the same way.
+    pub async fn connect(
+        builder: ZooKeeperBuilder,
+        addr: SocketAddr,
+        size: usize,
+    ) -> Result<Self, Error> {
+        Self::with_connector(size, move || {
+            let builder = builder.clone();
+            async move { builder.connect(&addr).await }
+        })
+        .await
+    }
+
+    /// Connect `size` sessions by calling `connect`, and every session that replaces an evicted
+    /// one by calling it again.
+    ///
+    /// The sessions are connected concurrently, and if any of them fails to connect, so does
+    /// this.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is 0.
+    pub async fn with_connector<F, Fut>(size: usize, connect: F) -> Result<Self, Error>
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(ZooKeeper, WatchedEventStream), Error>> + Send + 'static,
+    {
+        assert!(size > 0, "a pool needs at least one session");
+        let connected = future::try_join_all((0..size).map(|_| connect())).await?;
+        let members = connected
+            .into_iter()
+            .map(|(zk, _)| Member {
+                zk,
+                replacing: false,
+            })
+            .collect();
+        Ok(ZooKeeperPool {
+            inner: Arc::new(Inner {
+                connect: Box::new(move || connect().boxed()),
+                members: Mutex::new(Members { members, writer: 0 }),
+                next: AtomicUsize::new(0),
+            }),
+        })
+    }
+
+    /// Return a handle to the session to send the next read to.
+    ///
+    /// Reads go round the connected sessions in turn. If none is connected, this is the session
+    /// that takes the writes.
+    pub fn reader(&self) -> ZooKeeper {
+        let members = self.inner.evict();
+        let n = members.members.len();
+        let start = self.inner.next.fetch_add(1, Ordering::Relaxed);
+        (0..n)
+            .map(|i| &members.members[(start + i) % n])
+            .find(|m| !m.replacing && m.zk.is_connected())
+            .map(|m| m.zk.clone())
+            .unwrap_or_else(|| members.writer().clone())
+    }
+
+    /// Return a handle to the session that takes the writes.
+    pub fn writer(&self) -> ZooKeeper {
+        self.inner.evict().writer().clone()
+    }
+
+    /// Return how many of the pool's sessions are connected.
+    pub fn healthy(&self) -> usize {
+        let members = self.inner.evict();
+        members
+            .members
+            .iter()
+            .filter(|m| !m.replacing && m.zk.is_connected())
+            .count()
+    }
+}
+
+impl Members {
+    fn writer(&self) -> &ZooKeeper {
+        &self.members[self.writer].zk
+    }
+}
+
+impl Inner {
+    /// Evict the sessions that have ended, and start connecting new ones to take their place.
+    fn evict(self: &Arc<Self>) -> MutexGuard<'_, Members> {
+        let mut members = self.members.lock().unwrap();
+        for (i, member) in members.members.iter_mut().enumerate() {
+            if member.replacing || !member.zk.state().is_terminal() {
+                continue;
+            }
+            warn!(member.zk.logger, "evicting session from pool";
+                  "session_id" => member.zk.stats().session_id);
+            member.replacing = true;
+            let runtime = member.zk.runtime;
+            runtime.spawn(replace(Arc::downgrade(self), i));
+        }
+        if members.members[members.writer].replacing {
+            // writes move to a session that is still there, if there is one
+            if let Some(i) = members.members.iter().position(|m| !m.replacing) {
+                members.writer = i;
+            }
+        }
+        members
+    }
+}
+
+/// Connect a new session to take the place of the evicted member at `index`, until one connects
+/// or the pool is dropped.
+async fn replace(inner: Weak<Inner>, index: usize) {
+    let backoff = ExponentialBackoff::default();
+    let mut retries = 0;
+    loop {
+        let inner = match inner.upgrade() {
+            Some(inner) => inner,
+            None => return,
+        };
+        let (logger, runtime) = {
+            let members = inner.members.lock().unwrap();
+            let evicted = &members.members[index].zk;
+            (evicted.logger.clone(), evicted.runtime)
+        };
+        match (inner.connect)().await {
+            Ok((zk, _)) => {
+                debug!(logger, "replaced evicted session"; "session_id" => zk.stats().session_id);
+                let mut members = inner.members.lock().unwrap();
+                members.members[index] = Member {
+                    zk,
+                    replacing: false,
+                };
+                return;
+            }
+            Err(e) => warn!(logger, "failed to replace evicted session: {}", e),
+        }
+        drop(inner);
+        let delay = backoff.next_delay(retries).unwrap_or(backoff.max_delay);
+        runtime.sleep(delay).await;
+        retries += 1;
+    }
+}
+
+impl ZkClient for ZooKeeperPool {
+    fn create<'a>(
+        &'a self,
+        path: &'a str,
+        data: Cow<'static, [u8]>,
+        acl: Cow<'static, [Acl]>,
+        mode: CreateMode,
+    ) -> ZkFuture<'a, Result<String, error::Create>> {
+        let zk = self.writer();
+        Box::pin(async move { ZkClient::create(&zk, path, data, acl, mode).await })
+    }
+
+    fn set_data<'a>(
+        &'a self,
+        path: &'a str,
+        version: Option<i32>,
+        data: Cow<'static, [u8]>,
+    ) -> ZkFuture<'a, Result<Stat, error::SetData>> {
+        let zk = self.writer();
+        Box::pin(async move { ZkClient::set_data(&zk, path, version, data).await })
+    }
+
+    fn delete<'a>(
+        &'a self,
+        path: &'a str,
+        version: Option<i32>,
+    ) -> ZkFuture<'a, Result<(), error::Delete>> {
+        let zk = self.writer();
+        Box::pin(async move { ZkClient::delete(&zk, path, version).await })
+    }
+
+    fn get_acl<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Result<(Vec<Acl>, Stat), error::GetAcl>> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::get_acl(&zk, path).await })
+    }
+
+    fn set_acl<'a>(
+        &'a self,
+        path: &'a str,
+        acl: Cow<'static, [Acl]>,
+        version: Option<i32>,
+    ) -> ZkFuture<'a, Result<Stat, error::SetAcl>> {
+        let zk = self.writer();
+        Box::pin(async move { ZkClient::set_acl(&zk, path, acl, version).await })
+    }
+
+    fn exists<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Stat>> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::exists(&zk, path).await })
+    }
+
+    fn get_children<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<Vec<String>>> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::get_children(&zk, path).await })
+    }
+
+    fn get_data<'a>(&'a self, path: &'a str) -> ZkFuture<'a, Option<(Vec<u8>, Stat)>> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::get_data(&zk, path).await })
+    }
+
+    fn multi(&self, ops: Vec<MultiOp>) -> ZkFuture<'_, Vec<Result<MultiResponse, error::Multi>>> {
+        let zk = self.writer();
+        Box::pin(async move { ZkClient::multi(&zk, ops).await })
+    }
+
+    fn watch_exists<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, (oneshot::Receiver<WatchedEvent>, Option<Stat>)> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::watch_exists(&zk, path).await })
+    }
+
+    fn watch_children<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<String>)>> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::watch_children(&zk, path).await })
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn watch_data<'a>(
+        &'a self,
+        path: &'a str,
+    ) -> ZkFuture<'a, Option<(oneshot::Receiver<WatchedEvent>, Vec<u8>, Stat)>> {
+        let zk = self.reader();
+        Box::pin(async move { ZkClient::watch_data(&zk, path).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+    use super::*;
+    use crate::testing::MockZk;
+
+    async fn pool(server: &MockZk, size: usize) -> ZooKeeperPool {
+        let server = server.clone();
+        ZooKeeperPool::with_connector(size, move || {
+            let server = server.clone();
+            async move { server.connect().await }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn spreads_reads() {
+        let server = MockZk::new();
+        let pool = pool(&server, 3).await;
+        let readers: HashSet<_> = (0..3).map(|_| pool.reader().stats().session_id).collect();
+        assert_eq!(readers.len(), 3);
+        let writer = pool.writer().stats().session_id;
+        assert!(readers.contains(&writer));
+        assert_eq!(pool.writer().stats().session_id, writer);
+
+        let acl = Cow::Borrowed(Acl::open_unsafe());
+        let mode = CreateMode::Persistent;
+        let created = ZkClient::create(&pool, "/p", Cow::Borrowed(b"x"), acl, mode).await;
+        assert_eq!(created.unwrap().unwrap(), "/p");
+        let (data, _) = ZkClient::get_data(&pool, "/p").await.unwrap().unwrap();
+        assert_eq!(data, b"x");
+    }
+
+    #[tokio::test]
+    async fn evicts_ended_sessions() {
+        let server = MockZk::new();
+        let pool = pool(&server, 2).await;
+        let writer = pool.writer();
+        assert!(server.expire_session(writer.stats().session_id));
+        while !writer.state().is_terminal() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // writes move to the session that is left, which takes every read until the new one is in
+        let moved = pool.writer().stats().session_id;
+        assert_ne!(moved, writer.stats().session_id);
+        assert_eq!(pool.reader().stats().session_id, moved);
+        while pool.healthy() < 2 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert_eq!(pool.writer().stats().session_id, moved);
+        let replacement = (0..2)
+            .map(|_| pool.reader().stats().session_id)
+            .find(|&id| id != moved)
+            .unwrap();
+        assert!(server.sessions().contains(&replacement));
+    }
+}
@@ -0,0 +1,111 @@
+//! Configuring a builder from the system properties of ZooKeeper's Java client.
+
+use std::env;
+use std::time::Duration;
+use crate::error::InvalidProperty;
+use crate::ZooKeeperBuilder;
+
+/// The properties that [`ZooKeeperBuilder::apply_properties`] understands.
+const PROPERTIES: &[&str] = &[
+    "zookeeper.connectTimeout",
+    "zookeeper.request.timeout",
+    "jute.maxbuffer",
+    "zookeeper.sasl.client",
+];
+
+impl ZooKeeperBuilder {
+    /// Configure the builder from the system properties that ZooKeeper's Java client is
+    /// configured with, to share that configuration with services that use the Java client.
+    ///
+    /// The properties that are understood are:
+    ///
+    /// - `zookeeper.connectTimeout`, in milliseconds, sets
+    ///   [`ZooKeeperBuilder::set_connect_timeout`];
+    /// - `zookeeper.request.timeout`, in milliseconds, sets
+    ///   [`ZooKeeperBuilder::set_request_timeout`], where 0 means no timeout;
+    /// - `jute.maxbuffer`, in bytes, sets [`ZooKeeperBuilder::set_max_request_size`];
+    /// - `zookeeper.sasl.client` has to be `false`, since the client does not support SASL.
+    ///
+    /// Other properties are ignored, so that a service's properties can be passed in whole. If a
+    /// property cannot be applied, none of the properties after it are.
+    pub fn apply_properties<I, K, V>(&mut self, properties: I) -> Result<(), InvalidProperty>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<str>,
+        V: AsRef<str>,
+    {
+        for (name, value) in properties {
+            let (name, value) = (name.as_ref(), value.as_ref().trim());
+            let invalid = |reason| InvalidProperty {
+                name: name.to_string(),
+                value: value.to_string(),
+                reason,
+            };
+            let number = || value.parse::<u64>().map_err(|_| invalid("not a number"));
+            match name {
+                "zookeeper.connectTimeout" => {
+                    let timeout = Duration::from_millis(number()?);
+                    self.set_connect_timeout(Some(timeout).filter(|t| !t.is_zero()));
+                }
+                "zookeeper.request.timeout" => {
+                    let timeout = Duration::from_millis(number()?);
+                    self.set_request_timeout(Some(timeout).filter(|t| !t.is_zero()));
+                }
+                "jute.maxbuffer" => match number()? {
+                    0 => return Err(invalid("must not be zero")),
+                    bytes => self.set_max_request_size(bytes as usize),
+                },
+                "zookeeper.sasl.client" => match value {
+                    "false" => {}
+                    "true" => return Err(invalid("SASL is not supported")),
+                    _ => return Err(invalid("not a boolean")),
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Configure the builder from environment variables named after the system properties of
+    /// ZooKeeper's Java client.
+    ///
+    /// The variables are named like the properties, in uppercase and with `_` in place of `.`:
+    /// `ZOOKEEPER_CONNECTTIMEOUT`, `ZOOKEEPER_REQUEST_TIMEOUT`, `JUTE_MAXBUFFER` and
+    /// `ZOOKEEPER_SASL_CLIENT`. See [`ZooKeeperBuilder::apply_properties`] for what they mean.
+    pub fn apply_env(&mut self) -> Result<(), InvalidProperty> {
+        let set = PROPERTIES.iter().filter_map(|property| {
+            let var = property.to_ascii_uppercase().replace('.', "_");
+            env::var(var).ok().map(|value| (property, value))
+        });
+        self.apply_properties(set)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    #[test]
+    fn apply() {
+        let mut builder = ZooKeeperBuilder::default();
+        let mut properties = HashMap::new();
+        properties.insert("zookeeper.connectTimeout", "1500");
+        properties.insert("zookeeper.request.timeout", "0");
+        properties.insert("jute.maxbuffer", "4194304");
+        properties.insert("zookeeper.sasl.client", "false");
+        properties.insert("zookeeper.client.port", "2181");
+        builder.apply_properties(&properties).unwrap();
+        assert_eq!(builder.options.connect_timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(builder.options.request_timeout, None);
+        assert_eq!(builder.options.max_request_size, Some(4 << 20));
+
+        let invalid = builder.apply_properties([("jute.maxbuffer", "1MB")]).unwrap_err();
+        assert_eq!(invalid.reason, "not a number");
+        let invalid = builder.apply_properties([("zookeeper.sasl.client", "true")]).unwrap_err();
+        assert_eq!(
+            invalid.to_string(),
+            "cannot apply zookeeper.sasl.client=\"true\": SASL is not supported"
+        );
+    }
+}
@@ -1,218 +1,512 @@
-use super::{request, watch::WatchType, Request, Response};
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
-use failure;
-use futures::sync::{mpsc, oneshot};
-use slog;
-use std::collections::HashMap;
+use super::outbox::Outbox;
+use super::stats::SharedStats;
+use super::trace::RequestSpan;
+use super::watch::{PersistentSender, WatchSink, WatchType};
+use super::{request, DefaultWatcher, Logged, Options, Reply, Request, Response};
+use bytes::{Buf, BytesMut};
+use futures::channel::oneshot;
+use futures::ready;
+use crate::codec::frame::frame_len;
+use crate::codec::jute::ReadFrom;
+use crate::codec::records::proto::ReplyHeader;
+use crate::logging::Logger;
+use crate::namespace::Namespace;
+use crate::runtime::Sleep;
+use crate::subtree::is_within;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::future::Future;
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll};
 use std::{mem, time};
-use tokio;
-use tokio::prelude::*;
-use {WatchedEvent, WatchedEventType, ZkError};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::io::{poll_read_buf, poll_write_buf};
+use crate::{
+    error, ConnectionState, Error, FlushStrategy, KeeperState, WatchedEvent, WatchedEventType,
+    ZkError,
+};
+
+/// How many bytes to try to read from the server at a time, at least. Reading more than the next
+/// packet lets a burst of responses be picked up with a single read.
+const MIN_READ: usize = 8 * 1024;
+
+/// How many bytes to make room for in the read buffer at a time, at most.
+const MAX_READ: usize = 1024 * 1024;
+
+/// The xid of `SetWatches` requests, whose responses are handled by the packetizer itself.
+const SET_WATCHES_XID: i32 = -8;
+
+/// A watcher to register once the request that sets it succeeds.
+pub(super) type PendingWatcher = (Arc<str>, WatchSink, WatchType, Namespace);
+
+/// A watcher that is registered with the server.
+///
+/// Global watches, whose events only go to the default watcher, are kept too, so that they can be
+/// registered again after a reconnect.
+pub(super) type Watcher = (WatchSink, WatchType, Namespace);
+
+/// A persistent watch that is registered with the server.
+///
+/// It is registered as soon as the request that sets it is sent, and forgotten once nobody
+/// listens to its channel any more, which is also how it goes away if that request fails.
+#[derive(Debug)]
+pub(super) struct PersistentWatcher {
+    pub(super) tx: PersistentSender,
+    pub(super) recursive: bool,
+    pub(super) namespace: Namespace,
+}
+
+/// A read to send again once the connection has been re-established, see `disconnect`.
+pub(super) type Reissue = (
+    Request,
+    oneshot::Sender<Reply>,
+    Option<PendingWatcher>,
+    RequestSpan,
+);
+
+/// A request that has been sent, and is waiting for its response.
+struct Pending {
+    xid: i32,
+    opcode: request::OpCode,
+    tx: oneshot::Sender<Reply>,
+    watcher: Option<PendingWatcher>,
+    sent: time::Instant,
+    span: RequestSpan,
+    path: Option<String>,
+    /// A copy of the request, if it is to be re-issued should the connection be lost.
+    reissue: Option<Request>,
+}
+
+impl Pending {
+    /// Describe the request for an error that it failed with.
+    fn context(&mut self) -> Option<error::Context> {
+        let path = self.path.take();
+        let xid = self.xid;
+        self.opcode
+            .operation()
+            .map(|operation| error::Context {
+                operation,
+                path,
+                xid,
+            })
+    }
+
+    /// Fail the request with `e`.
+    fn fail(mut self, e: ZkError, options: &Options) {
+        if let Some(op) = self.opcode.operation() {
+            options
+                .metrics
+                .on_request_complete(op, self.sent.elapsed(), Some(e));
+        }
+        let context = self.context();
+        self.span.completed(Some(e));
+        // NOTE: the caller may have stopped waiting for the response
+        let _ = self.tx.send(Err((e, context)));
+    }
+
+    /// Give up on the request, which was never sent, so that it fails with [`Error::NotSent`].
+    fn abandon(self, options: &Options) {
+        if let Some(op) = self.opcode.operation() {
+            options.metrics.on_request_complete(
+                op,
+                self.sent.elapsed(),
+                Some(ZkError::ConnectionLoss),
+            );
+        }
+        self.span.completed(Some(ZkError::ConnectionLoss));
+        // dropping `tx` tells the caller that the request was not sent, see `Enqueuer::enqueue`
+    }
+}
 
 pub(super) struct ActivePacketizer<S> {
     stream: S,
 
     /// Heartbeat timer,
-    timer: tokio::timer::Delay,
+    timer: Sleep,
     timeout: time::Duration,
 
-    /// Bytes we have not yet set.
-    pub(super) outbox: Vec<u8>,
+    /// Frames we have not yet sent.
+    pub(super) outbox: Outbox,
 
-    /// Prefix of outbox that has been sent.
-    outstart: usize,
+    pub(super) options: Options,
 
-    /// Bytes we have not yet deserialized.
-    inbox: Vec<u8>,
+    pub(super) stats: Arc<SharedStats>,
+
+    /// Closes the current batching window, if one is open.
+    batch: Option<Sleep>,
 
-    /// Prefix of inbox that has been sent.
-    instart: usize,
+    /// Bytes we have not yet deserialized.
+    inbox: BytesMut,
 
-    /// What operation are we waiting for a response for?
-    reply: HashMap<i32, (request::OpCode, oneshot::Sender<Result<Response, ZkError>>)>,
+    /// What operations are we waiting for responses for?
+    ///
+    /// The server responds to requests in the order they were sent, so the next response is
+    /// always for the request at the front.
+    reply: VecDeque<Pending>,
 
     /// Custom registered watchers (path -> watcher)
-    watchers: HashMap<String, Vec<(oneshot::Sender<WatchedEvent>, WatchType)>>,
+    ///
+    /// Paths are shared with pending watchers for the same path, see `intern`.
+    pub(super) watchers: HashMap<Arc<str>, Vec<Watcher>>,
 
-    /// Custom registered watchers (xid -> watcher to add when ok)
-    pub(super) pending_watchers: HashMap<i32, (String, oneshot::Sender<WatchedEvent>, WatchType)>,
+    /// Persistent watchers (path -> watchers)
+    pub(super) persistent: HashMap<String, Vec<PersistentWatcher>>,
 
     first: bool,
 
+    /// Whether the server has told us that the session has expired.
+    expired: bool,
+
     /// Fields for re-connection
     pub(super) last_zxid_seen: i64,
     pub(super) session_id: i64,
     pub(super) password: Vec<u8>,
+    /// The session timeout negotiated with the server, in milliseconds.
+    pub(super) session_timeout: i32,
+    /// When the server was last heard from, on this connection or the ones before it.
+    pub(super) last_contact: time::Instant,
 }
 
 impl<S> ActivePacketizer<S>
 where
-    S: AsyncRead + AsyncWrite,
+    S: AsyncRead + AsyncWrite + Unpin,
 {
-    pub(super) fn new(stream: S) -> Self {
+    pub(super) fn new(stream: S, options: Options, stats: Arc<SharedStats>) -> Self {
         ActivePacketizer {
             stream,
-            timer: tokio::timer::Delay::new(
-                time::Instant::now() + time::Duration::from_secs(86_400),
-            ),
+            timer: options.runtime.sleep(time::Duration::from_secs(86_400)),
             timeout: time::Duration::new(86_400, 0),
-            outbox: Vec::new(),
-            outstart: 0,
-            inbox: Vec::new(),
-            instart: 0,
+            outbox: Outbox::default(),
+            options,
+            stats,
+            batch: None,
+            inbox: BytesMut::new(),
             reply: Default::default(),
             watchers: Default::default(),
-            pending_watchers: Default::default(),
+            persistent: Default::default(),
             first: true,
+            expired: false,
 
             last_zxid_seen: 0,
             session_id: 0,
             password: Vec::new(),
+            session_timeout: 0,
+            last_contact: time::Instant::now(),
+        }
+    }
+
+    /// Return a shared copy of `path` for a new watcher, reusing the one of the watchers that are
+    /// already registered for it, if there are any.
+    pub(super) fn intern(&self, path: &str) -> Arc<str> {
+        match self.watchers.get_key_value(path) {
+            Some((path, _)) => Arc::clone(path),
+            None => Arc::from(path),
+        }
+    }
+
+    /// The error that requests fail with now that the connection is gone.
+    ///
+    /// Requests that were in flight may or may not have taken effect if the connection was lost,
+    /// whereas the session's ephemeral nodes and watches are gone for good if it expired.
+    pub(super) fn lost(&self) -> ZkError {
+        if self.expired {
+            ZkError::SessionExpired
+        } else {
+            ZkError::ConnectionLoss
+        }
+    }
+
+    /// Whether the session can be resumed on a new connection, which it can once it has been
+    /// established, unless it has expired. That includes when this connection was to resume it,
+    /// but was lost before the server answered the handshake.
+    pub(super) fn resumable(&self) -> bool {
+        self.session_id != 0 && !self.expired
+    }
+
+    /// Fail every request that is still waiting for a response with the error given by `lost`.
+    pub(super) fn fail_pending(&mut self) -> ZkError {
+        let e = self.lost();
+        for pending in mem::take(&mut self.reply) {
+            pending.fail(e, &self.options);
         }
+        self.options.metrics.on_queue_depth(0);
+        self.stats.in_flight.store(0, Ordering::Relaxed);
+        e
+    }
+
+    /// Whether every request that was sent has been responded to.
+    pub(super) fn drained(&self) -> bool {
+        self.reply.is_empty()
     }
 
-    fn outlen(&self) -> usize {
-        self.outbox.len() - self.outstart
+    /// Fail every request that is still waiting for a response now that the connection has been
+    /// lost, except for the reads to send again on the next connection, which are returned.
+    ///
+    /// Requests that never made it out of the outbox fail with [`Error::NotSent`], since they are
+    /// known not to have taken effect, while the others fail with [`ZkError::ConnectionLoss`].
+    pub(super) fn disconnect(&mut self) -> Vec<Reissue> {
+        let mut reissue = Vec::new();
+        // the requests that are queued last are the ones still in the outbox
+        let sent = self.reply.len().saturating_sub(self.outbox.unsent());
+        for (i, mut pending) in mem::take(&mut self.reply).into_iter().enumerate() {
+            match pending.reissue.take() {
+                Some(request) => {
+                    // this attempt is over, even if the request is not
+                    if let Some(op) = pending.opcode.operation() {
+                        self.options.metrics.on_request_complete(
+                            op,
+                            pending.sent.elapsed(),
+                            Some(ZkError::ConnectionLoss),
+                        );
+                    }
+                    reissue.push((request, pending.tx, pending.watcher, pending.span));
+                }
+                None if i >= sent => pending.abandon(&self.options),
+                None => pending.fail(ZkError::ConnectionLoss, &self.options),
+            }
+        }
+        self.options.metrics.on_queue_depth(0);
+        self.stats.in_flight.store(0, Ordering::Relaxed);
+
+        // persistent watches miss whatever changes until they are set again on a new connection
+        let event = WatchedEvent {
+            event_type: WatchedEventType::None,
+            keeper_state: KeeperState::Disconnected,
+            path: String::new(),
+        };
+        for w in self.persistent.values().flatten() {
+            let _ = w.tx.unbounded_send((self.last_zxid_seen, event.clone()));
+        }
+        reissue
     }
 
-    fn inlen(&self) -> usize {
-        self.inbox.len() - self.instart
+    /// Resume the session on this new connection, by sending the handshake for it followed by the
+    /// watches that were registered on the previous connection.
+    pub(super) fn resume(&mut self) {
+        let connect = Request::Connect {
+            protocol_version: 0,
+            last_zxid_seen: self.last_zxid_seen,
+            timeout: self.session_timeout,
+            session_id: self.session_id,
+            passwd: self.password.clone(),
+            read_only: self.options.read_only,
+        };
+        let span = RequestSpan::new(&connect);
+        // the response is handled here, so nobody needs to wait for it
+        let (tx, _) = oneshot::channel();
+        self.enqueue(0, connect, tx, None, span);
+
+        self.persistent.retain(|_, watchers| {
+            watchers.retain(|w| !w.tx.is_closed());
+            !watchers.is_empty()
+        });
+        if self.watchers.is_empty() && self.persistent.is_empty() {
+            return;
+        }
+        let mut paths = [BTreeSet::new(), BTreeSet::new(), BTreeSet::new()];
+        for (path, watchers) in &self.watchers {
+            for w in watchers {
+                let i = match w.1 {
+                    WatchType::Data => 0,
+                    WatchType::Exist => 1,
+                    WatchType::Child => 2,
+                };
+                paths[i].insert(path.to_string());
+            }
+        }
+        let [data, exist, child] = paths;
+        let (mut persistent, mut recursive) = (BTreeSet::new(), BTreeSet::new());
+        for (path, watchers) in &self.persistent {
+            for w in watchers {
+                if w.recursive {
+                    recursive.insert(path.clone());
+                } else {
+                    persistent.insert(path.clone());
+                }
+            }
+        }
+        let set_watches = Request::SetWatches {
+            relative_zxid: self.last_zxid_seen,
+            data: data.into_iter().collect(),
+            exist: exist.into_iter().collect(),
+            child: child.into_iter().collect(),
+            persistent: persistent.into_iter().collect(),
+            recursive: recursive.into_iter().collect(),
+        };
+        self.push_frame(SET_WATCHES_XID, &set_watches);
+    }
+
+    /// Send the event `e` of the change with the given `zxid` to the persistent watchers that it
+    /// concerns, and forget the ones that nobody listens to any more.
+    fn notify_persistent(&mut self, zxid: i64, e: &WatchedEvent) {
+        self.persistent.retain(|path, watchers| {
+            watchers.retain(|w| {
+                let triggers = if w.recursive {
+                    // recursive watches do not report child changes, since they report the
+                    // creation and deletion of the children themselves
+                    e.event_type != WatchedEventType::NodeChildrenChanged
+                        && is_within(&e.path, path)
+                } else {
+                    e.path == *path
+                };
+                if triggers {
+                    let _ = w.tx.unbounded_send((zxid, w.namespace.present(e)));
+                }
+                !w.tx.is_closed()
+            });
+            !watchers.is_empty()
+        });
+    }
+
+    /// Queue the frame for `item`.
+    fn push_frame(&mut self, xid: i32, item: &Request) {
+        self.outbox.push(|frame| item.frame_into(xid, frame));
     }
 
     pub(super) fn enqueue(
         &mut self,
         xid: i32,
         item: Request,
-        tx: oneshot::Sender<Result<Response, ZkError>>,
+        tx: oneshot::Sender<Reply>,
+        watcher: Option<PendingWatcher>,
+        span: RequestSpan,
     ) {
-        let lengthi = self.outbox.len();
-        // dummy length
-        self.outbox.push(0);
-        self.outbox.push(0);
-        self.outbox.push(0);
-        self.outbox.push(0);
-
-        let old = self.reply.insert(xid, (item.opcode(), tx));
-        assert!(old.is_none());
-
-        if let Request::Connect { .. } = item {
-        } else {
-            // xid
-            self.outbox
-                .write_i32::<BigEndian>(xid)
-                .expect("Vec::write should never fail");
-            // opcode
-            self.outbox
-                .write_i32::<BigEndian>(item.opcode() as i32)
-                .expect("Vec::write should never fail");
+        let opcode = item.opcode();
+        if let Some(op) = opcode.operation() {
+            self.options.metrics.on_request_start(op);
         }
 
-        // type and payload
-        item.serialize_into(&mut self.outbox)
-            .expect("Vec::write should never fail");
-        // set true length
-        let written = self.outbox.len() - lengthi - 4;
-        let mut length = &mut self.outbox[lengthi..lengthi + 4];
-        length
-            .write_i32::<BigEndian>(written as i32)
-            .expect("Vec::write should never fail");
+        self.push_frame(xid, &item);
+        let reissue = if self.options.reissue_reads {
+            item.reissuable()
+        } else {
+            None
+        };
+
+        // the path is kept to report slow and failed requests
+        let path = item.into_path();
+        self.reply.push_back(Pending {
+            xid,
+            opcode,
+            tx,
+            watcher,
+            sent: time::Instant::now(),
+            span,
+            path,
+            reissue,
+        });
+        self.options.metrics.on_queue_depth(self.reply.len());
+        self.stats.in_flight.store(self.reply.len(), Ordering::Relaxed);
+        self.stats
+            .outbox_bytes
+            .store(self.outbox.remaining(), Ordering::Relaxed);
     }
 
     fn poll_write(
         &mut self,
+        cx: &mut Context<'_>,
         exiting: bool,
-        logger: &mut slog::Logger,
-    ) -> Result<Async<()>, failure::Error>
-    where
-        S: AsyncWrite,
-    {
+        logger: &mut Logger,
+    ) -> Poll<Result<(), Error>> {
+        if let FlushStrategy::Batched { delay, max_bytes } = self.options.flush {
+            if !exiting && !self.outbox.is_empty() && self.outbox.remaining() < max_bytes {
+                let runtime = self.options.runtime;
+                let batch = self.batch.get_or_insert_with(|| runtime.sleep(delay));
+                if Pin::new(batch).poll(cx).is_pending() {
+                    // keep collecting requests until the window closes
+                    return Poll::Pending;
+                }
+            }
+            self.batch = None;
+        }
+
         let mut wrote = false;
-        while self.outlen() != 0 {
-            let n = try_ready!(self.stream.poll_write(&self.outbox[self.outstart..]));
-            wrote = true;
-            self.outstart += n;
-            if self.outstart == self.outbox.len() {
-                self.outbox.clear();
-                self.outstart = 0;
+        while !self.outbox.is_empty() {
+            // all queued frames go out together if the transport supports vectored writes
+            let n = poll_write_buf(Pin::new(&mut self.stream), cx, &mut self.outbox);
+            self.stats
+                .outbox_bytes
+                .store(self.outbox.remaining(), Ordering::Relaxed);
+            if ready!(n)? == 0 {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero).into()));
             }
+            wrote = true;
         }
 
         if wrote {
             // heartbeat is since last write traffic!
             trace!(logger, "resetting heartbeat timer");
-            self.timer.reset(time::Instant::now() + self.timeout);
+            self.timer.reset(self.timeout);
         }
 
-        self.stream.poll_flush().map_err(failure::Error::from)?;
+        if let Poll::Ready(Err(e)) = Pin::new(&mut self.stream).poll_flush(cx) {
+            return Poll::Ready(Err(e.into()));
+        }
 
         if exiting {
             debug!(logger, "shutting down writer");
-            try_ready!(self.stream.shutdown());
+            ready!(Pin::new(&mut self.stream).poll_shutdown(cx))?;
         }
 
-        Ok(Async::Ready(()))
+        Poll::Ready(Ok(()))
     }
 
     fn poll_read(
         &mut self,
-        default_watcher: &mut mpsc::UnboundedSender<WatchedEvent>,
-        logger: &mut slog::Logger,
-    ) -> Result<Async<()>, failure::Error>
-    where
-        S: AsyncRead,
-    {
+        cx: &mut Context<'_>,
+        default_watcher: &mut DefaultWatcher,
+        logger: &mut Logger,
+    ) -> Poll<Result<(), Error>> {
         loop {
-            let mut need = if self.inlen() >= 4 {
-                let length = (&mut &self.inbox[self.instart..]).read_i32::<BigEndian>()? as usize;
-                length + 4
+            let mut need = if self.inbox.len() >= 4 {
+                frame_len(&self.inbox)?
             } else {
                 4
             };
-            trace!(logger, "need {} bytes, have {}", need, self.inlen());
-
-            while self.inlen() < need {
-                let read_from = self.inbox.len();
-                self.inbox.resize(self.instart + need, 0);
-                match self.stream.poll_read(&mut self.inbox[read_from..])? {
-                    Async::Ready(n) => {
-                        self.inbox.truncate(read_from + n);
-                        if n == 0 {
-                            if self.inlen() != 0 {
-                                bail!(
-                                    "connection closed with {} bytes left in buffer: {:x?}",
-                                    self.inlen(),
-                                    &self.inbox[self.instart..]
-                                );
-                            } else {
-                                // Server closed session with no bytes left in buffer
-                                debug!(logger, "server closed connection");
-                                return Ok(Async::Ready(()));
-                            }
-                        }
-
-                        if self.inlen() >= 4 && need == 4 {
-                            let length = (&mut &self.inbox[self.instart..]).read_i32::<BigEndian>()?
-                                as usize;
-                            need += length;
+            trace!(logger, "need {} bytes, have {}", need, self.inbox.len());
+
+            while self.inbox.len() < need {
+                // a frame only gets room for as much of it as has arrived, so a bogus length
+                // cannot make the buffer allocate whatever it claims
+                let want = (need - self.inbox.len()).clamp(MIN_READ, MAX_READ);
+                self.inbox.reserve(want);
+                match ready!(poll_read_buf(Pin::new(&mut self.stream), cx, &mut self.inbox))? {
+                    0 => {
+                        if !self.inbox.is_empty() {
+                            return Poll::Ready(Err(Error::Protocol(format!(
+                                "connection closed with {} bytes left in buffer: {:x?}",
+                                self.inbox.len(),
+                                &self.inbox[..]
+                            ))));
+                        } else {
+                            // Server closed session with no bytes left in buffer
+                            debug!(logger, "server closed connection");
+                            lifecycle!("server closed connection");
+                            return Poll::Ready(Ok(()));
                         }
                     }
-                    Async::NotReady => {
-                        self.inbox.truncate(read_from);
-                        return Ok(Async::NotReady);
+                    _ => {
+                        if self.inbox.len() >= 4 && need == 4 {
+                            need = frame_len(&self.inbox)?;
+                        }
                     }
                 }
             }
 
             {
                 let mut err = None;
-                let mut buf = &self.inbox[self.instart + 4..self.instart + need];
-                self.instart += need;
-
-                let xid = if self.first {
-                    0
+                // the packet shares the read buffer, so data in the response is never copied
+                let packet = self.inbox.split_to(need).freeze();
+                self.stats.received_packet();
+                self.last_contact = time::Instant::now();
+                let mut buf = Cursor::new(packet.slice(4..));
+
+                let (xid, zxid) = if self.first {
+                    (0, 0)
                 } else {
-                    let xid = buf.read_i32::<BigEndian>()?;
-                    let zxid = buf.read_i64::<BigEndian>()?;
+                    let ReplyHeader { xid, zxid, err: code } = ReplyHeader::read_from(&mut buf)?;
                     if zxid > 0 {
                         trace!(
                             logger,
@@ -221,14 +515,20 @@ where
                             zxid
                         );
 
-                        assert!(zxid >= self.last_zxid_seen);
+                        if zxid < self.last_zxid_seen {
+                            return Poll::Ready(Err(Error::Protocol(format!(
+                                "zxid went back from {} to {}",
+                                self.last_zxid_seen, zxid
+                            ))));
+                        }
                         self.last_zxid_seen = zxid;
+                        self.stats.last_zxid_seen.store(zxid, Ordering::Relaxed);
                     }
-                    let zk_err: ZkError = buf.read_i32::<BigEndian>()?.into();
+                    let zk_err = ZkError::from(code);
                     if zk_err != ZkError::Ok {
                         err = Some(zk_err);
                     }
-                    xid
+                    (xid, zxid)
                 };
 
                 if xid == 0 && !self.first {
@@ -236,23 +536,37 @@ where
                     // XXX: in theory, server should now shut down receive end
                     trace!(logger, "got response to CloseSession");
                     if let Some(e) = err {
-                        bail!("failed to close session: {:?}", e);
+                        return Poll::Ready(Err(e.into()));
                     }
                 } else if xid == -1 {
                     // watch event
-                    use super::response::ReadFrom;
+                    use crate::codec::jute::ReadFrom;
                     let e = WatchedEvent::read_from(&mut buf)?;
                     trace!(logger, "got watcher event {:?}", e);
+                    self.options.metrics.on_watch_fired(e.event_type);
+                    if e.event_type == WatchedEventType::None {
+                        match e.keeper_state {
+                            KeeperState::Expired => {
+                                self.expired = true;
+                                self.stats.set_state(ConnectionState::Expired);
+                                self.options.callbacks.session_expired()
+                            }
+                            KeeperState::AuthFailed => self.options.callbacks.auth_failed(),
+                            _ => {}
+                        }
+                    }
 
                     let mut remove = false;
-                    if let Some(watchers) = self.watchers.get_mut(&e.path) {
-                        // custom watchers were set by the user -- notify them
+                    if let Some(watchers) = self.watchers.get_mut(e.path.as_str()) {
+                        // watchers were set by the user -- notify the custom ones
                         let mut i = (watchers.len() - 1) as isize;
                         trace!(logger,
                                "found potentially waiting custom watchers";
                                "n" => watchers.len()
                         );
 
+                        // a handle that set several watches that fire hears of the event once
+                        let mut handles = Vec::new();
                         while i >= 0 {
                             let triggers = match (&watchers[i as usize].1, e.event_type) {
                                 (WatchType::Child, WatchedEventType::NodeDeleted)
@@ -267,9 +581,20 @@ where
 
                             if triggers {
                                 // this watcher is no longer active
-                                let w = watchers.swap_remove(i as usize);
-                                // NOTE: ignore the case where the receiver has been dropped
-                                let _ = w.0.send(e.clone());
+                                let (sink, _, namespace) = watchers.swap_remove(i as usize);
+                                let event = || namespace.present(&e);
+                                match sink {
+                                    WatchSink::Session => {}
+                                    WatchSink::Once(tx) => {
+                                        // NOTE: ignore the case where the receiver has been dropped
+                                        let _ = tx.send(event());
+                                    }
+                                    WatchSink::Handle(id) if !handles.contains(&id) => {
+                                        handles.push(id);
+                                        default_watcher.send_to_handle(id, event());
+                                    }
+                                    WatchSink::Handle(_) => {}
+                                }
                             }
                             i -= 1;
                         }
@@ -281,39 +606,96 @@ where
 
                     if remove {
                         self.watchers
-                            .remove(&e.path)
+                            .remove(e.path.as_str())
                             .expect("tried to remove watcher that didn't exist");
                     }
+                    if e.event_type != WatchedEventType::None {
+                        self.notify_persistent(zxid, &e);
+                    }
 
                     // NOTE: ignoring error, because the user may not care about events
-                    let _ = default_watcher.unbounded_send(e);
+                    let _ = default_watcher.unbounded_send((e, time::Instant::now()));
                 } else if xid == -2 {
                     // response to ping -- empty response
                     trace!(logger, "got response to heartbeat");
                     if let Some(e) = err {
-                        bail!("bad response to ping: {:?}", e);
+                        return Poll::Ready(Err(e.into()));
+                    }
+                } else if xid == SET_WATCHES_XID {
+                    trace!(logger, "got response to SetWatches");
+                    if let Some(e) = err {
+                        return Poll::Ready(Err(e.into()));
                     }
                 } else {
-                    // response to user request
-                    self.first = false;
+                    // response to user request, or to the handshake if it is the first
+                    let first = mem::replace(&mut self.first, false);
 
                     // find the waiting request future
-                    let (opcode, tx) = match self.reply.remove(&xid) {
-                        Some(tuple) => tuple,
-                        None => bail!("No waiting request future found for xid {:?}", xid)
+                    let expected = match self.reply.front() {
+                        Some(pending) => pending.xid,
+                        None => {
+                            return Poll::Ready(Err(Error::Protocol(format!(
+                                "no waiting request future found for xid {:?}",
+                                xid
+                            ))))
+                        }
                     };
+                    if xid != expected {
+                        // the request stays queued, so that it fails with the connection
+                        return Poll::Ready(Err(Error::Protocol(format!(
+                            "got response for xid {:?}, but expected xid {:?}",
+                            xid, expected
+                        ))));
+                    }
+                    let Pending {
+                        opcode,
+                        tx,
+                        watcher,
+                        sent,
+                        span,
+                        path,
+                        ..
+                    } = self.reply.pop_front().expect("the front request was just seen");
+                    let latency = sent.elapsed();
+                    if let Some(op) = opcode.operation() {
+                        self.options.metrics.on_request_complete(op, latency, err);
+                    }
+                    match self.options.slow_request_threshold {
+                        Some(threshold) if latency >= threshold => warn!(
+                            logger,
+                            "slow request";
+                            "opcode" => ?opcode,
+                            "path" => path.as_ref().map_or("", |p| &p[..]),
+                            "latency_ms" => latency.as_millis() as u64,
+                            "error" => ?err,
+                            "state" => if first { "connecting" } else { "connected" },
+                            "in_flight" => self.reply.len(),
+                            "xid" => xid
+                        ),
+                        _ => {}
+                    }
+                    self.options.metrics.on_queue_depth(self.reply.len());
+                    self.stats.in_flight.store(self.reply.len(), Ordering::Relaxed);
+                    span.completed(err);
 
-                    if let Some(w) = self.pending_watchers.remove(&xid) {
+                    if let Some(w) = watcher {
                         // normally, watches are *only* added for successful operations
                         // the exception to this is if an exists call fails with NoNode
                         if err.is_none()
                             || (opcode == request::OpCode::Exists && err == Some(ZkError::NoNode))
                         {
                             trace!(logger, "pending watcher turned into real watcher"; "xid" => xid);
+                            // the server keeps the watch of an `exists` on a node that exists with
+                            // its data watches, which is where it must be set again on a reconnect
+                            let wtype = if opcode == request::OpCode::Exists && err.is_none() {
+                                WatchType::Data
+                            } else {
+                                w.2
+                            };
                             self.watchers
                                 .entry(w.0)
-                                .or_insert_with(Vec::new)
-                                .push((w.1, w.2));
+                                .or_default()
+                                .push((w.1, wtype, w.3));
                         } else {
                             trace!(logger,
                                    "pending watcher not turned into real watcher: {:?}",
@@ -323,94 +705,152 @@ where
                         }
                     }
 
-                    if let Some(e) = err {
+                    if opcode == request::OpCode::Raw {
+                        // whoever sent it gets the header as it is, error and all, along with
+                        // whatever body there is
+                        let body = buf.get_ref().slice(buf.position() as usize..);
+                        let err = err.unwrap_or(ZkError::Ok);
+                        let _ = tx.send(Ok(Response::Raw {
+                            xid,
+                            zxid,
+                            err,
+                            body,
+                        }));
+                    } else if let Some(e) = err {
                         info!(logger,
                                "handling server error response: {:?}", e;
                                "xid" => xid, "opcode" => ?opcode);
 
-                        tx.send(Err(e)).is_ok();
+                        match e {
+                            ZkError::SessionExpired => {
+                                self.expired = true;
+                                self.stats.set_state(ConnectionState::Expired);
+                                self.options.callbacks.session_expired()
+                            }
+                            ZkError::AuthFailed => self.options.callbacks.auth_failed(),
+                            _ => {}
+                        }
+
+                        let context = opcode.operation().map(|operation| error::Context {
+                            operation,
+                            path,
+                            xid,
+                        });
+                        let _ = tx.send(Err((e, context)));
                     } else {
-                        let mut r = Response::parse(opcode, &mut buf)?;
+                        let r = match Response::parse(opcode, &mut buf) {
+                            Ok(r) => r,
+                            Err(e) => {
+                                // the request was sent, so it must not look like it was not
+                                let context = opcode.operation().map(|operation| error::Context {
+                                    operation,
+                                    path,
+                                    xid,
+                                });
+                                let _ = tx.send(Err((ZkError::MarshallingError, context)));
+                                return Poll::Ready(Err(e));
+                            }
+                        };
 
                         debug!(logger,
-                               "handling server response: {:?}", r;
+                               "handling server response: {:?}",
+                               Logged(&r, self.options.log_payloads);
                                "xid" => xid, "opcode" => ?opcode);
 
                         if let Response::Connect {
                             timeout,
                             session_id,
-                            ref mut password,
+                            ref password,
+                            read_only,
                             ..
                         } = r
                         {
-                            assert!(timeout >= 0);
+                            if timeout <= 0 {
+                                // the server would not resume the session
+                                lifecycle!(session_id = self.session_id, "session expired");
+                                self.expired = true;
+                                self.stats.set_state(ConnectionState::Expired);
+                                self.options.callbacks.session_expired();
+                                let e = WatchedEvent {
+                                    event_type: WatchedEventType::None,
+                                    keeper_state: KeeperState::Expired,
+                                    path: String::new(),
+                                };
+                                let _ = default_watcher.unbounded_send((e, time::Instant::now()));
+                                let _ = tx.send(Err((ZkError::SessionExpired, None)));
+                                return Poll::Ready(Err(Error::SessionExpired));
+                            }
                             trace!(logger, "negotiated session timeout: {}ms", timeout);
+                            let resumed = self.session_id != 0;
 
                             self.timeout = time::Duration::from_millis(2 * timeout as u64 / 3);
-                            self.timer.reset(time::Instant::now() + self.timeout);
+                            self.timer.reset(self.timeout);
+
+                            lifecycle!(session_id, timeout, "session established");
+                            self.stats.set_state(if read_only {
+                                ConnectionState::ConnectedReadOnly
+                            } else {
+                                ConnectionState::Connected
+                            });
+                            self.options.callbacks.connected();
 
                             // keep track of these for consistent re-connect
                             self.session_id = session_id;
-                            mem::swap(&mut self.password, password);
+                            self.session_timeout = timeout;
+                            self.stats.session_id.store(session_id, Ordering::Relaxed);
+                            self.password.clone_from(password);
+
+                            if resumed {
+                                let e = WatchedEvent {
+                                    event_type: WatchedEventType::None,
+                                    keeper_state: KeeperState::SyncConnected,
+                                    path: String::new(),
+                                };
+                                let _ = default_watcher.unbounded_send((e, time::Instant::now()));
+                            }
                         }
 
-                        tx.send(Ok(r)).is_ok(); // if receiver doesn't care, we don't either
+                        let _ = tx.send(Ok(r)); // if receiver doesn't care, we don't either
                     }
                 }
             }
-
-            if self.instart == self.inbox.len() {
-                self.inbox.clear();
-                self.instart = 0;
-            }
         }
     }
 
     pub(super) fn poll(
         &mut self,
+        cx: &mut Context<'_>,
         exiting: bool,
-        logger: &mut slog::Logger,
-        default_watcher: &mut mpsc::UnboundedSender<WatchedEvent>,
-    ) -> Result<Async<()>, failure::Error> {
+        logger: &mut Logger,
+        default_watcher: &mut DefaultWatcher,
+    ) -> Poll<Result<(), Error>> {
         trace!(logger, "poll_read");
-        let r = self.poll_read(default_watcher, logger)?;
+        let r = self.poll_read(cx, default_watcher, logger)?;
 
-        if let Async::Ready(()) = self.timer.poll()? {
+        // polled again after a reset, so that the timer is armed for the next heartbeat
+        while Pin::new(&mut self.timer).poll(cx).is_ready() {
             if self.outbox.is_empty() {
                 // send a ping!
-                // length is known for pings
-                self.outbox
-                    .write_i32::<BigEndian>(8)
-                    .expect("Vec::write should never fail");
-                // xid
-                self.outbox
-                    .write_i32::<BigEndian>(-2)
-                    .expect("Vec::write should never fail");
-                // opcode
-                self.outbox
-                    .write_i32::<BigEndian>(request::OpCode::Ping as i32)
-                    .expect("Vec::write should never fail");
+                self.outbox.push(request::write_ping);
                 trace!(logger, "sending heartbeat");
             } else {
                 // already request in flight, so no need to also send heartbeat
             }
 
-            self.timer.reset(time::Instant::now() + self.timeout);
+            self.timer.reset(self.timeout);
         }
 
         trace!(logger, "poll_write");
-        let w = self.poll_write(exiting, logger)?;
+        let w = self.poll_write(cx, exiting, logger)?;
 
         match (r, w) {
-            (Async::Ready(()), Async::Ready(())) if exiting => {
+            (Poll::Ready(()), Poll::Ready(())) if exiting => {
                 debug!(logger, "packetizer done");
-                Ok(Async::Ready(()))
-            }
-            (Async::Ready(()), Async::Ready(())) => {
-                bail!("Not exiting, but server closed connection")
+                Poll::Ready(Ok(()))
             }
-            (Async::Ready(()), _) => bail!("outstanding requests, but response channel closed"),
-            _ => Ok(Async::NotReady),
+            // the server hung up on us
+            (Poll::Ready(()), _) => Poll::Ready(Err(self.lost().into())),
+            _ => Poll::Pending,
         }
     }
 }
@@ -0,0 +1,112 @@
+//! Entry points into the codec for the benchmarks in `benches/`.
+//!
+//! This is only compiled with the `bench` feature, and is not part of the public API.
+
+use super::request::OpCode;
+use super::{Request, Response, Watch};
+use byteorder::{BigEndian, WriteBytesExt};
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::io::Cursor;
+use crate::{Acl, CreateMode};
+
+fn encode(buf: &mut Vec<u8>, request: &Request) {
+    buf.clear();
+    buf.reserve(request.serialized_len());
+    request
+        .serialize_into(buf)
+        .expect("Vec::write should never fail");
+}
+
+/// Serialize a `get_data` request for `path` into `buf`.
+pub fn encode_get_data(buf: &mut Vec<u8>, path: &str) {
+    encode(
+        buf,
+        &Request::GetData {
+            path: path.to_string(),
+            watch: Watch::None,
+        },
+    )
+}
+
+/// Serialize a `set_data` request for `path` into `buf`.
+pub fn encode_set_data(buf: &mut Vec<u8>, path: &str, data: &'static [u8]) {
+    encode(buf, &set_data(path, data))
+}
+
+/// Serialize a `create` request for `path` into `buf`.
+pub fn encode_create(buf: &mut Vec<u8>, path: &str, data: &'static [u8]) {
+    encode(
+        buf,
+        &Request::Create {
+            path: path.to_string(),
+            data: Cow::Borrowed(data),
+            acl: Cow::Borrowed(Acl::open_unsafe()),
+            mode: CreateMode::Persistent,
+            with_stat: false,
+        },
+    )
+}
+
+/// Serialize a `set_data` request for `path` into a new buffer that grows as it is written, and
+/// return the capacity it ends up with.
+pub fn encode_set_data_grown(path: &str, data: &'static [u8]) -> usize {
+    let mut buf = Vec::new();
+    set_data(path, data).serialize_into(&mut buf).expect("Vec::write should never fail");
+    buf.capacity()
+}
+
+/// Serialize a `set_data` request for `path` into a new buffer that is sized up front, and
+/// return the capacity it ends up with.
+pub fn encode_set_data_sized(path: &str, data: &'static [u8]) -> usize {
+    let request = set_data(path, data);
+    let mut buf = Vec::with_capacity(request.serialized_len());
+    request.serialize_into(&mut buf).expect("Vec::write should never fail");
+    buf.capacity()
+}
+
+fn set_data(path: &str, data: &'static [u8]) -> Request {
+    Request::SetData {
+        path: path.to_string(),
+        data: Cow::Borrowed(data),
+        version: -1,
+    }
+}
+
+/// Build the body of a response to `get_data` that returns `data`.
+pub fn get_data_response(data: &[u8]) -> Bytes {
+    let mut body = Vec::with_capacity(4 + data.len() + 68);
+    body.write_i32::<BigEndian>(data.len() as i32).unwrap();
+    body.extend_from_slice(data);
+    // an all-zero Stat
+    body.extend_from_slice(&[0; 68]);
+    body.into()
+}
+
+/// Build the body of a response to `get_children` that returns `n` children.
+pub fn get_children_response(n: usize) -> Bytes {
+    let mut body = Vec::new();
+    body.write_i32::<BigEndian>(n as i32).unwrap();
+    for i in 0..n {
+        let child = format!("child-{:010}", i);
+        body.write_i32::<BigEndian>(child.len() as i32).unwrap();
+        body.extend_from_slice(child.as_bytes());
+    }
+    body.into()
+}
+
+/// Parse the body of a response to `get_data`, and return the length of the data.
+pub fn decode_get_data(body: &Bytes) -> usize {
+    match Response::parse(OpCode::GetData, &mut Cursor::new(body.clone())) {
+        Ok(Response::GetData { bytes, .. }) => bytes.len(),
+        r => panic!("bad get_data response: {:?}", r),
+    }
+}
+
+/// Parse the body of a response to `get_children`, and return the number of children.
+pub fn decode_get_children(body: &Bytes) -> usize {
+    match Response::parse(OpCode::GetChildren, &mut Cursor::new(body.clone())) {
+        Ok(Response::Strings(children)) => children.len(),
+        r => panic!("bad get_children response: {:?}", r),
+    }
+}
@@ -0,0 +1,73 @@
+//! Closing a session from a connection of its own, without a client to go with it.
+
+use super::request::{self, OpCode};
+use super::{Request, Response, ZkError, ZooKeeperTransport};
+use byteorder::{BigEndian, ReadBytesExt};
+use bytes::Bytes;
+use std::io::Cursor;
+use tokio::io::AsyncWriteExt;
+use crate::{Error, Session};
+
+/// Resume `session` on a new connection to `addr`, and close it there.
+///
+/// The request to close the session is sent right behind the handshake rather than after its
+/// response, so that the server has both in hand before the session's own client, whose
+/// connection the handshake takes the session away from, gets to reconnect and take it back.
+pub(crate) async fn close_session<S>(addr: &S::Addr, session: &Session) -> Result<(), Error>
+where
+    S: ZooKeeperTransport,
+{
+    let mut stream = S::connect(addr).await.map_err(Into::into)?;
+    let connect = Request::Connect {
+        protocol_version: 0,
+        last_zxid_seen: 0,
+        timeout: 0,
+        session_id: session.id,
+        passwd: session.password.clone(),
+        read_only: false,
+    };
+    let mut frames = Vec::new();
+    connect.frame_into(0, &mut frames);
+    request::write_close_session(&mut frames);
+    stream.write_all(&frames).await?;
+    stream.flush().await?;
+
+    let mut frame = read_frame(&mut stream).await?;
+    match Response::parse(OpCode::CreateSession, &mut frame)? {
+        Response::Connect { timeout, .. } if timeout <= 0 => return Err(Error::SessionExpired),
+        Response::Connect { .. } => {}
+        _ => unreachable!("the handshake is answered with a connect response"),
+    }
+
+    let mut frame = read_frame(&mut stream).await?;
+    let xid = frame.read_i32::<BigEndian>()?;
+    let _zxid = frame.read_i64::<BigEndian>()?;
+    let err = frame.read_i32::<BigEndian>()?;
+    if xid != 0 {
+        return Err(Error::Protocol(format!(
+            "response with xid {} to the closing of the session",
+            xid
+        )));
+    }
+    match ZkError::from(err) {
+        // the session is closed by the time it is answered
+        ZkError::Ok => Ok(()),
+        e => Err(e.into()),
+    }
+}
+
+async fn read_frame<S>(stream: &mut S) -> Result<Cursor<Bytes>, Error>
+where
+    S: ZooKeeperTransport,
+{
+    // imported here, as it would clash with the synchronous reads of the frame's contents
+    use tokio::io::AsyncReadExt;
+
+    let len = stream.read_i32().await?;
+    if len < 0 {
+        return Err(Error::Protocol(format!("frame of length {}", len)));
+    }
+    let mut frame = vec![0; len as usize];
+    stream.read_exact(&mut frame).await?;
+    Ok(Cursor::new(Bytes::from(frame)))
+}
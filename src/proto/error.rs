@@ -1,59 +1,121 @@
+/// An error code returned by the ZooKeeper server.
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-#[repr(i32)]
 pub enum ZkError {
     /// This code is never returned from the server. It should not be used other than to indicate a
     /// range. Specifically error codes greater than this value are API errors (while values less
     /// than this indicate a system error.
-    APIError = -100,
+    APIError,
     /// Client authentication failed.
-    AuthFailed = -115,
+    AuthFailed,
     /// Invalid arguments.
-    BadArguments = -8,
+    BadArguments,
     /// Version conflict in `set` operation. In case of reconfiguration: reconfig requested from
     /// config version X but last seen config has a different version Y.
-    BadVersion = -103,
+    BadVersion,
     /// Connection to the server has been lost.
-    ConnectionLoss = -4,
+    ConnectionLoss,
     /// A data inconsistency was found.
-    DataInconsistency = -3,
+    DataInconsistency,
     /// Attempt to create ephemeral node on a local session.
-    EphemeralOnLocalSession = -120,
+    EphemeralOnLocalSession,
     /// Invalid `Acl` specified.
-    InvalidACL = -114,
+    InvalidACL,
     /// Invalid callback specified.
-    InvalidCallback = -113,
+    InvalidCallback,
     /// Error while marshalling or unmarshalling data.
-    MarshallingError = -5,
+    MarshallingError,
+    /// No quorum of the new configuration is connected and up-to-date with the leader of the
+    /// last committed configuration.
+    NewConfigNoQuorum,
     /// Not authenticated.
-    NoAuth = -102,
+    NoAuth,
     /// Ephemeral nodes may not have children.
-    NoChildrenForEphemerals = -108,
-    /// Request to create node that already exists.
-    NodeExists = -110,
+    NoChildrenForEphemerals,
     /// Attempted to read a node that does not exist.
-    NoNode = -101,
+    NoNode,
+    /// Attempt to remove a non-existing watcher.
+    NoWatcher,
+    /// Request to create node that already exists.
+    NodeExists,
     /// The node has children.
-    NotEmpty = -111,
+    NotEmpty,
     /// State-changing request is passed to read-only server.
-    NotReadOnly = -119,
-    /// Attempt to remove a non-existing watcher.
-    NoWatcher = -121,
+    NotReadOnly,
     /// No error occurred.
-    Ok = 0,
+    Ok,
     /// Operation timeout.
-    OperationTimeout = -7,
+    OperationTimeout,
+    /// The operation would exceed a hard quota of the node.
+    QuotaExceeded,
+    /// Dynamic reconfiguration is disabled on the server.
+    ReconfigDisabled,
+    /// Another reconfiguration is in progress.
+    ReconfigInProgress,
+    /// The request timed out on the server.
+    RequestTimeout,
     /// A runtime inconsistency was found.
-    RuntimeInconsistency = -2,
+    RuntimeInconsistency,
+    /// The session was closed because the client did not authenticate with SASL, which the
+    /// server requires.
+    SessionClosedRequireSaslAuth,
     /// The session has been expired by the server.
-    SessionExpired = -112,
+    SessionExpired,
     /// Session moved to another server, so operation is ignored.
-    SessionMoved = -118,
+    SessionMoved,
     /// System and server-side errors. This is never thrown by the server, it shouldn't be used
     /// other than to indicate a range. Specifically error codes greater than this value, but lesser
     /// than `APIError`, are system errors.
-    SystemError = -1,
+    SystemError,
+    /// The server is overloaded, and rejected the operation.
+    Throttled,
     /// Operation is unimplemented.
-    Unimplemented = -6,
+    Unimplemented,
+    /// The session is not known to the server.
+    UnknownSession,
+    /// An error code that this client does not know about, such as one that was introduced by a
+    /// newer version of ZooKeeper.
+    Unknown(i32),
+}
+
+impl ZkError {
+    /// The code that the server uses for this error on the wire.
+    pub fn code(self) -> i32 {
+        match self {
+            ZkError::APIError => -100,
+            ZkError::AuthFailed => -115,
+            ZkError::BadArguments => -8,
+            ZkError::BadVersion => -103,
+            ZkError::ConnectionLoss => -4,
+            ZkError::DataInconsistency => -3,
+            ZkError::EphemeralOnLocalSession => -120,
+            ZkError::InvalidACL => -114,
+            ZkError::InvalidCallback => -113,
+            ZkError::MarshallingError => -5,
+            ZkError::NewConfigNoQuorum => -13,
+            ZkError::NoAuth => -102,
+            ZkError::NoChildrenForEphemerals => -108,
+            ZkError::NoNode => -101,
+            ZkError::NoWatcher => -121,
+            ZkError::NodeExists => -110,
+            ZkError::NotEmpty => -111,
+            ZkError::NotReadOnly => -119,
+            ZkError::Ok => 0,
+            ZkError::OperationTimeout => -7,
+            ZkError::QuotaExceeded => -125,
+            ZkError::ReconfigDisabled => -123,
+            ZkError::ReconfigInProgress => -14,
+            ZkError::RequestTimeout => -122,
+            ZkError::RuntimeInconsistency => -2,
+            ZkError::SessionClosedRequireSaslAuth => -124,
+            ZkError::SessionExpired => -112,
+            ZkError::SessionMoved => -118,
+            ZkError::SystemError => -1,
+            ZkError::Throttled => -127,
+            ZkError::Unimplemented => -6,
+            ZkError::UnknownSession => -12,
+            ZkError::Unknown(code) => code,
+        }
+    }
 }
 
 impl From<i32> for ZkError {
@@ -69,21 +131,44 @@ impl From<i32> for ZkError {
             -114 => ZkError::InvalidACL,
             -113 => ZkError::InvalidCallback,
             -5 => ZkError::MarshallingError,
+            -13 => ZkError::NewConfigNoQuorum,
             -102 => ZkError::NoAuth,
             -108 => ZkError::NoChildrenForEphemerals,
-            -110 => ZkError::NodeExists,
             -101 => ZkError::NoNode,
+            -121 => ZkError::NoWatcher,
+            -110 => ZkError::NodeExists,
             -111 => ZkError::NotEmpty,
             -119 => ZkError::NotReadOnly,
-            -121 => ZkError::NoWatcher,
             0 => ZkError::Ok,
             -7 => ZkError::OperationTimeout,
+            -125 => ZkError::QuotaExceeded,
+            -123 => ZkError::ReconfigDisabled,
+            -14 => ZkError::ReconfigInProgress,
+            -122 => ZkError::RequestTimeout,
             -2 => ZkError::RuntimeInconsistency,
+            -124 => ZkError::SessionClosedRequireSaslAuth,
             -112 => ZkError::SessionExpired,
             -118 => ZkError::SessionMoved,
             -1 => ZkError::SystemError,
+            -127 => ZkError::Throttled,
             -6 => ZkError::Unimplemented,
-            _ => panic!("unknown error code {}", code),
+            -12 => ZkError::UnknownSession,
+            code => ZkError::Unknown(code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn codes() {
+        for code in -130..=0 {
+            assert_eq!(ZkError::from(code).code(), code);
         }
+        assert_eq!(ZkError::from(-124), ZkError::SessionClosedRequireSaslAuth);
+        assert_eq!(ZkError::from(-127), ZkError::Throttled);
+        assert_eq!(ZkError::from(-200), ZkError::Unknown(-200));
     }
 }
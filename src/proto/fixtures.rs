@@ -0,0 +1,607 @@
+//! Golden wire fixtures: frames as the Java client and server put them on the wire, checked
+//! byte for byte against what the client encodes, and parsed the way the client parses them.
+//!
+//! Each fixture in `fixtures/` holds one exchange, in hex, with the frame that the client sends
+//! on lines that start with `>` and the frame that the server answers with on lines that start
+//! with `<`. Everything after a `#` is a comment, which by convention names the Jute field that
+//! the bytes on its line encode, so that a mismatch can be traced to the field it is in. Every
+//! frame starts with its length prefix, as it does on the wire.
+
+use super::request::{self, OpCode, Request};
+use super::response::Response;
+use super::{Watch, ZkError};
+use byteorder::{BigEndian, ReadBytesExt};
+use bytes::Bytes;
+use std::borrow::Cow;
+use std::io::Cursor;
+use crate::codec::jute::ReadFrom;
+use crate::{Acl, CreateMode, KeeperState, Stat, WatchedEvent, WatchedEventType};
+
+/// The frames of one exchange.
+struct Fixture {
+    name: &'static str,
+    sent: Vec<u8>,
+    received: Vec<u8>,
+}
+
+macro_rules! fixture {
+    ($name:expr) => {
+        Fixture::parse($name, include_str!(concat!("fixtures/", $name, ".txt")))
+    };
+}
+
+impl Fixture {
+    fn parse(name: &'static str, text: &str) -> Fixture {
+        let mut fixture = Fixture {
+            name,
+            sent: Vec::new(),
+            received: Vec::new(),
+        };
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            let (frame, hex) = match line.chars().next() {
+                None => continue,
+                Some('>') => (&mut fixture.sent, &line[1..]),
+                Some('<') => (&mut fixture.received, &line[1..]),
+                Some(_) => panic!("{}:{}: no direction", name, n + 1),
+            };
+            let hex: String = hex.split_whitespace().collect();
+            assert!(hex.len().is_multiple_of(2), "{}:{}: odd number of digits", name, n + 1);
+            for i in (0..hex.len()).step_by(2) {
+                let byte = u8::from_str_radix(&hex[i..i + 2], 16);
+                frame.push(byte.unwrap_or_else(|_| panic!("{}:{}: bad hex", name, n + 1)));
+            }
+        }
+        for frame in [&fixture.sent, &fixture.received] {
+            if !frame.is_empty() {
+                let len = (&frame[..4]).read_i32::<BigEndian>().unwrap();
+                assert_eq!(len as usize, frame.len() - 4, "{}: bad length prefix", name);
+            }
+        }
+        fixture
+    }
+
+    /// Check that the client encodes `request` with the given `xid` exactly as it was sent.
+    fn check_sent(&self, xid: i32, request: &Request) {
+        let mut frame = Vec::new();
+        request.frame_into(xid, &mut frame);
+        assert_eq!(hex(&frame), hex(&self.sent), "{}: request differs", self.name);
+    }
+
+    /// Read the header of the received frame, and return its xid, zxid, and error along with the
+    /// rest of the frame.
+    fn reply(&self) -> (i32, i64, ZkError, Cursor<Bytes>) {
+        let mut reader = Cursor::new(Bytes::from(self.received[4..].to_vec()));
+        let xid = reader.read_i32::<BigEndian>().unwrap();
+        let zxid = reader.read_i64::<BigEndian>().unwrap();
+        let err = reader.read_i32::<BigEndian>().unwrap().into();
+        (xid, zxid, err, reader)
+    }
+
+    /// Parse the received frame as the successful response to a request with `opcode`.
+    fn response(&self, xid: i32, opcode: OpCode) -> Response {
+        let (got, _, err, mut reader) = self.reply();
+        assert_eq!((got, err), (xid, ZkError::Ok), "{}: not a successful reply", self.name);
+        let response = Response::parse(opcode, &mut reader).unwrap();
+        self.assert_consumed(&reader);
+        response
+    }
+
+    /// Parse the received frame as a failed reply to the request with `xid`, and return its
+    /// error.
+    fn error(&self, xid: i32) -> ZkError {
+        let (got, _, err, reader) = self.reply();
+        assert_eq!(got, xid, "{}: reply to another request", self.name);
+        self.assert_consumed(&reader);
+        err
+    }
+
+    fn assert_consumed(&self, reader: &Cursor<Bytes>) {
+        let left = reader.get_ref().len() - reader.position() as usize;
+        assert_eq!(left, 0, "{}: {} bytes left over", self.name, left);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn path(path: &str) -> String {
+    path.to_string()
+}
+
+fn open_acl() -> Cow<'static, [Acl]> {
+    Cow::Borrowed(Acl::open_unsafe())
+}
+
+const CTIME: i64 = 1_700_000_000_000;
+const SESSION_ID: i64 = 0x0100_0023_5bd4_0000;
+const PASSWD: [u8; 16] = [
+    0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xab, 0xac, 0xad, 0xae, 0xaf,
+];
+
+/// The stat of `/app` once it has been created with two bytes of data.
+fn created() -> Stat {
+    Stat {
+        czxid: 2,
+        mzxid: 2,
+        ctime: CTIME,
+        mtime: CTIME,
+        version: 0,
+        cversion: 0,
+        aversion: 0,
+        ephemeral_owner: 0,
+        data_length: 2,
+        num_children: 0,
+        pzxid: 2,
+    }
+}
+
+fn connect(last_zxid_seen: i64, session_id: i64, passwd: &[u8]) -> Request {
+    Request::Connect {
+        protocol_version: 0,
+        last_zxid_seen,
+        timeout: 30_000,
+        session_id,
+        passwd: passwd.to_vec(),
+        read_only: false,
+    }
+}
+
+fn check_connected(fixture: &Fixture, timeout: i32, session_id: i64, passwd: &[u8]) {
+    let mut reader = Cursor::new(Bytes::from(fixture.received[4..].to_vec()));
+    match Response::parse(OpCode::CreateSession, &mut reader).unwrap() {
+        Response::Connect {
+            protocol_version: 0,
+            timeout: t,
+            session_id: id,
+            password,
+            read_only: false,
+        } => {
+            assert_eq!((t, id), (timeout, session_id));
+            assert_eq!(password, passwd);
+        }
+        r => panic!("{}: unexpected response {:?}", fixture.name, r),
+    }
+    fixture.assert_consumed(&reader);
+}
+
+#[test]
+fn connect_new_session() {
+    let f = fixture!("connect");
+    f.check_sent(0, &connect(0, 0, &[0; 16]));
+    check_connected(&f, 30_000, SESSION_ID, &PASSWD);
+}
+
+#[test]
+fn reconnect() {
+    let f = fixture!("reconnect");
+    f.check_sent(0, &connect(0x1a, SESSION_ID, &PASSWD));
+    check_connected(&f, 30_000, SESSION_ID, &PASSWD);
+}
+
+#[test]
+fn session_expired() {
+    let f = fixture!("session_expired");
+    f.check_sent(0, &connect(0x1a, SESSION_ID, &PASSWD));
+    check_connected(&f, 0, 0, &[0; 16]);
+}
+
+#[test]
+fn ping() {
+    let f = fixture!("ping");
+    let mut frame = Vec::new();
+    request::write_ping(&mut frame);
+    assert_eq!(hex(&frame), hex(&f.sent));
+    assert_eq!(f.error(-2), ZkError::Ok);
+}
+
+#[test]
+fn create() {
+    let f = fixture!("create");
+    f.check_sent(
+        1,
+        &Request::Create {
+            path: path("/app"),
+            data: Cow::Borrowed(b"hi"),
+            acl: open_acl(),
+            mode: CreateMode::Persistent,
+            with_stat: false,
+        },
+    );
+    match f.response(1, OpCode::Create) {
+        Response::String(ref p) if p == "/app" => {}
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn create_sequential() {
+    let f = fixture!("create_sequential");
+    f.check_sent(
+        2,
+        &Request::Create {
+            path: path("/lock-"),
+            data: Cow::Borrowed(b""),
+            acl: Cow::Borrowed(Acl::creator_all()),
+            mode: CreateMode::EphemeralSequential,
+            with_stat: false,
+        },
+    );
+    match f.response(2, OpCode::Create) {
+        Response::String(ref p) if p == "/lock-0000000007" => {}
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn create_node_exists() {
+    let f = fixture!("create_node_exists");
+    f.check_sent(
+        3,
+        &Request::Create {
+            path: path("/app"),
+            data: Cow::Borrowed(b""),
+            acl: open_acl(),
+            mode: CreateMode::Persistent,
+            with_stat: false,
+        },
+    );
+    assert_eq!(f.error(3), ZkError::NodeExists);
+}
+
+#[test]
+fn exists() {
+    let f = fixture!("exists");
+    f.check_sent(
+        4,
+        &Request::Exists {
+            path: path("/app"),
+            watch: Watch::Global,
+        },
+    );
+    match f.response(4, OpCode::Exists) {
+        Response::Stat(stat) => assert_eq!(stat, created()),
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn exists_no_node() {
+    let f = fixture!("exists_no_node");
+    f.check_sent(
+        5,
+        &Request::Exists {
+            path: path("/missing"),
+            watch: Watch::None,
+        },
+    );
+    assert_eq!(f.error(5), ZkError::NoNode);
+}
+
+#[test]
+fn get_data() {
+    let f = fixture!("get_data");
+    f.check_sent(
+        6,
+        &Request::GetData {
+            path: path("/app"),
+            watch: Watch::None,
+        },
+    );
+    match f.response(6, OpCode::GetData) {
+        Response::GetData { bytes, stat } => {
+            assert_eq!(&bytes[..], b"hi");
+            assert_eq!(stat, created());
+        }
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn set_data() {
+    let f = fixture!("set_data");
+    let request = Request::SetData {
+        path: path("/app"),
+        data: Cow::Borrowed(b"hello"),
+        version: 0,
+    };
+    f.check_sent(7, &request);
+    match f.response(7, OpCode::SetData) {
+        Response::Stat(stat) => {
+            let expected = Stat {
+                mzxid: 4,
+                version: 1,
+                data_length: 5,
+                ..created()
+            };
+            assert_eq!(stat, expected);
+        }
+        r => panic!("unexpected response {:?}", r),
+    }
+
+    let f = fixture!("set_data_bad_version");
+    f.check_sent(8, &request);
+    assert_eq!(f.error(8), ZkError::BadVersion);
+}
+
+#[test]
+fn get_children() {
+    let f = fixture!("get_children");
+    f.check_sent(
+        9,
+        &Request::GetChildren {
+            path: path("/app"),
+            watch: Watch::Global,
+        },
+    );
+    match f.response(9, OpCode::GetChildren) {
+        Response::Strings(children) => assert_eq!(children, ["a", "b"]),
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn get_acl() {
+    let f = fixture!("get_acl");
+    f.check_sent(10, &Request::GetAcl { path: path("/app") });
+    match f.response(10, OpCode::GetACL) {
+        Response::GetAcl { acl, stat } => {
+            assert_eq!(acl, Acl::open_unsafe());
+            assert_eq!((stat.cversion, stat.num_children, stat.pzxid), (2, 2, 6));
+        }
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn set_acl() {
+    let f = fixture!("set_acl");
+    f.check_sent(
+        11,
+        &Request::SetAcl {
+            path: path("/app"),
+            acl: Cow::Borrowed(Acl::read_unsafe()),
+            version: 0,
+        },
+    );
+    match f.response(11, OpCode::SetACL) {
+        Response::Stat(stat) => assert_eq!(stat.aversion, 1),
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn delete() {
+    let f = fixture!("delete");
+    f.check_sent(
+        12,
+        &Request::Delete {
+            path: path("/app/a"),
+            version: -1,
+        },
+    );
+    match f.response(12, OpCode::Delete) {
+        Response::Empty => {}
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn multi() {
+    let f = fixture!("multi");
+    f.check_sent(
+        13,
+        &Request::Multi(vec![
+            Request::Create {
+                path: path("/m"),
+                data: Cow::Borrowed(b""),
+                acl: open_acl(),
+                mode: CreateMode::Persistent,
+                with_stat: false,
+            },
+            Request::SetData {
+                path: path("/m"),
+                data: Cow::Borrowed(b"x"),
+                version: 0,
+            },
+            Request::Check {
+                path: path("/m"),
+                version: 1,
+            },
+        ]),
+    );
+    match f.response(13, OpCode::Multi) {
+        Response::Multi(results) => match results[..] {
+            [Ok(Response::String(ref p)), Ok(Response::Stat(ref stat)), Ok(Response::Empty)] => {
+                assert_eq!(p, "/m");
+                assert_eq!((stat.version, stat.data_length), (1, 1));
+            }
+            ref r => panic!("unexpected results {:?}", r),
+        },
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn multi_failed() {
+    let f = fixture!("multi_failed");
+    f.check_sent(
+        14,
+        &Request::Multi(vec![
+            Request::SetData {
+                path: path("/m"),
+                data: Cow::Borrowed(b"y"),
+                version: -1,
+            },
+            Request::Check {
+                path: path("/m"),
+                version: 0,
+            },
+            Request::Delete {
+                path: path("/m"),
+                version: -1,
+            },
+        ]),
+    );
+    match f.response(14, OpCode::Multi) {
+        Response::Multi(results) => {
+            let errors: Vec<_> = results.iter().map(|r| r.as_ref().err().copied()).collect();
+            assert_eq!(
+                errors,
+                [
+                    Some(ZkError::Ok),
+                    Some(ZkError::BadVersion),
+                    Some(ZkError::RuntimeInconsistency),
+                ]
+            );
+        }
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn set_watches() {
+    let f = fixture!("set_watches");
+    f.check_sent(
+        -8,
+        &Request::SetWatches {
+            relative_zxid: 0x1a,
+            data: vec![path("/app")],
+            exist: vec![path("/missing")],
+            child: vec![path("/app")],
+            persistent: vec![],
+            recursive: vec![],
+        },
+    );
+    assert_eq!(f.error(-8), ZkError::Ok);
+}
+
+#[test]
+fn set_watches2() {
+    let f = fixture!("set_watches2");
+    f.check_sent(
+        -8,
+        &Request::SetWatches {
+            relative_zxid: 0x1a,
+            data: vec![path("/app")],
+            exist: vec![],
+            child: vec![],
+            persistent: vec![],
+            recursive: vec![path("/journal")],
+        },
+    );
+    assert_eq!(f.error(-8), ZkError::Ok);
+}
+
+#[test]
+fn add_watch() {
+    let f = fixture!("add_watch");
+    f.check_sent(
+        13,
+        &Request::AddWatch {
+            path: path("/app"),
+            recursive: true,
+            watch: Watch::None,
+        },
+    );
+    match f.response(13, OpCode::AddWatch) {
+        Response::Empty => {}
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn sync() {
+    let f = fixture!("sync");
+    f.check_sent(14, &Request::Sync { path: path("/app") });
+    match f.response(14, OpCode::Synchronize) {
+        Response::String(synced) => assert_eq!(synced, "/app"),
+        r => panic!("unexpected response {:?}", r),
+    }
+}
+
+#[test]
+fn notification() {
+    let f = fixture!("notification");
+    assert!(f.sent.is_empty());
+    let (xid, zxid, err, mut reader) = f.reply();
+    assert_eq!((xid, zxid, err), (-1, -1, ZkError::Ok));
+    let e = WatchedEvent::read_from(&mut reader).unwrap();
+    f.assert_consumed(&reader);
+    assert_eq!(
+        e,
+        WatchedEvent {
+            event_type: WatchedEventType::NodeDataChanged,
+            keeper_state: KeeperState::SyncConnected,
+            path: path("/app"),
+        }
+    );
+}
+
+/// The public codec reads every frame the way the client and the server do, and writes it back
+/// byte for byte.
+#[test]
+fn codec() {
+    use crate::codec::jute::WriteTo;
+    use crate::codec::{self, frame::frame, Op, Reply, ReplyHeader, RequestHeader};
+
+    for f in [fixture!("connect"), fixture!("reconnect"), fixture!("session_expired")] {
+        let request = codec::ConnectRequest::read(&f.sent[4..]).unwrap();
+        assert_eq!(hex(&frame(|w| request.write_to(w))), hex(&f.sent), "{}", f.name);
+        let response = codec::ConnectResponse::read(&f.received[4..]).unwrap();
+        assert_eq!(hex(&frame(|w| response.write_to(w))), hex(&f.received), "{}", f.name);
+    }
+
+    let exchanges = [
+        fixture!("ping"),
+        fixture!("create"),
+        fixture!("create_sequential"),
+        fixture!("create_node_exists"),
+        fixture!("exists"),
+        fixture!("exists_no_node"),
+        fixture!("get_data"),
+        fixture!("set_data"),
+        fixture!("set_data_bad_version"),
+        fixture!("get_children"),
+        fixture!("get_acl"),
+        fixture!("set_acl"),
+        fixture!("delete"),
+        fixture!("multi"),
+        fixture!("multi_failed"),
+        fixture!("set_watches"),
+        fixture!("notification"),
+    ];
+    for f in exchanges {
+        let mut opcode = None;
+        if !f.sent.is_empty() {
+            let mut r = &f.sent[4..];
+            let header = RequestHeader::read(&mut r).unwrap();
+            let op = Op::read(header.opcode, &mut r).unwrap();
+            assert!(r.is_empty(), "{}: request left over", f.name);
+            let sent = frame(|w| {
+                header.write_to(w);
+                op.write_to(w);
+            });
+            assert_eq!(hex(&sent), hex(&f.sent), "{}: request differs", f.name);
+            opcode = Some(header.opcode);
+        }
+
+        let mut r = &f.received[4..];
+        let header = ReplyHeader::read(&mut r).unwrap();
+        let mut body = Vec::new();
+        if header.xid == codec::NOTIFICATION_XID {
+            let event = WatchedEvent::read_from(&mut r).unwrap();
+            event.write_to(&mut body).unwrap();
+        } else if header.err == ZkError::Ok {
+            let reply = Reply::read(opcode.unwrap(), &mut r).unwrap();
+            reply.write_to(&mut body);
+        }
+        assert!(r.is_empty(), "{}: reply left over", f.name);
+        let received = frame(|w| {
+            header.write_to(w);
+            w.extend_from_slice(&body);
+        });
+        assert_eq!(hex(&received), hex(&f.received), "{}: reply differs", f.name);
+    }
+}
@@ -0,0 +1,207 @@
+//! Entry points into the decoder for the fuzz targets in `fuzz/`.
+//!
+//! This is only compiled with the `fuzz` feature, and is not part of the public API.
+
+use super::active_packetizer::ActivePacketizer;
+use super::request::OpCode;
+use super::stats::SharedStats;
+use super::trace::RequestSpan;
+use super::watch::{WatchSink, WatchType};
+use super::{DefaultWatcher, Options, Request, Response, Watch};
+use bytes::Bytes;
+use futures::channel::{mpsc, oneshot};
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use crate::codec::jute::ReadFrom;
+use crate::logging::Logger;
+use crate::namespace::Namespace;
+use crate::{Acl, CreateMode, WatchedEvent};
+
+/// Parse `body` as the body of a response to the request with the given opcode, if it is a known
+/// one.
+pub fn decode_response(opcode: i32, body: &[u8]) {
+    if let Ok(opcode) = OpCode::try_from(opcode) {
+        let _ = Response::parse(opcode, &mut Cursor::new(Bytes::copy_from_slice(body)));
+    }
+}
+
+/// Parse `body` as the body of a response to a handshake.
+pub fn decode_connect(body: &[u8]) {
+    decode_response(OpCode::CreateSession as i32, body)
+}
+
+/// Parse `body` as the body of a watch notification.
+pub fn decode_event(body: &[u8]) {
+    let _ = WatchedEvent::read_from(&mut &body[..]);
+}
+
+/// A connection whose server sends `input`, and then closes it.
+struct Replay<'a> {
+    input: &'a [u8],
+}
+
+impl AsyncRead for Replay<'_> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        // a few bytes at a time, so that frames arrive in pieces
+        let n = self.input.len().min(buf.remaining()).min(61);
+        buf.put_slice(&self.input[..n]);
+        self.input = &self.input[n..];
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Replay<'_> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The requests that `read_frames` has in flight, in the order of their xids from 1 on, along
+/// with the watch that each of them sets.
+fn requests() -> Vec<(Request, Option<WatchType>)> {
+    let path = || "/a".to_string();
+    let acl = || Cow::Borrowed(Acl::open_unsafe());
+    vec![
+        (
+            Request::Create {
+                path: path(),
+                data: Cow::Borrowed(b""),
+                acl: acl(),
+                mode: CreateMode::Persistent,
+                with_stat: false,
+            },
+            None,
+        ),
+        (
+            Request::Exists {
+                path: path(),
+                watch: Watch::Global,
+            },
+            Some(WatchType::Exist),
+        ),
+        (
+            Request::GetData {
+                path: path(),
+                watch: Watch::Global,
+            },
+            Some(WatchType::Data),
+        ),
+        (
+            Request::GetChildren {
+                path: path(),
+                watch: Watch::Global,
+            },
+            Some(WatchType::Child),
+        ),
+        (
+            Request::GetChildren2 {
+                path: path(),
+                watch: Watch::Global,
+            },
+            Some(WatchType::Child),
+        ),
+        (
+            Request::SetData {
+                path: path(),
+                data: Cow::Borrowed(b""),
+                version: -1,
+            },
+            None,
+        ),
+        (Request::GetAcl { path: path() }, None),
+        (
+            Request::SetAcl {
+                path: path(),
+                acl: acl(),
+                version: -1,
+            },
+            None,
+        ),
+        (
+            Request::Delete {
+                path: path(),
+                version: -1,
+            },
+            None,
+        ),
+        (
+            Request::Multi(vec![Request::Check {
+                path: path(),
+                version: -1,
+            }]),
+            None,
+        ),
+    ]
+}
+
+/// Feed `input` to a connection as everything that the server sends on it, from the response to
+/// the handshake on.
+///
+/// The connection has sent a handshake, and then one request of every kind with xids from 1 on,
+/// so that `input` can answer them all, in between pings, watch notifications, and whatever else
+/// it holds.
+pub fn read_frames(input: &[u8]) {
+    // the packetizer arms its timers on Tokio, though none of them ever fires here
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .expect("failed to build a runtime");
+    let _guard = runtime.enter();
+
+    let mut ap = ActivePacketizer::new(
+        Replay { input },
+        Options::default(),
+        Arc::new(SharedStats::default()),
+    );
+    let mut watches = Vec::new();
+    let connect = Request::Connect {
+        protocol_version: 0,
+        last_zxid_seen: 0,
+        timeout: 30_000,
+        session_id: 0,
+        passwd: vec![0; 16],
+        read_only: false,
+    };
+    let requests = std::iter::once((connect, None)).chain(requests());
+    for (xid, (request, watch)) in requests.enumerate() {
+        let (tx, rx) = oneshot::channel();
+        let watcher = watch.map(|watch| {
+            let (tx, rx) = oneshot::channel();
+            watches.push(rx);
+            let path = ap.intern(request.path().expect("watches are set on paths"));
+            (path, WatchSink::Once(tx), watch, Namespace::default())
+        });
+        let span = RequestSpan::new(&request);
+        ap.enqueue(xid as i32, request, tx, watcher, span);
+        drop(rx);
+    }
+
+    let (tx, _events) = mpsc::unbounded();
+    let mut default_watcher = DefaultWatcher::new(tx, Arc::new(SharedStats::default()));
+    let mut logger = Logger::default();
+    let waker = futures::task::noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    // reading stops at the end of the input, or at the first frame that is rejected
+    let _ = ap.poll(&mut cx, false, &mut logger, &mut default_watcher);
+}
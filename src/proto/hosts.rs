@@ -0,0 +1,50 @@
+//! The servers of an ensemble that a session may connect to.
+
+/// The addresses of the servers of an ensemble, in the order that connections are made to them.
+///
+/// The session connects to the first address to begin with, and every connection that
+/// re-establishes it moves on to the next, wrapping around after the last. So a server that has
+/// gone away is not tried again until every other server has been, much like the Java client's
+/// `StaticHostProvider` does.
+#[derive(Clone, Debug)]
+pub(crate) struct HostProvider<A> {
+    addrs: Vec<A>,
+    current: usize,
+}
+
+impl<A> HostProvider<A> {
+    /// Hand out `addrs` in order, starting with the first of them, of which there has to be one.
+    pub(crate) fn new(addrs: Vec<A>) -> Self {
+        assert!(!addrs.is_empty(), "a session needs a server to connect to");
+        HostProvider { addrs, current: 0 }
+    }
+
+    /// Return how many servers there are.
+    pub(crate) fn len(&self) -> usize {
+        self.addrs.len()
+    }
+
+    /// Return the address that was connected to last.
+    pub(crate) fn current(&self) -> &A {
+        &self.addrs[self.current]
+    }
+
+    /// Move on to the next address, and return it.
+    pub(crate) fn advance(&mut self) -> &A {
+        self.current = (self.current + 1) % self.addrs.len();
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_around() {
+        let mut hosts = HostProvider::new(vec![1, 2, 3]);
+        assert_eq!(*hosts.current(), 1);
+        let order: Vec<_> = (0..4).map(|_| *hosts.advance()).collect();
+        assert_eq!(order, [2, 3, 1, 2]);
+    }
+}
@@ -1,33 +1,184 @@
-use failure;
+use crate::metrics::Metrics;
+use std::fmt;
+use std::future::Future;
+use std::io;
 use std::net::SocketAddr;
-use tokio;
-use tokio::prelude::*;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time;
+use tokio::io::{AsyncRead, AsyncWrite};
+#[cfg(any(feature = "async-std", feature = "smol"))]
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt};
+use crate::circuit::CircuitBreaker;
+use crate::error::Context;
+use crate::rate::RateLimit;
+use crate::runtime::Runtime;
+use crate::{Error, FlushStrategy};
+
+#[macro_use]
+mod trace;
 
 mod active_packetizer;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(any(test, feature = "testing"))]
+mod close;
 mod error;
+#[cfg(test)]
+mod fixtures;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+mod hosts;
+mod outbox;
 mod packetizer;
+mod redact;
 mod request;
 mod response;
+#[cfg(test)]
+mod roundtrip;
+mod stats;
 mod watch;
 
-pub(crate) use self::error::ZkError;
+#[cfg(any(test, feature = "testing"))]
+pub(crate) use self::close::close_session;
+pub use self::error::ZkError;
+pub(crate) use self::hosts::HostProvider;
 pub(crate) use self::packetizer::{Enqueuer, Packetizer};
+pub(crate) use self::redact::Logged;
 pub(crate) use self::request::Request;
 pub(crate) use self::response::Response;
-pub(crate) use self::watch::Watch;
+pub(crate) use self::watch::{DefaultWatcher, Watch};
+
+/// The server's answer to a request, along with the request itself if it failed.
+pub(crate) type Reply = Result<Response, (ZkError, Option<Context>)>;
+
+/// The size of the largest request frame that servers accept by default, set by their
+/// `jute.maxbuffer`.
+pub(crate) const DEFAULT_MAX_REQUEST_SIZE: usize = 0xfffff;
+
+/// Connection settings, as configured through the `ZooKeeperBuilder`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Options {
+    /// When to write queued frames to the server.
+    pub(crate) flush: FlushStrategy,
+    pub(crate) metrics: Metrics,
+    /// Log requests that take at least this long to complete.
+    pub(crate) slow_request_threshold: Option<time::Duration>,
+    /// Log watch events that take at least this long to be taken from the watch stream.
+    pub(crate) slow_watch_threshold: Option<time::Duration>,
+    pub(crate) callbacks: Callbacks,
+    /// Log the data in requests and responses in full, rather than just its length and hash.
+    pub(crate) log_payloads: bool,
+    /// Send reads that were in flight when the connection was lost again once it is re-established.
+    pub(crate) reissue_reads: bool,
+    /// Where the connection's timers come from.
+    pub(crate) runtime: Runtime,
+    /// Warn that the session may be about to expire once the server has not been heard from for
+    /// this fraction of the session timeout.
+    pub(crate) expiry_warning: Option<f64>,
+    /// Do not close the session once all handles are dropped, but leave it to expire.
+    pub(crate) detach_on_drop: bool,
+    /// When to fail requests right away instead of sending them.
+    pub(crate) circuit_breaker: Option<CircuitBreaker>,
+    /// How fast requests may be sent.
+    pub(crate) rate_limit: Option<RateLimit>,
+    /// Fail requests whose frames are larger than this rather than send them, or ones larger than
+    /// `DEFAULT_MAX_REQUEST_SIZE` if `None`.
+    pub(crate) max_request_size: Option<usize>,
+    /// Allow the server to be in read-only mode.
+    pub(crate) read_only: bool,
+    /// Give up on connecting once this much time has passed.
+    pub(crate) connect_timeout: Option<time::Duration>,
+    /// Fail requests that are not answered within this long.
+    pub(crate) request_timeout: Option<time::Duration>,
+}
+
+type Callback = Arc<dyn Fn() + Send + Sync>;
+
+/// Hooks that are called synchronously as the state of the connection changes.
+#[derive(Clone, Default)]
+pub(crate) struct Callbacks {
+    pub(crate) connected: Option<Callback>,
+    pub(crate) disconnected: Option<Callback>,
+    pub(crate) session_expired: Option<Callback>,
+    pub(crate) auth_failed: Option<Callback>,
+}
+
+impl Callbacks {
+    fn call(callback: &Option<Callback>) {
+        if let Some(ref f) = *callback {
+            f();
+        }
+    }
+
+    pub(crate) fn connected(&self) {
+        Self::call(&self.connected);
+    }
+
+    pub(crate) fn disconnected(&self) {
+        Self::call(&self.disconnected);
+    }
+
+    pub(crate) fn session_expired(&self) {
+        Self::call(&self.session_expired);
+    }
 
-pub trait ZooKeeperTransport: AsyncRead + AsyncWrite + Sized + Send {
+    pub(crate) fn auth_failed(&self) {
+        Self::call(&self.auth_failed);
+    }
+}
+
+impl fmt::Debug for Callbacks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Callbacks")
+            .field("connected", &self.connected.is_some())
+            .field("disconnected", &self.disconnected.is_some())
+            .field("session_expired", &self.session_expired.is_some())
+            .field("auth_failed", &self.auth_failed.is_some())
+            .finish()
+    }
+}
+
+pub trait ZooKeeperTransport: AsyncRead + AsyncWrite + Sized + Send + Unpin {
     type Addr: Send;
-    type ConnectError: Into<failure::Error>;
-    type ConnectFut: Future<Item = Self, Error = Self::ConnectError> + Send + 'static;
-    fn connect(&Self::Addr) -> Self::ConnectFut;
+    type ConnectError: Into<Error>;
+    type ConnectFut: Future<Output = Result<Self, Self::ConnectError>> + Send + Unpin + 'static;
+    fn connect(addr: &Self::Addr) -> Self::ConnectFut;
 }
 
 impl ZooKeeperTransport for tokio::net::TcpStream {
     type Addr = SocketAddr;
-    type ConnectError = tokio::io::Error;
-    type ConnectFut = tokio::net::tcp::ConnectFuture;
+    type ConnectError = io::Error;
+    type ConnectFut = Pin<Box<dyn Future<Output = io::Result<Self>> + Send>>;
+    fn connect(addr: &Self::Addr) -> Self::ConnectFut {
+        Box::pin(tokio::net::TcpStream::connect(*addr))
+    }
+}
+
+#[cfg(feature = "async-std")]
+impl ZooKeeperTransport for Compat<async_std::net::TcpStream> {
+    type Addr = SocketAddr;
+    type ConnectError = io::Error;
+    type ConnectFut = Pin<Box<dyn Future<Output = io::Result<Self>> + Send>>;
+    fn connect(addr: &Self::Addr) -> Self::ConnectFut {
+        let addr = *addr;
+        Box::pin(async move {
+            let stream = async_std::net::TcpStream::connect(addr).await?;
+            Ok(stream.compat())
+        })
+    }
+}
+
+#[cfg(feature = "smol")]
+impl ZooKeeperTransport for Compat<smol::net::TcpStream> {
+    type Addr = SocketAddr;
+    type ConnectError = io::Error;
+    type ConnectFut = Pin<Box<dyn Future<Output = io::Result<Self>> + Send>>;
     fn connect(addr: &Self::Addr) -> Self::ConnectFut {
-        tokio::net::TcpStream::connect(addr)
+        let addr = *addr;
+        Box::pin(async move {
+            let stream = smol::net::TcpStream::connect(addr).await?;
+            Ok(stream.compat())
+        })
     }
 }
@@ -0,0 +1,174 @@
+use bytes::Buf;
+use std::collections::VecDeque;
+use std::io::IoSlice;
+
+/// The most sent frames that are kept for reuse.
+const MAX_SPARE: usize = 16;
+
+/// The largest allocation that a sent frame may have to be kept for reuse.
+///
+/// Most requests are small, so keeping the frames of a burst of large writes would hold on to
+/// their memory for as long as the connection lasts.
+const MAX_SPARE_CAPACITY: usize = 64 * 1024;
+
+/// The xid that follows the length of a ping's frame.
+const PING_XID: [u8; 4] = (-2i32).to_be_bytes();
+
+/// Frames that are waiting to be written to the server.
+///
+/// Each request is serialized into a frame of its own, so enqueueing never has to move bytes that
+/// are already queued. The outbox implements `Buf` over all of its frames, which lets transports
+/// that support vectored writes flush many small requests with a single `writev`.
+#[derive(Debug, Default)]
+pub(super) struct Outbox {
+    frames: VecDeque<Vec<u8>>,
+
+    /// Prefix of the front frame that has been sent.
+    start: usize,
+
+    /// Total number of bytes that have not yet been sent.
+    len: usize,
+
+    /// Sent frames, kept so that their allocations can be reused for new frames.
+    spare: Vec<Vec<u8>>,
+}
+
+impl Outbox {
+    /// Return whether all queued bytes have been sent.
+    pub(super) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Return how many of the queued frames have not had any of their bytes sent, leaving out
+    /// pings, which nobody waits for the response to.
+    pub(super) fn unsent(&self) -> usize {
+        let started = usize::from(self.start > 0);
+        self.frames
+            .iter()
+            .skip(started)
+            .filter(|frame| frame.get(4..8) != Some(&PING_XID[..]))
+            .count()
+    }
+
+    /// Queue a new frame, with the contents that `write` writes into it.
+    ///
+    /// The frame must not be left empty.
+    pub(super) fn push<F: FnOnce(&mut Vec<u8>)>(&mut self, write: F) {
+        let mut frame = self.spare.pop().unwrap_or_default();
+        write(&mut frame);
+        debug_assert!(!frame.is_empty(), "empty frames cannot be written");
+        self.len += frame.len();
+        self.frames.push_back(frame);
+    }
+}
+
+impl Buf for Outbox {
+    fn remaining(&self) -> usize {
+        self.len
+    }
+
+    fn chunk(&self) -> &[u8] {
+        match self.frames.front() {
+            Some(frame) => &frame[self.start..],
+            None => &[],
+        }
+    }
+
+    fn advance(&mut self, mut cnt: usize) {
+        assert!(cnt <= self.len, "cannot advance past the end of the outbox");
+        self.len -= cnt;
+        while cnt != 0 {
+            let left = self.frames[0].len() - self.start;
+            if cnt < left {
+                self.start += cnt;
+                return;
+            }
+            cnt -= left;
+            self.start = 0;
+            let mut frame = self
+                .frames
+                .pop_front()
+                .expect("len covers only queued frames");
+            if self.spare.len() < MAX_SPARE && frame.capacity() <= MAX_SPARE_CAPACITY {
+                frame.clear();
+                self.spare.push(frame);
+            }
+        }
+    }
+
+    fn chunks_vectored<'a>(&'a self, dst: &mut [IoSlice<'a>]) -> usize {
+        let mut start = self.start;
+        let mut n = 0;
+        for (frame, dst) in self.frames.iter().zip(dst.iter_mut()) {
+            *dst = IoSlice::new(&frame[start..]);
+            start = 0;
+            n += 1;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slices(outbox: &Outbox) -> Vec<Vec<u8>> {
+        let mut iovs = [IoSlice::new(b"x"), IoSlice::new(b"x")];
+        let n = outbox.chunks_vectored(&mut iovs);
+        iovs[..n].iter().map(|iov| iov.to_vec()).collect()
+    }
+
+    #[test]
+    fn frames() {
+        let mut outbox = Outbox::default();
+        assert!(outbox.is_empty());
+        outbox.push(|f| f.extend_from_slice(b"abc"));
+        outbox.push(|f| f.extend_from_slice(b"de"));
+        outbox.push(|f| f.extend_from_slice(b"fghi"));
+        assert_eq!(outbox.remaining(), 9);
+        assert_eq!(slices(&outbox), [&b"abc"[..], b"de"]);
+
+        outbox.advance(4);
+        assert_eq!(outbox.chunk(), b"e");
+        assert_eq!(slices(&outbox), [&b"e"[..], b"fghi"]);
+        assert_eq!(outbox.spare.len(), 1);
+
+        // the front frame has been sent in part
+        assert_eq!(outbox.unsent(), 1);
+
+        outbox.advance(5);
+        assert!(outbox.is_empty());
+        assert_eq!(outbox.chunk(), b"");
+        assert!(slices(&outbox).is_empty());
+
+        // sent frames are reused
+        outbox.push(|f| f.push(1));
+        assert_eq!(outbox.spare.len(), 2);
+        assert_eq!(outbox.chunk(), [1]);
+    }
+
+    #[test]
+    fn unsent_pings() {
+        let mut outbox = Outbox::default();
+        outbox.push(super::super::request::write_ping);
+        outbox.push(|f| f.extend_from_slice(&[0, 0, 0, 8, 0, 0, 0, 1, 0, 0, 0, 3]));
+        assert_eq!(outbox.unsent(), 1);
+    }
+
+    #[test]
+    fn spare_limits() {
+        let mut outbox = Outbox::default();
+        // large frames are dropped once sent
+        outbox.push(|f| f.resize(MAX_SPARE_CAPACITY + 1, 0));
+        outbox.advance(MAX_SPARE_CAPACITY + 1);
+        assert!(outbox.spare.is_empty());
+
+        // and only so many small ones are kept
+        for _ in 0..2 * MAX_SPARE {
+            outbox.frames.push_back(vec![0]);
+            outbox.len += 1;
+        }
+        outbox.advance(2 * MAX_SPARE);
+        assert_eq!(outbox.spare.len(), MAX_SPARE);
+    }
+}
@@ -1,103 +1,380 @@
 use super::{
-    active_packetizer::ActivePacketizer, request, watch::WatchType, Request, Response,
-    ZooKeeperTransport,
+    active_packetizer::{ActivePacketizer, PersistentWatcher, Reissue, Watcher},
+    request,
+    stats::SharedStats,
+    trace::RequestSpan,
+    watch::{WatchSink, WatchType},
+    Callbacks, DefaultWatcher, HostProvider, Logged, Options, Reply, Request, ZooKeeperTransport,
+    DEFAULT_MAX_REQUEST_SIZE,
 };
-use byteorder::{BigEndian, WriteBytesExt};
-use failure;
 use futures::{
-    future::Either,
-    sync::{mpsc, oneshot},
+    channel::{mpsc, oneshot},
+    future::{self, Either},
+    future::Shared,
+    ready, FutureExt, StreamExt,
 };
-use slog;
+use crate::circuit::Breaker;
+use crate::namespace::Namespace;
+use crate::rate::Limiter;
+use std::collections::HashMap;
+use std::future::Future;
 use std::mem;
-use tokio;
-use tokio::prelude::*;
-use {Watch, WatchedEvent, ZkError};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use crate::logging::Logger;
+use crate::runtime::{Runtime, Sleep};
+use crate::{
+    ConnectionState, Error, KeeperState, Watch, WatchedEvent, WatchedEventStream, WatchedEventType,
+    ZkError,
+};
 
 pub(crate) struct Packetizer<S>
 where
     S: ZooKeeperTransport,
 {
-    /// ZooKeeper address
-    addr: S::Addr,
+    /// The servers to connect to, in turn, whenever the connection is lost.
+    hosts: HostProvider<S::Addr>,
 
     /// Current state
     state: PacketizerState<S>,
 
     /// Watcher to send watch events to.
-    default_watcher: mpsc::UnboundedSender<WatchedEvent>,
+    default_watcher: DefaultWatcher,
 
     /// Incoming requests
-    rx: mpsc::UnboundedReceiver<(Request, oneshot::Sender<Result<Response, ZkError>>)>,
+    rx: mpsc::UnboundedReceiver<Enqueued>,
 
     /// Next xid to issue
     xid: i32,
 
-    logger: slog::Logger,
+    logger: Logger,
+
+    /// Hooks to call when the connection is lost.
+    callbacks: Callbacks,
+
+    stats: Arc<SharedStats>,
+
+    /// Warns that the session may be about to expire, if asked to.
+    expiry: Option<ExpiryWarning>,
+
+    /// Holds requests back while they come in too fast, if a rate limit is set.
+    limiter: Option<Limiter>,
+
+    runtime: Runtime,
+
+    /// Ends a shutdown that is taking too long, once one has begun.
+    drain: Option<Sleep>,
+
+    /// Whether the close of the session has been queued, after which the connection shuts down
+    /// once it is written out.
+    closing: bool,
+
+    /// Dropped along with the packetizer, to tell whoever waits for the shutdown that it is over.
+    _finished: oneshot::Sender<()>,
 
     exiting: bool,
 }
 
+// the packetizer is never pinned in place; the timers and futures it drives are boxed or `Unpin`
+impl<S> Unpin for Packetizer<S> where S: ZooKeeperTransport {}
+
 impl<S> Packetizer<S>
 where
     S: ZooKeeperTransport,
 {
+    /// Return a handle for enqueueing requests, along with the packetizer that sends them, which
+    /// has to be polled for any of them to make progress.
     pub(crate) fn new(
-        addr: S::Addr,
+        hosts: HostProvider<S::Addr>,
         stream: S,
-        log: slog::Logger,
-        default_watcher: mpsc::UnboundedSender<WatchedEvent>,
-    ) -> Enqueuer
-    where
-        S: Send + 'static + AsyncRead + AsyncWrite,
-    {
+        log: Logger,
+        default_watcher: mpsc::UnboundedSender<(WatchedEvent, Instant)>,
+        options: Options,
+    ) -> (Enqueuer, Self) {
         let (tx, rx) = mpsc::unbounded();
+        let (finished, shut_down) = oneshot::channel();
+        let mut stats = SharedStats::default();
+        stats.breaker = options.circuit_breaker.map(Breaker::new);
+        let stats = Arc::new(stats);
+        stats.detached.store(options.detach_on_drop, Ordering::Relaxed);
+        stats.handle_watchers.lock().unwrap().configure(&options);
+        let expiry = options
+            .expiry_warning
+            .map(|fraction| ExpiryWarning::new(fraction, options.runtime));
+
+        let runtime = options.runtime;
+        let limiter = options.rate_limit.map(|limit| Limiter::new(limit, runtime));
+        let max_request_size = options.max_request_size.unwrap_or(DEFAULT_MAX_REQUEST_SIZE);
+        let packetizer = Packetizer {
+            hosts,
+            callbacks: options.callbacks.clone(),
+            state: PacketizerState::Connected(ActivePacketizer::new(
+                stream,
+                options,
+                stats.clone(),
+            )),
+            stats: stats.clone(),
+            xid: 0,
+            default_watcher: DefaultWatcher::new(default_watcher, stats.clone()),
+            rx,
+            logger: log,
+            expiry,
+            limiter,
+            runtime,
+            drain: None,
+            closing: false,
+            _finished: finished,
+            exiting: false,
+        };
 
-        let exitlogger = log.clone();
-        tokio::spawn(
-            Packetizer {
-                addr,
-                state: PacketizerState::Connected(ActivePacketizer::new(stream)),
-                xid: 0,
-                default_watcher,
-                rx: rx,
-                logger: log,
-                exiting: false,
-            }.map_err(move |e| {
-                error!(exitlogger, "packetizer exiting: {:?}", e);
-                drop(e);
-            }),
-        );
-
-        Enqueuer(tx)
+        (Enqueuer(tx, stats, shut_down.shared(), max_request_size), packetizer)
     }
 }
 
-enum PacketizerState<S> {
+/// Warns the default watcher once the server has not been heard from for a fraction of the
+/// session timeout, since the session expires if the silence lasts for all of it.
+struct ExpiryWarning {
+    fraction: f64,
+    runtime: Runtime,
+    /// Fires when the warning is due, for silence since the given last contact.
+    timer: Option<(Instant, Sleep)>,
+    /// The last contact that the warning was given for, and whether the connection has been lost
+    /// since.
+    warned: Option<(Instant, bool)>,
+}
+
+impl ExpiryWarning {
+    fn new(fraction: f64, runtime: Runtime) -> Self {
+        ExpiryWarning {
+            fraction,
+            runtime,
+            timer: None,
+            warned: None,
+        }
+    }
+
+    /// Warn `default_watcher` if the server has been silent for too long since `last_contact`,
+    /// and tell it when the server is heard from again.
+    fn poll(
+        &mut self,
+        cx: &mut Context<'_>,
+        last_contact: Instant,
+        session_timeout: i32,
+        logger: &Logger,
+        default_watcher: &DefaultWatcher,
+    ) {
+        if session_timeout <= 0 {
+            // no session yet
+            return;
+        }
+        match self.warned {
+            Some((warned, _)) if warned == last_contact => return,
+            Some((_, lost)) => {
+                self.warned = None;
+                // a resumed session already says that it is connected again
+                if !lost {
+                    info!(logger, "heard from the server again");
+                    send_state(default_watcher, KeeperState::SyncConnected);
+                }
+            }
+            None => {}
+        }
+        if self.timer.as_ref().is_none_or(|&(armed, _)| armed != last_contact) {
+            let timeout = Duration::from_millis(session_timeout as u64).mul_f64(self.fraction);
+            let due = timeout.saturating_sub(last_contact.elapsed());
+            self.timer = Some((last_contact, self.runtime.sleep(due)));
+        }
+        if let Some((_, ref mut timer)) = self.timer {
+            if Pin::new(timer).poll(cx).is_ready() {
+                self.timer = None;
+                self.warned = Some((last_contact, false));
+                let silent = last_contact.elapsed().as_millis() as u64;
+                warn!(logger, "session may be about to expire"; "silent_ms" => silent);
+                send_state(default_watcher, KeeperState::Expiring);
+            }
+        }
+    }
+
+    fn disconnected(&mut self) {
+        if let Some((_, ref mut lost)) = self.warned {
+            *lost = true;
+        }
+    }
+}
+
+fn send_state(default_watcher: &DefaultWatcher, keeper_state: KeeperState) {
+    let event = WatchedEvent {
+        event_type: WatchedEventType::None,
+        keeper_state,
+        path: String::new(),
+    };
+    let _ = default_watcher.unbounded_send((event, Instant::now()));
+}
+
+#[allow(clippy::large_enum_variant)]
+enum PacketizerState<S>
+where
+    S: ZooKeeperTransport,
+{
     Connected(ActivePacketizer<S>),
-    Reconnecting(Box<Future<Item = ActivePacketizer<S>, Error = failure::Error> + Send + 'static>),
+    Reconnecting(Reconnect<S>),
+}
+
+/// How long to wait before the second round of attempts to reconnect, once every server has
+/// failed to accept a connection; every further round waits twice as long as the one before, up
+/// to [`MAX_RECONNECT_BACKOFF`].
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The longest wait between two rounds of attempts to reconnect.
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A connection to the server that is being re-established for an existing session.
+///
+/// Every server is tried in turn, and once they have all failed, they are tried again after a
+/// backoff, for as long as the session may not have expired yet, that is until the session
+/// timeout has passed since the server was last heard from. Once the transport has connected,
+/// polling it returns a fresh `ActivePacketizer` that carries over the session's credentials,
+/// watches, and the last zxid seen, and that has queued the handshake to resume the session,
+/// along with the reads to send again on it.
+///
+/// One timer serves every attempt, both to give up on it and to back off before it, so an
+/// attempt allocates nothing beyond what the transport's `ConnectFut` does; the built-in
+/// transports box theirs, since the futures that their runtimes connect with cannot be named.
+struct Reconnect<S>
+where
+    S: ZooKeeperTransport,
+{
+    /// The attempt to connect that is under way.
+    connect: Option<S::ConnectFut>,
+    /// When the attempt that is under way is given up on, or, while backing off, when the next
+    /// one is made.
+    timer: Sleep,
+    /// Whether the next attempt is being held back, after every server has failed.
+    backing_off: bool,
+    /// How many attempts have failed in a row.
+    failures: u32,
+    last_zxid_seen: i64,
+    session_id: i64,
+    password: Vec<u8>,
+    session_timeout: i32,
+    last_contact: Instant,
+    watchers: HashMap<Arc<str>, Vec<Watcher>>,
+    persistent: HashMap<String, Vec<PersistentWatcher>>,
+    reissue: Vec<Reissue>,
+    options: Options,
+    stats: Arc<SharedStats>,
 }
 
-impl<S> PacketizerState<S>
+impl<S> Reconnect<S>
 where
-    S: AsyncRead + AsyncWrite,
+    S: ZooKeeperTransport,
 {
+    fn new(ap: &mut ActivePacketizer<S>) -> Self {
+        Reconnect {
+            connect: None,
+            timer: ap.options.runtime.sleep(Duration::ZERO),
+            backing_off: false,
+            failures: 0,
+            last_zxid_seen: ap.last_zxid_seen,
+            session_id: ap.session_id,
+            password: mem::take(&mut ap.password),
+            session_timeout: ap.session_timeout,
+            last_contact: ap.last_contact,
+            watchers: mem::take(&mut ap.watchers),
+            reissue: ap.disconnect(),
+            persistent: mem::take(&mut ap.persistent),
+            options: ap.options.clone(),
+            stats: ap.stats.clone(),
+        }
+    }
+
+    /// Fail the reads that were to be sent again, now that the connection cannot be
+    /// re-established.
+    fn fail_pending(&mut self) -> ZkError {
+        for (_, tx, _, span) in self.reissue.drain(..) {
+            span.completed(Some(ZkError::ConnectionLoss));
+            let _ = tx.send(Err((ZkError::ConnectionLoss, None)));
+        }
+        ZkError::ConnectionLoss
+    }
+
+    /// Return how much longer the session may last without the server hearing from the client.
+    fn remaining(&self) -> Duration {
+        let timeout = Duration::from_millis(self.session_timeout.max(0) as u64);
+        timeout.saturating_sub(self.last_contact.elapsed())
+    }
+
+    /// Connect to the next of `hosts` until one accepts the connection, or fail with the error of
+    /// the last attempt once the session may have expired. The reads to send again are left for
+    /// the caller to take.
     fn poll(
         &mut self,
-        exiting: bool,
-        logger: &mut slog::Logger,
-        default_watcher: &mut mpsc::UnboundedSender<WatchedEvent>,
-    ) -> Result<Async<()>, failure::Error> {
-        let ap = match *self {
-            PacketizerState::Connected(ref mut ap) => {
-                return ap.poll(exiting, logger, default_watcher)
-            }
-            PacketizerState::Reconnecting(ref mut c) => try_ready!(c.poll()),
-        };
+        cx: &mut Context<'_>,
+        hosts: &mut HostProvider<S::Addr>,
+        logger: &Logger,
+    ) -> Poll<Result<ActivePacketizer<S>, Error>> {
+        loop {
+            if self.backing_off {
+                ready!(Pin::new(&mut self.timer).poll(cx));
+                self.backing_off = false;
+            }
+            if self.connect.is_none() {
+                // share the session timeout between the servers, as the Java client does, so
+                // that one that does not answer cannot use all of it up
+                let timeout = Duration::from_millis(self.session_timeout.max(0) as u64);
+                let timeout = (timeout / hosts.len() as u32).min(self.remaining());
+                self.connect = Some(S::connect(hosts.advance()));
+                self.timer.reset(timeout);
+            }
+            let connect = self.connect.as_mut().expect("an attempt was just made");
+            let e = match Pin::new(connect).poll(cx) {
+                Poll::Ready(Ok(stream)) => {
+                    self.connect = None;
+                    return Poll::Ready(Ok(self.resume(stream)));
+                }
+                Poll::Ready(Err(e)) => e.into(),
+                Poll::Pending => {
+                    ready!(Pin::new(&mut self.timer).poll(cx));
+                    Error::Timeout
+                }
+            };
+            self.connect = None;
+            self.failures += 1;
+            let remaining = self.remaining();
+            if remaining == Duration::ZERO {
+                return Poll::Ready(Err(e));
+            }
+            warn!(logger, "failed to reconnect: {}", e; "failures" => self.failures);
+            if self.failures.is_multiple_of(hosts.len() as u32) {
+                let round = self.failures / hosts.len() as u32;
+                let backoff = RECONNECT_BACKOFF
+                    .saturating_mul(1 << (round - 1).min(16))
+                    .min(MAX_RECONNECT_BACKOFF)
+                    .min(remaining);
+                self.timer.reset(backoff);
+                self.backing_off = true;
+            }
+        }
+    }
 
-        // we are now connected!
-        mem::replace(self, PacketizerState::Connected(ap));
-        self.poll(exiting, logger, default_watcher)
+    /// Start resuming the session on the newly connected `stream`.
+    fn resume(&mut self, stream: S) -> ActivePacketizer<S> {
+        self.options.metrics.on_reconnect();
+        lifecycle!(session_id = self.session_id, "reconnected");
+        let mut ap = ActivePacketizer::new(stream, self.options.clone(), self.stats.clone());
+        ap.last_zxid_seen = self.last_zxid_seen;
+        ap.session_id = self.session_id;
+        ap.session_timeout = self.session_timeout;
+        ap.last_contact = self.last_contact;
+        mem::swap(&mut ap.password, &mut self.password);
+        mem::swap(&mut ap.watchers, &mut self.watchers);
+        mem::swap(&mut ap.persistent, &mut self.persistent);
+        ap.resume();
+        ap
     }
 }
 
@@ -105,14 +382,24 @@ impl<S> Packetizer<S>
 where
     S: ZooKeeperTransport,
 {
-    fn poll_enqueue(&mut self) -> Result<Async<()>, ()> {
+    fn poll_enqueue(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), ()>> {
         while let PacketizerState::Connected(ref mut ap) = self.state {
-            let (mut item, tx) = match try_ready!(self.rx.poll()) {
-                Some((request, response)) => (request, response),
-                None => return Err(()),
+            if let Some(ref mut limiter) = self.limiter {
+                ready!(limiter.poll_ready(cx));
+            }
+            let (mut item, tx, span) = match ready!(self.rx.poll_next_unpin(cx)) {
+                Some((request, response, span)) => (request, response, span),
+                None => return Poll::Ready(Err(())),
             };
-            debug!(self.logger, "enqueueing request {:?}", item; "xid" => self.xid);
+            if let Some(ref mut limiter) = self.limiter {
+                limiter.take();
+            }
+            self.stats.queued.fetch_sub(1, Ordering::Relaxed);
+            span.sent(self.xid);
+            debug!(self.logger, "enqueueing request {:?}", Logged(&item, ap.options.log_payloads);
+                   "xid" => self.xid);
 
+            let mut watcher = None;
             match item {
                 Request::GetData {
                     ref path,
@@ -124,42 +411,122 @@ where
                     ref mut watch,
                     ..
                 }
+                | Request::GetChildren2 {
+                    ref path,
+                    ref mut watch,
+                    ..
+                }
                 | Request::Exists {
                     ref path,
                     ref mut watch,
                     ..
-                } => {
-                    if let Watch::Custom(_) = *watch {
-                        // set to Global so that watch will be sent as 1u8
-                        let w = mem::replace(watch, Watch::Global);
-                        if let Watch::Custom(w) = w {
-                            let wtype = match item {
-                                Request::GetData { .. } => WatchType::Data,
-                                Request::GetChildren { .. } => WatchType::Child,
-                                Request::Exists { .. } => WatchType::Exist,
-                                _ => unreachable!(),
-                            };
-                            trace!(
-                                self.logger,
-                                "adding pending watcher";
-                                "xid" => self.xid,
-                                "path" => path,
-                                "wtype" => ?wtype
-                            );
-                            ap.pending_watchers
-                                .insert(self.xid, (path.to_string(), w, wtype));
-                        } else {
-                            unreachable!();
+                } if !matches!(*watch, Watch::None) => {
+                    // set to Global so that watch will be sent as 1u8
+                    let w = mem::replace(watch, Watch::Global);
+                    let wtype = match item {
+                        Request::GetData { .. } => WatchType::Data,
+                        Request::GetChildren { .. } | Request::GetChildren2 { .. } => {
+                            WatchType::Child
                         }
+                        Request::Exists { .. } => WatchType::Exist,
+                        _ => unreachable!(),
+                    };
+                    trace!(
+                        self.logger,
+                        "adding pending watcher";
+                        "xid" => self.xid,
+                        "path" => path,
+                        "wtype" => ?wtype
+                    );
+                    let (w, namespace) = match w {
+                        // tracked so that it can be set again after a reconnect
+                        Watch::Global => (WatchSink::Session, Namespace::default()),
+                        Watch::Custom(w, namespace) => (WatchSink::Once(w), namespace),
+                        Watch::Handle(id, namespace) => (WatchSink::Handle(id), namespace),
+                        Watch::None => unreachable!(),
+                        Watch::Persistent(..) => unreachable!("only set by AddWatch"),
+                    };
+                    watcher = Some((ap.intern(path), w, wtype, namespace));
+                }
+                Request::AddWatch {
+                    ref path,
+                    recursive,
+                    ref mut watch,
+                } => {
+                    if let Watch::Persistent(tx, namespace) = mem::replace(watch, Watch::None) {
+                        trace!(
+                            self.logger,
+                            "adding persistent watcher";
+                            "xid" => self.xid,
+                            "path" => path,
+                            "recursive" => recursive
+                        );
+                        let w = PersistentWatcher {
+                            tx,
+                            recursive,
+                            namespace,
+                        };
+                        ap.persistent.entry(path.clone()).or_default().push(w);
                     }
                 }
                 _ => {}
             }
 
-            ap.enqueue(self.xid, item, tx);
+            ap.enqueue(self.xid, item, tx, watcher, span);
             self.xid += 1;
         }
-        Ok(Async::NotReady)
+        Poll::Pending
+    }
+
+    /// Drive the connection, and re-establish it if it is lost while the session can still be
+    /// resumed.
+    fn poll_connection(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        loop {
+            let (mut ap, reissue) = match self.state {
+                PacketizerState::Connected(ref mut ap) => {
+                    let polled =
+                        ap.poll(cx, self.closing, &mut self.logger, &mut self.default_watcher);
+                    let e = match polled {
+                        Poll::Ready(Err(e)) => e,
+                        r => return r,
+                    };
+                    if self.exiting || !ap.resumable() {
+                        return Poll::Ready(Err(e));
+                    }
+                    warn!(self.logger, "connection lost, reconnecting: {}", e);
+                    // a connection that was lost before it resumed the session was never
+                    // announced, so neither is losing it
+                    if self.stats.state() != ConnectionState::Reconnecting {
+                        lifecycle!("connection lost");
+                        self.stats.set_state(ConnectionState::Reconnecting);
+                        self.callbacks.disconnected();
+                        send_state(&self.default_watcher, KeeperState::Disconnected);
+                        if let Some(ref mut expiry) = self.expiry {
+                            expiry.disconnected();
+                        }
+                    }
+                    let reconnect = Reconnect::new(ap);
+                    self.state = PacketizerState::Reconnecting(reconnect);
+                    continue;
+                }
+                PacketizerState::Reconnecting(ref mut c) => {
+                    let ap = ready!(c.poll(cx, &mut self.hosts, &self.logger))?;
+                    (ap, mem::take(&mut c.reissue))
+                }
+            };
+
+            // we are now connected!
+            for (request, tx, watcher, span) in reissue {
+                let logged = Logged(&request, ap.options.log_payloads);
+                debug!(self.logger, "re-issuing request {:?}", logged; "xid" => self.xid);
+                span.sent(self.xid);
+                ap.enqueue(self.xid, request, tx, watcher, span);
+                self.xid += 1;
+            }
+            self.state = PacketizerState::Connected(ap);
+            // pick up the requests that were issued while reconnecting
+            cx.waker().wake_by_ref();
+        }
     }
 }
 
@@ -167,33 +534,40 @@ impl<S> Future for Packetizer<S>
 where
     S: ZooKeeperTransport,
 {
-    type Item = ();
-    type Error = failure::Error;
-
-    fn poll(&mut self) -> Result<Async<Self::Item>, Self::Error> {
-        trace!(self.logger, "packetizer polled");
-        if !self.exiting {
-            trace!(self.logger, "poll_enqueue");
-            match self.poll_enqueue() {
-                Ok(_) => {}
-                Err(()) => {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        trace!(this.logger, "packetizer polled");
+        this.stats.packetizer.register(cx.waker());
+        if this.drain.is_none() {
+            if let Some(timeout) = this.stats.shutdown.lock().unwrap().take() {
+                lifecycle!("shutting down");
+                this.drain = Some(this.runtime.sleep(timeout));
+            }
+        }
+        if !this.exiting {
+            trace!(this.logger, "poll_enqueue");
+            match this.poll_enqueue(cx) {
+                Poll::Ready(Ok(())) | Poll::Pending => {}
+                Poll::Ready(Err(())) => {
                     // no more requests will be enqueued
-                    self.exiting = true;
-
-                    if let PacketizerState::Connected(ref mut ap) = self.state {
-                        // send CloseSession
-                        // length is fixed
-                        ap.outbox
-                            .write_i32::<BigEndian>(8)
-                            .expect("Vec::write should never fail");
-                        // xid
-                        ap.outbox
-                            .write_i32::<BigEndian>(0)
-                            .expect("Vec::write should never fail");
-                        // opcode
-                        ap.outbox
-                            .write_i32::<BigEndian>(request::OpCode::CloseSession as i32)
-                            .expect("Vec::write should never fail");
+                    this.exiting = true;
+
+                    if let PacketizerState::Connected(ref mut ap) = this.state {
+                        if this.drain.is_none() && this.stats.detached.load(Ordering::Relaxed) {
+                            // hang up without a word, so the session lingers until it times out
+                            lifecycle!("detaching from session");
+                            ap.fail_pending();
+                            this.stats.set_state(ConnectionState::Closed);
+                            return Poll::Ready(Ok(()));
+                        }
+                        // a shutdown closes the session once the requests in flight are done
+                        if this.drain.is_none() {
+                            lifecycle!("closing session");
+                            ap.outbox.push(request::write_close_session);
+                            this.closing = true;
+                        }
                     } else {
                         unreachable!("poll_enqueue will never return Err() if not connected");
                     }
@@ -201,28 +575,163 @@ where
             }
         }
 
-        self.state.poll(self.exiting, &mut self.logger, &mut self.default_watcher)
+        if let Some(ref mut drain) = this.drain {
+            if Pin::new(drain).poll(cx).is_ready() {
+                warn!(this.logger, "shutdown timed out with requests in flight");
+                lifecycle!("shutdown timed out");
+                match this.state {
+                    PacketizerState::Connected(ref mut ap) => {
+                        ap.fail_pending();
+                        if !this.closing {
+                            ap.outbox.push(request::write_close_session);
+                        }
+                        // write the close out if it fits, but do not wait for the server
+                        let logger = &mut this.logger;
+                        let _ = ap.poll(cx, true, logger, &mut this.default_watcher);
+                    }
+                    PacketizerState::Reconnecting(ref mut c) => {
+                        c.fail_pending();
+                    }
+                }
+                this.stats.set_state(ConnectionState::Closed);
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        let r = this.poll_connection(cx);
+        if let (Poll::Pending, PacketizerState::Connected(ref mut ap)) = (&r, &mut this.state) {
+            if this.exiting && !this.closing && ap.drained() {
+                lifecycle!("closing session");
+                ap.outbox.push(request::write_close_session);
+                this.closing = true;
+                // the close has to be written out
+                cx.waker().wake_by_ref();
+            }
+        }
+        if let (Poll::Pending, Some(expiry), false) = (&r, &mut this.expiry, this.exiting) {
+            let (last_contact, session_timeout) = match this.state {
+                PacketizerState::Connected(ref ap) => (ap.last_contact, ap.session_timeout),
+                PacketizerState::Reconnecting(ref c) => (c.last_contact, c.session_timeout),
+            };
+            expiry.poll(cx, last_contact, session_timeout, &this.logger, &this.default_watcher);
+        }
+        if let Poll::Ready(Err(_)) = r {
+            // the connection is gone for good, so tell everyone who is still waiting why
+            let lost = match this.state {
+                PacketizerState::Connected(ref mut ap) => ap.fail_pending(),
+                PacketizerState::Reconnecting(ref mut c) => c.fail_pending(),
+            };
+            this.rx.close();
+            while let Ok((_, tx, span)) = this.rx.try_recv() {
+                this.stats.queued.fetch_sub(1, Ordering::Relaxed);
+                span.completed(Some(lost));
+                if lost == ZkError::SessionExpired {
+                    let _ = tx.send(Err((lost, None)));
+                }
+                // otherwise, dropping `tx` tells the caller that the request was never sent
+            }
+            this.callbacks.disconnected();
+            if lost != ZkError::SessionExpired {
+                this.stats.set_state(ConnectionState::Closed);
+            }
+        }
+        if let Poll::Ready(Ok(())) = r {
+            this.stats.set_state(ConnectionState::Closed);
+        }
+        r
     }
 }
 
+/// A request on its way to the packetizer, along with where to send its response.
+type Enqueued = (
+    Request,
+    oneshot::Sender<Reply>,
+    RequestSpan,
+);
+
 #[derive(Clone, Debug)]
 pub(crate) struct Enqueuer(
-    mpsc::UnboundedSender<(Request, oneshot::Sender<Result<Response, ZkError>>)>,
+    mpsc::UnboundedSender<Enqueued>,
+    Arc<SharedStats>,
+    Shared<oneshot::Receiver<()>>,
+    /// The size of the largest frame that requests are sent in.
+    usize,
 );
 
 impl Enqueuer {
     pub(crate) fn enqueue(
         &self,
         request: Request,
-    ) -> impl Future<Item = Result<Response, ZkError>, Error = failure::Error> {
+    ) -> impl Future<Output = Result<Reply, Error>> {
+        if let Err(e) = request.validate(self.3) {
+            return Either::Right(future::err(Error::InvalidRequest(e)));
+        }
+        if let Some(ref breaker) = self.1.breaker {
+            if !breaker.allows(self.1.disconnected_for()) {
+                return Either::Right(future::err(Error::BrokenCircuit));
+            }
+        }
         let (tx, rx) = oneshot::channel();
-        match self.0.unbounded_send((request, tx)) {
+        let span = RequestSpan::new(&request);
+        self.1.queued.fetch_add(1, Ordering::Relaxed);
+        match self.0.unbounded_send((request, tx, span)) {
             Ok(()) => {
-                Either::A(rx.map_err(|e| format_err!("Error processing request: {:?}", e)))
+                let stats = Arc::clone(&self.1);
+                Either::Left(rx.map(move |reply| {
+                    // the packetizer only drops requests it has not sent, see `Packetizer::poll`
+                    let reply = reply.map_err(|_| Error::NotSent);
+                    if let Some(ref breaker) = stats.breaker {
+                        breaker.record(&reply);
+                    }
+                    reply
+                }))
             }
-            Err(e) => {
-                Either::B(Err(format_err!("failed to enqueue new request: {:?}", e)).into_future())
+            Err(_) => {
+                self.1.queued.fetch_sub(1, Ordering::Relaxed);
+                Either::Right(future::err(Error::NotSent))
             }
         }
     }
+
+    /// Stop taking requests, and close the session once those in flight are done, or once
+    /// `timeout` has passed; the returned future resolves once the connection has shut down.
+    pub(crate) fn shutdown(&self, timeout: Duration) -> impl Future<Output = ()> {
+        *self.1.shutdown.lock().unwrap() = Some(timeout);
+        self.0.close_channel();
+        self.1.packetizer.wake();
+        self.2.clone().map(|_| ())
+    }
+
+    /// Leave the session to expire once every handle is gone, rather than closing it.
+    pub(crate) fn detach(&self) {
+        self.1.detached.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the connection has shut down for good, so that no further requests will be sent.
+    pub(crate) fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        self.1.state()
+    }
+
+    /// The size of the largest frame that requests may be sent in.
+    pub(crate) fn max_request_size(&self) -> usize {
+        self.3
+    }
+
+    /// Resolve with the state of the connection once it is connected or can no longer be.
+    pub(crate) fn settled(&self) -> impl Future<Output = ConnectionState> {
+        Arc::clone(&self.1).wait_for_state(|s| s.is_connected() || s.is_terminal())
+    }
+
+    /// Add a default watcher for a handle, and return its id along with the stream of its events.
+    pub(crate) fn add_watcher(&self, logger: Logger) -> (usize, WatchedEventStream) {
+        self.1.handle_watchers.lock().unwrap().add(logger)
+    }
+
+    pub(crate) fn stats(&self, server_addr: SocketAddr) -> crate::ConnectionStats {
+        self.1.snapshot(server_addr)
+    }
 }
@@ -0,0 +1,82 @@
+//! Formatting of requests and responses for logs, without the data that they carry.
+//!
+//! Node data may well be secret, so the `Debug` output of a request or response shows every
+//! payload as its length and a hash. The hash lets a payload be recognized across log lines
+//! without revealing it. Session passwords are never shown.
+//!
+//! `Logged` shows the payloads in full instead if the client was configured to do so.
+
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// Something that can be formatted with or without its payloads.
+pub(crate) trait FmtPayloads {
+    /// Format `self` like `Debug` does, with payloads in full only if `full` is set.
+    fn fmt_payloads(&self, f: &mut fmt::Formatter, full: bool) -> fmt::Result;
+}
+
+/// Formats a request or response for a log statement, with payloads in full if the flag is set.
+pub(crate) struct Logged<'a, T: 'a + ?Sized>(pub(crate) &'a T, pub(crate) bool);
+
+impl<'a, T: FmtPayloads + ?Sized> fmt::Debug for Logged<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_payloads(f, self.1)
+    }
+}
+
+impl<T: FmtPayloads> FmtPayloads for [T] {
+    fn fmt_payloads(&self, f: &mut fmt::Formatter, full: bool) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|item| Logged(item, full)))
+            .finish()
+    }
+}
+
+/// A payload, which is shown as its length and hash unless `full` is set.
+pub(super) struct Payload<'a> {
+    pub(super) bytes: &'a [u8],
+    pub(super) full: bool,
+}
+
+impl<'a> fmt::Debug for Payload<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.full {
+            return fmt::Debug::fmt(self.bytes, f);
+        }
+        let mut hasher = DefaultHasher::new();
+        self.bytes.hash(&mut hasher);
+        write!(f, "<{} bytes, hash {:016x}>", self.bytes.len(), hasher.finish())
+    }
+}
+
+/// A value that is never shown, such as a password.
+pub(super) struct Secret;
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload() {
+        let bytes = b"hunter2";
+        let redacted = format!("{:?}", Payload { bytes, full: false });
+        assert!(redacted.starts_with("<7 bytes, hash "), "{}", redacted);
+        assert_eq!(redacted.len(), "<7 bytes, hash >".len() + 16, "{}", redacted);
+        // the same payload always gets the same hash
+        assert_eq!(redacted, format!("{:?}", Payload { bytes, full: false }));
+        assert_ne!(
+            redacted,
+            format!("{:?}", Payload { bytes: b"hunter3", full: false })
+        );
+
+        let full = format!("{:?}", Payload { bytes, full: true });
+        assert_eq!(full, format!("{:?}", &bytes[..]));
+    }
+}
@@ -1,11 +1,17 @@
+use super::redact::{FmtPayloads, Logged, Payload, Secret};
 use super::Watch;
 use super::ZkError;
 use byteorder::{BigEndian, WriteBytesExt};
 use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fmt;
 use std::io::{self, Write};
-use {Acl, CreateMode};
+use crate::codec::jute::WriteTo;
+use crate::codec::records::{data, proto as records};
+use crate::metrics::Operation;
+use crate::error::InvalidRequest;
+use crate::{Acl, CreateMode, ZkPath};
 
-#[derive(Debug)]
 pub(crate) enum Request {
     Connect {
         protocol_version: i32,
@@ -33,11 +39,18 @@ pub(crate) enum Request {
         data: Cow<'static, [u8]>,
         acl: Cow<'static, [Acl]>,
         mode: CreateMode,
+        /// Whether to send the request as a `Create2`, which the server answers with the stat of
+        /// the new node along with its path.
+        with_stat: bool,
     },
     GetChildren {
         path: String,
         watch: Watch,
     },
+    GetChildren2 {
+        path: String,
+        watch: Watch,
+    },
     GetData {
         path: String,
         watch: Watch,
@@ -55,6 +68,174 @@ pub(crate) enum Request {
         version: i32,
     },
     Multi(Vec<Request>),
+    Sync {
+        path: String,
+    },
+    /// Set a persistent watch, which is only set if `watch` is a `Watch::Persistent`.
+    AddWatch {
+        path: String,
+        recursive: bool,
+        watch: Watch,
+    },
+    /// Re-register the watches of a resumed session on a new connection.
+    ///
+    /// This is sent as a `SetWatches2` if there are persistent watches, which only servers that
+    /// support them know.
+    SetWatches {
+        relative_zxid: i64,
+        data: Vec<String>,
+        exist: Vec<String>,
+        child: Vec<String>,
+        persistent: Vec<String>,
+        recursive: Vec<String>,
+    },
+    /// A request whose opcode the crate may not know, with a body that the caller encoded.
+    Raw {
+        opcode: i32,
+        body: Cow<'static, [u8]>,
+    },
+}
+
+impl FmtPayloads for Request {
+    fn fmt_payloads(&self, f: &mut fmt::Formatter, full: bool) -> fmt::Result {
+        match *self {
+            Request::Connect {
+                protocol_version,
+                last_zxid_seen,
+                timeout,
+                session_id,
+                read_only,
+                ..
+            } => f
+                .debug_struct("Connect")
+                .field("protocol_version", &protocol_version)
+                .field("last_zxid_seen", &last_zxid_seen)
+                .field("timeout", &timeout)
+                .field("session_id", &session_id)
+                .field("passwd", &Secret)
+                .field("read_only", &read_only)
+                .finish(),
+            Request::Exists {
+                ref path,
+                ref watch,
+            } => f
+                .debug_struct("Exists")
+                .field("path", path)
+                .field("watch", watch)
+                .finish(),
+            Request::Delete { ref path, version } => f
+                .debug_struct("Delete")
+                .field("path", path)
+                .field("version", &version)
+                .finish(),
+            Request::SetData {
+                ref path,
+                ref data,
+                version,
+            } => f
+                .debug_struct("SetData")
+                .field("path", path)
+                .field("data", &Payload { bytes: data, full })
+                .field("version", &version)
+                .finish(),
+            Request::Create {
+                ref path,
+                ref data,
+                ref acl,
+                mode,
+                with_stat,
+            } => f
+                .debug_struct("Create")
+                .field("path", path)
+                .field("data", &Payload { bytes: data, full })
+                .field("acl", acl)
+                .field("mode", &mode)
+                .field("with_stat", &with_stat)
+                .finish(),
+            Request::GetChildren {
+                ref path,
+                ref watch,
+            } => f
+                .debug_struct("GetChildren")
+                .field("path", path)
+                .field("watch", watch)
+                .finish(),
+            Request::GetChildren2 {
+                ref path,
+                ref watch,
+            } => f
+                .debug_struct("GetChildren2")
+                .field("path", path)
+                .field("watch", watch)
+                .finish(),
+            Request::GetData {
+                ref path,
+                ref watch,
+            } => f
+                .debug_struct("GetData")
+                .field("path", path)
+                .field("watch", watch)
+                .finish(),
+            Request::GetAcl { ref path } => f.debug_struct("GetAcl").field("path", path).finish(),
+            Request::SetAcl {
+                ref path,
+                ref acl,
+                version,
+            } => f
+                .debug_struct("SetAcl")
+                .field("path", path)
+                .field("acl", acl)
+                .field("version", &version)
+                .finish(),
+            Request::Check { ref path, version } => f
+                .debug_struct("Check")
+                .field("path", path)
+                .field("version", &version)
+                .finish(),
+            Request::Sync { ref path } => f.debug_struct("Sync").field("path", path).finish(),
+            Request::Multi(ref requests) => f
+                .debug_tuple("Multi")
+                .field(&Logged(&requests[..], full))
+                .finish(),
+            Request::AddWatch {
+                ref path,
+                recursive,
+                ref watch,
+            } => f
+                .debug_struct("AddWatch")
+                .field("path", path)
+                .field("recursive", &recursive)
+                .field("watch", watch)
+                .finish(),
+            Request::SetWatches {
+                relative_zxid,
+                ref data,
+                ref exist,
+                ref child,
+                ref persistent,
+                ref recursive,
+            } => f
+                .debug_struct("SetWatches")
+                .field("relative_zxid", &relative_zxid)
+                .field("data", data)
+                .field("exist", exist)
+                .field("child", child)
+                .field("persistent", persistent)
+                .field("recursive", recursive)
+                .finish(),
+            Request::Raw { opcode, ref body } => f
+                .debug_struct("Raw")
+                .field("opcode", &opcode)
+                .field("body", &Payload { bytes: body, full })
+                .finish(),
+        }
+    }
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_payloads(f, false)
+    }
 }
 
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq, Ord, PartialOrd)]
@@ -75,17 +256,25 @@ pub(super) enum OpCode {
     GetChildren2 = 12,
     Check = 13,
     Multi = 14,
+    Create2 = 15,
     Auth = 100,
     SetWatches = 101,
     Sasl = 102,
+    SetWatches2 = 105,
+    AddWatch = 106,
     CreateSession = -10,
     CloseSession = -11,
     Error = -1,
+    /// Stands in for the opcode of a [`Request::Raw`], which the request itself carries.
+    Raw = i32::MIN,
 }
 
-impl From<i32> for OpCode {
-    fn from(code: i32) -> Self {
-        match code {
+impl TryFrom<i32> for OpCode {
+    type Error = i32;
+
+    /// Return the opcode with the given code, or the code if it is not one that is known.
+    fn try_from(code: i32) -> Result<Self, i32> {
+        Ok(match code {
             0 => OpCode::Notification,
             1 => OpCode::Create,
             2 => OpCode::Delete,
@@ -100,14 +289,40 @@ impl From<i32> for OpCode {
             12 => OpCode::GetChildren2,
             13 => OpCode::Check,
             14 => OpCode::Multi,
+            15 => OpCode::Create2,
             100 => OpCode::Auth,
             101 => OpCode::SetWatches,
             102 => OpCode::Sasl,
+            105 => OpCode::SetWatches2,
+            106 => OpCode::AddWatch,
             -10 => OpCode::CreateSession,
             -11 => OpCode::CloseSession,
             -1 => OpCode::Error,
-            _ => unimplemented!(),
-        }
+            _ => return Err(code),
+        })
+    }
+}
+
+impl OpCode {
+    /// The operation a request with this opcode performs, if it is one that users issue.
+    pub(super) fn operation(self) -> Option<Operation> {
+        Some(match self {
+            OpCode::CreateSession => Operation::Connect,
+            OpCode::Create | OpCode::Create2 => Operation::Create,
+            OpCode::Delete => Operation::Delete,
+            OpCode::Exists => Operation::Exists,
+            OpCode::GetData => Operation::GetData,
+            OpCode::SetData => Operation::SetData,
+            OpCode::GetACL => Operation::GetAcl,
+            OpCode::SetACL => Operation::SetAcl,
+            OpCode::GetChildren | OpCode::GetChildren2 => Operation::GetChildren,
+            OpCode::Check => Operation::Check,
+            OpCode::Multi => Operation::Multi,
+            OpCode::Synchronize => Operation::Sync,
+            OpCode::AddWatch => Operation::AddWatch,
+            OpCode::Raw => Operation::Raw,
+            _ => return None,
+        })
     }
 }
 
@@ -117,74 +332,98 @@ pub(super) enum MultiHeader {
     Done,
 }
 
-pub trait WriteTo {
-    fn write_to<W: Write>(&self, writer: W) -> io::Result<()>;
-}
-
-impl WriteTo for Acl {
-    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        writer.write_u32::<BigEndian>(self.perms.code())?;
-        self.scheme.write_to(&mut writer)?;
-        self.id.write_to(writer)
-    }
-}
-
 impl WriteTo for MultiHeader {
-    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        match *self {
-            MultiHeader::NextOk(opcode) => {
-                writer.write_i32::<BigEndian>(opcode as i32)?;
-                writer.write_u8(false as u8)?;
-                writer.write_i32::<BigEndian>(-1)
-            }
+    fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        let (opcode, done) = match *self {
+            MultiHeader::NextOk(opcode) => (opcode as i32, false),
             MultiHeader::NextErr(_) => {
                 panic!("client should not serialize MultiHeader::NextErr");
             }
-            MultiHeader::Done => {
-                writer.write_i32::<BigEndian>(-1)?;
-                writer.write_u8(true as u8)?;
-                writer.write_i32::<BigEndian>(-1)
-            }
+            MultiHeader::Done => (-1, true),
+        };
+        records::MultiHeader {
+            r#type: opcode,
+            done,
+            err: -1,
         }
+        .write_to(writer)
     }
 }
 
-impl WriteTo for u8 {
-    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        try!(writer.write_u8(*self));
-        Ok(())
-    }
+/// Write the frame of a ping, which has a header but no body.
+pub(super) fn write_ping(frame: &mut Vec<u8>) {
+    // length is known for pings
+    frame
+        .write_i32::<BigEndian>(8)
+        .expect("Vec::write should never fail");
+    // xid
+    frame
+        .write_i32::<BigEndian>(-2)
+        .expect("Vec::write should never fail");
+    // opcode
+    frame
+        .write_i32::<BigEndian>(OpCode::Ping as i32)
+        .expect("Vec::write should never fail");
 }
 
-impl WriteTo for str {
-    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        try!(writer.write_i32::<BigEndian>(self.len() as i32));
-        writer.write_all(self.as_ref())
-    }
+/// Write the frame that ends the session, which like a ping has a header but no body.
+pub(super) fn write_close_session(frame: &mut Vec<u8>) {
+    // length is fixed
+    frame
+        .write_i32::<BigEndian>(8)
+        .expect("Vec::write should never fail");
+    // xid
+    frame
+        .write_i32::<BigEndian>(0)
+        .expect("Vec::write should never fail");
+    // opcode
+    frame
+        .write_i32::<BigEndian>(OpCode::CloseSession as i32)
+        .expect("Vec::write should never fail");
 }
 
-impl WriteTo for [u8] {
-    fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
-        try!(writer.write_i32::<BigEndian>(self.len() as i32));
-        writer.write_all(self.as_ref())
-    }
-}
+impl Request {
+    /// Write the whole frame for this request with the given `xid`, length prefix and all.
+    pub(super) fn frame_into(&self, xid: i32, frame: &mut Vec<u8>) {
+        let connect = matches!(*self, Request::Connect { .. });
+        let start = frame.len();
+        // size the frame up front so that serializing never reallocates it
+        let header = if connect { 4 } else { 4 + 4 + 4 };
+        frame.reserve(header + self.serialized_len());
+
+        // dummy length
+        frame.extend_from_slice(&[0; 4]);
+
+        if !connect {
+            // xid
+            frame
+                .write_i32::<BigEndian>(xid)
+                .expect("Vec::write should never fail");
+            // opcode
+            let opcode = match *self {
+                Request::Raw { opcode, .. } => opcode,
+                _ => self.opcode() as i32,
+            };
+            frame
+                .write_i32::<BigEndian>(opcode)
+                .expect("Vec::write should never fail");
+        }
 
-fn write_list<W, T>(mut writer: W, ts: &[T]) -> io::Result<()>
-where
-    T: WriteTo,
-    W: Write,
-{
-    writer.write_i32::<BigEndian>(ts.len() as i32)?;
-    for elem in ts {
-        elem.write_to(&mut writer)?;
+        // type and payload
+        self.serialize_into(frame)
+            .expect("Vec::write should never fail");
+        // set true length
+        let written = frame.len() - start - 4;
+        let mut length = &mut frame[start..start + 4];
+        length
+            .write_i32::<BigEndian>(written as i32)
+            .expect("Vec::write should never fail");
     }
-    Ok(())
-}
 
-impl Request {
     pub(super) fn serialize_into(&self, buffer: &mut Vec<u8>) -> Result<(), io::Error> {
         match *self {
+            // every request goes out as the record generated for it from the jute definitions,
+            // which are what keep its fields in the order the server reads them in
             Request::Connect {
                 protocol_version,
                 last_zxid_seen,
@@ -192,70 +431,94 @@ impl Request {
                 session_id,
                 ref passwd,
                 read_only,
-            } => {
-                buffer.write_i32::<BigEndian>(protocol_version)?;
-                buffer.write_i64::<BigEndian>(last_zxid_seen)?;
-                buffer.write_i32::<BigEndian>(timeout)?;
-                buffer.write_i64::<BigEndian>(session_id)?;
-                buffer.write_i32::<BigEndian>(passwd.len() as i32)?;
-                buffer.write_all(passwd)?;
-                buffer.write_u8(read_only as u8)?;
+            } => records::ConnectRequest {
+                protocol_version,
+                last_zxid_seen,
+                time_out: timeout,
+                session_id,
+                passwd: passwd.clone(),
+                read_only,
             }
+            .write_to(&mut *buffer)?,
             Request::GetData {
                 ref path,
                 ref watch,
+            } => records::GetDataRequest {
+                path: path.clone(),
+                watch: watch.to_u8() != 0,
             }
-            | Request::GetChildren {
+            .write_to(&mut *buffer)?,
+            Request::GetChildren {
                 ref path,
                 ref watch,
+            } => records::GetChildrenRequest {
+                path: path.clone(),
+                watch: watch.to_u8() != 0,
             }
-            | Request::Exists {
+            .write_to(&mut *buffer)?,
+            Request::GetChildren2 {
                 ref path,
                 ref watch,
-            } => {
-                path.write_to(&mut *buffer)?;
-                buffer.write_u8(watch.to_u8())?;
+            } => records::GetChildren2Request {
+                path: path.clone(),
+                watch: watch.to_u8() != 0,
+            }
+            .write_to(&mut *buffer)?,
+            Request::Exists {
+                ref path,
+                ref watch,
+            } => records::ExistsRequest {
+                path: path.clone(),
+                watch: watch.to_u8() != 0,
             }
-            Request::Delete { ref path, version } => {
-                path.write_to(&mut *buffer)?;
-                buffer.write_i32::<BigEndian>(version)?;
+            .write_to(&mut *buffer)?,
+            Request::Delete { ref path, version } => records::DeleteRequest {
+                path: path.clone(),
+                version,
             }
+            .write_to(&mut *buffer)?,
             Request::SetData {
                 ref path,
                 ref data,
                 version,
-            } => {
-                path.write_to(&mut *buffer)?;
-                data.write_to(&mut *buffer)?;
-                buffer.write_i32::<BigEndian>(version)?;
+            } => records::SetDataRequest {
+                path: path.clone(),
+                data: data.to_vec(),
+                version,
             }
+            .write_to(&mut *buffer)?,
             Request::Create {
                 ref path,
                 ref data,
                 mode,
                 ref acl,
-            } => {
-                path.write_to(&mut *buffer)?;
-                data.write_to(&mut *buffer)?;
-                write_list(&mut *buffer, acl)?;
-                buffer.write_i32::<BigEndian>(mode as i32)?;
-            }
-            Request::GetAcl { ref path } => {
-                path.write_to(&mut *buffer)?;
+                ..
+            } => records::CreateRequest {
+                path: path.clone(),
+                data: data.to_vec(),
+                acl: acl.iter().map(data::Acl::from).collect(),
+                flags: mode as i32,
             }
+            .write_to(&mut *buffer)?,
+            Request::GetAcl { ref path } => records::GetAclRequest { path: path.clone() }
+                .write_to(&mut *buffer)?,
             Request::SetAcl {
                 ref path,
                 ref acl,
                 version,
-            } => {
-                path.write_to(&mut *buffer)?;
-                write_list(&mut *buffer, acl)?;
-                buffer.write_i32::<BigEndian>(version)?;
+            } => records::SetAclRequest {
+                path: path.clone(),
+                acl: acl.iter().map(data::Acl::from).collect(),
+                version,
             }
-            Request::Check { ref path, version } => {
-                path.write_to(&mut *buffer)?;
-                buffer.write_i32::<BigEndian>(version)?;
+            .write_to(&mut *buffer)?,
+            Request::Check { ref path, version } => records::CheckVersionRequest {
+                path: path.clone(),
+                version,
             }
+            .write_to(&mut *buffer)?,
+            Request::Sync { ref path } => records::SyncRequest { path: path.clone() }
+                .write_to(&mut *buffer)?,
             Request::Multi(ref requests) => {
                 for r in requests {
                     MultiHeader::NextOk(r.opcode()).write_to(&mut *buffer)?;
@@ -263,23 +526,467 @@ impl Request {
                 }
                 MultiHeader::Done.write_to(&mut *buffer)?;
             }
+            Request::AddWatch {
+                ref path,
+                recursive,
+                ..
+            } => records::AddWatchRequest {
+                path: path.clone(),
+                // the modes of AddWatchMode
+                mode: if recursive { 1 } else { 0 },
+            }
+            .write_to(&mut *buffer)?,
+            Request::SetWatches {
+                relative_zxid,
+                ref data,
+                ref exist,
+                ref child,
+                ref persistent,
+                ref recursive,
+            } => {
+                if self.opcode() == OpCode::SetWatches2 {
+                    records::SetWatches2 {
+                        relative_zxid,
+                        data_watches: data.clone(),
+                        exist_watches: exist.clone(),
+                        child_watches: child.clone(),
+                        persistent_watches: persistent.clone(),
+                        persistent_recursive_watches: recursive.clone(),
+                    }
+                    .write_to(&mut *buffer)?
+                } else {
+                    records::SetWatches {
+                        relative_zxid,
+                        data_watches: data.clone(),
+                        exist_watches: exist.clone(),
+                        child_watches: child.clone(),
+                    }
+                    .write_to(&mut *buffer)?
+                }
+            }
+            Request::Raw { ref body, .. } => buffer.write_all(body)?,
+        }
+        Ok(())
+    }
+
+    /// The number of bytes `serialize_into` writes for this request.
+    ///
+    /// This is exact, so that a buffer can be sized for a request before it is serialized.
+    pub(super) fn serialized_len(&self) -> usize {
+        fn string(s: &[u8]) -> usize {
+            4 + s.len()
+        }
+        fn acls(acl: &[Acl]) -> usize {
+            acl.iter().fold(4, |len, acl| {
+                len + 4 + string(acl.scheme.as_bytes()) + string(acl.id.as_bytes())
+            })
+        }
+
+        match *self {
+            Request::Connect { ref passwd, .. } => 4 + 8 + 4 + 8 + string(passwd) + 1,
+            Request::GetData { ref path, .. }
+            | Request::GetChildren { ref path, .. }
+            | Request::GetChildren2 { ref path, .. }
+            | Request::Exists { ref path, .. } => string(path.as_bytes()) + 1,
+            Request::Sync { ref path } => string(path.as_bytes()),
+            Request::Delete { ref path, .. } | Request::Check { ref path, .. } => {
+                string(path.as_bytes()) + 4
+            }
+            Request::SetData {
+                ref path, ref data, ..
+            } => string(path.as_bytes()) + string(data) + 4,
+            Request::Create {
+                ref path,
+                ref data,
+                ref acl,
+                ..
+            } => string(path.as_bytes()) + string(data) + acls(acl) + 4,
+            Request::GetAcl { ref path } => string(path.as_bytes()),
+            Request::SetAcl {
+                ref path, ref acl, ..
+            } => string(path.as_bytes()) + acls(acl) + 4,
+            Request::Multi(ref requests) => {
+                // each request and the terminator is preceded by a 9-byte MultiHeader
+                requests
+                    .iter()
+                    .fold(9, |len, r| len + 9 + r.serialized_len())
+            }
+            Request::AddWatch { ref path, .. } => string(path.as_bytes()) + 4,
+            Request::SetWatches {
+                ref data,
+                ref exist,
+                ref child,
+                ref persistent,
+                ref recursive,
+                ..
+            } => {
+                let paths = |paths: &[String]| {
+                    paths
+                        .iter()
+                        .fold(4, |len, path| len + string(path.as_bytes()))
+                };
+                let len = 8 + paths(data) + paths(exist) + paths(child);
+                if self.opcode() == OpCode::SetWatches2 {
+                    len + paths(persistent) + paths(recursive)
+                } else {
+                    len
+                }
+            }
+            Request::Raw { ref body, .. } => body.len(),
+        }
+    }
+
+    /// Check that the server would accept this request, whose frame is at most `max_size` bytes.
+    pub(super) fn validate(&self, max_size: usize) -> Result<(), InvalidRequest> {
+        self.validate_paths()?;
+        // the frame's xid and opcode count towards the server's limit, its length prefix does not
+        let size = 4 + 4 + self.serialized_len();
+        if size > max_size {
+            return Err(InvalidRequest::TooLarge {
+                path: self.path().map(str::to_string),
+                size,
+                max: max_size,
+            });
         }
         Ok(())
     }
 
+    fn validate_paths(&self) -> Result<(), InvalidRequest> {
+        let invalid = |path: &str, error| InvalidRequest::Path {
+            path: path.to_string(),
+            error,
+        };
+        match *self {
+            Request::Multi(ref requests) => requests.iter().try_for_each(Request::validate_paths),
+            // the server appends the sequence number before it checks the path, so that the
+            // children of `/queue` can be created as `/queue/`
+            Request::Create {
+                ref path,
+                mode: CreateMode::PersistentSequential | CreateMode::EphemeralSequential,
+                ..
+            } => ZkPath::validate(&format!("{}0", path)).map_err(|e| invalid(path, e)),
+            _ => match self.path() {
+                Some(path) => ZkPath::validate(path).map_err(|e| invalid(path, e)),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// The path of the node this request operates on, if it operates on a single node.
+    pub(crate) fn path(&self) -> Option<&str> {
+        match *self {
+            Request::Exists { ref path, .. }
+            | Request::Delete { ref path, .. }
+            | Request::SetData { ref path, .. }
+            | Request::Create { ref path, .. }
+            | Request::GetChildren { ref path, .. }
+            | Request::GetChildren2 { ref path, .. }
+            | Request::GetData { ref path, .. }
+            | Request::GetAcl { ref path }
+            | Request::SetAcl { ref path, .. }
+            | Request::Check { ref path, .. }
+            | Request::Sync { ref path }
+            | Request::AddWatch { ref path, .. } => Some(path),
+            Request::Connect { .. }
+            | Request::Multi(..)
+            | Request::SetWatches { .. }
+            | Request::Raw { .. } => None,
+        }
+    }
+
+    /// The path of the node this request operates on, if it operates on a single node.
+    pub(crate) fn path_mut(&mut self) -> Option<&mut String> {
+        match *self {
+            Request::Exists { ref mut path, .. }
+            | Request::Delete { ref mut path, .. }
+            | Request::SetData { ref mut path, .. }
+            | Request::Create { ref mut path, .. }
+            | Request::GetChildren { ref mut path, .. }
+            | Request::GetChildren2 { ref mut path, .. }
+            | Request::GetData { ref mut path, .. }
+            | Request::GetAcl { ref mut path }
+            | Request::SetAcl { ref mut path, .. }
+            | Request::Check { ref mut path, .. }
+            | Request::Sync { ref mut path }
+            | Request::AddWatch { ref mut path, .. } => Some(path),
+            Request::Connect { .. }
+            | Request::Multi(..)
+            | Request::SetWatches { .. }
+            | Request::Raw { .. } => None,
+        }
+    }
+
+    /// The operation this request performs, if it is one that users issue.
+    pub(crate) fn operation(&self) -> Option<Operation> {
+        self.opcode().operation()
+    }
+
+    /// Consume the request, returning the path of the node it operates on, if any.
+    pub(super) fn into_path(self) -> Option<String> {
+        match self {
+            Request::Exists { path, .. }
+            | Request::Delete { path, .. }
+            | Request::SetData { path, .. }
+            | Request::Create { path, .. }
+            | Request::GetChildren { path, .. }
+            | Request::GetChildren2 { path, .. }
+            | Request::GetData { path, .. }
+            | Request::GetAcl { path }
+            | Request::SetAcl { path, .. }
+            | Request::Check { path, .. }
+            | Request::Sync { path }
+            | Request::AddWatch { path, .. } => Some(path),
+            Request::Connect { .. }
+            | Request::Multi(..)
+            | Request::SetWatches { .. }
+            | Request::Raw { .. } => None,
+        }
+    }
+
     pub(super) fn opcode(&self) -> OpCode {
         match *self {
             Request::Connect { .. } => OpCode::CreateSession,
             Request::Exists { .. } => OpCode::Exists,
             Request::Delete { .. } => OpCode::Delete,
+            Request::Create {
+                with_stat: true, ..
+            } => OpCode::Create2,
             Request::Create { .. } => OpCode::Create,
             Request::GetChildren { .. } => OpCode::GetChildren,
+            Request::GetChildren2 { .. } => OpCode::GetChildren2,
             Request::SetData { .. } => OpCode::SetData,
             Request::GetData { .. } => OpCode::GetData,
             Request::GetAcl { .. } => OpCode::GetACL,
             Request::SetAcl { .. } => OpCode::SetACL,
             Request::Multi { .. } => OpCode::Multi,
             Request::Check { .. } => OpCode::Check,
+            Request::Sync { .. } => OpCode::Synchronize,
+            Request::AddWatch { .. } => OpCode::AddWatch,
+            Request::SetWatches {
+                ref persistent,
+                ref recursive,
+                ..
+            } if persistent.is_empty() && recursive.is_empty() => OpCode::SetWatches,
+            Request::SetWatches { .. } => OpCode::SetWatches2,
+            Request::Raw { .. } => OpCode::Raw,
+        }
+    }
+
+    /// A copy of this request to send again on a new connection, if it is a read whose watch, if
+    /// any, is tracked by the connection rather than by the request itself.
+    pub(super) fn reissuable(&self) -> Option<Request> {
+        fn copy(watch: &Watch) -> Option<Watch> {
+            match *watch {
+                Watch::None => Some(Watch::None),
+                Watch::Global => Some(Watch::Global),
+                Watch::Custom(..) | Watch::Handle(..) | Watch::Persistent(..) => None,
+            }
+        }
+
+        match *self {
+            Request::Exists {
+                ref path,
+                ref watch,
+            } => Some(Request::Exists {
+                path: path.clone(),
+                watch: copy(watch)?,
+            }),
+            Request::GetChildren {
+                ref path,
+                ref watch,
+            } => Some(Request::GetChildren {
+                path: path.clone(),
+                watch: copy(watch)?,
+            }),
+            Request::GetChildren2 {
+                ref path,
+                ref watch,
+            } => Some(Request::GetChildren2 {
+                path: path.clone(),
+                watch: copy(watch)?,
+            }),
+            Request::GetData {
+                ref path,
+                ref watch,
+            } => Some(Request::GetData {
+                path: path.clone(),
+                watch: copy(watch)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::InvalidPath;
+
+    fn requests() -> Vec<Request> {
+        let acl: &'static [Acl] = Acl::open_unsafe();
+        let blob: &'static [u8] = &[0xab; 1024];
+        let path = || String::from("/some/path/to/a/node");
+        vec![
+            Request::Connect {
+                protocol_version: 0,
+                last_zxid_seen: 0,
+                timeout: 0,
+                session_id: 0,
+                passwd: vec![0; 16],
+                read_only: false,
+            },
+            Request::Exists {
+                path: path(),
+                watch: Watch::None,
+            },
+            Request::Delete {
+                path: path(),
+                version: -1,
+            },
+            Request::SetData {
+                path: path(),
+                data: Cow::Borrowed(blob),
+                version: -1,
+            },
+            Request::Create {
+                path: path(),
+                data: Cow::Borrowed(blob),
+                acl: Cow::Borrowed(acl),
+                mode: CreateMode::Persistent,
+                with_stat: false,
+            },
+            Request::GetChildren {
+                path: path(),
+                watch: Watch::Global,
+            },
+            Request::GetChildren2 {
+                path: path(),
+                watch: Watch::None,
+            },
+            Request::GetData {
+                path: path(),
+                watch: Watch::None,
+            },
+            Request::GetAcl { path: path() },
+            Request::Sync { path: path() },
+            Request::SetAcl {
+                path: path(),
+                acl: Cow::Borrowed(acl),
+                version: 3,
+            },
+            Request::Check {
+                path: path(),
+                version: 3,
+            },
+            Request::Multi(vec![
+                Request::Check {
+                    path: path(),
+                    version: 3,
+                },
+                Request::SetData {
+                    path: path(),
+                    data: Cow::Borrowed(blob),
+                    version: -1,
+                },
+            ]),
+            Request::AddWatch {
+                path: path(),
+                recursive: true,
+                watch: Watch::None,
+            },
+            Request::SetWatches {
+                relative_zxid: 42,
+                data: vec![path(), path()],
+                exist: vec![],
+                child: vec![path()],
+                persistent: vec![],
+                recursive: vec![],
+            },
+            Request::SetWatches {
+                relative_zxid: 42,
+                data: vec![],
+                exist: vec![path()],
+                child: vec![],
+                persistent: vec![path()],
+                recursive: vec![path(), path()],
+            },
+        ]
+    }
+
+    #[test]
+    fn serialized_len_is_exact() {
+        for request in requests() {
+            let mut buffer = Vec::with_capacity(request.serialized_len());
+            let capacity = buffer.capacity();
+            request.serialize_into(&mut buffer).unwrap();
+            assert_eq!(buffer.len(), request.serialized_len(), "{:?}", request);
+            assert_eq!(buffer.capacity(), capacity, "{:?} reallocated", request);
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let create = |path: &str, mode| Request::Create {
+            path: path.to_string(),
+            data: Cow::Borrowed(&[]),
+            acl: Cow::Borrowed(Acl::open_unsafe()),
+            mode,
+            with_stat: false,
+        };
+        assert_eq!(create("/a", CreateMode::Persistent).validate(100), Ok(()));
+        assert_eq!(create("/a/", CreateMode::PersistentSequential).validate(100), Ok(()));
+        assert_eq!(
+            create("/a/", CreateMode::Persistent).validate(100),
+            Err(InvalidRequest::Path {
+                path: "/a/".to_string(),
+                error: InvalidPath::TrailingSlash,
+            })
+        );
+        let multi = Request::Multi(vec![
+            create("/a", CreateMode::Persistent),
+            create("/a/../b", CreateMode::Persistent),
+        ]);
+        assert!(matches!(
+            multi.validate(100),
+            Err(InvalidRequest::Path {
+                error: InvalidPath::RelativeNode,
+                ..
+            })
+        ));
+
+        let set = Request::SetData {
+            path: "/a".to_string(),
+            data: Cow::Owned(vec![0; 100]),
+            version: -1,
+        };
+        // the xid, opcode, path, data and version
+        let size = 4 + 4 + (4 + 2) + (4 + 100) + 4;
+        assert_eq!(set.validate(size), Ok(()));
+        assert_eq!(
+            set.validate(size - 1),
+            Err(InvalidRequest::TooLarge {
+                path: Some("/a".to_string()),
+                size,
+                max: size - 1,
+            })
+        );
+    }
+
+    #[test]
+    fn debug_redacts_payloads() {
+        for request in requests() {
+            let redacted = format!("{:?}", request);
+            assert!(!redacted.contains("171,"), "{}", redacted);
+            let full = format!("{:?}", Logged(&request, true));
+            match request {
+                Request::SetData { .. } | Request::Create { .. } | Request::Multi(..) => {
+                    assert!(full.contains("171, 171"), "{}", full)
+                }
+                _ => assert_eq!(full, redacted),
+            }
+            if let Request::Connect { .. } = request {
+                assert!(redacted.contains("passwd: <redacted>"), "{}", redacted);
+            }
         }
     }
 }
@@ -1,12 +1,17 @@
 use super::error::ZkError;
+use super::redact::{FmtPayloads, Logged, Payload, Secret};
 use super::request::{MultiHeader, OpCode};
 use byteorder::{BigEndian, ReadBytesExt};
-use failure;
-use std::io::{self, Read};
-use {Acl, KeeperState, Permission, Stat, WatchedEvent, WatchedEventType};
+use bytes::Bytes;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Cursor, Read};
+use crate::codec::jute::{invalid, ReadFrom, StringReader};
+use crate::codec::records::proto as records;
+use crate::{Acl, Error, Stat};
 
-#[derive(Debug)]
 pub(crate) enum Response {
+    #[allow(dead_code)]
     Connect {
         protocol_version: i32,
         timeout: i32,
@@ -16,7 +21,7 @@ pub(crate) enum Response {
     },
     Stat(Stat),
     GetData {
-        bytes: Vec<u8>,
+        bytes: Bytes,
         stat: Stat,
     },
     GetAcl {
@@ -25,161 +30,187 @@ pub(crate) enum Response {
     },
     Empty,
     Strings(Vec<String>),
+    GetChildren2 {
+        children: Vec<String>,
+        stat: Stat,
+    },
     String(String),
+    Created {
+        path: String,
+        stat: Stat,
+    },
     Multi(Vec<Result<Response, ZkError>>),
+    /// The response to a `Request::Raw`, with its header as the server sent it.
+    Raw {
+        xid: i32,
+        zxid: i64,
+        err: ZkError,
+        body: Bytes,
+    },
 }
 
-pub trait ReadFrom: Sized {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<Self>;
-}
-
-impl ReadFrom for Vec<String> {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
-        let len = try!(read.read_i32::<BigEndian>());
-        let mut items = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            items.push(try!(read.read_string()));
+impl FmtPayloads for Response {
+    fn fmt_payloads(&self, f: &mut fmt::Formatter, full: bool) -> fmt::Result {
+        match *self {
+            Response::Connect {
+                protocol_version,
+                timeout,
+                session_id,
+                read_only,
+                ..
+            } => f
+                .debug_struct("Connect")
+                .field("protocol_version", &protocol_version)
+                .field("timeout", &timeout)
+                .field("session_id", &session_id)
+                .field("password", &Secret)
+                .field("read_only", &read_only)
+                .finish(),
+            Response::Stat(ref stat) => f.debug_tuple("Stat").field(stat).finish(),
+            Response::GetData {
+                ref bytes,
+                ref stat,
+            } => f
+                .debug_struct("GetData")
+                .field("bytes", &Payload { bytes, full })
+                .field("stat", stat)
+                .finish(),
+            Response::GetAcl { ref acl, ref stat } => f
+                .debug_struct("GetAcl")
+                .field("acl", acl)
+                .field("stat", stat)
+                .finish(),
+            Response::Empty => f.write_str("Empty"),
+            Response::Strings(ref strings) => f.debug_tuple("Strings").field(strings).finish(),
+            Response::GetChildren2 {
+                ref children,
+                ref stat,
+            } => f
+                .debug_struct("GetChildren2")
+                .field("children", children)
+                .field("stat", stat)
+                .finish(),
+            Response::String(ref string) => f.debug_tuple("String").field(string).finish(),
+            Response::Created { ref path, ref stat } => f
+                .debug_struct("Created")
+                .field("path", path)
+                .field("stat", stat)
+                .finish(),
+            Response::Multi(ref responses) => f
+                .debug_tuple("Multi")
+                .field(&Logged(&responses[..], full))
+                .finish(),
+            Response::Raw {
+                xid,
+                zxid,
+                err,
+                ref body,
+            } => f
+                .debug_struct("Raw")
+                .field("xid", &xid)
+                .field("zxid", &zxid)
+                .field("err", &err)
+                .field("body", &Payload { bytes: body, full })
+                .finish(),
         }
-        Ok(items)
-    }
-}
-
-impl ReadFrom for Stat {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<Stat> {
-        Ok(Stat {
-            czxid: try!(read.read_i64::<BigEndian>()),
-            mzxid: try!(read.read_i64::<BigEndian>()),
-            ctime: try!(read.read_i64::<BigEndian>()),
-            mtime: try!(read.read_i64::<BigEndian>()),
-            version: try!(read.read_i32::<BigEndian>()),
-            cversion: try!(read.read_i32::<BigEndian>()),
-            aversion: try!(read.read_i32::<BigEndian>()),
-            ephemeral_owner: try!(read.read_i64::<BigEndian>()),
-            data_length: try!(read.read_i32::<BigEndian>()),
-            num_children: try!(read.read_i32::<BigEndian>()),
-            pzxid: try!(read.read_i64::<BigEndian>()),
-        })
-    }
-}
-
-impl ReadFrom for WatchedEvent {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<WatchedEvent> {
-        let wtype = read.read_i32::<BigEndian>()?;
-        let state = read.read_i32::<BigEndian>()?;
-        let path = read.read_string()?;
-        Ok(WatchedEvent {
-            event_type: WatchedEventType::from(wtype),
-            keeper_state: KeeperState::from(state),
-            path,
-        })
     }
 }
 
-impl ReadFrom for Vec<Acl> {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
-        let len = try!(read.read_i32::<BigEndian>());
-        let mut items = Vec::with_capacity(len as usize);
-        for _ in 0..len {
-            items.push(try!(Acl::read_from(read)));
+impl<E: fmt::Debug> FmtPayloads for Result<Response, E> {
+    fn fmt_payloads(&self, f: &mut fmt::Formatter, full: bool) -> fmt::Result {
+        match *self {
+            Ok(ref response) => f.debug_tuple("Ok").field(&Logged(response, full)).finish(),
+            Err(ref e) => f.debug_tuple("Err").field(e).finish(),
         }
-        Ok(items)
-    }
-}
-
-impl ReadFrom for Acl {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
-        let perms = try!(Permission::read_from(read));
-        let scheme = try!(read.read_string());
-        let id = try!(read.read_string());
-        Ok(Acl { perms, scheme, id })
     }
 }
 
-impl ReadFrom for Permission {
-    fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
-        Ok(Permission::from_raw(try!(read.read_u32::<BigEndian>())))
+impl fmt::Debug for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_payloads(f, false)
     }
 }
 
 impl ReadFrom for MultiHeader {
     fn read_from<R: Read>(read: &mut R) -> io::Result<Self> {
-        let opcode = read.read_i32::<BigEndian>()?;
-        let done = read.read_u8()? != 0;
-        let err = read.read_i32::<BigEndian>()?;
-        if done {
+        let header = records::MultiHeader::read_from(read)?;
+        if header.done {
             Ok(MultiHeader::Done)
-        } else if opcode == -1 {
-            Ok(MultiHeader::NextErr(err.into()))
-        } else {
-            Ok(MultiHeader::NextOk(opcode.into()))
-        }
-    }
-}
-
-pub trait BufferReader: Read {
-    fn read_buffer(&mut self) -> io::Result<Vec<u8>>;
-}
-
-impl<R: Read> BufferReader for R {
-    fn read_buffer(&mut self) -> io::Result<Vec<u8>> {
-        let len = try!(self.read_i32::<BigEndian>());
-        let len = if len < 0 { 0 } else { len as usize };
-        let mut buf = vec![0; len];
-        let read = try!(self.read(&mut buf));
-        if read == len {
-            Ok(buf)
+        } else if header.r#type == -1 {
+            Ok(MultiHeader::NextErr(header.err.into()))
         } else {
-            Err(io::Error::new(
-                io::ErrorKind::WouldBlock,
-                "read_buffer failed",
-            ))
+            let opcode = OpCode::try_from(header.r#type).map_err(|_| invalid("unknown opcode"))?;
+            Ok(MultiHeader::NextOk(opcode))
         }
     }
 }
 
-trait StringReader: Read {
-    fn read_string(&mut self) -> io::Result<String>;
-}
-
-impl<R: Read> StringReader for R {
-    fn read_string(&mut self) -> io::Result<String> {
-        let raw = try!(self.read_buffer());
-        Ok(String::from_utf8(raw).unwrap())
+/// Read a length-prefixed buffer as a slice of the underlying `Bytes`, without copying it.
+fn read_bytes(reader: &mut Cursor<Bytes>) -> io::Result<Bytes> {
+    let len = reader.read_i32::<BigEndian>()?;
+    let len = if len < 0 { 0 } else { len as usize };
+    let start = reader.position() as usize;
+    if start + len > reader.get_ref().len() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "read_bytes failed",
+        ));
     }
+    reader.set_position((start + len) as u64);
+    Ok(reader.get_ref().slice(start..start + len))
 }
 
 impl Response {
-    pub(super) fn parse(opcode: OpCode, reader: &mut &[u8]) -> Result<Self, failure::Error> {
+    pub(super) fn parse(
+        opcode: OpCode,
+        reader: &mut Cursor<Bytes>,
+    ) -> Result<Self, Error> {
         match opcode {
-            OpCode::CreateSession => Ok(Response::Connect {
-                protocol_version: reader.read_i32::<BigEndian>()?,
-                timeout: reader.read_i32::<BigEndian>()?,
-                session_id: reader.read_i64::<BigEndian>()?,
-                password: reader.read_buffer()?,
-                read_only: reader.read_u8()? != 0,
-            }),
+            OpCode::CreateSession => {
+                let res = records::ConnectResponse::read_from(reader)?;
+                Ok(Response::Connect {
+                    protocol_version: res.protocol_version,
+                    timeout: res.time_out,
+                    session_id: res.session_id,
+                    password: res.passwd,
+                    read_only: res.read_only,
+                })
+            }
             OpCode::Exists | OpCode::SetData | OpCode::SetACL => {
                 Ok(Response::Stat(Stat::read_from(reader)?))
             }
             OpCode::GetData => Ok(Response::GetData {
-                bytes: reader.read_buffer()?,
+                bytes: read_bytes(reader)?,
                 stat: Stat::read_from(reader)?,
             }),
             OpCode::Delete => Ok(Response::Empty),
             OpCode::GetChildren => Ok(Response::Strings(Vec::<String>::read_from(reader)?)),
-            OpCode::Create => Ok(Response::String(reader.read_string()?)),
+            OpCode::GetChildren2 => Ok(Response::GetChildren2 {
+                children: Vec::<String>::read_from(reader)?,
+                stat: Stat::read_from(reader)?,
+            }),
+            OpCode::Create | OpCode::Synchronize => Ok(Response::String(reader.read_string()?)),
+            OpCode::Create2 => Ok(Response::Created {
+                path: reader.read_string()?,
+                stat: Stat::read_from(reader)?,
+            }),
             OpCode::GetACL => Ok(Response::GetAcl {
                 acl: Vec::<Acl>::read_from(reader)?,
                 stat: Stat::read_from(reader)?,
             }),
-            OpCode::Check => Ok(Response::Empty),
+            OpCode::Check | OpCode::AddWatch => Ok(Response::Empty),
             OpCode::Multi => {
                 let mut responses = Vec::new();
                 loop {
                     match MultiHeader::read_from(reader)? {
                         MultiHeader::NextErr(e) => {
                             responses.push(Err(e));
-                            let _ = reader.read_i32::<BigEndian>()?;
+                            let _ = records::ErrorResponse::read_from(reader)?;
+                        }
+                        MultiHeader::NextOk(OpCode::Multi) => {
+                            // a multi cannot contain another, and nesting them would recurse
+                            // as deep as the server cares to go
+                            return Err(Error::Protocol("multi inside a multi".to_string()));
                         }
                         MultiHeader::NextOk(opcode) => {
                             responses.push(Ok(Response::parse(opcode, reader)?));
@@ -189,7 +220,10 @@ impl Response {
                 }
                 Ok(Response::Multi(responses))
             }
-            _ => panic!("got unexpected response opcode {:?}", opcode),
+            _ => Err(Error::Protocol(format!(
+                "got unexpected response opcode {:?}",
+                opcode
+            ))),
         }
     }
 }
@@ -0,0 +1,655 @@
+//! Property tests for the codec: every request the client encodes and every response it decodes
+//! survives a round trip through the wire format, and malformed responses are rejected rather
+//! than accepted, panicked on, or allowed to allocate whatever they claim to need.
+//!
+//! The client only ever encodes requests and decodes responses, so the other half of each round
+//! trip is written out here from the wire format, rather than with any of the code under test.
+
+use super::redact::Logged;
+use super::request::{OpCode, Request};
+use super::response::Response;
+use super::{Watch, ZkError};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+use proptest::collection::vec;
+use proptest::prelude::*;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read};
+use crate::codec::jute::ReadFrom;
+use crate::{Acl, CreateMode, KeeperState, Permission, Stat, WatchedEvent, WatchedEventType};
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, what)
+}
+
+fn path() -> impl Strategy<Value = String> {
+    "/|(/[a-z0-9.-]{1,8}){1,4}"
+}
+
+fn string() -> impl Strategy<Value = String> {
+    ".{0,12}"
+}
+
+fn bytes() -> impl Strategy<Value = Vec<u8>> {
+    vec(any::<u8>(), 0..64)
+}
+
+fn acls() -> impl Strategy<Value = Vec<Acl>> {
+    let acl = (any::<u32>(), string(), string()).prop_map(|(perms, scheme, id)| Acl {
+        perms: Permission::from_raw(perms),
+        scheme,
+        id,
+    });
+    vec(acl, 0..4)
+}
+
+fn mode() -> impl Strategy<Value = CreateMode> {
+    prop_oneof![
+        Just(CreateMode::Persistent),
+        Just(CreateMode::Ephemeral),
+        Just(CreateMode::PersistentSequential),
+        Just(CreateMode::EphemeralSequential),
+        Just(CreateMode::Container),
+    ]
+}
+
+/// Watches that go on the wire as they are; a custom watch goes on it like the global one.
+fn watch() -> impl Strategy<Value = Watch> {
+    any::<bool>().prop_map(|set| if set { Watch::Global } else { Watch::None })
+}
+
+fn stat() -> impl Strategy<Value = Stat> {
+    let times = any::<(i64, i64, i64, i64)>();
+    let versions = any::<(i32, i32, i32)>();
+    let rest = any::<(i64, i32, i32, i64)>();
+    (times, versions, rest).prop_map(
+        |(
+            (czxid, mzxid, ctime, mtime),
+            (version, cversion, aversion),
+            (ephemeral_owner, data_length, num_children, pzxid),
+        )| Stat {
+            czxid,
+            mzxid,
+            ctime,
+            mtime,
+            version,
+            cversion,
+            aversion,
+            ephemeral_owner,
+            data_length,
+            num_children,
+            pzxid,
+        },
+    )
+}
+
+/// The requests that may be part of a multi.
+fn multi_op() -> impl Strategy<Value = Request> {
+    prop_oneof![
+        (path(), bytes(), acls(), mode()).prop_map(|(path, data, acl, mode)| Request::Create {
+            path,
+            data: Cow::Owned(data),
+            acl: Cow::Owned(acl),
+            mode,
+            with_stat: false,
+        }),
+        (path(), any::<i32>()).prop_map(|(path, version)| Request::Delete { path, version }),
+        (path(), bytes(), any::<i32>()).prop_map(|(path, data, version)| Request::SetData {
+            path,
+            data: Cow::Owned(data),
+            version,
+        }),
+        (path(), any::<i32>()).prop_map(|(path, version)| Request::Check { path, version }),
+    ]
+}
+
+fn request() -> impl Strategy<Value = Request> {
+    let connect = (any::<(i32, i64, i32, i64)>(), bytes(), any::<bool>()).prop_map(
+        |((protocol_version, last_zxid_seen, timeout, session_id), passwd, read_only)| {
+            Request::Connect {
+                protocol_version,
+                last_zxid_seen,
+                timeout,
+                session_id,
+                passwd,
+                read_only,
+            }
+        },
+    );
+    let paths = || vec(path(), 0..4);
+    let set_watches = (any::<i64>(), paths(), paths(), paths(), paths(), paths()).prop_map(
+        |(relative_zxid, data, exist, child, persistent, recursive)| Request::SetWatches {
+            relative_zxid,
+            data,
+            exist,
+            child,
+            persistent,
+            recursive,
+        },
+    );
+    prop_oneof![
+        connect,
+        (path(), watch()).prop_map(|(path, watch)| Request::Exists { path, watch }),
+        (path(), watch()).prop_map(|(path, watch)| Request::GetChildren { path, watch }),
+        (path(), watch()).prop_map(|(path, watch)| Request::GetChildren2 { path, watch }),
+        (path(), watch()).prop_map(|(path, watch)| Request::GetData { path, watch }),
+        (path(), any::<bool>()).prop_map(|(path, recursive)| Request::AddWatch {
+            path,
+            recursive,
+            watch: Watch::None,
+        }),
+        path().prop_map(|path| Request::GetAcl { path }),
+        path().prop_map(|path| Request::Sync { path }),
+        (path(), acls(), any::<i32>()).prop_map(|(path, acl, version)| Request::SetAcl {
+            path,
+            acl: Cow::Owned(acl),
+            version,
+        }),
+        multi_op(),
+        (path(), bytes(), acls(), mode()).prop_map(|(path, data, acl, mode)| Request::Create {
+            path,
+            data: Cow::Owned(data),
+            acl: Cow::Owned(acl),
+            mode,
+            with_stat: true,
+        }),
+        vec(multi_op(), 0..4).prop_map(Request::Multi),
+        set_watches,
+    ]
+}
+
+fn read_buffer(r: &mut &[u8]) -> io::Result<Vec<u8>> {
+    let len = r.read_i32::<BigEndian>()?;
+    let len = usize::try_from(len).map_err(|_| invalid("negative length"))?;
+    if len > r.len() {
+        return Err(invalid("buffer longer than input"));
+    }
+    let (buffer, rest) = r.split_at(len);
+    *r = rest;
+    Ok(buffer.to_vec())
+}
+
+fn read_string(r: &mut &[u8]) -> io::Result<String> {
+    String::from_utf8(read_buffer(r)?).map_err(|_| invalid("string is not UTF-8"))
+}
+
+fn read_list<T, F>(r: &mut &[u8], mut read: F) -> io::Result<Vec<T>>
+where
+    F: FnMut(&mut &[u8]) -> io::Result<T>,
+{
+    let len = r.read_i32::<BigEndian>()?;
+    (0..len).map(|_| read(r)).collect()
+}
+
+fn read_acl(r: &mut &[u8]) -> io::Result<Acl> {
+    Ok(Acl {
+        perms: Permission::from_raw(r.read_u32::<BigEndian>()?),
+        scheme: read_string(r)?,
+        id: read_string(r)?,
+    })
+}
+
+fn read_watch(r: &mut &[u8]) -> io::Result<Watch> {
+    match r.read_u8()? {
+        0 => Ok(Watch::None),
+        1 => Ok(Watch::Global),
+        _ => Err(invalid("watch flag is not a bool")),
+    }
+}
+
+fn read_mode(r: &mut &[u8]) -> io::Result<CreateMode> {
+    Ok(match r.read_i32::<BigEndian>()? {
+        0 => CreateMode::Persistent,
+        1 => CreateMode::Ephemeral,
+        2 => CreateMode::PersistentSequential,
+        3 => CreateMode::EphemeralSequential,
+        4 => CreateMode::Container,
+        _ => return Err(invalid("unknown create mode")),
+    })
+}
+
+/// Read the body of a request with the given opcode, as a server would.
+fn read_request(opcode: OpCode, r: &mut &[u8]) -> io::Result<Request> {
+    Ok(match opcode {
+        OpCode::CreateSession => Request::Connect {
+            protocol_version: r.read_i32::<BigEndian>()?,
+            last_zxid_seen: r.read_i64::<BigEndian>()?,
+            timeout: r.read_i32::<BigEndian>()?,
+            session_id: r.read_i64::<BigEndian>()?,
+            passwd: read_buffer(r)?,
+            read_only: r.read_u8()? != 0,
+        },
+        OpCode::Exists => Request::Exists {
+            path: read_string(r)?,
+            watch: read_watch(r)?,
+        },
+        OpCode::GetChildren => Request::GetChildren {
+            path: read_string(r)?,
+            watch: read_watch(r)?,
+        },
+        OpCode::GetChildren2 => Request::GetChildren2 {
+            path: read_string(r)?,
+            watch: read_watch(r)?,
+        },
+        OpCode::GetData => Request::GetData {
+            path: read_string(r)?,
+            watch: read_watch(r)?,
+        },
+        OpCode::GetACL => Request::GetAcl {
+            path: read_string(r)?,
+        },
+        OpCode::Synchronize => Request::Sync {
+            path: read_string(r)?,
+        },
+        OpCode::SetACL => Request::SetAcl {
+            path: read_string(r)?,
+            acl: Cow::Owned(read_list(r, read_acl)?),
+            version: r.read_i32::<BigEndian>()?,
+        },
+        OpCode::Create => Request::Create {
+            path: read_string(r)?,
+            data: Cow::Owned(read_buffer(r)?),
+            acl: Cow::Owned(read_list(r, read_acl)?),
+            mode: read_mode(r)?,
+            with_stat: false,
+        },
+        OpCode::Create2 => Request::Create {
+            path: read_string(r)?,
+            data: Cow::Owned(read_buffer(r)?),
+            acl: Cow::Owned(read_list(r, read_acl)?),
+            mode: read_mode(r)?,
+            with_stat: true,
+        },
+        OpCode::Delete => Request::Delete {
+            path: read_string(r)?,
+            version: r.read_i32::<BigEndian>()?,
+        },
+        OpCode::SetData => Request::SetData {
+            path: read_string(r)?,
+            data: Cow::Owned(read_buffer(r)?),
+            version: r.read_i32::<BigEndian>()?,
+        },
+        OpCode::Check => Request::Check {
+            path: read_string(r)?,
+            version: r.read_i32::<BigEndian>()?,
+        },
+        OpCode::Multi => {
+            let mut requests = Vec::new();
+            loop {
+                let opcode = r.read_i32::<BigEndian>()?;
+                let done = r.read_u8()? != 0;
+                if r.read_i32::<BigEndian>()? != -1 {
+                    return Err(invalid("request header carries an error"));
+                }
+                if done {
+                    break;
+                }
+                let opcode = OpCode::try_from(opcode).map_err(|_| invalid("unknown opcode"))?;
+                requests.push(read_request(opcode, r)?);
+            }
+            Request::Multi(requests)
+        }
+        OpCode::AddWatch => Request::AddWatch {
+            path: read_string(r)?,
+            recursive: match r.read_i32::<BigEndian>()? {
+                0 => false,
+                1 => true,
+                _ => return Err(invalid("unknown watch mode")),
+            },
+            watch: Watch::None,
+        },
+        OpCode::SetWatches | OpCode::SetWatches2 => Request::SetWatches {
+            relative_zxid: r.read_i64::<BigEndian>()?,
+            data: read_list(r, read_string)?,
+            exist: read_list(r, read_string)?,
+            child: read_list(r, read_string)?,
+            persistent: match opcode {
+                OpCode::SetWatches2 => read_list(r, read_string)?,
+                _ => Vec::new(),
+            },
+            recursive: match opcode {
+                OpCode::SetWatches2 => read_list(r, read_string)?,
+                _ => Vec::new(),
+            },
+        },
+        _ => return Err(invalid("not a request the client sends")),
+    })
+}
+
+/// What the response to a request with the given opcode decodes into.
+fn response() -> impl Strategy<Value = (OpCode, Response)> {
+    let connect = (any::<(i32, i32, i64)>(), bytes(), any::<bool>()).prop_map(
+        |((protocol_version, timeout, session_id), password, read_only)| {
+            let response = Response::Connect {
+                protocol_version,
+                timeout,
+                session_id,
+                password,
+                read_only,
+            };
+            (OpCode::CreateSession, response)
+        },
+    );
+    let stat_op = prop_oneof![
+        Just(OpCode::Exists),
+        Just(OpCode::SetData),
+        Just(OpCode::SetACL)
+    ];
+    let empty_op = prop_oneof![
+        Just(OpCode::Delete),
+        Just(OpCode::Check),
+        Just(OpCode::AddWatch)
+    ];
+    let multi = vec(prop_oneof![multi_result().prop_map(Ok), zk_error().prop_map(Err)], 0..4)
+        .prop_map(|results| (OpCode::Multi, Response::Multi(results)));
+    prop_oneof![
+        connect,
+        (stat_op, stat()).prop_map(|(opcode, stat)| (opcode, Response::Stat(stat))),
+        (bytes(), stat()).prop_map(|(bytes, stat)| {
+            let bytes = Bytes::from(bytes);
+            (OpCode::GetData, Response::GetData { bytes, stat })
+        }),
+        (acls(), stat()).prop_map(|(acl, stat)| (OpCode::GetACL, Response::GetAcl { acl, stat })),
+        empty_op.prop_map(|opcode| (opcode, Response::Empty)),
+        vec(string(), 0..4).prop_map(|children| (OpCode::GetChildren, Response::Strings(children))),
+        (vec(string(), 0..4), stat()).prop_map(|(children, stat)| {
+            (OpCode::GetChildren2, Response::GetChildren2 { children, stat })
+        }),
+        path().prop_map(|path| (OpCode::Create, Response::String(path))),
+        (path(), stat()).prop_map(|(path, stat)| {
+            (OpCode::Create2, Response::Created { path, stat })
+        }),
+        path().prop_map(|path| (OpCode::Synchronize, Response::String(path))),
+        multi,
+    ]
+}
+
+/// The responses that may be part of a multi.
+fn multi_result() -> impl Strategy<Value = Response> {
+    prop_oneof![
+        stat().prop_map(Response::Stat),
+        Just(()).prop_map(|()| Response::Empty),
+        path().prop_map(Response::String),
+    ]
+}
+
+fn zk_error() -> impl Strategy<Value = ZkError> {
+    (-130..0).prop_map(ZkError::from)
+}
+
+fn write_buffer(w: &mut Vec<u8>, buffer: &[u8]) {
+    w.write_i32::<BigEndian>(buffer.len() as i32).unwrap();
+    w.extend_from_slice(buffer);
+}
+
+fn write_stat(w: &mut Vec<u8>, stat: &Stat) {
+    for &zxid_or_time in &[stat.czxid, stat.mzxid, stat.ctime, stat.mtime] {
+        w.write_i64::<BigEndian>(zxid_or_time).unwrap();
+    }
+    for &version in &[stat.version, stat.cversion, stat.aversion] {
+        w.write_i32::<BigEndian>(version).unwrap();
+    }
+    w.write_i64::<BigEndian>(stat.ephemeral_owner).unwrap();
+    w.write_i32::<BigEndian>(stat.data_length).unwrap();
+    w.write_i32::<BigEndian>(stat.num_children).unwrap();
+    w.write_i64::<BigEndian>(stat.pzxid).unwrap();
+}
+
+/// Write the body of a response, as a server would.
+fn write_response(w: &mut Vec<u8>, response: &Response) {
+    match *response {
+        Response::Connect {
+            protocol_version,
+            timeout,
+            session_id,
+            ref password,
+            read_only,
+        } => {
+            w.write_i32::<BigEndian>(protocol_version).unwrap();
+            w.write_i32::<BigEndian>(timeout).unwrap();
+            w.write_i64::<BigEndian>(session_id).unwrap();
+            write_buffer(w, password);
+            w.write_u8(read_only as u8).unwrap();
+        }
+        Response::Stat(ref stat) => write_stat(w, stat),
+        Response::GetData {
+            ref bytes,
+            ref stat,
+        } => {
+            write_buffer(w, bytes);
+            write_stat(w, stat);
+        }
+        Response::GetAcl { ref acl, ref stat } => {
+            w.write_i32::<BigEndian>(acl.len() as i32).unwrap();
+            for acl in acl {
+                w.write_u32::<BigEndian>(acl.perms.code()).unwrap();
+                write_buffer(w, acl.scheme.as_bytes());
+                write_buffer(w, acl.id.as_bytes());
+            }
+            write_stat(w, stat);
+        }
+        Response::Empty => {}
+        Response::Strings(ref strings) => {
+            w.write_i32::<BigEndian>(strings.len() as i32).unwrap();
+            for string in strings {
+                write_buffer(w, string.as_bytes());
+            }
+        }
+        Response::GetChildren2 {
+            ref children,
+            ref stat,
+        } => {
+            w.write_i32::<BigEndian>(children.len() as i32).unwrap();
+            for child in children {
+                write_buffer(w, child.as_bytes());
+            }
+            write_stat(w, stat);
+        }
+        Response::String(ref string) => write_buffer(w, string.as_bytes()),
+        Response::Created { ref path, ref stat } => {
+            write_buffer(w, path.as_bytes());
+            write_stat(w, stat);
+        }
+        Response::Raw { ref body, .. } => w.extend_from_slice(body),
+        Response::Multi(ref results) => {
+            for result in results {
+                match *result {
+                    Ok(ref response) => {
+                        let opcode = match *response {
+                            Response::Stat(_) => OpCode::SetData,
+                            Response::String(_) => OpCode::Create,
+                            _ => OpCode::Check,
+                        };
+                        w.write_i32::<BigEndian>(opcode as i32).unwrap();
+                        w.write_u8(0).unwrap();
+                        w.write_i32::<BigEndian>(-1).unwrap();
+                        write_response(w, response);
+                    }
+                    Err(e) => {
+                        w.write_i32::<BigEndian>(-1).unwrap();
+                        w.write_u8(0).unwrap();
+                        w.write_i32::<BigEndian>(e.code()).unwrap();
+                        w.write_i32::<BigEndian>(e.code()).unwrap();
+                    }
+                }
+            }
+            w.write_i32::<BigEndian>(-1).unwrap();
+            w.write_u8(1).unwrap();
+            w.write_i32::<BigEndian>(-1).unwrap();
+        }
+    }
+}
+
+fn event() -> impl Strategy<Value = WatchedEvent> {
+    let event_type = prop_oneof![(1..=6).boxed(), Just(-1).boxed()];
+    let keeper_state = prop::sample::select(vec![0, 3, 4, 5, 6, -112]);
+    (event_type, keeper_state, path()).prop_map(|(event_type, keeper_state, path)| {
+        WatchedEvent {
+            event_type: WatchedEventType::from(event_type),
+            keeper_state: KeeperState::from(keeper_state),
+            path,
+        }
+    })
+}
+
+fn parse(opcode: OpCode, body: &[u8]) -> Result<Response, crate::Error> {
+    let mut reader = Cursor::new(Bytes::copy_from_slice(body));
+    let response = Response::parse(opcode, &mut reader)?;
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty(), "{} bytes left over", rest.len());
+    Ok(response)
+}
+
+/// The response opcodes, and a few that no response carries.
+fn any_opcode() -> impl Strategy<Value = OpCode> {
+    prop::sample::select(vec![
+        OpCode::CreateSession,
+        OpCode::Create,
+        OpCode::Delete,
+        OpCode::Exists,
+        OpCode::GetData,
+        OpCode::SetData,
+        OpCode::GetACL,
+        OpCode::SetACL,
+        OpCode::GetChildren,
+        OpCode::GetChildren2,
+        OpCode::Check,
+        OpCode::Multi,
+        OpCode::Create2,
+        OpCode::AddWatch,
+        OpCode::Synchronize,
+        OpCode::Ping,
+        OpCode::Notification,
+        OpCode::Auth,
+    ])
+}
+
+proptest! {
+    #[test]
+    fn requests_round_trip(request in request()) {
+        let mut encoded = Vec::new();
+        request.serialize_into(&mut encoded).unwrap();
+        prop_assert_eq!(encoded.len(), request.serialized_len());
+
+        let mut r = &encoded[..];
+        let decoded = read_request(request.opcode(), &mut r).unwrap();
+        prop_assert!(r.is_empty(), "{} bytes left over", r.len());
+        // `Debug` redacts the connect password, which is covered by encoding the request again
+        prop_assert_eq!(
+            format!("{:?}", Logged(&decoded, true)),
+            format!("{:?}", Logged(&request, true))
+        );
+        let mut again = Vec::new();
+        decoded.serialize_into(&mut again).unwrap();
+        prop_assert_eq!(again, encoded);
+    }
+
+    #[test]
+    fn responses_round_trip((opcode, response) in response()) {
+        let mut encoded = Vec::new();
+        write_response(&mut encoded, &response);
+        let decoded = parse(opcode, &encoded).unwrap();
+        prop_assert_eq!(
+            format!("{:?}", Logged(&decoded, true)),
+            format!("{:?}", Logged(&response, true))
+        );
+        let mut again = Vec::new();
+        write_response(&mut again, &decoded);
+        prop_assert_eq!(again, encoded);
+    }
+
+    #[test]
+    fn events_round_trip(e in event()) {
+        let mut encoded = Vec::new();
+        encoded.write_i32::<BigEndian>(e.event_type as i32).unwrap();
+        encoded.write_i32::<BigEndian>(e.keeper_state as i32).unwrap();
+        write_buffer(&mut encoded, e.path.as_bytes());
+        let mut r = &encoded[..];
+        prop_assert_eq!(WatchedEvent::read_from(&mut r).unwrap(), e);
+        prop_assert!(r.is_empty());
+    }
+
+    #[test]
+    fn truncated_responses_are_rejected((opcode, response) in response()) {
+        let mut encoded = Vec::new();
+        write_response(&mut encoded, &response);
+        for len in 0..encoded.len() {
+            prop_assert!(parse(opcode, &encoded[..len]).is_err(), "parsed {} bytes", len);
+        }
+    }
+
+    #[test]
+    fn garbage_responses_do_not_panic(opcode in any_opcode(), body in vec(any::<u8>(), 0..256)) {
+        let mut reader = Cursor::new(Bytes::from(body));
+        let _ = Response::parse(opcode, &mut reader);
+    }
+
+    #[test]
+    fn garbage_events_do_not_panic(body in vec(any::<u8>(), 0..64)) {
+        let _ = WatchedEvent::read_from(&mut &body[..]);
+    }
+
+    #[test]
+    fn huge_lengths_are_rejected(opcode in any_opcode(), len in 1 << 24..i32::MAX) {
+        // every response that starts with a length-prefixed list or buffer
+        let mut body = Vec::new();
+        body.write_i32::<BigEndian>(len).unwrap();
+        body.extend_from_slice(&[0; 16]);
+        match opcode {
+            OpCode::GetData
+            | OpCode::GetACL
+            | OpCode::GetChildren
+            | OpCode::GetChildren2
+            | OpCode::Create
+            | OpCode::Create2 => {
+                prop_assert!(parse(opcode, &body).is_err())
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn unknown_codes_are_rejected() {
+    // a multi entry for an operation that does not exist
+    let mut body = Vec::new();
+    body.write_i32::<BigEndian>(42).unwrap();
+    body.write_u8(0).unwrap();
+    body.write_i32::<BigEndian>(-1).unwrap();
+    assert!(parse(OpCode::Multi, &body).is_err());
+
+    // a multi entry for an operation whose response is not part of a multi
+    let mut body = Vec::new();
+    body.write_i32::<BigEndian>(OpCode::Ping as i32).unwrap();
+    body.write_u8(0).unwrap();
+    body.write_i32::<BigEndian>(-1).unwrap();
+    assert!(parse(OpCode::Multi, &body).is_err());
+
+    // events of unknown types and states
+    for &(event_type, keeper_state) in &[(7, 3), (1, 1), (0, 3)] {
+        let mut body = Vec::new();
+        body.write_i32::<BigEndian>(event_type).unwrap();
+        body.write_i32::<BigEndian>(keeper_state).unwrap();
+        write_buffer(&mut body, b"/");
+        assert!(WatchedEvent::read_from(&mut &body[..]).is_err());
+    }
+
+    // strings that are not UTF-8
+    let mut body = Vec::new();
+    write_buffer(&mut body, &[0xff, 0xfe]);
+    assert!(parse(OpCode::Create, &body).is_err());
+}
+
+#[test]
+fn nested_multis_are_rejected() {
+    // deep enough to overflow the stack if every level were parsed with a recursive call
+    let mut body = Vec::new();
+    for _ in 0..100_000 {
+        body.write_i32::<BigEndian>(OpCode::Multi as i32).unwrap();
+        body.write_u8(0).unwrap();
+        body.write_i32::<BigEndian>(-1).unwrap();
+    }
+    assert!(parse(OpCode::Multi, &body).is_err());
+}
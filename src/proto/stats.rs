@@ -0,0 +1,112 @@
+//! State of the connection that the packetizer shares with the client handles.
+
+use futures::future;
+use futures::task::AtomicWaker;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+use super::watch::HandleWatchers;
+use crate::circuit::Breaker;
+use crate::{ConnectionState, ConnectionStats};
+
+/// Counters that the packetizer keeps up to date for `ZooKeeper::stats`.
+///
+/// Each value is updated on its own, so a snapshot may mix values from slightly different points
+/// in time.
+#[derive(Debug, Default)]
+pub(crate) struct SharedStats {
+    /// Requests that have been issued, but not yet picked up by the packetizer.
+    pub(super) queued: AtomicUsize,
+    pub(super) in_flight: AtomicUsize,
+    pub(super) outbox_bytes: AtomicUsize,
+    pub(super) last_zxid_seen: AtomicI64,
+    pub(super) session_id: AtomicI64,
+    pub(super) last_packet: Mutex<Option<Instant>>,
+    /// Leave the session to expire once all handles are dropped, rather than closing it.
+    pub(super) detached: AtomicBool,
+    /// How long to wait for requests in flight before closing the session, once
+    /// `ZooKeeper::shutdown` has been called.
+    pub(super) shutdown: Mutex<Option<Duration>>,
+    /// Wakes the packetizer, so that it notices a shutdown even while it is reconnecting.
+    pub(super) packetizer: AtomicWaker,
+    /// Fails requests right away while it is open, if one is configured.
+    pub(super) breaker: Option<Breaker>,
+    /// The default watchers of the handles that have their own.
+    pub(super) handle_watchers: Mutex<HandleWatchers>,
+    /// The `ConnectionState` of the connection, see `SharedStats::state`.
+    state: AtomicU8,
+    /// Woken whenever the state changes.
+    state_waiters: Mutex<Vec<Waker>>,
+}
+
+impl SharedStats {
+    pub(super) fn received_packet(&self) {
+        *self.last_packet.lock().unwrap() = Some(Instant::now());
+    }
+
+    pub(super) fn set_state(&self, state: ConnectionState) {
+        self.state.store(state as u8, Ordering::Relaxed);
+        for waker in self.state_waiters.lock().unwrap().drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// Resolve with the state as soon as `done` holds for it.
+    pub(crate) fn wait_for_state<F>(
+        self: Arc<Self>,
+        done: F,
+    ) -> impl Future<Output = ConnectionState>
+    where
+        F: Fn(ConnectionState) -> bool,
+    {
+        future::poll_fn(move |cx| {
+            let state = self.state();
+            if done(state) {
+                return Poll::Ready(state);
+            }
+            self.state_waiters.lock().unwrap().push(cx.waker().clone());
+            // the state may have changed before the waker was in place
+            let state = self.state();
+            if done(state) {
+                Poll::Ready(state)
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+
+    /// How long the server has not been heard from for, if the connection is being re-established.
+    pub(super) fn disconnected_for(&self) -> Option<Duration> {
+        if self.state() != ConnectionState::Reconnecting {
+            return None;
+        }
+        self.last_packet.lock().unwrap().map(|at| at.elapsed())
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        // the default of 0 is `Connecting`
+        match self.state.load(Ordering::Relaxed) {
+            0 => ConnectionState::Connecting,
+            1 => ConnectionState::Connected,
+            2 => ConnectionState::ConnectedReadOnly,
+            3 => ConnectionState::Reconnecting,
+            4 => ConnectionState::Expired,
+            _ => ConnectionState::Closed,
+        }
+    }
+
+    pub(crate) fn snapshot(&self, server_addr: SocketAddr) -> ConnectionStats {
+        ConnectionStats {
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            queued: self.queued.load(Ordering::Relaxed),
+            outbox_bytes: self.outbox_bytes.load(Ordering::Relaxed),
+            last_zxid_seen: self.last_zxid_seen.load(Ordering::Relaxed),
+            session_id: self.session_id.load(Ordering::Relaxed),
+            server_addr,
+            since_last_packet: self.last_packet.lock().unwrap().map(|t| t.elapsed()),
+        }
+    }
+}
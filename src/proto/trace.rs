@@ -0,0 +1,78 @@
+//! Integration with `tracing`, if the `tracing` feature is enabled.
+//!
+//! Every request gets a span named `zookeeper` with the fields `op`, `path`, and `xid`. The span is
+//! created when the request is issued, so it is a child of whatever span is current at that
+//! point, and closes when the response arrives. Connection lifecycle changes are emitted as
+//! events with the `tokio_zookeeper` target.
+//!
+//! Without the feature, everything in here compiles down to nothing.
+
+/// Emit a connection lifecycle event at INFO level.
+macro_rules! lifecycle {
+    ($($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::info!(target: "tokio_zookeeper", $($arg)+);
+    };
+}
+
+#[cfg(feature = "tracing")]
+mod imp {
+    use super::super::{Request, ZkError};
+    use crate::metrics::Operation;
+    use tracing::{self, field, Span};
+
+    /// The span that covers a single request.
+    #[derive(Debug)]
+    pub(crate) struct RequestSpan(Span);
+
+    impl RequestSpan {
+        pub(crate) fn new(request: &Request) -> Self {
+            let op = request
+                .opcode()
+                .operation()
+                .map_or("internal", Operation::name);
+            RequestSpan(tracing::debug_span!(
+                target: "tokio_zookeeper",
+                "zookeeper",
+                op,
+                path = request.path().unwrap_or(""),
+                xid = field::Empty,
+            ))
+        }
+
+        pub(crate) fn sent(&self, xid: i32) {
+            self.0.record("xid", xid);
+        }
+
+        pub(crate) fn completed(self, error: Option<ZkError>) {
+            let _enter = self.0.enter();
+            match error {
+                Some(e) => tracing::debug!(target: "tokio_zookeeper", error = ?e, "request failed"),
+                None => tracing::trace!(target: "tokio_zookeeper", "request completed"),
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+mod imp {
+    use super::super::{Request, ZkError};
+
+    #[derive(Debug)]
+    pub(crate) struct RequestSpan;
+
+    impl RequestSpan {
+        #[inline]
+        pub(crate) fn new(_: &Request) -> Self {
+            RequestSpan
+        }
+
+        #[inline]
+        pub(crate) fn sent(&self, _: i32) {}
+
+        #[inline]
+        pub(crate) fn completed(self, _: Option<ZkError>) {}
+    }
+}
+
+pub(crate) use self::imp::RequestSpan;
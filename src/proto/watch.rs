@@ -1,11 +1,127 @@
-use futures::sync::oneshot;
-use WatchedEvent;
+use futures::channel::{mpsc, oneshot};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use super::stats::SharedStats;
+use super::Options;
+use crate::logging::Logger;
+use crate::metrics::Metrics;
+use crate::namespace::Namespace;
+use crate::{WatchedEvent, WatchedEventStream, WatchedEventType};
 
 #[derive(Debug)]
 pub(crate) enum Watch {
     None,
     Global,
-    Custom(oneshot::Sender<WatchedEvent>),
+    /// A watch whose event is sent on the given channel, with its path relative to the namespace
+    /// it was set in.
+    Custom(oneshot::Sender<WatchedEvent>, Namespace),
+    /// A watch whose event is sent to the default watcher of the handle with the given id, see
+    /// `HandleWatchers`, with its path relative to the namespace it was set in.
+    Handle(usize, Namespace),
+    /// A persistent watch, set with `AddWatch`, whose events are sent on the given channel for as
+    /// long as it is open.
+    Persistent(PersistentSender, Namespace),
+}
+
+/// Where the events of a persistent watch go, along with the zxid of the change that fired them.
+///
+/// When the connection is lost, the channel is sent an event with no type and a
+/// [`KeeperState::Disconnected`](crate::KeeperState::Disconnected) state, since the changes made
+/// until the watch is set again on the next connection are missed.
+pub(crate) type PersistentSender = mpsc::UnboundedSender<(i64, WatchedEvent)>;
+
+/// Where the event of a one-shot watch goes once it fires, besides the session's default watcher.
+#[derive(Debug)]
+pub(crate) enum WatchSink {
+    /// Nowhere else, since it is a global watch.
+    Session,
+    /// To the channel of the watch.
+    Once(oneshot::Sender<WatchedEvent>),
+    /// To the default watcher of the handle with the given id.
+    Handle(usize),
+}
+
+/// Where the packetizer sends every watch event, along with when it was received.
+///
+/// Every event goes to the stream that was returned when connecting. Changes to the state of the
+/// session also go to the default watchers of the handles made with `ZooKeeper::with_own_watcher`,
+/// which end once this is dropped along with the packetizer.
+pub(crate) struct DefaultWatcher {
+    tx: mpsc::UnboundedSender<(WatchedEvent, Instant)>,
+    stats: Arc<SharedStats>,
+}
+
+impl DefaultWatcher {
+    pub(crate) fn new(
+        tx: mpsc::UnboundedSender<(WatchedEvent, Instant)>,
+        stats: Arc<SharedStats>,
+    ) -> Self {
+        DefaultWatcher { tx, stats }
+    }
+
+    pub(crate) fn unbounded_send(
+        &self,
+        event: (WatchedEvent, Instant),
+    ) -> Result<(), mpsc::TrySendError<(WatchedEvent, Instant)>> {
+        if event.0.event_type == WatchedEventType::None {
+            let mut handles = self.stats.handle_watchers.lock().unwrap();
+            handles
+                .senders
+                .retain(|_, tx| tx.unbounded_send(event.clone()).is_ok());
+        }
+        self.tx.unbounded_send(event)
+    }
+
+    /// Send `event` to the default watcher of the handle with the given id.
+    pub(super) fn send_to_handle(&self, id: usize, event: WatchedEvent) {
+        let mut handles = self.stats.handle_watchers.lock().unwrap();
+        if let Some(tx) = handles.senders.get(&id) {
+            if tx.unbounded_send((event, Instant::now())).is_err() {
+                handles.senders.remove(&id);
+            }
+        }
+    }
+}
+
+impl Drop for DefaultWatcher {
+    fn drop(&mut self) {
+        let mut handles = self.stats.handle_watchers.lock().unwrap();
+        handles.closed = true;
+        handles.senders.clear();
+    }
+}
+
+/// The default watchers of the handles made with `ZooKeeper::with_own_watcher`, by id.
+#[derive(Debug, Default)]
+pub(crate) struct HandleWatchers {
+    next_id: usize,
+    senders: HashMap<usize, mpsc::UnboundedSender<(WatchedEvent, Instant)>>,
+    /// Set once the packetizer is gone, after which no events will come.
+    closed: bool,
+    /// What the streams of the watchers report to, as configured for the session's own.
+    metrics: Metrics,
+    slow_threshold: Option<Duration>,
+}
+
+impl HandleWatchers {
+    pub(super) fn configure(&mut self, options: &Options) {
+        self.metrics = options.metrics.clone();
+        self.slow_threshold = options.slow_watch_threshold;
+    }
+
+    /// Add a default watcher, and return its id along with the stream of its events.
+    pub(super) fn add(&mut self, logger: Logger) -> (usize, WatchedEventStream) {
+        let (tx, rx) = mpsc::unbounded();
+        let id = self.next_id;
+        self.next_id += 1;
+        // a watcher added after the session is gone ends right away
+        if !self.closed {
+            self.senders.insert(id, tx);
+        }
+        let stream = WatchedEventStream::new(rx, logger, self.metrics.clone(), self.slow_threshold);
+        (id, stream)
+    }
 }
 
 impl Watch {
@@ -0,0 +1,494 @@
+//! A building block for proxies that sit between ZooKeeper clients and a server.
+//!
+//! A [`Proxy`] accepts connections from clients, and opens a connection to the server for each
+//! of them. It decodes every request that a client sends with the [`codec`], and hands it to a
+//! [`Handler`], which decides whether to send it on to the server, possibly rewritten, or to
+//! answer it with an error on the server's behalf. The handler also gets to rewrite the server's
+//! replies and watch events before the client sees them. This is enough to build a proxy that
+//! audits what its clients do, or that confines them to a part of the tree, without anything
+//! having to know the wire format.
+//!
+//! ```no_run
+//! use tokio_zookeeper::codec::Op;
+//! use tokio_zookeeper::proxy::{Client, Proxy, Verdict};
+//! use tokio_zookeeper::ZkError;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! // keep clients out of everything but `/app`
+//! let handler = |_: &Client, op: Op| match op {
+//!     Op::Create { ref path, .. } | Op::Delete { ref path, .. } | Op::SetData { ref path, .. }
+//!         if !path.starts_with("/app") =>
+//!     {
+//!         Verdict::Deny(ZkError::NoAuth)
+//!     }
+//!     op => Verdict::Forward(op),
+//! };
+//! let proxy = Proxy::new("127.0.0.1:2181".parse().unwrap(), handler);
+//! let listener = tokio::net::TcpListener::bind("127.0.0.1:2182").await?;
+//! proxy.run(listener).await
+//! # }
+//! ```
+//!
+//! The four-letter words that some clients send instead of a handshake are passed through as
+//! they are.
+
+use bytes::{Bytes, BytesMut};
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use crate::codec::frame::{frame, split_frame};
+use crate::codec::jute::{ReadFrom, WriteTo};
+use crate::codec::{self, ConnectResponse, Op, Reply, ReplyHeader, RequestHeader};
+use crate::logging::Logger;
+use crate::{Error, WatchedEvent, ZkError};
+
+/// The client on the other end of a proxied connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Client {
+    /// The address that the client connected from.
+    pub peer: SocketAddr,
+    /// The session that the server opened or resumed for the client.
+    pub session_id: i64,
+}
+
+/// What to do with a request that a client sent through a [`Proxy`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    /// Send the request to the server, as it was sent or rewritten.
+    Forward(Op),
+    /// Answer the request with the given error, without sending it to the server.
+    Deny(ZkError),
+}
+
+/// What a [`Proxy`] does with the traffic that passes through it.
+///
+/// Every request that a client sends once it is connected goes through [`Handler::request`],
+/// including the pings that keep its session alive and the request that closes it. Closures that
+/// take the client and the request implement the trait, with replies and events left alone.
+pub trait Handler: Send + Sync + 'static {
+    /// Decide what to do with the request `op` that `client` sent.
+    fn request(&self, client: &Client, op: Op) -> Verdict;
+
+    /// Rewrite the successful reply to a request with `opcode` before `client` sees it.
+    fn reply(&self, client: &Client, opcode: i32, reply: Reply) -> Reply {
+        let _ = (client, opcode);
+        reply
+    }
+
+    /// Rewrite a watch event before `client` sees it, or drop it by returning `None`.
+    fn event(&self, client: &Client, event: WatchedEvent) -> Option<WatchedEvent> {
+        let _ = client;
+        Some(event)
+    }
+}
+
+impl<F> Handler for F
+where
+    F: Fn(&Client, Op) -> Verdict + Send + Sync + 'static,
+{
+    fn request(&self, client: &Client, op: Op) -> Verdict {
+        self(client, op)
+    }
+}
+
+/// A proxy between ZooKeeper clients and the server at one address.
+pub struct Proxy<H> {
+    upstream: SocketAddr,
+    handler: Arc<H>,
+    logger: Logger,
+}
+
+impl<H> Clone for Proxy<H> {
+    fn clone(&self) -> Self {
+        Proxy {
+            upstream: self.upstream,
+            handler: Arc::clone(&self.handler),
+            logger: self.logger.clone(),
+        }
+    }
+}
+
+impl<H> fmt::Debug for Proxy<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Proxy")
+            .field("upstream", &self.upstream)
+            .finish()
+    }
+}
+
+impl<H: Handler> Proxy<H> {
+    /// A proxy that connects its clients to the server at `upstream`, with `handler` deciding
+    /// what to do with their traffic.
+    pub fn new(upstream: SocketAddr, handler: H) -> Self {
+        Proxy {
+            upstream,
+            handler: Arc::new(handler),
+            logger: Logger::default(),
+        }
+    }
+
+    /// Set the logger that the proxy logs the connections it serves to.
+    ///
+    /// This is only available with the `slog` feature, which is enabled by default.
+    #[cfg(feature = "slog")]
+    pub fn set_logger(&mut self, l: slog::Logger) {
+        self.logger = l.into();
+    }
+
+    /// Accept clients from `listener`, and serve each of them on a task of its own, until
+    /// accepting fails.
+    pub async fn run(&self, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy.serve(stream, peer).await {
+                    debug!(proxy.logger, "proxied connection failed: {}", e; "peer" => %peer);
+                }
+            });
+        }
+    }
+
+    /// Serve the client that connected from `peer` over `client`, until either it or the server
+    /// closes its connection.
+    pub async fn serve<C>(&self, client: C, peer: SocketAddr) -> Result<(), Error>
+    where
+        C: AsyncRead + AsyncWrite + Unpin + Send,
+    {
+        let upstream = TcpStream::connect(self.upstream).await?;
+        relay(client, upstream, peer, &self.handler, &self.logger).await
+    }
+}
+
+/// A reply that the client is owed, in the order that it sent the requests.
+#[derive(Debug)]
+enum Pending {
+    /// The request went to the server, which answers it.
+    Forwarded { xid: i32, opcode: i32 },
+    /// The request was denied, and is answered once everything before it has been.
+    Denied { xid: i32, err: ZkError },
+}
+
+struct State {
+    pending: VecDeque<Pending>,
+    /// The latest zxid that the server has answered with, which denials are answered with too.
+    zxid: i64,
+    client: Client,
+    tx: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl State {
+    fn send(&self, frame: Vec<u8>) {
+        let _ = self.tx.unbounded_send(frame);
+    }
+
+    fn deny(&self, xid: i32, err: ZkError) {
+        let zxid = self.zxid;
+        self.send(frame(|w| ReplyHeader { xid, zxid, err }.write_to(w)));
+    }
+}
+
+/// Read the next frame from `reader`, or `None` if it is closed between frames.
+async fn read_frame<R>(reader: &mut R, buf: &mut BytesMut) -> Result<Option<Bytes>, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    loop {
+        if let Some(frame) = split_frame(buf)? {
+            return Ok(Some(frame));
+        }
+        if reader.read_buf(buf).await? == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            return Err(Error::Protocol("connection closed inside a frame".to_string()));
+        }
+    }
+}
+
+/// Relay the traffic between `client` and the server over `upstream`.
+pub(crate) async fn relay<C, U, H>(
+    mut client: C,
+    mut upstream: U,
+    peer: SocketAddr,
+    handler: &Arc<H>,
+    logger: &Logger,
+) -> Result<(), Error>
+where
+    C: AsyncRead + AsyncWrite + Unpin + Send,
+    U: AsyncRead + AsyncWrite + Unpin + Send,
+    H: Handler,
+{
+    let mut from_client = BytesMut::new();
+    while from_client.len() < 4 {
+        if client.read_buf(&mut from_client).await? == 0 {
+            return Ok(());
+        }
+    }
+    // frames start with their length, which is never large enough to be spelled in letters
+    if from_client[..4].iter().all(u8::is_ascii_lowercase) {
+        trace!(logger, "passing through four-letter word"; "peer" => %peer);
+        upstream.write_all(&from_client).await?;
+        tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+        return Ok(());
+    }
+
+    // the handshake goes through as it is, and only tells the proxy which session it is for
+    let connect = match read_frame(&mut client, &mut from_client).await? {
+        Some(connect) => connect,
+        None => return Ok(()),
+    };
+    upstream.write_all(&frame(|w| w.extend_from_slice(&connect))).await?;
+    let mut from_upstream = BytesMut::new();
+    let connected = match read_frame(&mut upstream, &mut from_upstream).await? {
+        Some(connected) => connected,
+        None => return Ok(()),
+    };
+    let session_id = ConnectResponse::read(&connected)?.session_id;
+    client.write_all(&frame(|w| w.extend_from_slice(&connected))).await?;
+    debug!(logger, "proxying session"; "peer" => %peer, "session_id" => session_id);
+
+    let (tx, rx) = mpsc::unbounded();
+    let state = Mutex::new(State {
+        pending: VecDeque::new(),
+        zxid: 0,
+        client: Client { peer, session_id },
+        tx,
+    });
+    let (mut client_r, mut client_w) = tokio::io::split(client);
+    let (mut upstream_r, mut upstream_w) = tokio::io::split(upstream);
+
+    let requests = async {
+        while let Some(request) = read_frame(&mut client_r, &mut from_client).await? {
+            let mut r = &request[..];
+            let header = RequestHeader::read(&mut r)?;
+            let op = Op::read(header.opcode, &mut r)?;
+            let client = state.lock().unwrap().client;
+            match handler.request(&client, op) {
+                Verdict::Forward(op) => {
+                    let (xid, opcode) = (header.xid, op.opcode());
+                    // queued before it is sent, so that it is there by the time it is answered
+                    let pending = Pending::Forwarded { xid, opcode };
+                    state.lock().unwrap().pending.push_back(pending);
+                    let request = frame(|w| {
+                        RequestHeader { xid, opcode }.write_to(w);
+                        op.write_to(w);
+                    });
+                    upstream_w.write_all(&request).await?;
+                }
+                Verdict::Deny(err) => {
+                    trace!(logger, "denied request"; "peer" => %peer, "xid" => header.xid);
+                    let mut state = state.lock().unwrap();
+                    if state.pending.is_empty() {
+                        state.deny(header.xid, err);
+                    } else {
+                        state.pending.push_back(Pending::Denied {
+                            xid: header.xid,
+                            err,
+                        });
+                    }
+                }
+            }
+        }
+        Ok::<_, Error>(())
+    };
+
+    let replies = async {
+        while let Some(message) = read_frame(&mut upstream_r, &mut from_upstream).await? {
+            let mut r = &message[..];
+            let header = ReplyHeader::read(&mut r)?;
+            let mut state = state.lock().unwrap();
+            if header.xid == codec::NOTIFICATION_XID {
+                let event = WatchedEvent::read_from(&mut r)?;
+                if let Some(event) = handler.event(&state.client, event) {
+                    state.send(frame(|w| {
+                        header.write_to(w);
+                        event.write_to(&mut *w).unwrap();
+                    }));
+                }
+                continue;
+            }
+
+            if header.zxid > state.zxid {
+                state.zxid = header.zxid;
+            }
+            let opcode = match state.pending.front() {
+                Some(&Pending::Forwarded { xid, opcode }) if xid == header.xid => {
+                    state.pending.pop_front();
+                    Some(opcode)
+                }
+                _ => None,
+            };
+            let reply = match opcode {
+                Some(opcode) if header.err == ZkError::Ok => Reply::read(opcode, &mut r)
+                    .ok()
+                    .filter(|_| r.is_empty())
+                    .map(|reply| handler.reply(&state.client, opcode, reply)),
+                _ => None,
+            };
+            match reply {
+                Some(reply) => state.send(frame(|w| {
+                    header.write_to(w);
+                    reply.write_to(w);
+                })),
+                // whatever the proxy cannot make sense of goes to the client as it came
+                None => state.send(frame(|w| w.extend_from_slice(&message))),
+            }
+
+            while let Some(&Pending::Denied { xid, err }) = state.pending.front() {
+                state.pending.pop_front();
+                state.deny(xid, err);
+            }
+        }
+        Ok::<_, Error>(())
+    };
+
+    let relayed = async {
+        let relayed = futures::future::select(Box::pin(requests), Box::pin(replies))
+            .await
+            .factor_first()
+            .0;
+        state.lock().unwrap().tx.close_channel();
+        relayed
+    };
+    let written = async {
+        let mut rx = rx;
+        while let Some(frame) = rx.next().await {
+            client_w.write_all(&frame).await?;
+        }
+        client_w.shutdown().await
+    };
+    let (relayed, written) = futures::join!(relayed, written);
+    debug!(logger, "proxied session ended"; "peer" => %peer, "session_id" => session_id);
+    relayed?;
+    written.map_err(Error::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::DuplexStream;
+    use crate::proto::ZooKeeperTransport;
+    use crate::testing::MockZk;
+    use crate::{admin, Acl, CreateMode, ZooKeeper, ZooKeeperBuilder};
+
+    /// Connect a client to `server` through a proxy with `handler`.
+    async fn proxied<H: Handler>(server: &MockZk, handler: H) -> ZooKeeper {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (server, handler) = (server.clone(), Arc::new(handler));
+        tokio::spawn(async move {
+            let (client, peer) = listener.accept().await.unwrap();
+            let upstream = DuplexStream::connect(&server).await.unwrap();
+            relay(client, upstream, peer, &handler, &Logger::default()).await
+        });
+        let (zk, _, driver) = ZooKeeperBuilder::default()
+            .connect_with_driver(&addr)
+            .await
+            .unwrap();
+        tokio::spawn(driver);
+        zk
+    }
+
+    /// Confines clients to `/tenant`, which they see as the root, and keeps them out of
+    /// `/secret`.
+    struct Chroot;
+
+    impl Handler for Chroot {
+        fn request(&self, _: &Client, mut op: Op) -> Verdict {
+            match op {
+                Op::Create { ref mut path, .. }
+                | Op::Delete { ref mut path, .. }
+                | Op::Exists { ref mut path, .. }
+                | Op::GetData { ref mut path, .. }
+                | Op::SetData { ref mut path, .. } => {
+                    if path.starts_with("/secret") {
+                        return Verdict::Deny(ZkError::NoAuth);
+                    }
+                    path.insert_str(0, "/tenant");
+                }
+                _ => {}
+            }
+            Verdict::Forward(op)
+        }
+
+        fn reply(&self, _: &Client, _: i32, reply: Reply) -> Reply {
+            match reply {
+                Reply::Path(path) => Reply::Path(path["/tenant".len()..].to_string()),
+                reply => reply,
+            }
+        }
+
+        fn event(&self, _: &Client, mut event: WatchedEvent) -> Option<WatchedEvent> {
+            event.path.replace_range(.."/tenant".len(), "");
+            Some(event)
+        }
+    }
+
+    #[tokio::test]
+    async fn chroot() {
+        let server = MockZk::new();
+        let (direct, _) = server.connect().await.unwrap();
+        let tenant = direct.create("/tenant", &b""[..], Acl::open_unsafe(), CreateMode::Persistent);
+        tenant.await.unwrap().unwrap();
+        let zk = proxied(&server, Chroot).await;
+
+        let path = zk
+            .create("/a", &b"x"[..], Acl::open_unsafe(), CreateMode::Persistent)
+            .await
+            .unwrap();
+        assert_eq!(path.as_deref(), Ok("/a"));
+        assert!(direct.exists("/tenant/a").await.unwrap().is_some());
+
+        // a denied request is answered in its turn, between the ones around it
+        let (before, denied, after) = futures::join!(
+            zk.get_data("/a"),
+            zk.exists("/secret"),
+            zk.exists("/missing")
+        );
+        assert_eq!(before.unwrap().unwrap().0, b"x");
+        assert!(matches!(
+            denied,
+            Err(Error::Server {
+                error: ZkError::NoAuth,
+                ..
+            })
+        ));
+        assert_eq!(after.unwrap(), None);
+
+        let (stat, watch) = zk.exists_watch("/a").await.unwrap();
+        assert!(stat.is_some());
+        direct
+            .set_data("/tenant/a", None, &b"y"[..])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(watch.await.unwrap().path, "/a");
+    }
+
+    #[tokio::test]
+    async fn four_letter_words() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (upstream, mut server) = tokio::io::duplex(64);
+        let handler = Arc::new(|_: &Client, op: Op| Verdict::Forward(op));
+        tokio::spawn(async move {
+            let (client, peer) = listener.accept().await.unwrap();
+            relay(client, upstream, peer, &handler, &Logger::default()).await
+        });
+        let server = async {
+            let mut command = [0; 4];
+            server.read_exact(&mut command).await.unwrap();
+            assert_eq!(&command, b"ruok");
+            server.write_all(b"imok").await.unwrap();
+            server.shutdown().await.unwrap();
+        };
+        let (ok, ()) = futures::join!(admin::flw::ruok(&addr), server);
+        assert!(ok.unwrap());
+    }
+}
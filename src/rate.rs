@@ -0,0 +1,103 @@
+//! A token bucket that limits how fast a client sends requests.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use crate::runtime::{Runtime, Sleep};
+
+/// How fast a client may send requests to the server.
+///
+/// The client keeps a bucket of up to `burst` tokens, which fills up at `per_second` tokens a
+/// second, and every request takes a token out of it. Requests that find the bucket empty wait
+/// in the queue, in order, until it has filled up enough for them.
+///
+/// See [`ZooKeeperBuilder::set_rate_limit`](crate::ZooKeeperBuilder::set_rate_limit).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    /// How many requests a second the client may send once it has used up its burst.
+    pub per_second: f64,
+    /// How many requests the client may send at once after it has been idle.
+    pub burst: u32,
+}
+
+/// Holds requests back while the bucket of a [`RateLimit`] is empty.
+pub(crate) struct Limiter {
+    limit: RateLimit,
+    tokens: f64,
+    refilled: Instant,
+    runtime: Runtime,
+    /// Fires once the bucket has a token again.
+    timer: Option<Sleep>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limit: RateLimit, runtime: Runtime) -> Self {
+        Limiter {
+            limit,
+            tokens: f64::from(limit.burst),
+            refilled: Instant::now(),
+            runtime,
+            timer: None,
+        }
+    }
+
+    /// Resolve once there is a token for a request in the bucket.
+    ///
+    /// The token is only taken out with [`Limiter::take`], once there is a request to send.
+    pub(crate) fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            let now = Instant::now();
+            let refill = now.duration_since(self.refilled).as_secs_f64() * self.limit.per_second;
+            self.tokens = (self.tokens + refill).min(f64::from(self.limit.burst));
+            self.refilled = now;
+            if self.tokens >= 1.0 {
+                self.timer = None;
+                return Poll::Ready(());
+            }
+            let timer = match self.timer {
+                Some(ref mut timer) => timer,
+                None => {
+                    let wait = (1.0 - self.tokens) / self.limit.per_second;
+                    let wait = self.runtime.sleep(Duration::from_secs_f64(wait));
+                    self.timer.get_or_insert(wait)
+                }
+            };
+            match Pin::new(timer).poll(cx) {
+                Poll::Ready(()) => self.timer = None,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Take the token that [`Limiter::poll_ready`] found out of the bucket.
+    pub(crate) fn take(&mut self) {
+        self.tokens -= 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use std::time::Instant;
+    use super::*;
+    use crate::testing::MockZk;
+    use crate::ZooKeeperBuilder;
+
+    #[tokio::test]
+    async fn limits() {
+        let server = MockZk::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_rate_limit(Some(RateLimit {
+            per_second: 20.0,
+            burst: 1,
+        }));
+        let (zk, _) = builder.connect_mock(&server).await.unwrap();
+
+        // the bucket holds a single token, and refills one every 50ms
+        let start = Instant::now();
+        let exists = future::join_all((0..5).map(|_| zk.exists("/"))).await;
+        assert!(exists.iter().all(|e| matches!(e, Ok(Some(_)))));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
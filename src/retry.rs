@@ -0,0 +1,390 @@
+//! Retrying operations that failed because of the connection to the server.
+//!
+//! [`ZooKeeper::with_retry`] returns a proxy whose operations are retried according to a
+//! [`RetryPolicy`] when they fail in a way that retrying may fix. Reads are retried after a
+//! [`Error::ConnectionLoss`], [`Error::NotSent`], [`Error::BrokenCircuit`], or [`Error::Timeout`].
+//! Writes are only retried after an [`Error::NotSent`], such as when the connection was lost
+//! before the write left the client, or an [`Error::BrokenCircuit`], since a write that was sent
+//! may have taken effect even if its response was lost, and repeating it could then fail, or
+//! worse, succeed a second time.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use tokio_zookeeper::retry::ExponentialBackoff;
+//! # async fn run(zk: ZooKeeper) -> Result<(), Error> {
+//! let data = zk
+//!     .with_retry(ExponentialBackoff::default())
+//!     .get_data("/config")
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::{error, Acl, CreateMode, Error, Stat, ZkPath, ZooKeeper};
+
+/// Decides whether, and when, to retry an operation that failed.
+pub trait RetryPolicy: Send + Sync {
+    /// Return how long to wait before retrying an operation that has been retried `retries` times
+    /// so far, or `None` to give up and fail with the last error.
+    fn next_delay(&self, retries: u32) -> Option<Duration>;
+}
+
+/// Retry with a delay that doubles with every retry, up to a maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExponentialBackoff {
+    /// The delay before the first retry.
+    ///
+    /// Defaults to 100 milliseconds.
+    pub base: Duration,
+
+    /// The longest delay between two attempts.
+    ///
+    /// Defaults to 10 seconds.
+    pub max_delay: Duration,
+
+    /// How many times to retry an operation before giving up.
+    ///
+    /// Defaults to 5.
+    pub max_retries: u32,
+}
+
+impl Default for ExponentialBackoff {
+    fn default() -> Self {
+        ExponentialBackoff {
+            base: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            max_retries: 5,
+        }
+    }
+}
+
+impl RetryPolicy for ExponentialBackoff {
+    fn next_delay(&self, retries: u32) -> Option<Duration> {
+        if retries >= self.max_retries {
+            return None;
+        }
+        let delay = self
+            .base
+            .checked_mul(1 << retries.min(31))
+            .unwrap_or(self.max_delay);
+        Some(delay.min(self.max_delay))
+    }
+}
+
+/// Retry a fixed number of times, with the same delay before every retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundedRetries {
+    /// How many times to retry an operation before giving up.
+    pub retries: u32,
+
+    /// The delay before every retry.
+    pub delay: Duration,
+}
+
+impl RetryPolicy for BoundedRetries {
+    fn next_delay(&self, retries: u32) -> Option<Duration> {
+        if retries < self.retries {
+            Some(self.delay)
+        } else {
+            None
+        }
+    }
+}
+
+/// Retry until the operation no longer fails in a way that retrying may fix, with the same delay
+/// before every retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryForever {
+    /// The delay before every retry.
+    pub delay: Duration,
+}
+
+impl RetryPolicy for RetryForever {
+    fn next_delay(&self, _: u32) -> Option<Duration> {
+        Some(self.delay)
+    }
+}
+
+/// Proxy for [`ZooKeeper`] that retries operations according to a [`RetryPolicy`].
+///
+/// See the [module documentation](index.html) for which failures are retried.
+#[derive(Clone)]
+pub struct WithRetry<'a> {
+    zk: &'a ZooKeeper,
+    policy: Arc<dyn RetryPolicy>,
+}
+
+impl fmt::Debug for WithRetry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WithRetry").field("zk", &self.zk).finish()
+    }
+}
+
+impl ZooKeeper {
+    /// Retry the next chained operation according to `policy` if it fails in a way that retrying
+    /// may fix.
+    pub fn with_retry<P>(&self, policy: P) -> WithRetry<'_>
+    where
+        P: RetryPolicy + 'static,
+    {
+        WithRetry {
+            zk: self,
+            policy: Arc::new(policy),
+        }
+    }
+}
+
+impl<'a> WithRetry<'a> {
+    /// Run `op` until it succeeds, or fails in a way that `retryable` says retrying cannot fix, or
+    /// the policy gives up, or the session has ended and no attempt can succeed any more.
+    async fn run<T, F, R>(&self, retryable: fn(&Error) -> bool, mut op: F) -> Result<T, Error>
+    where
+        F: FnMut(&'a ZooKeeper) -> R,
+        R: Future<Output = Result<T, Error>>,
+    {
+        let mut retries = 0;
+        loop {
+            let e = match op(self.zk).await {
+                Ok(r) => return Ok(r),
+                Err(e) => e,
+            };
+            match self.policy.next_delay(retries) {
+                // once the session is over, requests fail with `NotSent` for good
+                Some(_) if self.zk.state().is_terminal() => return Err(e),
+                Some(delay) if retryable(&e) => {
+                    debug!(self.zk.logger, "retrying operation: {}", e; "retries" => retries);
+                    self.zk.runtime.sleep(delay).await;
+                    retries += 1;
+                }
+                _ => return Err(e),
+            }
+        }
+    }
+
+    /// Run the read `op`, retrying it after any failure that retrying may fix.
+    async fn read<T, F, R>(&self, op: F) -> Result<T, Error>
+    where
+        F: FnMut(&'a ZooKeeper) -> R,
+        R: Future<Output = Result<T, Error>>,
+    {
+        self.run(
+            |e| {
+                matches!(
+                    *e,
+                    Error::ConnectionLoss | Error::NotSent | Error::BrokenCircuit | Error::Timeout
+                )
+            },
+            op,
+        )
+        .await
+    }
+
+    /// Run the write `op`, retrying it only if it was never sent to the server.
+    async fn write<T, F, R>(&self, op: F) -> Result<T, Error>
+    where
+        F: FnMut(&'a ZooKeeper) -> R,
+        R: Future<Output = Result<T, Error>>,
+    {
+        self.run(|e| matches!(*e, Error::NotSent | Error::BrokenCircuit), op)
+            .await
+    }
+
+    /// See [`ZooKeeper::exists`].
+    pub async fn exists<P>(&self, path: P) -> Result<Option<Stat>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.read(|zk| zk.exists(&path)).await
+    }
+
+    /// See [`ZooKeeper::get_children`].
+    pub async fn get_children<P>(&self, path: P) -> Result<Option<Vec<String>>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.read(|zk| zk.get_children(&path)).await
+    }
+
+    /// See [`ZooKeeper::get_data`].
+    pub async fn get_data<P>(&self, path: P) -> Result<Option<(Vec<u8>, Stat)>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.read(|zk| zk.get_data(&path)).await
+    }
+
+    /// See [`ZooKeeper::get_acl`].
+    pub async fn get_acl<P>(
+        &self,
+        path: P,
+    ) -> Result<Result<(Vec<Acl>, Stat), error::GetAcl>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.read(|zk| zk.get_acl(&path)).await
+    }
+
+    /// See [`ZooKeeper::create`].
+    pub async fn create<P, D, A>(
+        &self,
+        path: P,
+        data: D,
+        acl: A,
+        mode: CreateMode,
+    ) -> Result<Result<String, error::Create>, Error>
+    where
+        P: AsRef<str>,
+        D: Into<Cow<'static, [u8]>>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        let path = path.as_ref();
+        let (data, acl) = (data.into(), acl.into());
+        self.write(|zk| zk.create(path, data.clone(), acl.clone(), mode))
+            .await
+    }
+
+    /// See [`ZooKeeper::set_data`].
+    pub async fn set_data<P, D>(
+        &self,
+        path: P,
+        version: Option<i32>,
+        data: D,
+    ) -> Result<Result<Stat, error::SetData>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+        D: Into<Cow<'static, [u8]>>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let data = data.into();
+        self.write(|zk| zk.set_data(&path, version, data.clone()))
+            .await
+    }
+
+    /// See [`ZooKeeper::delete`].
+    pub async fn delete<P>(
+        &self,
+        path: P,
+        version: Option<i32>,
+    ) -> Result<Result<(), error::Delete>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        self.write(|zk| zk.delete(&path, version)).await
+    }
+
+    /// See [`ZooKeeper::set_acl`].
+    pub async fn set_acl<P, A>(
+        &self,
+        path: P,
+        acl: A,
+        version: Option<i32>,
+    ) -> Result<Result<Stat, error::SetAcl>, Error>
+    where
+        P: TryInto<ZkPath>,
+        P::Error: Into<Error>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        let path = path.try_into().map_err(Into::into)?;
+        let acl = acl.into();
+        self.write(|zk| zk.set_acl(&path, acl.clone(), version))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Faults, MockZk};
+    use crate::ZooKeeperBuilder;
+
+    #[test]
+    fn exponential_backoff() {
+        let policy = ExponentialBackoff {
+            base: Duration::from_millis(100),
+            max_delay: Duration::from_millis(450),
+            max_retries: 4,
+        };
+        let delays: Vec<_> = (0..5).map(|n| policy.next_delay(n)).collect();
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(100)),
+                Some(Duration::from_millis(200)),
+                Some(Duration::from_millis(400)),
+                Some(Duration::from_millis(450)),
+                None,
+            ]
+        );
+        // the delay does not overflow however many times the operation is retried
+        let forever = ExponentialBackoff {
+            max_retries: u32::MAX,
+            ..policy
+        };
+        assert_eq!(forever.next_delay(100), Some(Duration::from_millis(450)));
+    }
+
+    #[test]
+    fn bounded_retries() {
+        let delay = Duration::from_millis(10);
+        let policy = BoundedRetries { retries: 2, delay };
+        assert_eq!(policy.next_delay(0), Some(delay));
+        assert_eq!(policy.next_delay(1), Some(delay));
+        assert_eq!(policy.next_delay(2), None);
+        assert_eq!(RetryForever { delay }.next_delay(1000), Some(delay));
+    }
+
+    #[tokio::test]
+    async fn ended_session() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        assert!(server.expire_session(zk.stats().session_id));
+        while !zk.state().is_terminal() {
+            tokio::task::yield_now().await;
+        }
+
+        let retrying = zk.with_retry(RetryForever {
+            delay: Duration::from_millis(1),
+        });
+        let read = tokio::time::timeout(Duration::from_secs(5), retrying.exists("/r")).await;
+        assert!(matches!(read, Ok(Err(Error::NotSent))));
+        let write = retrying.delete("/r", None);
+        let write = tokio::time::timeout(Duration::from_secs(5), write).await;
+        assert!(matches!(write, Ok(Err(Error::NotSent))));
+    }
+
+    #[tokio::test]
+    async fn unsent_write() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let builder = ZooKeeperBuilder::default();
+        let (zk, _) = builder.connect_mock_faulty(&server, &faults).await.unwrap();
+        let retrying = zk.with_retry(RetryForever {
+            delay: Duration::from_millis(1),
+        });
+
+        // the connection is found lost before the create is written out, so it is sent again
+        faults.disconnect();
+        let acl = Acl::open_unsafe();
+        let created = retrying.create("/w", &b""[..], acl, CreateMode::Persistent);
+        let created = tokio::time::timeout(Duration::from_secs(5), created).await;
+        assert_eq!(created.unwrap().unwrap().as_deref(), Ok("/w"));
+        assert_eq!(faults.connections(), 2);
+    }
+}
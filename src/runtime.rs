@@ -0,0 +1,95 @@
+//! The async runtimes that a connection can run on.
+//!
+//! The packetizer, and the helpers that wait or run in the background, get their timers and
+//! tasks from the runtime that the connection was made with, so that a connection made with
+//! async-std or smol never needs a Tokio runtime to be running.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// A timer that resolves once its duration has passed, and that can be set again.
+pub(crate) struct Sleep(Timer);
+
+enum Timer {
+    Tokio(Pin<Box<tokio::time::Sleep>>),
+    #[cfg(feature = "async-std")]
+    AsyncStd(Pin<Box<dyn Future<Output = ()> + Send>>),
+    #[cfg(feature = "smol")]
+    Smol(smol::Timer),
+}
+
+impl Sleep {
+    /// Set the timer to resolve after `duration` instead, whether or not it already has.
+    ///
+    /// This reuses the timer rather than allocating a new one, except on async-std, whose timers
+    /// cannot be set again.
+    pub(crate) fn reset(&mut self, duration: Duration) {
+        match self.0 {
+            Timer::Tokio(ref mut sleep) => {
+                sleep.as_mut().reset(tokio::time::Instant::now() + duration);
+            }
+            #[cfg(feature = "async-std")]
+            Timer::AsyncStd(ref mut sleep) => *sleep = Box::pin(async_std::task::sleep(duration)),
+            #[cfg(feature = "smol")]
+            Timer::Smol(ref mut timer) => timer.set_after(duration),
+        }
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match self.0 {
+            Timer::Tokio(ref mut sleep) => sleep.as_mut().poll(cx),
+            #[cfg(feature = "async-std")]
+            Timer::AsyncStd(ref mut sleep) => sleep.as_mut().poll(cx),
+            #[cfg(feature = "smol")]
+            Timer::Smol(ref mut timer) => Pin::new(timer).poll(cx).map(drop),
+        }
+    }
+}
+
+/// The runtime that a connection's timers and background tasks come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub(crate) enum Runtime {
+    #[default]
+    Tokio,
+    #[cfg(feature = "async-std")]
+    AsyncStd,
+    #[cfg(feature = "smol")]
+    Smol,
+}
+
+impl Runtime {
+    /// Return a timer that resolves after `duration`.
+    pub(crate) fn sleep(self, duration: Duration) -> Sleep {
+        Sleep(match self {
+            Runtime::Tokio => Timer::Tokio(Box::pin(tokio::time::sleep(duration))),
+            #[cfg(feature = "async-std")]
+            Runtime::AsyncStd => Timer::AsyncStd(Box::pin(async_std::task::sleep(duration))),
+            #[cfg(feature = "smol")]
+            Runtime::Smol => Timer::Smol(smol::Timer::after(duration)),
+        })
+    }
+
+    /// Run `task` in the background until it completes.
+    pub(crate) fn spawn<F>(self, task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match self {
+            Runtime::Tokio => {
+                tokio::spawn(task);
+            }
+            #[cfg(feature = "async-std")]
+            Runtime::AsyncStd => {
+                async_std::task::spawn(task);
+            }
+            #[cfg(feature = "smol")]
+            Runtime::Smol => smol::spawn(task).detach(),
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Utilities for working with sequential znodes.
+//!
+//! Nodes created with [`CreateMode::PersistentSequential`](../enum.CreateMode.html) or
+//! [`CreateMode::EphemeralSequential`](../enum.CreateMode.html) have a 10-digit, zero-padded
+//! sequence number appended to their name by the server. Most coordination recipes (locks,
+//! queues, leader election) need to extract that number again, order sibling nodes by it, and
+//! locate the node they created themselves. The functions here do so consistently.
+//!
+//! A sequential create whose response is lost, for instance because the connection to the server
+//! failed, leaves the client without any way of telling which node it created. Recipes should
+//! therefore create sequential nodes with [`ZooKeeper::create_protected`], which embeds a GUID in
+//! the node's name and uses it to find the node again.
+//!
+//! ```
+//! # use tokio_zookeeper::sequential;
+//! let mut children = vec!["lock-0000000003", "lock-0000000001", "lock-0000000002"];
+//! sequential::sort_by_sequence(&mut children);
+//! assert_eq!(children, ["lock-0000000001", "lock-0000000002", "lock-0000000003"]);
+//! assert_eq!(sequential::sequence_number("/locks/lock-0000000002"), Some(2));
+//! assert_eq!(
+//!     sequential::predecessor(&children, "lock-0000000002"),
+//!     Some(&"lock-0000000001")
+//! );
+//! ```
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use crate::subtree::join;
+use uuid::Uuid;
+use crate::{error, Acl, CreateMode, Error, ZooKeeper};
+
+/// The number of digits in the sequence suffix the server appends to sequential nodes.
+pub const SEQUENCE_DIGITS: usize = 10;
+
+/// The prefix of the names of nodes created by [`ZooKeeper::create_protected`]. It is followed by
+/// a GUID and a `-`, and then by the requested name.
+pub const PROTECTED_PREFIX: &str = "_c_";
+
+/// The length of the GUID in a protected node name, in its hyphenated form.
+const GUID_LEN: usize = 36;
+
+/// How many times [`ZooKeeper::create_protected`] sends its create request before giving up.
+const PROTECTED_CREATE_ATTEMPTS: usize = 3;
+
+/// Split a sequential node name (or path) into the prefix given at creation time and the
+/// sequence number the server appended to it.
+///
+/// Returns `None` if `name` does not end with a sequence suffix.
+pub fn split(name: &str) -> Option<(&str, i32)> {
+    if name.len() < SEQUENCE_DIGITS || !name.is_char_boundary(name.len() - SEQUENCE_DIGITS) {
+        return None;
+    }
+    let (prefix, suffix) = name.split_at(name.len() - SEQUENCE_DIGITS);
+    // the server formats the (signed) counter with %010d, so it may be negative after overflow
+    let digits = suffix.trim_start_matches('-');
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    suffix.parse().ok().map(|seq| (prefix, seq))
+}
+
+/// Extract the sequence number from a sequential node name (or path).
+///
+/// Returns `None` if `name` does not end with a sequence suffix.
+pub fn sequence_number(name: &str) -> Option<i32> {
+    split(name).map(|(_, seq)| seq)
+}
+
+fn compare<S: AsRef<str>>(a: &S, b: &S) -> Ordering {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    match (sequence_number(a), sequence_number(b)) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.cmp(b)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+/// Sort node names by their sequence number.
+///
+/// Nodes with equal sequence numbers are ordered by name. Nodes without a sequence suffix are
+/// placed after all sequential nodes.
+pub fn sort_by_sequence<S: AsRef<str>>(children: &mut [S]) {
+    children.sort_by(compare);
+}
+
+/// Find the child whose name was created with the given `prefix`.
+///
+/// This is how a client locates "its" node among the children returned by `get_children` after
+/// a sequential create, for instance when the create's response was lost. If several children
+/// match, the one with the lowest sequence number is returned.
+pub fn find_by_prefix<'a, S: AsRef<str>>(children: &'a [S], prefix: &str) -> Option<&'a S> {
+    children
+        .iter()
+        .filter(|child| match split(child.as_ref()) {
+            Some((p, _)) => p == prefix,
+            None => false,
+        })
+        .min_by(|a, b| compare(*a, *b))
+}
+
+/// Find the sequential child that immediately precedes `name` in sequence order.
+///
+/// Lock and leader-election recipes watch this node to avoid the herd effect. Returns `None` if
+/// `name` has the lowest sequence number among `children`, or if `name` is not sequential.
+pub fn predecessor<'a, S: AsRef<str>>(children: &'a [S], name: &str) -> Option<&'a S> {
+    let seq = sequence_number(name)?;
+    children
+        .iter()
+        .filter(|child| match sequence_number(child.as_ref()) {
+            Some(s) => s < seq,
+            None => false,
+        })
+        .max_by(|a, b| compare(*a, *b))
+}
+
+/// Split a node name created by [`ZooKeeper::create_protected`] into its GUID and the name that
+/// was requested (including any sequence suffix).
+///
+/// Returns `None` if `name` is not a protected node name.
+pub fn split_protected(name: &str) -> Option<(Uuid, &str)> {
+    if !name.starts_with(PROTECTED_PREFIX) {
+        return None;
+    }
+    let rest = &name[PROTECTED_PREFIX.len()..];
+    if rest.len() <= GUID_LEN || !rest.is_char_boundary(GUID_LEN) {
+        return None;
+    }
+    let (guid, rest) = rest.split_at(GUID_LEN);
+    if !rest.starts_with('-') {
+        return None;
+    }
+    Uuid::parse_str(guid).ok().map(|guid| (guid, &rest[1..]))
+}
+
+/// Return the name that was requested for a node created by [`ZooKeeper::create_protected`],
+/// that is, `name` without its protection prefix. Other names are returned unchanged.
+pub fn unprotected(name: &str) -> &str {
+    split_protected(name).map_or(name, |(_, name)| name)
+}
+
+fn protected_name(guid: &Uuid, name: &str) -> String {
+    format!("{}{}-{}", PROTECTED_PREFIX, guid.hyphenated(), name)
+}
+
+impl ZooKeeper {
+    /// Create a node like [`ZooKeeper::create`], but in "protected" mode, so that it can be found
+    /// again if the response to the create request is lost.
+    ///
+    /// The node's name is prefixed with [`PROTECTED_PREFIX`] and a freshly generated GUID. If the
+    /// request then fails without a response from the server, for instance because the
+    /// connection was lost, the children of the node's parent are searched for the GUID. If a
+    /// match is found, its path is returned just as if the create had succeeded; otherwise the
+    /// create is retried. The future only resolves with an error if the node can neither be found
+    /// nor created after several attempts.
+    ///
+    /// This is primarily useful for sequential nodes, which would otherwise be leaked, but works
+    /// with any `mode`. Use [`unprotected`] to recover the requested name from the created one.
+    pub async fn create_protected<D, A>(
+        &self,
+        path: &str,
+        data: D,
+        acl: A,
+        mode: CreateMode,
+    ) -> Result<Result<String, error::Create>, Error>
+    where
+        D: Into<Cow<'static, [u8]>>,
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        trace!(self.logger, "create_protected"; "path" => path, "mode" => ?mode);
+        let guid = Uuid::new_v4();
+        let (parent, name, target) = match path.rfind('/') {
+            Some(i) => {
+                let parent = if i == 0 { "/" } else { &path[..i] };
+                let name = protected_name(&guid, &path[i + 1..]);
+                let target = join(parent, &name);
+                (parent.to_string(), name, target)
+            }
+            // not a valid path; let the server reject it
+            None => (String::new(), String::new(), path.to_string()),
+        };
+        let (data, acl) = (data.into(), acl.into());
+        let sequential = matches!(
+            mode,
+            CreateMode::PersistentSequential | CreateMode::EphemeralSequential
+        );
+
+        let mut attempt = 1;
+        loop {
+            let e = match self.create(&target, data.clone(), acl.clone(), mode).await {
+                Ok(res) => return Ok(res),
+                Err(e) => e,
+            };
+            let children = match self.get_children(&parent).await {
+                Ok(children) => children,
+                // still cannot reach the server
+                Err(_) => return Err(e),
+            };
+            let created = children.and_then(|children| {
+                if sequential {
+                    find_by_prefix(&children, &name).cloned()
+                } else {
+                    children.into_iter().find(|child| *child == name)
+                }
+            });
+            match created {
+                Some(child) => return Ok(Ok(join(&parent, &child))),
+                None if attempt < PROTECTED_CREATE_ATTEMPTS => attempt += 1,
+                None => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_names() {
+        assert_eq!(split("lock-0000000042"), Some(("lock-", 42)));
+        assert_eq!(split("/a/b/0000000000"), Some(("/a/b/", 0)));
+        assert_eq!(split("x-2147483647"), Some(("x-", 2_147_483_647)));
+        assert_eq!(split("x--000000001"), Some(("x-", -1)));
+        assert_eq!(split("lock-42"), None);
+        assert_eq!(split("lock-00000000x1"), None);
+        assert_eq!(split("lock-+000000001"), None);
+        assert_eq!(split("l\u{e9}000000000"), None);
+    }
+
+    #[test]
+    fn sort() {
+        let mut children = vec![
+            String::from("b-0000000002"),
+            String::from("config"),
+            String::from("a-0000000003"),
+            String::from("a-0000000001"),
+        ];
+        sort_by_sequence(&mut children);
+        assert_eq!(
+            children,
+            ["a-0000000001", "b-0000000002", "a-0000000003", "config"]
+        );
+    }
+
+    #[test]
+    fn find() {
+        let children = ["_c_abc-lock-0000000007", "_c_def-lock-0000000003", "other"];
+        assert_eq!(
+            find_by_prefix(&children, "_c_abc-lock-"),
+            Some(&"_c_abc-lock-0000000007")
+        );
+        assert_eq!(find_by_prefix(&children, "_c_xyz-lock-"), None);
+        assert_eq!(find_by_prefix(&children, "oth"), None);
+    }
+
+    #[test]
+    fn protected_names() {
+        let guid = Uuid::parse_str("0f8fad5b-d9cb-469f-a165-70867728950e").unwrap();
+        let name = protected_name(&guid, "lock-0000000003");
+        assert_eq!(
+            name,
+            "_c_0f8fad5b-d9cb-469f-a165-70867728950e-lock-0000000003"
+        );
+        assert_eq!(split_protected(&name), Some((guid, "lock-0000000003")));
+        assert_eq!(unprotected(&name), "lock-0000000003");
+        assert_eq!(sequence_number(&name), Some(3));
+
+        assert_eq!(unprotected("lock-0000000003"), "lock-0000000003");
+        assert_eq!(split_protected("_c_not-a-guid-lock"), None);
+        assert_eq!(
+            split_protected("_c_0f8fad5b-d9cb-469f-a165-70867728950e"),
+            None
+        );
+        assert_eq!(
+            split_protected("_c_0f8fad5b-d9cb-469f-a165-70867728950e_x"),
+            None
+        );
+    }
+
+    #[test]
+    fn predecessors() {
+        let children = ["n-0000000005", "n-0000000001", "n-0000000009", "x"];
+        assert_eq!(
+            predecessor(&children, "n-0000000009"),
+            Some(&"n-0000000005")
+        );
+        assert_eq!(
+            predecessor(&children, "n-0000000005"),
+            Some(&"n-0000000001")
+        );
+        assert_eq!(predecessor(&children, "n-0000000001"), None);
+        assert_eq!(predecessor(&children, "x"), None);
+    }
+}
@@ -0,0 +1,163 @@
+//! A [`tower_service::Service`] over the ZooKeeper operations.
+//!
+//! `ZooKeeper` implements `Service<Request>`, so that standard middleware such as timeouts, rate
+//! limits, retries, or load shedding can be composed around the operations it supports. Each
+//! [`Request`] resolves with the [`Response`] variant of the same name, which holds what the
+//! corresponding method on [`ZooKeeper`] resolves with.
+//!
+//! ```no_run
+//! # use tokio_zookeeper::*;
+//! # use tokio_zookeeper::service::{Request, Response};
+//! # use tower_service::Service;
+//! # async fn run(mut zk: ZooKeeper) -> Result<(), Error> {
+//! futures::future::poll_fn(|cx| zk.poll_ready(cx)).await?;
+//! let response = zk
+//!     .call(Request::GetData {
+//!         path: "/config".to_string(),
+//!     })
+//!     .await?;
+//! if let Response::GetData(Some((data, _))) = response {
+//!     println!("{} bytes of config", data.len());
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! This module is only available with the `tower` feature enabled.
+
+use std::borrow::Cow;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tower_service::Service;
+use crate::{error, Acl, CreateMode, Error, Stat, ZooKeeper};
+
+/// An operation to run through [`ZooKeeper`]'s `Service` implementation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Request {
+    /// See [`ZooKeeper::exists`].
+    Exists {
+        /// The path of the node.
+        path: String,
+    },
+    /// See [`ZooKeeper::get_children`].
+    GetChildren {
+        /// The path of the node.
+        path: String,
+    },
+    /// See [`ZooKeeper::get_data`].
+    GetData {
+        /// The path of the node.
+        path: String,
+    },
+    /// See [`ZooKeeper::get_acl`].
+    GetAcl {
+        /// The path of the node.
+        path: String,
+    },
+    /// See [`ZooKeeper::create`].
+    Create {
+        /// The path of the node to create.
+        path: String,
+        /// The data of the new node.
+        data: Cow<'static, [u8]>,
+        /// The ACL of the new node.
+        acl: Cow<'static, [Acl]>,
+        /// The kind of node to create.
+        mode: CreateMode,
+    },
+    /// See [`ZooKeeper::set_data`].
+    SetData {
+        /// The path of the node.
+        path: String,
+        /// The version the node must have, if any.
+        version: Option<i32>,
+        /// The new data of the node.
+        data: Cow<'static, [u8]>,
+    },
+    /// See [`ZooKeeper::delete`].
+    Delete {
+        /// The path of the node.
+        path: String,
+        /// The version the node must have, if any.
+        version: Option<i32>,
+    },
+    /// See [`ZooKeeper::set_acl`].
+    SetAcl {
+        /// The path of the node.
+        path: String,
+        /// The new ACL of the node.
+        acl: Cow<'static, [Acl]>,
+        /// The ACL version the node must have, if any.
+        version: Option<i32>,
+    },
+}
+
+/// The outcome of a [`Request`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    /// The outcome of [`Request::Exists`].
+    Exists(Option<Stat>),
+    /// The outcome of [`Request::GetChildren`].
+    GetChildren(Option<Vec<String>>),
+    /// The outcome of [`Request::GetData`].
+    GetData(Option<(Vec<u8>, Stat)>),
+    /// The outcome of [`Request::GetAcl`].
+    GetAcl(Result<(Vec<Acl>, Stat), error::GetAcl>),
+    /// The outcome of [`Request::Create`].
+    Create(Result<String, error::Create>),
+    /// The outcome of [`Request::SetData`].
+    SetData(Result<Stat, error::SetData>),
+    /// The outcome of [`Request::Delete`].
+    Delete(Result<(), error::Delete>),
+    /// The outcome of [`Request::SetAcl`].
+    SetAcl(Result<Stat, error::SetAcl>),
+}
+
+impl Service<Request> for ZooKeeper {
+    type Response = Response;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Error>> + Send>>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+        // requests are queued without bound, so there is always room for one more until the
+        // connection has shut down for good
+        if self.connection.is_closed() {
+            Poll::Ready(Err(Error::NotSent))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let zk = self.clone();
+        Box::pin(async move {
+            let response = match request {
+                Request::Exists { path } => Response::Exists(zk.exists(&path).await?),
+                Request::GetChildren { path } => {
+                    Response::GetChildren(zk.get_children(&path).await?)
+                }
+                Request::GetData { path } => Response::GetData(zk.get_data(&path).await?),
+                Request::GetAcl { path } => Response::GetAcl(zk.get_acl(&path).await?),
+                Request::Create {
+                    path,
+                    data,
+                    acl,
+                    mode,
+                } => Response::Create(zk.create(&path, data, acl, mode).await?),
+                Request::SetData {
+                    path,
+                    version,
+                    data,
+                } => Response::SetData(zk.set_data(&path, version, data).await?),
+                Request::Delete { path, version } => {
+                    Response::Delete(zk.delete(&path, version).await?)
+                }
+                Request::SetAcl { path, acl, version } => {
+                    Response::SetAcl(zk.set_acl(&path, acl, version).await?)
+                }
+            };
+            Ok(response)
+        })
+    }
+}
@@ -0,0 +1,738 @@
+use futures::prelude::*;
+use futures::{future, stream};
+use std::borrow::Cow;
+use std::collections::{BTreeSet, VecDeque};
+use crate::{error, Acl, CreateMode, Error, ZooKeeper, ANY_VERSION};
+
+/// What [`ZooKeeper::copy_subtree`] should do when a destination node already exists.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnExisting {
+    /// Stop copying, and report [`error::CopySubtree::Create`] with `NodeExists`.
+    Fail,
+    /// Leave the existing node as it is, but continue copying its children.
+    Skip,
+    /// Replace the existing node's data (and ACL, if ACLs are copied) with that of the source.
+    Overwrite,
+}
+
+/// Options for [`ZooKeeper::copy_subtree`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct CopyOptions {
+    /// The ACL to give every copied node. If `None`, each node gets the ACL of its source node.
+    ///
+    /// Defaults to `None`.
+    pub acl: Option<Vec<Acl>>,
+
+    /// What to do when a destination node already exists.
+    ///
+    /// Defaults to [`OnExisting::Fail`].
+    pub on_existing: OnExisting,
+
+    /// Also copy ephemeral nodes. The copies are ephemeral nodes owned by this client's session.
+    ///
+    /// Defaults to `false`.
+    pub include_ephemerals: bool,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            acl: None,
+            on_existing: OnExisting::Fail,
+            include_ephemerals: false,
+        }
+    }
+}
+
+/// Options for [`ZooKeeper::move_subtree`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MoveOptions {
+    /// How the source subtree is copied to the destination.
+    ///
+    /// [`OnExisting::Skip`] is treated like [`OnExisting::Fail`], since the skipped source node
+    /// would otherwise be deleted without having been copied.
+    pub copy: CopyOptions,
+
+    /// Delete each source node in a `multi` transaction that also checks that its copy still
+    /// exists, so that no node is ever missing from both trees.
+    ///
+    /// Defaults to `false`.
+    pub transactional: bool,
+}
+
+/// The differences between two subtrees, as returned by [`ZooKeeper::diff_subtrees`].
+///
+/// All paths are relative to the roots of the two subtrees, but are written as absolute paths:
+/// the roots themselves are `/`, their children `/child`, and so on. Each list is sorted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubtreeDiff {
+    /// Nodes that exist only in the second subtree.
+    pub added: Vec<String>,
+    /// Nodes that exist only in the first subtree.
+    pub removed: Vec<String>,
+    /// Nodes that exist in both subtrees, but whose data (or ACL, if compared) differ.
+    pub modified: Vec<String>,
+}
+
+impl SubtreeDiff {
+    /// Whether the two subtrees were found to be identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+/// How many nodes the subtree operations read or write at a time by default.
+pub(crate) const CONCURRENCY: usize = 16;
+
+/// Options for [`ZooKeeper::set_acl_recursive`] and [`ZooKeeper::update_acl_recursive`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AclUpdateOptions {
+    /// How many nodes are read or updated at a time.
+    ///
+    /// Defaults to 16.
+    pub max_concurrent: usize,
+
+    /// Only report the changes that would be made, without making them.
+    ///
+    /// Defaults to `false`.
+    pub dry_run: bool,
+}
+
+impl Default for AclUpdateOptions {
+    fn default() -> Self {
+        AclUpdateOptions {
+            max_concurrent: CONCURRENCY,
+            dry_run: false,
+        }
+    }
+}
+
+/// A change to the ACL of a single node.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AclChange {
+    /// The path of the node.
+    pub path: String,
+    /// The node's ACL before the change.
+    pub old: Vec<Acl>,
+    /// The node's ACL after the change.
+    pub new: Vec<Acl>,
+}
+
+/// The outcome of [`ZooKeeper::update_acl_recursive`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AclUpdate {
+    /// The changes that were made (or, in a dry run, would have been made), in breadth-first
+    /// order.
+    pub changed: Vec<AclChange>,
+    /// The nodes whose ACL could not be changed, and why.
+    pub failed: Vec<(String, error::SetAcl)>,
+}
+
+/// Join a parent path and a child's node name.
+pub(crate) fn join(parent: &str, child: &str) -> String {
+    if parent == "/" {
+        format!("/{}", child)
+    } else {
+        format!("{}/{}", parent, child)
+    }
+}
+
+/// Map `path`, which is inside the subtree rooted at `from`, to the corresponding path in the
+/// subtree rooted at `to`.
+pub(crate) fn rebase(path: &str, from: &str, to: &str) -> String {
+    let relative = match from {
+        "/" if path == "/" => "",
+        "/" => path,
+        _ => &path[from.len()..],
+    };
+    match (to, relative) {
+        (_, "") => to.to_string(),
+        ("/", _) => relative.to_string(),
+        _ => format!("{}{}", to, relative),
+    }
+}
+
+/// Whether `path` is `root`, or a descendant of `root`.
+pub(crate) fn is_within(path: &str, root: &str) -> bool {
+    root == "/"
+        || path == root
+        || (path.starts_with(root) && path.as_bytes().get(root.len()) == Some(&b'/'))
+}
+
+/// The outcome of a single step of a subtree operation.
+type Phase<T, E> = Result<Result<T, E>, Error>;
+
+/// The outcome of copying a single node: the version of the source that was copied, if the
+/// destination was written to.
+type Step = Phase<Option<i32>, error::CopySubtree>;
+
+/// A source node that was copied: its path, the path of its copy, and the version that was copied.
+type Copied = (String, String, i32);
+
+impl ZooKeeper {
+    /// Return the paths of the node at the given `path` and all of its descendants, or `None` if
+    /// the node does not exist.
+    ///
+    /// The tree is walked breadth-first, so every node appears after its parent. Note that the
+    /// listing is not atomic: nodes that are created or deleted while the walk is in progress
+    /// may or may not be included.
+    pub async fn list_subtree(&self, path: &str) -> Result<Option<Vec<String>>, Error> {
+        trace!(self.logger, "list_subtree"; "path" => path);
+        let children = match self.get_children(path).await? {
+            Some(children) => children,
+            None => return Ok(None),
+        };
+        let mut queue: VecDeque<_> = children.iter().map(|c| join(path, c)).collect();
+        let mut paths = vec![path.to_string()];
+        while let Some(path) = queue.pop_front() {
+            // nodes that disappeared during the walk are left out
+            if let Some(children) = self.get_children(&path).await? {
+                queue.extend(children.iter().map(|c| join(&path, c)));
+                paths.push(path);
+            }
+        }
+        Ok(Some(paths))
+    }
+
+    /// Recursively copy the node at `src` and all of its descendants to `dst`.
+    ///
+    /// Each node's data is copied, and its ACL is either copied or set to the one given in
+    /// `options`. Ephemeral nodes are skipped unless `options.include_ephemerals` is set. On
+    /// success, the number of nodes that were created or overwritten at the destination is
+    /// returned.
+    ///
+    /// The parent of `dst` must already exist. The copy is not atomic: if it fails part-way
+    /// through, or the source is modified while it is being copied, the destination may end up
+    /// with a partial or mixed copy.
+    pub async fn copy_subtree(
+        &self,
+        src: &str,
+        dst: &str,
+        options: CopyOptions,
+    ) -> Result<Result<usize, error::CopySubtree>, Error> {
+        trace!(self.logger, "copy_subtree"; "src" => src, "dst" => dst);
+        let res = copy_tree(self, src, dst, options).await?;
+        Ok(res.map(|copied| copied.len()))
+    }
+
+    /// Compare the subtree rooted at `a` with the one rooted at `b`.
+    ///
+    /// Nodes are matched up by their paths relative to `a` and `b`, and two matching nodes are
+    /// considered equal if they hold the same data and, if `compare_acls` is set, the same ACL
+    /// entries (in any order). Stats such as versions and timestamps are ignored. A root that does
+    /// not exist is treated as an empty subtree.
+    ///
+    /// Like [`ZooKeeper::list_subtree`], the comparison is not atomic, so the subtrees should not
+    /// be modified while they are being compared.
+    pub async fn diff_subtrees(
+        &self,
+        a: &str,
+        b: &str,
+        compare_acls: bool,
+    ) -> Result<SubtreeDiff, Error> {
+        trace!(self.logger, "diff_subtrees"; "a" => a, "b" => b, "compare_acls" => compare_acls);
+        let (left, right) = future::try_join(self.list_subtree(a), self.list_subtree(b)).await?;
+        let relative = |paths: Option<Vec<String>>, root: &str| -> BTreeSet<String> {
+            paths
+                .unwrap_or_default()
+                .iter()
+                .map(|path| rebase(path, root, "/"))
+                .collect()
+        };
+        let (left, right) = (relative(left, a), relative(right, b));
+        let diff = SubtreeDiff {
+            added: right.difference(&left).cloned().collect(),
+            removed: left.difference(&right).cloned().collect(),
+            modified: Vec::new(),
+        };
+
+        let common: Vec<String> = left.intersection(&right).cloned().collect();
+        let mut diff = stream::iter(common)
+            .map(|path| async move {
+                let (x, y) = (rebase(&path, "/", a), rebase(&path, "/", b));
+                let (x, y) = future::try_join(
+                    node_state(self, &x, compare_acls),
+                    node_state(self, &y, compare_acls),
+                )
+                .await?;
+                Ok::<_, Error>((path, x, y))
+            })
+            .buffered(CONCURRENCY)
+            .try_fold(diff, |mut diff, (path, x, y)| {
+                match (x, y) {
+                    (Some(x), Some(y)) => {
+                        let acl_differs = match (x.1, y.1) {
+                            (Some(a), Some(b)) => !same_acl(&a, &b),
+                            _ => false,
+                        };
+                        if x.0 != y.0 || acl_differs {
+                            diff.modified.push(path);
+                        }
+                    }
+                    // deleted since we listed it
+                    (Some(_), None) => diff.removed.push(path),
+                    (None, Some(_)) => diff.added.push(path),
+                    (None, None) => {}
+                }
+                future::ok(diff)
+            })
+            .await?;
+        diff.added.sort();
+        diff.removed.sort();
+        Ok(diff)
+    }
+
+    /// Set the ACL of the node at the given `path` and of all of its descendants to `acl`.
+    ///
+    /// See [`ZooKeeper::update_acl_recursive`].
+    pub async fn set_acl_recursive<A>(
+        &self,
+        path: &str,
+        acl: A,
+        options: AclUpdateOptions,
+    ) -> Result<Option<AclUpdate>, Error>
+    where
+        A: Into<Cow<'static, [Acl]>>,
+    {
+        let acl = acl.into().into_owned();
+        self.update_acl_recursive(path, move |_, _| acl.clone(), options)
+            .await
+    }
+
+    /// Replace the ACL of the node at the given `path` and of all of its descendants with the
+    /// ACL returned by `f`, which is called with the path and current ACL of each node. Returns
+    /// `None` if the node at `path` does not exist.
+    ///
+    /// Nodes whose ACL `f` leaves unchanged (ignoring the order of its entries) are not written
+    /// to. Each update is conditional on the ACL version that was read, so a node whose ACL is
+    /// changed concurrently is reported in [`AclUpdate::failed`] with
+    /// [`error::SetAcl::BadVersion`] rather than being overwritten; a failure does not stop the
+    /// remaining nodes from being updated. With `options.dry_run`, the changes are only reported.
+    pub async fn update_acl_recursive<F>(
+        &self,
+        path: &str,
+        mut f: F,
+        options: AclUpdateOptions,
+    ) -> Result<Option<AclUpdate>, Error>
+    where
+        F: FnMut(&str, &[Acl]) -> Vec<Acl>,
+    {
+        trace!(self.logger, "update_acl_recursive"; "path" => path, "dry_run" => options.dry_run);
+        let max_concurrent = options.max_concurrent.max(1);
+        let dry_run = options.dry_run;
+        let paths = match self.list_subtree(path).await? {
+            Some(paths) => paths,
+            None => return Ok(None),
+        };
+
+        let update = stream::iter(paths)
+            .map(|path| async move {
+                let res = self.get_acl(&path).await?;
+                Ok::<_, Error>((path, res))
+            })
+            .buffered(max_concurrent)
+            .try_filter_map(|(path, res)| {
+                // nodes deleted since we listed them are left out
+                let change = res.ok().and_then(|(old, stat)| {
+                    let new = f(&path, &old);
+                    if same_acl(&old, &new) {
+                        return None;
+                    }
+                    Some((AclChange { path, old, new }, stat.aversion))
+                });
+                future::ok(change)
+            })
+            .map_ok(|(change, aversion)| async move {
+                if dry_run {
+                    return Ok((change, None));
+                }
+                let res = self
+                    .set_acl(&change.path, change.new.clone(), Some(aversion))
+                    .await?;
+                Ok((change, res.err()))
+            })
+            .try_buffered(max_concurrent)
+            .try_fold(AclUpdate::default(), |mut update, (change, res)| {
+                match res {
+                    None => update.changed.push(change),
+                    // deleted since we read its ACL
+                    Some(error::SetAcl::NoNode { .. }) => {}
+                    Some(e) => update.failed.push((change.path, e)),
+                }
+                future::ok(update)
+            })
+            .await?;
+        Ok(Some(update))
+    }
+
+    /// Recursively move the node at `src` and all of its descendants to `dst`.
+    ///
+    /// The subtree is first copied as by [`ZooKeeper::copy_subtree`]. Every copy is then read back
+    /// and compared with its source, and finally the source nodes are deleted, children before
+    /// their parents. Each delete is conditional on the source node still having the version
+    /// that was copied, so changes made to the source during the move are never lost; instead,
+    /// the move stops with [`error::MoveSubtree::Modified`]. With `options.transactional`, each
+    /// delete also atomically checks that the node's copy still exists. On success, the number
+    /// of nodes that were moved is returned.
+    ///
+    /// The move as a whole is not atomic. If it fails before any source node is deleted, the
+    /// destination may hold a partial copy; if it fails afterwards, the subtree is split between
+    /// the source and the destination. Ephemeral nodes that are not copied (see
+    /// [`CopyOptions::include_ephemerals`]) are left in place, which prevents their ancestors from
+    /// being deleted.
+    pub async fn move_subtree(
+        &self,
+        src: &str,
+        dst: &str,
+        options: MoveOptions,
+    ) -> Result<Result<usize, error::MoveSubtree>, Error> {
+        trace!(self.logger, "move_subtree"; "src" => src, "dst" => dst);
+        let MoveOptions {
+            mut copy,
+            transactional,
+        } = options;
+        if copy.on_existing == OnExisting::Skip {
+            copy.on_existing = OnExisting::Fail;
+        }
+
+        let copied = match copy_tree(self, src, dst, copy).await? {
+            Ok(copied) => copied,
+            Err(e) => {
+                let e = match e {
+                    error::CopySubtree::NoNode => error::MoveSubtree::NoNode,
+                    error::CopySubtree::Overlapping => error::MoveSubtree::Overlapping,
+                    e => error::MoveSubtree::Copy(e),
+                };
+                return Ok(Err(e));
+            }
+        };
+        match verify(self, copied).await? {
+            Ok(copied) => delete_sources(self, copied, transactional).await,
+            Err(e) => Ok(Err(e)),
+        }
+    }
+
+    /// Return the paths of the ephemeral nodes at or below the given `path` that are owned by the
+    /// session with the id `session_id`, or `None` if the node at `path` does not exist.
+    ///
+    /// Servers only list the ephemeral nodes of the session that asks, so this walks the subtree
+    /// instead, and reads the owner of each node off its [`Stat`](crate::Stat). That works for
+    /// any session, such as one whose client hung while holding locks, but takes a request or two
+    /// per node. Like [`ZooKeeper::list_subtree`], the walk is not atomic.
+    pub async fn list_ephemerals(
+        &self,
+        session_id: i64,
+        path: &str,
+    ) -> Result<Option<Vec<String>>, Error> {
+        trace!(self.logger, "list_ephemerals"; "session_id" => session_id, "path" => path);
+        let owned = ephemerals_of(self, session_id, path).await?;
+        Ok(owned.map(|owned| owned.into_iter().map(|(path, _)| path).collect()))
+    }
+
+    /// Delete the ephemeral nodes at or below the given `path` that are owned by the session with
+    /// the id `session_id`, and return the paths of the nodes that were deleted, or `None` if the
+    /// node at `path` does not exist.
+    ///
+    /// This cleans up after a client that cannot be stopped cleanly, before its session expires.
+    /// The session itself lives on until it does, and its client is not told that its nodes are
+    /// gone. The nodes are found as by [`ZooKeeper::list_ephemerals`], and each delete is
+    /// conditional on the version that was read, so a node that changed since is left alone and
+    /// left out of the result. With `dry_run`, the nodes are only listed.
+    pub async fn delete_ephemerals(
+        &self,
+        session_id: i64,
+        path: &str,
+        dry_run: bool,
+    ) -> Result<Option<Vec<String>>, Error> {
+        trace!(self.logger, "delete_ephemerals"; "session_id" => session_id, "dry_run" => dry_run);
+        let owned = match ephemerals_of(self, session_id, path).await? {
+            Some(owned) => owned,
+            None => return Ok(None),
+        };
+        let mut deleted = Vec::new();
+        for (path, version) in owned {
+            // the node may be gone already, with its session
+            if dry_run || self.delete(&path, Some(version)).await?.is_ok() {
+                deleted.push(path);
+            }
+        }
+        Ok(Some(deleted))
+    }
+}
+
+/// The paths and versions of the ephemeral nodes at or below `path` that are owned by
+/// `session_id`, in breadth-first order.
+async fn ephemerals_of(
+    zk: &ZooKeeper,
+    session_id: i64,
+    path: &str,
+) -> Result<Option<Vec<(String, i32)>>, Error> {
+    if zk.exists(path).await?.is_none() {
+        return Ok(None);
+    }
+    let mut owned = Vec::new();
+    let mut queue = VecDeque::from(vec![path.to_string()]);
+    while let Some(path) = queue.pop_front() {
+        // nodes that disappeared during the walk are left out
+        let stat = match zk.exists(&path).await? {
+            Some(stat) => stat,
+            None => continue,
+        };
+        if stat.owner_session() == Some(session_id) {
+            owned.push((path, stat.version));
+        } else if stat.num_children() > 0 {
+            // ephemeral nodes cannot have children, so only others are walked into
+            if let Some(children) = zk.get_children(&path).await? {
+                queue.extend(children.iter().map(|c| join(&path, c)));
+            }
+        }
+    }
+    Ok(Some(owned))
+}
+
+/// Read the data, and if `with_acl` is set the ACL, of the node at `path`.
+async fn node_state(
+    zk: &ZooKeeper,
+    path: &str,
+    with_acl: bool,
+) -> Result<Option<(Vec<u8>, Option<Vec<Acl>>)>, Error> {
+    let data = match zk.get_data(path).await? {
+        Some((data, _)) => data,
+        None => return Ok(None),
+    };
+    if !with_acl {
+        return Ok(Some((data, None)));
+    }
+    let res = zk.get_acl(path).await?;
+    Ok(res.ok().map(|(acl, _)| (data, Some(acl))))
+}
+
+/// Whether two ACLs hold the same entries, ignoring their order.
+fn same_acl(a: &[Acl], b: &[Acl]) -> bool {
+    a.len() == b.len() && a.iter().all(|e| b.contains(e)) && b.iter().all(|e| a.contains(e))
+}
+
+/// Copy the subtree at `src` to `dst`, resolving to the nodes that were written to, in
+/// breadth-first order.
+async fn copy_tree(
+    zk: &ZooKeeper,
+    src: &str,
+    dst: &str,
+    options: CopyOptions,
+) -> Phase<Vec<Copied>, error::CopySubtree> {
+    if is_within(dst, src) {
+        return Ok(Err(error::CopySubtree::Overlapping));
+    }
+
+    let paths = match zk.list_subtree(src).await? {
+        Some(paths) => paths,
+        None => return Ok(Err(error::CopySubtree::NoNode)),
+    };
+    let mut copied = Vec::new();
+    for path in paths {
+        let target = rebase(&path, src, dst);
+        match copy_node(zk, &path, &target, &options).await? {
+            Ok(Some(version)) => copied.push((path, target, version)),
+            Ok(None) => {}
+            Err(e) => return Ok(Err(e)),
+        }
+    }
+    Ok(Ok(copied))
+}
+
+/// Check that every copied node still has the version that was copied, and that its copy holds
+/// the same data.
+async fn verify(zk: &ZooKeeper, copied: Vec<Copied>) -> Phase<Vec<Copied>, error::MoveSubtree> {
+    for &(ref path, ref target, version) in &copied {
+        let (src, dst) = future::try_join(zk.get_data(path), zk.get_data(target)).await?;
+        match (src, dst) {
+            (Some((_, ref stat)), _) if stat.version != version => {
+                return Ok(Err(error::MoveSubtree::Modified { path: path.clone() }))
+            }
+            (None, _) => return Ok(Err(error::MoveSubtree::Modified { path: path.clone() })),
+            (Some((ref a, _)), Some((ref b, _))) if a == b => {}
+            _ => {
+                return Ok(Err(error::MoveSubtree::Mismatch {
+                    path: target.clone(),
+                }))
+            }
+        }
+    }
+    Ok(Ok(copied))
+}
+
+/// Delete the source of every copied node, children first.
+async fn delete_sources(
+    zk: &ZooKeeper,
+    copied: Vec<Copied>,
+    transactional: bool,
+) -> Phase<usize, error::MoveSubtree> {
+    let mut moved = 0;
+    // breadth-first order puts every node after its parent, so reversing it deletes leaves first
+    for (path, target, version) in copied.into_iter().rev() {
+        let res = if transactional {
+            let res = zk
+                .multi()
+                .check(&target, ANY_VERSION)
+                .delete(&path, Some(version))
+                .run()
+                .await?;
+            let e = res.into_iter().filter_map(Result::err).find(|e| {
+                !matches!(*e, error::Multi::RolledBack | error::Multi::Skipped)
+            });
+            e.map_or(Ok(()), Err)
+        } else {
+            zk.delete(&path, Some(version))
+                .await?
+                .map_err(error::Multi::Delete)
+        };
+        let e = match res {
+            // someone else already deleted it; that is fine, since it was copied
+            Ok(()) | Err(error::Multi::Delete(error::Delete::NoNode { .. })) => {
+                moved += 1;
+                continue;
+            }
+            Err(error::Multi::Delete(error::Delete::NotEmpty { .. })) => {
+                error::MoveSubtree::NotEmpty { path }
+            }
+            Err(error::Multi::Check(_)) => error::MoveSubtree::Mismatch { path: target },
+            Err(_) => error::MoveSubtree::Modified { path },
+        };
+        return Ok(Err(e));
+    }
+    Ok(Ok(moved))
+}
+
+/// Copy a single node, resolving to the version of the source that was copied if the destination
+/// was written to.
+async fn copy_node(zk: &ZooKeeper, src: &str, dst: &str, options: &CopyOptions) -> Step {
+    let (data, stat) = match zk.get_data(src).await? {
+        Some(res) => res,
+        // deleted since we listed it
+        None => return Ok(Ok(None)),
+    };
+    let mode = if stat.is_ephemeral() {
+        if !options.include_ephemerals {
+            return Ok(Ok(None));
+        }
+        CreateMode::Ephemeral
+    } else {
+        CreateMode::Persistent
+    };
+
+    let acl = match options.acl {
+        Some(ref acl) => acl.clone(),
+        None => match zk.get_acl(src).await? {
+            Ok((acl, _)) => acl,
+            // deleted since we read its data
+            Err(_) => return Ok(Ok(None)),
+        },
+    };
+
+    let version = stat.version;
+    let overwrite_acl = options.acl.is_none();
+    let data: Cow<'static, [u8]> = data.into();
+    match zk.create(dst, data.clone(), acl.clone(), mode).await? {
+        Ok(_) => Ok(Ok(Some(version))),
+        Err(error::Create::NodeExists { .. }) if options.on_existing == OnExisting::Skip => {
+            Ok(Ok(None))
+        }
+        Err(error::Create::NodeExists { .. }) if options.on_existing == OnExisting::Overwrite => {
+            overwrite(zk, dst, data, acl, overwrite_acl, version).await
+        }
+        Err(error) => Ok(Err(error::CopySubtree::Create {
+            path: dst.to_string(),
+            error,
+        })),
+    }
+}
+
+/// Replace the data (and, if `set_acl` is true, the ACL) of an existing node at `dst` with that
+/// of `version` of its source.
+async fn overwrite(
+    zk: &ZooKeeper,
+    dst: &str,
+    data: Cow<'static, [u8]>,
+    acl: Vec<Acl>,
+    set_acl: bool,
+    version: i32,
+) -> Step {
+    if let Err(error) = zk.set_data_any(dst, data).await? {
+        return Ok(Err(error::CopySubtree::SetData {
+            path: dst.to_string(),
+            error,
+        }));
+    }
+    if !set_acl {
+        return Ok(Ok(Some(version)));
+    }
+    match zk.set_acl(dst, acl, None).await? {
+        Ok(_) => Ok(Ok(Some(version))),
+        Err(error) => Ok(Err(error::CopySubtree::SetAcl {
+            path: dst.to_string(),
+            error,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockZk;
+
+    #[test]
+    fn paths() {
+        assert_eq!(join("/", "a"), "/a");
+        assert_eq!(join("/a", "b"), "/a/b");
+
+        assert_eq!(rebase("/a/b/c", "/a", "/x"), "/x/b/c");
+        assert_eq!(rebase("/a", "/a", "/x/y"), "/x/y");
+        assert_eq!(rebase("/a/b", "/", "/x"), "/x/a/b");
+        assert_eq!(rebase("/a/b", "/a", "/"), "/b");
+        assert_eq!(rebase("/", "/", "/x"), "/x");
+
+        assert!(is_within("/a/b", "/a"));
+        assert!(is_within("/a", "/a"));
+        assert!(is_within("/a", "/"));
+        assert!(!is_within("/ab", "/a"));
+        assert!(!is_within("/", "/a"));
+    }
+
+    #[tokio::test]
+    async fn ephemerals() {
+        let server = MockZk::new();
+        let (hung, _) = server.connect().await.unwrap();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/locks", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        for (client, path) in [(&hung, "/locks/a"), (&zk, "/locks/b"), (&hung, "/held")] {
+            let created = client.create(path, &b""[..], acl, CreateMode::Ephemeral).await;
+            created.unwrap().unwrap();
+        }
+        let id = hung.session().id;
+
+        let owned = zk.list_ephemerals(id, "/").await.unwrap();
+        assert_eq!(owned, Some(vec!["/held".to_string(), "/locks/a".to_string()]));
+        assert_eq!(zk.list_ephemerals(id, "/nope").await.unwrap(), None);
+
+        let deleted = zk.delete_ephemerals(id, "/locks", true).await.unwrap();
+        assert_eq!(deleted, Some(vec!["/locks/a".to_string()]));
+        assert!(zk.exists_bool("/locks/a").await.unwrap());
+        let deleted = zk.delete_ephemerals(id, "/locks", false).await.unwrap();
+        assert_eq!(deleted, Some(vec!["/locks/a".to_string()]));
+        assert!(!zk.exists_bool("/locks/a").await.unwrap());
+        assert!(zk.exists_bool("/locks/b").await.unwrap());
+        assert!(zk.exists_bool("/held").await.unwrap());
+    }
+
+    #[test]
+    fn acl_equality() {
+        let mut both = Acl::creator_all().to_vec();
+        both.extend(Acl::read_unsafe().iter().cloned());
+        let mut reversed = both.clone();
+        reversed.reverse();
+        assert!(same_acl(&both, &reversed));
+        assert!(!same_acl(&both, Acl::read_unsafe()));
+        assert!(!same_acl(&both[..1], &both[1..]));
+    }
+}
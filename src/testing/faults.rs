@@ -0,0 +1,713 @@
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream, ReadBuf};
+use crate::proto::{HostProvider, ZooKeeperTransport};
+use crate::runtime::{Runtime, Sleep};
+use crate::testing::MockZk;
+use crate::{Error, WatchedEventStream, ZooKeeper, ZooKeeperBuilder};
+
+#[derive(Default)]
+struct State {
+    delay: Option<Duration>,
+    /// How many more bytes may be read before the connection is cut.
+    cut_after: Option<usize>,
+    drop_requests: usize,
+    drop_responses: usize,
+    /// How many more bytes are read until the one to corrupt.
+    corrupt_at: Option<usize>,
+    connections: usize,
+    refuse_connects: usize,
+    /// The reader of the newest connection, to wake when a fault is scheduled for it.
+    reader: Option<Waker>,
+}
+
+/// The faults to inject into the connections made through a [`FaultyTransport`].
+///
+/// Clones refer to the same faults, so a test can keep one and schedule faults while its client
+/// runs. Each fault applies to whichever connection is current when it takes effect, so a fault
+/// that breaks a connection does not also break the connection that the client makes to recover.
+/// Bytes and frames are counted from when the fault was scheduled.
+#[derive(Clone, Default)]
+pub struct Faults(Arc<Mutex<State>>);
+
+impl fmt::Debug for Faults {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("Faults")
+            .field("delay", &state.delay)
+            .field("cut_after", &state.cut_after)
+            .field("drop_requests", &state.drop_requests)
+            .field("drop_responses", &state.drop_responses)
+            .field("corrupt_at", &state.corrupt_at)
+            .field("connections", &state.connections)
+            .field("refuse_connects", &state.refuse_connects)
+            .finish()
+    }
+}
+
+impl Faults {
+    /// Return a set of faults that does not inject any yet.
+    pub fn new() -> Self {
+        Faults::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap()
+    }
+
+    /// Run `f` on the faults, and wake the reader so that it notices the change.
+    fn schedule<F: FnOnce(&mut State)>(&self, f: F) {
+        let mut state = self.state();
+        f(&mut state);
+        if let Some(ref reader) = state.reader {
+            reader.wake_by_ref();
+        }
+    }
+
+    /// Hold back everything that is read from the server by `delay`, or stop doing so if `delay`
+    /// is `None`.
+    ///
+    /// Delays use Tokio's timer.
+    pub fn set_delay(&self, delay: Option<Duration>) {
+        self.schedule(|state| state.delay = delay);
+    }
+
+    /// Cut the connection once `bytes` more bytes have been read from the server, which usually
+    /// leaves a frame cut short.
+    pub fn disconnect_after(&self, bytes: usize) {
+        self.schedule(|state| state.cut_after = Some(bytes));
+    }
+
+    /// Cut the connection now.
+    pub fn disconnect(&self) {
+        self.disconnect_after(0);
+    }
+
+    /// Silently drop the next `n` frames that the client writes, so that the server never sees
+    /// those requests.
+    pub fn drop_requests(&self, n: usize) {
+        self.schedule(|state| state.drop_requests += n);
+    }
+
+    /// Silently drop the next `n` frames that the server writes, so that the client never sees
+    /// those responses or events.
+    pub fn drop_responses(&self, n: usize) {
+        self.schedule(|state| state.drop_responses += n);
+    }
+
+    /// Flip the bits of the byte that is read from the server `offset` bytes from now.
+    pub fn corrupt_at(&self, offset: usize) {
+        self.schedule(|state| state.corrupt_at = Some(offset));
+    }
+
+    /// Refuse the next `n` connections that the client tries to make, as a server that is down
+    /// would.
+    pub fn refuse_connects(&self, n: usize) {
+        self.schedule(|state| state.refuse_connects += n);
+    }
+
+    /// Stop injecting faults that have not taken effect yet, and any delay.
+    pub fn clear(&self) {
+        self.schedule(|state| {
+            state.delay = None;
+            state.refuse_connects = 0;
+            state.cut_after = None;
+            state.drop_requests = 0;
+            state.drop_responses = 0;
+            state.corrupt_at = None;
+        });
+    }
+
+    /// Return how many connections have been made with these faults.
+    pub fn connections(&self) -> usize {
+        self.state().connections
+    }
+}
+
+/// Tracks where the frames of one direction of a connection start and end, so that whole frames
+/// can be dropped without the other side losing track of them.
+#[derive(Default)]
+struct Frames {
+    header: [u8; 4],
+    /// How many bytes of the current frame's length have been seen.
+    have: usize,
+    /// How many bytes of the current frame's body are still to come.
+    remaining: usize,
+    /// Whether the current frame is being dropped, once that has been decided.
+    dropping: Option<bool>,
+}
+
+impl Frames {
+    /// Return whether the bytes at the start of `bytes` are kept, and how many of them share that
+    /// fate, deciding to drop the next frame if `drops` says to.
+    fn span(&mut self, bytes: &[u8], drops: &mut usize) -> (bool, usize) {
+        let dropping = *self.dropping.get_or_insert_with(|| {
+            let drop = *drops > 0;
+            if drop {
+                *drops -= 1;
+            }
+            drop
+        });
+        let len = if self.have < 4 {
+            4 - self.have
+        } else {
+            self.remaining
+        };
+        (!dropping, len.min(bytes.len()))
+    }
+
+    /// Move past `bytes`, which must lie within the span last returned by `span`.
+    fn advance(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            if self.have < 4 {
+                self.header[self.have] = b;
+                self.have += 1;
+                if self.have == 4 {
+                    self.remaining = i32::from_be_bytes(self.header).max(0) as usize;
+                }
+            } else {
+                self.remaining -= 1;
+            }
+        }
+        if self.have == 4 && self.remaining == 0 {
+            *self = Frames::default();
+        }
+    }
+}
+
+/// A transport that wraps the transport `S`, and injects the [`Faults`] it was made with.
+///
+/// Clients connect through it with [`ZooKeeperBuilder::connect_faulty`] or
+/// [`ZooKeeperBuilder::connect_mock_faulty`].
+///
+/// [`ZooKeeperBuilder::connect_faulty`]: crate::ZooKeeperBuilder::connect_faulty
+/// [`ZooKeeperBuilder::connect_mock_faulty`]: crate::ZooKeeperBuilder::connect_mock_faulty
+pub struct FaultyTransport<S> {
+    inner: S,
+    faults: Faults,
+    reads: Frames,
+    writes: Frames,
+    sleep: Option<Sleep>,
+    /// Set once the connection has been cut.
+    cut: bool,
+}
+
+impl<S> fmt::Debug for FaultyTransport<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FaultyTransport")
+            .field("faults", &self.faults)
+            .field("cut", &self.cut)
+            .finish()
+    }
+}
+
+fn cut() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionReset,
+        "connection cut by injected fault",
+    )
+}
+
+impl<S> FaultyTransport<S> {
+    /// Wrap `inner`, injecting `faults` into it.
+    pub fn new(inner: S, faults: Faults) -> Self {
+        {
+            let mut state = faults.state();
+            state.connections += 1;
+            state.reader = None;
+        }
+        FaultyTransport {
+            inner,
+            faults,
+            reads: Frames::default(),
+            writes: Frames::default(),
+            sleep: None,
+            cut: false,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultyTransport<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            let delay = {
+                let mut state = this.faults.state();
+                state.reader = Some(cx.waker().clone());
+                if this.cut || state.cut_after == Some(0) {
+                    state.cut_after = None;
+                    this.cut = true;
+                    return Poll::Ready(Err(cut()));
+                }
+                state.delay
+            };
+            if let Some(delay) = delay {
+                let sleep = this
+                    .sleep
+                    .get_or_insert_with(|| Runtime::Tokio.sleep(delay));
+                futures::ready!(Pin::new(sleep).poll(cx));
+            }
+
+            // read at most as much as may be read before the connection is cut
+            let mut want = buf.remaining();
+            if let Some(left) = this.faults.state().cut_after {
+                want = want.min(left);
+            }
+            let mut read = vec![0; want];
+            let mut read_buf = ReadBuf::new(&mut read);
+            futures::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read_buf))?;
+            this.sleep = None;
+            let read = read_buf.filled();
+            if read.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut state = this.faults.state();
+            if let Some(ref mut left) = state.cut_after {
+                *left -= read.len();
+            }
+            let mut kept = 0;
+            let mut at = 0;
+            while at < read.len() {
+                let (keep, len) = this.reads.span(&read[at..], &mut state.drop_responses);
+                let span = &read[at..at + len];
+                this.reads.advance(span);
+                if keep {
+                    let start = buf.filled().len();
+                    buf.put_slice(span);
+                    if let Some(offset) = state.corrupt_at {
+                        if offset < len {
+                            buf.filled_mut()[start + offset] ^= 0xff;
+                            state.corrupt_at = None;
+                        } else {
+                            state.corrupt_at = Some(offset - len);
+                        }
+                    }
+                    kept += len;
+                }
+                at += len;
+            }
+            if kept > 0 {
+                return Poll::Ready(Ok(()));
+            }
+            // everything that was read was dropped, so read again
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FaultyTransport<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.cut {
+            return Poll::Ready(Err(cut()));
+        }
+        let (keep, len) = {
+            let mut state = this.faults.state();
+            this.writes.span(buf, &mut state.drop_requests)
+        };
+        let written = if keep {
+            futures::ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]))?
+        } else {
+            len
+        };
+        this.writes.advance(&buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.cut {
+            return Poll::Ready(Err(cut()));
+        }
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<S> ZooKeeperTransport for FaultyTransport<S>
+where
+    S: ZooKeeperTransport + 'static,
+    S::ConnectError: Send,
+{
+    type Addr = (S::Addr, Faults);
+    type ConnectError = Error;
+    type ConnectFut = Pin<Box<dyn Future<Output = Result<Self, Error>> + Send>>;
+    fn connect(addr: &Self::Addr) -> Self::ConnectFut {
+        let faults = addr.1.clone();
+        let refused = {
+            let mut state = faults.state();
+            let refused = state.refuse_connects > 0;
+            state.refuse_connects = state.refuse_connects.saturating_sub(1);
+            refused
+        };
+        if refused {
+            let e = io::Error::new(io::ErrorKind::ConnectionRefused, "refused by injected fault");
+            return Box::pin(futures::future::ready(Err(Error::Io(e))));
+        }
+        let connect = S::connect(&addr.0);
+        Box::pin(async move {
+            let stream = connect.await.map_err(Into::into)?;
+            Ok(FaultyTransport::new(stream, faults))
+        })
+    }
+}
+
+impl ZooKeeperBuilder {
+    /// Connect to a ZooKeeper server instance at the given address, injecting `faults` into the
+    /// connection and into every connection that the client makes to recover from losing one.
+    ///
+    /// This is otherwise like [`ZooKeeperBuilder::connect`].
+    pub async fn connect_faulty(
+        self,
+        addr: &SocketAddr,
+        faults: &Faults,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        let hosts = HostProvider::new(vec![(*addr, faults.clone())]);
+        self.connect_spawned::<FaultyTransport<tokio::net::TcpStream>>(
+            hosts,
+            *addr,
+            Runtime::Tokio,
+        )
+        .await
+    }
+
+    /// Connect to the in-memory `server`, injecting `faults` into the connection and into every
+    /// connection that the client makes to recover from losing one.
+    ///
+    /// This is otherwise like [`ZooKeeperBuilder::connect_mock`].
+    pub async fn connect_mock_faulty(
+        self,
+        server: &MockZk,
+        faults: &Faults,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], 0));
+        let hosts = HostProvider::new(vec![(server.clone(), faults.clone())]);
+        self.connect_spawned::<FaultyTransport<DuplexStream>>(hosts, addr, Runtime::Tokio)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::time::Instant;
+    use crate::{Acl, CircuitBreaker, CreateMode, KeeperState, WatchedEventType};
+
+    async fn connect(server: &MockZk, faults: &Faults) -> (ZooKeeper, WatchedEventStream) {
+        ZooKeeperBuilder::default()
+            .connect_mock_faulty(server, faults)
+            .await
+            .unwrap()
+    }
+
+    /// Wait for the client to tell its default watcher that it has reconnected.
+    async fn reconnected(default_watcher: &mut WatchedEventStream) {
+        let mut states = Vec::new();
+        while states.last() != Some(&KeeperState::SyncConnected) {
+            let e = default_watcher.next().await.unwrap();
+            if e.event_type == WatchedEventType::None {
+                states.push(e.keeper_state);
+            }
+        }
+        assert_eq!(
+            states,
+            [KeeperState::Disconnected, KeeperState::SyncConnected]
+        );
+    }
+
+    #[tokio::test]
+    async fn cut_mid_frame() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, mut default_watcher) = connect(&server, &faults).await;
+        let (other, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/c", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let (changed, _, _) = zk.with_watcher().get_data("/c").await.unwrap().unwrap();
+
+        // the response is cut off after its length and xid
+        faults.disconnect_after(8);
+        assert!(matches!(zk.exists("/c").await, Err(Error::ConnectionLoss)));
+        reconnected(&mut default_watcher).await;
+        assert_eq!(faults.connections(), 2);
+
+        // the watch is set again on the new connection
+        other
+            .set_data("/c", None, &b"x"[..])
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            changed.await.unwrap().event_type,
+            WatchedEventType::NodeDataChanged
+        );
+        assert!(zk.exists("/c").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn dropped_response() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, mut default_watcher) = connect(&server, &faults).await;
+        zk.exists("/").await.unwrap();
+
+        // the client notices that a response is missing once the next one arrives
+        faults.drop_responses(1);
+        let (first, second) = futures::join!(zk.exists("/"), zk.exists("/zookeeper"));
+        assert!(matches!(first, Err(Error::ConnectionLoss)));
+        assert!(matches!(second, Err(Error::ConnectionLoss)));
+        reconnected(&mut default_watcher).await;
+        assert!(zk.exists("/").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn dropped_request() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, _) = connect(&server, &faults).await;
+        let acl = Acl::open_unsafe();
+        zk.exists("/").await.unwrap();
+
+        faults.drop_requests(1);
+        let (lost, _) = futures::join!(
+            zk.create("/d", &b""[..], acl, CreateMode::Persistent),
+            zk.exists("/"),
+        );
+        assert!(matches!(lost, Err(Error::ConnectionLoss)));
+        // the server never saw the request
+        assert_eq!(zk.exists("/d").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn corrupted() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, mut default_watcher) = connect(&server, &faults).await;
+        zk.exists("/").await.unwrap();
+
+        // a corrupted xid does not match the request's
+        faults.corrupt_at(4);
+        assert!(matches!(zk.exists("/").await, Err(Error::ConnectionLoss)));
+        reconnected(&mut default_watcher).await;
+        assert!(zk.exists("/").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn delayed() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, _) = connect(&server, &faults).await;
+        let delay = Duration::from_millis(50);
+        faults.set_delay(Some(delay));
+        let start = Instant::now();
+        zk.exists("/").await.unwrap();
+        assert!(start.elapsed() >= delay);
+        faults.clear();
+        zk.exists("/").await.unwrap();
+        assert_eq!(faults.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn refused_reconnects() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, mut default_watcher) = connect(&server, &faults).await;
+        let session_id = zk.stats().session_id;
+
+        // the server turns the first attempts to reconnect away, as one that is restarting would
+        faults.refuse_connects(3);
+        faults.disconnect();
+        reconnected(&mut default_watcher).await;
+        assert_eq!(faults.connections(), 2);
+        assert_eq!(zk.stats().session_id, session_id);
+        assert_eq!(server.sessions(), [session_id]);
+        assert!(zk.exists("/").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn lost_before_resuming() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, mut default_watcher) = connect(&server, &faults).await;
+        let session_id = zk.stats().session_id;
+
+        // hold the handshake's response back until the fault that cuts it short is in place
+        faults.set_delay(Some(Duration::from_millis(100)));
+        faults.disconnect();
+        let e = default_watcher.next().await.unwrap();
+        assert_eq!(e.keeper_state, KeeperState::Disconnected);
+        faults.disconnect_after(4);
+        let e = default_watcher.next().await.unwrap();
+        assert_eq!(e.keeper_state, KeeperState::SyncConnected);
+        assert_eq!(faults.connections(), 3);
+        faults.clear();
+        assert_eq!(zk.stats().session_id, session_id);
+        assert!(zk.exists("/").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn expiry_warning() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_timeout(Duration::from_millis(600));
+        builder.set_expiry_warning(Some(0.5));
+        let (zk, mut default_watcher) =
+            builder.connect_mock_faulty(&server, &faults).await.unwrap();
+
+        // the server goes quiet for longer than half the session timeout, but not all of it
+        faults.set_delay(Some(Duration::from_millis(450)));
+        let (_, states) = futures::join!(zk.exists("/"), async {
+            let mut states = Vec::new();
+            for _ in 0..2 {
+                states.push(default_watcher.next().await.unwrap().keeper_state);
+            }
+            states
+        });
+        assert_eq!(states, [KeeperState::Expiring, KeeperState::SyncConnected]);
+        faults.clear();
+        assert_eq!(faults.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn wait_until_connected() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, mut default_watcher) = connect(&server, &faults).await;
+        zk.wait_until_connected(Duration::from_secs(1)).await.unwrap();
+
+        // the session is resumed, but slowly
+        faults.set_delay(Some(Duration::from_millis(200)));
+        faults.disconnect();
+        let e = default_watcher.next().await.unwrap();
+        assert_eq!(e.keeper_state, KeeperState::Disconnected);
+        let wait = zk.wait_until_connected(Duration::from_millis(10)).await;
+        assert!(matches!(wait, Err(Error::Timeout)));
+        zk.wait_until_connected(Duration::from_secs(5)).await.unwrap();
+        assert!(zk.is_connected());
+
+        assert!(server.expire_session(zk.stats().session_id));
+        while default_watcher.next().await.is_some() {}
+        let wait = zk.wait_until_connected(Duration::from_secs(5)).await;
+        assert!(matches!(wait, Err(Error::SessionExpired)));
+    }
+
+    #[tokio::test]
+    async fn shutdown_timeout() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let (zk, _) = connect(&server, &faults).await;
+        zk.exists("/").await.unwrap();
+
+        faults.set_delay(Some(Duration::from_secs(10)));
+        let start = Instant::now();
+        let (exists, ()) = futures::join!(zk.exists("/"), zk.shutdown(Duration::from_millis(50)));
+        assert!(matches!(exists, Err(Error::ConnectionLoss)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(zk.exists("/").await, Err(Error::NotSent)));
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_circuit_breaker(Some(CircuitBreaker {
+            min_requests: 2,
+            open_for: Duration::from_millis(100),
+            ..CircuitBreaker::default()
+        }));
+        let (zk, mut default_watcher) =
+            builder.connect_mock_faulty(&server, &faults).await.unwrap();
+        zk.exists("/").await.unwrap();
+
+        // two of three requests fail
+        faults.drop_responses(1);
+        let (first, second) = futures::join!(zk.exists("/"), zk.exists("/zookeeper"));
+        assert!(matches!(first, Err(Error::ConnectionLoss)));
+        assert!(matches!(second, Err(Error::ConnectionLoss)));
+        reconnected(&mut default_watcher).await;
+        assert!(matches!(zk.exists("/").await, Err(Error::BrokenCircuit)));
+
+        // the first request once the circuit half-opens closes it again
+        Runtime::Tokio.sleep(Duration::from_millis(100)).await;
+        assert!(zk.exists("/").await.unwrap().is_some());
+        assert!(zk.exists("/").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn circuit_breaker_disconnected() {
+        let server = MockZk::new();
+        let faults = Faults::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_circuit_breaker(Some(CircuitBreaker {
+            disconnected_for: Some(Duration::from_secs(0)),
+            ..CircuitBreaker::default()
+        }));
+        let (zk, mut default_watcher) =
+            builder.connect_mock_faulty(&server, &faults).await.unwrap();
+        zk.exists("/").await.unwrap();
+
+        faults.set_delay(Some(Duration::from_millis(200)));
+        faults.disconnect();
+        let e = default_watcher.next().await.unwrap();
+        assert_eq!(e.keeper_state, KeeperState::Disconnected);
+        assert!(matches!(zk.exists("/").await, Err(Error::BrokenCircuit)));
+        zk.wait_until_connected(Duration::from_secs(5)).await.unwrap();
+        assert!(zk.exists("/").await.unwrap().is_some());
+    }
+
+    fn frame(body: &[u8]) -> Vec<u8> {
+        let mut frame = (body.len() as i32).to_be_bytes().to_vec();
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    /// Feed `bytes` through a new `Frames` in chunks of `chunk` bytes, and return what is kept.
+    fn filter(bytes: &[u8], chunk: usize, mut drops: usize) -> Vec<u8> {
+        let mut frames = Frames::default();
+        let mut kept = Vec::new();
+        for mut chunk in bytes.chunks(chunk) {
+            while !chunk.is_empty() {
+                let (keep, len) = frames.span(chunk, &mut drops);
+                frames.advance(&chunk[..len]);
+                if keep {
+                    kept.extend_from_slice(&chunk[..len]);
+                }
+                chunk = &chunk[len..];
+            }
+        }
+        kept
+    }
+
+    #[test]
+    fn drops_whole_frames() {
+        let (a, b, c) = (frame(b"first"), frame(b""), frame(b"third"));
+        let stream = [&a[..], &b[..], &c[..]].concat();
+        for chunk in 1..=stream.len() {
+            assert_eq!(filter(&stream, chunk, 0), stream);
+            assert_eq!(filter(&stream, chunk, 2), c);
+            assert_eq!(filter(&stream, chunk, 3), Vec::<u8>::new());
+        }
+    }
+}
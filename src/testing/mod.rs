@@ -0,0 +1,1240 @@
+//! An in-memory ZooKeeper server, for testing code that uses this crate without a real one.
+//!
+//! A [`MockZk`] keeps a tree of znodes in memory and speaks the ZooKeeper wire protocol to
+//! clients that connect to it in-process, so that their requests go through the exact same code
+//! as they would against a real server. It implements the parts of ZooKeeper that applications
+//! tend to rely on:
+//!
+//!  - persistent, ephemeral, and sequential nodes, with their versions and stats;
+//!  - `multi` transactions, which are applied atomically;
+//!  - data, existence, and child watches, and persistent and persistent recursive watches, which
+//!    are set again when a client reconnects;
+//!  - sessions, which expire when their client has been disconnected for longer than the session
+//!    timeout, taking their ephemeral nodes with them.
+//!
+//! ACLs are stored and returned, but not enforced, and authentication, quotas, and container and
+//! TTL node cleanup are not implemented. Tests can disconnect clients with
+//! [`MockZk::disconnect_all`] and expire sessions with [`MockZk::expire_session`] to exercise how
+//! code copes with connection loss and session expiry. For finer control over how a connection
+//! fails, clients can connect through a [`FaultyTransport`], which delays, cuts off, drops, and
+//! corrupts what goes over it as its [`Faults`] say. Against a real server, [`kill_session`]
+//! expires the session of a client the way that [`MockZk::expire_session`] does.
+//!
+//! ```
+//! # use tokio_zookeeper::*;
+//! # use tokio_zookeeper::testing::MockZk;
+//! # #[tokio::main(flavor = "current_thread")]
+//! # async fn main() -> Result<(), Error> {
+//! let server = MockZk::new();
+//! let (zk, _) = server.connect().await?;
+//! zk.create("/lock", &b""[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+//!     .await?
+//!     .unwrap();
+//! assert!(zk.exists("/lock").await?.is_some());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The server runs on the Tokio runtime that clients connect to it from. This module is only
+//! available with the `testing` feature enabled.
+//!
+//! # Simulated time
+//!
+//! A connection that runs on Tokio takes all of its timers from Tokio's clock: its pings, the
+//! delay of batched writes, and the waits between the attempts of [`ZooKeeper::with_retry`], as
+//! well as the timers that expire the sessions of a [`MockZk`]. Tests that pause the clock, with
+//! `#[tokio::test(start_paused = true)]` or `tokio::time::pause`, have Tokio jump straight to the
+//! next timer whenever there is nothing else left to do, so that session timeouts, ping
+//! schedules, and backoff sequences play out instantly, and in the same order on every run. The
+//! `testing` feature enables the `test-util` feature of Tokio that this needs.
+//!
+//! Where a [`MockZk`] answers clients by itself, a [`Script`] hands every connection to the test
+//! to answer, so that it can check exactly what a client sends, and when:
+//!
+//! ```
+//! # use tokio_zookeeper::*;
+//! # use tokio_zookeeper::testing::Script;
+//! # use std::time::Duration;
+//! # #[tokio::main(flavor = "current_thread", start_paused = true)]
+//! # async fn main() -> Result<(), Error> {
+//! let script = Script::new();
+//! let server = async {
+//!     let mut conn = script.accept().await;
+//!     conn.handshake().await.unwrap();
+//!     conn.accept(1, 6_000, &[0; 16]).await.unwrap();
+//!     conn
+//! };
+//! let client = ZooKeeperBuilder::default().connect_scripted(&script);
+//! let (client, mut conn) = futures::join!(client, server);
+//! let (zk, _) = client?;
+//!
+//! // with a session timeout of 6 seconds, an idle client pings every 4 seconds
+//! let start = tokio::time::Instant::now();
+//! let ping = conn.recv().await.unwrap().unwrap();
+//! assert!(ping.is_ping());
+//! assert_eq!(start.elapsed(), Duration::from_secs(4));
+//! # drop(zk);
+//! # Ok(())
+//! # }
+//! ```
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{self, Duration};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, DuplexStream, WriteHalf};
+use crate::proto::{HostProvider, ZooKeeperTransport};
+use crate::runtime::Runtime;
+use crate::subtree::is_within;
+use crate::{Error, WatchedEventStream, WatchedEventType, ZkError, ZooKeeper, ZooKeeperBuilder};
+
+mod faults;
+mod script;
+mod session;
+mod tree;
+mod wire;
+
+pub use self::faults::{FaultyTransport, Faults};
+pub use self::script::{Handshake, Script, ScriptedConnection, ScriptedRequest};
+pub use self::session::kill_session;
+use self::tree::{Tree, Trigger};
+use crate::codec::{ConnectRequest, Op, Reply};
+
+/// How much data a connection buffers in either direction before writes have to wait.
+const BUFFER: usize = 64 * 1024;
+
+/// The bounds that session timeouts are negotiated within, as with a real server's default tick
+/// time of two seconds.
+const MIN_SESSION_TIMEOUT: i32 = 4_000;
+const MAX_SESSION_TIMEOUT: i32 = 40_000;
+
+/// What the writer of a connection should do next.
+enum Outgoing {
+    Frame(Vec<u8>),
+    /// Close the connection, as a server does when it drops a client.
+    Close,
+}
+
+/// A client's connection to the server.
+struct Connection {
+    id: u64,
+    tx: mpsc::UnboundedSender<Outgoing>,
+}
+
+struct Session {
+    passwd: Vec<u8>,
+    timeout: i32,
+    connection: Option<Connection>,
+    /// The connection that the session was last attached to, so that an expiry timer can tell
+    /// whether the session has been resumed since.
+    last_connection: u64,
+}
+
+#[derive(Default)]
+struct Watches {
+    data: HashMap<String, HashSet<i64>>,
+    child: HashMap<String, HashSet<i64>>,
+    persistent: HashMap<String, HashSet<i64>>,
+    recursive: HashMap<String, HashSet<i64>>,
+}
+
+struct State {
+    tree: Tree,
+    zxid: i64,
+    sessions: HashMap<i64, Session>,
+    watches: Watches,
+    next_session: i64,
+    next_connection: u64,
+}
+
+/// An in-memory ZooKeeper server.
+///
+/// Clones refer to the same server. See the [module documentation](index.html) for what it
+/// implements.
+#[derive(Clone)]
+pub struct MockZk {
+    state: Arc<Mutex<State>>,
+}
+
+impl Default for MockZk {
+    fn default() -> Self {
+        MockZk::new()
+    }
+}
+
+impl fmt::Debug for MockZk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("MockZk")
+            .field("zxid", &state.zxid)
+            .field("sessions", &state.sessions.len())
+            .finish()
+    }
+}
+
+fn now() -> i64 {
+    time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as i64)
+}
+
+impl MockZk {
+    /// Start a server that holds nothing but the root node and `/zookeeper`, like a fresh real
+    /// server does.
+    pub fn new() -> Self {
+        MockZk {
+            state: Arc::new(Mutex::new(State {
+                tree: Tree::new(),
+                zxid: 0,
+                sessions: HashMap::new(),
+                watches: Watches::default(),
+                next_session: 1,
+                next_connection: 0,
+            })),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Connect to this server with default parameters.
+    ///
+    /// See [`ZooKeeperBuilder::connect_mock`].
+    pub async fn connect(&self) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        ZooKeeperBuilder::default().connect_mock(self).await
+    }
+
+    /// Return the ids of the sessions that are open on this server, whether their clients are
+    /// currently connected or not.
+    pub fn sessions(&self) -> Vec<i64> {
+        let mut sessions: Vec<_> = self.state().sessions.keys().cloned().collect();
+        sessions.sort_unstable();
+        sessions
+    }
+
+    /// Drop the connections of all clients, as if the server had restarted without losing any
+    /// state.
+    ///
+    /// Clients reconnect and resume their sessions, unless they stay away for longer than their
+    /// session timeout.
+    pub fn disconnect_all(&self) {
+        let mut state = self.state();
+        let ids: Vec<_> = state.sessions.keys().cloned().collect();
+        for id in ids {
+            self.detach(&mut state, id);
+        }
+    }
+
+    /// Expire the session with the given id, deleting its ephemeral nodes and dropping its
+    /// connection, and return whether the session was open.
+    ///
+    /// The session id of a client is in its [`ZooKeeper::stats`].
+    pub fn expire_session(&self, session_id: i64) -> bool {
+        self.state().close(session_id)
+    }
+
+    /// Detach session `id` from its connection, if it has one, and expire it unless it is resumed
+    /// within its timeout.
+    fn detach(&self, state: &mut State, id: i64) {
+        let session = match state.sessions.get_mut(&id) {
+            Some(session) => session,
+            None => return,
+        };
+        let connection = match session.connection.take() {
+            Some(connection) => connection,
+            None => return,
+        };
+        let _ = connection.tx.unbounded_send(Outgoing::Close);
+        let timeout = Duration::from_millis(session.timeout as u64);
+        let server = self.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            let mut state = server.state();
+            let resumed = match state.sessions.get(&id) {
+                Some(session) => {
+                    session.connection.is_some() || session.last_connection != connection.id
+                }
+                None => true,
+            };
+            if !resumed {
+                state.close(id);
+            }
+        });
+    }
+
+    /// Serve a single connection until either side closes it.
+    async fn serve(self, stream: DuplexStream) {
+        let (mut reader, writer) = tokio::io::split(stream);
+        let (tx, rx) = mpsc::unbounded();
+        tokio::spawn(write_frames(writer, rx));
+
+        let connection = {
+            let mut state = self.state();
+            state.next_connection += 1;
+            state.next_connection
+        };
+        let connect = match read_frame(&mut reader)
+            .await
+            .and_then(|f| wire::read_connect(&f))
+        {
+            Ok(connect) => connect,
+            Err(_) => return,
+        };
+        let session = match self.state().attach(connect, connection, tx.clone()) {
+            Some(session) => session,
+            None => return,
+        };
+
+        while let Ok(frame) = read_frame(&mut reader).await {
+            let request = match wire::read_request(&frame) {
+                Ok(request) => request,
+                Err(_) => break,
+            };
+            if !self.state().handle(session, connection, request, &tx) {
+                return;
+            }
+        }
+
+        let mut state = self.state();
+        let current = state
+            .sessions
+            .get(&session)
+            .and_then(|s| s.connection.as_ref())
+            .map(|c| c.id);
+        if current == Some(connection) {
+            self.detach(&mut state, session);
+        }
+    }
+}
+
+impl State {
+    /// Open or resume the session that `connect` asks for on `connection`, and return its id.
+    fn attach(
+        &mut self,
+        connect: ConnectRequest,
+        connection: u64,
+        tx: mpsc::UnboundedSender<Outgoing>,
+    ) -> Option<i64> {
+        let id = if connect.session_id == 0 {
+            let id = self.next_session;
+            self.next_session += 1;
+            let timeout = connect
+                .timeout
+                .clamp(MIN_SESSION_TIMEOUT, MAX_SESSION_TIMEOUT);
+            let session = Session {
+                passwd: uuid::Uuid::new_v4().as_bytes().to_vec(),
+                timeout,
+                connection: None,
+                last_connection: connection,
+            };
+            self.sessions.insert(id, session);
+            id
+        } else {
+            connect.session_id
+        };
+
+        match self.sessions.get_mut(&id) {
+            Some(session) if connect.session_id == 0 || session.passwd == connect.passwd => {
+                if let Some(old) = session.connection.take() {
+                    let _ = old.tx.unbounded_send(Outgoing::Close);
+                }
+                let response = wire::connect_response(session.timeout, id, &session.passwd);
+                let _ = tx.unbounded_send(Outgoing::Frame(response));
+                session.connection = Some(Connection { id: connection, tx });
+                session.last_connection = connection;
+                Some(id)
+            }
+            _ => {
+                let response = wire::connect_response(0, 0, &[0; 16]);
+                let _ = tx.unbounded_send(Outgoing::Frame(response));
+                let _ = tx.unbounded_send(Outgoing::Close);
+                None
+            }
+        }
+    }
+
+    /// Close session `id`, deleting its ephemeral nodes and watches, and return whether it was
+    /// open.
+    fn close(&mut self, id: i64) -> bool {
+        let session = match self.sessions.remove(&id) {
+            Some(session) => session,
+            None => return false,
+        };
+        if let Some(connection) = session.connection {
+            let _ = connection.tx.unbounded_send(Outgoing::Close);
+        }
+        let Watches {
+            ref mut data,
+            ref mut child,
+            ref mut persistent,
+            ref mut recursive,
+        } = self.watches;
+        for watchers in data
+            .values_mut()
+            .chain(child.values_mut())
+            .chain(persistent.values_mut())
+            .chain(recursive.values_mut())
+        {
+            watchers.remove(&id);
+        }
+
+        let ephemerals = self.tree.ephemerals(id);
+        if !ephemerals.is_empty() {
+            self.zxid += 1;
+        }
+        let mut triggers = Vec::new();
+        for path in ephemerals {
+            let deleted = self.tree.delete(&path, -1, self.zxid);
+            triggers.extend(deleted.expect("ephemeral nodes have no children"));
+        }
+        self.fire(triggers);
+        true
+    }
+
+    /// Handle `request` from session `id` on `connection`, and return whether to keep reading
+    /// requests from the connection.
+    fn handle(
+        &mut self,
+        id: i64,
+        connection: u64,
+        (xid, op): (i32, Op),
+        tx: &mpsc::UnboundedSender<Outgoing>,
+    ) -> bool {
+        // a request that arrives after the session was moved or closed is dropped along with its
+        // connection
+        match self.sessions.get(&id).and_then(|s| s.connection.as_ref()) {
+            Some(c) if c.id == connection => {}
+            _ => return false,
+        }
+        let reply = match op {
+            Op::CloseSession => {
+                let response = wire::response(xid, self.zxid, Ok(Reply::Empty));
+                let _ = tx.unbounded_send(Outgoing::Frame(response));
+                self.close(id);
+                return false;
+            }
+            Op::SetWatches {
+                relative_zxid,
+                data,
+                exist,
+                child,
+            } => {
+                let response = wire::response(xid, self.zxid, Ok(Reply::Empty));
+                let _ = tx.unbounded_send(Outgoing::Frame(response));
+                self.set_watches(id, relative_zxid, data, exist, child);
+                return true;
+            }
+            Op::SetWatches2 {
+                relative_zxid,
+                data,
+                exist,
+                child,
+                persistent,
+                recursive,
+            } => {
+                let response = wire::response(xid, self.zxid, Ok(Reply::Empty));
+                let _ = tx.unbounded_send(Outgoing::Frame(response));
+                // persistent watches do not fire for what they missed
+                for path in persistent {
+                    self.watches.persistent.entry(path).or_default().insert(id);
+                }
+                for path in recursive {
+                    self.watches.recursive.entry(path).or_default().insert(id);
+                }
+                self.set_watches(id, relative_zxid, data, exist, child);
+                return true;
+            }
+            Op::Multi(ops) => self.multi(id, ops),
+            op => {
+                let (reply, triggers) = self.apply(id, op);
+                self.fire(triggers);
+                reply
+            }
+        };
+        let response = wire::response(xid, self.zxid, reply);
+        let _ = tx.unbounded_send(Outgoing::Frame(response));
+        true
+    }
+
+    /// Apply a single request of session `id` to the tree, and return its reply along with the
+    /// watches that it triggers.
+    fn apply(&mut self, id: i64, op: Op) -> (Result<Reply, ZkError>, Vec<Trigger>) {
+        let write = matches!(
+            op,
+            Op::Create { .. }
+                | Op::Create2 { .. }
+                | Op::Delete { .. }
+                | Op::SetData { .. }
+                | Op::SetAcl { .. }
+        );
+        let zxid = if write { self.zxid + 1 } else { self.zxid };
+        let mut triggers = Vec::new();
+        let reply = match op {
+            Op::Create {
+                path,
+                data,
+                acl,
+                mode,
+            } => self
+                .tree
+                .create(&path, data, acl, mode, id, zxid, now())
+                .map(|(path, t)| {
+                    triggers = t;
+                    Reply::Path(path)
+                }),
+            Op::Create2 {
+                path,
+                data,
+                acl,
+                mode,
+            } => self
+                .tree
+                .create(&path, data, acl, mode, id, zxid, now())
+                .map(|(path, t)| {
+                    triggers = t;
+                    let stat = self.tree.get(&path).expect("the node was just created").stat;
+                    Reply::Created(path, stat)
+                }),
+            Op::Delete { path, version } => self.tree.delete(&path, version, zxid).map(|t| {
+                triggers = t;
+                Reply::Empty
+            }),
+            Op::SetData {
+                path,
+                data,
+                version,
+            } => self
+                .tree
+                .set_data(&path, data, version, zxid, now())
+                .map(|(stat, t)| {
+                    triggers = t;
+                    Reply::Stat(stat)
+                }),
+            Op::SetAcl { path, acl, version } => {
+                self.tree.set_acl(&path, acl, version).map(Reply::Stat)
+            }
+            Op::Check { path, version } => self.tree.check(&path, version).map(|_| Reply::Empty),
+            Op::Exists { path, watch } => {
+                let stat = self.tree.get(&path).map(|node| node.stat);
+                // an existence watch is kept whether or not the node exists
+                if watch && !matches!(stat, Err(ZkError::BadArguments)) {
+                    self.watch(id, &path, false);
+                }
+                stat.map(Reply::Stat)
+            }
+            Op::GetData { path, watch } => {
+                let reply = self
+                    .tree
+                    .get(&path)
+                    .map(|node| Reply::Data(node.data.clone(), node.stat));
+                if watch && reply.is_ok() {
+                    self.watch(id, &path, false);
+                }
+                reply
+            }
+            Op::GetChildren { path, watch } => {
+                let reply = self
+                    .tree
+                    .get(&path)
+                    .map(|node| Reply::Children(node.children.iter().cloned().collect()));
+                if watch && reply.is_ok() {
+                    self.watch(id, &path, true);
+                }
+                reply
+            }
+            Op::GetChildren2 { path, watch } => {
+                let reply = self.tree.get(&path).map(|node| {
+                    Reply::Children2(node.children.iter().cloned().collect(), node.stat)
+                });
+                if watch && reply.is_ok() {
+                    self.watch(id, &path, true);
+                }
+                reply
+            }
+            Op::GetAcl { path } => self
+                .tree
+                .get(&path)
+                .map(|node| Reply::Acl(node.acl.clone(), node.stat)),
+            Op::AddWatch { path, recursive } => {
+                let watches = if recursive {
+                    &mut self.watches.recursive
+                } else {
+                    &mut self.watches.persistent
+                };
+                watches.entry(path).or_default().insert(id);
+                Ok(Reply::Empty)
+            }
+            Op::Ping => Ok(Reply::Empty),
+            // there are no other servers to catch up with
+            Op::Sync { path } => Ok(Reply::Path(path)),
+            Op::Unknown { .. } => Err(ZkError::Unimplemented),
+            Op::Multi(..) | Op::SetWatches { .. } | Op::SetWatches2 { .. } | Op::CloseSession => {
+                unreachable!("handled by the caller")
+            }
+        };
+        if write && reply.is_ok() {
+            self.zxid = zxid;
+        }
+        (reply, triggers)
+    }
+
+    /// Apply `ops` as one transaction, that either takes effect as a whole or not at all.
+    fn multi(&mut self, id: i64, ops: Vec<Op>) -> Result<Reply, ZkError> {
+        let (tree, zxid, total) = (self.tree.clone(), self.zxid, ops.len());
+        let mut committed = zxid;
+        let mut results = Vec::with_capacity(total);
+        let mut triggers = Vec::new();
+        let mut failed = None;
+        for (i, op) in ops.into_iter().enumerate() {
+            let opcode = op.opcode();
+            let allowed = matches!(
+                op,
+                Op::Create { .. } | Op::Delete { .. } | Op::SetData { .. } | Op::Check { .. }
+            );
+            if !allowed {
+                failed = Some((i, ZkError::Unimplemented));
+                results.push(Err(ZkError::Unimplemented));
+                break;
+            }
+            // all operations of the transaction share a zxid
+            self.zxid = zxid;
+            let (reply, t) = self.apply(id, op);
+            committed = committed.max(self.zxid);
+            match reply {
+                Ok(reply) => {
+                    triggers.extend(t);
+                    results.push(Ok((opcode, reply)));
+                }
+                Err(e) => {
+                    failed = Some((i, e));
+                    results.push(Err(e));
+                    break;
+                }
+            }
+        }
+        if let Some((failed, e)) = failed {
+            // roll back, and tell which operation failed and which were never tried
+            self.tree = tree;
+            self.zxid = zxid;
+            let results = (0..total)
+                .map(|i| match i.cmp(&failed) {
+                    Ordering::Less => Err(ZkError::Ok),
+                    Ordering::Equal => Err(e),
+                    Ordering::Greater => Err(ZkError::RuntimeInconsistency),
+                })
+                .collect();
+            return Ok(Reply::Multi(results));
+        }
+        self.zxid = committed;
+        self.fire(triggers);
+        Ok(Reply::Multi(results))
+    }
+
+    fn watch(&mut self, id: i64, path: &str, child: bool) {
+        let watches = if child {
+            &mut self.watches.child
+        } else {
+            &mut self.watches.data
+        };
+        watches.entry(path.to_string()).or_default().insert(id);
+    }
+
+    /// Register the watches of a resumed session, firing those whose nodes changed since the
+    /// session last heard from the server.
+    fn set_watches(
+        &mut self,
+        id: i64,
+        relative_zxid: i64,
+        data: Vec<String>,
+        exist: Vec<String>,
+        child: Vec<String>,
+    ) {
+        let mut events = Vec::new();
+        for path in data {
+            match self.tree.get(&path) {
+                Err(_) => events.push((path, WatchedEventType::NodeDeleted)),
+                Ok(node) if node.stat.mzxid > relative_zxid => {
+                    events.push((path, WatchedEventType::NodeDataChanged))
+                }
+                Ok(_) => self.watch(id, &path, false),
+            }
+        }
+        for path in exist {
+            match self.tree.get(&path) {
+                Ok(_) => events.push((path, WatchedEventType::NodeCreated)),
+                Err(_) => self.watch(id, &path, false),
+            }
+        }
+        for path in child {
+            match self.tree.get(&path) {
+                Err(_) => events.push((path, WatchedEventType::NodeDeleted)),
+                Ok(node) if node.stat.pzxid > relative_zxid => {
+                    events.push((path, WatchedEventType::NodeChildrenChanged))
+                }
+                Ok(_) => self.watch(id, &path, true),
+            }
+        }
+        for (path, event_type) in events {
+            self.notify(id, event_type, &path);
+        }
+    }
+
+    /// Send the events of the watches that `triggers` fire to the sessions that set them.
+    fn fire(&mut self, triggers: Vec<Trigger>) {
+        for (path, event_type) in triggers {
+            let mut sessions = HashSet::new();
+            let data = !matches!(event_type, WatchedEventType::NodeChildrenChanged);
+            let child = matches!(
+                event_type,
+                WatchedEventType::NodeDeleted | WatchedEventType::NodeChildrenChanged
+            );
+            if data {
+                sessions.extend(self.watches.data.remove(&path).unwrap_or_default());
+            }
+            if child {
+                sessions.extend(self.watches.child.remove(&path).unwrap_or_default());
+            }
+            // persistent watches stay, and recursive ones fire for every descendant, but not
+            // for child changes
+            sessions.extend(self.watches.persistent.get(&path).into_iter().flatten());
+            if data {
+                let recursive = self.watches.recursive.iter();
+                sessions.extend(
+                    recursive
+                        .filter(|&(root, _)| is_within(&path, root))
+                        .flat_map(|(_, ids)| ids),
+                );
+            }
+            for id in sessions {
+                self.notify(id, event_type, &path);
+            }
+        }
+    }
+
+    fn notify(&self, id: i64, event_type: WatchedEventType, path: &str) {
+        let connection = self.sessions.get(&id).and_then(|s| s.connection.as_ref());
+        if let Some(connection) = connection {
+            let event = wire::event(self.zxid, event_type, path);
+            let _ = connection.tx.unbounded_send(Outgoing::Frame(event));
+        }
+    }
+}
+
+/// Read a frame, without its length.
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = reader.read_i32().await?;
+    if len < 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "negative frame length",
+        ));
+    }
+    let mut frame = vec![0; len as usize];
+    reader.read_exact(&mut frame).await?;
+    Ok(frame)
+}
+
+async fn write_frames(
+    mut writer: WriteHalf<DuplexStream>,
+    mut rx: mpsc::UnboundedReceiver<Outgoing>,
+) {
+    while let Some(outgoing) = rx.next().await {
+        match outgoing {
+            Outgoing::Frame(frame) => {
+                if writer.write_all(&frame).await.is_err() {
+                    return;
+                }
+            }
+            Outgoing::Close => break,
+        }
+    }
+    let _ = writer.shutdown().await;
+}
+
+impl ZooKeeperTransport for DuplexStream {
+    type Addr = MockZk;
+    type ConnectError = io::Error;
+    type ConnectFut = Pin<Box<dyn Future<Output = io::Result<Self>> + Send>>;
+    fn connect(addr: &Self::Addr) -> Self::ConnectFut {
+        let server = addr.clone();
+        Box::pin(async move {
+            let (client, stream) = tokio::io::duplex(BUFFER);
+            tokio::spawn(server.serve(stream));
+            Ok(client)
+        })
+    }
+}
+
+impl ZooKeeperBuilder {
+    /// Connect to the in-memory `server`.
+    ///
+    /// This is like [`ZooKeeperBuilder::connect`], except that the connection is made in-process,
+    /// and its [stats](ZooKeeper::stats) report an unspecified server address. Like the server, the
+    /// connection runs on the current Tokio runtime.
+    pub async fn connect_mock(
+        self,
+        server: &MockZk,
+    ) -> Result<(ZooKeeper, WatchedEventStream), Error> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], 0));
+        let hosts = HostProvider::new(vec![server.clone()]);
+        self.connect_spawned::<DuplexStream>(hosts, addr, Runtime::Tokio)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error, Acl, ConnectionState, CreateMode, KeeperState, MultiResponse, Upsert, WatchedEvent,
+        ZkPath,
+    };
+
+    fn event(event_type: WatchedEventType, path: &str) -> WatchedEvent {
+        WatchedEvent {
+            event_type,
+            keeper_state: KeeperState::SyncConnected,
+            path: path.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn nodes() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        let res = zk.create("/a", &b"1"[..], acl, CreateMode::Persistent).await;
+        assert_eq!(res.unwrap(), Ok("/a".to_string()));
+        let res = zk.create("/a", &b"1"[..], acl, CreateMode::Persistent).await;
+        assert_eq!(res.unwrap(), Err(error::Create::NodeExists { path: "/a".into() }));
+        let res = zk.create("/a/", &b""[..], acl, CreateMode::EphemeralSequential).await;
+        assert_eq!(res.unwrap(), Ok("/a/0000000000".to_string()));
+
+        let (data, stat) = zk.get_data("/a").await.unwrap().unwrap();
+        assert_eq!(data, b"1");
+        assert_eq!(stat.num_children, 1);
+        let res = zk.set_data("/a", Some(1), &b"2"[..]).await.unwrap();
+        assert_eq!(res, Err(error::SetData::BadVersion { path: "/a".into(), expected: 1 }));
+        let stat = zk.set_data("/a", Some(0), &b"2"[..]).await.unwrap().unwrap();
+        assert_eq!(stat.version, 1);
+        assert!(stat.mzxid > stat.czxid);
+
+        let children = zk.get_children("/").await.unwrap().unwrap();
+        assert_eq!(children, ["a", "zookeeper"]);
+        let res = zk.delete("/a", None).await.unwrap();
+        assert_eq!(res, Err(error::Delete::NotEmpty { path: "/a".into() }));
+
+        // the session's ephemeral node is gone once it closes
+        let session = zk.stats().session_id;
+        assert_eq!(server.sessions(), [session]);
+        drop(zk);
+        let (zk, _) = server.connect().await.unwrap();
+        assert_eq!(zk.get_children("/a").await.unwrap(), Some(vec![]));
+        assert!(!server.sessions().contains(&session));
+    }
+
+    #[tokio::test]
+    async fn children_with_stat() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/c", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let (children, before) = zk.get_children_with_stat("/c").await.unwrap().unwrap();
+        assert!(children.is_empty());
+        assert_eq!(before.cversion, 0);
+
+        let watched = zk.with_watcher().get_children_with_stat("/c").await.unwrap();
+        let (changed, _, _) = watched.unwrap();
+        zk.create("/c/x", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let (children, after) = zk.get_children_with_stat("/c").await.unwrap().unwrap();
+        assert_eq!(children, ["x"]);
+        assert_eq!(after.cversion, before.cversion + 1);
+        assert!(after.pzxid > before.pzxid);
+        assert_eq!(changed.await.unwrap(), event(WatchedEventType::NodeChildrenChanged, "/c"));
+
+        assert_eq!(zk.get_children_with_stat("/missing").await.unwrap(), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn compare_and_swap_backs_off() {
+        use crate::retry::BoundedRetries;
+        use tokio::runtime::Handle;
+
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/cas", &b"0"[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+
+        // every call but the last has another client write to the node before the swap does
+        let swap = |conflicts: u32, retries: u32| {
+            let mut calls = 0;
+            let policy = BoundedRetries {
+                retries,
+                delay: Duration::from_millis(20),
+            };
+            let other = &other;
+            let f = move |data: &[u8]| {
+                calls += 1;
+                if calls <= conflicts {
+                    tokio::task::block_in_place(|| {
+                        Handle::current().block_on(other.set_data_any("/cas", &b"x"[..]))
+                    })
+                    .unwrap()
+                    .unwrap();
+                }
+                [data, b"+"].concat()
+            };
+            zk.compare_and_swap("/cas", policy, f)
+        };
+
+        let start = std::time::Instant::now();
+        let stat = swap(2, 2).await.unwrap().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        assert_eq!(zk.get_data("/cas").await.unwrap().unwrap(), (b"x+".to_vec(), stat));
+        let res = swap(2, 1).await.unwrap();
+        assert!(matches!(
+            res,
+            Err(error::CompareAndSwap::BadVersion { ref path, .. }) if path == "/cas"
+        ));
+    }
+
+    #[tokio::test]
+    async fn create_or_set() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+
+        let res = zk.create_or_set("/cs", &b"a"[..], acl, CreateMode::Persistent).await.unwrap();
+        let (upsert, created) = res.unwrap();
+        assert_eq!(upsert, Upsert::Created);
+        // the stat is the one that the create left the node with
+        assert_eq!(zk.exists("/cs").await.unwrap(), Some(created));
+        let res = zk.create_or_set("/cs", &b"b"[..], acl, CreateMode::Persistent).await.unwrap();
+        let (upsert, updated) = res.unwrap();
+        assert_eq!(upsert, Upsert::Updated);
+        assert_eq!(updated.version, created.version + 1);
+        assert_eq!(zk.get_data("/cs").await.unwrap(), Some((b"b".to_vec(), updated)));
+
+        let res = zk.create_or_set("/cs/n", &b""[..], acl, CreateMode::PersistentSequential).await;
+        let path = "/cs/n".into();
+        assert_eq!(res.unwrap(), Err(error::CreateOrSet::Sequential { path }));
+        assert_eq!(zk.get_children("/cs").await.unwrap(), Some(vec![]));
+    }
+
+    #[tokio::test]
+    async fn unconditional() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/u", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        zk.set_data("/u", Some(0), &b"a"[..]).await.unwrap().unwrap();
+        // a write that matches any version goes through whatever was written before it
+        let stat = zk.set_data_any("/u", &b"b"[..]).await.unwrap().unwrap();
+        assert_eq!(stat.version, 2);
+        let res = zk.set_data_any("/missing", &b""[..]).await.unwrap();
+        assert_eq!(res, Err(error::SetData::NoNode { path: "/missing".into() }));
+
+        let multi = zk.multi().check("/u", crate::ANY_VERSION).set_data_any("/u", &b"c"[..]);
+        let res = multi.delete_any("/u").run().await.unwrap();
+        assert!(res.iter().all(Result::is_ok));
+        let res = zk.delete_any("/u").await.unwrap();
+        assert_eq!(res, Err(error::Delete::NoNode { path: "/u".into() }));
+    }
+
+    #[tokio::test]
+    async fn watches() {
+        let server = MockZk::new();
+        let (zk, mut default_watcher) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+
+        let (created, stat) = zk.with_watcher().exists("/w").await.unwrap();
+        assert_eq!(stat, None);
+        assert_eq!(zk.watch().get_children("/").await.unwrap().unwrap().len(), 1);
+        other.create("/w", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let created_event = event(WatchedEventType::NodeCreated, "/w");
+        assert_eq!(created.await.unwrap(), created_event);
+        // the default watcher sees the events of all watches, along with those of its own
+        assert_eq!(default_watcher.next().await, Some(created_event));
+        let children_changed = event(WatchedEventType::NodeChildrenChanged, "/");
+        assert_eq!(default_watcher.next().await, Some(children_changed));
+
+        let (changed, _, _) = zk.with_watcher().get_data("/w").await.unwrap().unwrap();
+        other.set_data("/w", None, &b"x"[..]).await.unwrap().unwrap();
+        assert_eq!(changed.await.unwrap(), event(WatchedEventType::NodeDataChanged, "/w"));
+
+        // watches fire once, and only for the sessions that set them
+        let (deleted, _, _) = zk.with_watcher().get_data("/w").await.unwrap().unwrap();
+        other.set_data("/w", None, &b"y"[..]).await.unwrap().unwrap();
+        let (_, _, _) = other.with_watcher().get_data("/w").await.unwrap().unwrap();
+        drop(deleted);
+        let (deleted, _) = zk.with_watcher().exists("/w").await.unwrap();
+        zk.delete("/w", None).await.unwrap().unwrap();
+        assert_eq!(deleted.await.unwrap(), event(WatchedEventType::NodeDeleted, "/w"));
+    }
+
+    #[tokio::test]
+    async fn own_watchers() {
+        let server = MockZk::new();
+        let (zk, mut default_watcher) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/lib", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let (lib, mut lib_watcher) = zk.using_namespace("/lib").unwrap().with_own_watcher();
+
+        assert_eq!(lib.watch().exists("/a").await.unwrap(), None);
+        // a handle hears of an event once, however many of its watches it fires
+        assert_eq!(lib.watch().exists("/a").await.unwrap(), None);
+        assert_eq!(zk.watch().exists("/b").await.unwrap(), None);
+        zk.create("/b", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        zk.create("/lib/a", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        assert_eq!(lib_watcher.next().await, Some(event(WatchedEventType::NodeCreated, "/a")));
+        // the session's watcher still sees every event
+        assert_eq!(default_watcher.next().await, Some(event(WatchedEventType::NodeCreated, "/b")));
+        let created = event(WatchedEventType::NodeCreated, "/lib/a");
+        assert_eq!(default_watcher.next().await, Some(created));
+
+        assert!(server.expire_session(zk.stats().session_id));
+        let states: Vec<_> = lib_watcher.map(|e| (e.event_type, e.keeper_state)).collect().await;
+        let expected = [
+            (WatchedEventType::None, KeeperState::Disconnected),
+            (WatchedEventType::None, KeeperState::Expired),
+        ];
+        assert_eq!(states, expected);
+        // a watcher added once the session is gone ends right away
+        assert_eq!(lib.with_own_watcher().1.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn multi() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        let res = zk
+            .multi()
+            .create("/m", &b""[..], acl, CreateMode::Persistent)
+            .check("/m", 1)
+            .set_data("/m", None, &b"x"[..])
+            .commit()
+            .await
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(res.index, 1);
+        assert_eq!(
+            res.results,
+            vec![
+                Err(error::Multi::RolledBack),
+                Err(error::Multi::Check(error::Check::BadVersion {
+                    path: "/m".into(),
+                    expected: 1
+                })),
+                Err(error::Multi::Skipped),
+            ]
+        );
+        assert_eq!(zk.exists("/m").await.unwrap(), None);
+
+        let res = zk
+            .multi()
+            .create("/m", &b""[..], acl, CreateMode::Persistent)
+            .check("/m", 0)
+            .commit()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(res, [MultiResponse::Create("/m".to_string()), MultiResponse::Check]);
+        let stat = zk.exists("/m").await.unwrap().unwrap();
+        assert_eq!(zk.stats().last_zxid_seen, stat.czxid);
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        let server = MockZk::new();
+        let (zk, mut default_watcher) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/r", &b""[..], acl, CreateMode::Ephemeral).await.unwrap().unwrap();
+        let (changed, _, _) = zk.with_watcher().get_data("/r").await.unwrap().unwrap();
+        let session = zk.stats().session_id;
+
+        server.disconnect_all();
+        let e = default_watcher.next().await.unwrap();
+        assert_eq!(e.keeper_state, KeeperState::Disconnected);
+        // the session is resumed, with its ephemeral node and watch
+        assert!(zk.exists("/r").await.unwrap().is_some());
+        assert_eq!(zk.stats().session_id, session);
+        let e = default_watcher.next().await.unwrap();
+        assert_eq!(e.keeper_state, KeeperState::SyncConnected);
+        zk.set_data("/r", None, &b"x"[..]).await.unwrap().unwrap();
+        assert_eq!(changed.await.unwrap(), event(WatchedEventType::NodeDataChanged, "/r"));
+    }
+
+    #[tokio::test]
+    async fn expire() {
+        let server = MockZk::new();
+        let (zk, default_watcher) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/e", &b""[..], acl, CreateMode::Ephemeral).await.unwrap().unwrap();
+
+        assert!(server.expire_session(zk.stats().session_id));
+        assert_eq!(other.exists("/e").await.unwrap(), None);
+        let states: Vec<_> = default_watcher.map(|e| e.keeper_state).collect().await;
+        assert_eq!(states, [KeeperState::Disconnected, KeeperState::Expired]);
+        assert!(zk.exists("/e").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn wait_for_zxid() {
+        let server = MockZk::new();
+        let (writer, _) = server.connect().await.unwrap();
+        let (reader, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        writer.create("/z", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let zxid = writer.last_zxid_seen();
+        assert!(reader.last_zxid_seen() < zxid);
+
+        assert!(reader.wait_for_zxid(zxid).await.unwrap());
+        assert!(reader.last_zxid_seen() >= zxid);
+        // no server has seen this one yet
+        assert!(!reader.wait_for_zxid(zxid + 10).await.unwrap());
+        reader.sync("/z").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn state() {
+        let server = MockZk::new();
+        let (zk, mut default_watcher) = server.connect().await.unwrap();
+        assert_eq!(zk.state(), ConnectionState::Connected);
+        assert!(zk.is_connected());
+
+        server.disconnect_all();
+        assert_eq!(default_watcher.next().await.unwrap().keeper_state, KeeperState::Disconnected);
+        // the mock takes the client back before it could be seen reconnecting
+        assert_eq!(default_watcher.next().await.unwrap().keeper_state, KeeperState::SyncConnected);
+        assert_eq!(zk.state(), ConnectionState::Connected);
+
+        assert!(server.expire_session(zk.stats().session_id));
+        assert_eq!(default_watcher.count().await, 2);
+        assert_eq!(zk.state(), ConnectionState::Expired);
+
+        let (zk, _) = server.connect().await.unwrap();
+        zk.shutdown(Duration::from_secs(1)).await;
+        assert_eq!(zk.state(), ConnectionState::Closed);
+        assert!(zk.state().is_terminal());
+    }
+
+    #[tokio::test]
+    async fn detach() {
+        let server = MockZk::new();
+        let (zk, default_watcher) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/d", &b""[..], acl, CreateMode::Ephemeral).await.unwrap().unwrap();
+        let session = zk.stats().session_id;
+
+        zk.detach();
+        assert_eq!(default_watcher.count().await, 0);
+        // the session was not closed, so it keeps its ephemeral node until it expires
+        assert!(server.sessions().contains(&session));
+        assert!(other.exists("/d").await.unwrap().is_some());
+        assert!(server.expire_session(session));
+        assert_eq!(other.exists("/d").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let server = MockZk::new();
+        let (zk, default_watcher) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/s", &b""[..], acl, CreateMode::Ephemeral).await.unwrap().unwrap();
+        let session = zk.stats().session_id;
+
+        // the request in flight completes before the session is closed
+        let (exists, ()) = futures::join!(zk.exists("/s"), zk.shutdown(Duration::from_secs(5)));
+        assert!(exists.unwrap().is_some());
+        assert!(matches!(zk.exists("/s").await, Err(Error::NotSent)));
+        assert_eq!(default_watcher.collect::<Vec<_>>().await, []);
+        assert!(!server.sessions().contains(&session));
+        assert_eq!(other.exists("/s").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn batches_fit_max_request_size() {
+        let server = MockZk::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_max_request_size(1024);
+        let (zk, _) = builder.connect_mock(&server).await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/b", &b""[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        let ns = zk.using_namespace("/b").unwrap();
+
+        // twenty entries of 100 bytes each need several multis of at most 1024 bytes
+        let entries = (0..20).map(|i| {
+            let path = format!("/n{:02}", i);
+            (path, vec![0; 100], acl, CreateMode::Persistent)
+        });
+        let created = ns.create_many(entries).await.unwrap();
+        assert_eq!(created.len(), 20);
+        assert!(created.iter().all(Result::is_ok));
+        assert_eq!(zk.get_children("/b").await.unwrap().unwrap().len(), 20);
+    }
+
+    #[tokio::test]
+    async fn invalid_requests() {
+        let server = MockZk::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_max_request_size(1024);
+        let (zk, _) = builder.connect_mock(&server).await.unwrap();
+        let acl = Acl::open_unsafe();
+
+        let created = zk.create("/big", vec![0; 1024], acl, CreateMode::Persistent).await;
+        assert!(matches!(
+            created,
+            Err(Error::InvalidRequest(error::InvalidRequest::TooLarge { size, max: 1024, .. }))
+                if size > 1024
+        ));
+        let created = zk.create("/a//b", &b""[..], acl, CreateMode::Persistent).await;
+        assert!(matches!(
+            created,
+            Err(Error::InvalidRequest(error::InvalidRequest::Path {
+                error: error::InvalidPath::EmptyNode,
+                ..
+            }))
+        ));
+        // neither request reached the server, which still serves the session
+        assert_eq!(zk.exists("/big").await.unwrap(), None);
+        assert_eq!(server.sessions().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn path_arguments() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+
+        // strings are checked as they are converted, before any request is made
+        let exists = zk.exists("/a//b").await;
+        assert!(matches!(exists, Err(Error::InvalidPath(error::InvalidPath::EmptyNode))));
+        let deleted = zk.delete_any(String::from("a")).await;
+        assert!(matches!(deleted, Err(Error::InvalidPath(error::InvalidPath::NotAbsolute))));
+        let res = zk.multi().check("/a/", 0).run().await;
+        assert!(matches!(res, Err(Error::InvalidPath(error::InvalidPath::TrailingSlash))));
+
+        let path = ZkPath::new("/p").unwrap();
+        zk.create(&path, &b"1"[..], acl, CreateMode::Persistent).await.unwrap().unwrap();
+        zk.set_data_any(&path, &b"2"[..]).await.unwrap().unwrap();
+        let (data, _) = zk.get_data(path.clone()).await.unwrap().unwrap();
+        assert_eq!(data, b"2");
+        let res = zk.multi().check(&path, 1).delete(path, Some(1)).run().await.unwrap();
+        assert!(res.iter().all(Result::is_ok));
+        assert_eq!(zk.exists("/p").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,392 @@
+//! Connections whose server side is played by the test itself.
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream, ReadBuf};
+use super::{read_frame, wire, BUFFER};
+use crate::proto::{HostProvider, ZooKeeperTransport};
+use crate::runtime::Runtime;
+use crate::{Error, WatchedEventStream, ZkError, ZooKeeperBuilder};
+
+/// A server whose every move is made by the test, one connection at a time.
+///
+/// Clients connect to it with [`ZooKeeperBuilder::connect_scripted`], and the test takes their
+/// connections with [`Script::accept`], in the order in which they were made. A connection is up
+/// to the test from its handshake on: nothing is sent to the client that the test does not send,
+/// and nothing the client sends goes unseen.
+#[derive(Clone)]
+pub struct Script {
+    tx: mpsc::UnboundedSender<DuplexStream>,
+    rx: Arc<Mutex<mpsc::UnboundedReceiver<DuplexStream>>>,
+}
+
+impl fmt::Debug for Script {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Script").finish()
+    }
+}
+
+impl Default for Script {
+    fn default() -> Self {
+        let (tx, rx) = mpsc::unbounded();
+        Script {
+            tx,
+            rx: Arc::new(Mutex::new(rx)),
+        }
+    }
+}
+
+impl Script {
+    /// A script that no client has connected to yet.
+    pub fn new() -> Self {
+        Script::default()
+    }
+
+    /// Wait for the next connection that a client makes.
+    pub async fn accept(&self) -> ScriptedConnection {
+        let stream = futures::future::poll_fn(|cx| self.rx.lock().unwrap().poll_next_unpin(cx))
+            .await
+            .expect("the script holds a sender of its own");
+        ScriptedConnection { stream }
+    }
+}
+
+/// The handshake that a client opens a connection with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Handshake {
+    /// The session that the client wants to resume, or 0 for a new one.
+    pub session_id: i64,
+    /// The session timeout that the client asks for, in milliseconds.
+    pub timeout: i32,
+    /// The password of the session that the client wants to resume.
+    pub passwd: Vec<u8>,
+    /// Whether the client accepts a server that is in read-only mode, as sent in the trailing byte
+    /// of the handshake.
+    pub read_only: bool,
+}
+
+/// A request that a client sent over a [`ScriptedConnection`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptedRequest {
+    /// The id that the response to this request has to carry.
+    pub xid: i32,
+    /// The code of the operation that is requested.
+    pub opcode: i32,
+    /// The encoded arguments of the operation.
+    pub body: Vec<u8>,
+}
+
+impl ScriptedRequest {
+    /// Whether this is a ping, which the client sends when it has had nothing else to send for a
+    /// while.
+    pub fn is_ping(&self) -> bool {
+        self.xid == wire::PING_XID
+    }
+}
+
+/// The server's side of a connection that a client made to a [`Script`].
+///
+/// Dropping it closes the connection.
+#[derive(Debug)]
+pub struct ScriptedConnection {
+    stream: DuplexStream,
+}
+
+impl ScriptedConnection {
+    /// Read the handshake that the client opens the connection with.
+    pub async fn handshake(&mut self) -> io::Result<Handshake> {
+        let connect = wire::read_connect(&read_frame(&mut self.stream).await?)?;
+        Ok(Handshake {
+            session_id: connect.session_id,
+            timeout: connect.timeout,
+            passwd: connect.passwd,
+            read_only: connect.read_only,
+        })
+    }
+
+    /// Establish the session with the given id, timeout in milliseconds, and password.
+    pub async fn accept(&mut self, session_id: i64, timeout: i32, passwd: &[u8]) -> io::Result<()> {
+        let response = wire::connect_response(timeout, session_id, passwd);
+        self.stream.write_all(&response).await
+    }
+
+    /// Tell the client that the session it tried to resume has expired, and close the connection.
+    pub async fn expire(mut self) -> io::Result<()> {
+        let response = wire::connect_response(0, 0, &[0; 16]);
+        self.stream.write_all(&response).await?;
+        self.stream.shutdown().await
+    }
+
+    /// Read the next request that the client sends, or `None` if it has closed the connection.
+    pub async fn recv(&mut self) -> io::Result<Option<ScriptedRequest>> {
+        let frame = match read_frame(&mut self.stream).await {
+            Ok(frame) => frame,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        if frame.len() < 8 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request without a header",
+            ));
+        }
+        Ok(Some(ScriptedRequest {
+            xid: i32::from_be_bytes([frame[0], frame[1], frame[2], frame[3]]),
+            opcode: i32::from_be_bytes([frame[4], frame[5], frame[6], frame[7]]),
+            body: frame[8..].to_vec(),
+        }))
+    }
+
+    /// Answer the request with the given `xid`, with either the encoded response body or an error.
+    pub async fn reply(
+        &mut self,
+        xid: i32,
+        zxid: i64,
+        result: Result<&[u8], ZkError>,
+    ) -> io::Result<()> {
+        let response = wire::encoded_response(xid, zxid, result);
+        self.stream.write_all(&response).await
+    }
+}
+
+/// The client's side of a connection to a [`Script`].
+struct ScriptedStream(DuplexStream);
+
+impl AsyncRead for ScriptedStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ScriptedStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl ZooKeeperTransport for ScriptedStream {
+    type Addr = mpsc::UnboundedSender<DuplexStream>;
+    type ConnectError = io::Error;
+    type ConnectFut = futures::future::Ready<io::Result<Self>>;
+    fn connect(addr: &Self::Addr) -> Self::ConnectFut {
+        let (client, server) = tokio::io::duplex(BUFFER);
+        futures::future::ready(
+            addr.unbounded_send(server)
+                .map(|_| ScriptedStream(client))
+                .map_err(|_| io::Error::from(io::ErrorKind::ConnectionRefused)),
+        )
+    }
+}
+
+impl ZooKeeperBuilder {
+    /// Connect to `script`, which the test then has to take the connection from with
+    /// [`Script::accept`] and play the server on.
+    ///
+    /// This is otherwise like [`ZooKeeperBuilder::connect_mock`]. Since the handshake needs an
+    /// answer from the test, this has to be awaited alongside the test's side of the connection.
+    pub async fn connect_scripted(
+        self,
+        script: &Script,
+    ) -> Result<(crate::ZooKeeper, WatchedEventStream), Error> {
+        let addr = SocketAddr::from(([0, 0, 0, 0], 0));
+        let hosts = HostProvider::new(vec![script.tx.clone()]);
+        self.connect_spawned::<ScriptedStream>(hosts, addr, Runtime::Tokio)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time::Instant;
+    use crate::retry::ExponentialBackoff;
+    use crate::{KeeperState, ZooKeeper};
+
+    /// Connect a client with a 30 second session timeout, and establish its session.
+    async fn connect(script: &Script) -> (ZooKeeper, WatchedEventStream, ScriptedConnection) {
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_timeout(Duration::from_secs(30));
+        let server = async {
+            let mut conn = script.accept().await;
+            let handshake = conn.handshake().await.unwrap();
+            assert_eq!((handshake.session_id, handshake.timeout), (0, 30_000));
+            // like the Java client, a new session is asked for with an all-zero password
+            assert_eq!(handshake.passwd, [0; 16]);
+            conn.accept(1, handshake.timeout, &[1; 16]).await.unwrap();
+            conn
+        };
+        let (client, conn) = futures::join!(builder.connect_scripted(script), server);
+        let (zk, w) = client.unwrap();
+        (zk, w, conn)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pings() {
+        let script = Script::new();
+        let (zk, _w, mut conn) = connect(&script).await;
+
+        // an idle client pings every two thirds of the session timeout
+        let start = Instant::now();
+        for n in 1..=3 {
+            let ping = conn.recv().await.unwrap().unwrap();
+            assert!(ping.is_ping());
+            assert_eq!(start.elapsed(), Duration::from_secs(20 * n));
+            conn.reply(ping.xid, 0, Ok(&[])).await.unwrap();
+        }
+
+        // and any other request puts the next ping off
+        let exists = zk.exists("/foo");
+        let server = async {
+            let req = conn.recv().await.unwrap().unwrap();
+            assert!(!req.is_ping());
+            conn.reply(req.xid, 1, Err(ZkError::NoNode)).await.unwrap();
+        };
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let (stat, ()) = futures::join!(exists, server);
+        assert_eq!(stat.unwrap(), None);
+        let ping = conn.recv().await.unwrap().unwrap();
+        assert!(ping.is_ping());
+        assert_eq!(start.elapsed(), Duration::from_secs(90));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backoff() {
+        let script = Script::new();
+        let (zk, _w, mut conn) = connect(&script).await;
+
+        let retry = zk.with_retry(ExponentialBackoff::default());
+        let exists = retry.exists("/foo");
+        let server = async {
+            let mut delays = Vec::new();
+            let mut last = None;
+            for _ in 0..4 {
+                let req = conn.recv().await.unwrap().unwrap();
+                if let Some(last) = last {
+                    delays.push(Instant::now() - last);
+                }
+                conn.reply(req.xid, 1, Err(ZkError::ConnectionLoss))
+                    .await
+                    .unwrap();
+                last = Some(Instant::now());
+            }
+            let req = conn.recv().await.unwrap().unwrap();
+            conn.reply(req.xid, 1, Err(ZkError::NoNode)).await.unwrap();
+            delays
+        };
+        let (stat, delays) = futures::join!(exists, server);
+        assert_eq!(stat.unwrap(), None);
+        assert_eq!(
+            delays,
+            [100, 200, 400].map(Duration::from_millis).to_vec()
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_on_reconnect() {
+        let script = Script::new();
+        let (zk, mut w, conn) = connect(&script).await;
+
+        // the client tries to resume its session when a connection is lost
+        drop(conn);
+        let mut conn = script.accept().await;
+        let handshake = conn.handshake().await.unwrap();
+        assert_eq!(handshake.session_id, 1);
+        assert_eq!(handshake.passwd, [1; 16]);
+        conn.expire().await.unwrap();
+
+        let mut states = Vec::new();
+        while let Some(e) = w.next().await {
+            states.push(e.keeper_state);
+        }
+        assert_eq!(states, [KeeperState::Disconnected, KeeperState::Expired]);
+        drop(zk);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn read_only() {
+        let script = Script::new();
+        let mut builder = ZooKeeperBuilder::default();
+        builder.set_read_only(true);
+        let server = async {
+            let mut conn = script.accept().await;
+            let handshake = conn.handshake().await.unwrap();
+            conn.accept(1, 30_000, &[1; 16]).await.unwrap();
+            (conn, handshake)
+        };
+        let (client, (conn, handshake)) = futures::join!(builder.connect_scripted(&script), server);
+        let (_zk, _w) = client.unwrap();
+        // the first handshake already asks for a read-only session, not just the resumed ones
+        assert!(handshake.read_only);
+
+        drop(conn);
+        let mut conn = script.accept().await;
+        assert!(conn.handshake().await.unwrap().read_only);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn raw_request() {
+        let script = Script::new();
+        let (zk, _w, mut conn) = connect(&script).await;
+
+        // the request goes out as it is, and the response comes back as it is, errors included
+        let requests = async {
+            let ok = zk.raw_request(1000, &b"\x00\x00\x00\x01"[..]).await.unwrap();
+            let err = zk.raw_request(1001, Vec::new()).await.unwrap();
+            (ok, err)
+        };
+        let server = async {
+            let req = conn.recv().await.unwrap().unwrap();
+            assert_eq!((req.opcode, &req.body[..]), (1000, &[0, 0, 0, 1][..]));
+            conn.reply(req.xid, 7, Ok(b"pong")).await.unwrap();
+            let req = conn.recv().await.unwrap().unwrap();
+            assert_eq!((req.opcode, req.body.len()), (1001, 0));
+            conn.reply(req.xid, 8, Err(ZkError::Unimplemented))
+                .await
+                .unwrap();
+        };
+        let ((ok, err), ()) = futures::join!(requests, server);
+        assert_eq!((ok.zxid, ok.err, &ok.body[..]), (7, ZkError::Ok, &b"pong"[..]));
+        assert_eq!((err.zxid, err.err, err.body.len()), (8, ZkError::Unimplemented, 0));
+        assert_eq!(err.xid, ok.xid + 1);
+
+        // the connection carries on as before
+        let exists = zk.exists("/foo");
+        let server = async {
+            let req = conn.recv().await.unwrap().unwrap();
+            conn.reply(req.xid, 9, Err(ZkError::NoNode)).await.unwrap();
+        };
+        let (stat, ()) = futures::join!(exists, server);
+        assert_eq!(stat.unwrap(), None);
+    }
+
+    #[tokio::test(start_paused = true)]
+    #[should_panic(expected = "opcode 11 cannot be sent with raw_request")]
+    async fn raw_ping() {
+        let script = Script::new();
+        let (zk, _w, _conn) = connect(&script).await;
+        let _ = zk.raw_request(11, Vec::new()).await;
+    }
+}
@@ -0,0 +1,70 @@
+//! Expiring the sessions of other clients.
+
+use std::net::SocketAddr;
+use crate::proto;
+use crate::{Error, Session};
+
+/// Expire `session` on the server at `addr`, as if its client had not been heard from for longer
+/// than its timeout.
+///
+/// Like Curator's `KillSession`, this takes the session over on a connection of its own with the
+/// session's credentials, and closes it there. The ephemeral nodes of the session are gone by the
+/// time this returns. The client whose session it was loses its connection, and learns that the
+/// session has expired when it reconnects, just as it would after a real expiry. Fails with
+/// [`Error::SessionExpired`] if the session is already gone.
+///
+/// ```no_run
+/// # use tokio_zookeeper::*;
+/// # async fn run(zk: ZooKeeper, addr: std::net::SocketAddr) -> Result<(), Error> {
+/// tokio_zookeeper::testing::kill_session(&addr, &zk.session()).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn kill_session(addr: &SocketAddr, session: &Session) -> Result<(), Error> {
+    proto::close_session::<tokio::net::TcpStream>(addr, session).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use tokio::io::DuplexStream;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode, KeeperState, WatchedEventType};
+
+    async fn kill_mock(server: &MockZk, session: &Session) -> Result<(), Error> {
+        proto::close_session::<DuplexStream>(server, session).await
+    }
+
+    #[tokio::test]
+    async fn expires() {
+        let server = MockZk::new();
+        let (zk, mut w) = server.connect().await.unwrap();
+        let (other, _) = server.connect().await.unwrap();
+        let session = zk.session();
+        assert_eq!(session.id, zk.stats().session_id);
+        assert_eq!(format!("{:?}", session).matches("<redacted>").count(), 1);
+
+        zk.create("/eph", &b""[..], Acl::open_unsafe(), CreateMode::Ephemeral)
+            .await
+            .unwrap()
+            .unwrap();
+        let (deleted, _) = other.with_watcher().exists("/eph").await.unwrap();
+        kill_mock(&server, &session).await.unwrap();
+        // the session is gone by the time the kill returns
+        assert_eq!(other.exists("/eph").await.unwrap(), None);
+        assert_eq!(deleted.await.unwrap().event_type, WatchedEventType::NodeDeleted);
+
+        let mut states = Vec::new();
+        while let Some(e) = w.next().await {
+            states.push(e.keeper_state);
+        }
+        assert_eq!(states, [KeeperState::Disconnected, KeeperState::Expired]);
+
+        // the session cannot be killed twice
+        assert!(matches!(
+            kill_mock(&server, &session).await,
+            Err(Error::SessionExpired)
+        ));
+    }
+}
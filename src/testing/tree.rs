@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, BTreeSet};
+use crate::{Acl, CreateMode, Stat, WatchedEventType, ZkError, ZkPath};
+
+/// A change to the tree that watches may have to be told about.
+pub(super) type Trigger = (String, WatchedEventType);
+
+#[derive(Clone, Debug)]
+pub(super) struct Node {
+    pub(super) data: Vec<u8>,
+    pub(super) acl: Vec<Acl>,
+    pub(super) stat: Stat,
+    pub(super) children: BTreeSet<String>,
+}
+
+/// The znodes held by a mock server, and the rules for changing them.
+///
+/// Every change is made at a zxid and time that the caller picks, so that the operations of a
+/// multi can share them.
+#[derive(Clone, Debug)]
+pub(super) struct Tree {
+    nodes: BTreeMap<String, Node>,
+}
+
+fn parent(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) => "/",
+        Some(i) => &path[..i],
+        None => "/",
+    }
+}
+
+fn name(path: &str) -> &str {
+    &path[path.rfind('/').map_or(0, |i| i + 1)..]
+}
+
+fn check_version(stat_version: i32, version: i32) -> Result<(), ZkError> {
+    if version == -1 || version == stat_version {
+        Ok(())
+    } else {
+        Err(ZkError::BadVersion)
+    }
+}
+
+impl Tree {
+    /// A tree with just the root and the `/zookeeper` nodes that real servers have, as of 3.5.
+    pub(super) fn new() -> Self {
+        let mut tree = Tree {
+            nodes: BTreeMap::new(),
+        };
+        tree.nodes
+            .insert("/".to_string(), Tree::node(Vec::new(), Acl::open_unsafe().to_vec(), 0, 0, 0));
+        for path in &["/zookeeper", "/zookeeper/config"] {
+            tree.create(
+                path,
+                Vec::new(),
+                Acl::open_unsafe().to_vec(),
+                CreateMode::Persistent,
+                0,
+                0,
+                0,
+            )
+            .expect("the parent exists");
+        }
+        tree
+    }
+
+    fn node(data: Vec<u8>, acl: Vec<Acl>, owner: i64, zxid: i64, now: i64) -> Node {
+        Node {
+            stat: Stat {
+                czxid: zxid,
+                mzxid: zxid,
+                ctime: now,
+                mtime: now,
+                version: 0,
+                cversion: 0,
+                aversion: 0,
+                ephemeral_owner: owner,
+                data_length: data.len() as i32,
+                num_children: 0,
+                pzxid: zxid,
+            },
+            data,
+            acl,
+            children: BTreeSet::new(),
+        }
+    }
+
+    pub(super) fn get(&self, path: &str) -> Result<&Node, ZkError> {
+        ZkPath::validate(path).map_err(|_| ZkError::BadArguments)?;
+        self.nodes.get(path).ok_or(ZkError::NoNode)
+    }
+
+    fn get_mut(&mut self, path: &str) -> Result<&mut Node, ZkError> {
+        ZkPath::validate(path).map_err(|_| ZkError::BadArguments)?;
+        self.nodes.get_mut(path).ok_or(ZkError::NoNode)
+    }
+
+    /// Create a node, and return the path it was created at, which only differs from `path` for
+    /// sequential nodes.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn create(
+        &mut self,
+        path: &str,
+        data: Vec<u8>,
+        acl: Vec<Acl>,
+        mode: CreateMode,
+        session: i64,
+        zxid: i64,
+        now: i64,
+    ) -> Result<(String, Vec<Trigger>), ZkError> {
+        let sequential = matches!(
+            mode,
+            CreateMode::PersistentSequential | CreateMode::EphemeralSequential
+        );
+        let owner = match mode {
+            CreateMode::Ephemeral | CreateMode::EphemeralSequential => session,
+            _ => 0,
+        };
+        // a sequential node's name may be nothing but its sequence number
+        let checked = if sequential {
+            format!("{}0", path)
+        } else {
+            path.to_string()
+        };
+        ZkPath::validate(&checked).map_err(|_| ZkError::BadArguments)?;
+        if checked == "/" {
+            return Err(ZkError::NodeExists);
+        }
+        if acl.is_empty() {
+            return Err(ZkError::InvalidACL);
+        }
+
+        let parent_path = parent(&checked).to_string();
+        let parent = self.nodes.get_mut(&parent_path).ok_or(ZkError::NoNode)?;
+        if parent.stat.is_ephemeral() {
+            return Err(ZkError::NoChildrenForEphemerals);
+        }
+        let path = if sequential {
+            format!("{}{:010}", path, parent.stat.cversion)
+        } else {
+            path.to_string()
+        };
+        let name = name(&path).to_string();
+        if parent.children.contains(&name) {
+            return Err(ZkError::NodeExists);
+        }
+        parent.children.insert(name);
+        parent.stat.cversion += 1;
+        parent.stat.num_children += 1;
+        parent.stat.pzxid = zxid;
+
+        self.nodes
+            .insert(path.clone(), Tree::node(data, acl, owner, zxid, now));
+        let triggers = vec![
+            (path.clone(), WatchedEventType::NodeCreated),
+            (parent_path, WatchedEventType::NodeChildrenChanged),
+        ];
+        Ok((path, triggers))
+    }
+
+    pub(super) fn delete(
+        &mut self,
+        path: &str,
+        version: i32,
+        zxid: i64,
+    ) -> Result<Vec<Trigger>, ZkError> {
+        if path == "/" {
+            return Err(ZkError::BadArguments);
+        }
+        let node = self.get(path)?;
+        check_version(node.stat.version, version)?;
+        if !node.children.is_empty() {
+            return Err(ZkError::NotEmpty);
+        }
+        self.nodes.remove(path);
+        let parent_path = parent(path).to_string();
+        let parent = self
+            .nodes
+            .get_mut(&parent_path)
+            .expect("a node's parent exists");
+        parent.children.remove(name(path));
+        parent.stat.cversion += 1;
+        parent.stat.num_children -= 1;
+        parent.stat.pzxid = zxid;
+        Ok(vec![
+            (path.to_string(), WatchedEventType::NodeDeleted),
+            (parent_path, WatchedEventType::NodeChildrenChanged),
+        ])
+    }
+
+    pub(super) fn set_data(
+        &mut self,
+        path: &str,
+        data: Vec<u8>,
+        version: i32,
+        zxid: i64,
+        now: i64,
+    ) -> Result<(Stat, Vec<Trigger>), ZkError> {
+        let node = self.get_mut(path)?;
+        check_version(node.stat.version, version)?;
+        node.stat.version += 1;
+        node.stat.mzxid = zxid;
+        node.stat.mtime = now;
+        node.stat.data_length = data.len() as i32;
+        node.data = data;
+        let triggers = vec![(path.to_string(), WatchedEventType::NodeDataChanged)];
+        Ok((node.stat, triggers))
+    }
+
+    pub(super) fn set_acl(
+        &mut self,
+        path: &str,
+        acl: Vec<Acl>,
+        version: i32,
+    ) -> Result<Stat, ZkError> {
+        if acl.is_empty() {
+            return Err(ZkError::InvalidACL);
+        }
+        let node = self.get_mut(path)?;
+        check_version(node.stat.aversion, version)?;
+        node.stat.aversion += 1;
+        node.acl = acl;
+        Ok(node.stat)
+    }
+
+    pub(super) fn check(&self, path: &str, version: i32) -> Result<(), ZkError> {
+        check_version(self.get(path)?.stat.version, version)
+    }
+
+    /// The paths of the ephemeral nodes owned by `session`.
+    pub(super) fn ephemerals(&self, session: i64) -> Vec<String> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.stat.ephemeral_owner == session)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create(tree: &mut Tree, path: &str, mode: CreateMode) -> Result<String, ZkError> {
+        let acl = Acl::open_unsafe().to_vec();
+        tree.create(path, b"x".to_vec(), acl, mode, 7, 1, 0)
+            .map(|(path, _)| path)
+    }
+
+    #[test]
+    fn create_and_delete() {
+        let mut tree = Tree::new();
+        assert_eq!(
+            create(&mut tree, "/a", CreateMode::Persistent),
+            Ok("/a".to_string())
+        );
+        assert_eq!(
+            create(&mut tree, "/a", CreateMode::Persistent),
+            Err(ZkError::NodeExists)
+        );
+        assert_eq!(
+            create(&mut tree, "/b/c", CreateMode::Persistent),
+            Err(ZkError::NoNode)
+        );
+        assert_eq!(
+            create(&mut tree, "/a/", CreateMode::Persistent),
+            Err(ZkError::BadArguments)
+        );
+        assert_eq!(
+            create(&mut tree, "/a/b", CreateMode::Persistent),
+            Ok("/a/b".to_string())
+        );
+        assert_eq!(tree.get("/a").unwrap().stat.num_children, 1);
+        assert_eq!(tree.delete("/a", -1, 2), Err(ZkError::NotEmpty));
+        assert_eq!(tree.delete("/a/b", 1, 2), Err(ZkError::BadVersion));
+        assert!(tree.delete("/a/b", 0, 2).is_ok());
+        let a = tree.get("/a").unwrap();
+        assert_eq!(
+            (a.stat.num_children, a.stat.cversion, a.stat.pzxid),
+            (0, 2, 2)
+        );
+        assert!(tree.delete("/a", -1, 3).is_ok());
+        assert_eq!(tree.get("/a").map(|_| ()), Err(ZkError::NoNode));
+        assert_eq!(tree.delete("/", -1, 3), Err(ZkError::BadArguments));
+    }
+
+    #[test]
+    fn sequential_and_ephemeral() {
+        let mut tree = Tree::new();
+        create(&mut tree, "/q", CreateMode::Persistent).unwrap();
+        let first = create(&mut tree, "/q/n-", CreateMode::EphemeralSequential).unwrap();
+        let second = create(&mut tree, "/q/", CreateMode::PersistentSequential).unwrap();
+        assert_eq!(first, "/q/n-0000000000");
+        assert_eq!(second, "/q/0000000001");
+        assert_eq!(
+            create(&mut tree, "/q/n-0000000000/x", CreateMode::Persistent),
+            Err(ZkError::NoChildrenForEphemerals)
+        );
+        assert_eq!(tree.ephemerals(7), vec![first]);
+        assert_eq!(tree.ephemerals(8), Vec::<String>::new());
+    }
+
+    #[test]
+    fn versions() {
+        let mut tree = Tree::new();
+        create(&mut tree, "/v", CreateMode::Persistent).unwrap();
+        assert_eq!(
+            tree.set_data("/v", vec![], 1, 2, 5).map(|_| ()),
+            Err(ZkError::BadVersion)
+        );
+        let (stat, _) = tree.set_data("/v", b"yz".to_vec(), 0, 2, 5).unwrap();
+        assert_eq!(
+            (stat.version, stat.mzxid, stat.mtime, stat.data_length),
+            (1, 2, 5, 2)
+        );
+        assert_eq!(tree.check("/v", 1), Ok(()));
+        assert_eq!(tree.check("/v", 0), Err(ZkError::BadVersion));
+        assert_eq!(tree.set_acl("/v", vec![], -1), Err(ZkError::InvalidACL));
+        let stat = tree.set_acl("/v", Acl::read_unsafe().to_vec(), 0).unwrap();
+        assert_eq!((stat.version, stat.aversion), (1, 1));
+    }
+}
@@ -0,0 +1,67 @@
+//! The server's side of the wire protocol: reading requests, and writing responses and events.
+
+use std::io;
+use crate::codec::frame::frame;
+use crate::codec::jute::WriteTo;
+use crate::codec::{ConnectRequest, ConnectResponse, Op, Reply, ReplyHeader, RequestHeader};
+use crate::{KeeperState, WatchedEvent, WatchedEventType, ZkError};
+
+pub(super) use crate::codec::{NOTIFICATION_XID, PING_XID};
+
+/// Read the handshake from the first frame of a connection, without its length.
+pub(super) fn read_connect(r: &[u8]) -> io::Result<ConnectRequest> {
+    ConnectRequest::read(r)
+}
+
+/// Read the xid and request from a frame of a connected client, without its length.
+pub(super) fn read_request(mut r: &[u8]) -> io::Result<(i32, Op)> {
+    let header = RequestHeader::read(&mut r)?;
+    Ok((header.xid, Op::read(header.opcode, &mut r)?))
+}
+
+/// The answer to a handshake; a `timeout` of zero tells the client that its session has expired.
+pub(super) fn connect_response(timeout: i32, session_id: i64, passwd: &[u8]) -> Vec<u8> {
+    let response = ConnectResponse {
+        protocol_version: 0,
+        timeout,
+        session_id,
+        passwd: passwd.to_vec(),
+        read_only: false,
+    };
+    frame(|w| response.write_to(w))
+}
+
+/// The response to the request with the given `xid`.
+pub(super) fn response(xid: i32, zxid: i64, reply: Result<Reply, ZkError>) -> Vec<u8> {
+    let err = reply.as_ref().err().copied().unwrap_or(ZkError::Ok);
+    frame(|w| {
+        ReplyHeader { xid, zxid, err }.write_to(w);
+        if let Ok(reply) = reply {
+            reply.write_to(w);
+        }
+    })
+}
+
+/// The response to the request with the given `xid`, with a body that is already encoded.
+pub(super) fn encoded_response(xid: i32, zxid: i64, result: Result<&[u8], ZkError>) -> Vec<u8> {
+    response(xid, zxid, result.map(|body| Reply::Unknown(body.to_vec())))
+}
+
+/// The notification that a watch on `path` has fired, as servers since 3.9 send it, with the
+/// `zxid` of the change that fired it.
+pub(super) fn event(zxid: i64, event_type: WatchedEventType, path: &str) -> Vec<u8> {
+    let event = WatchedEvent {
+        event_type,
+        keeper_state: KeeperState::SyncConnected,
+        path: path.to_string(),
+    };
+    frame(|w| {
+        ReplyHeader {
+            xid: NOTIFICATION_XID,
+            zxid,
+            err: ZkError::Ok,
+        }
+        .write_to(w);
+        event.write_to(&mut *w).unwrap();
+    })
+}
@@ -1,111 +1,180 @@
-use proto::{Request, Response, ZkError};
-use {error, Acl, MultiResponse, Stat};
+use bytes::Bytes;
+use crate::namespace::Namespace;
+use crate::proto::{Reply, Request, Response, ZkError};
+use crate::{error, Acl, Error, MultiResponse, RawResponse, Stat};
+
+fn unexpected(op: &str, res: Response) -> Error {
+    Error::Protocol(format!("got unexpected response to {}: {:?}", op, res))
+}
 
 pub(crate) fn create(
-    res: Result<Response, ZkError>,
-) -> Result<Result<String, error::Create>, failure::Error> {
+    path: &str,
+    res: Reply,
+) -> Result<Result<String, error::Create>, Error> {
     match res {
         Ok(Response::String(s)) => Ok(Ok(s)),
-        Ok(r) => bail!("got non-string response to create: {:?}", r),
-        Err(ZkError::NoNode) => Ok(Err(error::Create::NoNode)),
-        Err(ZkError::NodeExists) => Ok(Err(error::Create::NodeExists)),
-        Err(ZkError::InvalidACL) => Ok(Err(error::Create::InvalidAcl)),
-        Err(ZkError::NoChildrenForEphemerals) => Ok(Err(error::Create::NoChildrenForEphemerals)),
-        Err(e) => Err(format_err!("create call failed: {:?}", e)),
+        Ok(Response::Created { path, .. }) => Ok(Ok(path)),
+        res => create2(path, res).map(|res| res.map(|(path, _)| path)),
+    }
+}
+
+/// Like `create`, but for a `Create2`, which also returns the stat of the new node.
+pub(crate) fn create2(
+    path: &str,
+    res: Reply,
+) -> Result<Result<(String, Stat), error::Create>, Error> {
+    match res {
+        Ok(Response::Created { path, stat }) => Ok(Ok((path, stat))),
+        Ok(r) => Err(unexpected("create", r)),
+        Err((ZkError::NoNode, _)) => Ok(Err(error::Create::NoNode { path: path.to_string() })),
+        Err((ZkError::NodeExists, _)) => Ok(Err(error::Create::NodeExists {
+            path: path.to_string(),
+        })),
+        Err((ZkError::InvalidACL, _)) => Ok(Err(error::Create::InvalidAcl {
+            path: path.to_string(),
+        })),
+        Err((ZkError::NoChildrenForEphemerals, _)) => {
+            Ok(Err(error::Create::NoChildrenForEphemerals { path: path.to_string() }))
+        }
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
 pub(crate) fn set_data(
+    path: &str,
     version: i32,
-    res: Result<Response, ZkError>,
-) -> Result<Result<Stat, error::SetData>, failure::Error> {
+    res: Reply,
+) -> Result<Result<Stat, error::SetData>, Error> {
     match res {
         Ok(Response::Stat(stat)) => Ok(Ok(stat)),
-        Ok(r) => bail!("got a non-stat response to a set_data request: {:?}", r),
-        Err(ZkError::NoNode) => Ok(Err(error::SetData::NoNode)),
-        Err(ZkError::BadVersion) => Ok(Err(error::SetData::BadVersion { expected: version })),
-        Err(ZkError::NoAuth) => Ok(Err(error::SetData::NoAuth)),
-        Err(e) => bail!("set_data call failed: {:?}", e),
+        Ok(r) => Err(unexpected("set_data", r)),
+        Err((ZkError::NoNode, _)) => Ok(Err(error::SetData::NoNode { path: path.to_string() })),
+        Err((ZkError::BadVersion, _)) => Ok(Err(error::SetData::BadVersion {
+            path: path.to_string(),
+            expected: version,
+        })),
+        Err((ZkError::NoAuth, _)) => Ok(Err(error::SetData::NoAuth { path: path.to_string() })),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
 pub(crate) fn delete(
+    path: &str,
     version: i32,
-    res: Result<Response, ZkError>,
-) -> Result<Result<(), error::Delete>, failure::Error> {
+    res: Reply,
+) -> Result<Result<(), error::Delete>, Error> {
     match res {
         Ok(Response::Empty) => Ok(Ok(())),
-        Ok(r) => bail!("got non-empty response to delete: {:?}", r),
-        Err(ZkError::NoNode) => Ok(Err(error::Delete::NoNode)),
-        Err(ZkError::NotEmpty) => Ok(Err(error::Delete::NotEmpty)),
-        Err(ZkError::BadVersion) => Ok(Err(error::Delete::BadVersion { expected: version })),
-        Err(e) => Err(format_err!("delete call failed: {:?}", e)),
+        Ok(r) => Err(unexpected("delete", r)),
+        Err((ZkError::NoNode, _)) => Ok(Err(error::Delete::NoNode { path: path.to_string() })),
+        Err((ZkError::NotEmpty, _)) => Ok(Err(error::Delete::NotEmpty { path: path.to_string() })),
+        Err((ZkError::BadVersion, _)) => Ok(Err(error::Delete::BadVersion {
+            path: path.to_string(),
+            expected: version,
+        })),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
 pub(crate) fn get_acl(
-    res: Result<Response, ZkError>,
-) -> Result<Result<(Vec<Acl>, Stat), error::GetAcl>, failure::Error> {
+    path: &str,
+    res: Reply,
+) -> Result<Result<(Vec<Acl>, Stat), error::GetAcl>, Error> {
     match res {
         Ok(Response::GetAcl { acl, stat }) => Ok(Ok((acl, stat))),
-        Ok(r) => bail!("got non-acl response to a get_acl request: {:?}", r),
-        Err(ZkError::NoNode) => Ok(Err(error::GetAcl::NoNode)),
-        Err(e) => Err(format_err!("get_acl call failed: {:?}", e)),
+        Ok(r) => Err(unexpected("get_acl", r)),
+        Err((ZkError::NoNode, _)) => Ok(Err(error::GetAcl::NoNode { path: path.to_string() })),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
 pub(crate) fn set_acl(
+    path: &str,
     version: i32,
-    res: Result<Response, ZkError>,
-) -> Result<Result<Stat, error::SetAcl>, failure::Error> {
+    res: Reply,
+) -> Result<Result<Stat, error::SetAcl>, Error> {
     match res {
         Ok(Response::Stat(stat)) => Ok(Ok(stat)),
-        Ok(r) => bail!("got non-stat response to a set_acl request: {:?}", r),
-        Err(ZkError::NoNode) => Ok(Err(error::SetAcl::NoNode)),
-        Err(ZkError::BadVersion) => Ok(Err(error::SetAcl::BadVersion { expected: version })),
-        Err(ZkError::InvalidACL) => Ok(Err(error::SetAcl::InvalidAcl)),
-        Err(ZkError::NoAuth) => Ok(Err(error::SetAcl::NoAuth)),
-        Err(e) => Err(format_err!("set_acl call failed: {:?}", e)),
+        Ok(r) => Err(unexpected("set_acl", r)),
+        Err((ZkError::NoNode, _)) => Ok(Err(error::SetAcl::NoNode { path: path.to_string() })),
+        Err((ZkError::BadVersion, _)) => Ok(Err(error::SetAcl::BadVersion {
+            path: path.to_string(),
+            expected: version,
+        })),
+        Err((ZkError::InvalidACL, _)) => Ok(Err(error::SetAcl::InvalidAcl {
+            path: path.to_string(),
+        })),
+        Err((ZkError::NoAuth, _)) => Ok(Err(error::SetAcl::NoAuth { path: path.to_string() })),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
-pub(crate) fn exists(res: Result<Response, ZkError>) -> Result<Option<Stat>, failure::Error> {
+pub(crate) fn exists(res: Reply) -> Result<Option<Stat>, Error> {
     match res {
         Ok(Response::Stat(stat)) => Ok(Some(stat)),
-        Ok(r) => bail!("got a non-create response to a create request: {:?}", r),
-        Err(ZkError::NoNode) => Ok(None),
-        Err(e) => bail!("exists call failed: {:?}", e),
+        Ok(r) => Err(unexpected("exists", r)),
+        Err((ZkError::NoNode, _)) => Ok(None),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
-pub(crate) fn get_children(res: Result<Response, ZkError>) -> Result<Option<Vec<String>>, failure::Error> {
+pub(crate) fn get_children(res: Reply) -> Result<Option<Vec<String>>, Error> {
     match res {
         Ok(Response::Strings(children)) => Ok(Some(children)),
-        Ok(r) => bail!("got non-strings response to get-children: {:?}", r),
-        Err(ZkError::NoNode) => Ok(None),
-        Err(e) => Err(format_err!("get-children call failed: {:?}", e)),
+        Ok(r) => Err(unexpected("get_children", r)),
+        Err((ZkError::NoNode, _)) => Ok(None),
+        Err((e, context)) => Err(Error::server(e, context)),
+    }
+}
+
+pub(crate) fn get_children2(res: Reply) -> Result<Option<(Vec<String>, Stat)>, Error> {
+    match res {
+        Ok(Response::GetChildren2 { children, stat }) => Ok(Some((children, stat))),
+        Ok(r) => Err(unexpected("get_children_with_stat", r)),
+        Err((ZkError::NoNode, _)) => Ok(None),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
-pub(crate) fn get_data(res: Result<Response, ZkError>) -> Result<Option<(Vec<u8>, Stat)>, failure::Error> {
+pub(crate) fn get_data(res: Reply) -> Result<Option<(Bytes, Stat)>, Error> {
     match res {
         Ok(Response::GetData { bytes, stat }) => Ok(Some((bytes, stat))),
-        Ok(r) => bail!("got non-data response to get-data: {:?}", r),
-        Err(ZkError::NoNode) => Ok(None),
-        Err(e) => Err(format_err!("get-data call failed: {:?}", e)),
+        Ok(r) => Err(unexpected("get_data", r)),
+        Err((ZkError::NoNode, _)) => Ok(None),
+        Err((e, context)) => Err(Error::server(e, context)),
+    }
+}
+
+pub(crate) fn sync(res: Reply) -> Result<(), Error> {
+    match res {
+        Ok(Response::String(_)) => Ok(()),
+        Ok(r) => Err(unexpected("sync", r)),
+        Err((e, context)) => Err(Error::server(e, context)),
+    }
+}
+
+pub(crate) fn add_watch(res: Reply) -> Result<(), Error> {
+    match res {
+        Ok(Response::Empty) => Ok(()),
+        Ok(r) => Err(unexpected("add_watch", r)),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
 pub(crate) fn check(
+    path: &str,
     version: i32,
-    res: Result<Response, ZkError>,
-) -> Result<Result<(), error::Check>, failure::Error> {
+    res: Reply,
+) -> Result<Result<(), error::Check>, Error> {
     match res {
         Ok(Response::Empty) => Ok(Ok(())),
-        Ok(r) => bail!("got a non-check response to a check request: {:?}", r),
-        Err(ZkError::NoNode) => Ok(Err(error::Check::NoNode)),
-        Err(ZkError::BadVersion) => Ok(Err(error::Check::BadVersion { expected: version })),
-        Err(e) => bail!("check call failed: {:?}", e),
+        Ok(r) => Err(unexpected("check", r)),
+        Err((ZkError::NoNode, _)) => Ok(Err(error::Check::NoNode { path: path.to_string() })),
+        Err((ZkError::BadVersion, _)) => Ok(Err(error::Check::BadVersion {
+            path: path.to_string(),
+            expected: version,
+        })),
+        Err((e, context)) => Err(Error::server(e, context)),
     }
 }
 
@@ -115,23 +184,53 @@ pub(crate) fn check(
 /// expected version for each constituent set data, delete, or check operation.
 /// Unfortunately, executing a multi request requires transferring ownership of
 /// the `proto::Request`, which contains this information, to the future. A
+pub(crate) fn raw(res: Reply) -> Result<RawResponse, Error> {
+    match res {
+        Ok(Response::Raw {
+            xid,
+            zxid,
+            err,
+            body,
+        }) => Ok(RawResponse {
+            xid,
+            zxid,
+            err,
+            body: body.to_vec(),
+        }),
+        Ok(r) => Err(unexpected("raw_request", r)),
+        Err((e, context)) => Err(Error::server(e, context)),
+    }
+}
+
 /// `RequestMarker` is used to avoid cloning the whole `proto::Request`, which
-/// can be rather large, when only the version information is necessary.
+/// can be rather large, when only the path and version information is necessary.
 #[derive(Debug)]
 pub(crate) enum RequestMarker {
-    Create,
-    SetData { version: i32 },
-    Delete { version: i32 },
-    Check { version: i32 },
+    Create { path: String },
+    SetData { path: String, version: i32 },
+    Delete { path: String, version: i32 },
+    Check { path: String, version: i32 },
 }
 
-impl From<&Request> for RequestMarker {
-    fn from(r: &Request) -> RequestMarker {
+impl RequestMarker {
+    /// Retain what is needed of `r`, with its path relative to `namespace`.
+    pub(crate) fn new(r: &Request, namespace: &Namespace) -> RequestMarker {
         match r {
-            Request::Create { .. } => RequestMarker::Create,
-            Request::SetData { version, .. } => RequestMarker::SetData { version: *version },
-            Request::Delete { version, .. } => RequestMarker::Delete { version: *version },
-            Request::Check { version, .. } => RequestMarker::Check { version: *version },
+            Request::Create { path, .. } => RequestMarker::Create {
+                path: namespace.strip(path),
+            },
+            Request::SetData { path, version, .. } => RequestMarker::SetData {
+                path: namespace.strip(path),
+                version: *version,
+            },
+            Request::Delete { path, version } => RequestMarker::Delete {
+                path: namespace.strip(path),
+                version: *version,
+            },
+            Request::Check { path, version } => RequestMarker::Check {
+                path: namespace.strip(path),
+                version: *version,
+            },
             _ => unimplemented!(),
         }
     }
@@ -139,30 +238,30 @@ impl From<&Request> for RequestMarker {
 
 pub(crate) fn multi(
     req: &RequestMarker,
-    res: Result<Response, ZkError>,
-) -> Result<Result<MultiResponse, error::Multi>, failure::Error> {
+    res: Reply,
+) -> Result<Result<MultiResponse, error::Multi>, Error> {
     // Handle multi-specific errors.
     match res {
-        Err(ZkError::Ok) => return Ok(Err(error::Multi::RolledBack)),
+        Err((ZkError::Ok, _)) => return Ok(Err(error::Multi::RolledBack)),
         // Confusingly, the ZooKeeper server uses RuntimeInconsistency to
         // indicate that a request in a multi batch was skipped because an
         // earlier request in the batch failed.
         // Source: https://github.com/apache/zookeeper/blob/372e713a9/zookeeper-server/src/main/java/org/apache/zookeeper/server/DataTree.java#L945-L946
-        Err(ZkError::RuntimeInconsistency) => return Ok(Err(error::Multi::Skipped)),
+        Err((ZkError::RuntimeInconsistency, _)) => return Ok(Err(error::Multi::Skipped)),
         _ => (),
     };
 
     Ok(match req {
-        RequestMarker::Create => create(res)?
-            .map(|name| MultiResponse::Create(name))
+        RequestMarker::Create { path } => create(path, res)?
+            .map(MultiResponse::Create)
             .map_err(|err| err.into()),
-        RequestMarker::SetData { version } => set_data(*version, res)?
-            .map(|stat| MultiResponse::SetData(stat))
+        RequestMarker::SetData { path, version } => set_data(path, *version, res)?
+            .map(MultiResponse::SetData)
             .map_err(|err| err.into()),
-        RequestMarker::Delete { version } => delete(*version, res)?
+        RequestMarker::Delete { path, version } => delete(path, *version, res)?
             .map(|_| MultiResponse::Delete)
             .map_err(|err| err.into()),
-        RequestMarker::Check { version } => check(*version, res)?
+        RequestMarker::Check { path, version } => check(path, *version, res)?
             .map(|_| MultiResponse::Check)
             .map_err(|err| err.into()),
     })
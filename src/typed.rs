@@ -0,0 +1,353 @@
+//! Accessors that (de)serialize znode data to and from Rust types.
+//!
+//! ZooKeeper itself only stores opaque bytes. The methods in this module let you read and write
+//! those bytes as values of any type that implements `serde`'s `Serialize` and `Deserialize`,
+//! using a [`Format`] to decide what the bytes look like on the wire. [`Json`] is provided out of
+//! the box; other formats such as bincode or MessagePack can be supported by implementing
+//! [`Format`] for a marker type of your own.
+//!
+//! [`ZNode`] wraps a single node whose data is always a value of the same type, and takes care of
+//! its version for conditional writes.
+//!
+//! This module is only available with the `serde` feature enabled.
+
+use futures::channel::oneshot;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::error::Error as StdError;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use crate::{error, Error, Stat, WatchedEvent, ZooKeeper};
+
+/// A serialization format for znode data.
+pub trait Format {
+    /// Serialize `value` into the bytes that should be stored in a znode.
+    fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>>;
+
+    /// Deserialize the bytes stored in a znode into a `T`.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Box<dyn StdError + Send + Sync>>;
+}
+
+/// Stores znode data as JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Json;
+
+impl Format for Json {
+    fn encode<T: Serialize + ?Sized>(
+        value: &T,
+    ) -> Result<Vec<u8>, Box<dyn StdError + Send + Sync>> {
+        serde_json::to_vec(value).map_err(Into::into)
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, Box<dyn StdError + Send + Sync>> {
+        serde_json::from_slice(bytes).map_err(Into::into)
+    }
+}
+
+impl ZooKeeper {
+    /// Return the data of the node at the given `path` decoded as a `T` using the format `F`,
+    /// along with the node's [`Stat`], or `None` if the node does not exist.
+    ///
+    /// If the node's data cannot be decoded as a `T`, the returned future resolves with
+    /// [`Error::Codec`].
+    pub async fn get_as<F, T>(&self, path: &str) -> Result<Option<(T, Stat)>, Error>
+    where
+        F: Format,
+        T: DeserializeOwned,
+    {
+        match self.get_data(path).await? {
+            Some((bytes, stat)) => F::decode(&bytes)
+                .map(|value| Some((value, stat)))
+                .map_err(|error| Error::Codec {
+                    path: path.to_string(),
+                    error,
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// Set the data of the node at the given `path` to `value` encoded using the format `F`.
+    ///
+    /// See [`ZooKeeper::set_data`] for the semantics of `version`. If `value` cannot be encoded,
+    /// no request is sent, and the returned future resolves with [`Error::Codec`].
+    pub async fn set_as<F, T>(
+        &self,
+        path: &str,
+        value: &T,
+        version: Option<i32>,
+    ) -> Result<Result<Stat, error::SetData>, Error>
+    where
+        F: Format,
+        T: Serialize + ?Sized,
+    {
+        match F::encode(value) {
+            Ok(data) => self.set_data(path, version, data).await,
+            Err(error) => Err(Error::Codec {
+                path: path.to_string(),
+                error,
+            }),
+        }
+    }
+
+    /// Return the data of the node at the given `path` decoded from JSON, along with the node's
+    /// [`Stat`], or `None` if the node does not exist.
+    ///
+    /// See [`ZooKeeper::get_as`].
+    pub async fn get_json<T>(&self, path: &str) -> Result<Option<(T, Stat)>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.get_as::<Json, T>(path).await
+    }
+
+    /// Set the data of the node at the given `path` to `value` encoded as JSON.
+    ///
+    /// See [`ZooKeeper::set_as`].
+    pub async fn set_json<T>(
+        &self,
+        path: &str,
+        value: &T,
+        version: Option<i32>,
+    ) -> Result<Result<Stat, error::SetData>, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.set_as::<Json, T>(path, value, version).await
+    }
+}
+
+/// A node whose data is a `T`, stored in the format `F`.
+///
+/// The handle remembers the version of the node that it last read or wrote, so that
+/// [`ZNode::cas`] and [`ZNode::update`] only change the node if nobody else has since. This makes
+/// it a convenient way to keep configuration in ZooKeeper:
+///
+/// ```no_run
+/// # use tokio_zookeeper::*;
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Serialize, Deserialize)]
+/// struct Limits {
+///     max_connections: u32,
+/// }
+///
+/// # async fn run(zk: ZooKeeper) -> Result<(), Error> {
+/// let limits = zk.znode::<Limits>("/config/limits");
+/// limits
+///     .update(|mut limits| {
+///         limits.max_connections *= 2;
+///         limits
+///     })
+///     .await?
+///     .expect("the node exists");
+/// # Ok(())
+/// # }
+/// ```
+pub struct ZNode<T, F = Json> {
+    zk: ZooKeeper,
+    path: String,
+    version: Mutex<Option<i32>>,
+    _value: PhantomData<fn(T) -> (T, F)>,
+}
+
+impl<T, F> ZNode<T, F>
+where
+    T: Serialize + DeserializeOwned,
+    F: Format,
+{
+    /// Return a handle to the node at `path`, through `zk`.
+    pub fn new(zk: ZooKeeper, path: &str) -> Self {
+        ZNode {
+            zk,
+            path: path.to_string(),
+            version: Mutex::new(None),
+            _value: PhantomData,
+        }
+    }
+
+    /// The path of the node.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The version of the node that this handle last read or wrote, if it has.
+    pub fn version(&self) -> Option<i32> {
+        *self.version.lock().unwrap()
+    }
+
+    fn saw(&self, stat: &Stat) {
+        *self.version.lock().unwrap() = Some(stat.version);
+    }
+
+    /// Return the value of the node, or `None` if it does not exist.
+    ///
+    /// If the node's data cannot be decoded as a `T`, the returned future resolves with
+    /// [`Error::Codec`].
+    pub async fn get(&self) -> Result<Option<T>, Error> {
+        let got = self.zk.get_as::<F, T>(&self.path).await?;
+        Ok(got.map(|(value, stat)| {
+            self.saw(&stat);
+            value
+        }))
+    }
+
+    /// Return the value of the node, along with a watch that fires once the node changes, or
+    /// `None` without setting a watch if the node does not exist.
+    pub async fn watch(&self) -> Result<Option<(T, oneshot::Receiver<WatchedEvent>)>, Error> {
+        match self.zk.with_watcher().get_data(&self.path).await? {
+            Some((watch, bytes, stat)) => {
+                let value = F::decode(&bytes).map_err(|error| Error::Codec {
+                    path: self.path.clone(),
+                    error,
+                })?;
+                self.saw(&stat);
+                Ok(Some((value, watch)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Set the value of the node to `value`, whatever its version.
+    pub async fn set(&self, value: &T) -> Result<Result<Stat, error::SetData>, Error> {
+        self.set_version(value, None).await
+    }
+
+    /// Set the value of the node to `value`, if it is still at the version that this handle last
+    /// read or wrote.
+    ///
+    /// If it is not, or if the handle has not read or written the node yet, this fails with
+    /// [`error::SetData::BadVersion`], and the node should be read again.
+    pub async fn cas(&self, value: &T) -> Result<Result<Stat, error::SetData>, Error> {
+        match self.version() {
+            Some(version) => self.set_version(value, Some(version)).await,
+            None => Ok(Err(error::SetData::BadVersion {
+                path: self.path.clone(),
+                expected: -1,
+            })),
+        }
+    }
+
+    /// Replace the value of the node with what `f` makes of it, and return the new value.
+    ///
+    /// The node is read, and then written with [`ZNode::cas`]; if somebody else changed it in
+    /// between, it is read again and `f` is called on its new value, until the write succeeds.
+    /// Fails with [`error::SetData::NoNode`] if the node does not exist.
+    pub async fn update<U>(&self, mut f: U) -> Result<Result<T, error::SetData>, Error>
+    where
+        U: FnMut(T) -> T,
+    {
+        loop {
+            let value = match self.get().await? {
+                Some(value) => f(value),
+                None => {
+                    return Ok(Err(error::SetData::NoNode {
+                        path: self.path.clone(),
+                    }))
+                }
+            };
+            match self.cas(&value).await? {
+                Ok(_) => return Ok(Ok(value)),
+                Err(error::SetData::BadVersion { .. }) => continue,
+                Err(e) => return Ok(Err(e)),
+            }
+        }
+    }
+
+    async fn set_version(
+        &self,
+        value: &T,
+        version: Option<i32>,
+    ) -> Result<Result<Stat, error::SetData>, Error> {
+        let set = self.zk.set_as::<F, T>(&self.path, value, version).await?;
+        if let Ok(ref stat) = set {
+            self.saw(stat);
+        }
+        Ok(set)
+    }
+}
+
+impl<T, F> fmt::Debug for ZNode<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ZNode")
+            .field("path", &self.path)
+            .field("version", &*self.version.lock().unwrap())
+            .finish()
+    }
+}
+
+impl ZooKeeper {
+    /// Return a handle to the node at `path`, whose data is a `T` stored as JSON.
+    ///
+    /// See [`ZNode`]; use [`ZNode::new`] for other formats.
+    pub fn znode<T>(&self, path: &str) -> ZNode<T>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        ZNode::new(self.clone(), path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use crate::testing::MockZk;
+    use crate::{Acl, CreateMode};
+
+    #[test]
+    fn json_roundtrip() {
+        let mut value = BTreeMap::new();
+        value.insert("answer".to_string(), 42u32);
+        let bytes = Json::encode(&value).unwrap();
+        assert_eq!(bytes, br#"{"answer":42}"#);
+        let decoded: BTreeMap<String, u32> = Json::decode(&bytes).unwrap();
+        assert_eq!(decoded, value);
+    }
+
+    #[test]
+    fn json_decode_error() {
+        assert!(Json::decode::<u32>(b"not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn znode() {
+        let server = MockZk::new();
+        let (zk, _) = server.connect().await.unwrap();
+        let acl = Acl::open_unsafe();
+        zk.create("/count", &b"1"[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let count = zk.znode::<u32>("/count");
+        let other = zk.znode::<u32>("/count");
+
+        // nothing to compare against before the node was read
+        assert!(matches!(
+            count.cas(&0).await.unwrap(),
+            Err(error::SetData::BadVersion { .. })
+        ));
+        assert_eq!(count.get().await.unwrap(), Some(1));
+        assert_eq!(count.version(), Some(0));
+        assert_eq!(other.get().await.unwrap(), Some(1));
+        other.cas(&2).await.unwrap().unwrap();
+        assert!(matches!(
+            count.cas(&3).await.unwrap(),
+            Err(error::SetData::BadVersion { expected: 0, .. })
+        ));
+
+        let (value, watch) = count.watch().await.unwrap().unwrap();
+        assert_eq!(value, 2);
+        assert_eq!(count.update(|n| n * 10).await.unwrap(), Ok(20));
+        assert_eq!(watch.await.unwrap().path, "/count");
+        assert_eq!(other.update(|n| n + 1).await.unwrap(), Ok(21));
+        assert_eq!(count.version(), Some(2));
+
+        let missing = zk.znode::<u32>("/missing");
+        assert_eq!(missing.get().await.unwrap(), None);
+        assert!(missing.watch().await.unwrap().is_none());
+        let res = missing.update(|n| n).await.unwrap();
+        assert_eq!(res, Err(error::SetData::NoNode { path: "/missing".into() }));
+        zk.set_data("/count", None, &b"nope"[..]).await.unwrap().unwrap();
+        assert!(matches!(count.get().await, Err(Error::Codec { .. })));
+    }
+}
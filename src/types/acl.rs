@@ -7,6 +7,8 @@ use std::string::ToString;
 ///
 /// Permissions can be mixed together like integers with `|` and `&`.
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct Permission(u32);
 
 impl Permission {
@@ -146,6 +148,7 @@ mod tests {
 /// See the [ZooKeeper Programmer's Guide](https://zookeeper.apache.org/doc/current/zookeeperProgrammers.html#sc_ZooKeeperAccessControl)
 /// for more information.
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Acl {
     /// The permissions associated with this ACL.
     pub perms: Permission,
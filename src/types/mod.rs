@@ -1,3 +1,8 @@
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::proto::ZkError;
+
 mod acl;
 pub use self::acl::*;
 
@@ -7,6 +12,19 @@ pub use self::watch::*;
 mod multi;
 pub use self::multi::*;
 
+mod path;
+pub use self::path::*;
+
+/// The version that matches any version of a node, in the operations that only go through if the
+/// node is at a given version.
+///
+/// Methods that take an `Option<i32>` version take `None` to mean the same; this is for the ones
+/// that take an `i32`, such as [`MultiBuilder::check`](crate::MultiBuilder::check), where a check
+/// for any version succeeds if the node exists. Writes that should replace whatever the node
+/// holds have their own methods, [`ZooKeeper::set_data_any`](crate::ZooKeeper::set_data_any) and
+/// [`ZooKeeper::delete_any`](crate::ZooKeeper::delete_any).
+pub const ANY_VERSION: i32 = -1;
+
 /// Statistics about a znode, similar to the UNIX `stat` structure.
 ///
 /// # Time in ZooKeeper
@@ -22,6 +40,7 @@ pub use self::multi::*;
 /// - **Clock Time**: ZooKeeper does not use clock time to make decisions, but it uses it to put
 ///   timestamps into the `Stat` structure.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stat {
     /// The transaction ID that created the znode.
     pub czxid: i64,
@@ -47,9 +66,137 @@ pub struct Stat {
     pub pzxid: i64,
 }
 
+impl Stat {
+    /// When the znode was created, by the clock of the server that created it.
+    pub fn ctime(&self) -> SystemTime {
+        Stat::time(self.ctime)
+    }
+
+    /// When the znode was last modified, by the clock of the server that modified it.
+    pub fn mtime(&self) -> SystemTime {
+        Stat::time(self.mtime)
+    }
+
+    fn time(millis: i64) -> SystemTime {
+        let since_epoch = Duration::from_millis(millis.unsigned_abs());
+        if millis < 0 {
+            UNIX_EPOCH - since_epoch
+        } else {
+            UNIX_EPOCH + since_epoch
+        }
+    }
+
+    /// Whether the znode is ephemeral, and so is deleted once the session that owns it ends.
+    pub fn is_ephemeral(&self) -> bool {
+        self.ephemeral_owner != 0
+    }
+
+    /// The ID of the session that owns the znode, if it is ephemeral.
+    pub fn owner_session(&self) -> Option<i64> {
+        Some(self.ephemeral_owner).filter(|&owner| owner != 0)
+    }
+
+    /// The number of children the znode has.
+    pub fn num_children(&self) -> usize {
+        self.num_children as usize
+    }
+
+    /// The length of the znode's data, in bytes.
+    pub fn data_len(&self) -> usize {
+        self.data_length as usize
+    }
+}
+
+/// A snapshot of the state of a client's connection, as returned by `ZooKeeper::stats`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ConnectionStats {
+    /// The number of requests that have been sent, and are waiting for a response.
+    pub in_flight: usize,
+    /// The number of requests that have been issued, but not yet sent.
+    pub queued: usize,
+    /// The number of bytes of sent requests that have not yet been written to the server.
+    pub outbox_bytes: usize,
+    /// The last transaction ID that the client has seen from the server.
+    pub last_zxid_seen: i64,
+    /// The ID of the client's session, or 0 if none has been established yet.
+    pub session_id: i64,
+    /// The address of the server that the client is connected to.
+    pub server_addr: SocketAddr,
+    /// How long ago the client last received a packet from the server, if it ever has.
+    ///
+    /// Since the client sends heartbeats when it is otherwise idle, this growing much beyond the
+    /// session timeout means that the server is not responding.
+    pub since_last_packet: Option<Duration>,
+}
+
+/// The state of a client's connection, as returned by `ZooKeeper::state`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ConnectionState {
+    /// The client has not yet established a session.
+    Connecting,
+    /// The client has a session, and is connected to a server that can serve writes.
+    Connected,
+    /// The client has a session, and is connected to a read-only server.
+    ConnectedReadOnly,
+    /// The client lost its connection, and is trying to resume its session on a new one.
+    Reconnecting,
+    /// The session has expired. The client will not connect again.
+    Expired,
+    /// The client has shut down, or lost its connection for good. It will not connect again.
+    Closed,
+}
+
+impl ConnectionState {
+    /// Whether the client is connected to a server, so that requests are sent right away.
+    pub fn is_connected(self) -> bool {
+        matches!(self, ConnectionState::Connected | ConnectionState::ConnectedReadOnly)
+    }
+
+    /// Whether the client will never connect again, so that requests will fail.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, ConnectionState::Expired | ConnectionState::Closed)
+    }
+}
+
+/// The credentials of a client's session, as returned by `ZooKeeper::session`.
+///
+/// Whoever holds them can take the session over from another connection, so they are not shown
+/// by `Debug`.
+#[derive(PartialEq, Eq, Clone)]
+pub struct Session {
+    /// The ID of the session.
+    pub id: i64,
+    /// The password that the server handed out for the session.
+    pub password: Vec<u8>,
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("id", &self.id)
+            .field("password", &format_args!("<redacted>"))
+            .finish()
+    }
+}
+
+/// The response to a request sent with `ZooKeeper::raw_request`, as the server sent it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawResponse {
+    /// The xid that the client sent the request with, and that the server answered it with.
+    pub xid: i32,
+    /// The zxid that the server was at when it answered.
+    pub zxid: i64,
+    /// The error that the server answered with, which is `ZkError::Ok` if the request succeeded.
+    pub err: ZkError,
+    /// The rest of the response, which is usually empty if the request failed.
+    pub body: Vec<u8>,
+}
+
 /// CreateMode value determines how the znode is created on ZooKeeper.
 #[repr(i32)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum CreateMode {
     /// The znode will not be automatically deleted upon client's disconnect.
     Persistent = 0,
@@ -76,3 +223,86 @@ pub enum CreateMode {
     //  ^---- is it sequential?
     //   ^--- is it ephemeral?
 }
+
+/// Describes which operation took effect in a successful `create_or_set` call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upsert {
+    /// No node existed at the given path, so a new one was created.
+    Created,
+    /// A node already existed at the given path, and its data was replaced.
+    Updated,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat() {
+        let mut stat = Stat {
+            czxid: 1,
+            mzxid: 2,
+            ctime: 1_500_000_000_000,
+            mtime: 1_500_000_000_250,
+            version: 0,
+            cversion: 0,
+            aversion: 0,
+            ephemeral_owner: 0,
+            data_length: 3,
+            num_children: 2,
+            pzxid: 1,
+        };
+        let ctime = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        assert_eq!(stat.ctime(), ctime);
+        assert_eq!(stat.mtime().duration_since(ctime).unwrap(), Duration::from_millis(250));
+        assert!(!stat.is_ephemeral());
+        assert_eq!(stat.owner_session(), None);
+        assert_eq!((stat.num_children(), stat.data_len()), (2, 3));
+
+        stat.ephemeral_owner = 0x1234;
+        assert!(stat.is_ephemeral());
+        assert_eq!(stat.owner_session(), Some(0x1234));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        let acl = Acl::creator_all()[0].clone();
+        let json = serde_json::to_string(&acl).unwrap();
+        assert_eq!(json, r#"{"perms":31,"scheme":"auth","id":""}"#);
+        assert_eq!(serde_json::from_str::<Acl>(&json).unwrap(), acl);
+
+        let event = WatchedEvent {
+            event_type: WatchedEventType::NodeDataChanged,
+            keeper_state: KeeperState::SyncConnected,
+            path: "/a".to_string(),
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"event_type":"NodeDataChanged","keeper_state":"SyncConnected","path":"/a"}"#
+        );
+        assert_eq!(serde_json::from_str::<WatchedEvent>(&json).unwrap(), event);
+
+        let stat = Stat {
+            czxid: 1,
+            mzxid: 2,
+            ctime: 3,
+            mtime: 4,
+            version: 5,
+            cversion: 6,
+            aversion: 7,
+            ephemeral_owner: 8,
+            data_length: 9,
+            num_children: 10,
+            pzxid: 11,
+        };
+        let json = serde_json::to_string(&stat).unwrap();
+        assert_eq!(serde_json::from_str::<Stat>(&json).unwrap(), stat);
+
+        // paths are validated as they are deserialized
+        let path = serde_json::from_str::<ZkPath>(r#""/a/b""#).unwrap();
+        assert_eq!(path, ZkPath::new("/a/b").unwrap());
+        assert!(serde_json::from_str::<ZkPath>(r#""a/b""#).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use crate::error::InvalidPath;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops;
+use std::str::FromStr;
+
+/// A validated, absolute ZooKeeper path.
+///
+/// ZooKeeper paths look like UNIX file paths, but are more restricted: they must start with `/`,
+/// must not end with `/` (unless the path is the root itself), may not contain empty node names
+/// (`//`) or the relative node names `.` and `..`, and may not contain certain control and
+/// non-printable characters. See the [ZooKeeper Programmer's
+/// Guide](https://zookeeper.apache.org/doc/current/zookeeperProgrammers.html#ch_zkDataModel) for
+/// the full set of rules.
+///
+/// A `ZkPath` is checked against those rules when it is constructed, so invalid paths are caught
+/// before any request is sent. The [`ZooKeeper`](struct.ZooKeeper.html) methods for single nodes
+/// take anything that converts into a `ZkPath`: a `ZkPath` is used as it is, and a string is
+/// checked as it is converted, failing the call with
+/// [`Error::InvalidPath`](crate::Error::InvalidPath) if it is not a valid path. Since it
+/// dereferences to `str`, a `ZkPath` can also be passed to any method that takes a `&str`:
+///
+/// ```
+/// # use tokio_zookeeper::ZkPath;
+/// let path = ZkPath::new("/app").unwrap().join("config").unwrap();
+/// assert_eq!(&*path, "/app/config");
+/// assert_eq!(path.basename(), "config");
+/// assert_eq!(path.parent(), Some(ZkPath::new("/app").unwrap()));
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "String", into = "String"))]
+pub struct ZkPath(String);
+
+impl ZkPath {
+    /// Validate `path` and wrap it in a `ZkPath`.
+    pub fn new<S: Into<String>>(path: S) -> Result<ZkPath, InvalidPath> {
+        let path = path.into();
+        ZkPath::validate(&path)?;
+        Ok(ZkPath(path))
+    }
+
+    /// The root path, `/`.
+    pub fn root() -> ZkPath {
+        ZkPath(String::from("/"))
+    }
+
+    /// Check that `path` is a legal ZooKeeper path without constructing a `ZkPath`.
+    pub fn validate(path: &str) -> Result<(), InvalidPath> {
+        if path.is_empty() {
+            return Err(InvalidPath::Empty);
+        }
+        if !path.starts_with('/') {
+            return Err(InvalidPath::NotAbsolute);
+        }
+        if path == "/" {
+            return Ok(());
+        }
+        if path.ends_with('/') {
+            return Err(InvalidPath::TrailingSlash);
+        }
+
+        for node in path[1..].split('/') {
+            match node {
+                "" => return Err(InvalidPath::EmptyNode),
+                "." | ".." => return Err(InvalidPath::RelativeNode),
+                _ => {}
+            }
+        }
+
+        for (index, character) in path.char_indices() {
+            let illegal = matches!(
+                character,
+                '\u{0000}'..='\u{001f}'
+                    | '\u{007f}'..='\u{009f}'
+                    | '\u{e000}'..='\u{f8ff}'
+                    | '\u{fff0}'..='\u{ffff}'
+            );
+            if illegal {
+                return Err(InvalidPath::IllegalCharacter { character, index });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the path as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Append the relative path `child` (which may contain several node names separated by `/`)
+    /// to this path.
+    pub fn join(&self, child: &str) -> Result<ZkPath, InvalidPath> {
+        if child.is_empty() || child.starts_with('/') {
+            return Err(InvalidPath::EmptyNode);
+        }
+        let mut path = self.0.clone();
+        if path != "/" {
+            path.push('/');
+        }
+        path.push_str(child);
+        ZkPath::new(path)
+    }
+
+    /// Return the path of this node's parent, or `None` if this is the root.
+    pub fn parent(&self) -> Option<ZkPath> {
+        if self.0 == "/" {
+            return None;
+        }
+        match self.0.rfind('/') {
+            Some(0) => Some(ZkPath::root()),
+            Some(i) => Some(ZkPath(self.0[..i].to_string())),
+            None => unreachable!("validated paths are absolute"),
+        }
+    }
+
+    /// Return the last node name in this path, or the empty string if this is the root.
+    pub fn basename(&self) -> &str {
+        let i = self.0.rfind('/').expect("validated paths are absolute");
+        &self.0[i + 1..]
+    }
+
+    /// Consume the `ZkPath`, returning the underlying `String`.
+    pub fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl ops::Deref for ZkPath {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for ZkPath {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for ZkPath {
+    type Err = InvalidPath;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ZkPath::new(s)
+    }
+}
+
+impl TryFrom<String> for ZkPath {
+    type Error = InvalidPath;
+
+    fn try_from(path: String) -> Result<Self, Self::Error> {
+        ZkPath::new(path)
+    }
+}
+
+impl TryFrom<&str> for ZkPath {
+    type Error = InvalidPath;
+
+    fn try_from(path: &str) -> Result<Self, Self::Error> {
+        ZkPath::new(path)
+    }
+}
+
+impl TryFrom<&&str> for ZkPath {
+    type Error = InvalidPath;
+
+    fn try_from(path: &&str) -> Result<Self, Self::Error> {
+        ZkPath::new(*path)
+    }
+}
+
+impl TryFrom<&String> for ZkPath {
+    type Error = InvalidPath;
+
+    fn try_from(path: &String) -> Result<Self, Self::Error> {
+        ZkPath::new(path.as_str())
+    }
+}
+
+impl From<&ZkPath> for ZkPath {
+    fn from(path: &ZkPath) -> Self {
+        path.clone()
+    }
+}
+
+impl From<ZkPath> for String {
+    fn from(path: ZkPath) -> String {
+        path.0
+    }
+}
+
+impl fmt::Display for ZkPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate() {
+        assert_eq!(ZkPath::validate("/"), Ok(()));
+        assert_eq!(ZkPath::validate("/a/b-c_d.e"), Ok(()));
+        assert_eq!(ZkPath::validate(""), Err(InvalidPath::Empty));
+        assert_eq!(ZkPath::validate("a/b"), Err(InvalidPath::NotAbsolute));
+        assert_eq!(ZkPath::validate("/a/"), Err(InvalidPath::TrailingSlash));
+        assert_eq!(ZkPath::validate("/a//b"), Err(InvalidPath::EmptyNode));
+        assert_eq!(ZkPath::validate("/a/./b"), Err(InvalidPath::RelativeNode));
+        assert_eq!(ZkPath::validate("/a/.."), Err(InvalidPath::RelativeNode));
+        assert_eq!(ZkPath::validate("/a/..b"), Ok(()));
+        assert_eq!(
+            ZkPath::validate("/a\u{0}"),
+            Err(InvalidPath::IllegalCharacter {
+                character: '\u{0}',
+                index: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn join() {
+        let root = ZkPath::root();
+        assert_eq!(root.join("a").unwrap().as_str(), "/a");
+        assert_eq!(root.join("a/b").unwrap().join("c").unwrap().as_str(), "/a/b/c");
+        assert_eq!(root.join("/a"), Err(InvalidPath::EmptyNode));
+        assert_eq!(root.join("a/"), Err(InvalidPath::TrailingSlash));
+        assert_eq!(root.join(""), Err(InvalidPath::EmptyNode));
+    }
+
+    #[test]
+    fn conversions() {
+        let path = ZkPath::try_from("/a").unwrap();
+        assert_eq!(ZkPath::try_from(&String::from("/a")), Ok(path.clone()));
+        assert_eq!(ZkPath::try_from(&"/a"), Ok(path.clone()));
+        assert_eq!(ZkPath::from(&path), path);
+        assert_eq!(ZkPath::try_from("a/"), Err(InvalidPath::NotAbsolute));
+    }
+
+    #[test]
+    fn parent_and_basename() {
+        let path: ZkPath = "/a/b".parse().unwrap();
+        assert_eq!(path.basename(), "b");
+        let parent = path.parent().unwrap();
+        assert_eq!(parent.as_str(), "/a");
+        assert_eq!(parent.basename(), "a");
+        let root = parent.parent().unwrap();
+        assert_eq!(root, ZkPath::root());
+        assert_eq!(root.basename(), "");
+        assert_eq!(root.parent(), None);
+    }
+}
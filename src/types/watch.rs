@@ -3,6 +3,7 @@
 /// The `WatchedEvent` includes exactly what happened, the current state of the ZooKeeper, and the
 /// path of the znode that was involved in the event.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WatchedEvent {
     /// The trigger that caused the watch to hit.
     pub event_type: WatchedEventType,
@@ -17,6 +18,7 @@ pub struct WatchedEvent {
 /// server at the time the event was generated.
 #[repr(i32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum KeeperState {
     /// The client is in the disconnected state - it is not connected to any server in the ensemble.
     Disconnected = 0,
@@ -37,25 +39,41 @@ pub enum KeeperState {
     /// is no longer valid. You must create a new client connection (instantiate a new `ZooKeeper`
     /// instance) if you with to access the ensemble.
     Expired = -112,
+    /// The client has not heard from the server for long enough that the session may be about to
+    /// expire; see [`ZooKeeperBuilder::set_expiry_warning`].
+    ///
+    /// This state is only ever reported by the client, never by the server.
+    ///
+    /// [`ZooKeeperBuilder::set_expiry_warning`]: crate::ZooKeeperBuilder::set_expiry_warning
+    Expiring = -113,
 }
 
-impl From<i32> for KeeperState {
-    fn from(code: i32) -> Self {
-        match code {
+impl KeeperState {
+    /// Return the state with the given code, if it is one that is known.
+    pub(crate) fn from_code(code: i32) -> Option<Self> {
+        Some(match code {
             0 => KeeperState::Disconnected,
             3 => KeeperState::SyncConnected,
             4 => KeeperState::AuthFailed,
             5 => KeeperState::ConnectedReadOnly,
             6 => KeeperState::SaslAuthenticated,
             -112 => KeeperState::Expired,
-            _ => unreachable!("unknown keeper state {:x}", code),
-        }
+            _ => return None,
+        })
+    }
+}
+
+impl From<i32> for KeeperState {
+    fn from(code: i32) -> Self {
+        KeeperState::from_code(code)
+            .unwrap_or_else(|| unreachable!("unknown keeper state {:x}", code))
     }
 }
 
 /// Enumeration of types of events that may occur on the znode.
 #[repr(i32)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WatchedEventType {
     /// Nothing known has occurred on the znode. This value is issued as part of a `WatchedEvent`
     /// when the `KeeperState` changes.
@@ -77,9 +95,10 @@ pub enum WatchedEventType {
     ChildWatchRemoved = 6,
 }
 
-impl From<i32> for WatchedEventType {
-    fn from(code: i32) -> Self {
-        match code {
+impl WatchedEventType {
+    /// Return the event type with the given code, if it is one that is known.
+    pub(crate) fn from_code(code: i32) -> Option<Self> {
+        Some(match code {
             -1 => WatchedEventType::None,
             1 => WatchedEventType::NodeCreated,
             2 => WatchedEventType::NodeDeleted,
@@ -87,7 +106,14 @@ impl From<i32> for WatchedEventType {
             4 => WatchedEventType::NodeChildrenChanged,
             5 => WatchedEventType::DataWatchRemoved,
             6 => WatchedEventType::ChildWatchRemoved,
-            _ => unreachable!("unknown event type {:x}", code),
-        }
+            _ => return None,
+        })
+    }
+}
+
+impl From<i32> for WatchedEventType {
+    fn from(code: i32) -> Self {
+        WatchedEventType::from_code(code)
+            .unwrap_or_else(|| unreachable!("unknown event type {:x}", code))
     }
 }
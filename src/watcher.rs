@@ -0,0 +1,115 @@
+use futures::channel::mpsc;
+use futures::stream::{FusedStream, Stream};
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time;
+use crate::logging::Logger;
+use crate::metrics::Metrics;
+use crate::WatchedEvent;
+
+/// The stream of events of watches set on a session with [`ZooKeeper::watch`], and of changes to
+/// the session's state.
+///
+/// It is returned alongside the [`ZooKeeper`] handle when connecting, and ends once the session
+/// has been closed and every event before that has been received. After ending, it keeps
+/// returning `None`, so it can be polled again without care, for instance from inside
+/// `futures::select!`.
+///
+/// [`ZooKeeper`]: crate::ZooKeeper
+/// [`ZooKeeper::watch`]: crate::ZooKeeper::watch
+pub struct WatchedEventStream {
+    rx: mpsc::UnboundedReceiver<(WatchedEvent, time::Instant)>,
+    logger: Logger,
+    metrics: Metrics,
+    slow_threshold: Option<time::Duration>,
+}
+
+impl WatchedEventStream {
+    pub(crate) fn new(
+        rx: mpsc::UnboundedReceiver<(WatchedEvent, time::Instant)>,
+        logger: Logger,
+        metrics: Metrics,
+        slow_threshold: Option<time::Duration>,
+    ) -> Self {
+        WatchedEventStream {
+            rx,
+            logger,
+            metrics,
+            slow_threshold,
+        }
+    }
+}
+
+impl fmt::Debug for WatchedEventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WatchedEventStream")
+            .field("terminated", &self.rx.is_terminated())
+            .finish()
+    }
+}
+
+impl Stream for WatchedEventStream {
+    type Item = WatchedEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<WatchedEvent>> {
+        // an exhausted receiver keeps returning `None`, which is what makes this stream fused
+        let (e, received) = match Pin::new(&mut self.rx).poll_next(cx) {
+            Poll::Ready(Some(e)) => e,
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        let latency = received.elapsed();
+        self.metrics.on_watch_delivered(e.event_type, latency);
+        match self.slow_threshold {
+            Some(threshold) if latency >= threshold => warn!(
+                self.logger,
+                "slow watch consumer";
+                "event_type" => ?e.event_type,
+                "path" => &e.path,
+                "latency_ms" => latency.as_millis() as u64
+            ),
+            _ => {}
+        }
+        Poll::Ready(Some(e))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rx.size_hint()
+    }
+}
+
+impl FusedStream for WatchedEventStream {
+    fn is_terminated(&self) -> bool {
+        self.rx.is_terminated()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use crate::{KeeperState, WatchedEventType};
+
+    #[test]
+    fn fused() {
+        let (tx, rx) = mpsc::unbounded();
+        let mut events =
+            WatchedEventStream::new(rx, Logger::default(), Metrics::default(), None);
+        let e = WatchedEvent {
+            event_type: WatchedEventType::NodeDeleted,
+            keeper_state: KeeperState::SyncConnected,
+            path: "/foo".to_string(),
+        };
+        tx.unbounded_send((e.clone(), time::Instant::now())).unwrap();
+        drop(tx);
+
+        futures::executor::block_on(async {
+            assert!(!events.is_terminated());
+            assert_eq!(events.next().await, Some(e));
+            assert_eq!(events.next().await, None);
+            assert!(events.is_terminated());
+            assert_eq!(events.next().await, None);
+        });
+    }
+}
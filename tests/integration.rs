@@ -0,0 +1,381 @@
+//! Runs the same matrix of operations against every supported ZooKeeper release, each in a
+//! container of its own.
+//!
+//! This needs Docker, and is only built with `--features integration-tests`:
+//!
+//! ```text
+//! cargo test --features integration-tests --test integration
+//! ```
+//!
+//! `ZK_VERSIONS` restricts the run to a comma-separated list of versions (such as `3.4,3.8`), and
+//! `ZK_IMAGE` picks the image that is run, which defaults to the official `zookeeper` image and
+//! has to accept the versions as tags. Checks that only some releases support name the first
+//! release that does, and are skipped for older ones.
+#![cfg(feature = "integration-tests")]
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::env;
+use std::net::SocketAddr;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tokio_zookeeper::*;
+
+/// How long a freshly started server gets to start accepting sessions.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A `major.minor` ZooKeeper release.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Version(u32, u32);
+
+impl Version {
+    fn parse(s: &str) -> Version {
+        let mut parts = s.trim().split('.').map(|p| p.parse().expect("bad version"));
+        let major = parts.next().expect("bad version");
+        let minor = parts.next().unwrap_or(0);
+        Version(major, minor)
+    }
+}
+
+/// A server running in a container, which is removed again on drop.
+struct Server {
+    id: String,
+    addr: SocketAddr,
+}
+
+fn docker(args: &[&str]) -> String {
+    let output = Command::new("docker")
+        .args(args)
+        .output()
+        .expect("failed to run docker");
+    assert!(
+        output.status.success(),
+        "docker {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+impl Server {
+    fn start(version: Version) -> Server {
+        let image = env::var("ZK_IMAGE").unwrap_or_else(|_| "zookeeper".to_string());
+        let image = format!("{}:{}.{}", image, version.0, version.1);
+        let id = docker(&["run", "--detach", "--rm", "--publish", "127.0.0.1::2181", &image]);
+        let port = docker(&["port", &id, "2181/tcp"]);
+        // there may be one line per address family
+        let addr = port
+            .lines()
+            .find_map(|line| line.parse().ok())
+            .unwrap_or_else(|| panic!("unexpected port mapping {:?}", port));
+        Server { id, addr }
+    }
+
+    /// Connect to the server, waiting for it to come up if it has only just been started.
+    async fn connect(&self) -> (ZooKeeper, WatchedEventStream) {
+        let start = Instant::now();
+        loop {
+            let attempt = async {
+                let (zk, w) = ZooKeeper::connect(&self.addr).await.ok()?;
+                zk.exists("/").await.ok()?;
+                Some((zk, w))
+            };
+            if let Ok(Some(connected)) = tokio::time::timeout(Duration::from_secs(5), attempt).await
+            {
+                return connected;
+            }
+            assert!(
+                start.elapsed() < STARTUP_TIMEOUT,
+                "server at {} did not come up",
+                self.addr
+            );
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = Command::new("docker").args(["rm", "--force", &self.id]).output();
+    }
+}
+
+type Check = for<'a> fn(&'a Server, &'a ZooKeeper) -> BoxFuture<'a, ()>;
+
+/// The operation matrix: every check, and the first release that supports what it checks.
+const CHECKS: &[(&str, Version, Check)] = &[
+    ("crud", Version(3, 4), |_, zk| crud(zk).boxed()),
+    ("sequential", Version(3, 4), |_, zk| sequential(zk).boxed()),
+    ("ephemeral", Version(3, 4), ephemeral),
+    ("acl", Version(3, 4), |_, zk| acl(zk).boxed()),
+    ("multi", Version(3, 4), |_, zk| multi(zk).boxed()),
+    ("watches", Version(3, 4), watches),
+    ("containers", Version(3, 5), |_, zk| containers(zk).boxed()),
+];
+
+async fn crud(zk: &ZooKeeper) {
+    let acl = Acl::open_unsafe();
+    let path = zk
+        .create("/crud", &b"1"[..], acl, CreateMode::Persistent)
+        .await
+        .unwrap();
+    assert_eq!(path.as_deref(), Ok("/crud"));
+    let res = zk.create("/crud", &b""[..], acl, CreateMode::Persistent).await;
+    assert_eq!(
+        res.unwrap(),
+        Err(error::Create::NodeExists {
+            path: "/crud".to_string()
+        })
+    );
+    let res = zk.create("/nope/crud", &b""[..], acl, CreateMode::Persistent).await;
+    assert_eq!(
+        res.unwrap(),
+        Err(error::Create::NoNode {
+            path: "/nope/crud".to_string()
+        })
+    );
+
+    let (data, stat) = zk.get_data("/crud").await.unwrap().unwrap();
+    assert_eq!((&data[..], stat.version), (&b"1"[..], 0));
+    let res = zk.set_data("/crud", Some(1), &b"2"[..]).await.unwrap();
+    assert_eq!(
+        res,
+        Err(error::SetData::BadVersion {
+            path: "/crud".to_string(),
+            expected: 1
+        })
+    );
+    let stat = zk.set_data("/crud", Some(0), &b"2"[..]).await.unwrap().unwrap();
+    assert_eq!((stat.version, stat.data_length), (1, 1));
+
+    zk.create("/crud/child", &b""[..], acl, CreateMode::Persistent)
+        .await
+        .unwrap()
+        .unwrap();
+    let children = zk.get_children("/crud").await.unwrap();
+    assert_eq!(children, Some(vec!["child".to_string()]));
+    let res = zk.delete("/crud", None).await.unwrap();
+    assert_eq!(
+        res,
+        Err(error::Delete::NotEmpty {
+            path: "/crud".to_string()
+        })
+    );
+    zk.delete("/crud/child", Some(0)).await.unwrap().unwrap();
+    zk.delete("/crud", None).await.unwrap().unwrap();
+    assert_eq!(zk.exists("/crud").await.unwrap(), None);
+    assert_eq!(zk.get_data("/crud").await.unwrap(), None);
+}
+
+async fn sequential(zk: &ZooKeeper) {
+    let acl = Acl::open_unsafe();
+    zk.create("/seq", &b""[..], acl, CreateMode::Persistent)
+        .await
+        .unwrap()
+        .unwrap();
+    let first = zk
+        .create("/seq/n-", &b""[..], acl, CreateMode::PersistentSequential)
+        .await
+        .unwrap()
+        .unwrap();
+    let second = zk
+        .create("/seq/n-", &b""[..], acl, CreateMode::PersistentSequential)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(first, "/seq/n-0000000000");
+    assert_eq!(second, "/seq/n-0000000001");
+}
+
+fn ephemeral<'a>(server: &'a Server, zk: &'a ZooKeeper) -> BoxFuture<'a, ()> {
+    async move {
+        let acl = Acl::open_unsafe();
+        let (other, _) = server.connect().await;
+        other
+            .create("/eph", &b""[..], acl, CreateMode::Ephemeral)
+            .await
+            .unwrap()
+            .unwrap();
+        let res = other
+            .create("/eph/child", &b""[..], acl, CreateMode::Persistent)
+            .await;
+        assert_eq!(
+            res.unwrap(),
+            Err(error::Create::NoChildrenForEphemerals {
+                path: "/eph/child".to_string()
+            })
+        );
+        let stat = zk.exists("/eph").await.unwrap().unwrap();
+        assert_eq!(stat.ephemeral_owner, other.stats().session_id);
+
+        // closing the session takes its ephemeral nodes with it
+        let (deleted, _) = zk.with_watcher().exists("/eph").await.unwrap();
+        drop(other);
+        assert_eq!(deleted.await.unwrap().event_type, WatchedEventType::NodeDeleted);
+        assert_eq!(zk.exists("/eph").await.unwrap(), None);
+    }
+    .boxed()
+}
+
+async fn acl(zk: &ZooKeeper) {
+    zk.create("/acl", &b""[..], Acl::open_unsafe(), CreateMode::Persistent)
+        .await
+        .unwrap()
+        .unwrap();
+    let (acl, _) = zk.get_acl("/acl").await.unwrap().unwrap();
+    assert_eq!(acl, Acl::open_unsafe());
+    let res = zk.set_acl("/acl", Acl::read_unsafe(), Some(1)).await.unwrap();
+    assert_eq!(
+        res,
+        Err(error::SetAcl::BadVersion {
+            path: "/acl".to_string(),
+            expected: 1
+        })
+    );
+    let stat = zk
+        .set_acl("/acl", Acl::read_unsafe(), Some(0))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stat.aversion, 1);
+    let res = zk.set_data("/acl", None, &b"x"[..]).await.unwrap();
+    assert_eq!(
+        res,
+        Err(error::SetData::NoAuth {
+            path: "/acl".to_string()
+        })
+    );
+}
+
+async fn multi(zk: &ZooKeeper) {
+    let acl = Acl::open_unsafe();
+    let res = zk
+        .multi()
+        .create("/multi", &b""[..], acl, CreateMode::Persistent)
+        .set_data("/multi", Some(0), &b"x"[..])
+        .check("/multi", 1)
+        .commit()
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(res.len(), 3);
+    assert_eq!(res[0], MultiResponse::Create("/multi".to_string()));
+
+    // a failed multi is rolled back as a whole
+    let res = zk
+        .multi()
+        .set_data("/multi", None, &b"y"[..])
+        .check("/multi", 0)
+        .create("/multi2", &b""[..], acl, CreateMode::Persistent)
+        .commit()
+        .await
+        .unwrap()
+        .unwrap_err();
+    assert_eq!(res.index, 1);
+    assert_eq!(
+        res.results,
+        vec![
+            Err(error::Multi::RolledBack),
+            Err(error::Multi::Check(error::Check::BadVersion {
+                path: "/multi".to_string(),
+                expected: 0
+            })),
+            Err(error::Multi::Skipped),
+        ]
+    );
+    let (data, _) = zk.get_data("/multi").await.unwrap().unwrap();
+    assert_eq!(data, b"x");
+    assert_eq!(zk.exists("/multi2").await.unwrap(), None);
+}
+
+async fn containers(zk: &ZooKeeper) {
+    assert!(zk.capabilities().await.containers());
+    let path = zk
+        .create("/container", &b""[..], Acl::open_unsafe(), CreateMode::Container)
+        .await
+        .unwrap();
+    assert_eq!(path.as_deref(), Ok("/container"));
+}
+
+fn watches<'a>(server: &'a Server, zk: &'a ZooKeeper) -> BoxFuture<'a, ()> {
+    async move {
+        let acl = Acl::open_unsafe();
+        let (other, _) = server.connect().await;
+        let (created, stat) = zk.with_watcher().exists("/watch").await.unwrap();
+        assert_eq!(stat, None);
+        other
+            .create("/watch", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(created.await.unwrap().event_type, WatchedEventType::NodeCreated);
+
+        let (changed, _, _) = zk.with_watcher().get_data("/watch").await.unwrap().unwrap();
+        let (children, _) = zk
+            .with_watcher()
+            .get_children("/watch")
+            .await
+            .unwrap()
+            .unwrap();
+        other.set_data("/watch", None, &b"x"[..]).await.unwrap().unwrap();
+        assert_eq!(changed.await.unwrap().event_type, WatchedEventType::NodeDataChanged);
+        other
+            .create("/watch/child", &b""[..], acl, CreateMode::Persistent)
+            .await
+            .unwrap()
+            .unwrap();
+        let e = children.await.unwrap();
+        assert_eq!(e.event_type, WatchedEventType::NodeChildrenChanged);
+        assert_eq!(e.path, "/watch");
+    }
+    .boxed()
+}
+
+/// Run every check that `version` supports against a fresh server of that version, each on its
+/// own paths, and report all the checks that failed at once.
+async fn run(version: &str) {
+    let version = Version::parse(version);
+    if let Ok(versions) = env::var("ZK_VERSIONS") {
+        if !versions.split(',').any(|v| Version::parse(v) == version) {
+            return;
+        }
+    }
+
+    let server = Server::start(version);
+    let (zk, _) = server.connect().await;
+    let mut failed = Vec::new();
+    for &(name, since, check) in CHECKS {
+        if version < since {
+            eprintln!("{:?}: skipping {}, which needs {:?}", version, name, since);
+            continue;
+        }
+        if std::panic::AssertUnwindSafe(check(&server, &zk))
+            .catch_unwind()
+            .await
+            .is_err()
+        {
+            failed.push(name);
+        }
+    }
+    assert!(failed.is_empty(), "{:?}: failed {:?}", version, failed);
+}
+
+macro_rules! versions {
+    ($($test:ident => $version:expr),*) => {
+        $(
+            #[tokio::test(flavor = "multi_thread")]
+            async fn $test() {
+                run($version).await;
+            }
+        )*
+    };
+}
+
+versions! {
+    zookeeper_3_4 => "3.4",
+    zookeeper_3_5 => "3.5",
+    zookeeper_3_6 => "3.6",
+    zookeeper_3_7 => "3.7",
+    zookeeper_3_8 => "3.8",
+    zookeeper_3_9 => "3.9"
+}